@@ -78,6 +78,41 @@ function sortObjectKeys(obj) {
   return obj;
 }
 
+/**
+ * Record a SHA-256 hash of each script the extension runs (every tool's
+ * luaScript or pythonModule, plus hooks.lua and hooks.py) in manifest.files,
+ * so the signature covers them.
+ * This matches the Rust verify_files() check.
+ *
+ * @param readFile - returns the file's bytes for a relative path, or null if absent
+ */
+function addFileHashes(manifest, readFile) {
+  const scripts = new Set();
+  for (const tool of manifest.tools || []) {
+    for (const script of [tool.luaScript, tool.pythonModule]) {
+      if (script) {
+        scripts.add(path.posix.normalize(script));
+      }
+    }
+  }
+  for (const hooks of ['hooks.lua', 'hooks.py']) {
+    if (readFile(hooks)) {
+      scripts.add(hooks);
+    }
+  }
+
+  const files = {};
+  for (const script of [...scripts].sort()) {
+    const content = readFile(script);
+    if (!content) {
+      console.error(`Error: script not found: ${script}`);
+      process.exit(1);
+    }
+    files[script] = crypto.createHash('sha256').update(content).digest('hex');
+  }
+  manifest.files = files;
+}
+
 /**
  * Sign manifest content
  */
@@ -111,6 +146,11 @@ function signDirectory(dirPath) {
   console.log(`Signing extension: ${manifest.id || manifest.name}`);
   console.log(`  Publisher: ${publisherId}`);
 
+  addFileHashes(manifest, (file) => {
+    const filePath = path.join(dirPath, file);
+    return fs.existsSync(filePath) ? fs.readFileSync(filePath) : null;
+  });
+
   // Sign it
   const signature = signManifest(manifest);
 
@@ -151,6 +191,8 @@ function signVsext(vsextPath) {
   console.log(`Signing extension: ${manifest.id || manifest.name}`);
   console.log(`  Publisher: ${publisherId}`);
 
+  addFileHashes(manifest, (file) => files[file] || null);
+
   // Sign it
   const signature = signManifest(manifest);
 
@@ -0,0 +1,101 @@
+//! Where answers to approval requests come from.
+//!
+//! When a gated tool call needs a person's OK, the loop registers the request
+//! with an [`ApprovalHandler`], announces it with a `ToolApprovalRequired`
+//! event, and waits for the answer (or a timeout, or a cancel). Registering
+//! first means an answer can't arrive before anyone is listening for it.
+//!
+//! The app's handler is the [`ToolApprovalStore`], which the frontend answers
+//! through `respond_tool_approval`.
+
+use futures_util::FutureExt;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{oneshot, Mutex};
+
+use super::types::ApprovalScope;
+
+/// The UI's answer to an approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApprovalAnswer {
+    pub approved: bool,
+    /// Which later calls the answer also applies to
+    pub scope: ApprovalScope,
+}
+
+impl ApprovalAnswer {
+    /// An answer for this call only
+    #[cfg(test)]
+    pub fn once(approved: bool) -> Self {
+        ApprovalAnswer {
+            approved,
+            scope: ApprovalScope::Call,
+        }
+    }
+}
+
+/// Future returned by [`ApprovalHandler`] methods
+pub type ApprovalFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// An answer still to come; `None` if whoever would give it went away first
+pub type PendingAnswer = ApprovalFuture<'static, Option<ApprovalAnswer>>;
+
+/// Answers approval requests for gated tool calls
+pub trait ApprovalHandler: Send + Sync {
+    /// Start listening for the answer to `approval_id`, before anyone is asked
+    fn register<'a>(&'a self, approval_id: &'a str) -> ApprovalFuture<'a, PendingAnswer>;
+
+    /// Stop listening for `approval_id`, once the run has its answer or stops
+    /// waiting for one
+    fn withdraw<'a>(&'a self, approval_id: &'a str) -> ApprovalFuture<'a, ()>;
+}
+
+/// Pending tool approval requests (approval_id -> response channel).
+///
+/// This is managed at the app level so the frontend can approve/deny tool calls via IPC.
+pub type ToolApprovalStore = Arc<Mutex<HashMap<String, oneshot::Sender<ApprovalAnswer>>>>;
+
+impl ApprovalHandler for ToolApprovalStore {
+    fn register<'a>(&'a self, approval_id: &'a str) -> ApprovalFuture<'a, PendingAnswer> {
+        let (tx, rx) = oneshot::channel::<ApprovalAnswer>();
+        // A dropped sender means the responder went away without answering
+        let pending: PendingAnswer = Box::pin(rx.map(Result::ok));
+        let insert = async move {
+            self.lock().await.insert(approval_id.to_string(), tx);
+        };
+        Box::pin(insert.map(|()| pending))
+    }
+
+    fn withdraw<'a>(&'a self, approval_id: &'a str) -> ApprovalFuture<'a, ()> {
+        Box::pin(async move {
+            self.lock().await.remove(approval_id);
+        })
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_delivers_answers_to_registered_requests() {
+        let store: ToolApprovalStore = Arc::new(Mutex::new(HashMap::new()));
+
+        let pending = store.register("approval-1").await;
+        let tx = store.lock().await.remove("approval-1").unwrap();
+        tx.send(ApprovalAnswer::once(true)).unwrap();
+        assert_eq!(pending.await, Some(ApprovalAnswer::once(true)));
+
+        // A request nobody answers resolves to None once its sender is gone
+        let pending = store.register("approval-2").await;
+        store.withdraw("approval-2").await;
+        assert!(store.lock().await.is_empty());
+        assert_eq!(pending.await, None);
+    }
+}
@@ -0,0 +1,196 @@
+//! Crash-safe file writes.
+//!
+//! [`write_atomic`] writes to a temp file next to the target, syncs it, and
+//! renames it over the target, so a crash or a full disk leaves either the old
+//! file or the new one, never a truncated mix.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Replace the contents of `path` with `bytes` atomically.
+///
+/// An existing file keeps its permissions; a read-only one is refused, as a
+/// plain write would be. A symlink is followed and its target replaced.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, |file| file.write_all(bytes))
+}
+
+/// [`write_atomic`] with the temp file's contents produced by `write`
+fn write_atomic_with<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let target = resolve_symlink(path)?;
+    let existing = match fs::metadata(&target) {
+        Ok(meta) => Some(meta.permissions()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    if existing.as_ref().is_some_and(|p| p.readonly()) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file is read-only",
+        ));
+    }
+
+    let temp = temp_path(&target)?;
+    let result = (|| {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        write(&mut file)?;
+        file.sync_all()?;
+        drop(file);
+        if let Some(permissions) = existing {
+            fs::set_permissions(&temp, permissions)?;
+        }
+        rename_over(&temp, &target)
+    })();
+
+    if result.is_err() {
+        if let Err(e) = fs::remove_file(&temp) {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("Failed to remove temp file {}: {}", temp.display(), e);
+            }
+        }
+        return result;
+    }
+    sync_dir(&target);
+    Ok(())
+}
+
+/// The file a symlink points to, so the link itself survives the rename
+fn resolve_symlink(path: &Path) -> io::Result<PathBuf> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path),
+        _ => Ok(path.to_path_buf()),
+    }
+}
+
+/// A hidden temp name in the target's directory (the rename must not cross
+/// filesystems). The leading dot keeps the workspace watcher from firing on it.
+pub(crate) fn temp_path(target: &Path) -> io::Result<PathBuf> {
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let temp_name = format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        uuid::Uuid::new_v4().simple()
+    );
+    Ok(target.with_file_name(temp_name))
+}
+
+/// Rename `from` over `to`. Windows replaces an existing file too, but fails
+/// while another process (an editor, a virus scanner) briefly holds it open,
+/// so permission errors there are retried for a moment.
+#[cfg(windows)]
+fn rename_over(from: &Path, to: &Path) -> io::Result<()> {
+    const RETRIES: u32 = 5;
+    let mut attempt = 1;
+    loop {
+        match fs::rename(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied && attempt < RETRIES => {
+                std::thread::sleep(std::time::Duration::from_millis(20 * attempt as u64));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn rename_over(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)
+}
+
+/// Make the rename itself durable. Best effort: not every platform or
+/// filesystem lets a directory be synced.
+#[cfg(unix)]
+fn sync_dir(target: &Path) {
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Err(e) = File::open(dir).and_then(|d| d.sync_all()) {
+        log::debug!("Failed to sync directory {}: {}", dir.display(), e);
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_target: &Path) {}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn dir_entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_write_atomic_creates_and_replaces() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("ch1.md");
+
+        write_atomic(&path, b"First draft").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "First draft");
+
+        write_atomic(&path, b"Second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Second");
+        assert_eq!(dir_entries(dir.path()), vec!["ch1.md"]);
+    }
+
+    #[test]
+    fn test_failed_write_leaves_original_untouched() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("ch1.md");
+        fs::write(&path, "The whole chapter").unwrap();
+
+        // Part of the new content lands before the disk fills up
+        let err = write_atomic_with(&path, |file| {
+            file.write_all(b"The wh")?;
+            Err(io::Error::other("no space left"))
+        })
+        .unwrap_err();
+
+        assert!(err.to_string().contains("no space left"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "The whole chapter");
+        assert_eq!(dir_entries(dir.path()), vec!["ch1.md"]);
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notes.md");
+        fs::write(&path, "old").unwrap();
+
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        assert!(write_atomic(&path, b"new").is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+            write_atomic(&path, b"new").unwrap();
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o640);
+            assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//! Snapshots of files the agent is about to overwrite or delete.
+//!
+//! A workspace opts in with `.vswrite/backups.toml`:
+//!
+//! ```toml
+//! enabled = true
+//! keep_runs = 20   # newest runs kept
+//! max_mb = 200     # total size kept across runs
+//! ```
+//!
+//! Before a run first changes an existing file, the file is copied to
+//! `.vswrite/backups/<run_id>/<relative-path>` and recorded in the run's
+//! manifest, `.vswrite/backups/<run_id>.json`. Only the first change in a run
+//! is saved, so restoring puts the file back as it was before the run.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::atomic_write::write_atomic;
+use super::tools::{safe_path, PathValidator};
+
+/// Workspace-relative directory holding backups
+pub const BACKUPS_DIR: &str = ".vswrite/backups";
+
+/// Workspace-relative path of the backup settings
+pub const BACKUP_CONFIG_FILE: &str = ".vswrite/backups.toml";
+
+/// A workspace's backup settings
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Whether runs back up files at all
+    pub enabled: bool,
+    /// Number of most recent runs whose backups are kept
+    pub keep_runs: usize,
+    /// Total size of kept backups, in megabytes
+    pub max_mb: u64,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            enabled: false,
+            keep_runs: 20,
+            max_mb: 200,
+        }
+    }
+}
+
+impl BackupConfig {
+    /// Read the workspace's settings. A missing or invalid file disables backups.
+    pub fn load(workspace: &Path) -> Self {
+        let Ok(text) = fs::read_to_string(workspace.join(BACKUP_CONFIG_FILE)) else {
+            return BackupConfig::default();
+        };
+        Self::parse(&text).unwrap_or_else(|e| {
+            log::warn!("Ignoring {}: {}", BACKUP_CONFIG_FILE, e);
+            BackupConfig::default()
+        })
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let config: BackupConfig = toml::from_str(text).map_err(|e| e.to_string())?;
+        if config.keep_runs == 0 {
+            return Err("keep_runs must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+
+    fn max_bytes(&self) -> u64 {
+        self.max_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+/// What a run backed up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub run_id: String,
+    pub created_at: DateTime<Utc>,
+    pub entries: Vec<BackupEntry>,
+}
+
+/// One saved file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Workspace-relative path, with `/` separators
+    pub path: String,
+    /// The tool whose call replaced or deleted the file
+    pub operation: String,
+    pub timestamp: DateTime<Utc>,
+    pub bytes: u64,
+}
+
+impl BackupManifest {
+    fn bytes(&self) -> u64 {
+        self.entries.iter().map(|e| e.bytes).sum()
+    }
+}
+
+/// Per-run state: which paths have been seen, and what was saved
+#[derive(Default)]
+struct RunState {
+    seen: HashSet<PathBuf>,
+    manifest: Option<BackupManifest>,
+}
+
+/// Backs up files for one run, before tools change them
+pub struct RunBackups {
+    run_id: String,
+    config: BackupConfig,
+    state: Mutex<RunState>,
+}
+
+impl RunBackups {
+    /// Backups for `run_id`, or `None` when the workspace hasn't enabled them
+    pub fn for_run(workspace: &Path, run_id: &str) -> Option<Self> {
+        let config = BackupConfig::load(workspace);
+        if !config.enabled {
+            return None;
+        }
+        Some(Self::with_config(run_id, config))
+    }
+
+    fn with_config(run_id: &str, config: BackupConfig) -> Self {
+        RunBackups {
+            run_id: run_id.to_string(),
+            config,
+            state: Mutex::new(RunState::default()),
+        }
+    }
+
+    /// Save the current version of the existing files in `targets`, which a
+    /// `tool` call is about to replace or delete. Paths that don't resolve are
+    /// left for the tool to reject.
+    pub fn before_tool(
+        &self,
+        paths: &PathValidator,
+        tool: &str,
+        targets: &[String],
+    ) -> Result<(), String> {
+        if targets.is_empty() {
+            return Ok(());
+        }
+        let workspace = paths.canonical_workspace()?;
+        let backups_dir = workspace.join(BACKUPS_DIR);
+
+        for target in targets {
+            let Ok(resolved) = paths.resolve(target) else {
+                continue;
+            };
+            if resolved.starts_with(&backups_dir) {
+                continue;
+            }
+            let Ok(relative) = resolved.strip_prefix(&workspace) else {
+                continue;
+            };
+
+            let mut state = self.lock();
+            // Only the version from before the run is worth keeping; a file
+            // the run created has no earlier version
+            if !state.seen.insert(resolved.clone()) || !resolved.is_file() {
+                continue;
+            }
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if let Err(e) = self.save(&mut state, &workspace, &resolved, &relative, tool) {
+                // Let the next call try again
+                state.seen.remove(&resolved);
+                return Err(format!("Failed to back up {}: {}", relative, e));
+            }
+        }
+        Ok(())
+    }
+
+    fn save(
+        &self,
+        state: &mut RunState,
+        workspace: &Path,
+        source: &Path,
+        relative: &str,
+        tool: &str,
+    ) -> Result<(), String> {
+        let first = state.manifest.is_none();
+        let manifest = state.manifest.get_or_insert_with(|| BackupManifest {
+            run_id: self.run_id.clone(),
+            created_at: Utc::now(),
+            entries: Vec::new(),
+        });
+
+        let copy = run_dir(workspace, &self.run_id)?.join(relative);
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let bytes = fs::copy(source, &copy).map_err(|e| e.to_string())?;
+
+        manifest.entries.push(BackupEntry {
+            path: relative.to_string(),
+            operation: tool.to_string(),
+            timestamp: Utc::now(),
+            bytes,
+        });
+        write_manifest(workspace, manifest)?;
+
+        if first {
+            prune(workspace, &self.config, &self.run_id);
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RunState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Run IDs are UUIDs; anything else could name a path outside the backups
+fn check_run_id(run_id: &str) -> Result<(), String> {
+    let valid = !run_id.is_empty()
+        && run_id.len() <= 64
+        && run_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid run ID: '{}'", run_id))
+    }
+}
+
+fn run_dir(workspace: &Path, run_id: &str) -> Result<PathBuf, String> {
+    check_run_id(run_id)?;
+    Ok(workspace.join(BACKUPS_DIR).join(run_id))
+}
+
+fn manifest_path(workspace: &Path, run_id: &str) -> Result<PathBuf, String> {
+    check_run_id(run_id)?;
+    Ok(workspace.join(BACKUPS_DIR).join(format!("{}.json", run_id)))
+}
+
+fn write_manifest(workspace: &Path, manifest: &BackupManifest) -> Result<(), String> {
+    let json = serde_json::to_vec_pretty(manifest).map_err(|e| e.to_string())?;
+    write_atomic(&manifest_path(workspace, &manifest.run_id)?, &json).map_err(|e| e.to_string())
+}
+
+fn read_manifest(path: &Path) -> Result<BackupManifest, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+/// The manifest of a run's backups
+pub fn list_run_backups(workspace: &Path, run_id: &str) -> Result<BackupManifest, String> {
+    let path = manifest_path(workspace, run_id)?;
+    if !path.is_file() {
+        return Err(format!("No backups for run {}", run_id));
+    }
+    read_manifest(&path).map_err(|e| format!("Failed to read backups for run {}: {}", run_id, e))
+}
+
+/// Put backed-up files back: just `path` when given, else every file the run
+/// saved. Returns the restored paths.
+pub fn restore_run_backup(
+    workspace: &Path,
+    run_id: &str,
+    path: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let manifest = list_run_backups(workspace, run_id)?;
+    let dir = run_dir(workspace, run_id)?;
+
+    let entries: Vec<&BackupEntry> = match path {
+        Some(path) => {
+            let wanted = path.trim_start_matches("./").replace('\\', "/");
+            let entry = manifest
+                .entries
+                .iter()
+                .find(|e| e.path == wanted)
+                .ok_or_else(|| format!("Run {} has no backup of {}", run_id, path))?;
+            vec![entry]
+        }
+        None => manifest.entries.iter().collect(),
+    };
+
+    let mut restored = Vec::new();
+    for entry in entries {
+        let source = safe_path(&dir, &entry.path)?;
+        let target = safe_path(workspace, &entry.path)?;
+        let content = fs::read(&source)
+            .map_err(|e| format!("Failed to read backup of {}: {}", entry.path, e))?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directories: {}", e))?;
+        }
+        write_atomic(&target, &content)
+            .map_err(|e| format!("Failed to restore {}: {}", entry.path, e))?;
+        restored.push(entry.path.clone());
+    }
+    log::info!("Restored {} file(s) from run {}", restored.len(), run_id);
+    Ok(restored)
+}
+
+/// Drop the oldest runs' backups beyond `keep_runs` or `max_mb`. The current
+/// run's backups are always kept.
+fn prune(workspace: &Path, config: &BackupConfig, current_run: &str) {
+    let Ok(entries) = fs::read_dir(workspace.join(BACKUPS_DIR)) else {
+        return;
+    };
+    let mut runs: Vec<BackupManifest> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+        .filter_map(|p| read_manifest(&p).ok())
+        .filter(|m| m.run_id != current_run && check_run_id(&m.run_id).is_ok())
+        .collect();
+    runs.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+    let mut kept_runs = 1;
+    let mut kept_bytes = manifest_path(workspace, current_run)
+        .and_then(|p| read_manifest(&p))
+        .map(|m| m.bytes())
+        .unwrap_or(0);
+    for run in runs {
+        let bytes = run.bytes();
+        if kept_runs < config.keep_runs && kept_bytes + bytes <= config.max_bytes() {
+            kept_runs += 1;
+            kept_bytes += bytes;
+            continue;
+        }
+        log::info!("Pruning backups of run {}", run.run_id);
+        if let Ok(dir) = run_dir(workspace, &run.run_id) {
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove {}: {}", dir.display(), e);
+                }
+            }
+        }
+        if let Ok(path) = manifest_path(workspace, &run.run_id) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::core::{execute_tool, ToolContext};
+    use crate::agent::staged_writes::StagedWrites;
+    use crate::agent::tools::{Scratchpad, ShellSettings};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn enabled(keep_runs: usize, max_mb: u64) -> BackupConfig {
+        BackupConfig {
+            enabled: true,
+            keep_runs,
+            max_mb,
+        }
+    }
+
+    fn run_tool(paths: &PathValidator, backups: &RunBackups, name: &str, args: serde_json::Value) {
+        run_tools(paths, &StagedWrites::new(), backups, name, args);
+    }
+
+    fn run_tools(
+        paths: &PathValidator,
+        staged_writes: &StagedWrites,
+        backups: &RunBackups,
+        name: &str,
+        args: serde_json::Value,
+    ) -> String {
+        let context = ToolContext {
+            paths,
+            staged_writes,
+            scratchpad: &Scratchpad::new(),
+            shell: &ShellSettings::default(),
+            backups: Some(backups),
+            cancel: None,
+        };
+        execute_tool(&context, name, &args).unwrap().into()
+    }
+
+    #[test]
+    fn test_config_load() {
+        let dir = TempDir::new().unwrap();
+        assert!(RunBackups::for_run(dir.path(), "run-1").is_none());
+
+        fs::create_dir_all(dir.path().join(".vswrite")).unwrap();
+        fs::write(
+            dir.path().join(BACKUP_CONFIG_FILE),
+            "enabled = true\nkeep_runs = 5\n",
+        )
+        .unwrap();
+        assert_eq!(BackupConfig::load(dir.path()), enabled(5, 200));
+        assert!(RunBackups::for_run(dir.path(), "run-1").is_some());
+
+        assert!(BackupConfig::parse("enabled = true\nkeep_runs = 0").is_err());
+        assert!(BackupConfig::parse("enabled = true\nkeep = 3").is_err());
+    }
+
+    #[test]
+    fn test_overwrite_and_delete_are_backed_up() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        fs::write(dir.path().join("sections/ch1.md"), "Original chapter").unwrap();
+        fs::write(dir.path().join("notes.md"), "Notes").unwrap();
+        let paths = PathValidator::new(dir.path());
+        let backups = RunBackups::with_config("run-1", enabled(20, 200));
+
+        let ch1 = json!({"path": "sections/ch1.md", "content": "Rewrite"});
+        run_tool(&paths, &backups, "write_file", ch1.clone());
+        run_tool(&paths, &backups, "write_file", ch1);
+        run_tool(&paths, &backups, "delete_file", json!({"path": "notes.md"}));
+        // New files have nothing to back up, even when changed again
+        let new = json!({"path": "new.md", "content": "a"});
+        run_tool(&paths, &backups, "write_file", new.clone());
+        run_tool(&paths, &backups, "write_file", new);
+
+        let manifest = list_run_backups(dir.path(), "run-1").unwrap();
+        let saved: Vec<(&str, &str)> = manifest
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.operation.as_str()))
+            .collect();
+        assert_eq!(
+            saved,
+            vec![
+                ("sections/ch1.md", "write_file"),
+                ("notes.md", "delete_file")
+            ]
+        );
+        let copy = dir.path().join(BACKUPS_DIR).join("run-1/sections/ch1.md");
+        assert_eq!(fs::read_to_string(copy).unwrap(), "Original chapter");
+    }
+
+    /// Section tools and staged writes change files without naming their paths
+    #[test]
+    fn test_section_edits_and_staged_writes_are_backed_up() {
+        const SECTION: &str = "44444444-4444-4444-8444-444444444444";
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        fs::write(
+            dir.path().join("sections/01.md"),
+            format!("---\nid: {}\ntitle: One\norder: 0\n---\nOnce.", SECTION),
+        )
+        .unwrap();
+        fs::write(dir.path().join("draft.md"), "Old draft").unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+        let backups = RunBackups::with_config("run-1", enabled(20, 200));
+
+        let append = json!({"section_id": SECTION, "text": " Twice."});
+        run_tools(&paths, &staged, &backups, "append_to_section", append);
+        let begun = run_tools(
+            &paths,
+            &staged,
+            &backups,
+            "begin_write",
+            json!({"path": "draft.md"}),
+        );
+        let handle = serde_json::from_str::<serde_json::Value>(&begun).unwrap()["handle"].clone();
+        let chunk = json!({"handle": handle, "content": "New draft"});
+        run_tools(&paths, &staged, &backups, "write_chunk", chunk);
+        run_tools(
+            &paths,
+            &staged,
+            &backups,
+            "commit_write",
+            json!({"handle": handle}),
+        );
+
+        let manifest = list_run_backups(dir.path(), "run-1").unwrap();
+        let saved: Vec<(&str, &str)> = manifest
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.operation.as_str()))
+            .collect();
+        assert_eq!(
+            saved,
+            vec![
+                ("sections/01.md", "append_to_section"),
+                ("draft.md", "commit_write")
+            ]
+        );
+        restore_run_backup(dir.path(), "run-1", None).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("draft.md")).unwrap(),
+            "Old draft"
+        );
+        assert!(fs::read_to_string(dir.path().join("sections/01.md"))
+            .unwrap()
+            .ends_with("Once."));
+    }
+
+    #[test]
+    fn test_restore_one_file_or_whole_run() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+        fs::write(dir.path().join("b.md"), "B").unwrap();
+        let paths = PathValidator::new(dir.path());
+        let backups = RunBackups::with_config("run-1", enabled(20, 200));
+
+        let edit = json!({"path": "a.md", "old_string": "A", "new_string": "AA"});
+        run_tool(&paths, &backups, "edit_file", edit);
+        run_tool(&paths, &backups, "delete_file", json!({"path": "b.md"}));
+
+        let restored = restore_run_backup(dir.path(), "run-1", Some("b.md")).unwrap();
+        assert_eq!(restored, vec!["b.md"]);
+        assert_eq!(fs::read_to_string(dir.path().join("b.md")).unwrap(), "B");
+        assert_eq!(fs::read_to_string(dir.path().join("a.md")).unwrap(), "AA");
+
+        let restored = restore_run_backup(dir.path(), "run-1", None).unwrap();
+        assert_eq!(restored, vec!["a.md", "b.md"]);
+        assert_eq!(fs::read_to_string(dir.path().join("a.md")).unwrap(), "A");
+
+        assert!(restore_run_backup(dir.path(), "run-1", Some("c.md")).is_err());
+        assert!(restore_run_backup(dir.path(), "run-2", None).is_err());
+        assert!(list_run_backups(dir.path(), "../run-1").is_err());
+    }
+
+    #[test]
+    fn test_prune_old_runs() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let write = json!({"path": "a.md", "content": "changed"});
+        for run in ["run-1", "run-2", "run-3"] {
+            let backups = RunBackups::with_config(run, enabled(2, 200));
+            run_tool(&paths, &backups, "write_file", write.clone());
+            // Keep creation times strictly ordered
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(list_run_backups(dir.path(), "run-1").is_err());
+        assert!(!dir.path().join(BACKUPS_DIR).join("run-1").exists());
+        assert!(list_run_backups(dir.path(), "run-2").is_ok());
+        assert!(list_run_backups(dir.path(), "run-3").is_ok());
+
+        // A size budget of zero keeps only the current run
+        let backups = RunBackups::with_config("run-4", enabled(20, 0));
+        run_tool(&paths, &backups, "write_file", write);
+        assert!(list_run_backups(dir.path(), "run-3").is_err());
+        assert!(list_run_backups(dir.path(), "run-4").is_ok());
+    }
+}
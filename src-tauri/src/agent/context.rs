@@ -0,0 +1,262 @@
+//! Keeping the conversation inside the model's context window.
+//!
+//! Token counts are estimated at four characters per token. Before each model
+//! call, if the conversation is over budget, the oldest tool results are replaced
+//! with a short stub until it fits. The system prompt, the task, and the most
+//! recent turns are never touched, and elided results keep their `tool_call_id`
+//! so providers still see every call answered.
+
+use super::pricing::match_model;
+use super::types::{AgentConfig, Message, MessageRole};
+
+/// Context window assumed for models missing from the table (local models included)
+pub const DEFAULT_CONTEXT_WINDOW: usize = 32_000;
+
+/// Assistant turns (with their tool results) always kept in full
+pub const KEEP_RECENT_TURNS: usize = 2;
+
+/// Stub prefix for an elided tool result
+const ELIDED_PREFIX: &str = "[output elided";
+
+/// Fixed per-message overhead (role, ids, framing) in tokens
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// (model prefix, context window in tokens), matched like prices
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-5", 400_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude", 200_000),
+];
+
+/// What a call to [`fit_to_budget`] elided
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Truncation {
+    pub elided_messages: usize,
+    pub tokens_before: usize,
+    pub tokens_after: usize,
+}
+
+/// Context window of `model` in tokens
+pub fn context_window(model: &str) -> usize {
+    match_model(model, CONTEXT_WINDOWS, |entry| entry.0)
+        .map(|&(_, window)| window)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Prompt budget for a run: the configured override, or the model's window less
+/// room for the reply
+pub fn budget_for(config: &AgentConfig) -> usize {
+    if let Some(budget) = config.context_budget {
+        return budget as usize;
+    }
+    let window = context_window(&config.model);
+    window
+        .saturating_sub(config.max_tokens as usize)
+        .max(window / 2)
+}
+
+/// Rough token count of one message
+pub fn estimate_tokens(message: &Message) -> usize {
+    let mut chars = message.content.as_deref().map_or(0, str::len);
+    for call in message.tool_calls.iter().flatten() {
+        chars += call.function.name.len() + call.function.arguments.len();
+    }
+    chars / 4 + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// Elide the oldest tool results until `messages` fits in `budget` tokens.
+/// Returns `None` when nothing had to change.
+pub fn fit_to_budget(messages: &mut [Message], budget: usize) -> Option<Truncation> {
+    let tokens_before: usize = messages.iter().map(estimate_tokens).sum();
+    if tokens_before <= budget {
+        return None;
+    }
+
+    // Everything from the start of the last few assistant turns is kept
+    let protected_from = messages
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, m)| m.role == MessageRole::Assistant)
+        .nth(KEEP_RECENT_TURNS - 1)
+        .map_or(0, |(i, _)| i);
+
+    let mut tokens = tokens_before;
+    let mut elided_messages = 0;
+    for message in messages[..protected_from].iter_mut() {
+        if tokens <= budget {
+            break;
+        }
+        if message.role != MessageRole::Tool {
+            continue;
+        }
+        let Some(content) = message.content.as_deref() else {
+            continue;
+        };
+        if content.starts_with(ELIDED_PREFIX) {
+            continue;
+        }
+
+        let before = estimate_tokens(message);
+        message.content = Some(format!(
+            "{}, {} tokens]",
+            ELIDED_PREFIX,
+            group_thousands(before - MESSAGE_OVERHEAD_TOKENS)
+        ));
+        tokens = tokens - before + estimate_tokens(message);
+        elided_messages += 1;
+    }
+
+    if tokens > budget {
+        log::warn!(
+            "Conversation is still ~{} tokens after eliding old tool output (budget {})",
+            tokens,
+            budget
+        );
+    }
+    (elided_messages > 0).then_some(Truncation {
+        elided_messages,
+        tokens_before,
+        tokens_after: tokens,
+    })
+}
+
+/// `5200` -> `"5,200"`
+fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::types::{FunctionCall, ToolCall};
+
+    fn call(id: &str) -> Message {
+        Message::assistant_with_tools(
+            None,
+            vec![ToolCall {
+                id: id.to_string(),
+                call_type: "function".to_string(),
+                function: FunctionCall {
+                    name: "read_file".to_string(),
+                    arguments: "{}".to_string(),
+                },
+            }],
+        )
+    }
+
+    /// System prompt, task, then `turns` read_file turns with 4,000-token results
+    fn conversation(turns: usize) -> Vec<Message> {
+        let mut messages = vec![
+            Message::system("You are a writing assistant."),
+            Message::user(&"Summarize the book. ".repeat(100)),
+        ];
+        for i in 0..turns {
+            let id = format!("call-{}", i);
+            messages.push(call(&id));
+            messages.push(Message::tool_result(&id, &"x".repeat(16_000)));
+        }
+        messages
+    }
+
+    fn total(messages: &[Message]) -> usize {
+        messages.iter().map(estimate_tokens).sum()
+    }
+
+    #[test]
+    fn test_context_window_table() {
+        assert_eq!(context_window("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("claude-sonnet-4-20250514"), 200_000);
+        assert_eq!(context_window("anthropic/claude-3.5-haiku"), 200_000);
+        assert_eq!(context_window("llama3.1"), DEFAULT_CONTEXT_WINDOW);
+
+        let config = AgentConfig {
+            model: "gpt-4o".to_string(),
+            max_tokens: 4096,
+            ..AgentConfig::default()
+        };
+        assert_eq!(budget_for(&config), 128_000 - 4096);
+        let config = AgentConfig {
+            context_budget: Some(50_000),
+            ..config
+        };
+        assert_eq!(budget_for(&config), 50_000);
+    }
+
+    #[test]
+    fn test_under_budget_is_untouched() {
+        let mut messages = conversation(3);
+        assert_eq!(fit_to_budget(&mut messages, 100_000), None);
+        assert_eq!(messages[3].content.as_deref().unwrap().len(), 16_000);
+    }
+
+    #[test]
+    fn test_oldest_tool_results_are_elided_first() {
+        let mut messages = conversation(5);
+        let before = total(&messages);
+
+        let truncation = fit_to_budget(&mut messages, before - 6_000).unwrap();
+        assert_eq!(truncation.elided_messages, 2);
+        assert_eq!(truncation.tokens_before, before);
+        assert_eq!(truncation.tokens_after, total(&messages));
+        assert!(truncation.tokens_after <= before - 6_000);
+
+        assert_eq!(
+            messages[3].content.as_deref(),
+            Some("[output elided, 4,000 tokens]")
+        );
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("call-0"));
+        assert!(messages[5]
+            .content
+            .as_deref()
+            .unwrap()
+            .starts_with(ELIDED_PREFIX));
+        assert_eq!(messages[7].content.as_deref().unwrap().len(), 16_000);
+    }
+
+    #[test]
+    fn test_task_and_recent_turns_are_kept() {
+        let mut messages = conversation(3);
+        let task = messages[1].content.clone();
+
+        let truncation = fit_to_budget(&mut messages, 10).unwrap();
+        // Only the first turn is old enough to elide
+        assert_eq!(truncation.elided_messages, 1);
+        assert!(truncation.tokens_after > 10);
+        assert_eq!(
+            messages[0].content.as_deref(),
+            Some("You are a writing assistant.")
+        );
+        assert_eq!(messages[1].content, task);
+        assert_eq!(messages[5].content.as_deref().unwrap().len(), 16_000);
+        assert_eq!(messages[7].content.as_deref().unwrap().len(), 16_000);
+
+        // Elided stubs aren't elided again
+        assert_eq!(fit_to_budget(&mut messages, 10), None);
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(5_200), "5,200");
+        assert_eq!(group_thousands(1_234_567), "1,234,567");
+    }
+}
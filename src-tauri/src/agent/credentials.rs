@@ -1,42 +1,94 @@
 //! Credential management for the native agent.
 //!
-//! This module provides fallback API key management via environment variables.
 //! The primary source of API keys is the frontend Settings UI (stored in localStorage).
-//! Environment variables serve as a fallback when no UI-provided key is available.
+//! When a run arrives without one, the key is looked up in the OS keychain and then
+//! in the provider's environment variable. Keys saved with `set_provider_api_key`
+//! go to the keychain; where no keychain is available (e.g. headless Linux) only
+//! environment variables are used. Keys are never sent back to the frontend.
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Arc;
 
 use super::types::LlmProvider;
 
+/// Keychain service the app's keys are stored under
+pub const KEYCHAIN_SERVICE: &str = "com.vswrite.vswrite";
+
+// ============================================================================
+// Secret Store
+// ============================================================================
+
+/// Persistent storage for API keys, one secret per account name
+pub trait SecretStore: Send + Sync + fmt::Debug {
+    /// The stored secret, `None` if there isn't one
+    fn get(&self, account: &str) -> Result<Option<String>, String>;
+    fn set(&self, account: &str, secret: &str) -> Result<(), String>;
+    /// Remove a secret; removing one that doesn't exist is not an error
+    fn delete(&self, account: &str) -> Result<(), String>;
+}
+
+/// The OS keychain (macOS Keychain, Windows Credential Manager, Secret Service)
+#[derive(Debug, Default)]
+pub struct OsKeychain;
+
+impl OsKeychain {
+    fn entry(account: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYCHAIN_SERVICE, account)
+            .map_err(|e| format!("Keychain unavailable: {}", e))
+    }
+}
+
+impl SecretStore for OsKeychain {
+    fn get(&self, account: &str) -> Result<Option<String>, String> {
+        match Self::entry(account)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(format!("Keychain unavailable: {}", e)),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+        Self::entry(account)?
+            .set_password(secret)
+            .map_err(|e| format!("Failed to save to the keychain: {}", e))
+    }
+
+    fn delete(&self, account: &str) -> Result<(), String> {
+        match Self::entry(account)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(format!("Failed to remove from the keychain: {}", e)),
+        }
+    }
+}
+
 // ============================================================================
 // Credential Manager
 // ============================================================================
 
 /// Manages API credentials for LLM providers.
-/// Keys are loaded from environment variables and never exposed to frontend.
-#[derive(Debug, Clone)]
+/// Keys are read from the keychain or environment and never exposed to frontend.
+#[derive(Debug, Clone, Default)]
 pub struct CredentialManager {
-    // Keys are read on-demand from environment
-    // This allows hot-reloading if env vars change
+    // Keys are read on demand, so keychain and env var changes apply immediately
+    keychain: Option<Arc<dyn SecretStore>>,
 }
 
 impl CredentialManager {
-    /// Create a new credential manager
+    /// Create a credential manager that only reads environment variables
     pub fn new() -> Self {
-        CredentialManager {}
+        CredentialManager { keychain: None }
     }
 
-    /// Get the API key for a provider (if configured)
-    pub fn get_key(&self, provider: LlmProvider) -> Option<String> {
-        let env_var = match provider {
-            LlmProvider::OpenAI => "OPENAI_API_KEY",
-            LlmProvider::Claude => "ANTHROPIC_API_KEY",
-            LlmProvider::OpenRouter => "OPENROUTER_API_KEY",
-            LlmProvider::Ollama => return Some(String::new()), // Ollama doesn't need a key
-        };
+    /// Also store and look up keys in `keychain`
+    pub fn with_keychain(mut self, keychain: Arc<dyn SecretStore>) -> Self {
+        self.keychain = Some(keychain);
+        self
+    }
 
-        std::env::var(env_var).ok().filter(|k| !k.is_empty())
+    /// Get the API key for a provider (if configured): keychain first, then env var
+    pub fn get_key(&self, provider: LlmProvider) -> Option<String> {
+        self.lookup(provider).map(|(key, _)| key)
     }
 
     /// Check if a provider has credentials configured
@@ -47,40 +99,126 @@ impl CredentialManager {
         }
     }
 
+    /// Where a provider's key would come from
+    pub fn key_source(&self, provider: LlmProvider) -> KeySource {
+        match provider {
+            LlmProvider::Ollama => KeySource::None,
+            _ => self
+                .lookup(provider)
+                .map_or(KeySource::None, |(_, source)| source),
+        }
+    }
+
+    /// Save a provider's key in the keychain
+    pub fn set_key(&self, provider: LlmProvider, key: &str) -> Result<(), String> {
+        let account = keychain_account(provider)
+            .ok_or_else(|| format!("{:?} doesn't use an API key", provider))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err("API key cannot be empty".to_string());
+        }
+        self.require_keychain(provider)?.set(account, key)
+    }
+
+    /// Remove a provider's key from the keychain (environment variables are untouched)
+    pub fn clear_key(&self, provider: LlmProvider) -> Result<(), String> {
+        let account = keychain_account(provider)
+            .ok_or_else(|| format!("{:?} doesn't use an API key", provider))?;
+        self.require_keychain(provider)?.delete(account)
+    }
+
+    /// Whether the keychain can be read. `Ok` when no keychain is configured.
+    pub fn keychain_status(&self) -> Result<(), String> {
+        match self.keychain {
+            Some(ref keychain) => keychain.get(PROBE_ACCOUNT).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
     /// Get status of all providers
     pub fn get_provider_status(&self) -> Vec<ProviderStatus> {
         vec![
             ProviderStatus {
                 provider: LlmProvider::OpenAI,
                 available: self.has_key(LlmProvider::OpenAI),
+                source: self.key_source(LlmProvider::OpenAI),
                 default_model: "gpt-5-mini".to_string(),
                 env_var: "OPENAI_API_KEY".to_string(),
             },
             ProviderStatus {
                 provider: LlmProvider::Claude,
                 available: self.has_key(LlmProvider::Claude),
+                source: self.key_source(LlmProvider::Claude),
                 default_model: "claude-sonnet-4-20250514".to_string(),
                 env_var: "ANTHROPIC_API_KEY".to_string(),
             },
             ProviderStatus {
                 provider: LlmProvider::OpenRouter,
                 available: self.has_key(LlmProvider::OpenRouter),
+                source: self.key_source(LlmProvider::OpenRouter),
                 default_model: "openai/gpt-4o-mini".to_string(),
                 env_var: "OPENROUTER_API_KEY".to_string(),
             },
             ProviderStatus {
                 provider: LlmProvider::Ollama,
                 available: true, // Ollama is always "available" (may not be running though)
+                source: KeySource::None,
                 default_model: "llama3.2".to_string(),
                 env_var: String::new(),
             },
         ]
     }
+
+    /// The key and where it came from. Ollama needs no key and gets an empty one.
+    fn lookup(&self, provider: LlmProvider) -> Option<(String, KeySource)> {
+        let (Some(account), Some(env_var)) = (keychain_account(provider), env_var(provider)) else {
+            return Some((String::new(), KeySource::None));
+        };
+
+        if let Some(ref keychain) = self.keychain {
+            match keychain.get(account) {
+                Ok(Some(key)) if !key.is_empty() => return Some((key, KeySource::Keychain)),
+                Ok(_) => {}
+                Err(e) => log::warn!("Falling back to {}: {}", env_var, e),
+            }
+        }
+
+        std::env::var(env_var)
+            .ok()
+            .filter(|k| !k.is_empty())
+            .map(|key| (key, KeySource::Env))
+    }
+
+    fn require_keychain(&self, provider: LlmProvider) -> Result<&Arc<dyn SecretStore>, String> {
+        self.keychain.as_ref().ok_or_else(|| {
+            format!(
+                "No OS keychain is available; set {} instead",
+                env_var(provider).unwrap_or("an environment variable")
+            )
+        })
+    }
+}
+
+/// Keychain account read by [`CredentialManager::keychain_status`]
+const PROBE_ACCOUNT: &str = "openai";
+
+/// Keychain account holding a provider's key (`None` for Ollama)
+fn keychain_account(provider: LlmProvider) -> Option<&'static str> {
+    match provider {
+        LlmProvider::OpenAI => Some("openai"),
+        LlmProvider::Claude => Some("claude"),
+        LlmProvider::OpenRouter => Some("openrouter"),
+        LlmProvider::Ollama => None,
+    }
 }
 
-impl Default for CredentialManager {
-    fn default() -> Self {
-        Self::new()
+/// Environment variable holding a provider's key (`None` for Ollama)
+fn env_var(provider: LlmProvider) -> Option<&'static str> {
+    match provider {
+        LlmProvider::OpenAI => Some("OPENAI_API_KEY"),
+        LlmProvider::Claude => Some("ANTHROPIC_API_KEY"),
+        LlmProvider::OpenRouter => Some("OPENROUTER_API_KEY"),
+        LlmProvider::Ollama => None,
     }
 }
 
@@ -91,6 +229,15 @@ pub type SharedCredentialManager = Arc<CredentialManager>;
 // Provider Status
 // ============================================================================
 
+/// Where a provider's fallback key comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    Keychain,
+    Env,
+    None,
+}
+
 /// Status of a single LLM provider
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderStatus {
@@ -98,6 +245,8 @@ pub struct ProviderStatus {
     pub provider: LlmProvider,
     /// Whether the provider has credentials configured
     pub available: bool,
+    /// Where the key was found (never the key itself)
+    pub source: KeySource,
     /// Default model for this provider
     pub default_model: String,
     /// Environment variable name for the API key (empty for Ollama)
@@ -137,4 +286,99 @@ mod tests {
         assert!(cm.has_key(LlmProvider::Ollama));
         assert_eq!(cm.get_key(LlmProvider::Ollama), Some(String::new()));
     }
+
+    /// In-memory keychain; `broken` makes every call fail like a missing Secret Service
+    #[derive(Debug, Default)]
+    struct MemoryStore {
+        secrets: std::sync::Mutex<std::collections::HashMap<String, String>>,
+        broken: bool,
+    }
+
+    impl SecretStore for MemoryStore {
+        fn get(&self, account: &str) -> Result<Option<String>, String> {
+            if self.broken {
+                return Err("Keychain unavailable: no secret service".to_string());
+            }
+            Ok(self.secrets.lock().unwrap().get(account).cloned())
+        }
+
+        fn set(&self, account: &str, secret: &str) -> Result<(), String> {
+            if self.broken {
+                return Err("Failed to save to the keychain".to_string());
+            }
+            self.secrets
+                .lock()
+                .unwrap()
+                .insert(account.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&self, account: &str) -> Result<(), String> {
+            self.secrets.lock().unwrap().remove(account);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_keychain_key_is_used_first() {
+        let store = Arc::new(MemoryStore::default());
+        let cm = CredentialManager::new().with_keychain(store.clone());
+
+        cm.set_key(LlmProvider::Claude, "  sk-ant-test \n").unwrap();
+        assert_eq!(
+            store
+                .secrets
+                .lock()
+                .unwrap()
+                .get("claude")
+                .map(String::as_str),
+            Some("sk-ant-test")
+        );
+        assert_eq!(
+            cm.get_key(LlmProvider::Claude).as_deref(),
+            Some("sk-ant-test")
+        );
+        assert_eq!(cm.key_source(LlmProvider::Claude), KeySource::Keychain);
+
+        let status = cm.get_provider_status();
+        let claude = status
+            .iter()
+            .find(|s| s.provider == LlmProvider::Claude)
+            .unwrap();
+        assert!(claude.available);
+        assert_eq!(claude.source, KeySource::Keychain);
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(!json.contains("sk-ant-test"));
+        assert!(json.contains("\"source\":\"keychain\""));
+
+        cm.clear_key(LlmProvider::Claude).unwrap();
+        assert_ne!(cm.key_source(LlmProvider::Claude), KeySource::Keychain);
+        // Clearing twice is fine
+        cm.clear_key(LlmProvider::Claude).unwrap();
+    }
+
+    #[test]
+    fn test_set_key_rejections() {
+        let cm = CredentialManager::new().with_keychain(Arc::new(MemoryStore::default()));
+        assert!(cm.set_key(LlmProvider::OpenAI, "   ").is_err());
+        assert!(cm.set_key(LlmProvider::Ollama, "key").is_err());
+
+        let err = CredentialManager::new()
+            .set_key(LlmProvider::OpenRouter, "key")
+            .unwrap_err();
+        assert!(err.contains("OPENROUTER_API_KEY"), "{}", err);
+    }
+
+    #[test]
+    fn test_unavailable_keychain_degrades_to_env() {
+        let cm = CredentialManager::new().with_keychain(Arc::new(MemoryStore {
+            broken: true,
+            ..Default::default()
+        }));
+        assert!(cm.keychain_status().is_err());
+        assert!(cm.set_key(LlmProvider::OpenAI, "sk-test").is_err());
+        // Lookups fall through to the environment instead of failing
+        assert_ne!(cm.key_source(LlmProvider::OpenAI), KeySource::Keychain);
+        assert!(CredentialManager::new().keychain_status().is_ok());
+    }
 }
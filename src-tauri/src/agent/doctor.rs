@@ -2,13 +2,44 @@
 //!
 //! This module provides diagnostics to identify configuration issues,
 //! security risks, and other problems before they cause runtime errors.
+//!
+//! A lighter probe runs shortly after launch so problems show up before the
+//! first agent run; its report is cached in [`HealthCache`] and served by
+//! `run_agent_health_check` until it goes stale.
+//!
+//! [`check_workspace`] is a separate pass over one project's files.
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 use super::credentials::CredentialManager;
-use super::lua_extensions::ExtensionRegistry;
+use super::entity_api::{parse_section_file, read_entity_file};
+use super::entity_tags::utf16_len;
+use super::extension_stats::SLOW_EXTENSION_P95;
+use super::global_config::SharedGlobalConfig;
+use super::idle::{Clock, SystemClock};
+use super::lua_extensions::{ExtensionRegistry, ExtensionSmokeTest};
+use super::ollama::{self, OllamaStatus};
+use super::redact::mask_key;
 use super::types::LlmProvider;
 
+/// How long the Ollama reachability check waits for a reply
+const OLLAMA_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long each provider connectivity probe waits for a reply
+const PROVIDER_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long one extension script may take to load in the smoke test
+const EXTENSION_SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+
 // ============================================================================
 // Health Check Types
 // ============================================================================
@@ -39,6 +70,8 @@ pub enum IssueCategory {
     Security,
     /// Runtime environment issues
     Environment,
+    /// Services the agent talks to are unreachable
+    Connectivity,
 }
 
 /// A single health issue
@@ -71,17 +104,50 @@ impl HealthIssue {
     }
 }
 
+/// Which checks a health run covers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthScope {
+    /// The launch probe: credentials, extensions, and Ollama reachability
+    Startup,
+    /// Every check
+    #[default]
+    Full,
+}
+
+impl HealthScope {
+    /// Whether a report of this scope has every check `other` runs
+    fn covers(self, other: HealthScope) -> bool {
+        self == HealthScope::Full || self == other
+    }
+}
+
 /// Complete health report
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HealthReport {
     /// Whether the agent is healthy (no errors)
     pub healthy: bool,
+    /// Which checks produced this report
+    #[serde(default)]
+    pub scope: HealthScope,
     /// List of issues found
     pub issues: Vec<HealthIssue>,
     /// When the check was performed
     pub checked_at: String,
     /// Summary counts
     pub summary: HealthSummary,
+    /// Provider endpoint probes, when the network was probed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub connectivity: Vec<ConnectivityResult>,
+    /// Tools turned off in the agent settings the caller passed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled_tools: Vec<String>,
+    /// Models on the local Ollama server, when it was probed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ollama: Option<OllamaStatus>,
+    /// Per-tool and per-hook results of loading each extension's scripts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extension_checks: Vec<ExtensionSmokeTest>,
 }
 
 /// Summary of health check results
@@ -93,14 +159,56 @@ pub struct HealthSummary {
     pub info: usize,
 }
 
+/// A provider endpoint to probe
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProbeTarget {
+    pub provider: LlmProvider,
+    /// Defaults to the provider's standard URL
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Sent to check the key is accepted; falls back to the environment key
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+}
+
+impl fmt::Debug for ProbeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProbeTarget")
+            .field("provider", &self.provider)
+            .field("base_url", &self.base_url)
+            .field("api_key", &self.api_key.as_deref().map(mask_key))
+            .finish()
+    }
+}
+
+/// Outcome of probing one provider endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectivityResult {
+    pub provider: LlmProvider,
+    /// The URL that was requested
+    pub url: String,
+    /// Whether the server answered at all (even with an error status)
+    pub reachable: bool,
+    /// Time until the response arrived
+    pub latency_ms: Option<u64>,
+    /// HTTP status of the response
+    pub status: Option<u16>,
+    /// What went wrong, if anything
+    pub error: Option<String>,
+}
+
 // ============================================================================
 // Health Check Implementation
 // ============================================================================
 
-/// Run a comprehensive health check
-pub fn run_health_check(
+/// Run the checks in `scope`. Ollama reachability is probed at `ollama_url`
+/// when given; the extension registry is only locked while its check runs
+/// (a full check smoke-tests the scripts of a snapshot of it).
+pub async fn run_health_check(
     credentials: &CredentialManager,
-    extensions: &ExtensionRegistry,
+    extensions: &RwLock<Arc<ExtensionRegistry>>,
+    scope: HealthScope,
+    ollama_url: Option<&str>,
 ) -> HealthReport {
     let mut issues = Vec::new();
 
@@ -108,38 +216,322 @@ pub fn run_health_check(
     check_credentials(credentials, &mut issues);
 
     // Check extensions
-    check_extensions(extensions, &mut issues);
+    let snapshot = match extensions.read() {
+        Ok(registry) => {
+            check_extensions(&registry, &mut issues);
+            (scope == HealthScope::Full && !registry.list_extensions().is_empty())
+                .then(|| Arc::clone(&registry))
+        }
+        Err(e) => {
+            issues.push(HealthIssue::new(
+                IssueSeverity::Error,
+                IssueCategory::Extensions,
+                format!("Extension registry is unavailable: {}", e),
+                "Restart the app to reload extensions",
+            ));
+            None
+        }
+    };
+    let extension_checks = match snapshot {
+        Some(registry) => smoke_test_extensions(registry, &mut issues).await,
+        None => Vec::new(),
+    };
 
     // Check environment
-    check_environment(&mut issues);
+    if scope == HealthScope::Full {
+        check_environment(&mut issues);
+    }
 
-    // Calculate summary
-    let errors = issues
-        .iter()
-        .filter(|i| i.severity == IssueSeverity::Error)
-        .count();
-    let warnings = issues
-        .iter()
-        .filter(|i| i.severity == IssueSeverity::Warning)
-        .count();
-    let info = issues
-        .iter()
-        .filter(|i| i.severity == IssueSeverity::Info)
-        .count();
+    // Check that a local Ollama server answers
+    if let Some(url) = ollama_url {
+        check_ollama(url, &mut issues).await;
+    }
 
     HealthReport {
-        healthy: errors == 0,
-        issues,
-        checked_at: chrono::Utc::now().to_rfc3339(),
-        summary: HealthSummary {
-            total_issues: errors + warnings + info,
-            errors,
-            warnings,
-            info,
-        },
+        extension_checks,
+        ..HealthReport::from_issues(issues, scope)
+    }
+}
+
+impl HealthReport {
+    /// Build a report (and its summary) from the issues found
+    fn from_issues(issues: Vec<HealthIssue>, scope: HealthScope) -> Self {
+        let errors = issues
+            .iter()
+            .filter(|i| i.severity == IssueSeverity::Error)
+            .count();
+        let warnings = issues
+            .iter()
+            .filter(|i| i.severity == IssueSeverity::Warning)
+            .count();
+        let info = issues
+            .iter()
+            .filter(|i| i.severity == IssueSeverity::Info)
+            .count();
+
+        HealthReport {
+            healthy: errors == 0,
+            scope,
+            issues,
+            checked_at: chrono::Utc::now().to_rfc3339(),
+            summary: HealthSummary {
+                total_issues: errors + warnings + info,
+                errors,
+                warnings,
+                info,
+            },
+            connectivity: Vec::new(),
+            disabled_tools: Vec::new(),
+            ollama: None,
+            extension_checks: Vec::new(),
+        }
+    }
+
+    /// The same report with `issues` in place of its own, and a new summary
+    fn with_issues(self, issues: Vec<HealthIssue>) -> Self {
+        HealthReport {
+            connectivity: self.connectivity,
+            disabled_tools: self.disabled_tools,
+            ollama: self.ollama,
+            extension_checks: self.extension_checks,
+            ..HealthReport::from_issues(issues, self.scope)
+        }
+    }
+
+    /// Add provider probe results, reporting the failed ones as issues
+    pub fn with_connectivity(mut self, results: Vec<ConnectivityResult>) -> Self {
+        let mut issues = std::mem::take(&mut self.issues);
+        issues.extend(results.iter().filter_map(connectivity_issue));
+        self.connectivity = results;
+        self.with_issues(issues)
+    }
+
+    /// Note which tools are turned off, so a missing tool isn't mistaken for a fault
+    pub fn with_disabled_tools(mut self, disabled_tools: Vec<String>) -> Self {
+        if disabled_tools.is_empty() {
+            return self;
+        }
+        let mut issues = std::mem::take(&mut self.issues);
+        issues.push(HealthIssue::new(
+            IssueSeverity::Info,
+            IssueCategory::Configuration,
+            format!("Tools turned off: {}", disabled_tools.join(", ")),
+            "Remove them from disabled_tools in the agent settings to let the agent use them again",
+        ));
+        self.disabled_tools = disabled_tools;
+        self.with_issues(issues)
+    }
+
+    /// Add the Ollama model listing, reporting a missing configured model or an
+    /// empty server. An unreachable server is left to the connectivity probe.
+    pub fn with_ollama(mut self, status: OllamaStatus) -> Self {
+        let mut issues = std::mem::take(&mut self.issues);
+        if status.reachable {
+            if let Some(error) = &status.error {
+                issues.push(HealthIssue::new(
+                    IssueSeverity::Warning,
+                    IssueCategory::Connectivity,
+                    format!(
+                        "Ollama at {} could not list its models: {}",
+                        status.base_url, error
+                    ),
+                    "Check that the base URL points at an Ollama server",
+                ));
+            } else if let (Some(model), Some(false)) = (&status.model, status.model_installed) {
+                issues.push(HealthIssue::new(
+                    IssueSeverity::Warning,
+                    IssueCategory::Configuration,
+                    format!("Ollama model '{}' is not installed", model),
+                    format!("Pull it from the app or run `ollama pull {}`", model),
+                ));
+            } else if status.models.is_empty() {
+                issues.push(HealthIssue::new(
+                    IssueSeverity::Info,
+                    IssueCategory::Configuration,
+                    format!("Ollama at {} has no models installed", status.base_url),
+                    "Pull a model before choosing Ollama as the provider",
+                ));
+            }
+        }
+        self.ollama = Some(status);
+        self.with_issues(issues)
+    }
+}
+
+// ============================================================================
+// Connectivity Probes
+// ============================================================================
+
+/// Targets to probe when the caller doesn't name any: providers with an
+/// keychain or environment key, plus a local Ollama server
+pub fn default_probe_targets(credentials: &CredentialManager) -> Vec<ProbeTarget> {
+    [
+        LlmProvider::OpenAI,
+        LlmProvider::Claude,
+        LlmProvider::OpenRouter,
+    ]
+    .into_iter()
+    .filter(|&provider| credentials.has_key(provider))
+    .chain([LlmProvider::Ollama])
+    .map(|provider| ProbeTarget {
+        provider,
+        base_url: None,
+        api_key: None,
+    })
+    .collect()
+}
+
+/// Probe every target concurrently, each with a short timeout. Results are in
+/// the order of `targets`.
+pub async fn probe_providers(
+    targets: Vec<ProbeTarget>,
+    credentials: &CredentialManager,
+) -> Vec<ConnectivityResult> {
+    let client = match reqwest::Client::builder()
+        .timeout(PROVIDER_PROBE_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("Could not build HTTP client for connectivity probes: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let handles: Vec<_> = targets
+        .into_iter()
+        .map(|mut target| {
+            target.api_key = target
+                .api_key
+                .or_else(|| credentials.get_key(target.provider))
+                .filter(|key| !key.is_empty());
+            let client = client.clone();
+            let url = probe_url(&target);
+            (
+                target.provider,
+                url.clone(),
+                tokio::spawn(probe(client, target, url)),
+            )
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for (provider, url, handle) in handles {
+        results.push(handle.await.unwrap_or_else(|e| ConnectivityResult {
+            provider,
+            url,
+            reachable: false,
+            latency_ms: None,
+            status: None,
+            error: Some(format!("Probe failed: {}", e)),
+        }));
+    }
+    results
+}
+
+/// List the models of the Ollama server among `targets`, if one is there, and
+/// whether `model` is installed
+pub async fn probe_ollama_models(
+    targets: &[ProbeTarget],
+    model: Option<&str>,
+) -> Option<OllamaStatus> {
+    let target = targets
+        .iter()
+        .find(|target| target.provider == LlmProvider::Ollama)?;
+    Some(ollama::list_models(&reqwest::Client::new(), target_base_url(target), model).await)
+}
+
+/// The target's base URL, or the provider's standard one
+fn target_base_url(target: &ProbeTarget) -> &str {
+    target
+        .base_url
+        .as_deref()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or(target.provider.default_base_url())
+        .trim_end_matches('/')
+}
+
+/// The cheapest endpoint that shows the provider is up
+fn probe_url(target: &ProbeTarget) -> String {
+    let base = target_base_url(target);
+    match target.provider {
+        LlmProvider::Ollama => format!("{}/api/tags", base),
+        LlmProvider::OpenAI | LlmProvider::Claude | LlmProvider::OpenRouter => {
+            format!("{}/models", base)
+        }
+    }
+}
+
+async fn probe(client: reqwest::Client, target: ProbeTarget, url: String) -> ConnectivityResult {
+    let mut request = client.get(&url);
+    if let Some(key) = target.api_key.as_deref() {
+        request = match target.provider {
+            LlmProvider::Claude => request
+                .header("x-api-key", key)
+                .header("anthropic-version", super::llm::ANTHROPIC_VERSION),
+            _ => request.bearer_auth(key),
+        };
+    }
+
+    let started = Instant::now();
+    let response = request.send().await;
+    let latency_ms = Some(started.elapsed().as_millis() as u64);
+    let (reachable, status, error) = match response {
+        Ok(response) if response.status().is_success() => {
+            (true, Some(response.status().as_u16()), None)
+        }
+        Ok(response) => {
+            let status = response.status();
+            let error = match status.as_u16() {
+                // Without a key to check, refusing the request still shows the server is up
+                401 | 403 if target.api_key.is_none() => None,
+                401 | 403 => Some(format!("HTTP {}: the API key was rejected", status)),
+                _ => Some(format!("HTTP {}", status)),
+            };
+            (true, Some(status.as_u16()), error)
+        }
+        Err(e) if e.is_timeout() => (
+            false,
+            None,
+            Some(format!(
+                "No response within {} s",
+                PROVIDER_PROBE_TIMEOUT.as_secs()
+            )),
+        ),
+        Err(e) => (false, None, Some(format!("Not reachable: {}", e))),
+    };
+
+    ConnectivityResult {
+        provider: target.provider,
+        url,
+        reachable,
+        latency_ms: latency_ms.filter(|_| reachable),
+        status,
+        error,
     }
 }
 
+/// The issue to report for a failed probe
+fn connectivity_issue(result: &ConnectivityResult) -> Option<HealthIssue> {
+    let error = result.error.as_deref()?;
+    let remediation = match (result.provider, result.reachable) {
+        (LlmProvider::Ollama, false) => {
+            "Start Ollama (`ollama serve`) or check its base URL in Settings"
+        }
+        (_, false) => "Check the provider's base URL in Settings and your network connection",
+        (_, true) if matches!(result.status, Some(401 | 403)) => {
+            "Check the provider's API key in Settings"
+        }
+        (_, true) => "Check the provider's base URL in Settings; the server answered with an error",
+    };
+    Some(HealthIssue::new(
+        IssueSeverity::Warning,
+        IssueCategory::Connectivity,
+        format!("{:?} at {}: {}", result.provider, result.url, error),
+        remediation,
+    ))
+}
+
 /// Check credential configuration
 fn check_credentials(credentials: &CredentialManager, issues: &mut Vec<HealthIssue>) {
     let providers = [
@@ -156,14 +548,24 @@ fn check_credentials(credentials: &CredentialManager, issues: &mut Vec<HealthIss
         }
     }
 
-    // No API keys configured via environment (fallback)
-    // Note: Primary source is frontend Settings UI, this only checks env var fallback
+    // Without a keychain, saved keys are unavailable and only env vars are used
+    if let Err(e) = credentials.keychain_status() {
+        issues.push(HealthIssue::new(
+            IssueSeverity::Warning,
+            IssueCategory::Credentials,
+            format!("OS keychain is not available: {}", e),
+            "Saved API keys can't be read. Keys entered in Settings and environment variables still work.",
+        ));
+    }
+
+    // No API keys configured via keychain or environment (fallback)
+    // Note: Primary source is frontend Settings UI, this only checks the fallbacks
     if !any_configured {
         issues.push(HealthIssue::new(
             IssueSeverity::Info,
             IssueCategory::Credentials,
-            "No fallback API keys in environment variables",
-            "API keys are primarily configured in Settings. Environment variables serve as fallback.",
+            "No fallback API keys in the keychain or environment variables",
+            "API keys are primarily configured in Settings. Saved keys and environment variables serve as fallback.",
         ));
     }
 
@@ -212,8 +614,9 @@ fn check_extensions(extensions: &ExtensionRegistry, issues: &mut Vec<HealthIssue
     let mut invalid_count = 0;
 
     for (ext_id, manifest_path) in manifest_paths {
-        match crate::extensions::verify_extension_signature(
-            manifest_path.to_string_lossy().to_string(),
+        match crate::extensions::verify_manifest_file(
+            &manifest_path.to_string_lossy(),
+            extensions.trust_store(),
         ) {
             Ok(verification) => {
                 if !verification.is_signed {
@@ -285,6 +688,106 @@ fn check_extensions(extensions: &ExtensionRegistry, issues: &mut Vec<HealthIssue
             "Consider removing unsigned or untrusted extensions for better security",
         ));
     }
+
+    check_extension_performance(extensions, issues);
+}
+
+/// Warn about extensions whose recent executions are slow
+fn check_extension_performance(extensions: &ExtensionRegistry, issues: &mut Vec<HealthIssue>) {
+    for summary in extensions.stats().summaries() {
+        if summary.is_slow() {
+            issues.push(HealthIssue::new(
+                IssueSeverity::Warning,
+                IssueCategory::Extensions,
+                format!(
+                    "Extension '{}' is slow: p95 {} ms over its last {} executions",
+                    summary.extension_id, summary.p95_ms, summary.window
+                ),
+                format!(
+                    "Extensions should finish within {} ms; check get_extension_stats and consider disabling it",
+                    SLOW_EXTENSION_P95.as_millis()
+                ),
+            ));
+        }
+    }
+}
+
+/// Load every extension's scripts in a scratch workspace, reporting each one
+/// that fails to load or doesn't define the function its manifest names
+async fn smoke_test_extensions(
+    registry: Arc<ExtensionRegistry>,
+    issues: &mut Vec<HealthIssue>,
+) -> Vec<ExtensionSmokeTest> {
+    let results =
+        tokio::task::spawn_blocking(move || registry.smoke_test(EXTENSION_SCRIPT_TIMEOUT))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result);
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            issues.push(HealthIssue::new(
+                IssueSeverity::Warning,
+                IssueCategory::Extensions,
+                format!("Could not smoke-test extension scripts: {}", e),
+                "Run the health check again; if it keeps failing, check the temp directory is writable",
+            ));
+            return Vec::new();
+        }
+    };
+
+    for test in results.iter().filter(|t| !t.passed) {
+        let failures: Vec<String> = test
+            .tools
+            .iter()
+            .chain(&test.hooks)
+            .filter_map(|check| {
+                check
+                    .error
+                    .as_ref()
+                    .map(|e| format!("{}: {}", check.name, e))
+            })
+            .collect();
+        issues.push(HealthIssue::new(
+            IssueSeverity::Error,
+            IssueCategory::Extensions,
+            format!(
+                "Extension '{}' failed to load: {}",
+                test.extension_id,
+                failures.join("; ")
+            ),
+            "Fix the script or unload the extension; agent runs that call it will fail",
+        ));
+    }
+    results
+}
+
+/// Check that the Ollama server at `base_url` responds
+async fn check_ollama(base_url: &str, issues: &mut Vec<HealthIssue>) {
+    let url = format!("{}/api/tags", base_url.trim_end_matches('/'));
+    let client = match reqwest::Client::builder()
+        .timeout(OLLAMA_PROBE_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::warn!("Could not build HTTP client for Ollama probe: {}", e);
+            return;
+        }
+    };
+
+    let problem = match client.get(&url).send().await {
+        Ok(response) if response.status().is_success() => return,
+        Ok(response) => format!("responded with {}", response.status()),
+        Err(e) if e.is_timeout() => "did not respond in time".to_string(),
+        Err(_) => "is not reachable".to_string(),
+    };
+    issues.push(HealthIssue::new(
+        IssueSeverity::Warning,
+        IssueCategory::Connectivity,
+        format!("Ollama at {} {}", base_url, problem),
+        "If you use local models, start Ollama (`ollama serve`) before running the agent",
+    ));
 }
 
 /// Check environment configuration
@@ -299,6 +802,19 @@ fn check_environment(issues: &mut Vec<HealthIssue>) {
         ));
     }
 
+    // run_shell only adds ~/.cargo/bin and ~/.local/bin to PATH when HOME is known
+    #[cfg(unix)]
+    {
+        if std::env::var("HOME").is_err() {
+            issues.push(HealthIssue::new(
+                IssueSeverity::Warning,
+                IssueCategory::Environment,
+                "HOME environment variable not set",
+                "run_shell won't find tools in ~/.cargo/bin or ~/.local/bin; use get_shell_environment to inspect its PATH",
+            ));
+        }
+    }
+
     // Check for common issues on Windows
     #[cfg(windows)]
     {
@@ -314,6 +830,362 @@ fn check_environment(issues: &mut Vec<HealthIssue>) {
     }
 }
 
+// ============================================================================
+// Workspace Diagnostics
+// ============================================================================
+
+/// Kind of problem found in a workspace's files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceIssueCode {
+    /// No `project.yaml` at the workspace root
+    MissingProjectFile,
+    /// No `sections/` directory
+    MissingSectionsDir,
+    /// An entity file whose YAML doesn't parse
+    InvalidEntityFile,
+    /// A section file whose frontmatter is missing or doesn't parse
+    InvalidSectionFile,
+    /// Two entity files with the same ID
+    DuplicateEntityId,
+    /// Two section files with the same ID
+    DuplicateSectionId,
+    /// Sibling sections with the same `order`
+    DuplicateSectionOrder,
+    /// A section's `entity_ids` names an entity that doesn't exist
+    UnknownSectionEntity,
+    /// A tag points at an entity that doesn't exist
+    UnknownTagEntity,
+    /// A tag's range is empty or runs outside the section's content
+    TagOutOfRange,
+}
+
+/// One problem found by [`check_workspace`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceFinding {
+    pub severity: IssueSeverity,
+    pub code: WorkspaceIssueCode,
+    /// Workspace-relative file the problem is in, if it's in one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub message: String,
+    /// Whether the problem could be repaired without a person deciding how
+    pub fixable: bool,
+}
+
+impl WorkspaceFinding {
+    fn new(
+        severity: IssueSeverity,
+        code: WorkspaceIssueCode,
+        path: Option<&str>,
+        message: impl Into<String>,
+    ) -> Self {
+        use WorkspaceIssueCode::*;
+        WorkspaceFinding {
+            severity,
+            code,
+            path: path.map(str::to_string),
+            message: message.into(),
+            fixable: !matches!(
+                code,
+                MissingProjectFile | InvalidEntityFile | InvalidSectionFile
+            ),
+        }
+    }
+}
+
+/// Check a workspace's project file, entities, and sections for problems
+/// that break loading or leave dangling references. Findings are ordered by
+/// check, then by file.
+pub fn check_workspace(workspace: &Path) -> Vec<WorkspaceFinding> {
+    use WorkspaceIssueCode::*;
+    let mut findings = Vec::new();
+
+    if !workspace.join("project.yaml").is_file() {
+        findings.push(WorkspaceFinding::new(
+            IssueSeverity::Error,
+            MissingProjectFile,
+            None,
+            "project.yaml is missing; this folder may not be a VS Write project",
+        ));
+    }
+    if !workspace.join("sections").is_dir() {
+        findings.push(WorkspaceFinding::new(
+            IssueSeverity::Warning,
+            MissingSectionsDir,
+            None,
+            "The sections/ directory is missing",
+        ));
+    }
+
+    // Entities
+    let mut entity_ids: HashMap<String, String> = HashMap::new();
+    for (relative, path) in workspace_files(workspace, "entities", &["yaml", "yml"]) {
+        let entity = match read_entity_file(&path) {
+            Ok(entity) => entity,
+            Err(e) => {
+                findings.push(WorkspaceFinding::new(
+                    IssueSeverity::Error,
+                    InvalidEntityFile,
+                    Some(&relative),
+                    e,
+                ));
+                continue;
+            }
+        };
+        if let Some(first) = entity_ids.get(&entity.id) {
+            findings.push(WorkspaceFinding::new(
+                IssueSeverity::Error,
+                DuplicateEntityId,
+                Some(&relative),
+                format!("Entity ID {} is also used by {}", entity.id, first),
+            ));
+        } else {
+            entity_ids.insert(entity.id, relative);
+        }
+    }
+
+    // Sections
+    let mut section_ids: HashMap<String, String> = HashMap::new();
+    let mut orders: HashMap<(Option<String>, i64), String> = HashMap::new();
+    for (relative, path) in workspace_files(workspace, "sections", &["md"]) {
+        let (frontmatter, content) = match parse_section_file(&path) {
+            Ok(section) => section,
+            Err(e) => {
+                findings.push(WorkspaceFinding::new(
+                    IssueSeverity::Error,
+                    InvalidSectionFile,
+                    Some(&relative),
+                    e,
+                ));
+                continue;
+            }
+        };
+        let here = Some(relative.as_str());
+
+        if let Some(first) = section_ids.get(&frontmatter.id) {
+            findings.push(WorkspaceFinding::new(
+                IssueSeverity::Error,
+                DuplicateSectionId,
+                here,
+                format!("Section ID {} is also used by {}", frontmatter.id, first),
+            ));
+        } else {
+            section_ids.insert(frontmatter.id.clone(), relative.clone());
+        }
+
+        let parent = frontmatter.parent_id.clone().filter(|p| !p.is_empty());
+        if let Some(first) = orders.get(&(parent.clone(), frontmatter.order)) {
+            findings.push(WorkspaceFinding::new(
+                IssueSeverity::Warning,
+                DuplicateSectionOrder,
+                here,
+                format!(
+                    "Order {} is also used by sibling section {}",
+                    frontmatter.order, first
+                ),
+            ));
+        } else {
+            orders.insert((parent, frontmatter.order), relative.clone());
+        }
+
+        for entity_id in &frontmatter.entity_ids {
+            if !entity_ids.contains_key(entity_id) {
+                findings.push(WorkspaceFinding::new(
+                    IssueSeverity::Warning,
+                    UnknownSectionEntity,
+                    here,
+                    format!("Linked entity {} doesn't exist", entity_id),
+                ));
+            }
+        }
+
+        let len = utf16_len(&content);
+        for tag in &frontmatter.tags {
+            if !entity_ids.contains_key(&tag.entity_id) {
+                findings.push(WorkspaceFinding::new(
+                    IssueSeverity::Warning,
+                    UnknownTagEntity,
+                    here,
+                    format!("Tag {} points at missing entity {}", tag.id, tag.entity_id),
+                ));
+            }
+            if tag.from < 0 || tag.from >= tag.to || tag.to > len {
+                findings.push(WorkspaceFinding::new(
+                    IssueSeverity::Warning,
+                    TagOutOfRange,
+                    here,
+                    format!(
+                        "Tag {} covers {}..{}, outside the section's {} characters",
+                        tag.id, tag.from, tag.to, len
+                    ),
+                ));
+            }
+        }
+    }
+
+    findings
+}
+
+/// Files directly in `workspace/dir` with one of `extensions`, as
+/// (workspace-relative path, full path), sorted by path
+fn workspace_files(workspace: &Path, dir: &str, extensions: &[&str]) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = fs::read_dir(workspace.join(dir)) else {
+        return Vec::new();
+    };
+    let mut files: Vec<(String, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| extensions.contains(&e))
+        })
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            (format!("{}/{}", dir, name), path)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+// ============================================================================
+// Report Cache
+// ============================================================================
+
+struct CachedReport {
+    report: HealthReport,
+    stored_at: Instant,
+}
+
+/// The most recent health report, served while it is fresh
+pub struct HealthCache {
+    clock: Box<dyn Clock>,
+    /// Held across a refresh so concurrent callers wait for one check instead of
+    /// starting their own
+    entry: Mutex<Option<CachedReport>>,
+}
+
+impl HealthCache {
+    pub fn new() -> Self {
+        Self::with_clock(Box::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Box<dyn Clock>) -> Self {
+        HealthCache {
+            clock,
+            entry: Mutex::new(None),
+        }
+    }
+
+    /// The cached report if it is younger than `max_age` and covers `scope`;
+    /// otherwise run `check`, cache its report, and return it. `force` always
+    /// runs the check.
+    pub async fn get_or_refresh<F, Fut>(
+        &self,
+        scope: HealthScope,
+        max_age: Duration,
+        force: bool,
+        check: F,
+    ) -> HealthReport
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = HealthReport>,
+    {
+        let mut entry = self.entry.lock().await;
+        if !force {
+            if let Some(cached) = entry.as_ref() {
+                if cached.report.scope.covers(scope)
+                    && self.clock.now().duration_since(cached.stored_at) < max_age
+                {
+                    return cached.report.clone();
+                }
+            }
+        }
+
+        let report = check().await;
+        *entry = Some(CachedReport {
+            report: report.clone(),
+            stored_at: self.clock.now(),
+        });
+        report
+    }
+}
+
+impl Default for HealthCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shared health cache type for Tauri state
+pub type SharedHealthCache = Arc<HealthCache>;
+
+/// Cancels the startup probe (e.g. when the app exits before it finishes)
+#[derive(Debug, Clone, Default)]
+pub struct StartupProbe(CancellationToken);
+
+impl StartupProbe {
+    /// Token the probe task watches
+    pub fn token(&self) -> CancellationToken {
+        self.0.clone()
+    }
+
+    /// Stop the probe if it is still pending
+    pub fn cancel(&self) {
+        self.0.cancel();
+    }
+}
+
+/// Probe health shortly after launch and cache the report.
+///
+/// Returns `None` if probing is disabled or `cancel` fires first.
+pub async fn run_startup_probe(
+    credentials: Arc<CredentialManager>,
+    extensions: Arc<RwLock<Arc<ExtensionRegistry>>>,
+    cache: SharedHealthCache,
+    config: SharedGlobalConfig,
+    cancel: CancellationToken,
+) -> Option<HealthReport> {
+    let settings = config.read().ok()?.health_probe.clone();
+    if !settings.enabled {
+        return None;
+    }
+
+    let probe = async {
+        tokio::time::sleep(settings.startup_delay()).await;
+        let ollama_url = LlmProvider::Ollama.default_base_url();
+        cache
+            .get_or_refresh(HealthScope::Startup, settings.cache_ttl(), true, || {
+                run_health_check(
+                    &credentials,
+                    &extensions,
+                    HealthScope::Startup,
+                    Some(ollama_url),
+                )
+            })
+            .await
+    };
+
+    tokio::select! {
+        _ = cancel.cancelled() => {
+            log::info!("Startup health probe cancelled");
+            None
+        }
+        report = probe => {
+            log::info!(
+                "Startup health probe: {} error(s), {} warning(s)",
+                report.summary.errors,
+                report.summary.warnings
+            );
+            Some(report)
+        }
+    }
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -336,12 +1208,12 @@ mod tests {
         assert_eq!(issue.message, "Test message");
     }
 
-    #[test]
-    fn test_health_check_runs() {
+    #[tokio::test]
+    async fn test_health_check_runs() {
         let credentials = CredentialManager::new();
-        let extensions = ExtensionRegistry::new();
+        let extensions = RwLock::new(Arc::new(ExtensionRegistry::new()));
 
-        let report = run_health_check(&credentials, &extensions);
+        let report = run_health_check(&credentials, &extensions, HealthScope::Full, None).await;
 
         // Should always have some issues (at least info messages)
         assert!(!report.issues.is_empty());
@@ -349,11 +1221,40 @@ mod tests {
     }
 
     #[test]
-    fn test_health_summary() {
+    fn test_slow_extension_warning() {
+        use crate::agent::extension_stats::{ExecutionKind, SLOW_EXTENSION_MIN_SAMPLES};
+
+        let registry = ExtensionRegistry::new();
+        for _ in 0..SLOW_EXTENSION_MIN_SAMPLES {
+            registry.stats().record(
+                "sluggish",
+                ExecutionKind::Tool,
+                "crawl",
+                SLOW_EXTENSION_P95 + Duration::from_millis(500),
+                None,
+            );
+            registry.stats().record(
+                "snappy",
+                ExecutionKind::Tool,
+                "dash",
+                Duration::from_millis(5),
+                None,
+            );
+        }
+
+        let mut issues = Vec::new();
+        check_extension_performance(&registry, &mut issues);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, IssueSeverity::Warning);
+        assert!(issues[0].message.contains("'sluggish' is slow"));
+    }
+
+    #[tokio::test]
+    async fn test_health_summary() {
         let credentials = CredentialManager::new();
-        let extensions = ExtensionRegistry::new();
+        let extensions = RwLock::new(Arc::new(ExtensionRegistry::new()));
 
-        let report = run_health_check(&credentials, &extensions);
+        let report = run_health_check(&credentials, &extensions, HealthScope::Full, None).await;
 
         // Summary should match issue counts
         assert_eq!(
@@ -361,4 +1262,490 @@ mod tests {
             report.summary.errors + report.summary.warnings + report.summary.info
         );
     }
+
+    // ------------------------------------------------------------------------
+    // Workspace diagnostics
+    // ------------------------------------------------------------------------
+
+    const ALICE: &str = "11111111-1111-4111-8111-111111111111";
+
+    /// A well-formed workspace: one entity, one section tagging it
+    fn fixture_workspace() -> tempfile::TempDir {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("project.yaml"), "name: Fixture\n").unwrap();
+        fs::create_dir(dir.path().join("entities")).unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        write_entity(dir.path(), "alice.yaml", ALICE);
+        write_section(
+            dir.path(),
+            "001-one.md",
+            "s1",
+            1,
+            &format!(
+                "entity_ids: [{}]\ntags:\n  - {{id: t1, entity_id: {}, from: 0, to: 5}}\n",
+                ALICE, ALICE
+            ),
+            "Alice walked in.",
+        );
+        dir
+    }
+
+    fn write_entity(workspace: &Path, file: &str, id: &str) {
+        let yaml = format!("id: {}\nname: Alice\ntype: fact\n", id);
+        fs::write(workspace.join("entities").join(file), yaml).unwrap();
+    }
+
+    fn write_section(workspace: &Path, file: &str, id: &str, order: i64, extra: &str, body: &str) {
+        let text = format!(
+            "---\nid: {}\ntitle: Section {}\norder: {}\n{}---\n{}\n",
+            id, id, order, extra, body
+        );
+        fs::write(workspace.join("sections").join(file), text).unwrap();
+    }
+
+    fn codes(findings: &[WorkspaceFinding]) -> Vec<(WorkspaceIssueCode, Option<&str>)> {
+        findings
+            .iter()
+            .map(|f| (f.code, f.path.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_workspace_has_no_findings() {
+        let dir = fixture_workspace();
+        assert_eq!(check_workspace(dir.path()), Vec::new());
+    }
+
+    #[test]
+    fn test_missing_structure() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let findings = check_workspace(dir.path());
+        assert_eq!(
+            codes(&findings),
+            vec![
+                (WorkspaceIssueCode::MissingProjectFile, None),
+                (WorkspaceIssueCode::MissingSectionsDir, None),
+            ]
+        );
+        assert!(!findings[0].fixable);
+        assert!(findings[1].fixable);
+    }
+
+    #[test]
+    fn test_unparseable_files() {
+        let dir = fixture_workspace();
+        fs::write(dir.path().join("entities/broken.yaml"), "id: [unclosed\n").unwrap();
+        fs::write(
+            dir.path().join("sections/002-plain.md"),
+            "No frontmatter here",
+        )
+        .unwrap();
+        write_section(dir.path(), "003-bad.md", "s3", 3, "order: twice\n", "Text");
+
+        let findings = check_workspace(dir.path());
+        assert_eq!(
+            codes(&findings),
+            vec![
+                (
+                    WorkspaceIssueCode::InvalidEntityFile,
+                    Some("entities/broken.yaml")
+                ),
+                (
+                    WorkspaceIssueCode::InvalidSectionFile,
+                    Some("sections/002-plain.md")
+                ),
+                (
+                    WorkspaceIssueCode::InvalidSectionFile,
+                    Some("sections/003-bad.md")
+                ),
+            ]
+        );
+        assert!(findings
+            .iter()
+            .all(|f| f.severity == IssueSeverity::Error && !f.fixable));
+    }
+
+    #[test]
+    fn test_duplicate_ids_and_orders() {
+        let dir = fixture_workspace();
+        write_entity(dir.path(), "alice-copy.yaml", ALICE);
+        write_section(dir.path(), "001-again.md", "s1", 2, "", "Copy");
+        write_section(dir.path(), "002-two.md", "s2", 1, "", "Two");
+        // Same order under a different parent is fine
+        write_section(
+            dir.path(),
+            "003-child.md",
+            "s3",
+            1,
+            "parent_id: s1\n",
+            "Child",
+        );
+
+        let findings = check_workspace(dir.path());
+        assert_eq!(
+            codes(&findings),
+            vec![
+                (
+                    WorkspaceIssueCode::DuplicateEntityId,
+                    Some("entities/alice.yaml")
+                ),
+                (
+                    WorkspaceIssueCode::DuplicateSectionId,
+                    Some("sections/001-one.md")
+                ),
+                (
+                    WorkspaceIssueCode::DuplicateSectionOrder,
+                    Some("sections/002-two.md")
+                ),
+            ]
+        );
+        assert!(findings[0].message.contains("entities/alice-copy.yaml"));
+        assert!(findings.iter().all(|f| f.fixable));
+    }
+
+    #[test]
+    fn test_dangling_references_and_tag_ranges() {
+        let dir = fixture_workspace();
+        let ghost = "22222222-2222-4222-8222-222222222222";
+        let extra = format!(
+            "entity_ids: [{ghost}]\ntags:\n  - {{id: t2, entity_id: {ghost}, from: 0, to: 3}}\n  - {{id: t3, entity_id: {ALICE}, from: 2, to: 40}}\n  - {{id: t4, entity_id: {ALICE}, from: 3, to: 3}}\n"
+        );
+        write_section(dir.path(), "002-two.md", "s2", 2, &extra, "Zoë ran.");
+
+        let findings = check_workspace(dir.path());
+        let here = Some("sections/002-two.md");
+        assert_eq!(
+            codes(&findings),
+            vec![
+                (WorkspaceIssueCode::UnknownSectionEntity, here),
+                (WorkspaceIssueCode::UnknownTagEntity, here),
+                (WorkspaceIssueCode::TagOutOfRange, here),
+                (WorkspaceIssueCode::TagOutOfRange, here),
+            ]
+        );
+        assert!(findings[2].message.contains("t3"));
+        assert!(findings[2].message.contains("8 characters"));
+        assert!(findings[3].message.contains("t4"));
+    }
+
+    // ------------------------------------------------------------------------
+    // Connectivity probes
+    // ------------------------------------------------------------------------
+
+    /// Answer one request with `status` and hand back the request head
+    async fn serve_status(status: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !String::from_utf8_lossy(&request).contains("\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{{}}",
+                status
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_lowercase()
+        });
+        (base, handle)
+    }
+
+    fn target(provider: LlmProvider, base_url: &str, api_key: Option<&str>) -> ProbeTarget {
+        ProbeTarget {
+            provider,
+            base_url: Some(base_url.to_string()),
+            api_key: api_key.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_probe_providers_reports_each_target() {
+        let (openai_url, openai) = serve_status("200 OK").await;
+        let (claude_url, claude) = serve_status("401 Unauthorized").await;
+        let (open_url, open) = serve_status("403 Forbidden").await;
+        // A port nothing listens on
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let results = probe_providers(
+            vec![
+                target(
+                    LlmProvider::OpenAI,
+                    &format!("{}/", openai_url),
+                    Some("sk-test"),
+                ),
+                target(LlmProvider::Claude, &claude_url, Some("bad-key")),
+                target(LlmProvider::Ollama, &open_url, None),
+                target(LlmProvider::OpenRouter, &closed, Some("sk-or")),
+            ],
+            &CredentialManager::new(),
+        )
+        .await;
+
+        assert_eq!(results.len(), 4);
+        let (ok, rejected, anonymous, down) = (&results[0], &results[1], &results[2], &results[3]);
+        assert!(ok.reachable && ok.error.is_none());
+        assert_eq!(ok.status, Some(200));
+        assert!(ok.latency_ms.is_some());
+        assert!(rejected.reachable);
+        assert!(rejected
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("API key was rejected"));
+        assert!(anonymous.reachable && anonymous.error.is_none());
+        assert!(!down.reachable && down.latency_ms.is_none());
+        assert!(down.url.ends_with("/models"));
+
+        let openai = openai.await.unwrap();
+        assert!(openai.starts_with("get /models "));
+        assert!(openai.contains("authorization: bearer sk-test"));
+        let claude = claude.await.unwrap();
+        assert!(claude.contains("x-api-key: bad-key"));
+        assert!(claude.contains("anthropic-version:"));
+        assert!(!open.await.unwrap().contains("authorization"));
+
+        // Only the rejected key and the unreachable server become issues
+        let report = report_with(0).with_connectivity(results);
+        assert_eq!(report.connectivity.len(), 4);
+        assert_eq!(report.summary.warnings, 2);
+        assert!(report
+            .issues
+            .iter()
+            .all(|i| i.category == IssueCategory::Connectivity));
+    }
+
+    #[test]
+    fn test_report_lists_disabled_tools() {
+        let report = report_with(1).with_disabled_tools(Vec::new());
+        assert!(report.disabled_tools.is_empty());
+        assert_eq!(report.summary.total_issues, 1);
+
+        let report = report.with_disabled_tools(vec!["run_shell".to_string(), "notes".to_string()]);
+        assert_eq!(report.disabled_tools, vec!["run_shell", "notes"]);
+        assert_eq!(report.summary.info, 1);
+        assert!(!report.healthy);
+        assert!(report.issues[1].message.contains("run_shell, notes"));
+
+        // Probe results added later keep the list
+        let report = report.with_connectivity(Vec::new());
+        assert_eq!(report.disabled_tools.len(), 2);
+    }
+
+    #[test]
+    fn test_report_flags_missing_ollama_model() {
+        let status = |reachable: bool, model_installed: Option<bool>| OllamaStatus {
+            base_url: "http://localhost:11434".to_string(),
+            reachable,
+            models: Vec::new(),
+            model: Some("llama3".to_string()),
+            model_installed,
+            error: None,
+            hint: None,
+        };
+
+        let report = report_with(0).with_ollama(status(true, Some(false)));
+        assert_eq!(report.summary.warnings, 1);
+        assert!(report.issues[0]
+            .message
+            .contains("'llama3' is not installed"));
+        assert!(report.issues[0].remediation.contains("ollama pull llama3"));
+        assert!(report.ollama.is_some());
+
+        // Unreachable servers are reported by the connectivity probe instead
+        let report = report_with(0).with_ollama(status(false, None));
+        assert_eq!(report.summary.total_issues, 0);
+        let report = report.with_disabled_tools(vec!["run_shell".to_string()]);
+        assert!(report.ollama.is_some());
+    }
+
+    #[test]
+    fn test_default_probe_targets_include_ollama() {
+        let targets = default_probe_targets(&CredentialManager::new());
+        assert!(targets.iter().any(|t| t.provider == LlmProvider::Ollama));
+        assert_eq!(
+            probe_url(&targets[targets.len() - 1]),
+            "http://localhost:11434/api/tags"
+        );
+    }
+
+    // ------------------------------------------------------------------------
+    // Cache and startup probe
+    // ------------------------------------------------------------------------
+
+    /// A clock that only moves when told to
+    #[derive(Clone)]
+    struct ManualClock(Arc<std::sync::Mutex<Instant>>);
+
+    impl ManualClock {
+        fn new() -> Self {
+            ManualClock(Arc::new(std::sync::Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn report_with(errors: usize) -> HealthReport {
+        let issues = (0..errors)
+            .map(|i| {
+                HealthIssue::new(
+                    IssueSeverity::Error,
+                    IssueCategory::Configuration,
+                    format!("problem {}", i),
+                    "fix it",
+                )
+            })
+            .collect();
+        HealthReport::from_issues(issues, HealthScope::Full)
+    }
+
+    async fn not_called() -> HealthReport {
+        panic!("check should not run")
+    }
+
+    #[tokio::test]
+    async fn test_cache_serves_fresh_report() {
+        let clock = ManualClock::new();
+        let cache = HealthCache::with_clock(Box::new(clock.clone()));
+        let ttl = Duration::from_secs(60);
+
+        let first = cache
+            .get_or_refresh(HealthScope::Full, ttl, false, || async { report_with(1) })
+            .await;
+        assert_eq!(first.summary.errors, 1);
+
+        // Within the TTL the check doesn't run again
+        clock.advance(Duration::from_secs(59));
+        let cached = cache
+            .get_or_refresh(HealthScope::Full, ttl, false, not_called)
+            .await;
+        assert_eq!(cached.checked_at, first.checked_at);
+
+        // Once stale, the check runs and its report replaces the cached one
+        clock.advance(Duration::from_secs(1));
+        let refreshed = cache
+            .get_or_refresh(HealthScope::Full, ttl, false, || async { report_with(0) })
+            .await;
+        assert!(refreshed.healthy);
+        let cached = cache
+            .get_or_refresh(HealthScope::Full, ttl, false, not_called)
+            .await;
+        assert!(cached.healthy);
+    }
+
+    #[tokio::test]
+    async fn test_cache_force_reruns_check() {
+        let cache = HealthCache::new();
+        let ttl = Duration::from_secs(3600);
+
+        cache
+            .get_or_refresh(HealthScope::Full, ttl, false, || async { report_with(0) })
+            .await;
+        let forced = cache
+            .get_or_refresh(HealthScope::Full, ttl, true, || async { report_with(2) })
+            .await;
+        assert_eq!(forced.summary.errors, 2);
+
+        // The forced report is what later callers see
+        let cached = cache
+            .get_or_refresh(HealthScope::Full, ttl, false, not_called)
+            .await;
+        assert_eq!(cached.summary.errors, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_startup_report_does_not_serve_full_check() {
+        let cache = HealthCache::new();
+        let ttl = Duration::from_secs(3600);
+        let startup = || async { HealthReport::from_issues(Vec::new(), HealthScope::Startup) };
+
+        cache
+            .get_or_refresh(HealthScope::Startup, ttl, false, startup)
+            .await;
+        let cached = cache
+            .get_or_refresh(HealthScope::Startup, ttl, false, not_called)
+            .await;
+        assert_eq!(cached.scope, HealthScope::Startup);
+
+        // A full check misses the startup report and replaces it
+        let full = cache
+            .get_or_refresh(HealthScope::Full, ttl, false, || async { report_with(1) })
+            .await;
+        assert_eq!(full.scope, HealthScope::Full);
+
+        // ...which then serves both scopes
+        let cached = cache
+            .get_or_refresh(HealthScope::Startup, ttl, false, not_called)
+            .await;
+        assert_eq!(cached.summary.errors, 1);
+    }
+
+    #[tokio::test]
+    async fn test_startup_probe_scope_and_cancel() {
+        let credentials = Arc::new(CredentialManager::new());
+        let extensions = Arc::new(RwLock::new(Arc::new(ExtensionRegistry::new())));
+        let config: SharedGlobalConfig = Default::default();
+
+        // The startup scope skips environment checks
+        let report = run_health_check(&credentials, &extensions, HealthScope::Startup, None).await;
+        assert_eq!(report.scope, HealthScope::Startup);
+        assert!(report
+            .issues
+            .iter()
+            .all(|i| i.category != IssueCategory::Environment));
+
+        // Cancelled during the launch delay: nothing is probed or cached
+        let cache: SharedHealthCache = Arc::new(HealthCache::new());
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let probed = run_startup_probe(
+            credentials.clone(),
+            extensions.clone(),
+            cache.clone(),
+            config.clone(),
+            cancel,
+        )
+        .await;
+        assert!(probed.is_none());
+        let cached = cache
+            .get_or_refresh(
+                HealthScope::Full,
+                Duration::from_secs(60),
+                false,
+                || async { report_with(3) },
+            )
+            .await;
+        assert_eq!(cached.summary.errors, 3);
+
+        // Disabled: returns immediately
+        config.write().unwrap().health_probe.enabled = false;
+        let probed = run_startup_probe(
+            credentials,
+            extensions,
+            Arc::new(HealthCache::new()),
+            config,
+            CancellationToken::new(),
+        )
+        .await;
+        assert!(probed.is_none());
+    }
 }
@@ -2,11 +2,20 @@
 //!
 //! This module provides read/write access to entities and sections for Lua extensions.
 //! It reads from and writes to the same YAML/Markdown formats used by the frontend.
+//! Parsed files are cached and indexed by ID, so lookups don't re-read every
+//! file. Stores on an open workspace share its [`EntityCache`].
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+use super::atomic_write::write_atomic;
+use super::entity_schemas::{EntitySchemas, MetadataIssue};
+use super::entity_tags::utf16_len;
+use super::ids;
 
 // ============================================================================
 // Entity Types (matching frontend schemas)
@@ -103,6 +112,17 @@ impl From<Entity> for EntityFile {
     }
 }
 
+/// An entity as written, with any ways its metadata strays from its type's
+/// schema
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedEntity {
+    #[serde(flatten)]
+    pub entity: Entity,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub metadata_warnings: Vec<MetadataIssue>,
+}
+
 // ============================================================================
 // Tag Types
 // ============================================================================
@@ -172,8 +192,14 @@ pub struct SectionFrontmatter {
     pub created_at: Option<String>,
     #[serde(default)]
     pub modified_at: Option<String>,
+    /// Fields the app doesn't know, kept so rewriting a section doesn't drop them
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_yaml::Value>,
 }
 
+/// Alignments the editor understands
+const SECTION_ALIGNMENTS: &[&str] = &["left", "center", "right"];
+
 /// Section for Lua API
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -189,6 +215,44 @@ pub struct Section {
     pub tags: Vec<Tag>,
 }
 
+/// Fields for a new section; the rest are filled in by `create_section`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSection {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub order: Option<i64>,
+    #[serde(default)]
+    pub alignment: Option<String>,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub entity_ids: Vec<String>,
+}
+
+/// Frontmatter changes for `update_section_metadata`; unset fields are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SectionMetadata {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub order: Option<i64>,
+    #[serde(default)]
+    pub alignment: Option<String>,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub collapsed: Option<bool>,
+    #[serde(default)]
+    pub entity_ids: Option<Vec<String>>,
+}
+
 // ============================================================================
 // Entity Relationships
 // ============================================================================
@@ -201,116 +265,89 @@ pub struct EntityRelationships {
     pub sections: Vec<Section>,
 }
 
+/// What `merge_entities` changed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeSummary {
+    /// The target entity after the merge
+    pub entity: Entity,
+    pub sections_updated: usize,
+    pub tags_updated: usize,
+}
+
+/// What `insert_into_section` / `append_to_section` changed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionInsert {
+    /// The section after the edit
+    pub section: Section,
+    /// UTF-16 offset the text went in at
+    pub offset: i64,
+    /// Tags after the insertion point, moved along with their text
+    pub tags_shifted: usize,
+    /// IDs of tags the insertion landed inside, widened to take in the new text
+    pub tags_expanded: Vec<String>,
+}
+
 // ============================================================================
 // EntityStore Implementation
 // ============================================================================
 
 /// Store for reading/writing entities and sections within a workspace
+#[derive(Clone)]
 pub struct EntityStore {
     workspace: PathBuf,
+    /// Parsed files, shared with the other stores given the same cache
+    cache: EntityCache,
 }
 
 impl EntityStore {
-    /// Create a new EntityStore for the given workspace
+    /// Create a new EntityStore for the given workspace, with a cache of its own
     pub fn new(workspace: &Path) -> Self {
         EntityStore {
             workspace: workspace.to_path_buf(),
+            cache: EntityCache::default(),
         }
     }
 
+    /// Share `cache` (an open workspace's) instead of parsing files afresh
+    pub fn with_cache(mut self, cache: EntityCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
     // ========================================================================
     // Entity Operations
     // ========================================================================
 
     /// Get an entity by ID
     pub fn get_entity(&self, entity_id: &str) -> Result<Option<Entity>, String> {
-        let entities_dir = self.workspace.join("entities");
-        if !entities_dir.exists() {
-            return Ok(None);
-        }
-
-        // Search through all entity files
-        for entry in fs::read_dir(&entities_dir)
-            .map_err(|e| format!("Failed to read entities directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
-
-            if path
-                .extension()
-                .map(|e| e == "yaml" || e == "yml")
-                .unwrap_or(false)
-            {
-                if let Ok(entity) = self.read_entity_file(&path) {
-                    if entity.id == entity_id {
-                        return Ok(Some(entity.into()));
-                    }
-                }
-            }
-        }
-
-        Ok(None)
+        Ok(self
+            .cache()
+            .entity(&self.workspace, entity_id)?
+            .map(|(_, entity)| entity.into()))
     }
 
     /// List entities by type
     pub fn list_by_type(&self, entity_type: &str) -> Result<Vec<Entity>, String> {
-        let entities_dir = self.workspace.join("entities");
-        if !entities_dir.exists() {
-            return Ok(Vec::new());
-        }
-
-        let mut results = Vec::new();
-
-        for entry in fs::read_dir(&entities_dir)
-            .map_err(|e| format!("Failed to read entities directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
-
-            if path
-                .extension()
-                .map(|e| e == "yaml" || e == "yml")
-                .unwrap_or(false)
-            {
-                if let Ok(entity) = self.read_entity_file(&path) {
-                    let type_str = format!("{:?}", entity.entity_type).to_lowercase();
-                    if type_str == entity_type.to_lowercase() {
-                        results.push(entity.into());
-                    }
-                }
-            }
-        }
-
-        Ok(results)
+        let entity_type = entity_type.to_lowercase();
+        Ok(self
+            .cache()
+            .entities(&self.workspace)?
+            .into_iter()
+            .filter(|entity| format!("{:?}", entity.entity_type).to_lowercase() == entity_type)
+            .map(Entity::from)
+            .collect())
     }
 
     /// List all entities
     pub fn list_all(&self) -> Result<Vec<Entity>, String> {
-        let entities_dir = self.workspace.join("entities");
-        if !entities_dir.exists() {
-            return Ok(Vec::new());
-        }
-
-        let mut results = Vec::new();
-
-        for entry in fs::read_dir(&entities_dir)
-            .map_err(|e| format!("Failed to read entities directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
-
-            if path
-                .extension()
-                .map(|e| e == "yaml" || e == "yml")
-                .unwrap_or(false)
-            {
-                if let Ok(entity) = self.read_entity_file(&path) {
-                    results.push(entity.into());
-                }
-            }
-        }
-
-        Ok(results)
+        Ok(self
+            .cache()
+            .entities(&self.workspace)?
+            .into_iter()
+            .map(Entity::from)
+            .collect())
     }
 
     /// Search entities by name or description
@@ -332,46 +369,57 @@ impl EntityStore {
         Ok(results)
     }
 
-    /// Create a new entity
-    #[allow(dead_code)]
-    pub fn create_entity(&self, entity: Entity) -> Result<Entity, String> {
+    /// Create a new entity. An empty ID is replaced with a generated UUID and
+    /// unknown types become "custom". The file is named after the entity, with a
+    /// numbered suffix when another entity's file already has that name.
+    pub fn create_entity(&self, mut entity: Entity) -> Result<SavedEntity, String> {
+        validate_name(&entity.name)?;
+        if entity.id.is_empty() {
+            entity.id = ids::new_uuid();
+        } else {
+            ids::validate_id("Entity", &entity.id)?;
+        }
+
         let entities_dir = self.workspace.join("entities");
         if !entities_dir.exists() {
             fs::create_dir_all(&entities_dir)
                 .map_err(|e| format!("Failed to create entities directory: {}", e))?;
         }
 
-        // Generate filename from name (sanitized)
-        let filename = sanitize_filename(&entity.name);
-        let path = entities_dir.join(format!("{}.yaml", filename));
-
         // Check if entity with this ID already exists
         if self.get_entity(&entity.id)?.is_some() {
             return Err(format!("Entity with ID {} already exists", entity.id));
         }
 
-        let entity_file: EntityFile = entity.clone().into();
+        let path = unique_entity_path(&entities_dir, &entity.name);
+        let entity_file: EntityFile = entity.into();
+        let metadata_warnings = self.check_metadata(&entity_file)?;
         let yaml = serde_yaml::to_string(&entity_file)
             .map_err(|e| format!("Failed to serialize entity: {}", e))?;
 
-        fs::write(&path, yaml).map_err(|e| format!("Failed to write entity file: {}", e))?;
+        write_atomic(&path, yaml.as_bytes())
+            .map_err(|e| format!("Failed to write entity file: {}", e))?;
+        self.cache().entities.forget(&path);
 
-        Ok(entity)
+        Ok(SavedEntity {
+            entity: entity_file.into(),
+            metadata_warnings,
+        })
     }
 
-    /// Update an existing entity
-    #[allow(dead_code)]
+    /// Update an existing entity. The ID can't change, and the entity keeps its
+    /// file even if it is renamed.
     pub fn update_entity(
         &self,
         entity_id: &str,
         updates: serde_json::Value,
-    ) -> Result<Entity, String> {
-        let existing = self
-            .get_entity(entity_id)?
+    ) -> Result<SavedEntity, String> {
+        let (file_path, existing) = self
+            .cache()
+            .entity(&self.workspace, entity_id)?
             .ok_or_else(|| format!("Entity {} not found", entity_id))?;
-
-        // Find the file path
-        let file_path = self.find_entity_file(entity_id)?;
+        let created_at = existing.created_at.clone();
+        let existing = Entity::from(existing);
 
         // Merge updates
         let mut entity_json = serde_json::to_value(&existing)
@@ -379,33 +427,181 @@ impl EntityStore {
 
         if let (Some(obj), Some(updates_obj)) = (entity_json.as_object_mut(), updates.as_object()) {
             for (key, value) in updates_obj {
+                if key == "id" && value.as_str() != Some(entity_id) {
+                    return Err(format!("Entity {} cannot change its ID", entity_id));
+                }
                 obj.insert(key.clone(), value.clone());
             }
         }
 
         let updated: Entity = serde_json::from_value(entity_json)
             .map_err(|e| format!("Failed to deserialize updated entity: {}", e))?;
+        validate_name(&updated.name)?;
 
-        let entity_file: EntityFile = updated.clone().into();
+        let mut entity_file: EntityFile = updated.into();
+        entity_file.created_at = created_at.or(entity_file.created_at);
+        let metadata_warnings = self.check_metadata(&entity_file)?;
         let yaml = serde_yaml::to_string(&entity_file)
             .map_err(|e| format!("Failed to serialize entity: {}", e))?;
 
-        fs::write(&file_path, yaml).map_err(|e| format!("Failed to write entity file: {}", e))?;
+        write_atomic(&file_path, yaml.as_bytes())
+            .map_err(|e| format!("Failed to write entity file: {}", e))?;
+        self.cache().entities.forget(&file_path);
+
+        Ok(SavedEntity {
+            entity: entity_file.into(),
+            metadata_warnings,
+        })
+    }
 
-        Ok(updated)
+    /// Check an entity's metadata against the workspace's schema for its type.
+    /// Mismatches are returned as warnings, or rejected if the schemas are
+    /// strict; schemas that can't be used are a warning either way.
+    fn check_metadata(&self, entity: &EntityFile) -> Result<Vec<MetadataIssue>, String> {
+        let schemas = EntitySchemas::load(&self.workspace);
+        let entity_type = format!("{:?}", entity.entity_type).to_lowercase();
+        let metadata = entity.metadata.clone().unwrap_or_default();
+        let issues = match schemas.check(&entity_type, &metadata) {
+            Ok(issues) => issues,
+            Err(reason) => {
+                return Ok(vec![MetadataIssue {
+                    field: String::new(),
+                    message: format!("not checked: {}", reason),
+                }])
+            }
+        };
+        if schemas.strict && !issues.is_empty() {
+            let issues: Vec<String> = issues.iter().map(ToString::to_string).collect();
+            return Err(format!(
+                "Entity {} doesn't match the {} schema: {}",
+                entity.id,
+                entity_type,
+                issues.join("; ")
+            ));
+        }
+        Ok(issues)
     }
 
-    /// Delete an entity
-    #[allow(dead_code)]
+    /// Delete an entity. Returns false if there was no such entity.
     pub fn delete_entity(&self, entity_id: &str) -> Result<bool, String> {
-        match self.find_entity_file(entity_id) {
-            Ok(path) => {
-                fs::remove_file(&path)
-                    .map_err(|e| format!("Failed to delete entity file: {}", e))?;
-                Ok(true)
+        let found = self.cache().entity(&self.workspace, entity_id)?;
+        let Some((path, _)) = found else {
+            return Ok(false);
+        };
+        fs::remove_file(&path).map_err(|e| format!("Failed to delete entity file: {}", e))?;
+        self.cache().entities.forget(&path);
+        Ok(true)
+    }
+
+    /// Fold `source_id` into `target_id`: the target gains the source's name and
+    /// aliases as aliases, and any metadata keys it lacks; every section
+    /// reference and tag pointing at the source is moved to the target; then the
+    /// source file is deleted.
+    pub fn merge_entities(&self, source_id: &str, target_id: &str) -> Result<MergeSummary, String> {
+        if source_id == target_id {
+            return Err(format!("Cannot merge entity {} into itself", source_id));
+        }
+        let (target_path, mut target) = self
+            .cache()
+            .entity(&self.workspace, target_id)?
+            .ok_or_else(|| format!("Entity {} not found", target_id))?;
+        let (source_path, source) = self
+            .cache()
+            .entity(&self.workspace, source_id)?
+            .ok_or_else(|| format!("Entity {} not found", source_id))?;
+
+        let mut known: HashSet<String> = std::iter::once(&target.name)
+            .chain(&target.aliases)
+            .map(|alias| alias.to_lowercase())
+            .collect();
+        for alias in std::iter::once(&source.name).chain(&source.aliases) {
+            if known.insert(alias.to_lowercase()) {
+                target.aliases.push(alias.clone());
             }
-            Err(_) => Ok(false),
         }
+        if let Some(source_metadata) = source.metadata {
+            let metadata = target.metadata.get_or_insert_with(HashMap::new);
+            for (key, value) in source_metadata {
+                metadata.entry(key).or_insert(value);
+            }
+        }
+        if target.description.trim().is_empty() {
+            target.description = source.description;
+        }
+        let now = chrono_now();
+        target.modified_at = Some(now.clone());
+        let entity_yaml = serde_yaml::to_string(&target)
+            .map_err(|e| format!("Failed to serialize entity: {}", e))?;
+
+        // Plan every section rewrite before writing anything
+        let referencing: Vec<String> = self
+            .cache()
+            .sections(&self.workspace)?
+            .into_iter()
+            .filter(|(frontmatter, _)| {
+                frontmatter.entity_ids.iter().any(|id| id == source_id)
+                    || frontmatter.tags.iter().any(|t| t.entity_id == source_id)
+            })
+            .map(|(frontmatter, _)| frontmatter.id)
+            .collect();
+        let mut section_files = Vec::new();
+        let mut tags_updated = 0;
+        for section_id in referencing {
+            let (path, mut frontmatter, content) = self.read_section(&section_id)?;
+            let mut changes: Vec<(&str, serde_yaml::Value)> = Vec::new();
+
+            if frontmatter.entity_ids.iter().any(|id| id == source_id) {
+                let mut entity_ids: Vec<String> = Vec::new();
+                for id in frontmatter.entity_ids.drain(..) {
+                    let id = if id == source_id {
+                        target_id.to_string()
+                    } else {
+                        id
+                    };
+                    if !entity_ids.contains(&id) {
+                        entity_ids.push(id);
+                    }
+                }
+                frontmatter.entity_ids = entity_ids;
+                changes.push(("entity_ids", yaml_value(&frontmatter.entity_ids)?));
+            }
+            let mut retagged = 0;
+            for tag in frontmatter
+                .tags
+                .iter_mut()
+                .filter(|t| t.entity_id == source_id)
+            {
+                tag.entity_id = target_id.to_string();
+                retagged += 1;
+            }
+            if retagged > 0 {
+                changes.push(("tags", yaml_value(&frontmatter.tags)?));
+                tags_updated += retagged;
+            }
+            changes.push(("modified_at", now.clone().into()));
+            frontmatter.modified_at = Some(now.clone());
+
+            let file = patched_section_file(&path, &frontmatter, &content, &changes)?;
+            section_files.push((path, file));
+        }
+
+        // Sections first, source file last: if a write fails partway, the source
+        // still exists and every reference still resolves
+        for (path, file) in &section_files {
+            self.write_section_file(path, file)?;
+        }
+        write_atomic(&target_path, entity_yaml.as_bytes())
+            .map_err(|e| format!("Failed to write entity file: {}", e))?;
+        self.cache().entities.forget(&target_path);
+        fs::remove_file(&source_path)
+            .map_err(|e| format!("Failed to delete entity file: {}", e))?;
+        self.cache().entities.forget(&source_path);
+
+        Ok(MergeSummary {
+            entity: target.into(),
+            sections_updated: section_files.len(),
+            tags_updated,
+        })
     }
 
     // ========================================================================
@@ -423,7 +619,7 @@ impl EntityStore {
         let (path, mut frontmatter, content) = self.read_section(section_id)?;
 
         let tag = Tag {
-            id: uuid::Uuid::new_v4().to_string(),
+            id: ids::new_uuid(),
             entity_id: entity_id.to_string(),
             from,
             to,
@@ -437,6 +633,34 @@ impl EntityStore {
         Ok(tag)
     }
 
+    /// Add several tags (entity ID, from, to) to a section in one write
+    pub fn add_tags(
+        &self,
+        section_id: &str,
+        ranges: &[(String, i64, i64)],
+    ) -> Result<Vec<Tag>, String> {
+        let (path, mut frontmatter, content) = self.read_section(section_id)?;
+
+        let tags: Vec<Tag> = ranges
+            .iter()
+            .map(|(entity_id, from, to)| Tag {
+                id: ids::new_uuid(),
+                entity_id: entity_id.clone(),
+                from: *from,
+                to: *to,
+            })
+            .collect();
+
+        frontmatter
+            .tags
+            .extend(tags.iter().cloned().map(TagFile::from));
+        frontmatter.modified_at = Some(chrono_now());
+
+        self.write_section(&path, &frontmatter, &content)?;
+
+        Ok(tags)
+    }
+
     /// Remove a tag from a section
     pub fn remove_tag(&self, section_id: &str, tag_id: &str) -> Result<bool, String> {
         let (path, mut frontmatter, content) = self.read_section(section_id)?;
@@ -454,6 +678,19 @@ impl EntityStore {
         Ok(true)
     }
 
+    /// Tags whose range no longer fits the section's content (past its end, or
+    /// ending before they start), e.g. after the content was edited as a file
+    pub fn validate_tags(&self, section_id: &str) -> Result<Vec<Tag>, String> {
+        let (_, frontmatter, content) = self.read_section(section_id)?;
+        let len = utf16_len(&content);
+        Ok(frontmatter
+            .tags
+            .into_iter()
+            .filter(|t| t.from < 0 || t.to < t.from || t.to > len)
+            .map(Tag::from)
+            .collect())
+    }
+
     /// Get all tags for a section
     pub fn get_tags(&self, section_id: &str) -> Result<Vec<Tag>, String> {
         let (_, frontmatter, _) = self.read_section(section_id)?;
@@ -489,50 +726,28 @@ impl EntityStore {
 
     /// Get a section by ID
     pub fn get_section(&self, section_id: &str) -> Result<Option<Section>, String> {
-        let sections_dir = self.workspace.join("sections");
-        if !sections_dir.exists() {
-            return Ok(None);
-        }
-
-        for entry in fs::read_dir(&sections_dir)
-            .map_err(|e| format!("Failed to read sections directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
-
-            if path.extension().map(|e| e == "md").unwrap_or(false) {
-                if let Ok((frontmatter, content)) = self.parse_section_file(&path) {
-                    if frontmatter.id == section_id {
-                        return Ok(Some(self.frontmatter_to_section(frontmatter, content)));
-                    }
-                }
-            }
-        }
+        Ok(self
+            .cache()
+            .section(&self.workspace, section_id)?
+            .map(|(_, (frontmatter, content))| self.frontmatter_to_section(frontmatter, content)))
+    }
 
-        Ok(None)
+    /// Path of a section's file
+    pub fn section_path(&self, section_id: &str) -> Result<Option<PathBuf>, String> {
+        Ok(self
+            .cache()
+            .section(&self.workspace, section_id)?
+            .map(|(path, _)| path))
     }
 
     /// List all sections
     pub fn list_all_sections(&self) -> Result<Vec<Section>, String> {
-        let sections_dir = self.workspace.join("sections");
-        if !sections_dir.exists() {
-            return Ok(Vec::new());
-        }
-
-        let mut results = Vec::new();
-
-        for entry in fs::read_dir(&sections_dir)
-            .map_err(|e| format!("Failed to read sections directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
-
-            if path.extension().map(|e| e == "md").unwrap_or(false) {
-                if let Ok((frontmatter, content)) = self.parse_section_file(&path) {
-                    results.push(self.frontmatter_to_section(frontmatter, content));
-                }
-            }
-        }
+        let mut results: Vec<Section> = self
+            .cache()
+            .sections(&self.workspace)?
+            .into_iter()
+            .map(|(frontmatter, content)| self.frontmatter_to_section(frontmatter, content))
+            .collect();
 
         // Sort by order
         results.sort_by_key(|s| s.order);
@@ -540,93 +755,241 @@ impl EntityStore {
         Ok(results)
     }
 
-    // ========================================================================
-    // Private Helpers
-    // ========================================================================
-
-    fn read_entity_file(&self, path: &Path) -> Result<EntityFile, String> {
-        let content =
-            fs::read_to_string(path).map_err(|e| format!("Failed to read entity file: {}", e))?;
-        serde_yaml::from_str(&content).map_err(|e| format!("Failed to parse entity YAML: {}", e))
+    /// Section files that `list_all_sections` leaves out because they don't
+    /// parse, with the error for each
+    pub fn list_section_failures(&self) -> Result<Vec<(PathBuf, String)>, String> {
+        self.cache().section_failures(&self.workspace)
     }
 
-    #[allow(dead_code)]
-    fn find_entity_file(&self, entity_id: &str) -> Result<PathBuf, String> {
-        let entities_dir = self.workspace.join("entities");
-        if !entities_dir.exists() {
-            return Err(format!("Entity {} not found", entity_id));
+    /// Create a section file. An empty ID is replaced with a generated UUID and a
+    /// missing order puts the section after the last one. The file is named
+    /// `NNN-slug-shortid.md`, like the frontend's.
+    pub fn create_section(&self, new: NewSection) -> Result<Section, String> {
+        validate_title(&new.title)?;
+        let id = if new.id.is_empty() {
+            ids::new_uuid()
+        } else {
+            ids::validate_id("Section", &new.id)?;
+            new.id
+        };
+        if self.cache().section(&self.workspace, &id)?.is_some() {
+            return Err(format!("Section with ID {} already exists", id));
         }
 
-        for entry in fs::read_dir(&entities_dir)
-            .map_err(|e| format!("Failed to read entities directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
+        let order = match new.order {
+            Some(order) => order,
+            None => self
+                .list_all_sections()?
+                .iter()
+                .map(|s| s.order + 1)
+                .max()
+                .unwrap_or(0),
+        };
 
-            if path
-                .extension()
-                .map(|e| e == "yaml" || e == "yml")
-                .unwrap_or(false)
-            {
-                if let Ok(entity) = self.read_entity_file(&path) {
-                    if entity.id == entity_id {
-                        return Ok(path);
-                    }
-                }
-            }
+        let sections_dir = self.workspace.join("sections");
+        fs::create_dir_all(&sections_dir)
+            .map_err(|e| format!("Failed to create sections directory: {}", e))?;
+        let path = sections_dir.join(section_filename(order, &new.title, &id));
+        if path.exists() {
+            return Err(format!("Section file {} already exists", path.display()));
         }
 
-        Err(format!("Entity {} not found", entity_id))
+        let now = chrono_now();
+        let frontmatter = SectionFrontmatter {
+            id,
+            title: new.title,
+            order,
+            alignment: Some(new.alignment.unwrap_or_else(|| "left".to_string())),
+            parent_id: new.parent_id.filter(|p| !p.is_empty()),
+            collapsed: Some(false),
+            entity_ids: new.entity_ids,
+            tags: Vec::new(),
+            created_at: Some(now.clone()),
+            modified_at: Some(now),
+            extra: BTreeMap::new(),
+        };
+        self.write_section(&path, &frontmatter, &new.content)?;
+
+        Ok(self.frontmatter_to_section(frontmatter, new.content.trim().to_string()))
     }
 
-    fn read_section(
+    /// Replace a section's content, keeping its frontmatter
+    pub fn update_section_content(
         &self,
         section_id: &str,
-    ) -> Result<(PathBuf, SectionFrontmatter, String), String> {
-        let sections_dir = self.workspace.join("sections");
-        if !sections_dir.exists() {
-            return Err(format!("Section {} not found", section_id));
-        }
+        content: &str,
+    ) -> Result<Section, String> {
+        let (path, mut frontmatter, _) = self.read_section(section_id)?;
+        frontmatter.modified_at = Some(chrono_now());
+        self.write_section(&path, &frontmatter, content)?;
+        Ok(self.frontmatter_to_section(frontmatter, content.trim().to_string()))
+    }
 
-        for entry in fs::read_dir(&sections_dir)
-            .map_err(|e| format!("Failed to read sections directory: {}", e))?
-        {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-            let path = entry.path();
+    /// Insert `text` into a section's content at `offset` (UTF-16 code units, like
+    /// tag ranges), keeping its tags on the text they mark: tags starting at or
+    /// after the offset move by the inserted length, tags the offset falls inside
+    /// grow to include the text, and tags before it stay put.
+    pub fn insert_into_section(
+        &self,
+        section_id: &str,
+        offset: i64,
+        text: &str,
+    ) -> Result<SectionInsert, String> {
+        self.insert_text(section_id, Some(offset), text)
+    }
 
-            if path.extension().map(|e| e == "md").unwrap_or(false) {
-                if let Ok((frontmatter, content)) = self.parse_section_file(&path) {
-                    if frontmatter.id == section_id {
-                        return Ok((path, frontmatter, content));
-                    }
-                }
+    /// Add `text` to the end of a section's content; tags are unchanged
+    pub fn append_to_section(&self, section_id: &str, text: &str) -> Result<SectionInsert, String> {
+        self.insert_text(section_id, None, text)
+    }
+
+    /// Rewrite a section's content with `edit`, which is given the content exactly
+    /// as it is on disk (everything after the closing `---`) and returns its
+    /// replacement. The frontmatter is kept byte for byte, `modified_at` included.
+    pub fn edit_section_body<F>(&self, section_id: &str, edit: F) -> Result<Section, String>
+    where
+        F: FnOnce(&str) -> Result<String, String>,
+    {
+        let (path, frontmatter, _) = self.read_section(section_id)?;
+        let raw =
+            fs::read_to_string(&path).map_err(|e| format!("Failed to read section file: {}", e))?;
+        let (yaml, rest) = split_frontmatter(&raw).ok_or("Section file missing frontmatter")?;
+        let body = edit(&rest["---".len()..])?;
+        self.write_section_file(&path, &format!("---{}---{}", yaml, body))?;
+        Ok(self.frontmatter_to_section(frontmatter, body.trim().to_string()))
+    }
+
+    /// Change a section's frontmatter fields, keeping its content, tags, and file.
+    /// An empty `parent_id` moves the section to the top level.
+    ///
+    /// Only the changed keys (and `modified_at`) are rewritten; every other line
+    /// of the file stays byte for byte as it was.
+    pub fn update_section_metadata(
+        &self,
+        section_id: &str,
+        metadata: SectionMetadata,
+    ) -> Result<Section, String> {
+        let (path, mut frontmatter, content) = self.read_section(section_id)?;
+        let mut changes: Vec<(&str, serde_yaml::Value)> = Vec::new();
+
+        if let Some(title) = metadata.title {
+            validate_title(&title)?;
+            changes.push(("title", title.clone().into()));
+            frontmatter.title = title;
+        }
+        if let Some(order) = metadata.order {
+            if order < 0 {
+                return Err(format!("Section order must be 0 or more (got {})", order));
             }
+            changes.push(("order", order.into()));
+            frontmatter.order = order;
         }
+        if let Some(alignment) = metadata.alignment {
+            if !SECTION_ALIGNMENTS.contains(&alignment.as_str()) {
+                return Err(format!(
+                    "Unknown alignment '{}'. Use {}.",
+                    alignment,
+                    SECTION_ALIGNMENTS.join(", ")
+                ));
+            }
+            changes.push(("alignment", alignment.clone().into()));
+            frontmatter.alignment = Some(alignment);
+        }
+        if let Some(parent_id) = metadata.parent_id {
+            if parent_id == section_id {
+                return Err(format!("Section {} cannot be its own parent", section_id));
+            }
+            frontmatter.parent_id = Some(parent_id).filter(|p| !p.is_empty());
+            changes.push(("parent_id", yaml_value(&frontmatter.parent_id)?));
+        }
+        if let Some(collapsed) = metadata.collapsed {
+            changes.push(("collapsed", collapsed.into()));
+            frontmatter.collapsed = Some(collapsed);
+        }
+        if let Some(entity_ids) = metadata.entity_ids {
+            changes.push(("entity_ids", yaml_value(&entity_ids)?));
+            frontmatter.entity_ids = entity_ids;
+        }
+        let now = chrono_now();
+        changes.push(("modified_at", now.clone().into()));
+        frontmatter.modified_at = Some(now);
 
-        Err(format!("Section {} not found", section_id))
+        let file = patched_section_file(&path, &frontmatter, &content, &changes)?;
+        self.write_section_file(&path, &file)?;
+        Ok(self.frontmatter_to_section(frontmatter, content))
     }
 
-    fn parse_section_file(&self, path: &Path) -> Result<(SectionFrontmatter, String), String> {
-        let content =
-            fs::read_to_string(path).map_err(|e| format!("Failed to read section file: {}", e))?;
+    /// Number of files parsed by this store's cache so far
+    #[cfg(test)]
+    pub(crate) fn parse_count(&self) -> usize {
+        self.cache().parses
+    }
 
-        // Parse YAML frontmatter (between --- markers)
-        if !content.starts_with("---") {
-            return Err("Section file missing frontmatter".to_string());
-        }
+    // ========================================================================
+    // Private Helpers
+    // ========================================================================
 
-        let parts: Vec<&str> = content.splitn(3, "---").collect();
-        if parts.len() < 3 {
-            return Err("Invalid frontmatter format".to_string());
-        }
+    fn cache(&self) -> MutexGuard<'_, WorkspaceCache> {
+        lock(&self.cache.0)
+    }
 
-        let yaml_str = parts[1].trim();
-        let markdown_content = parts[2].trim().to_string();
+    fn read_section(
+        &self,
+        section_id: &str,
+    ) -> Result<(PathBuf, SectionFrontmatter, String), String> {
+        self.cache()
+            .section(&self.workspace, section_id)?
+            .map(|(path, (frontmatter, content))| (path, frontmatter, content))
+            .ok_or_else(|| format!("Section {} not found", section_id))
+    }
 
-        let frontmatter: SectionFrontmatter = serde_yaml::from_str(yaml_str)
-            .map_err(|e| format!("Failed to parse section frontmatter: {}", e))?;
+    /// Insert `text` at `offset`, or at the end when it is `None`, shifting tags
+    fn insert_text(
+        &self,
+        section_id: &str,
+        offset: Option<i64>,
+        text: &str,
+    ) -> Result<SectionInsert, String> {
+        let (path, mut frontmatter, content) = self.read_section(section_id)?;
+        let len = utf16_len(&content);
+        let offset = offset.unwrap_or(len);
+        let at = utf16_byte_index(&content, offset).ok_or_else(|| {
+            format!(
+                "Offset {} is outside section {} ({} characters) or splits a character",
+                offset, section_id, len
+            )
+        })?;
+
+        let edited = format!("{}{}{}", &content[..at], text, &content[at..]);
+        // Content is stored trimmed, so whitespace the text adds at the very start
+        // is dropped and everything after it moves back
+        let stored = edited.trim();
+        let dropped = utf16_len(&edited) - utf16_len(edited.trim_start());
+        let inserted = utf16_len(text);
+
+        let mut tags_shifted = 0;
+        let mut tags_expanded = Vec::new();
+        for tag in &mut frontmatter.tags {
+            if tag.from >= offset {
+                tag.from += inserted;
+                tag.to += inserted;
+                tags_shifted += 1;
+            } else if tag.to > offset {
+                tag.to += inserted;
+                tags_expanded.push(tag.id.clone());
+            }
+            tag.from = (tag.from - dropped).max(0);
+            tag.to = (tag.to - dropped).max(0);
+        }
+        frontmatter.modified_at = Some(chrono_now());
 
-        Ok((frontmatter, markdown_content))
+        self.write_section(&path, &frontmatter, stored)?;
+        Ok(SectionInsert {
+            section: self.frontmatter_to_section(frontmatter, stored.to_string()),
+            offset: (offset - dropped).max(0),
+            tags_shifted,
+            tags_expanded,
+        })
     }
 
     fn write_section(
@@ -635,11 +998,14 @@ impl EntityStore {
         frontmatter: &SectionFrontmatter,
         content: &str,
     ) -> Result<(), String> {
-        let yaml = serde_yaml::to_string(frontmatter)
-            .map_err(|e| format!("Failed to serialize frontmatter: {}", e))?;
+        self.write_section_file(path, &render_section_file(frontmatter, content)?)
+    }
 
-        let file_content = format!("---\n{}---\n{}", yaml, content);
-        fs::write(path, file_content).map_err(|e| format!("Failed to write section file: {}", e))
+    fn write_section_file(&self, path: &Path, file_content: &str) -> Result<(), String> {
+        write_atomic(path, file_content.as_bytes())
+            .map_err(|e| format!("Failed to write section file: {}", e))?;
+        self.cache().sections.forget(path);
+        Ok(())
     }
 
     fn frontmatter_to_section(&self, fm: SectionFrontmatter, content: String) -> Section {
@@ -657,10 +1023,463 @@ impl EntityStore {
     }
 }
 
+// ============================================================================
+// File Cache
+// ============================================================================
+
+/// Parsed entity and section files for one workspace, shared by the
+/// [`EntityStore`]s given it. An open workspace keeps one for as long as it is
+/// open (see `WorkspaceState`).
+#[derive(Clone, Default)]
+pub struct EntityCache(Arc<Mutex<WorkspaceCache>>);
+
+/// A section's frontmatter and body
+type ParsedSection = (SectionFrontmatter, String);
+
+/// What an [`EntityCache`] holds. Entries are checked against each file's
+/// modification time and size before use, so edits made outside the store
+/// (by the frontend or the file tools) are picked up.
+#[derive(Default)]
+struct WorkspaceCache {
+    entities: FileCache<EntityFile>,
+    sections: FileCache<ParsedSection>,
+    /// Files parsed since the cache was created
+    parses: usize,
+}
+
+impl WorkspaceCache {
+    fn entity(
+        &mut self,
+        workspace: &Path,
+        id: &str,
+    ) -> Result<Option<(PathBuf, EntityFile)>, String> {
+        self.entities
+            .find(workspace, &ENTITY_FILES, id, &mut self.parses)
+    }
+
+    fn entities(&mut self, workspace: &Path) -> Result<Vec<EntityFile>, String> {
+        self.entities
+            .refresh(workspace, &ENTITY_FILES, &mut self.parses)?;
+        Ok(self.entities.values())
+    }
+
+    fn section(
+        &mut self,
+        workspace: &Path,
+        id: &str,
+    ) -> Result<Option<(PathBuf, ParsedSection)>, String> {
+        self.sections
+            .find(workspace, &SECTION_FILES, id, &mut self.parses)
+    }
+
+    fn sections(&mut self, workspace: &Path) -> Result<Vec<ParsedSection>, String> {
+        self.sections
+            .refresh(workspace, &SECTION_FILES, &mut self.parses)?;
+        Ok(self.sections.values())
+    }
+
+    fn section_failures(&mut self, workspace: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+        self.sections
+            .refresh(workspace, &SECTION_FILES, &mut self.parses)?;
+        Ok(self.sections.failures())
+    }
+}
+
+/// Caches are revalidated on every use, so one poisoned by a panic is still safe
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Where one kind of file lives and how to parse it
+struct FileKind<T> {
+    dir: &'static str,
+    extensions: &'static [&'static str],
+    parse: fn(&Path) -> Result<T, String>,
+    id: fn(&T) -> &str,
+}
+
+const ENTITY_FILES: FileKind<EntityFile> = FileKind {
+    dir: "entities",
+    extensions: &["yaml", "yml"],
+    parse: read_entity_file,
+    id: entity_file_id,
+};
+
+const SECTION_FILES: FileKind<ParsedSection> = FileKind {
+    dir: "sections",
+    extensions: &["md"],
+    parse: parse_section_file,
+    id: section_file_id,
+};
+
+fn entity_file_id(entity: &EntityFile) -> &str {
+    &entity.id
+}
+
+fn section_file_id(section: &ParsedSection) -> &str {
+    &section.0.id
+}
+
+/// Identifies one version of a file
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        meta.is_file().then(|| FileStamp {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+}
+
+struct CachedFile<T> {
+    stamp: FileStamp,
+    /// The parse error when the file failed to parse
+    parsed: Result<T, String>,
+}
+
+/// Parsed files of one kind, indexed by the ID each one defines
+struct FileCache<T> {
+    files: HashMap<PathBuf, CachedFile<T>>,
+    ids: HashMap<String, PathBuf>,
+}
+
+impl<T> Default for FileCache<T> {
+    fn default() -> Self {
+        FileCache {
+            files: HashMap::new(),
+            ids: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> FileCache<T> {
+    /// The file defining `id` and its contents. An indexed file only costs a
+    /// stat; a miss or a changed file rescans the directory.
+    fn find(
+        &mut self,
+        workspace: &Path,
+        kind: &FileKind<T>,
+        id: &str,
+        parses: &mut usize,
+    ) -> Result<Option<(PathBuf, T)>, String> {
+        if let Some(hit) = self.current(kind, id) {
+            return Ok(Some(hit));
+        }
+        self.refresh(workspace, kind, parses)?;
+        Ok(self.current(kind, id))
+    }
+
+    /// The indexed file for `id`, if it hasn't changed since it was parsed
+    fn current(&self, kind: &FileKind<T>, id: &str) -> Option<(PathBuf, T)> {
+        let path = self.ids.get(id)?;
+        let cached = self.files.get(path)?;
+        let parsed = cached.parsed.as_ref().ok()?;
+        (FileStamp::of(path) == Some(cached.stamp) && (kind.id)(parsed) == id)
+            .then(|| (path.clone(), parsed.clone()))
+    }
+
+    /// Bring the cache in line with the directory: stat every file, re-parse
+    /// the ones that changed, drop the ones that are gone, and rebuild the index
+    fn refresh(
+        &mut self,
+        workspace: &Path,
+        kind: &FileKind<T>,
+        parses: &mut usize,
+    ) -> Result<(), String> {
+        let dir = workspace.join(kind.dir);
+        if !dir.exists() {
+            self.files.clear();
+            self.ids.clear();
+            return Ok(());
+        }
+
+        let mut seen = HashSet::new();
+        for entry in fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to read {} directory: {}", kind.dir, e))?
+        {
+            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let path = entry.path();
+
+            let matches = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| kind.extensions.contains(&e));
+            if !matches {
+                continue;
+            }
+            // Stamp before reading, so a write racing the parse just means
+            // the file is parsed again next time
+            let Some(stamp) = FileStamp::of(&path) else {
+                continue;
+            };
+            seen.insert(path.clone());
+
+            if self.files.get(&path).is_some_and(|f| f.stamp == stamp) {
+                continue;
+            }
+            *parses += 1;
+            let parsed = (kind.parse)(&path);
+            self.files.insert(path, CachedFile { stamp, parsed });
+        }
+        self.files.retain(|path, _| seen.contains(path));
+
+        // When two files claim an ID, the first by path wins
+        self.ids.clear();
+        let mut paths: Vec<&PathBuf> = self.files.keys().collect();
+        paths.sort();
+        for path in paths {
+            if let Ok(parsed) = &self.files[path].parsed {
+                self.ids
+                    .entry((kind.id)(parsed).to_string())
+                    .or_insert_with(|| path.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Contents of every parsed file, ordered by path
+    fn values(&self) -> Vec<T> {
+        let mut files: Vec<(&PathBuf, &CachedFile<T>)> = self.files.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        files
+            .into_iter()
+            .filter_map(|(_, f)| f.parsed.clone().ok())
+            .collect()
+    }
+
+    /// Files that failed to parse and why, ordered by path
+    fn failures(&self) -> Vec<(PathBuf, String)> {
+        let mut failures: Vec<(PathBuf, String)> = self
+            .files
+            .iter()
+            .filter_map(|(path, f)| f.parsed.as_ref().err().map(|e| (path.clone(), e.clone())))
+            .collect();
+        failures.sort();
+        failures
+    }
+
+    /// Drop `path` after the store writes it, so it's parsed afresh
+    fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+}
+
+/// Read and parse one `entities/*.yaml` file
+pub fn read_entity_file(path: &Path) -> Result<EntityFile, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read entity file: {}", e))?;
+    serde_yaml::from_str(&content).map_err(|e| format!("Failed to parse entity YAML: {}", e))
+}
+
+/// Read one `sections/*.md` file as its frontmatter and content
+pub fn parse_section_file(path: &Path) -> Result<(SectionFrontmatter, String), String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read section file: {}", e))?;
+
+    // Parse YAML frontmatter (between --- markers)
+    if !content.starts_with("---") {
+        return Err("Section file missing frontmatter".to_string());
+    }
+
+    let parts: Vec<&str> = content.splitn(3, "---").collect();
+    if parts.len() < 3 {
+        return Err("Invalid frontmatter format".to_string());
+    }
+
+    let yaml_str = parts[1].trim();
+    let markdown_content = parts[2].trim().to_string();
+
+    let frontmatter: SectionFrontmatter = serde_yaml::from_str(yaml_str)
+        .map_err(|e| format!("Failed to parse section frontmatter: {}", e))?;
+
+    Ok((frontmatter, markdown_content))
+}
+
 // ============================================================================
 // Utilities
 // ============================================================================
 
+/// Byte index of UTF-16 offset `offset` in `text`, if it is in range and falls
+/// between characters
+fn utf16_byte_index(text: &str, offset: i64) -> Option<usize> {
+    let mut units = 0i64;
+    for (index, c) in text.char_indices() {
+        if units == offset {
+            return Some(index);
+        }
+        if units > offset {
+            return None;
+        }
+        units += c.len_utf16() as i64;
+    }
+    (units == offset).then_some(text.len())
+}
+
+/// A section file's full text, frontmatter serialized from scratch
+fn render_section_file(frontmatter: &SectionFrontmatter, content: &str) -> Result<String, String> {
+    let yaml = serde_yaml::to_string(frontmatter)
+        .map_err(|e| format!("Failed to serialize frontmatter: {}", e))?;
+    Ok(format!("---\n{}---\n{}", yaml, content))
+}
+
+/// The section file at `path` with `changes` patched into its frontmatter, so
+/// untouched lines keep their bytes. Falls back to a full rewrite when the
+/// patch doesn't read back as exactly `frontmatter`.
+fn patched_section_file(
+    path: &Path,
+    frontmatter: &SectionFrontmatter,
+    content: &str,
+    changes: &[(&str, serde_yaml::Value)],
+) -> Result<String, String> {
+    let raw =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read section file: {}", e))?;
+    match patch_frontmatter(&raw, changes) {
+        Some(patched) if parses_as(&patched, frontmatter) => Ok(patched),
+        _ => {
+            log::warn!(
+                "Rewriting all frontmatter of section {}: it couldn't be patched in place",
+                frontmatter.id
+            );
+            render_section_file(frontmatter, content)
+        }
+    }
+}
+
+fn yaml_value<T: Serialize>(value: &T) -> Result<serde_yaml::Value, String> {
+    serde_yaml::to_value(value).map_err(|e| format!("Failed to serialize frontmatter: {}", e))
+}
+
+/// Split a section file into its frontmatter YAML and the rest (closing `---`
+/// and content), the way [`parse_section_file`] reads it
+fn split_frontmatter(raw: &str) -> Option<(&str, &str)> {
+    let body = raw.strip_prefix("---")?;
+    let end = body.find("---")?;
+    Some(body.split_at(end))
+}
+
+/// A top-level `key:` line's key
+fn top_level_key(line: &str) -> Option<&str> {
+    if line.starts_with(|c: char| c.is_whitespace() || c == '-' || c == '#') {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    let key = key.trim_matches(|c| c == '"' || c == '\'');
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    plain.then_some(key)
+}
+
+/// Replace the given top-level keys in a section file's frontmatter, each with
+/// its value as the store's serializer writes it, appending keys that are
+/// missing. Everything else, content included, is left as it was. `None` when
+/// the file has no frontmatter to patch.
+fn patch_frontmatter(raw: &str, changes: &[(&str, serde_yaml::Value)]) -> Option<String> {
+    let (yaml, rest) = split_frontmatter(raw)?;
+    let newline = if yaml.contains("\r\n") { "\r\n" } else { "\n" };
+
+    // Each top-level key with the lines that belong to it
+    let mut blocks: Vec<(Option<String>, String)> = vec![(None, String::new())];
+    for line in yaml.split_inclusive('\n') {
+        if let Some(key) = top_level_key(line) {
+            blocks.push((Some(key.to_string()), String::new()));
+        }
+        blocks.last_mut()?.1.push_str(line);
+    }
+
+    for (key, value) in changes {
+        let mut mapping = serde_yaml::Mapping::new();
+        mapping.insert((*key).into(), value.clone());
+        let rendered = serde_yaml::to_string(&mapping).ok()?.replace('\n', newline);
+        match blocks.iter_mut().find(|(k, _)| k.as_deref() == Some(*key)) {
+            Some(block) => block.1 = rendered,
+            None => {
+                let last = &mut blocks.last_mut()?.1;
+                if !last.ends_with('\n') {
+                    last.push_str(newline);
+                }
+                blocks.push((Some(key.to_string()), rendered));
+            }
+        }
+    }
+
+    let mut patched = String::from("---");
+    for (_, text) in &blocks {
+        patched.push_str(text);
+    }
+    patched.push_str(rest);
+    Some(patched)
+}
+
+/// Whether `raw` parses to the same frontmatter as `expected`
+fn parses_as(raw: &str, expected: &SectionFrontmatter) -> bool {
+    let Some((yaml, _)) = split_frontmatter(raw) else {
+        return false;
+    };
+    let parsed = serde_yaml::from_str::<SectionFrontmatter>(yaml.trim())
+        .ok()
+        .and_then(|fm| serde_yaml::to_value(fm).ok());
+    parsed.is_some() && parsed == serde_yaml::to_value(expected).ok()
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Entity name cannot be empty".to_string());
+    }
+    Ok(())
+}
+
+fn validate_title(title: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("Section title cannot be empty".to_string());
+    }
+    Ok(())
+}
+
+/// `NNN-slug-shortid.md`, matching the frontend's FileService.generateSectionFilename
+fn section_filename(order: i64, title: &str, id: &str) -> String {
+    // Lowercase, keep ASCII word characters, turn whitespace runs into hyphens
+    let mut slug = String::new();
+    for c in title.to_lowercase().chars() {
+        let c = match c {
+            c if c.is_whitespace() => '-',
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '-' => c,
+            _ => continue,
+        };
+        if !(c == '-' && slug.ends_with('-')) {
+            slug.push(c);
+        }
+    }
+    let slug: String = slug.chars().take(30).collect();
+    let short_id: String = id.chars().take(8).collect();
+    format!("{:03}-{}-{}.md", order, slug, short_id)
+}
+
+/// `<dir>/<sanitized name>.yaml`, or `<name>-2.yaml` and up when taken. Names
+/// differing only in case or punctuation sanitize alike, so they're numbered too.
+fn unique_entity_path(dir: &Path, name: &str) -> PathBuf {
+    let stem = sanitize_filename(name);
+    let taken = |stem: &str| {
+        dir.join(format!("{}.yaml", stem)).exists() || dir.join(format!("{}.yml", stem)).exists()
+    };
+
+    let mut candidate = stem.clone();
+    let mut n = 2;
+    while taken(&candidate) {
+        candidate = format!("{}-{}", stem, n);
+        n += 1;
+    }
+    dir.join(format!("{}.yaml", candidate))
+}
+
 fn sanitize_filename(name: &str) -> String {
     name.chars()
         .map(|c| {
@@ -883,7 +1702,7 @@ The wizard explained that magic requires sacrifice."#;
             metadata: HashMap::new(),
         };
 
-        let created = store.create_entity(entity.clone()).unwrap();
+        let created = store.create_entity(entity.clone()).unwrap().entity;
         assert_eq!(created.name, "Fire burns");
 
         // Verify it was saved
@@ -891,4 +1710,414 @@ The wizard explained that magic requires sacrifice."#;
         assert!(loaded.is_some());
         assert_eq!(loaded.unwrap().name, "Fire burns");
     }
+
+    #[test]
+    fn test_create_entity_generates_or_validates_id() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+
+        let entity = |id: &str, name: &str| Entity {
+            id: id.to_string(),
+            name: name.to_string(),
+            entity_type: "concept".to_string(),
+            description: String::new(),
+            aliases: Vec::new(),
+            metadata: HashMap::new(),
+        };
+
+        let generated = store.create_entity(entity("", "Magic")).unwrap().entity;
+        assert!(ids::is_uuid(&generated.id));
+
+        let err = store
+            .create_entity(entity("magic-1", "Magic system"))
+            .unwrap_err();
+        assert!(err.contains("not a UUID or ULID"));
+    }
+
+    #[test]
+    fn test_cache_picks_up_edits_after_first_read() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let id = "550e8400-e29b-41d4-a716-446655440000";
+        assert_eq!(
+            store.get_entity(id).unwrap().unwrap().name,
+            "Magic requires sacrifice"
+        );
+
+        // Edited outside the store, e.g. by the frontend
+        fs::write(
+            dir.path().join("entities").join("alice.yaml"),
+            format!("id: \"{}\"\nname: \"Magic is free\"\ntype: rule\n", id),
+        )
+        .unwrap();
+        let entity = store.get_entity(id).unwrap().unwrap();
+        assert_eq!(entity.name, "Magic is free");
+        assert_eq!(store.list_by_type("rule").unwrap().len(), 1);
+
+        let section_id = "660e8400-e29b-41d4-a716-446655440001";
+        assert_eq!(store.get_tags(section_id).unwrap().len(), 1);
+        fs::write(
+            dir.path().join("sections").join("001-chapter-1.md"),
+            format!(
+                "---\nid: \"{}\"\ntitle: \"Prologue\"\norder: 0\n---\nBefore.",
+                section_id
+            ),
+        )
+        .unwrap();
+        let section = store.get_section(section_id).unwrap().unwrap();
+        assert_eq!(section.title, "Prologue");
+        assert!(section.tags.is_empty());
+
+        fs::remove_file(dir.path().join("entities").join("alice.yaml")).unwrap();
+        assert!(store.get_entity(id).unwrap().is_none());
+        assert!(store.list_all().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_resolves_entities_without_rereading() {
+        let dir = TempDir::new().unwrap();
+        let entities = dir.path().join("entities");
+        fs::create_dir(&entities).unwrap();
+        let id = |n: usize| format!("00000000-0000-4000-8000-{:012}", n);
+        for n in 0..1000 {
+            fs::write(
+                entities.join(format!("entity-{}.yaml", n)),
+                format!("id: \"{}\"\nname: \"Entity {}\"\ntype: fact\n", id(n), n),
+            )
+            .unwrap();
+        }
+
+        let cache = EntityCache::default();
+        let store = EntityStore::new(dir.path()).with_cache(cache.clone());
+        assert_eq!(
+            store.get_entity(&id(500)).unwrap().unwrap().name,
+            "Entity 500"
+        );
+        let parsed = store.parse_count();
+        assert_eq!(parsed, 1000);
+
+        // Later lookups, including from new stores sharing the cache, hit the index
+        let other = EntityStore::new(dir.path()).with_cache(cache);
+        for n in [0, 999, 500, 123] {
+            assert_eq!(
+                other.get_entity(&id(n)).unwrap().unwrap().name,
+                format!("Entity {}", n)
+            );
+        }
+        assert_eq!(other.list_all().unwrap().len(), 1000);
+        assert_eq!(store.parse_count(), parsed);
+
+        // Only the changed file is parsed again
+        fs::write(
+            entities.join("entity-7.yaml"),
+            format!("id: \"{}\"\nname: \"Entity seven\"\ntype: fact\n", id(7)),
+        )
+        .unwrap();
+        assert_eq!(
+            store.get_entity(&id(7)).unwrap().unwrap().name,
+            "Entity seven"
+        );
+        assert_eq!(store.parse_count(), parsed + 1);
+    }
+
+    #[test]
+    fn test_create_entity_numbers_colliding_filenames() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let entity = |name: &str, entity_type: &str| Entity {
+            id: String::new(),
+            name: name.to_string(),
+            entity_type: entity_type.to_string(),
+            description: String::new(),
+            aliases: Vec::new(),
+            metadata: HashMap::new(),
+        };
+
+        let first = store
+            .create_entity(entity("John Smith", "fact"))
+            .unwrap()
+            .entity;
+        let second = store
+            .create_entity(entity("john smith", "wizard"))
+            .unwrap()
+            .entity;
+        assert_eq!(second.entity_type, "custom");
+
+        let entities = dir.path().join("entities");
+        let first_file = read_entity_file(&entities.join("john-smith.yaml")).unwrap();
+        let second_file = read_entity_file(&entities.join("john-smith-2.yaml")).unwrap();
+        assert_eq!(first_file.id, first.id);
+        assert_eq!(second_file.id, second.id);
+
+        assert!(store
+            .create_entity(entity("", "fact"))
+            .unwrap_err()
+            .contains("name cannot be empty"));
+    }
+
+    #[test]
+    fn test_update_and_delete_entity() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let id = "550e8400-e29b-41d4-a716-446655440000";
+        let path = dir.path().join("entities").join("alice.yaml");
+        fs::write(
+            &path,
+            format!(
+                "id: \"{}\"\nname: Alice\ntype: fact\ncreated_at: \"2024-01-01T00:00:00.000Z\"\n",
+                id
+            ),
+        )
+        .unwrap();
+
+        let updated = store
+            .update_entity(
+                id,
+                serde_json::json!({ "name": "Alice Liddell", "type": "nope" }),
+            )
+            .unwrap()
+            .entity;
+        assert_eq!(updated.name, "Alice Liddell");
+        assert_eq!(updated.entity_type, "custom");
+        let file = read_entity_file(&path).unwrap();
+        assert_eq!(file.created_at.as_deref(), Some("2024-01-01T00:00:00.000Z"));
+
+        assert!(store
+            .update_entity(id, serde_json::json!({ "name": "" }))
+            .is_err());
+        assert!(store
+            .update_entity(id, serde_json::json!({ "id": "other" }))
+            .is_err());
+
+        assert!(store.delete_entity(id).unwrap());
+        assert!(!path.exists());
+        assert!(!store.delete_entity(id).unwrap());
+    }
+
+    #[test]
+    fn test_entity_metadata_is_checked_against_schemas() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let id = "550e8400-e29b-41d4-a716-446655440000";
+        let schemas = dir
+            .path()
+            .join(crate::agent::entity_schemas::ENTITY_SCHEMAS_FILE);
+        fs::create_dir_all(schemas.parent().unwrap()).unwrap();
+        let schema = "schemas:\n  fact:\n    type: object\n    properties:\n      chapter: { type: integer }\n";
+        fs::write(&schemas, schema).unwrap();
+
+        // Advisory: the write goes through and the mismatch comes back with it
+        let saved = store
+            .update_entity(id, serde_json::json!({ "metadata": { "chapter": "one" } }))
+            .unwrap();
+        assert_eq!(saved.metadata_warnings.len(), 1);
+        assert_eq!(saved.metadata_warnings[0].field, "/chapter");
+        let json = serde_json::to_value(&saved).unwrap();
+        assert_eq!(json["metadataWarnings"][0]["field"], "/chapter");
+        assert_eq!(json["name"], "Magic requires sacrifice");
+
+        // Strict: the write is refused and the file left alone
+        fs::write(&schemas, format!("strict: true\n{}", schema)).unwrap();
+        let err = store
+            .update_entity(id, serde_json::json!({ "metadata": { "chapter": "two" } }))
+            .unwrap_err();
+        assert!(err.contains("metadata/chapter"));
+        let entity = store.get_entity(id).unwrap().unwrap();
+        assert_eq!(entity.metadata["chapter"], "one");
+
+        let saved = store
+            .update_entity(id, serde_json::json!({ "metadata": { "chapter": 2 } }))
+            .unwrap();
+        assert!(saved.metadata_warnings.is_empty());
+        assert!(serde_json::to_value(&saved)
+            .unwrap()
+            .get("metadataWarnings")
+            .is_none());
+    }
+
+    #[test]
+    fn test_section_filename_matches_frontend() {
+        assert_eq!(
+            section_filename(7, "Chapter 1: The  Beginning!", "abcdef12-3456"),
+            "007-chapter-1-the-beginning-abcdef12.md"
+        );
+        assert_eq!(
+            section_filename(12, "Café - part_2", "01h"),
+            "012-caf-part_2-01h.md"
+        );
+        assert_eq!(
+            section_filename(0, &"long ".repeat(10), "x").len(),
+            "000--x.md".len() + 30
+        );
+    }
+
+    #[test]
+    fn test_update_section_metadata_keeps_content_and_tags() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let id = "660e8400-e29b-41d4-a716-446655440001";
+
+        let metadata = SectionMetadata {
+            title: Some("Chapter One".to_string()),
+            order: Some(3),
+            ..Default::default()
+        };
+        let section = store.update_section_metadata(id, metadata).unwrap();
+        assert_eq!(section.title, "Chapter One");
+        assert_eq!(section.order, 3);
+        assert_eq!(section.tags.len(), 1);
+        assert!(section.content.contains("sacrifice"));
+
+        let reread = store.get_section(id).unwrap().unwrap();
+        assert_eq!(reread.title, "Chapter One");
+        assert!(dir
+            .path()
+            .join("sections")
+            .join("001-chapter-1.md")
+            .exists());
+
+        let empty = SectionMetadata {
+            title: Some(" ".to_string()),
+            ..Default::default()
+        };
+        assert!(store.update_section_metadata(id, empty).is_err());
+    }
+
+    /// The text a tag marks, by its UTF-16 range
+    fn tagged(section: &Section, tag_id: &str) -> String {
+        let tag = section.tags.iter().find(|t| t.id == tag_id).unwrap();
+        let units: Vec<u16> = section.content.encode_utf16().collect();
+        String::from_utf16(&units[tag.from as usize..tag.to as usize]).unwrap()
+    }
+
+    #[test]
+    fn test_insert_into_section_keeps_tags_on_their_text() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let id = "660e8400-e29b-41d4-a716-446655440001";
+        let path = dir.path().join("sections").join("001-chapter-1.md");
+        // Offsets are UTF-16 units: "ë" is one, the dragon two
+        fs::write(
+            &path,
+            format!(
+                "---\nid: \"{}\"\ntitle: Chapter 1\norder: 1\ntags:\n  - {{ id: zoe, entity_id: e1, from: 0, to: 3 }}\n  - {{ id: dragon, entity_id: e2, from: 8, to: 10 }}\n  - {{ id: fly, entity_id: e3, from: 11, to: 14 }}\n---\nZoë saw 🐉 fly.\n",
+                id
+            ),
+        )
+        .unwrap();
+
+        // After the dragon, before "fly"
+        let insert = store.insert_into_section(id, 11, "the ").unwrap();
+        assert_eq!(insert.section.content, "Zoë saw 🐉 the fly.");
+        assert_eq!(insert.tags_shifted, 1);
+        assert!(insert.tags_expanded.is_empty());
+        for (tag, text) in [("zoe", "Zoë"), ("dragon", "🐉"), ("fly", "fly")] {
+            assert_eq!(tagged(&insert.section, tag), text);
+        }
+
+        // Inside a tag, which grows to take in the new text
+        let insert = store.insert_into_section(id, 2, "ë").unwrap();
+        assert_eq!(insert.tags_expanded, vec!["zoe".to_string()]);
+        assert_eq!(tagged(&insert.section, "zoe"), "Zoëë");
+        assert_eq!(tagged(&insert.section, "dragon"), "🐉");
+
+        // Before everything; leading whitespace is trimmed away with the content
+        let insert = store.insert_into_section(id, 0, "\n\nSo. ").unwrap();
+        assert_eq!(insert.section.content, "So. Zoëë saw 🐉 the fly.");
+        assert_eq!(insert.tags_shifted, 3);
+        let reread = store.get_section(id).unwrap().unwrap();
+        for (tag, text) in [("zoe", "Zoëë"), ("dragon", "🐉"), ("fly", "fly")] {
+            assert_eq!(tagged(&reread, tag), text);
+        }
+
+        let append = store.append_to_section(id, " It rained.").unwrap();
+        assert!(append.section.content.ends_with("fly. It rained."));
+        assert_eq!(append.tags_shifted, 0);
+        assert_eq!(tagged(&append.section, "fly"), "fly");
+
+        // Inside the dragon's surrogate pair, and past the end
+        assert!(store.insert_into_section(id, 14, "x").is_err());
+        assert!(store.insert_into_section(id, 1000, "x").is_err());
+        assert!(store.insert_into_section(id, -1, "x").is_err());
+        assert!(store.validate_tags(id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_tags_reports_ranges_past_the_content() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let id = "660e8400-e29b-41d4-a716-446655440001";
+        fs::write(
+            dir.path().join("sections").join("001-chapter-1.md"),
+            format!(
+                "---\nid: \"{}\"\ntitle: Chapter 1\norder: 1\ntags:\n  - {{ id: ok, entity_id: e1, from: 0, to: 4 }}\n  - {{ id: past, entity_id: e1, from: 3, to: 9 }}\n  - {{ id: backwards, entity_id: e1, from: 2, to: 1 }}\n---\nShort\n",
+                id
+            ),
+        )
+        .unwrap();
+
+        let invalid: Vec<String> = store
+            .validate_tags(id)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(invalid, vec!["past", "backwards"]);
+        assert!(store.validate_tags("missing").is_err());
+    }
+
+    #[test]
+    fn test_merge_entities_moves_references() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let source = "550e8400-e29b-41d4-a716-446655440000";
+        let target = "880e8400-e29b-41d4-a716-446655440003";
+        fs::write(
+            dir.path().join("entities").join("blood-price.yaml"),
+            format!(
+                "id: \"{}\"\nname: Blood price\ntype: rule\naliases:\n  - Sacrifice Rule\nmetadata:\n  source: target\n",
+                target
+            ),
+        )
+        .unwrap();
+
+        let summary = store.merge_entities(source, target).unwrap();
+        assert_eq!(summary.sections_updated, 1);
+        assert_eq!(summary.tags_updated, 1);
+        assert_eq!(summary.entity.entity_type, "rule");
+        // The source name joins the aliases; the case-insensitive duplicate doesn't
+        assert_eq!(
+            summary.entity.aliases,
+            ["Sacrifice Rule", "Magic requires sacrifice"]
+        );
+        assert_eq!(summary.entity.metadata["source"], "target");
+        assert_eq!(summary.entity.description, "Established in chapter 1");
+
+        assert!(store.get_entity(source).unwrap().is_none());
+        assert!(!dir.path().join("entities").join("alice.yaml").exists());
+        let section = store
+            .get_section("660e8400-e29b-41d4-a716-446655440001")
+            .unwrap()
+            .unwrap();
+        assert_eq!(section.entity_ids, [target]);
+        assert_eq!(section.tags[0].entity_id, target);
+        assert_eq!(section.tags[0].from, 0);
+        assert!(section.content.contains("sacrifice"));
+    }
+
+    #[test]
+    fn test_merge_entities_rejects_bad_ids_without_writing() {
+        let dir = setup_test_workspace();
+        let store = EntityStore::new(dir.path());
+        let source = "550e8400-e29b-41d4-a716-446655440000";
+        let section_path = dir.path().join("sections").join("001-chapter-1.md");
+        let before = fs::read_to_string(&section_path).unwrap();
+
+        assert!(store.merge_entities(source, source).is_err());
+        let err = store.merge_entities(source, "missing").unwrap_err();
+        assert!(err.contains("missing"), "{}", err);
+
+        assert_eq!(fs::read_to_string(&section_path).unwrap(), before);
+        assert!(store.get_entity(source).unwrap().is_some());
+    }
 }
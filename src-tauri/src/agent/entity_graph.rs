@@ -0,0 +1,438 @@
+//! Entity relationship graph export.
+//!
+//! Builds a graph of the workspace's entities for visualization: one node per
+//! entity and an undirected edge between two entities for every section that
+//! mentions both (via `entity_ids` or tags), weighted by the number of such
+//! sections. The graph renders as Graphviz DOT or a nodes/edges JSON document.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+
+use super::atomic_write::write_atomic;
+use super::entity_api::EntityStore;
+
+/// Largest rendered graph returned as a string; bigger graphs must go to a file
+pub const MAX_GRAPH_STRING_BYTES: usize = 2_000_000;
+
+// ============================================================================
+// Graph Types
+// ============================================================================
+
+/// Output format for an exported graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "dot" | "graphviz" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            other => Err(format!(
+                "Unknown graph format '{}'. Use \"dot\" or \"json\".",
+                other
+            )),
+        }
+    }
+}
+
+/// Which entities and edges to include
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GraphOptions {
+    /// Only include entities of these types (all types when empty)
+    #[serde(default)]
+    pub entity_types: Vec<String>,
+    /// Drop edges shared by fewer sections than this
+    #[serde(default)]
+    pub min_weight: u32,
+}
+
+/// One entity in the graph
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub entity_type: String,
+    /// Number of sections that mention this entity
+    pub section_count: u32,
+}
+
+/// Two entities that appear in the same sections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    /// Number of sections mentioning both entities
+    pub weight: u32,
+}
+
+/// Entity co-occurrence graph
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntityGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Result of an export: the rendered graph, or where it was written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphExport {
+    pub format: GraphFormat,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub bytes: usize,
+    /// The rendered graph, when no output path was given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// File the graph was written to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+// ============================================================================
+// Building
+// ============================================================================
+
+/// Build the graph from the entities and sections in `store`
+pub fn build_graph(store: &EntityStore, options: &GraphOptions) -> Result<EntityGraph, String> {
+    let types: Vec<String> = options
+        .entity_types
+        .iter()
+        .map(|t| t.to_lowercase())
+        .collect();
+
+    let mut nodes: HashMap<String, GraphNode> = store
+        .list_all()?
+        .into_iter()
+        .filter(|e| types.is_empty() || types.contains(&e.entity_type.to_lowercase()))
+        .map(|e| {
+            let node = GraphNode {
+                id: e.id.clone(),
+                name: e.name,
+                entity_type: e.entity_type,
+                section_count: 0,
+            };
+            (e.id, node)
+        })
+        .collect();
+
+    // (smaller id, larger id) -> number of shared sections
+    let mut weights: BTreeMap<(String, String), u32> = BTreeMap::new();
+    for section in store.list_all_sections()? {
+        let mentioned: BTreeSet<&str> = section
+            .entity_ids
+            .iter()
+            .map(String::as_str)
+            .chain(section.tags.iter().map(|t| t.entity_id.as_str()))
+            .filter(|id| nodes.contains_key(*id))
+            .collect();
+
+        for id in &mentioned {
+            if let Some(node) = nodes.get_mut(*id) {
+                node.section_count += 1;
+            }
+        }
+        let mentioned: Vec<&str> = mentioned.into_iter().collect();
+        for (i, a) in mentioned.iter().enumerate() {
+            for b in &mentioned[i + 1..] {
+                *weights.entry((a.to_string(), b.to_string())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut nodes: Vec<GraphNode> = nodes.into_values().collect();
+    nodes.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+
+    let edges = weights
+        .into_iter()
+        .filter(|(_, weight)| *weight >= options.min_weight)
+        .map(|((source, target), weight)| GraphEdge {
+            source,
+            target,
+            weight,
+        })
+        .collect();
+
+    Ok(EntityGraph { nodes, edges })
+}
+
+// ============================================================================
+// Rendering
+// ============================================================================
+
+/// Render the graph in `format`
+pub fn render(graph: &EntityGraph, format: GraphFormat) -> Result<String, String> {
+    match format {
+        GraphFormat::Dot => Ok(to_dot(graph)),
+        GraphFormat::Json => serde_json::to_string_pretty(graph)
+            .map_err(|e| format!("Failed to serialize graph: {}", e)),
+    }
+}
+
+/// Graphviz DOT for an undirected graph
+pub fn to_dot(graph: &EntityGraph) -> String {
+    let mut out = String::from("graph entities {\n  node [shape=box];\n");
+    for node in &graph.nodes {
+        out.push_str(&format!(
+            "  {} [label={}, type={}];\n",
+            dot_id(&node.id),
+            dot_id(&node.name),
+            dot_id(&node.entity_type)
+        ));
+    }
+    for edge in &graph.edges {
+        out.push_str(&format!(
+            "  {} -- {} [weight={}, label=\"{}\"];\n",
+            dot_id(&edge.source),
+            dot_id(&edge.target),
+            edge.weight,
+            edge.weight
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A quoted DOT identifier
+fn dot_id(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => {}
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// ============================================================================
+// Export
+// ============================================================================
+
+/// Build and render the graph, writing it to `output` when given or returning
+/// it inline (up to `MAX_GRAPH_STRING_BYTES`) otherwise
+pub fn export_graph(
+    store: &EntityStore,
+    format: GraphFormat,
+    options: &GraphOptions,
+    output: Option<&Path>,
+) -> Result<GraphExport, String> {
+    let graph = build_graph(store, options)?;
+    let rendered = render(&graph, format)?;
+
+    let mut export = GraphExport {
+        format,
+        node_count: graph.nodes.len(),
+        edge_count: graph.edges.len(),
+        bytes: rendered.len(),
+        content: None,
+        path: None,
+    };
+
+    match output {
+        Some(path) => {
+            write_graph_file(path, &rendered)?;
+            export.path = Some(path.display().to_string());
+        }
+        None if rendered.len() > MAX_GRAPH_STRING_BYTES => {
+            return Err(format!(
+                "Graph is too large to return ({} bytes, max {}). Export it to a file instead.",
+                rendered.len(),
+                MAX_GRAPH_STRING_BYTES
+            ));
+        }
+        None => export.content = Some(rendered),
+    }
+    Ok(export)
+}
+
+/// Write the graph atomically so readers never see a partial one
+fn write_graph_file(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if !parent.exists() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directories: {}", e))?;
+        }
+    }
+    write_atomic(path, content.as_bytes()).map_err(|e| format!("Failed to write graph: {}", e))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_entity(dir: &Path, id: &str, name: &str, entity_type: &str) {
+        let yaml = format!(
+            "id: \"{}\"\nname: \"{}\"\ntype: {}\n",
+            id, name, entity_type
+        );
+        fs::write(dir.join("entities").join(format!("{}.yaml", id)), yaml).unwrap();
+    }
+
+    fn write_section(dir: &Path, id: &str, order: i64, entity_ids: &[&str], tagged: &[&str]) {
+        let mut yaml = format!("id: \"{}\"\ntitle: \"{}\"\norder: {}\n", id, id, order);
+        yaml.push_str("entity_ids:\n");
+        for e in entity_ids {
+            yaml.push_str(&format!("  - \"{}\"\n", e));
+        }
+        yaml.push_str("tags:\n");
+        for (i, e) in tagged.iter().enumerate() {
+            yaml.push_str(&format!(
+                "  - id: \"{}-t{}\"\n    entity_id: \"{}\"\n    from: 0\n    to: 1\n",
+                id, i, e
+            ));
+        }
+        fs::write(
+            dir.join("sections").join(format!("{}.md", id)),
+            format!("---\n{}---\nText", yaml),
+        )
+        .unwrap();
+    }
+
+    /// Alice and Bob share two sections, Alice and the castle one, and the
+    /// "Magic \"rule\"" fact is mentioned alone
+    fn fixture() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("entities")).unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+
+        write_entity(dir.path(), "alice", "Alice", "concept");
+        write_entity(dir.path(), "bob", "Bob", "concept");
+        write_entity(dir.path(), "castle", "Castle", "custom");
+        write_entity(dir.path(), "magic", "Magic \\\"rule\\\"", "fact");
+
+        write_section(dir.path(), "ch1", 1, &["alice", "bob"], &["alice"]);
+        write_section(dir.path(), "ch2", 2, &["alice"], &["bob", "castle"]);
+        write_section(dir.path(), "ch3", 3, &["magic", "ghost"], &[]);
+        dir
+    }
+
+    fn edge<'a>(graph: &'a EntityGraph, a: &str, b: &str) -> Option<&'a GraphEdge> {
+        graph
+            .edges
+            .iter()
+            .find(|e| (e.source == a && e.target == b) || (e.source == b && e.target == a))
+    }
+
+    #[test]
+    fn test_build_graph_counts_co_occurrence() {
+        let dir = fixture();
+        let store = EntityStore::new(dir.path());
+
+        let graph = build_graph(&store, &GraphOptions::default()).unwrap();
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.edges.len(), 3);
+        assert_eq!(edge(&graph, "alice", "bob").unwrap().weight, 2);
+        assert_eq!(edge(&graph, "alice", "castle").unwrap().weight, 1);
+        assert_eq!(edge(&graph, "bob", "castle").unwrap().weight, 1);
+
+        // A tag and an entity_ids entry in one section count once
+        let alice = graph.nodes.iter().find(|n| n.id == "alice").unwrap();
+        assert_eq!(alice.section_count, 2);
+    }
+
+    #[test]
+    fn test_build_graph_filters() {
+        let dir = fixture();
+        let store = EntityStore::new(dir.path());
+
+        let heavy = build_graph(
+            &store,
+            &GraphOptions {
+                min_weight: 2,
+                ..GraphOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(heavy.nodes.len(), 4);
+        assert_eq!(heavy.edges.len(), 1);
+
+        let concepts = build_graph(
+            &store,
+            &GraphOptions {
+                entity_types: vec!["Concept".to_string()],
+                ..GraphOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(concepts.nodes.len(), 2);
+        assert_eq!(concepts.edges.len(), 1);
+    }
+
+    #[test]
+    fn test_dot_output_is_well_formed() {
+        let dir = fixture();
+        let store = EntityStore::new(dir.path());
+        let graph = build_graph(&store, &GraphOptions::default()).unwrap();
+        let dot = to_dot(&graph);
+
+        assert!(dot.starts_with("graph entities {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("\"alice\" -- \"bob\" [weight=2"));
+        assert!(dot.contains("[label=\"Magic \\\"rule\\\"\""));
+
+        // Every statement line is a node or edge statement with balanced quotes
+        let statement =
+            regex::Regex::new(r#"^  "(?:[^"\\]|\\.)*"(?: -- "(?:[^"\\]|\\.)*")? \[[^\]]*\];$"#)
+                .unwrap();
+        let lines: Vec<&str> = dot.lines().collect();
+        for line in &lines[2..lines.len() - 1] {
+            assert!(statement.is_match(line), "bad DOT line: {}", line);
+        }
+        assert_eq!(lines.len(), 2 + 4 + 3 + 1);
+    }
+
+    #[test]
+    fn test_export_inline_and_to_file() {
+        let dir = fixture();
+        let store = EntityStore::new(dir.path());
+
+        let inline =
+            export_graph(&store, GraphFormat::Json, &GraphOptions::default(), None).unwrap();
+        let parsed: EntityGraph = serde_json::from_str(inline.content.as_deref().unwrap()).unwrap();
+        assert_eq!(parsed.nodes.len(), inline.node_count);
+        assert_eq!(parsed.edges.len(), inline.edge_count);
+
+        let target = dir.path().join("exports").join("graph.dot");
+        let written = export_graph(
+            &store,
+            GraphFormat::Dot,
+            &GraphOptions::default(),
+            Some(&target),
+        )
+        .unwrap();
+        assert!(written.content.is_none());
+        let on_disk = fs::read_to_string(&target).unwrap();
+        assert_eq!(on_disk.len(), written.bytes);
+        assert!(on_disk.starts_with("graph entities"));
+
+        // No temp files left behind
+        let leftovers = fs::read_dir(dir.path().join("exports")).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn test_graph_format_parse() {
+        assert_eq!(GraphFormat::parse("DOT").unwrap(), GraphFormat::Dot);
+        assert_eq!(GraphFormat::parse("json").unwrap(), GraphFormat::Json);
+        assert!(GraphFormat::parse("svg").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Per-type schemas for entity metadata.
+//!
+//! A workspace describes the metadata each entity type should carry in
+//! `.vswrite/entity-schemas.yaml`, as one JSON Schema per type:
+//!
+//! ```yaml
+//! strict: false   # reject writes that don't match instead of warning
+//! schemas:
+//!   event:
+//!     type: object
+//!     required: [year]
+//!     properties:
+//!       year: { type: integer }
+//! ```
+//!
+//! Types without a schema accept any metadata. A file or schema that can't be
+//! used is reported as a warning on the writes it would have checked, never an
+//! error, so a typo in the schemas can't block edits.
+
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Workspace-relative path of the entity schemas
+pub const ENTITY_SCHEMAS_FILE: &str = ".vswrite/entity-schemas.yaml";
+
+/// The schemas file as written
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SchemasFile {
+    strict: bool,
+    schemas: HashMap<String, serde_json::Value>,
+}
+
+/// One way an entity's metadata strays from its type's schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataIssue {
+    /// JSON pointer to the field, e.g. `/year`; empty for the metadata as a whole
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for MetadataIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "metadata: {}", self.message)
+        } else {
+            write!(f, "metadata{}: {}", self.field, self.message)
+        }
+    }
+}
+
+/// A workspace's entity schemas
+#[derive(Default)]
+pub struct EntitySchemas {
+    /// Reject writes whose metadata doesn't match instead of warning
+    pub strict: bool,
+    /// Compiled schema per lowercase type, or why it wouldn't compile
+    schemas: HashMap<String, Result<JSONSchema, String>>,
+    /// Why the file as a whole couldn't be used
+    unusable: Option<String>,
+}
+
+impl EntitySchemas {
+    /// Read the workspace's schemas. A missing file means no schemas.
+    pub fn load(workspace: &Path) -> Self {
+        let text = match fs::read_to_string(workspace.join(ENTITY_SCHEMAS_FILE)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return EntitySchemas::default(),
+            Err(e) => return Self::unusable(e.to_string()),
+        };
+        Self::parse(&text).unwrap_or_else(|e| {
+            log::warn!("Ignoring {}: {}", ENTITY_SCHEMAS_FILE, e);
+            Self::unusable(e)
+        })
+    }
+
+    fn unusable(reason: String) -> Self {
+        EntitySchemas {
+            unusable: Some(format!("{} is invalid: {}", ENTITY_SCHEMAS_FILE, reason)),
+            ..EntitySchemas::default()
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        if text.trim().is_empty() {
+            return Ok(EntitySchemas::default());
+        }
+        let file: SchemasFile = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+        let schemas = file
+            .schemas
+            .into_iter()
+            .map(|(entity_type, schema)| {
+                let compiled = JSONSchema::compile(&schema).map_err(|e| {
+                    format!(
+                        "the {} schema in {} is invalid: {}",
+                        entity_type, ENTITY_SCHEMAS_FILE, e
+                    )
+                });
+                (entity_type.to_lowercase(), compiled)
+            })
+            .collect();
+        Ok(EntitySchemas {
+            strict: file.strict,
+            schemas,
+            unusable: None,
+        })
+    }
+
+    /// Check `metadata` against the schema for `entity_type`. `Err` says why
+    /// it couldn't be checked.
+    pub fn check(
+        &self,
+        entity_type: &str,
+        metadata: &HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<MetadataIssue>, String> {
+        if let Some(reason) = &self.unusable {
+            return Err(reason.clone());
+        }
+        let Some(schema) = self.schemas.get(&entity_type.to_lowercase()) else {
+            return Ok(Vec::new());
+        };
+        let schema = schema.as_ref().map_err(Clone::clone)?;
+        let instance = serde_json::to_value(metadata).map_err(|e| e.to_string())?;
+        let issues = match schema.validate(&instance) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .map(|e| MetadataIssue {
+                    field: e.instance_path.to_string(),
+                    message: e.to_string(),
+                })
+                .collect(),
+        };
+        Ok(issues)
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metadata(value: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_check_reports_fields_that_dont_match() {
+        let schemas = EntitySchemas::parse(
+            "strict: true\nschemas:\n  Event:\n    type: object\n    required: [year]\n    properties:\n      year: { type: integer }\n",
+        )
+        .unwrap();
+        assert!(schemas.strict);
+
+        let ok = metadata(json!({ "year": 1066 }));
+        assert_eq!(schemas.check("event", &ok), Ok(Vec::new()));
+
+        let issues = schemas
+            .check("event", &metadata(json!({ "year": "1066" })))
+            .unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].field, "/year");
+        assert!(issues[0].to_string().starts_with("metadata/year: "));
+
+        let issues = schemas.check("event", &HashMap::new()).unwrap();
+        assert_eq!(issues[0].field, "");
+
+        // Types without a schema take anything
+        assert_eq!(schemas.check("fact", &ok), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_unusable_schemas_are_reported_not_applied() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let anything = metadata(json!({ "year": "soon" }));
+        assert_eq!(
+            EntitySchemas::load(dir.path()).check("event", &anything),
+            Ok(Vec::new())
+        );
+
+        fs::create_dir(dir.path().join(".vswrite")).unwrap();
+        let path = dir.path().join(ENTITY_SCHEMAS_FILE);
+        fs::write(&path, "schemas: [not, a, map]\n").unwrap();
+        let err = EntitySchemas::load(dir.path())
+            .check("event", &anything)
+            .unwrap_err();
+        assert!(err.contains(ENTITY_SCHEMAS_FILE));
+
+        fs::write(
+            &path,
+            "schemas:\n  event: { type: 12 }\n  fact: { type: object }\n",
+        )
+        .unwrap();
+        let schemas = EntitySchemas::load(dir.path());
+        assert!(schemas
+            .check("event", &anything)
+            .unwrap_err()
+            .contains("event schema"));
+        assert_eq!(schemas.check("fact", &anything), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,774 @@
+//! Entity mentions and tag suggestions.
+//!
+//! `suggest_entity_tags(section_id)` finds mentions of entity names and aliases in a
+//! section's prose and proposes tags for them without writing anything;
+//! `apply_suggested_tags(section_id, tags)` writes the ones the user (or the agent)
+//! accepts. `find_entity_mentions(entity_id)` goes the other way and lists every
+//! mention of one entity across the manuscript.
+//!
+//! Matching is case-insensitive and whole-word. When candidates overlap, the longest
+//! wins; ties go to the earlier match, then to the lower entity ID, so the same text
+//! always yields the same suggestions. Ranges already covered by a tag are skipped.
+//!
+//! Tag offsets are UTF-16 code units into the section content, the same positions
+//! the editor uses, so tags written here line up with tags made by hand.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use super::entity_api::{Entity, EntityStore, Tag};
+use super::tools::PathValidator;
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Names of the entity tag tools
+pub const ENTITY_TAG_TOOLS: &[&str] = &[
+    "suggest_entity_tags",
+    "apply_suggested_tags",
+    "find_entity_mentions",
+];
+
+/// Most mentions `find_entity_mentions` reports; the rest are counted as truncated
+pub const MAX_MENTIONS: usize = 200;
+
+/// Characters of context kept on each side of a mention in its excerpt
+const EXCERPT_CONTEXT_CHARS: usize = 40;
+
+/// Whether a tool name is one of the entity tag tools
+pub fn is_entity_tag_tool(name: &str) -> bool {
+    ENTITY_TAG_TOOLS.contains(&name)
+}
+
+/// A proposed tag for a mention of an entity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestedTag {
+    pub entity_id: String,
+    pub entity_name: String,
+    /// The matched text as it appears in the section
+    pub text: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+/// One mention of an entity in a section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mention {
+    /// The name or alias that matched, as the entity spells it
+    pub alias_matched: String,
+    /// 1-based line within the section content
+    pub line: usize,
+    /// Start of the mention in UTF-16 code units (`from` for a tag)
+    pub char_offset: i64,
+    /// End of the mention in UTF-16 code units (`to` for a tag)
+    pub char_end: i64,
+    /// The mention's line, cut to some context around it
+    pub excerpt: String,
+    /// Whether an existing tag already covers part of the mention
+    pub tagged: bool,
+}
+
+/// Mentions of an entity in one section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SectionMentions {
+    pub section_id: String,
+    pub title: String,
+    pub matches: Vec<Mention>,
+}
+
+/// Every mention of an entity across the manuscript, in section order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MentionReport {
+    pub entity_id: String,
+    pub entity_name: String,
+    pub sections: Vec<SectionMentions>,
+    /// Mentions reported (at most `MAX_MENTIONS`)
+    pub total_matches: usize,
+    /// Whether mentions past `MAX_MENTIONS` were left out
+    pub truncated: bool,
+}
+
+/// A tag to write: which entity, and where
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagRange {
+    pub entity_id: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+// ============================================================================
+// Matching
+// ============================================================================
+
+/// Lowercase a character when it has a single-character lowercase form, so folded
+/// text stays index-aligned with the original
+fn fold(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// UTF-16 offset of each char index in `chars`, plus the total length at the end
+fn utf16_offsets(chars: &[char]) -> Vec<i64> {
+    let mut offsets = Vec::with_capacity(chars.len() + 1);
+    let mut offset = 0i64;
+    offsets.push(0);
+    for c in chars {
+        offset += c.len_utf16() as i64;
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// Length of `content` in UTF-16 code units
+pub fn utf16_len(content: &str) -> i64 {
+    content.encode_utf16().count() as i64
+}
+
+/// An entity's name and aliases, each with its folded form, without blanks or
+/// case-insensitive duplicates
+fn entity_terms(entity: &Entity) -> Vec<(&str, Vec<char>)> {
+    let mut terms: Vec<(&str, Vec<char>)> = Vec::new();
+    for term in std::iter::once(&entity.name).chain(&entity.aliases) {
+        let term = term.trim();
+        let folded: Vec<char> = term.chars().map(fold).collect();
+        if !term.is_empty() && !terms.iter().any(|(_, f)| *f == folded) {
+            terms.push((term, folded));
+        }
+    }
+    terms
+}
+
+/// Char ranges where the folded `term` occurs in `text` as a whole word. Terms are
+/// compared literally, so punctuation in an alias ("Dr. K") has no special meaning.
+fn term_matches(text: &[char], folded: &[char], term: &[char]) -> Vec<(usize, usize)> {
+    let first_is_word = is_word_char(term[0]);
+    let last_is_word = is_word_char(term[term.len() - 1]);
+    let mut matches = Vec::new();
+    for start in 0..folded.len().saturating_sub(term.len() - 1) {
+        let end = start + term.len();
+        if folded[start..end] != term[..] {
+            continue;
+        }
+        let bounded_left = start == 0 || !first_is_word || !is_word_char(text[start - 1]);
+        let bounded_right = end == text.len() || !last_is_word || !is_word_char(text[end]);
+        if bounded_left && bounded_right {
+            matches.push((start, end));
+        }
+    }
+    matches
+}
+
+struct Candidate<'a> {
+    start: usize,
+    end: usize,
+    entity: &'a Entity,
+}
+
+/// Propose tags for every entity name and alias mentioned in `content` that doesn't
+/// overlap an existing tag. Results are ordered by position.
+pub fn suggest_tags(content: &str, entities: &[Entity], existing: &[Tag]) -> Vec<SuggestedTag> {
+    let text: Vec<char> = content.chars().collect();
+    let folded: Vec<char> = text.iter().map(|&c| fold(c)).collect();
+    let offsets = utf16_offsets(&text);
+
+    let mut candidates = Vec::new();
+    for entity in entities {
+        for (_, term) in entity_terms(entity) {
+            for (start, end) in term_matches(&text, &folded, &term) {
+                candidates.push(Candidate { start, end, entity });
+            }
+        }
+    }
+
+    // Longest first, then earliest, then lowest entity ID
+    candidates.sort_by(|a, b| {
+        (b.end - b.start)
+            .cmp(&(a.end - a.start))
+            .then(a.start.cmp(&b.start))
+            .then(a.entity.id.cmp(&b.entity.id))
+    });
+
+    let mut taken: Vec<(i64, i64)> = existing.iter().map(|t| (t.from, t.to)).collect();
+    let mut suggestions = Vec::new();
+    for candidate in candidates {
+        let (from, to) = (offsets[candidate.start], offsets[candidate.end]);
+        if taken.iter().any(|&(f, t)| from < t && f < to) {
+            continue;
+        }
+        taken.push((from, to));
+        suggestions.push(SuggestedTag {
+            entity_id: candidate.entity.id.clone(),
+            entity_name: candidate.entity.name.clone(),
+            text: text[candidate.start..candidate.end].iter().collect(),
+            from,
+            to,
+        });
+    }
+
+    suggestions.sort_by_key(|s| s.from);
+    suggestions
+}
+
+/// Mentions of `entity` in `content`, in order. Where two of its terms overlap
+/// ("Anna" in "Anna Karenina") only the longer is reported.
+pub fn find_mentions(content: &str, entity: &Entity, existing: &[Tag]) -> Vec<Mention> {
+    let text: Vec<char> = content.chars().collect();
+    let folded: Vec<char> = text.iter().map(|&c| fold(c)).collect();
+    let offsets = utf16_offsets(&text);
+
+    let mut candidates: Vec<(usize, usize, &str)> = Vec::new();
+    for (term, folded_term) in entity_terms(entity) {
+        for (start, end) in term_matches(&text, &folded, &folded_term) {
+            candidates.push((start, end, term));
+        }
+    }
+    // Longest first, then earliest
+    candidates.sort_by(|a, b| (b.1 - b.0).cmp(&(a.1 - a.0)).then(a.0.cmp(&b.0)));
+    let mut kept: Vec<(usize, usize, &str)> = Vec::new();
+    for candidate in candidates {
+        if !kept.iter().any(|k| candidate.0 < k.1 && k.0 < candidate.1) {
+            kept.push(candidate);
+        }
+    }
+    kept.sort_by_key(|k| k.0);
+
+    kept.into_iter()
+        .map(|(start, end, term)| {
+            let (from, to) = (offsets[start], offsets[end]);
+            Mention {
+                alias_matched: term.to_string(),
+                line: 1 + text[..start].iter().filter(|&&c| c == '\n').count(),
+                char_offset: from,
+                char_end: to,
+                excerpt: excerpt(&text, start, end),
+                tagged: existing.iter().any(|t| from < t.to && t.from < to),
+            }
+        })
+        .collect()
+}
+
+/// The line holding `text[start..end]`, cut to `EXCERPT_CONTEXT_CHARS` on each side
+fn excerpt(text: &[char], start: usize, end: usize) -> String {
+    let line_start = text[..start]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1);
+    let line_end = text[end..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(text.len(), |i| end + i);
+    let from = start.saturating_sub(EXCERPT_CONTEXT_CHARS).max(line_start);
+    let to = (end + EXCERPT_CONTEXT_CHARS).min(line_end);
+
+    let mut excerpt = String::new();
+    if from > line_start {
+        excerpt.push('…');
+    }
+    excerpt.extend(&text[from..to]);
+    if to < line_end {
+        excerpt.push('…');
+    }
+    excerpt.trim().to_string()
+}
+
+// ============================================================================
+// Store Operations
+// ============================================================================
+
+/// Suggest tags for a section without writing anything
+pub fn suggest_entity_tags(
+    store: &EntityStore,
+    section_id: &str,
+) -> Result<Vec<SuggestedTag>, String> {
+    let section = store
+        .get_section(section_id)?
+        .ok_or_else(|| format!("Section {} not found", section_id))?;
+    let entities = store.list_all()?;
+    Ok(suggest_tags(&section.content, &entities, &section.tags))
+}
+
+/// Every mention of an entity's name or aliases across all sections, up to
+/// `MAX_MENTIONS`
+pub fn find_entity_mentions(store: &EntityStore, entity_id: &str) -> Result<MentionReport, String> {
+    let entity = store
+        .get_entity(entity_id)?
+        .ok_or_else(|| format!("Entity {} not found", entity_id))?;
+
+    let mut report = MentionReport {
+        entity_id: entity.id.clone(),
+        entity_name: entity.name.clone(),
+        sections: Vec::new(),
+        total_matches: 0,
+        truncated: false,
+    };
+    for section in store.list_all_sections()? {
+        let mut matches = find_mentions(&section.content, &entity, &section.tags);
+        if matches.is_empty() {
+            continue;
+        }
+        let room = MAX_MENTIONS - report.total_matches;
+        if matches.len() > room {
+            matches.truncate(room);
+            report.truncated = true;
+        }
+        report.total_matches += matches.len();
+        if !matches.is_empty() {
+            report.sections.push(SectionMentions {
+                section_id: section.id,
+                title: section.title,
+                matches,
+            });
+        }
+        if report.truncated {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+/// Write tags to a section after checking each names an existing entity, lies within
+/// the content, and overlaps neither an existing tag nor another new one
+pub fn apply_suggested_tags(
+    store: &EntityStore,
+    section_id: &str,
+    tags: &[TagRange],
+) -> Result<Vec<Tag>, String> {
+    if tags.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let section = store
+        .get_section(section_id)?
+        .ok_or_else(|| format!("Section {} not found", section_id))?;
+    let len = utf16_len(&section.content);
+
+    let mut known: HashSet<&str> = HashSet::new();
+    let mut taken: Vec<(i64, i64)> = section.tags.iter().map(|t| (t.from, t.to)).collect();
+    for tag in tags {
+        if tag.from < 0 || tag.from >= tag.to || tag.to > len {
+            return Err(format!(
+                "Invalid range {}..{} for entity {}: section content is {} characters",
+                tag.from, tag.to, tag.entity_id, len
+            ));
+        }
+        if !known.contains(tag.entity_id.as_str()) {
+            if store.get_entity(&tag.entity_id)?.is_none() {
+                return Err(format!("Entity {} not found", tag.entity_id));
+            }
+            known.insert(&tag.entity_id);
+        }
+        if taken.iter().any(|&(f, t)| tag.from < t && f < tag.to) {
+            return Err(format!(
+                "Range {}..{} for entity {} overlaps an existing tag",
+                tag.from, tag.to, tag.entity_id
+            ));
+        }
+        taken.push((tag.from, tag.to));
+    }
+
+    let ranges: Vec<(String, i64, i64)> = tags
+        .iter()
+        .map(|t| (t.entity_id.clone(), t.from, t.to))
+        .collect();
+    store.add_tags(section_id, &ranges)
+}
+
+// ============================================================================
+// Tool Interface
+// ============================================================================
+
+/// Dispatch an entity tag tool call
+pub fn dispatch(
+    paths: &PathValidator,
+    name: &str,
+    args: &serde_json::Value,
+) -> Result<String, String> {
+    let store = paths.entity_store();
+    if name == "find_entity_mentions" {
+        let entity_id = args
+            .get("entity_id")
+            .and_then(|v| v.as_str())
+            .ok_or("Missing 'entity_id' parameter")?;
+        let report = find_entity_mentions(&store, entity_id)?;
+        return serde_json::to_string_pretty(&report)
+            .map_err(|e| format!("Failed to serialize mentions: {}", e));
+    }
+
+    let section_id = args
+        .get("section_id")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing 'section_id' parameter")?;
+    match name {
+        "suggest_entity_tags" => {
+            let suggestions = suggest_entity_tags(&store, section_id)?;
+            serde_json::to_string_pretty(&suggestions)
+                .map_err(|e| format!("Failed to serialize suggestions: {}", e))
+        }
+        "apply_suggested_tags" => {
+            let tags: Vec<TagRange> = serde_json::from_value(
+                args.get("tags")
+                    .cloned()
+                    .ok_or("Missing 'tags' parameter")?,
+            )
+            .map_err(|e| format!("Invalid 'tags' parameter: {}", e))?;
+            let written = apply_suggested_tags(&store, section_id, &tags)?;
+            Ok(format!(
+                "Added {} tag(s) to section {}",
+                written.len(),
+                section_id
+            ))
+        }
+        _ => Err(format!("Unknown entity tag tool: {}", name)),
+    }
+}
+
+/// Schemas for the entity tag tools
+pub fn entity_tag_tool_schemas() -> Vec<Tool> {
+    vec![
+        suggest_entity_tags_schema(),
+        apply_suggested_tags_schema(),
+        find_entity_mentions_schema(),
+    ]
+}
+
+fn section_id_property() -> PropertySchema {
+    PropertySchema {
+        prop_type: "string".to_string(),
+        description: Some("ID of the section".to_string()),
+        default: None,
+        items: None,
+    }
+}
+
+fn suggest_entity_tags_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("section_id".to_string(), section_id_property());
+
+    Tool::new(
+        "suggest_entity_tags",
+        "Find mentions of entity names and aliases in a section and propose tags for the untagged ones. Writes nothing; returns entityId, entityName, text, from, and to for each suggestion.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string()]),
+        },
+    )
+}
+
+fn apply_suggested_tags_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("section_id".to_string(), section_id_property());
+    properties.insert(
+        "tags".to_string(),
+        PropertySchema {
+            prop_type: "array".to_string(),
+            description: Some(
+                "Tags to add, each {\"entityId\", \"from\", \"to\"} as returned by suggest_entity_tags"
+                    .to_string(),
+            ),
+            default: None,
+            items: Some(Box::new(PropertySchema {
+                prop_type: "object".to_string(),
+                description: None,
+                default: None,
+                items: None,
+            })),
+        },
+    );
+
+    Tool::new(
+        "apply_suggested_tags",
+        "Add entity tags to a section. Every tag must name an existing entity and must not overlap an existing tag.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string(), "tags".to_string()]),
+        },
+    )
+}
+
+fn find_entity_mentions_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "entity_id".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("ID of the entity".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "find_entity_mentions",
+        "Find every mention of an entity's name and aliases (case-insensitive, whole words) across all sections. Returns, per section, each match's alias_matched, line, char_offset and char_end (usable as from/to for apply_suggested_tags), excerpt, and whether it is already tagged.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["entity_id".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const ANNA: &str = "11111111-1111-4111-8111-111111111111";
+    const KARENINA: &str = "22222222-2222-4222-8222-222222222222";
+    const ZOE: &str = "33333333-3333-4333-8333-333333333333";
+    const SECTION: &str = "44444444-4444-4444-8444-444444444444";
+
+    fn entity(id: &str, name: &str, aliases: &[&str]) -> Entity {
+        Entity {
+            id: id.to_string(),
+            name: name.to_string(),
+            entity_type: "fact".to_string(),
+            description: String::new(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn tag(entity_id: &str, from: i64, to: i64) -> Tag {
+        Tag {
+            id: "t".to_string(),
+            entity_id: entity_id.to_string(),
+            from,
+            to,
+        }
+    }
+
+    fn spans(suggestions: &[SuggestedTag]) -> Vec<(&str, &str, i64, i64)> {
+        suggestions
+            .iter()
+            .map(|s| (s.entity_id.as_str(), s.text.as_str(), s.from, s.to))
+            .collect()
+    }
+
+    #[test]
+    fn test_names_and_aliases_whole_word_case_insensitive() {
+        let entities = vec![entity(ANNA, "Anna", &["Annie"])];
+        let suggestions = suggest_tags(
+            "ANNA met annie. Annabel and Joanna did not.",
+            &entities,
+            &[],
+        );
+        assert_eq!(
+            spans(&suggestions),
+            vec![(ANNA, "ANNA", 0, 4), (ANNA, "annie", 9, 14)]
+        );
+    }
+
+    #[test]
+    fn test_longest_overlapping_match_wins() {
+        let entities = vec![
+            entity(ANNA, "Anna", &[]),
+            entity(KARENINA, "Karenina", &["Anna Karenina"]),
+        ];
+        let suggestions = suggest_tags("Anna Karenina sighed. Anna laughed.", &entities, &[]);
+        assert_eq!(
+            spans(&suggestions),
+            vec![(KARENINA, "Anna Karenina", 0, 13), (ANNA, "Anna", 22, 26)]
+        );
+
+        // The same alias on two entities goes to the lower ID, every time
+        let entities = vec![
+            entity(KARENINA, "Karenina", &["the heroine"]),
+            entity(ANNA, "Anna", &["the heroine"]),
+        ];
+        let suggestions = suggest_tags("Enter the heroine.", &entities, &[]);
+        assert_eq!(spans(&suggestions), vec![(ANNA, "the heroine", 6, 17)]);
+    }
+
+    #[test]
+    fn test_skips_already_tagged_ranges() {
+        let entities = vec![entity(ANNA, "Anna", &[])];
+        let existing = vec![tag(ANNA, 0, 4), tag(ZOE, 12, 20)];
+        let suggestions = suggest_tags("Anna, then Anna, then Anna.", &entities, &existing);
+        assert_eq!(spans(&suggestions), vec![(ANNA, "Anna", 22, 26)]);
+    }
+
+    #[test]
+    fn test_multibyte_offsets_are_utf16() {
+        let entities = vec![entity(ZOE, "Zoë", &[]), entity(ANNA, "Ånna", &[])];
+        // '🎉' is two UTF-16 code units; 'ë' and 'Å' are one
+        let suggestions = suggest_tags("🎉 zoë and ÅNNA", &entities, &[]);
+        assert_eq!(
+            spans(&suggestions),
+            vec![(ZOE, "zoë", 3, 6), (ANNA, "ÅNNA", 11, 15)]
+        );
+        assert_eq!(utf16_len("🎉 zoë and ÅNNA"), 15);
+    }
+
+    fn setup_workspace() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("entities")).unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        fs::write(
+            dir.path().join("entities/anna.yaml"),
+            format!(
+                "id: {}\nname: Anna\ntype: fact\naliases:\n  - Annie\n",
+                ANNA
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("sections/01.md"),
+            format!(
+                "---\nid: {}\ntitle: One\norder: 0\ntags:\n  - id: old\n    entity_id: {}\n    from: 0\n    to: 4\n---\nAnna waved. Annie smiled.",
+                SECTION, ANNA
+            ),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_find_mentions_with_punctuated_aliases() {
+        // '.' and '*' would be special in a regex; here they only match themselves
+        let kat = entity(ANNA, "Katherine", &["Kat", "Dr. K", "K*t", "kat"]);
+        let content = "Katherine met Kathy.\nDR. K said: K*t, Kat's here. Dr Kx and Dr. Kat left.";
+        let mentions = find_mentions(content, &kat, &[tag(ZOE, 0, 3)]);
+
+        let found: Vec<(&str, usize, i64, i64, bool)> = mentions
+            .iter()
+            .map(|m| {
+                (
+                    m.alias_matched.as_str(),
+                    m.line,
+                    m.char_offset,
+                    m.char_end,
+                    m.tagged,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("Katherine", 1, 0, 9, true),
+                ("Dr. K", 2, 21, 26, false),
+                ("K*t", 2, 33, 36, false),
+                ("Kat", 2, 38, 41, false),
+                ("Kat", 2, 64, 67, false),
+            ]
+        );
+        // ASCII content, so UTF-16 offsets are byte offsets
+        for m in &mentions {
+            let text = &content[m.char_offset as usize..m.char_end as usize];
+            assert!(text.eq_ignore_ascii_case(&m.alias_matched), "{}", text);
+        }
+        assert_eq!(
+            mentions[1].excerpt,
+            "DR. K said: K*t, Kat's here. Dr Kx and Dr. Ka…"
+        );
+    }
+
+    #[test]
+    fn test_mention_excerpts_are_capped() {
+        let anna = entity(ANNA, "Anna", &[]);
+        let content = format!("{} Anna {}", "x".repeat(100), "y".repeat(100));
+        let mentions = find_mentions(&content, &anna, &[]);
+        assert_eq!(mentions.len(), 1);
+        let expected = format!("…{} Anna {}…", "x".repeat(39), "y".repeat(39));
+        assert_eq!(mentions[0].excerpt, expected);
+    }
+
+    #[test]
+    fn test_find_entity_mentions_across_sections() {
+        let dir = setup_workspace();
+        fs::write(
+            dir.path().join("sections/02.md"),
+            format!(
+                "---\nid: 55555555-5555-4555-8555-555555555555\ntitle: Two\norder: 1\n---\n{}",
+                "Anna. ".repeat(MAX_MENTIONS)
+            ),
+        )
+        .unwrap();
+        let store = EntityStore::new(dir.path());
+
+        let report = find_entity_mentions(&store, ANNA).unwrap();
+        assert_eq!(report.entity_name, "Anna");
+        assert!(report.truncated);
+        assert_eq!(report.total_matches, MAX_MENTIONS);
+        assert_eq!(report.sections.len(), 2);
+        assert_eq!(report.sections[0].section_id, SECTION);
+        assert_eq!(report.sections[0].matches.len(), 2);
+        assert!(report.sections[0].matches[0].tagged);
+        assert!(!report.sections[0].matches[1].tagged);
+
+        // The offsets are the ones apply_suggested_tags takes
+        let annie = &report.sections[0].matches[1];
+        let range = TagRange {
+            entity_id: ANNA.to_string(),
+            from: annie.char_offset,
+            to: annie.char_end,
+        };
+        apply_suggested_tags(&store, SECTION, &[range]).unwrap();
+
+        let err = find_entity_mentions(&store, ZOE).unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_suggest_then_apply() {
+        let dir = setup_workspace();
+        let paths = PathValidator::new(dir.path());
+        let args = serde_json::json!({"section_id": SECTION});
+
+        let suggested = dispatch(&paths, "suggest_entity_tags", &args).unwrap();
+        let suggested: Vec<SuggestedTag> = serde_json::from_str(&suggested).unwrap();
+        assert_eq!(spans(&suggested), vec![(ANNA, "Annie", 12, 17)]);
+
+        // Suggesting writes nothing
+        let store = EntityStore::new(dir.path());
+        assert_eq!(store.get_tags(SECTION).unwrap().len(), 1);
+
+        let args = serde_json::json!({"section_id": SECTION, "tags": suggested});
+        let result = dispatch(&paths, "apply_suggested_tags", &args).unwrap();
+        assert_eq!(result, format!("Added 1 tag(s) to section {}", SECTION));
+        let tags = store.get_tags(SECTION).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!((tags[1].from, tags[1].to), (12, 17));
+
+        // Nothing is left to suggest
+        assert!(suggest_entity_tags(&store, SECTION).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_apply_rejects_bad_tags() {
+        let dir = setup_workspace();
+        let store = EntityStore::new(dir.path());
+        let range = |entity_id: &str, from, to| TagRange {
+            entity_id: entity_id.to_string(),
+            from,
+            to,
+        };
+
+        let err = apply_suggested_tags(&store, SECTION, &[range(ANNA, 2, 6)]).unwrap_err();
+        assert!(err.contains("overlaps"), "{}", err);
+        let err = apply_suggested_tags(&store, SECTION, &[range(ANNA, 20, 99)]).unwrap_err();
+        assert!(err.contains("Invalid range"), "{}", err);
+        let err = apply_suggested_tags(&store, SECTION, &[range(ZOE, 5, 10)]).unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+        let err = apply_suggested_tags(&store, SECTION, &[range(ANNA, 5, 10), range(ANNA, 8, 11)])
+            .unwrap_err();
+        assert!(err.contains("overlaps"), "{}", err);
+
+        // Nothing was written
+        assert_eq!(store.get_tags(SECTION).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+//! Where a run's events go.
+//!
+//! A run hands its events to an [`EventSink`]. The app's sink is a channel the
+//! UI drains from a separate task ([`ChannelSink`]); if the webview stalls, the
+//! channel fills. Waiting for room would stall the run along with the UI, so
+//! events are queued with `try_send` and dropped (and counted) when the channel is
+//! full. Only the lifecycle events a client can't do without (`Start`,
+//! `ToolApprovalRequired`, `Complete`, `Error`, `Cancelled`) wait for room. A run
+//! that lost any events reports how many in a `DroppedEvents` event just before
+//! its final one.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use super::types::AgentEvent;
+
+/// Future returned by [`EventSink::on_lifecycle_event`]
+pub type EventFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Receives a run's events
+pub trait EventSink: Send + Sync {
+    /// Take `event`. The run calls this inline, so it must not block; a sink
+    /// that falls behind drops events instead.
+    fn on_event(&self, event: &AgentEvent);
+
+    /// Take a lifecycle event, which the receiver can't do without. A sink that
+    /// drops events waits for room for these instead.
+    fn on_lifecycle_event<'a>(&'a self, event: &'a AgentEvent) -> EventFuture<'a> {
+        self.on_event(event);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// Queues events on a channel, dropping what doesn't fit
+#[derive(Debug)]
+pub struct ChannelSink {
+    tx: mpsc::Sender<AgentEvent>,
+    dropped: AtomicU64,
+}
+
+impl ChannelSink {
+    pub fn new(tx: mpsc::Sender<AgentEvent>) -> Self {
+        ChannelSink {
+            tx,
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+impl EventSink for ChannelSink {
+    /// Send `event` if the channel has room, otherwise count it as dropped
+    fn on_event(&self, event: &AgentEvent) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(event.clone()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Wait for room, first reporting dropped events if `event` ends the run
+    fn on_lifecycle_event<'a>(&'a self, event: &'a AgentEvent) -> EventFuture<'a> {
+        Box::pin(async move {
+            if let Some(run_id) = final_run_id(event) {
+                let count = self.dropped.swap(0, Ordering::Relaxed);
+                if count > 0 {
+                    log::warn!("Dropped {} agent events: the UI fell behind", count);
+                    let _ = self
+                        .tx
+                        .send(AgentEvent::DroppedEvents { count, run_id })
+                        .await;
+                }
+            }
+            let _ = self.tx.send(event.clone()).await;
+        })
+    }
+}
+
+/// A run's handle on its event sink; clones share the sink
+#[derive(Clone)]
+pub struct EventSender {
+    sink: Arc<dyn EventSink>,
+}
+
+impl fmt::Debug for EventSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventSender").finish_non_exhaustive()
+    }
+}
+
+impl EventSender {
+    /// Send events to a channel (see [`ChannelSink`])
+    #[cfg(test)]
+    pub fn new(tx: mpsc::Sender<AgentEvent>) -> Self {
+        Self::from_sink(Arc::new(ChannelSink::new(tx)))
+    }
+
+    pub fn from_sink(sink: Arc<dyn EventSink>) -> Self {
+        EventSender { sink }
+    }
+
+    /// Send `event`, waiting for room only if it's a lifecycle event
+    pub async fn send(&self, event: AgentEvent) {
+        if is_lifecycle(&event) {
+            self.sink.on_lifecycle_event(&event).await;
+        } else {
+            self.sink.on_event(&event);
+        }
+    }
+
+    /// Send `event` without waiting, even if it's a lifecycle event
+    pub fn try_send(&self, event: AgentEvent) {
+        self.sink.on_event(&event);
+    }
+}
+
+/// Whether the UI must see `event` even if the run has to wait for it
+fn is_lifecycle(event: &AgentEvent) -> bool {
+    matches!(
+        event,
+        AgentEvent::Start { .. }
+            | AgentEvent::ToolApprovalRequired { .. }
+            | AgentEvent::Complete { .. }
+            | AgentEvent::Error { .. }
+            | AgentEvent::Cancelled { .. }
+    )
+}
+
+/// The run ID of an event that ends a run
+fn final_run_id(event: &AgentEvent) -> Option<Option<String>> {
+    match event {
+        AgentEvent::Complete { run_id, .. }
+        | AgentEvent::Error { run_id, .. }
+        | AgentEvent::Cancelled { run_id, .. } => Some(run_id.clone()),
+        _ => None,
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(n: usize) -> AgentEvent {
+        AgentEvent::TextChunk {
+            content: n.to_string(),
+            run_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_drops_and_reports() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let events = EventSender::new(tx);
+        for n in 0..5 {
+            events.send(chunk(n)).await;
+        }
+
+        // The final event waits for room, after the notice
+        let sender = events.clone();
+        let finish = tokio::spawn(async move {
+            sender
+                .send(AgentEvent::Cancelled {
+                    summary: None,
+                    reason: None,
+                    run_id: Some("run-1".to_string()),
+                })
+                .await;
+        });
+        let mut received = Vec::new();
+        while let Some(event) = rx.recv().await {
+            received.push(event);
+            if received.len() == 4 {
+                break;
+            }
+        }
+        finish.await.unwrap();
+
+        assert!(matches!(&received[0], AgentEvent::TextChunk { content, .. } if content == "0"));
+        assert!(matches!(&received[1], AgentEvent::TextChunk { content, .. } if content == "1"));
+        assert!(matches!(
+            &received[2],
+            AgentEvent::DroppedEvents { count: 3, run_id } if run_id.as_deref() == Some("run-1")
+        ));
+        assert!(matches!(received[3], AgentEvent::Cancelled { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_nothing_dropped_sends_no_notice() {
+        let (tx, mut rx) = mpsc::channel(8);
+        let events = EventSender::new(tx);
+        events.send(chunk(0)).await;
+        events
+            .send(AgentEvent::Error {
+                error: "failed".to_string(),
+                summary: None,
+                run_id: None,
+            })
+            .await;
+        drop(events);
+
+        let mut kinds = Vec::new();
+        while let Some(event) = rx.recv().await {
+            kinds.push(matches!(event, AgentEvent::DroppedEvents { .. }));
+        }
+        assert_eq!(kinds, vec![false, false]);
+    }
+}
@@ -0,0 +1,315 @@
+//! Execution audit for Lua extensions.
+//!
+//! Every extension tool call and lifecycle hook is recorded here with its duration
+//! and outcome. Counters cover the extension's whole lifetime in the registry;
+//! percentiles are computed over a rolling window of recent executions so one slow
+//! afternoon doesn't haunt an extension forever.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::tools::TOOL_CANCELLED;
+
+/// Recent executions kept per extension for percentile calculation
+pub const STATS_WINDOW: usize = 200;
+
+/// p95 duration above which doctor reports an extension as slow
+pub const SLOW_EXTENSION_P95: Duration = Duration::from_secs(2);
+
+/// Executions needed before an extension can be reported as slow
+pub const SLOW_EXTENSION_MIN_SAMPLES: usize = 5;
+
+/// What was executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionKind {
+    Tool,
+    Hook,
+}
+
+/// Coarse classification of an extension failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    /// The run was cancelled while the script ran
+    Cancelled,
+    /// A shell command or other operation timed out
+    Timeout,
+    /// The script or the Lua runtime failed to load
+    Load,
+    /// The script doesn't define the expected function
+    MissingFunction,
+    /// The function raised an error
+    Runtime,
+    Other,
+}
+
+impl ErrorClass {
+    /// Classify an error message produced by the Lua runtime
+    pub fn classify(error: &str) -> Self {
+        let lower = error.to_lowercase();
+        if error.contains(TOOL_CANCELLED) {
+            ErrorClass::Cancelled
+        } else if lower.contains("timed out") || lower.contains("timeout") {
+            ErrorClass::Timeout
+        } else if error.starts_with("Failed to load script")
+            || error.starts_with("Failed to create Lua runtime")
+        {
+            ErrorClass::Load
+        } else if error.starts_with("Function '") && error.contains("not found") {
+            ErrorClass::MissingFunction
+        } else if error.starts_with("Function call failed") {
+            ErrorClass::Runtime
+        } else {
+            ErrorClass::Other
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ExtensionRecord {
+    tool_executions: u64,
+    hook_executions: u64,
+    failures: u64,
+    errors: BTreeMap<ErrorClass, u64>,
+    /// Durations of the most recent executions
+    recent: VecDeque<Duration>,
+}
+
+/// Aggregated execution stats for one extension
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionStatsSummary {
+    pub extension_id: String,
+    pub executions: u64,
+    pub tool_executions: u64,
+    pub hook_executions: u64,
+    pub failures: u64,
+    /// Failures / executions, 0 when nothing has run
+    pub failure_rate: f64,
+    /// Failure counts by error class
+    pub errors: BTreeMap<ErrorClass, u64>,
+    /// Number of recent executions the durations below are computed over
+    pub window: usize,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+impl ExtensionStatsSummary {
+    /// Whether doctor should warn that this extension is slow
+    pub fn is_slow(&self) -> bool {
+        self.window >= SLOW_EXTENSION_MIN_SAMPLES
+            && self.p95_ms > SLOW_EXTENSION_P95.as_millis() as u64
+    }
+}
+
+/// Per-extension execution records, shared by every clone of the registry
+#[derive(Debug)]
+pub struct ExtensionStats {
+    records: Mutex<HashMap<String, ExtensionRecord>>,
+    window: usize,
+}
+
+impl ExtensionStats {
+    pub fn new() -> Self {
+        Self::with_window(STATS_WINDOW)
+    }
+
+    /// Stats keeping `window` recent executions per extension
+    pub fn with_window(window: usize) -> Self {
+        ExtensionStats {
+            records: Mutex::new(HashMap::new()),
+            window: window.max(1),
+        }
+    }
+
+    /// Record one execution. `name` is the tool or hook that ran.
+    pub fn record(
+        &self,
+        extension_id: &str,
+        kind: ExecutionKind,
+        name: &str,
+        duration: Duration,
+        error: Option<&str>,
+    ) {
+        let Ok(mut records) = self.records.lock() else {
+            return;
+        };
+        let record = records.entry(extension_id.to_string()).or_default();
+
+        match kind {
+            ExecutionKind::Tool => record.tool_executions += 1,
+            ExecutionKind::Hook => record.hook_executions += 1,
+        }
+        if let Some(error) = error {
+            let class = ErrorClass::classify(error);
+            record.failures += 1;
+            *record.errors.entry(class).or_insert(0) += 1;
+            log::debug!(
+                "Extension {} {:?} '{}' failed ({:?}) after {:?}",
+                extension_id,
+                kind,
+                name,
+                class,
+                duration
+            );
+        }
+
+        if record.recent.len() == self.window {
+            record.recent.pop_front();
+        }
+        record.recent.push_back(duration);
+    }
+
+    /// Summary for one extension, if it has run since it was loaded
+    pub fn summary(&self, extension_id: &str) -> Option<ExtensionStatsSummary> {
+        let records = self.records.lock().ok()?;
+        records
+            .get(extension_id)
+            .map(|record| summarize(extension_id, record))
+    }
+
+    /// Summaries for every extension that has run, sorted by id
+    pub fn summaries(&self) -> Vec<ExtensionStatsSummary> {
+        let Ok(records) = self.records.lock() else {
+            return Vec::new();
+        };
+        let mut summaries: Vec<_> = records
+            .iter()
+            .map(|(id, record)| summarize(id, record))
+            .collect();
+        summaries.sort_by(|a, b| a.extension_id.cmp(&b.extension_id));
+        summaries
+    }
+
+    /// Forget an extension's records (when it is unloaded)
+    pub fn remove(&self, extension_id: &str) {
+        if let Ok(mut records) = self.records.lock() {
+            records.remove(extension_id);
+        }
+    }
+}
+
+impl Default for ExtensionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn summarize(extension_id: &str, record: &ExtensionRecord) -> ExtensionStatsSummary {
+    let mut durations: Vec<u64> = record.recent.iter().map(|d| d.as_millis() as u64).collect();
+    durations.sort_unstable();
+
+    let executions = record.tool_executions + record.hook_executions;
+    ExtensionStatsSummary {
+        extension_id: extension_id.to_string(),
+        executions,
+        tool_executions: record.tool_executions,
+        hook_executions: record.hook_executions,
+        failures: record.failures,
+        failure_rate: if executions == 0 {
+            0.0
+        } else {
+            record.failures as f64 / executions as f64
+        },
+        errors: record.errors.clone(),
+        window: durations.len(),
+        p50_ms: percentile(&durations, 50),
+        p95_ms: percentile(&durations, 95),
+        max_ms: durations.last().copied().unwrap_or(0),
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_percentiles_and_failure_rate() {
+        let stats = ExtensionStats::new();
+        for i in 1..=20 {
+            let error = (i % 4 == 0).then_some("Function call failed: boom");
+            stats.record("ext", ExecutionKind::Tool, "t", ms(i * 10), error);
+        }
+        stats.record("ext", ExecutionKind::Hook, "on_activate", ms(5), None);
+
+        let summary = stats.summary("ext").unwrap();
+        assert_eq!(summary.executions, 21);
+        assert_eq!(summary.tool_executions, 20);
+        assert_eq!(summary.hook_executions, 1);
+        assert_eq!(summary.failures, 5);
+        assert!((summary.failure_rate - 5.0 / 21.0).abs() < 1e-9);
+        assert_eq!(summary.errors[&ErrorClass::Runtime], 5);
+        assert_eq!(summary.p50_ms, 100);
+        assert_eq!(summary.p95_ms, 190);
+        assert_eq!(summary.max_ms, 200);
+        assert!(stats.summary("other").is_none());
+    }
+
+    #[test]
+    fn test_window_rolls_but_totals_do_not() {
+        let stats = ExtensionStats::with_window(3);
+        stats.record("ext", ExecutionKind::Tool, "t", ms(5000), None);
+        for _ in 0..3 {
+            stats.record("ext", ExecutionKind::Tool, "t", ms(10), None);
+        }
+
+        let summary = stats.summary("ext").unwrap();
+        assert_eq!(summary.executions, 4);
+        assert_eq!(summary.window, 3);
+        assert_eq!(summary.max_ms, 10);
+    }
+
+    #[test]
+    fn test_slow_needs_enough_samples() {
+        let stats = ExtensionStats::new();
+        for _ in 0..SLOW_EXTENSION_MIN_SAMPLES - 1 {
+            stats.record("ext", ExecutionKind::Tool, "t", ms(3000), None);
+        }
+        assert!(!stats.summary("ext").unwrap().is_slow());
+
+        stats.record("ext", ExecutionKind::Tool, "t", ms(3000), None);
+        assert!(stats.summary("ext").unwrap().is_slow());
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        assert_eq!(ErrorClass::classify(TOOL_CANCELLED), ErrorClass::Cancelled);
+        assert_eq!(
+            ErrorClass::classify("Function call failed: Command timed out after 30s"),
+            ErrorClass::Timeout
+        );
+        assert_eq!(
+            ErrorClass::classify("Failed to load script: syntax error"),
+            ErrorClass::Load
+        );
+        assert_eq!(
+            ErrorClass::classify("Function 'go' not found: nil"),
+            ErrorClass::MissingFunction
+        );
+        assert_eq!(
+            ErrorClass::classify("Function call failed: attempt to index nil"),
+            ErrorClass::Runtime
+        );
+        assert_eq!(ErrorClass::classify("weird"), ErrorClass::Other);
+    }
+}
@@ -0,0 +1,652 @@
+//! Git tools for checkpointing work in the workspace repository.
+//!
+//! `git_status`, `git_diff`, and `git_commit(message, paths)` let the agent commit its
+//! own changes without the blanket access of `run_shell`. They go through libgit2
+//! rather than the `git` binary and only operate on a repository rooted at the
+//! workspace itself; a workspace nested inside a larger repository is refused so the
+//! agent can't see or commit files outside it.
+//!
+//! `git_commit` commits exactly the named paths, like `git commit --only <paths>`:
+//! anything else the user has staged stays staged and out of the commit.
+
+use git2::{
+    DiffFormat, DiffOptions, ErrorCode, Index, IndexAddOption, Repository, Signature, Status,
+    StatusOptions,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::tools::PathValidator;
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Names of the git tools
+pub const GIT_TOOLS: &[&str] = &["git_status", "git_diff", "git_commit"];
+
+/// Maximum size of `git_diff` output
+pub const MAX_DIFF_BYTES: usize = 20_000;
+
+/// Maximum length of a commit message
+const MAX_COMMIT_MESSAGE_BYTES: usize = 10_000;
+
+/// Identity used when the repository has no user.name/user.email configured
+const FALLBACK_AUTHOR: (&str, &str) = ("VS Write Agent", "agent@vswrite.local");
+
+/// Whether a tool name is one of the git tools
+pub fn is_git_tool(name: &str) -> bool {
+    GIT_TOOLS.contains(&name)
+}
+
+/// Open the repository rooted at the workspace
+fn open_workspace_repo(paths: &PathValidator) -> Result<Repository, String> {
+    let workspace = paths.canonical_workspace()?;
+
+    let repo = Repository::discover(&workspace).map_err(|e| {
+        if e.code() == ErrorCode::NotFound {
+            "Not a git repository: the workspace is not under version control".to_string()
+        } else {
+            format!("Failed to open git repository: {}", e.message())
+        }
+    })?;
+
+    let root = repo
+        .workdir()
+        .ok_or("Not a git repository: the workspace repository is bare")?;
+    let root = root
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve repository root: {}", e))?;
+    if root != workspace {
+        return Err(format!(
+            "Repository root {} is outside the workspace; git tools only work on a repository rooted at the workspace",
+            root.display()
+        ));
+    }
+
+    Ok(repo)
+}
+
+/// Resolve a requested path to a repository-relative path with `/` separators
+fn repo_relative(paths: &PathValidator, requested: &str) -> Result<(PathBuf, String), String> {
+    let resolved = paths.resolve(requested)?;
+    let workspace = paths.canonical_workspace()?;
+    let relative = resolved
+        .strip_prefix(&workspace)
+        .map_err(|_| format!("Path {} is outside the workspace", requested))?
+        .to_string_lossy()
+        .replace('\\', "/");
+    if relative.is_empty() {
+        return Err("Name specific files or directories, not the workspace root".to_string());
+    }
+    if relative == ".git" || relative.starts_with(".git/") {
+        return Err("Access denied: cannot operate on the .git directory".to_string());
+    }
+    Ok((resolved, relative))
+}
+
+/// Two-letter status code in the style of `git status --porcelain`
+fn status_code(status: Status) -> Option<String> {
+    if status.contains(Status::IGNORED) {
+        return None;
+    }
+    if status.contains(Status::WT_NEW) && !status.intersects(Status::INDEX_NEW) {
+        return Some("??".to_string());
+    }
+    if status.contains(Status::CONFLICTED) {
+        return Some("UU".to_string());
+    }
+
+    let index = if status.contains(Status::INDEX_NEW) {
+        'A'
+    } else if status.contains(Status::INDEX_MODIFIED) {
+        'M'
+    } else if status.contains(Status::INDEX_DELETED) {
+        'D'
+    } else if status.contains(Status::INDEX_RENAMED) {
+        'R'
+    } else if status.contains(Status::INDEX_TYPECHANGE) {
+        'T'
+    } else {
+        ' '
+    };
+    let worktree = if status.contains(Status::WT_MODIFIED) {
+        'M'
+    } else if status.contains(Status::WT_DELETED) {
+        'D'
+    } else if status.contains(Status::WT_RENAMED) {
+        'R'
+    } else if status.contains(Status::WT_TYPECHANGE) {
+        'T'
+    } else {
+        ' '
+    };
+
+    if index == ' ' && worktree == ' ' {
+        None
+    } else {
+        Some(format!("{}{}", index, worktree))
+    }
+}
+
+/// Summarize the working tree: current branch and changed paths
+pub fn git_status(paths: &PathValidator) -> Result<String, String> {
+    let repo = open_workspace_repo(paths)?;
+
+    let branch = match repo.head() {
+        Ok(head) => head.shorthand().unwrap_or("HEAD (detached)").to_string(),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => "(no commits yet)".to_string(),
+        Err(e) => return Err(format!("Failed to read HEAD: {}", e.message())),
+    };
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .map_err(|e| format!("Failed to read status: {}", e.message()))?;
+
+    let mut lines = vec![format!("On branch {}", branch)];
+    let mut changed = 0;
+    for entry in statuses.iter() {
+        let Some(code) = status_code(entry.status()) else {
+            continue;
+        };
+        let path = String::from_utf8_lossy(entry.path_bytes());
+        lines.push(format!("{} {}", code, path));
+        changed += 1;
+    }
+    if changed == 0 {
+        lines.push("Nothing to commit, working tree clean".to_string());
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Show the patch for unstaged (or, with `staged`, staged) changes
+pub fn git_diff(paths: &PathValidator, path: Option<&str>, staged: bool) -> Result<String, String> {
+    let repo = open_workspace_repo(paths)?;
+
+    let mut options = DiffOptions::new();
+    if let Some(path) = path {
+        options.pathspec(repo_relative(paths, path)?.1);
+    }
+
+    let diff = if staged {
+        let head_tree = match repo.head() {
+            Ok(head) => Some(
+                head.peel_to_tree()
+                    .map_err(|e| format!("Failed to read HEAD tree: {}", e.message()))?,
+            ),
+            Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(format!("Failed to read HEAD: {}", e.message())),
+        };
+        repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut options))
+    } else {
+        repo.diff_index_to_workdir(None, Some(&mut options))
+    }
+    .map_err(|e| format!("Failed to compute diff: {}", e.message()))?;
+
+    let mut output = String::new();
+    let mut truncated = false;
+    let printed = diff.print(DiffFormat::Patch, |_, _, line| {
+        let mut text = String::new();
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            text.push(line.origin());
+        }
+        text.push_str(&String::from_utf8_lossy(line.content()));
+        if output.len() + text.len() > MAX_DIFF_BYTES {
+            truncated = true;
+            return false;
+        }
+        output.push_str(&text);
+        true
+    });
+    if let Err(e) = printed {
+        // Stopping the callback early surfaces as an error; only report real ones
+        if !truncated {
+            return Err(format!("Failed to format diff: {}", e.message()));
+        }
+    }
+
+    if output.is_empty() {
+        return Ok(if staged {
+            "No staged changes".to_string()
+        } else {
+            "No unstaged changes".to_string()
+        });
+    }
+    if truncated {
+        output.push_str(&format!(
+            "\n[Diff truncated at {} bytes; pass a path to narrow it]",
+            MAX_DIFF_BYTES
+        ));
+    }
+    Ok(output)
+}
+
+/// Commit the current contents of exactly the named paths.
+///
+/// The named paths are staged in the repository index (as `git add` would), then
+/// committed on top of HEAD without including anything else that is staged.
+pub fn git_commit(
+    paths: &PathValidator,
+    message: &str,
+    targets: &[String],
+) -> Result<String, String> {
+    let message = message.trim();
+    if message.is_empty() {
+        return Err("Commit message cannot be empty".to_string());
+    }
+    if message.len() > MAX_COMMIT_MESSAGE_BYTES {
+        return Err(format!(
+            "Commit message too long (max {} bytes)",
+            MAX_COMMIT_MESSAGE_BYTES
+        ));
+    }
+    if targets.is_empty() {
+        return Err("Name at least one path to commit".to_string());
+    }
+
+    let repo = open_workspace_repo(paths)?;
+    let git_err = |what: &str, e: git2::Error| format!("Failed to {}: {}", what, e.message());
+
+    // Stage the named paths in the real index
+    let mut index = repo.index().map_err(|e| git_err("open index", e))?;
+    let mut relative_paths = Vec::with_capacity(targets.len());
+    for target in targets {
+        let (absolute, relative) = repo_relative(paths, target)?;
+        if absolute.is_dir() {
+            index
+                .add_all([relative.as_str()], IndexAddOption::DEFAULT, None)
+                .map_err(|e| git_err("stage directory", e))?;
+            index
+                .update_all([relative.as_str()], None)
+                .map_err(|e| git_err("stage directory", e))?;
+        } else if absolute.exists() {
+            index
+                .add_path(Path::new(&relative))
+                .map_err(|e| git_err("stage file", e))?;
+        } else if index.get_path(Path::new(&relative), 0).is_some() {
+            index
+                .remove_path(Path::new(&relative))
+                .map_err(|e| git_err("stage deletion", e))?;
+        } else {
+            return Err(format!("Path not found: {}", target));
+        }
+        relative_paths.push(relative);
+    }
+
+    let head_commit = match repo.head() {
+        Ok(head) => Some(
+            head.peel_to_commit()
+                .map_err(|e| git_err("read HEAD commit", e))?,
+        ),
+        Err(e) if e.code() == ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(git_err("read HEAD", e)),
+    };
+    let head_tree = match head_commit {
+        Some(ref commit) => Some(commit.tree().map_err(|e| git_err("read HEAD tree", e))?),
+        None => None,
+    };
+
+    // Build the commit tree: HEAD plus the named paths as now staged
+    let mut commit_index = Index::new().map_err(|e| git_err("create index", e))?;
+    if let Some(ref tree) = head_tree {
+        commit_index
+            .read_tree(tree)
+            .map_err(|e| git_err("read HEAD tree", e))?;
+    }
+    let is_named = |entry_path: &str| {
+        relative_paths.iter().any(|p| {
+            entry_path == p
+                || (entry_path.starts_with(p.as_str())
+                    && entry_path.as_bytes().get(p.len()) == Some(&b'/'))
+        })
+    };
+    let stale: Vec<String> = commit_index
+        .iter()
+        .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+        .filter(|p| is_named(p))
+        .collect();
+    for path in stale {
+        commit_index
+            .remove_path(Path::new(&path))
+            .map_err(|e| git_err("build commit tree", e))?;
+    }
+    for entry in index.iter() {
+        if is_named(&String::from_utf8_lossy(&entry.path)) {
+            commit_index
+                .add(&entry)
+                .map_err(|e| git_err("build commit tree", e))?;
+        }
+    }
+
+    let tree_id = commit_index
+        .write_tree_to(&repo)
+        .map_err(|e| git_err("write tree", e))?;
+    if head_tree.as_ref().map(|t| t.id()) == Some(tree_id) {
+        return Err("Nothing to commit: the named paths have no changes".to_string());
+    }
+    let tree = repo
+        .find_tree(tree_id)
+        .map_err(|e| git_err("read tree", e))?;
+
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now(FALLBACK_AUTHOR.0, FALLBACK_AUTHOR.1))
+        .map_err(|e| git_err("create signature", e))?;
+    let parents: Vec<_> = head_commit.iter().collect();
+    let commit_id = repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .map_err(|e| git_err("commit", e))?;
+
+    // Persist the staged paths only once the commit exists
+    index.write().map_err(|e| git_err("write index", e))?;
+
+    let short_id = commit_id.to_string().chars().take(7).collect::<String>();
+    let subject = message.lines().next().unwrap_or(message);
+    Ok(format!(
+        "Committed {} ({} path{}): {}",
+        short_id,
+        relative_paths.len(),
+        if relative_paths.len() == 1 { "" } else { "s" },
+        subject
+    ))
+}
+
+/// Dispatch one of the git tools
+pub fn dispatch(
+    paths: &PathValidator,
+    name: &str,
+    args: &serde_json::Value,
+) -> Result<String, String> {
+    match name {
+        "git_status" => git_status(paths),
+        "git_diff" => {
+            let path = args.get("path").and_then(|v| v.as_str());
+            let staged = args
+                .get("staged")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            git_diff(paths, path, staged)
+        }
+        "git_commit" => {
+            let message = args
+                .get("message")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'message' parameter")?;
+            let targets: Vec<String> = args
+                .get("paths")
+                .and_then(|v| v.as_array())
+                .ok_or("Missing 'paths' parameter")?
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(str::to_string)
+                        .ok_or("'paths' must be an array of strings")
+                })
+                .collect::<Result<_, _>>()?;
+            let result = git_commit(paths, message, &targets);
+            // Staging may have created index entries for paths we had cached as missing
+            paths.clear();
+            result
+        }
+        _ => Err(format!("Unknown tool: {}", name)),
+    }
+}
+
+// ============================================================================
+// Tool Schemas
+// ============================================================================
+
+/// Schemas for the git tools
+pub fn git_tool_schemas() -> Vec<Tool> {
+    vec![git_status_schema(), git_diff_schema(), git_commit_schema()]
+}
+
+fn git_status_schema() -> Tool {
+    Tool::new(
+        "git_status",
+        "Show the current branch and which files are modified, staged, or untracked in the workspace git repository.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(HashMap::new()),
+            required: None,
+        },
+    )
+}
+
+fn git_diff_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "path".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Limit the diff to this file or directory".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "staged".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some("Show staged changes instead of unstaged ones".to_string()),
+            default: Some(serde_json::json!(false)),
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "git_diff",
+        &format!(
+            "Show uncommitted changes in the workspace git repository as a patch. Output is limited to {} bytes.",
+            MAX_DIFF_BYTES
+        ),
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: None,
+        },
+    )
+}
+
+fn git_commit_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "message".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Commit message".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "paths".to_string(),
+        PropertySchema {
+            prop_type: "array".to_string(),
+            description: Some(
+                "Files or directories to commit (relative to workspace); nothing else is included"
+                    .to_string(),
+            ),
+            default: None,
+            items: Some(Box::new(PropertySchema {
+                prop_type: "string".to_string(),
+                description: None,
+                default: None,
+                items: None,
+            })),
+        },
+    );
+
+    Tool::new(
+        "git_commit",
+        "Commit the current contents of the named paths to the workspace git repository.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["message".to_string(), "paths".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn setup_repo() -> (TempDir, Repository, PathValidator) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test Writer").unwrap();
+            config.set_str("user.email", "writer@example.com").unwrap();
+        }
+        let paths = PathValidator::new(dir.path());
+        (dir, repo, paths)
+    }
+
+    fn head_files(repo: &Repository) -> Vec<String> {
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        tree.iter()
+            .map(|entry| entry.name().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_not_a_repository() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        let err = git_status(&paths).unwrap_err();
+        assert!(err.contains("Not a git repository"), "{}", err);
+    }
+
+    #[test]
+    fn test_repo_root_outside_workspace_is_refused() {
+        let (dir, _repo, _) = setup_repo();
+        fs::create_dir(dir.path().join("book")).unwrap();
+        fs::write(dir.path().join("book/ch1.md"), "text").unwrap();
+
+        let paths = PathValidator::new(&dir.path().join("book"));
+        let err = git_commit(&paths, "checkpoint", &["ch1.md".to_string()]).unwrap_err();
+        assert!(err.contains("outside the workspace"), "{}", err);
+        assert!(git_status(&paths).is_err());
+    }
+
+    #[test]
+    fn test_status_reflects_changes() {
+        let (dir, _repo, paths) = setup_repo();
+        let status = git_status(&paths).unwrap();
+        assert!(status.contains("no commits yet"));
+        assert!(status.contains("working tree clean"));
+
+        fs::write(dir.path().join("ch1.md"), "Once upon a time").unwrap();
+        git_commit(&paths, "Add chapter one", &["ch1.md".to_string()]).unwrap();
+        fs::write(dir.path().join("ch1.md"), "Once upon a midnight").unwrap();
+        fs::write(dir.path().join("ch2.md"), "Later").unwrap();
+
+        let status = git_status(&paths).unwrap();
+        assert!(status.contains(" M ch1.md"), "{}", status);
+        assert!(status.contains("?? ch2.md"), "{}", status);
+    }
+
+    #[test]
+    fn test_diff_is_bounded() {
+        let (dir, _repo, paths) = setup_repo();
+        let line = "The quick brown fox jumps over the lazy dog.\n";
+        fs::write(dir.path().join("long.md"), line.repeat(2000)).unwrap();
+        git_commit(&paths, "Add long chapter", &["long.md".to_string()]).unwrap();
+
+        assert_eq!(
+            git_diff(&paths, None, false).unwrap(),
+            "No unstaged changes"
+        );
+
+        // Change every other line so the diff runs well past the limit
+        let edited: String = (0..2000)
+            .map(|i| {
+                if i % 2 == 0 {
+                    line.to_uppercase()
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect();
+        fs::write(dir.path().join("long.md"), edited).unwrap();
+        let diff = git_diff(&paths, Some("long.md"), false).unwrap();
+        assert!(diff.len() <= MAX_DIFF_BYTES + 100);
+        assert!(diff.contains("[Diff truncated"));
+        assert!(diff.contains("+THE QUICK BROWN FOX"));
+        assert!(diff.contains("\n The quick brown fox"));
+    }
+
+    #[test]
+    fn test_commit_includes_only_named_paths() {
+        let (dir, repo, paths) = setup_repo();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+        fs::write(dir.path().join("b.md"), "B").unwrap();
+
+        // Something the user staged themselves must not ride along
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("b.md")).unwrap();
+        index.write().unwrap();
+
+        let result = git_commit(&paths, "Checkpoint a\n\nDetails", &["a.md".to_string()]).unwrap();
+        assert!(result.contains("(1 path): Checkpoint a"), "{}", result);
+        assert_eq!(head_files(&repo), vec!["a.md"]);
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("Checkpoint a\n\nDetails"));
+        assert_eq!(head.author().name(), Some("Test Writer"));
+
+        let status = git_status(&paths).unwrap();
+        assert!(status.contains("A  b.md"), "{}", status);
+        assert!(!status.contains("a.md"), "{}", status);
+
+        // Deleting a committed file commits the deletion
+        fs::remove_file(dir.path().join("a.md")).unwrap();
+        git_commit(&paths, "Remove a", &["a.md".to_string()]).unwrap();
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(tree.get_name("a.md").is_none());
+        assert_eq!(
+            head.id(),
+            repo.head()
+                .unwrap()
+                .peel_to_commit()
+                .unwrap()
+                .parent_id(0)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_commit_rejects_bad_input() {
+        let (dir, _repo, paths) = setup_repo();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+
+        assert!(git_commit(&paths, "  ", &["a.md".to_string()]).is_err());
+        assert!(git_commit(&paths, "msg", &[]).is_err());
+        assert!(git_commit(&paths, "msg", &["missing.md".to_string()])
+            .unwrap_err()
+            .contains("Path not found"));
+        assert!(git_commit(&paths, "msg", &["../outside.md".to_string()]).is_err());
+
+        git_commit(&paths, "Add a", &["a.md".to_string()]).unwrap();
+        let err = git_commit(&paths, "Again", &["a.md".to_string()]).unwrap_err();
+        assert!(err.contains("Nothing to commit"));
+    }
+}
@@ -0,0 +1,242 @@
+//! App-wide agent settings that apply across runs.
+//!
+//! Per-run settings (provider, model, approval mode) travel with each
+//! `run_native_agent` call; these are the knobs that govern the backend itself.
+//! They are saved in the app config dir so changes survive a restart.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::atomic_write::write_atomic;
+
+/// File in the app config dir that holds the saved settings
+pub const GLOBAL_CONFIG_FILE: &str = "agent_config.json";
+
+/// Settings for the reaper that cancels runs nobody is attending to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleReaperConfig {
+    /// Whether idle runs are cancelled at all
+    pub enabled: bool,
+    /// Seconds without activity after which a run is cancelled
+    pub idle_timeout_secs: u64,
+    /// Seconds between reaper sweeps
+    pub check_interval_secs: u64,
+}
+
+impl Default for IdleReaperConfig {
+    fn default() -> Self {
+        IdleReaperConfig {
+            enabled: true,
+            idle_timeout_secs: 180,
+            check_interval_secs: 15,
+        }
+    }
+}
+
+impl IdleReaperConfig {
+    /// Idle time after which a run is cancelled
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    /// Time between reaper sweeps
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs)
+    }
+
+    /// Validate the reaper settings
+    pub fn validate(&self) -> Result<(), String> {
+        if self.idle_timeout_secs < 30 {
+            return Err(format!(
+                "idle_timeout_secs must be at least 30 (got {})",
+                self.idle_timeout_secs
+            ));
+        }
+        if self.check_interval_secs == 0 {
+            return Err("check_interval_secs must be at least 1".to_string());
+        }
+        if self.check_interval_secs > self.idle_timeout_secs {
+            return Err(format!(
+                "check_interval_secs ({}) cannot exceed idle_timeout_secs ({})",
+                self.check_interval_secs, self.idle_timeout_secs
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Settings for the health probe run shortly after launch and the report cache
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthProbeConfig {
+    /// Whether to probe at startup at all
+    pub enabled: bool,
+    /// Seconds to wait after launch before probing
+    pub startup_delay_secs: u64,
+    /// Seconds a cached health report is served before a check runs again
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for HealthProbeConfig {
+    fn default() -> Self {
+        HealthProbeConfig {
+            enabled: true,
+            startup_delay_secs: 3,
+            cache_ttl_secs: 300,
+        }
+    }
+}
+
+impl HealthProbeConfig {
+    /// Delay between launch and the startup probe
+    pub fn startup_delay(&self) -> Duration {
+        Duration::from_secs(self.startup_delay_secs)
+    }
+
+    /// How long a cached report stays fresh
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl_secs)
+    }
+
+    /// Validate the probe settings
+    pub fn validate(&self) -> Result<(), String> {
+        if self.startup_delay_secs > 300 {
+            return Err(format!(
+                "startup_delay_secs must be at most 300 (got {})",
+                self.startup_delay_secs
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// App-wide agent settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GlobalConfig {
+    /// Cancellation of runs left idle (e.g. waiting on an approval nobody will give)
+    pub idle_reaper: IdleReaperConfig,
+    /// Health probe at launch and caching of health reports
+    pub health_probe: HealthProbeConfig,
+}
+
+impl GlobalConfig {
+    /// Validate all settings
+    pub fn validate(&self) -> Result<(), String> {
+        self.idle_reaper
+            .validate()
+            .map_err(|e| format!("idle_reaper: {}", e))?;
+        self.health_probe
+            .validate()
+            .map_err(|e| format!("health_probe: {}", e))
+    }
+
+    /// The settings saved at `path`. A missing file gives the defaults, as does
+    /// one that can't be read or fails validation, with a warning.
+    pub fn load(path: &Path) -> Self {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::warn!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        let config = match serde_json::from_str::<GlobalConfig>(&content) {
+            Ok(config) => config,
+            Err(e) => {
+                log::warn!("Failed to parse {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        if let Err(e) = config.validate() {
+            log::warn!("Ignoring invalid settings in {}: {}", path.display(), e);
+            return Self::default();
+        }
+        config
+    }
+
+    /// Save the settings to `path`, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize global config: {}", e))?;
+        write_atomic(path, json.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// Shared global config type for Tauri state
+pub type SharedGlobalConfig = Arc<RwLock<GlobalConfig>>;
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        assert!(GlobalConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_partial_json_uses_defaults() {
+        let config: GlobalConfig =
+            serde_json::from_value(serde_json::json!({"idle_reaper": {"idle_timeout_secs": 60}}))
+                .unwrap();
+        assert_eq!(config.idle_reaper.idle_timeout_secs, 60);
+        assert_eq!(
+            config.idle_reaper.check_interval_secs,
+            IdleReaperConfig::default().check_interval_secs
+        );
+        assert!(config.idle_reaper.enabled);
+    }
+
+    #[test]
+    fn test_invalid_reaper_settings() {
+        let mut config = GlobalConfig::default();
+        config.idle_reaper.idle_timeout_secs = 5;
+        assert!(config.validate().unwrap_err().contains("idle_timeout_secs"));
+
+        let mut config = GlobalConfig::default();
+        config.idle_reaper.check_interval_secs = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = GlobalConfig::default();
+        config.idle_reaper.check_interval_secs = config.idle_reaper.idle_timeout_secs + 1;
+        assert!(config.validate().is_err());
+
+        let mut config = GlobalConfig::default();
+        config.health_probe.startup_delay_secs = 301;
+        assert!(config.validate().unwrap_err().starts_with("health_probe:"));
+    }
+
+    #[test]
+    fn test_saved_config_is_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config").join(GLOBAL_CONFIG_FILE);
+        assert_eq!(GlobalConfig::load(&path), GlobalConfig::default());
+
+        let mut config = GlobalConfig::default();
+        config.idle_reaper.idle_timeout_secs = 600;
+        config.health_probe.enabled = false;
+        config.save(&path).unwrap();
+        assert_eq!(GlobalConfig::load(&path), config);
+
+        // Settings that no longer validate fall back to the defaults
+        fs::write(&path, r#"{"idle_reaper": {"idle_timeout_secs": 5}}"#).unwrap();
+        assert_eq!(GlobalConfig::load(&path), GlobalConfig::default());
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(GlobalConfig::load(&path), GlobalConfig::default());
+    }
+}
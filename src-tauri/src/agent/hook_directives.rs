@@ -0,0 +1,388 @@
+//! Follow-up actions requested by lifecycle hooks.
+//!
+//! A hook may return a table with a `directives` array:
+//!
+//! ```lua
+//! function on_section_save(args)
+//!   return { directives = {
+//!     { type = "notify", level = "warning", message = "This chapter has no POV character" },
+//!     { type = "add_tag", section_id = args.section.id, entity_id = "...", from = 0, to = 5 },
+//!     { type = "run_tool", name = "read_file", args = { path = "notes.md" } },
+//!   } }
+//! end
+//! ```
+//!
+//! Each entry is checked and carried out once the hook returns, and reported in
+//! `HookResult::directives`. `notify` is logged and passed on for the app to show;
+//! `add_tag` is written through [`EntityStore`] with the checks `apply_suggested_tags`
+//! makes; `run_tool` runs a built-in tool only if the approval system wouldn't ask
+//! for it. Hooks have nobody to ask, so they act under `approve_writes` and tools
+//! that would wait for approval are skipped. Entries that aren't understood are
+//! reported as invalid rather than dropped.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use tokio_util::sync::CancellationToken;
+
+use super::core::{execute_tool, ToolContext};
+use super::entity_api::EntityStore;
+use super::entity_tags::{apply_suggested_tags, TagRange};
+use super::staged_writes::StagedWrites;
+use super::text::truncate_to_char_boundary;
+use super::tools::{PathValidator, Scratchpad, ShellSettings, TOOL_RESULT_BYTES};
+use super::types::{ApprovalMode, ToolRisk};
+
+/// Most directives carried out from one hook result; the rest are skipped
+pub const MAX_DIRECTIVES: usize = 20;
+
+/// Longest `notify` message accepted
+const MAX_NOTIFY_CHARS: usize = 2000;
+
+/// Approval mode `run_tool` directives are judged under
+const HOOK_APPROVAL_MODE: ApprovalMode = ApprovalMode::ApproveWrites;
+
+/// Directive types this app understands
+const DIRECTIVE_TYPES: &[&str] = &["notify", "add_tag", "run_tool"];
+
+/// Severity of a `notify` directive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyLevel {
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+/// A follow-up action requested by a hook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HookDirective {
+    /// Show a message to the user
+    Notify {
+        #[serde(default)]
+        level: NotifyLevel,
+        message: String,
+    },
+    /// Tag a range of a section's content (UTF-16 offsets) with an entity
+    AddTag {
+        section_id: String,
+        entity_id: String,
+        from: i64,
+        to: i64,
+    },
+    /// Run a built-in tool
+    RunTool {
+        name: String,
+        #[serde(default)]
+        args: Value,
+    },
+}
+
+/// What became of one directive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectiveStatus {
+    /// Carried out (for `notify`, passed on to be shown)
+    Executed,
+    /// Understood but not carried out
+    Skipped,
+    /// Carried out, and it failed
+    Failed,
+    /// Malformed, or of a type this app doesn't know
+    Invalid,
+}
+
+/// Outcome of one entry of a hook's `directives`, in the order given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectiveOutcome {
+    /// The entry's `type`, as given
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub status: DirectiveStatus,
+    /// The parsed directive, if the entry was valid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directive: Option<HookDirective>,
+    /// Why it was skipped, failed, or was invalid; or what it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl DirectiveOutcome {
+    fn new(kind: &str, status: DirectiveStatus, detail: impl Into<String>) -> Self {
+        DirectiveOutcome {
+            kind: kind.to_string(),
+            status,
+            directive: None,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+/// Carry out the directives in a hook's result. A result that isn't a table with
+/// a `directives` key has none.
+pub fn run_directives(
+    extension_id: &str,
+    result: &str,
+    workspace: &Path,
+    cancel: Option<&CancellationToken>,
+) -> Vec<DirectiveOutcome> {
+    let entries = match serde_json::from_str::<Value>(result) {
+        Ok(Value::Object(mut map)) => match map.remove("directives") {
+            None => return Vec::new(),
+            Some(Value::Array(entries)) => entries,
+            // An empty Lua table converts to an empty object
+            Some(Value::Object(map)) if map.is_empty() => return Vec::new(),
+            Some(_) => {
+                return vec![DirectiveOutcome::new(
+                    "",
+                    DirectiveStatus::Invalid,
+                    "`directives` must be an array",
+                )]
+            }
+        },
+        _ => return Vec::new(),
+    };
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let kind = entry
+                .get("type")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string();
+            if index >= MAX_DIRECTIVES {
+                return DirectiveOutcome::new(
+                    &kind,
+                    DirectiveStatus::Skipped,
+                    format!(
+                        "Only the first {} directives are carried out",
+                        MAX_DIRECTIVES
+                    ),
+                );
+            }
+            let directive = match parse_directive(&kind, entry) {
+                Ok(directive) => directive,
+                Err(reason) => {
+                    log::warn!(
+                        "Invalid directive from extension '{}': {}",
+                        extension_id,
+                        reason
+                    );
+                    return DirectiveOutcome::new(&kind, DirectiveStatus::Invalid, reason);
+                }
+            };
+            let (status, detail) = execute(extension_id, &directive, workspace, cancel);
+            DirectiveOutcome {
+                kind,
+                status,
+                directive: Some(directive),
+                detail,
+            }
+        })
+        .collect()
+}
+
+/// Parse and check one entry
+fn parse_directive(kind: &str, entry: Value) -> Result<HookDirective, String> {
+    if kind.is_empty() {
+        return Err("Directive has no `type`".to_string());
+    }
+    if !DIRECTIVE_TYPES.contains(&kind) {
+        return Err(format!(
+            "Unknown directive type '{}' (expected one of: {})",
+            kind,
+            DIRECTIVE_TYPES.join(", ")
+        ));
+    }
+    let directive: HookDirective =
+        serde_json::from_value(entry).map_err(|e| format!("Invalid {} directive: {}", kind, e))?;
+    if let HookDirective::Notify { message, .. } = &directive {
+        if message.trim().is_empty() {
+            return Err("Invalid notify directive: message cannot be empty".to_string());
+        }
+        if message.chars().count() > MAX_NOTIFY_CHARS {
+            return Err(format!(
+                "Invalid notify directive: message is longer than {} characters",
+                MAX_NOTIFY_CHARS
+            ));
+        }
+    }
+    Ok(directive)
+}
+
+/// Carry out a parsed directive
+fn execute(
+    extension_id: &str,
+    directive: &HookDirective,
+    workspace: &Path,
+    cancel: Option<&CancellationToken>,
+) -> (DirectiveStatus, Option<String>) {
+    match directive {
+        HookDirective::Notify { level, message } => {
+            match level {
+                NotifyLevel::Info => log::info!("Extension '{}': {}", extension_id, message),
+                NotifyLevel::Warning => log::warn!("Extension '{}': {}", extension_id, message),
+                NotifyLevel::Error => log::error!("Extension '{}': {}", extension_id, message),
+            }
+            (DirectiveStatus::Executed, None)
+        }
+        HookDirective::AddTag {
+            section_id,
+            entity_id,
+            from,
+            to,
+        } => {
+            let range = TagRange {
+                entity_id: entity_id.clone(),
+                from: *from,
+                to: *to,
+            };
+            match apply_suggested_tags(&EntityStore::new(workspace), section_id, &[range]) {
+                Ok(_) => (
+                    DirectiveStatus::Executed,
+                    Some(format!(
+                        "Tagged {}..{} of section {} with entity {}",
+                        from, to, section_id, entity_id
+                    )),
+                ),
+                Err(e) => (DirectiveStatus::Failed, Some(e)),
+            }
+        }
+        HookDirective::RunTool { name, args } => {
+            if name.contains(':') {
+                return (
+                    DirectiveStatus::Skipped,
+                    Some("Extension tools can't be run from hooks".to_string()),
+                );
+            }
+            let risk = ToolRisk::for_tool(name);
+            if HOOK_APPROVAL_MODE.needs_approval(risk) {
+                return (
+                    DirectiveStatus::Skipped,
+                    Some(format!(
+                        "{} needs approval ({:?} risk); hooks can only run read-only tools",
+                        name, risk
+                    )),
+                );
+            }
+            let paths = PathValidator::new(workspace);
+            let context = ToolContext {
+                paths: &paths,
+                staged_writes: &StagedWrites::new(),
+                scratchpad: &Scratchpad::new(),
+                shell: &ShellSettings::default(),
+                backups: None,
+                cancel,
+            };
+            match execute_tool(&context, name, args).map(String::from) {
+                Ok(output) => (
+                    DirectiveStatus::Executed,
+                    Some(truncate_to_char_boundary(&output, TOOL_RESULT_BYTES).to_string()),
+                ),
+                Err(e) => (DirectiveStatus::Failed, Some(e)),
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn outcomes(result: Value, workspace: &Path) -> Vec<DirectiveOutcome> {
+        run_directives("test-ext", &result.to_string(), workspace, None)
+    }
+
+    #[test]
+    fn test_results_without_directives() {
+        let dir = TempDir::new().unwrap();
+        for result in ["", "done", "42", r#"{"count": 3}"#, r#"{"directives": {}}"#] {
+            assert!(run_directives("test-ext", result, dir.path(), None).is_empty());
+        }
+
+        let invalid = outcomes(serde_json::json!({"directives": "notify"}), dir.path());
+        assert_eq!(invalid.len(), 1);
+        assert_eq!(invalid[0].status, DirectiveStatus::Invalid);
+    }
+
+    #[test]
+    fn test_directive_schema() {
+        let dir = TempDir::new().unwrap();
+        let results = outcomes(
+            serde_json::json!({"directives": [
+                {"type": "notify", "message": "hello"},
+                {"type": "notify", "level": "loud", "message": "hello"},
+                {"type": "notify", "message": " "},
+                {"type": "add_tag", "section_id": "s1"},
+                {"type": "rewrite_chapter"},
+                {"message": "no type"},
+            ]}),
+            dir.path(),
+        );
+        let statuses: Vec<DirectiveStatus> = results.iter().map(|o| o.status).collect();
+        use DirectiveStatus::*;
+        assert_eq!(
+            statuses,
+            [Executed, Invalid, Invalid, Invalid, Invalid, Invalid]
+        );
+        assert_eq!(
+            results[0].directive,
+            Some(HookDirective::Notify {
+                level: NotifyLevel::Info,
+                message: "hello".to_string()
+            })
+        );
+        assert_eq!(results[4].kind, "rewrite_chapter");
+        assert!(results[4]
+            .detail
+            .as_deref()
+            .unwrap()
+            .contains("Unknown directive type 'rewrite_chapter'"));
+    }
+
+    #[test]
+    fn test_directive_limit() {
+        let dir = TempDir::new().unwrap();
+        let notes: Vec<Value> = (0..MAX_DIRECTIVES + 2)
+            .map(|i| serde_json::json!({"type": "notify", "message": format!("note {}", i)}))
+            .collect();
+        let results = outcomes(serde_json::json!({ "directives": notes }), dir.path());
+        assert_eq!(results.len(), MAX_DIRECTIVES + 2);
+        assert!(results[..MAX_DIRECTIVES]
+            .iter()
+            .all(|o| o.status == DirectiveStatus::Executed));
+        assert!(results[MAX_DIRECTIVES..]
+            .iter()
+            .all(|o| o.status == DirectiveStatus::Skipped));
+    }
+
+    #[test]
+    fn test_outcome_serialization() {
+        let outcome = DirectiveOutcome {
+            kind: "notify".to_string(),
+            status: DirectiveStatus::Executed,
+            directive: Some(HookDirective::Notify {
+                level: NotifyLevel::Warning,
+                message: "careful".to_string(),
+            }),
+            detail: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&outcome).unwrap(),
+            serde_json::json!({
+                "type": "notify",
+                "status": "executed",
+                "directive": {"type": "notify", "level": "warning", "message": "careful"}
+            })
+        );
+    }
+}
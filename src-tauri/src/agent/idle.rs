@@ -0,0 +1,487 @@
+//! Idle-run reaper.
+//!
+//! A run waiting on a tool approval holds one of the concurrency slots until the
+//! approval times out, so a user who closed the panel can lock up the agent. Every
+//! running task records when it last showed signs of life (a model response, tool
+//! output or completion, an approval request); a periodic sweep cancels runs that have been
+//! idle longer than the configured threshold and records the reason on the
+//! session. A run waiting on a model or tool call is busy, not idle, however long
+//! the call takes. The run's own `Cancelled` event is tagged with `reason: idle` on its
+//! way to the frontend so the UI can tell it apart from a user cancel.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::global_config::SharedGlobalConfig;
+use super::session::{SessionStore, SharedSessionStore};
+use super::types::{AgentEvent, CancelReason};
+
+/// Source of the current time, injectable for tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// What a run was last seen doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    /// The run was registered
+    Started,
+    /// The model produced text or tool calls
+    ModelResponse,
+    /// A running tool produced output
+    ToolOutput,
+    /// A tool finished (or was skipped)
+    ToolCompleted,
+    /// The run asked for a tool approval
+    ApprovalRequested,
+    /// The run is waiting on the model
+    ModelCall,
+    /// The run is executing a tool
+    ToolCall,
+}
+
+impl ActivityKind {
+    /// The activity an agent event signals, if any
+    pub fn from_event(event: &AgentEvent) -> Option<Self> {
+        match event {
+            AgentEvent::Start { .. } => Some(ActivityKind::Started),
+            AgentEvent::TextChunk { .. }
+            | AgentEvent::Reasoning { .. }
+            | AgentEvent::ToolCallStart { .. }
+            | AgentEvent::Warning { .. }
+            | AgentEvent::ContextTruncated { .. }
+            | AgentEvent::RetryingLlmCall { .. }
+            | AgentEvent::Waiting { .. } => Some(ActivityKind::ModelResponse),
+            AgentEvent::ToolOutputChunk { .. } => Some(ActivityKind::ToolOutput),
+            AgentEvent::ToolCallComplete { .. }
+            | AgentEvent::ToolSkipped { .. }
+            | AgentEvent::ToolBlocked { .. }
+            | AgentEvent::ExtensionToolKilled { .. }
+            | AgentEvent::InjectionSuspected { .. } => Some(ActivityKind::ToolCompleted),
+            AgentEvent::ToolApprovalRequired { .. } => Some(ActivityKind::ApprovalRequested),
+            AgentEvent::PlanGenerated { .. }
+            | AgentEvent::Queued { .. }
+            | AgentEvent::DroppedEvents { .. }
+            | AgentEvent::Complete { .. }
+            | AgentEvent::Error { .. }
+            | AgentEvent::Cancelled { .. } => None,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            ActivityKind::Started => "run start",
+            ActivityKind::ModelResponse => "model response",
+            ActivityKind::ToolOutput => "tool output",
+            ActivityKind::ToolCompleted => "tool completion",
+            ActivityKind::ApprovalRequested => "approval request",
+            ActivityKind::ModelCall => "model call",
+            ActivityKind::ToolCall => "tool call",
+        }
+    }
+}
+
+/// A run cancelled by the reaper
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdleCancellation {
+    pub run_id: String,
+    pub session_id: String,
+    /// How long the run had been idle when it was cancelled
+    pub idle_for: Duration,
+    /// The last thing the run was seen doing
+    pub last_activity: ActivityKind,
+}
+
+impl IdleCancellation {
+    /// Human-readable reason recorded on the session
+    pub fn reason(&self) -> String {
+        format!(
+            "Cancelled after {}s without activity (last activity: {})",
+            self.idle_for.as_secs(),
+            self.last_activity.describe()
+        )
+    }
+}
+
+/// A run the tracker knows about
+struct TrackedRun {
+    session_id: String,
+    token: CancellationToken,
+    last_activity: Instant,
+    last_kind: ActivityKind,
+    /// Model or tool calls in flight; a busy run is never idle
+    in_flight: usize,
+    cancelled: Option<IdleCancellation>,
+}
+
+/// Last-activity timestamps for every running task
+pub struct RunActivity {
+    clock: Arc<dyn Clock>,
+    runs: Mutex<HashMap<String, TrackedRun>>,
+}
+
+impl Default for RunActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunActivity {
+    /// Create a tracker using the system clock
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Create a tracker using a custom clock
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        RunActivity {
+            clock,
+            runs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start tracking a run
+    pub fn register(&self, run_id: &str, session_id: &str, token: CancellationToken) {
+        let run = TrackedRun {
+            session_id: session_id.to_string(),
+            token,
+            last_activity: self.clock.now(),
+            last_kind: ActivityKind::Started,
+            in_flight: 0,
+            cancelled: None,
+        };
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.insert(run_id.to_string(), run);
+        }
+    }
+
+    /// Note that a run is doing something
+    pub fn record(&self, run_id: &str, kind: ActivityKind) {
+        self.update(run_id, kind, |_| {});
+    }
+
+    /// Mark a run busy with a model or tool call until the returned guard drops.
+    /// The idle time counts from when the call ends.
+    pub fn busy(self: &Arc<Self>, run_id: &str, kind: ActivityKind) -> BusyGuard {
+        self.update(run_id, kind, |run| run.in_flight += 1);
+        BusyGuard {
+            activity: self.clone(),
+            run_id: run_id.to_string(),
+            kind,
+        }
+    }
+
+    fn update(&self, run_id: &str, kind: ActivityKind, change: impl FnOnce(&mut TrackedRun)) {
+        let now = self.clock.now();
+        if let Ok(mut runs) = self.runs.lock() {
+            if let Some(run) = runs.get_mut(run_id) {
+                run.last_activity = now;
+                run.last_kind = kind;
+                change(run);
+            }
+        }
+    }
+
+    /// Record the activity an event from the run's agent loop signals, and tag
+    /// the run's `Cancelled` event with the idle reason if the reaper cancelled it
+    pub fn observe_event(&self, run_id: &str, event: &mut AgentEvent) {
+        if let AgentEvent::Cancelled { reason, .. } = event {
+            if self.was_reaped(run_id) {
+                *reason = Some(CancelReason::Idle);
+            }
+        }
+        if let Some(kind) = ActivityKind::from_event(event) {
+            self.record(run_id, kind);
+        }
+    }
+
+    /// Whether the reaper has cancelled a run
+    fn was_reaped(&self, run_id: &str) -> bool {
+        self.runs
+            .lock()
+            .map(|runs| runs.get(run_id).is_some_and(|run| run.cancelled.is_some()))
+            .unwrap_or(false)
+    }
+
+    /// Stop tracking a run. Returns the reaper's cancellation if it was reaped.
+    pub fn unregister(&self, run_id: &str) -> Option<IdleCancellation> {
+        self.runs
+            .lock()
+            .ok()?
+            .remove(run_id)
+            .and_then(|run| run.cancelled)
+    }
+
+    /// Cancel every run idle for longer than `threshold`.
+    ///
+    /// Each reaped run's token is cancelled and its session is marked cancelled
+    /// with the idle reason.
+    pub fn reap_idle(&self, threshold: Duration, sessions: &SessionStore) -> Vec<IdleCancellation> {
+        let now = self.clock.now();
+        let mut reaped = Vec::new();
+
+        let Ok(mut runs) = self.runs.lock() else {
+            return reaped;
+        };
+
+        for (run_id, run) in runs.iter_mut() {
+            if run.cancelled.is_some() || run.in_flight > 0 {
+                continue;
+            }
+            let idle_for = now.saturating_duration_since(run.last_activity);
+            if idle_for <= threshold {
+                continue;
+            }
+
+            let cancellation = IdleCancellation {
+                run_id: run_id.clone(),
+                session_id: run.session_id.clone(),
+                idle_for,
+                last_activity: run.last_kind,
+            };
+            let reason = cancellation.reason();
+
+            run.token.cancel();
+            sessions.update_session(&run.session_id, |s| {
+                s.cancel();
+                s.error = Some(reason);
+            });
+
+            run.cancelled = Some(cancellation.clone());
+            reaped.push(cancellation);
+        }
+
+        reaped
+    }
+}
+
+/// Shared activity tracker type for Tauri state
+pub type SharedRunActivity = Arc<RunActivity>;
+
+/// Keeps a run busy while a model or tool call is in flight; see [`RunActivity::busy`]
+pub struct BusyGuard {
+    activity: SharedRunActivity,
+    run_id: String,
+    kind: ActivityKind,
+}
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.activity.update(&self.run_id, self.kind, |run| {
+            run.in_flight = run.in_flight.saturating_sub(1)
+        });
+    }
+}
+
+/// Sweep for idle runs forever, re-reading the interval and threshold from the
+/// global config before each sweep so changes apply without a restart.
+pub async fn run_idle_reaper(
+    activity: SharedRunActivity,
+    config: SharedGlobalConfig,
+    sessions: SharedSessionStore,
+) {
+    loop {
+        let reaper = config
+            .read()
+            .map(|c| c.idle_reaper.clone())
+            .unwrap_or_default();
+
+        tokio::time::sleep(reaper.check_interval()).await;
+
+        if reaper.enabled {
+            for reaped in activity.reap_idle(reaper.idle_timeout(), &sessions) {
+                log::warn!(
+                    "Agent run {} (session {}): {}",
+                    reaped.run_id,
+                    reaped.session_id,
+                    reaped.reason()
+                );
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::session::SessionStatus;
+    use crate::agent::types::{ApprovalMode, LlmProvider, ToolRisk};
+    use std::path::PathBuf;
+
+    /// A clock that only moves when told to
+    struct ManualClock {
+        now: Mutex<Instant>,
+    }
+
+    impl ManualClock {
+        fn new() -> Arc<Self> {
+            Arc::new(ManualClock {
+                now: Mutex::new(Instant::now()),
+            })
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.now.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    const THRESHOLD: Duration = Duration::from_secs(180);
+
+    fn setup() -> (
+        Arc<ManualClock>,
+        RunActivity,
+        SessionStore,
+        String,
+        CancellationToken,
+    ) {
+        let clock = ManualClock::new();
+        let activity = RunActivity::with_clock(clock.clone());
+        let sessions = SessionStore::new();
+        let session_id = sessions.create_session(
+            PathBuf::from("/tmp"),
+            LlmProvider::OpenAI,
+            "gpt-5-mini".to_string(),
+            ApprovalMode::ApproveAll,
+            "task".to_string(),
+        );
+        let token = CancellationToken::new();
+        activity.register("run-1", &session_id, token.clone());
+        (clock, activity, sessions, session_id, token)
+    }
+
+    fn approval_request() -> AgentEvent {
+        AgentEvent::ToolApprovalRequired {
+            approval_id: "approval-1".to_string(),
+            name: "write_file".to_string(),
+            args: serde_json::json!({}),
+            risk: ToolRisk::Medium,
+            preview: None,
+            run_id: None,
+        }
+    }
+
+    fn cancelled() -> AgentEvent {
+        AgentEvent::Cancelled {
+            summary: None,
+            reason: None,
+            run_id: None,
+        }
+    }
+
+    #[test]
+    fn test_idle_run_is_cancelled_with_reason() {
+        let (clock, activity, sessions, session_id, token) = setup();
+        activity.observe_event("run-1", &mut approval_request());
+
+        clock.advance(THRESHOLD + Duration::from_secs(1));
+        let reaped = activity.reap_idle(THRESHOLD, &sessions);
+
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].last_activity, ActivityKind::ApprovalRequested);
+        assert!(token.is_cancelled());
+
+        let session = sessions.get_session(&session_id).unwrap();
+        assert_eq!(session.status, SessionStatus::Cancelled);
+        let error = session.error.unwrap();
+        assert!(error.contains("181s without activity"));
+        assert!(error.contains("approval request"));
+
+        // The run's own Cancelled event is tagged as an idle cancellation
+        let mut event = cancelled();
+        activity.observe_event("run-1", &mut event);
+        assert!(matches!(
+            event,
+            AgentEvent::Cancelled {
+                reason: Some(CancelReason::Idle),
+                ..
+            }
+        ));
+
+        assert_eq!(activity.unregister("run-1"), Some(reaped[0].clone()));
+    }
+
+    #[test]
+    fn test_busy_run_is_not_reaped() {
+        let (clock, activity, sessions, _, token) = setup();
+        let activity = Arc::new(activity);
+
+        let call = activity.busy("run-1", ActivityKind::ModelCall);
+        clock.advance(THRESHOLD * 3);
+        assert!(activity.reap_idle(THRESHOLD, &sessions).is_empty());
+        drop(call);
+
+        // Idle time counts from the end of the call
+        clock.advance(THRESHOLD);
+        assert!(activity.reap_idle(THRESHOLD, &sessions).is_empty());
+        clock.advance(Duration::from_secs(1));
+        let reaped = activity.reap_idle(THRESHOLD, &sessions);
+        assert_eq!(reaped[0].last_activity, ActivityKind::ModelCall);
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_activity_resets_idle_time() {
+        let (clock, activity, sessions, _, token) = setup();
+
+        clock.advance(Duration::from_secs(120));
+        activity.record("run-1", ActivityKind::ToolCompleted);
+        clock.advance(Duration::from_secs(120));
+
+        assert!(activity.reap_idle(THRESHOLD, &sessions).is_empty());
+        assert!(!token.is_cancelled());
+
+        // A user cancel is not tagged as idle
+        let mut event = cancelled();
+        activity.observe_event("run-1", &mut event);
+        assert!(matches!(event, AgentEvent::Cancelled { reason: None, .. }));
+        assert_eq!(activity.unregister("run-1"), None);
+    }
+
+    #[test]
+    fn test_run_is_reaped_once() {
+        let (clock, activity, sessions, _, _) = setup();
+
+        clock.advance(THRESHOLD * 2);
+        assert_eq!(activity.reap_idle(THRESHOLD, &sessions).len(), 1);
+        clock.advance(THRESHOLD * 2);
+        assert!(activity.reap_idle(THRESHOLD, &sessions).is_empty());
+    }
+
+    #[test]
+    fn test_terminal_events_are_not_activity() {
+        let event = AgentEvent::Complete {
+            response: "done".to_string(),
+            usage: None,
+            estimated_cost: None,
+            summary: None,
+            served_model: None,
+            run_id: None,
+        };
+        assert_eq!(ActivityKind::from_event(&event), None);
+        assert_eq!(
+            ActivityKind::from_event(&approval_request()),
+            Some(ActivityKind::ApprovalRequested)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! ID generation and validation for entities, sections, and tags.
+//!
+//! Everything that mints an ID (EntityStore, the Lua `util` table) goes through
+//! here so generated IDs share one implementation. The frontend schemas expect
+//! hyphenated UUIDs; ULIDs are accepted for extension-created content that wants
+//! sortable IDs.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Crockford base32 alphabet used by ULIDs
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Length of a ULID string
+const ULID_LEN: usize = 26;
+
+/// Length of a hyphenated UUID string
+const UUID_LEN: usize = 36;
+
+/// Generate a random (v4) UUID in hyphenated form
+pub fn new_uuid() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Generate a ULID from the current time and 80 random bits
+pub fn new_ulid() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut random = [0u8; 10];
+    random.copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..10]);
+    encode_ulid(millis, random)
+}
+
+/// Encode a 48-bit millisecond timestamp and 80 random bits as a ULID
+fn encode_ulid(millis: u64, random: [u8; 10]) -> String {
+    let mut value = u128::from(millis & 0xFFFF_FFFF_FFFF) << 80;
+    for (i, byte) in random.iter().enumerate() {
+        value |= u128::from(*byte) << (72 - 8 * i);
+    }
+
+    // 26 base32 digits cover 130 bits; the top two are always zero
+    let mut out = [0u8; ULID_LEN];
+    for (i, slot) in out.iter_mut().enumerate() {
+        let shift = 5 * (ULID_LEN - 1 - i);
+        *slot = CROCKFORD[((value >> shift) & 0x1F) as usize];
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Whether `id` is a hyphenated UUID (any version)
+pub fn is_uuid(id: &str) -> bool {
+    id.len() == UUID_LEN && uuid::Uuid::parse_str(id).is_ok()
+}
+
+/// Whether `id` is a ULID (case-insensitive Crockford base32, 26 characters)
+pub fn is_ulid(id: &str) -> bool {
+    let bytes = id.as_bytes();
+    bytes.len() == ULID_LEN
+        // The first digit carries only 3 bits, so anything above '7' overflows 128 bits
+        && matches!(bytes[0], b'0'..=b'7')
+        && bytes
+            .iter()
+            .all(|b| CROCKFORD.contains(&b.to_ascii_uppercase()))
+}
+
+/// Check that an ID is UUID- or ULID-shaped.
+///
+/// `kind` names the object in the error (e.g. "Entity").
+pub fn validate_id(kind: &str, id: &str) -> Result<(), String> {
+    if id.trim().is_empty() {
+        return Err(format!("{} ID cannot be empty", kind));
+    }
+    if is_uuid(id) || is_ulid(id) {
+        return Ok(());
+    }
+    Err(format!(
+        "{} ID '{}' is not a UUID or ULID; generate one with util.uuid()",
+        kind, id
+    ))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_generated_ids_are_unique() {
+        let uuids: HashSet<String> = (0..10_000).map(|_| new_uuid()).collect();
+        let ulids: HashSet<String> = (0..10_000).map(|_| new_ulid()).collect();
+        assert_eq!(uuids.len(), 10_000);
+        assert_eq!(ulids.len(), 10_000);
+        assert!(uuids.iter().all(|id| is_uuid(id)));
+        assert!(ulids.iter().all(|id| is_ulid(id)));
+    }
+
+    #[test]
+    fn test_ulid_encoding() {
+        assert_eq!(encode_ulid(0, [0; 10]), "00000000000000000000000000");
+        assert_eq!(
+            encode_ulid(0xFFFF_FFFF_FFFF, [0xFF; 10]),
+            "7ZZZZZZZZZZZZZZZZZZZZZZZZZ"
+        );
+        // Later timestamps sort after earlier ones
+        assert!(encode_ulid(1, [0xFF; 10]) < encode_ulid(2, [0; 10]));
+    }
+
+    #[test]
+    fn test_validate_id() {
+        assert!(validate_id("Entity", "550e8400-e29b-41d4-a716-446655440000").is_ok());
+        assert!(validate_id("Entity", "01ARZ3NDEKTSV4RRFFQ69G5FAV").is_ok());
+        assert!(validate_id("Entity", "01arz3ndektsv4rrffq69g5fav").is_ok());
+
+        for bad in [
+            "entity-1",
+            "550e8400e29b41d4a716446655440000",
+            "81ARZ3NDEKTSV4RRFFQ69G5FAV",
+            "01ARZ3NDEKTSV4RRFFQ69G5FAU-",
+            "01ARZ3NDEKTSV4RRFFQ69G5FIL",
+        ] {
+            let err = validate_id("Entity", bad).unwrap_err();
+            assert!(err.contains("not a UUID or ULID"), "{}", bad);
+        }
+
+        assert!(validate_id("Section", "  ").is_err());
+    }
+}
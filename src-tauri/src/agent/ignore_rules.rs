@@ -0,0 +1,132 @@
+//! Workspace ignore rules for the file-finding tools.
+//!
+//! A `.vswriteignore` file at the workspace root lists paths (in gitignore syntax)
+//! that glob, grep, and list_dir leave out: exports, editor folders, extension build
+//! output. The rules are loaded once per tool call, and every tool reports how many
+//! paths they hid so the agent doesn't conclude a file doesn't exist.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Name of the ignore file at the workspace root
+pub const IGNORE_FILE: &str = ".vswriteignore";
+
+/// The workspace's ignore rules, counting the paths they skip
+pub struct IgnoreRules {
+    root: PathBuf,
+    matcher: Option<Gitignore>,
+    skipped: AtomicUsize,
+}
+
+impl IgnoreRules {
+    /// Load `.vswriteignore` from `workspace` (a canonical path). A missing file
+    /// means nothing is ignored; unparseable lines are logged and skipped.
+    pub fn load(workspace: &Path) -> Self {
+        let file = workspace.join(IGNORE_FILE);
+        let matcher = file.is_file().then(|| {
+            let mut builder = GitignoreBuilder::new(workspace);
+            if let Some(e) = builder.add(&file) {
+                log::warn!("Some {} rules were not loaded: {}", IGNORE_FILE, e);
+            }
+            builder.build().unwrap_or_else(|e| {
+                log::warn!("Failed to load {}: {}", IGNORE_FILE, e);
+                Gitignore::empty()
+            })
+        });
+
+        IgnoreRules {
+            root: workspace.to_path_buf(),
+            matcher,
+            skipped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether `path` (absolute, inside the workspace) is ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(ref matcher) = self.matcher else {
+            return false;
+        };
+        // The matcher wants paths below its root
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        matcher
+            .matched_path_or_any_parents(relative, is_dir)
+            .is_ignore()
+    }
+
+    /// Like `is_ignored`, but counts the path toward `skipped` when it is
+    pub fn skip(&self, path: &Path, is_dir: bool) -> bool {
+        let ignored = self.is_ignored(path, is_dir);
+        if ignored {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+        }
+        ignored
+    }
+
+    /// Paths skipped so far
+    pub fn skipped(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Note for tool output when anything was skipped
+    pub fn skipped_note(&self) -> Option<String> {
+        match self.skipped() {
+            0 => None,
+            n => Some(format!(
+                "{} path{} hidden by {} (list_dir with include_ignored=true shows them)",
+                n,
+                if n == 1 { "" } else { "s" },
+                IGNORE_FILE
+            )),
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitignore_syntax() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(
+            root.join(IGNORE_FILE),
+            "# junk\nexports/\n*.bak\n!keep.bak\n/drafts/old\n",
+        )
+        .unwrap();
+        let rules = IgnoreRules::load(&root);
+
+        assert!(rules.is_ignored(&root.join("exports"), true));
+        assert!(rules.is_ignored(&root.join("exports/book.pdf"), false));
+        assert!(rules.is_ignored(&root.join("chapters/one.bak"), false));
+        assert!(!rules.is_ignored(&root.join("keep.bak"), false));
+        assert!(rules.is_ignored(&root.join("drafts/old"), true));
+        assert!(!rules.is_ignored(&root.join("chapters/drafts/old"), true));
+        assert!(!rules.is_ignored(&root.join("chapters/one.md"), false));
+        assert!(!rules.is_ignored(&root, true));
+
+        assert_eq!(rules.skipped(), 0);
+        assert!(rules.skip(&root.join("a.bak"), false));
+        assert!(!rules.skip(&root.join("a.md"), false));
+        assert_eq!(rules.skipped(), 1);
+        assert!(rules.skipped_note().unwrap().starts_with("1 path hidden"));
+    }
+
+    #[test]
+    fn test_missing_file_ignores_nothing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let rules = IgnoreRules::load(dir.path());
+        assert!(!rules.is_ignored(&dir.path().join("exports"), true));
+        assert!(rules.skipped_note().is_none());
+    }
+}
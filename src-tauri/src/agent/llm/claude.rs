@@ -0,0 +1,967 @@
+//! Anthropic Claude messages API provider.
+//!
+//! With `AgentConfig::thinking_budget_tokens` set, requests enable extended
+//! thinking. Thinking blocks in the reply become `LlmResponse::reasoning` (and are
+//! streamed as reasoning, not text); their signed form rides on the assistant
+//! message so it can be sent back with the tool results that follow.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::rate_limits::{anthropic_rate_limits, RateLimitSnapshot};
+use super::sse::SseEvent;
+use super::{
+    parse_failed, rejected, request_failed, ChatProvider, LlmResponse, PreparedRequest,
+    StreamAccumulator, StreamDelta,
+};
+use crate::agent::types::{
+    AgentConfig, AgentError, FunctionCall, Message, MessageRole, ThinkingBlock, Tool, ToolCall,
+    Usage,
+};
+
+/// Anthropic API version sent with every request
+pub(crate) const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+// ============================================================================
+// Claude (Anthropic) Types
+// ============================================================================
+
+#[derive(Debug, Serialize)]
+struct ClaudeRequest {
+    model: String,
+    messages: Vec<ClaudeMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ClaudeTool>>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ClaudeThinking>,
+}
+
+/// Extended thinking settings; the budget counts toward `max_tokens`
+#[derive(Debug, Serialize)]
+struct ClaudeThinking {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    budget_tokens: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct ClaudeMessage {
+    role: String,
+    content: ClaudeContent,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ClaudeContent {
+    Text(String),
+    Blocks(Vec<ClaudeContentBlock>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum ClaudeContentBlock {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "tool_result")]
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String, signature: String },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+}
+
+impl From<ThinkingBlock> for ClaudeContentBlock {
+    fn from(block: ThinkingBlock) -> Self {
+        match block {
+            ThinkingBlock::Thinking {
+                thinking,
+                signature,
+            } => ClaudeContentBlock::Thinking {
+                thinking,
+                signature,
+            },
+            ThinkingBlock::RedactedThinking { data } => {
+                ClaudeContentBlock::RedactedThinking { data }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ClaudeTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeResponse {
+    #[allow(dead_code)]
+    id: String,
+    content: Vec<ClaudeResponseContent>,
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ClaudeResponseContent {
+    #[serde(rename = "text")]
+    Text { text: String },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "thinking")]
+    Thinking {
+        thinking: String,
+        #[serde(default)]
+        signature: String,
+    },
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
+    /// Block types the agent doesn't use
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeUsage {
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+/// One event from a streamed messages response
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeStreamEvent {
+    MessageStart {
+        message: ClaudeStreamMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ClaudeStreamBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: ClaudeStreamDelta,
+    },
+    MessageDelta {
+        delta: ClaudeMessageDelta,
+        #[serde(default)]
+        usage: Option<ClaudeOutputUsage>,
+    },
+    Error {
+        error: ClaudeErrorDetail,
+    },
+    /// ping, content_block_stop, message_stop
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeStreamMessage {
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeStreamBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    Thinking {
+        #[serde(default)]
+        thinking: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+    /// Block types the agent doesn't use
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeStreamDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    SignatureDelta {
+        signature: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeMessageDelta {
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeOutputUsage {
+    output_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeError {
+    error: ClaudeErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    error_type: Option<String>,
+}
+
+/// The text of the thinking blocks, if any have text
+fn reasoning_text(blocks: &[ThinkingBlock]) -> Option<String> {
+    let texts: Vec<&str> = blocks
+        .iter()
+        .filter_map(|block| match block {
+            ThinkingBlock::Thinking { thinking, .. } if !thinking.is_empty() => {
+                Some(thinking.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    (!texts.is_empty()).then(|| texts.join("\n\n"))
+}
+
+// ============================================================================
+// Claude Provider
+// ============================================================================
+
+/// Anthropic's messages API with native tool_use support
+#[derive(Debug, Clone, Copy)]
+pub struct ClaudeProvider;
+
+impl ChatProvider for ClaudeProvider {
+    fn name(&self) -> &'static str {
+        "Claude"
+    }
+
+    fn endpoint(&self, config: &AgentConfig) -> String {
+        format!("{}/messages", config.effective_base_url())
+    }
+
+    fn headers(&self, config: &AgentConfig) -> Vec<(&'static str, String)> {
+        vec![
+            ("x-api-key", config.api_key.clone()),
+            ("anthropic-version", ANTHROPIC_VERSION.to_string()),
+        ]
+    }
+
+    fn rate_limits(
+        &self,
+        headers: &reqwest::header::HeaderMap,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<RateLimitSnapshot> {
+        anthropic_rate_limits(headers, now)
+    }
+
+    fn build_request(
+        &self,
+        config: &AgentConfig,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+    ) -> Result<PreparedRequest, AgentError> {
+        // Extract system message and convert others
+        let mut system_prompt: Option<String> = None;
+        let mut claude_messages: Vec<ClaudeMessage> = Vec::new();
+
+        for msg in messages {
+            match msg.role {
+                MessageRole::System | MessageRole::Developer => {
+                    if let Some(content) = msg.content.clone() {
+                        system_prompt = Some(match system_prompt.take() {
+                            Some(existing) => format!("{}\n\n{}", existing, content),
+                            None => content,
+                        });
+                    }
+                }
+                MessageRole::User => {
+                    claude_messages.push(ClaudeMessage {
+                        role: "user".to_string(),
+                        content: ClaudeContent::Text(msg.content.clone().unwrap_or_default()),
+                    });
+                }
+                MessageRole::Assistant => {
+                    if let Some(tool_calls) = &msg.tool_calls {
+                        // Assistant message with tool calls; its thinking goes first
+                        let mut blocks: Vec<ClaudeContentBlock> =
+                            msg.thinking.iter().cloned().map(Into::into).collect();
+                        if let Some(text) = &msg.content {
+                            if !text.is_empty() {
+                                blocks.push(ClaudeContentBlock::Text { text: text.clone() });
+                            }
+                        }
+                        for tc in tool_calls {
+                            let input: serde_json::Value =
+                                serde_json::from_str(&tc.function.arguments)
+                                    .unwrap_or(serde_json::json!({}));
+                            blocks.push(ClaudeContentBlock::ToolUse {
+                                id: tc.id.clone(),
+                                name: tc.function.name.clone(),
+                                input,
+                            });
+                        }
+                        claude_messages.push(ClaudeMessage {
+                            role: "assistant".to_string(),
+                            content: ClaudeContent::Blocks(blocks),
+                        });
+                    } else {
+                        claude_messages.push(ClaudeMessage {
+                            role: "assistant".to_string(),
+                            content: ClaudeContent::Text(msg.content.clone().unwrap_or_default()),
+                        });
+                    }
+                }
+                MessageRole::Tool => {
+                    // Tool results go as user messages with tool_result block
+                    if let Some(tool_call_id) = &msg.tool_call_id {
+                        claude_messages.push(ClaudeMessage {
+                            role: "user".to_string(),
+                            content: ClaudeContent::Blocks(vec![ClaudeContentBlock::ToolResult {
+                                tool_use_id: tool_call_id.clone(),
+                                content: msg.content.clone().unwrap_or_default(),
+                            }]),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Convert tools to Claude format
+        let claude_tools: Option<Vec<ClaudeTool>> = tools.map(|ts| {
+            ts.iter()
+                .map(|t| ClaudeTool {
+                    name: t.function.name.clone(),
+                    description: t.function.description.clone(),
+                    input_schema: serde_json::to_value(&t.function.parameters)
+                        .unwrap_or(serde_json::json!({"type": "object"})),
+                })
+                .collect()
+        });
+
+        let thinking = config
+            .thinking_budget_tokens
+            .map(|budget_tokens| ClaudeThinking {
+                kind: "enabled",
+                budget_tokens,
+            });
+        let request = ClaudeRequest {
+            model: config.model.clone(),
+            messages: claude_messages,
+            system: system_prompt,
+            tools: claude_tools,
+            max_tokens: config.max_tokens,
+            // Thinking can't be combined with a custom temperature
+            temperature: thinking.is_none().then_some(config.temperature),
+            thinking,
+        };
+
+        let body = serde_json::to_value(&request)
+            .map_err(|e| AgentError::LlmError(format!("Failed to encode Claude request: {}", e)))?;
+
+        Ok(PreparedRequest::new(body))
+    }
+
+    fn parse_response(
+        &self,
+        body: &str,
+        _request: &PreparedRequest,
+    ) -> Result<LlmResponse, AgentError> {
+        let claude_response: ClaudeResponse =
+            serde_json::from_str(body).map_err(|e| parse_failed(self.name(), e))?;
+
+        // Extract text content, tool calls, and thinking
+        let mut content: Option<String> = None;
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        let mut thinking: Vec<ThinkingBlock> = Vec::new();
+
+        for block in claude_response.content {
+            match block {
+                ClaudeResponseContent::Text { text } => {
+                    content = Some(text);
+                }
+                ClaudeResponseContent::ToolUse { id, name, input } => {
+                    tool_calls.push(ToolCall {
+                        id,
+                        call_type: "function".to_string(),
+                        function: FunctionCall {
+                            name,
+                            arguments: serde_json::to_string(&input).unwrap_or_default(),
+                        },
+                    });
+                }
+                ClaudeResponseContent::Thinking {
+                    thinking: text,
+                    signature,
+                } => thinking.push(ThinkingBlock::Thinking {
+                    thinking: text,
+                    signature,
+                }),
+                ClaudeResponseContent::RedactedThinking { data } => {
+                    thinking.push(ThinkingBlock::RedactedThinking { data })
+                }
+                ClaudeResponseContent::Other => {}
+            }
+        }
+
+        let usage = claude_response.usage.map(|u| Usage {
+            prompt_tokens: u.input_tokens,
+            completion_tokens: u.output_tokens,
+            total_tokens: u.input_tokens + u.output_tokens,
+        });
+
+        Ok(LlmResponse {
+            content,
+            tool_calls,
+            usage,
+            finish_reason: claude_response.stop_reason,
+            warnings: Vec::new(),
+            reasoning: reasoning_text(&thinking),
+            thinking,
+            served_model: None,
+        })
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str, _config: &AgentConfig) -> AgentError {
+        if let Ok(api_error) = serde_json::from_str::<ClaudeError>(body) {
+            let message = format!("Claude API error ({}): {}", status, api_error.error.message);
+            return rejected(self.name(), status, body, message);
+        }
+        request_failed(self.name(), status, body)
+    }
+
+    fn start_stream(&self, body: &mut Value) -> Option<Box<dyn StreamAccumulator>> {
+        body["stream"] = Value::Bool(true);
+        Some(Box::<ClaudeStream>::default())
+    }
+}
+
+// ============================================================================
+// Streaming
+// ============================================================================
+
+/// A content block being assembled from stream events
+#[derive(Debug)]
+enum PartialBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input_json: String,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking(String),
+}
+
+/// Accumulates messages-API stream events into a response
+#[derive(Debug, Default)]
+struct ClaudeStream {
+    /// Keyed by the block's `index`
+    blocks: BTreeMap<usize, PartialBlock>,
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
+    stop_reason: Option<String>,
+}
+
+impl StreamAccumulator for ClaudeStream {
+    fn on_event(&mut self, event: &SseEvent) -> Result<Option<StreamDelta>, AgentError> {
+        let parsed: ClaudeStreamEvent =
+            serde_json::from_str(&event.data).map_err(|e| parse_failed("Claude", e))?;
+
+        match parsed {
+            ClaudeStreamEvent::MessageStart { message } => {
+                if let Some(usage) = message.usage {
+                    self.input_tokens = Some(usage.input_tokens);
+                    self.output_tokens = Some(usage.output_tokens);
+                }
+            }
+            ClaudeStreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                let block = match content_block {
+                    ClaudeStreamBlock::Text { text } => PartialBlock::Text(text),
+                    ClaudeStreamBlock::ToolUse { id, name } => PartialBlock::ToolUse {
+                        id,
+                        name,
+                        input_json: String::new(),
+                    },
+                    ClaudeStreamBlock::Thinking { thinking } => PartialBlock::Thinking {
+                        thinking,
+                        signature: String::new(),
+                    },
+                    ClaudeStreamBlock::RedactedThinking { data } => {
+                        PartialBlock::RedactedThinking(data)
+                    }
+                    ClaudeStreamBlock::Other => return Ok(None),
+                };
+                self.blocks.insert(index, block);
+            }
+            ClaudeStreamEvent::ContentBlockDelta { index, delta } => {
+                match (self.blocks.get_mut(&index), delta) {
+                    (Some(PartialBlock::Text(text)), ClaudeStreamDelta::TextDelta { text: d }) => {
+                        text.push_str(&d);
+                        return Ok(Some(StreamDelta::Text(d)));
+                    }
+                    (
+                        Some(PartialBlock::ToolUse { input_json, .. }),
+                        ClaudeStreamDelta::InputJsonDelta { partial_json },
+                    ) => input_json.push_str(&partial_json),
+                    (
+                        Some(PartialBlock::Thinking { thinking, .. }),
+                        ClaudeStreamDelta::ThinkingDelta { thinking: d },
+                    ) => {
+                        thinking.push_str(&d);
+                        return Ok(Some(StreamDelta::Reasoning(d)));
+                    }
+                    (
+                        Some(PartialBlock::Thinking { signature, .. }),
+                        ClaudeStreamDelta::SignatureDelta { signature: d },
+                    ) => signature.push_str(&d),
+                    _ => {}
+                }
+            }
+            ClaudeStreamEvent::MessageDelta { delta, usage } => {
+                if delta.stop_reason.is_some() {
+                    self.stop_reason = delta.stop_reason;
+                }
+                if let Some(usage) = usage {
+                    self.output_tokens = Some(usage.output_tokens);
+                }
+            }
+            ClaudeStreamEvent::Error { error } => {
+                return Err(AgentError::LlmError(format!(
+                    "Claude API error: {}",
+                    error.message
+                )));
+            }
+            ClaudeStreamEvent::Other => {}
+        }
+        Ok(None)
+    }
+
+    fn finish(self: Box<Self>, _request: &PreparedRequest) -> Result<LlmResponse, AgentError> {
+        // Same shape as `parse_response`: the last text block is the content
+        let mut content: Option<String> = None;
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        let mut thinking: Vec<ThinkingBlock> = Vec::new();
+
+        for block in self.blocks.into_values() {
+            match block {
+                PartialBlock::Text(text) => content = Some(text),
+                PartialBlock::ToolUse {
+                    id,
+                    name,
+                    input_json,
+                } => {
+                    // A tool with no parameters streams no input at all
+                    let arguments = if input_json.trim().is_empty() {
+                        "{}".to_string()
+                    } else {
+                        input_json
+                    };
+                    tool_calls.push(ToolCall {
+                        id,
+                        call_type: "function".to_string(),
+                        function: FunctionCall { name, arguments },
+                    });
+                }
+                PartialBlock::Thinking {
+                    thinking: text,
+                    signature,
+                } => thinking.push(ThinkingBlock::Thinking {
+                    thinking: text,
+                    signature,
+                }),
+                PartialBlock::RedactedThinking(data) => {
+                    thinking.push(ThinkingBlock::RedactedThinking { data })
+                }
+            }
+        }
+
+        let usage = self.input_tokens.map(|input| {
+            let output = self.output_tokens.unwrap_or(0);
+            Usage {
+                prompt_tokens: input,
+                completion_tokens: output,
+                total_tokens: input + output,
+            }
+        });
+
+        Ok(LlmResponse {
+            content,
+            tool_calls,
+            usage,
+            finish_reason: self.stop_reason,
+            warnings: Vec::new(),
+            reasoning: reasoning_text(&thinking),
+            thinking,
+            served_model: None,
+        })
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claude_request_serialization() {
+        let request = ClaudeRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            messages: vec![ClaudeMessage {
+                role: "user".to_string(),
+                content: ClaudeContent::Text("Hello".to_string()),
+            }],
+            system: Some("You are helpful".to_string()),
+            tools: None,
+            max_tokens: 1000,
+            temperature: Some(0.7),
+            thinking: None,
+        };
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("claude-sonnet-4-20250514"));
+        assert!(json.contains("Hello"));
+        assert!(json.contains("You are helpful"));
+    }
+
+    #[test]
+    fn test_claude_response_parsing() {
+        let json = r#"{
+            "id": "msg_123",
+            "content": [
+                {"type": "text", "text": "Hello!"}
+            ],
+            "stop_reason": "end_turn",
+            "usage": {
+                "input_tokens": 10,
+                "output_tokens": 5
+            }
+        }"#;
+
+        let response: ClaudeResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.content.len(), 1);
+        match &response.content[0] {
+            ClaudeResponseContent::Text { text } => assert_eq!(text, "Hello!"),
+            _ => panic!("Expected text content"),
+        }
+    }
+
+    #[test]
+    fn test_claude_tool_use_parsing() {
+        let json = r#"{
+            "id": "msg_123",
+            "content": [
+                {"type": "tool_use", "id": "tool_1", "name": "read_file", "input": {"path": "test.txt"}}
+            ],
+            "stop_reason": "tool_use"
+        }"#;
+
+        let response: ClaudeResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.content.len(), 1);
+        match &response.content[0] {
+            ClaudeResponseContent::ToolUse { id, name, input } => {
+                assert_eq!(id, "tool_1");
+                assert_eq!(name, "read_file");
+                assert_eq!(input["path"], "test.txt");
+            }
+            _ => panic!("Expected tool_use content"),
+        }
+    }
+
+    #[test]
+    fn test_claude_error_parsing() {
+        let json = r#"{
+            "error": {
+                "message": "Invalid API key",
+                "type": "authentication_error"
+            }
+        }"#;
+
+        let error: ClaudeError = serde_json::from_str(json).unwrap();
+        assert_eq!(error.error.message, "Invalid API key");
+    }
+
+    #[test]
+    fn test_claude_build_request_merges_system_prompts() {
+        let config = AgentConfig {
+            model: "claude-sonnet-4-20250514".to_string(),
+            ..AgentConfig::default()
+        };
+        let messages = vec![
+            Message::system("You are helpful"),
+            Message::developer("Be brief"),
+            Message::user("Hello"),
+        ];
+
+        let request = ClaudeProvider
+            .build_request(&config, &messages, None)
+            .unwrap();
+        assert_eq!(request.body["system"], "You are helpful\n\nBe brief");
+        assert_eq!(request.body["messages"].as_array().unwrap().len(), 1);
+        assert!(request.body.get("tools").is_none());
+    }
+
+    #[test]
+    fn test_stream_accumulates_text_and_tool_use() {
+        let mut body = serde_json::json!({});
+        let mut stream = ClaudeProvider.start_stream(&mut body).unwrap();
+        assert_eq!(body["stream"], true);
+
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"ping"}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"check."}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"read_file","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\": "}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"a.md\"}"}}"#,
+            r#"{"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_2","name":"list_dir","input":{}}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":30}}"#,
+            r#"{"type":"message_stop"}"#,
+        ];
+        let mut deltas = Vec::new();
+        for data in events {
+            let event = SseEvent {
+                data: data.to_string(),
+            };
+            deltas.extend(stream.on_event(&event).unwrap());
+        }
+        assert_eq!(
+            deltas,
+            [
+                StreamDelta::Text("Let me ".to_string()),
+                StreamDelta::Text("check.".to_string())
+            ]
+        );
+
+        let response = stream
+            .finish(&PreparedRequest::new(serde_json::json!({})))
+            .unwrap();
+        assert_eq!(response.content.as_deref(), Some("Let me check."));
+        assert_eq!(response.tool_calls.len(), 2);
+        assert_eq!(response.tool_calls[0].id, "toolu_1");
+        assert_eq!(
+            response.tool_calls[0].function.arguments,
+            r#"{"path": "a.md"}"#
+        );
+        assert_eq!(response.tool_calls[1].function.arguments, "{}");
+        let usage = response.usage.unwrap();
+        assert_eq!(usage.prompt_tokens, 12);
+        assert_eq!(usage.completion_tokens, 30);
+        assert_eq!(usage.total_tokens, 42);
+        assert_eq!(response.finish_reason.as_deref(), Some("tool_use"));
+    }
+
+    #[test]
+    fn test_thinking_request() {
+        let messages = vec![
+            Message::user("Read a.md"),
+            Message {
+                thinking: vec![
+                    ThinkingBlock::Thinking {
+                        thinking: "The user wants a.md".to_string(),
+                        signature: "sig-1".to_string(),
+                    },
+                    ThinkingBlock::RedactedThinking {
+                        data: "opaque".to_string(),
+                    },
+                ],
+                ..Message::assistant_with_tools(
+                    None,
+                    vec![ToolCall {
+                        id: "toolu_1".to_string(),
+                        call_type: "function".to_string(),
+                        function: FunctionCall {
+                            name: "read_file".to_string(),
+                            arguments: r#"{"path":"a.md"}"#.to_string(),
+                        },
+                    }],
+                )
+            },
+            Message::tool_result("toolu_1", "Hello"),
+        ];
+
+        let config = AgentConfig {
+            model: "claude-sonnet-4-20250514".to_string(),
+            max_tokens: 8192,
+            thinking_budget_tokens: Some(2048),
+            ..AgentConfig::default()
+        };
+        let body = ClaudeProvider
+            .build_request(&config, &messages, None)
+            .unwrap()
+            .body;
+        assert_eq!(
+            body["thinking"],
+            serde_json::json!({"type": "enabled", "budget_tokens": 2048})
+        );
+        assert!(body.get("temperature").is_none());
+        // The signed thinking goes back ahead of the tool call it led to
+        assert_eq!(
+            body["messages"][1]["content"],
+            serde_json::json!([
+                {"type": "thinking", "thinking": "The user wants a.md", "signature": "sig-1"},
+                {"type": "redacted_thinking", "data": "opaque"},
+                {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {"path": "a.md"}}
+            ])
+        );
+
+        // Without a budget, thinking is off and the temperature is sent
+        let config = AgentConfig {
+            thinking_budget_tokens: None,
+            ..config
+        };
+        let body = ClaudeProvider
+            .build_request(&config, &messages, None)
+            .unwrap()
+            .body;
+        assert!(body.get("thinking").is_none());
+        assert!(body.get("temperature").is_some());
+    }
+
+    #[test]
+    fn test_thinking_response_parsing() {
+        let body = r#"{
+            "id": "msg_123",
+            "content": [
+                {"type": "thinking", "thinking": "Check the notes first.", "signature": "sig-1"},
+                {"type": "redacted_thinking", "data": "opaque"},
+                {"type": "text", "text": "Done."},
+                {"type": "server_tool_use", "id": "srvtoolu_1", "name": "web_search", "input": {}}
+            ],
+            "stop_reason": "end_turn"
+        }"#;
+
+        let response = ClaudeProvider
+            .parse_response(body, &PreparedRequest::new(serde_json::json!({})))
+            .unwrap();
+        // Reasoning stays out of the answer
+        assert_eq!(response.content.as_deref(), Some("Done."));
+        assert_eq!(
+            response.reasoning.as_deref(),
+            Some("Check the notes first.")
+        );
+        assert_eq!(
+            response.thinking,
+            [
+                ThinkingBlock::Thinking {
+                    thinking: "Check the notes first.".to_string(),
+                    signature: "sig-1".to_string()
+                },
+                ThinkingBlock::RedactedThinking {
+                    data: "opaque".to_string()
+                }
+            ]
+        );
+        assert!(response.tool_calls.is_empty());
+    }
+
+    #[test]
+    fn test_stream_separates_thinking() {
+        let mut body = serde_json::json!({});
+        let mut stream = ClaudeProvider.start_stream(&mut body).unwrap();
+
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"thinking","thinking":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"Hmm, "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"short answer."}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"signature_delta","signature":"sig-1"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"text_delta","text":"Yes."}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":20}}"#,
+        ];
+        let mut deltas = Vec::new();
+        for data in events {
+            let event = SseEvent {
+                data: data.to_string(),
+            };
+            deltas.extend(stream.on_event(&event).unwrap());
+        }
+        assert_eq!(
+            deltas,
+            [
+                StreamDelta::Reasoning("Hmm, ".to_string()),
+                StreamDelta::Reasoning("short answer.".to_string()),
+                StreamDelta::Text("Yes.".to_string())
+            ]
+        );
+
+        let response = stream
+            .finish(&PreparedRequest::new(serde_json::json!({})))
+            .unwrap();
+        assert_eq!(response.content.as_deref(), Some("Yes."));
+        assert_eq!(response.reasoning.as_deref(), Some("Hmm, short answer."));
+        assert_eq!(
+            response.thinking,
+            [ThinkingBlock::Thinking {
+                thinking: "Hmm, short answer.".to_string(),
+                signature: "sig-1".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_stream_error_event() {
+        let mut body = serde_json::json!({});
+        let mut stream = ClaudeProvider.start_stream(&mut body).unwrap();
+        let event = SseEvent {
+            data: r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+                .to_string(),
+        };
+        let err = stream.on_event(&event).unwrap_err();
+        assert!(err.to_string().contains("Overloaded"));
+    }
+}
@@ -0,0 +1,1186 @@
+//! Multi-provider LLM client for OpenAI, Claude, Ollama, and OpenRouter APIs.
+//!
+//! Each provider lives in its own module and implements [`ChatProvider`], which
+//! turns the agent's conversation into a request body and the provider's reply back
+//! into an [`LlmResponse`]. [`LlmClient`] owns the HTTP plumbing shared by all of them.
+//!
+//! - OpenAI / OpenRouter: Full tool support via OpenAI-compatible function calling
+//! - Claude: Full tool support via Anthropic's tool_use
+//! - Ollama: Native tool calling for models that support it, chat-only otherwise
+//!
+//! OpenAI-compatible providers and Claude can also stream their replies over SSE;
+//! partial text is forwarded through a [`ChatSink`] while the full response is
+//! assembled as usual. Reasoning (Claude extended thinking) is forwarded as
+//! `Reasoning` events, never as part of the answer.
+//!
+//! Rate limits (429), server errors (500/502/503, Anthropic's 529 overload) and
+//! request timeouts are retried with exponential backoff and jitter, honoring
+//! `Retry-After` when the provider sends it. A request that still times out fails
+//! with [`AgentError::Timeout`]. Requests to a provider whose rate limit headers
+//! say it is nearly out of requests or tokens wait for the limit to reset first
+//! (see [`rate_limits`]).
+
+mod claude;
+mod ollama;
+mod openai;
+mod openai_compat;
+mod openrouter;
+pub mod rate_limits;
+mod sse;
+
+pub(crate) use claude::ANTHROPIC_VERSION;
+pub use rate_limits::{RateLimitSnapshot, RateLimitState, SharedRateLimits};
+
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use super::event_channel::EventSender;
+use super::redact::redact_secret;
+use super::types::{
+    AgentConfig, AgentError, AgentEvent, ErrorCode, EventDetail, LlmProvider, Message, ServedModel,
+    ThinkingBlock, Tool, ToolCall, Usage,
+};
+use sse::{SseDecoder, SseEvent};
+
+// ============================================================================
+// Common Response Type
+// ============================================================================
+
+/// Response from an LLM call (provider-agnostic)
+#[derive(Debug)]
+pub struct LlmResponse {
+    /// Text content from the assistant (may be None if only tool calls)
+    pub content: Option<String>,
+    /// Tool calls requested by the assistant
+    pub tool_calls: Vec<ToolCall>,
+    /// Token usage information
+    pub usage: Option<Usage>,
+    /// The finish reason
+    #[allow(dead_code)]
+    pub finish_reason: Option<String>,
+    /// Problems the client worked around while producing this response
+    pub warnings: Vec<String>,
+    /// The model that actually answered, when the provider reports it (routers
+    /// like OpenRouter may serve a fallback model or a different upstream)
+    pub served_model: Option<ServedModel>,
+    /// Reasoning text the model returned alongside its answer (Claude thinking)
+    pub reasoning: Option<String>,
+    /// The reasoning as the provider's blocks, to send back with the next turn
+    pub thinking: Vec<ThinkingBlock>,
+}
+
+// ============================================================================
+// Provider Trait
+// ============================================================================
+
+/// A request body ready to send, plus what's needed to read the reply
+#[derive(Debug)]
+pub struct PreparedRequest {
+    /// JSON body to POST to the provider
+    pub body: Value,
+    /// Provider-facing tool name -> original tool name, for names the provider
+    /// required us to rewrite
+    pub tool_names: HashMap<String, String>,
+}
+
+impl PreparedRequest {
+    /// A request whose tool names were sent unchanged
+    pub fn new(body: Value) -> Self {
+        PreparedRequest {
+            body,
+            tool_names: HashMap::new(),
+        }
+    }
+
+    /// Map a tool name from the provider's reply back to the name the agent knows
+    pub fn original_tool_name(&self, name: String) -> String {
+        match self.tool_names.get(&name) {
+            Some(original) => original.clone(),
+            None => name,
+        }
+    }
+}
+
+/// Wire format of a chat provider
+pub trait ChatProvider: Send + Sync {
+    /// Display name used in logs and error messages
+    fn name(&self) -> &'static str;
+
+    /// Whether requests need `AgentConfig::api_key`
+    fn requires_api_key(&self) -> bool {
+        true
+    }
+
+    /// Limit on a whole request when `AgentConfig::request_timeout_secs` isn't set
+    fn default_request_timeout(&self) -> Option<Duration> {
+        Some(DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    /// Chat endpoint for the configured base URL
+    fn endpoint(&self, config: &AgentConfig) -> String;
+
+    /// Headers to send besides `Content-Type` (auth, versioning, attribution)
+    fn headers(&self, config: &AgentConfig) -> Vec<(&'static str, String)>;
+
+    /// Build the request body for a conversation
+    fn build_request(
+        &self,
+        config: &AgentConfig,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+    ) -> Result<PreparedRequest, AgentError>;
+
+    /// Parse a successful response body
+    fn parse_response(
+        &self,
+        body: &str,
+        request: &PreparedRequest,
+    ) -> Result<LlmResponse, AgentError>;
+
+    /// Turn an error response into an `AgentError`
+    fn parse_error(&self, status: StatusCode, body: &str, _config: &AgentConfig) -> AgentError {
+        request_failed(self.name(), status, body)
+    }
+
+    /// Whether an error response means the model can't take tools at all, so the
+    /// request is worth retrying without them
+    fn tools_unsupported(&self, _status: StatusCode, _body: &str) -> bool {
+        false
+    }
+
+    /// Rate limits reported in a reply's headers, read at `now`
+    fn rate_limits(
+        &self,
+        _headers: &reqwest::header::HeaderMap,
+        _now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<RateLimitSnapshot> {
+        None
+    }
+
+    /// Switch a prepared request to streaming and return the accumulator that reads
+    /// the reply. Providers that can't stream return `None` and leave `body` alone.
+    fn start_stream(&self, _body: &mut Value) -> Option<Box<dyn StreamAccumulator>> {
+        None
+    }
+}
+
+/// Text a stream event added to the reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamDelta {
+    /// Part of the answer
+    Text(String),
+    /// Part of the model's reasoning, shown apart from the answer
+    Reasoning(String),
+}
+
+/// Assembles an [`LlmResponse`] from a provider's server-sent events
+pub trait StreamAccumulator: Send {
+    /// Consume one event, returning any text it added
+    fn on_event(&mut self, event: &SseEvent) -> Result<Option<StreamDelta>, AgentError>;
+
+    /// Build the complete response once the stream has ended
+    fn finish(self: Box<Self>, request: &PreparedRequest) -> Result<LlmResponse, AgentError>;
+}
+
+/// Specific code for a non-success response, from its status and the provider's
+/// error payload (OpenAI `code`, Anthropic error `type`, or message wording)
+fn classify_rejection(status: StatusCode, body: &str) -> ErrorCode {
+    let body = body.to_lowercase();
+    let has = |needles: &[&str]| needles.iter().any(|n| body.contains(n));
+    if status == StatusCode::PAYMENT_REQUIRED
+        || has(&["insufficient_quota", "credit balance is too low"])
+    {
+        ErrorCode::InsufficientQuota
+    } else if has(&[
+        "context_length_exceeded",
+        "context length exceeded",
+        "maximum context length",
+        "prompt is too long",
+    ]) {
+        ErrorCode::ContextLengthExceeded
+    } else if has(&["model_not_found"])
+        || (status == StatusCode::NOT_FOUND
+            && body.contains("model")
+            && has(&["not found", "not_found_error", "does not exist"]))
+    {
+        ErrorCode::ModelNotFound
+    } else if status == StatusCode::UNAUTHORIZED
+        || has(&["invalid_api_key", "authentication_error"])
+    {
+        ErrorCode::InvalidApiKey
+    } else if status == StatusCode::TOO_MANY_REQUESTS || has(&["rate_limit"]) {
+        ErrorCode::RateLimited
+    } else {
+        ErrorCode::ProviderError
+    }
+}
+
+/// Error for a non-success response, coded from its status and body
+fn rejected(name: &str, status: StatusCode, body: &str, message: String) -> AgentError {
+    AgentError::Provider {
+        code: classify_rejection(status, body),
+        provider: name.to_string(),
+        message,
+    }
+}
+
+/// Fallback error for a non-success response the provider couldn't explain
+fn request_failed(name: &str, status: StatusCode, body: &str) -> AgentError {
+    let message = format!("{} request failed ({}): {}", name, status, body);
+    rejected(name, status, body, message)
+}
+
+/// Error for a response body that doesn't match the provider's schema
+fn parse_failed(name: &str, err: serde_json::Error) -> AgentError {
+    AgentError::LlmError(format!("Failed to parse {} response: {}", name, err))
+}
+
+/// The provider implementation for a configured `LlmProvider`
+pub fn provider_for(provider: LlmProvider) -> Box<dyn ChatProvider> {
+    match provider {
+        LlmProvider::OpenAI => Box::new(openai::provider()),
+        LlmProvider::Claude => Box::new(claude::ClaudeProvider),
+        LlmProvider::Ollama => Box::new(ollama::OllamaProvider),
+        LlmProvider::OpenRouter => Box::new(openrouter::provider()),
+    }
+}
+
+// ============================================================================
+// LLM Client
+// ============================================================================
+
+/// Future returned by [`LlmChat::chat`]
+pub type ChatFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<LlmResponse, AgentError>> + Send + 'a>>;
+
+/// Forwards what happens during a model call to the UI: streamed assistant text
+/// as `TextChunk` events and retries as `RetryingLlmCall` events
+#[derive(Debug, Clone)]
+pub struct ChatSink {
+    events: EventSender,
+    run_id: String,
+    detail: EventDetail,
+}
+
+impl ChatSink {
+    pub fn new(events: EventSender, run_id: &str, detail: EventDetail) -> Self {
+        ChatSink {
+            events,
+            run_id: run_id.to_string(),
+            detail,
+        }
+    }
+
+    /// Whether streamed text is wanted (events that carry metadata only can't show it)
+    pub fn wants_text(&self) -> bool {
+        self.detail != EventDetail::MetadataOnly
+    }
+
+    /// Emit one chunk of text
+    pub async fn text(&self, text: &str) {
+        if text.is_empty() || !self.wants_text() {
+            return;
+        }
+        let event = AgentEvent::TextChunk {
+            content: text.to_string(),
+            run_id: Some(self.run_id.clone()),
+        };
+        self.events.send(event.with_detail(self.detail)).await;
+    }
+
+    /// Emit reasoning (a streamed chunk, or a whole reply's worth)
+    pub async fn reasoning(&self, text: &str) {
+        if text.is_empty() || !self.wants_text() {
+            return;
+        }
+        let event = AgentEvent::Reasoning {
+            content: text.to_string(),
+            run_id: Some(self.run_id.clone()),
+        };
+        self.events.send(event.with_detail(self.detail)).await;
+    }
+
+    /// Emit a streamed delta as text or reasoning
+    async fn delta(&self, delta: StreamDelta) {
+        match delta {
+            StreamDelta::Text(text) => self.text(&text).await,
+            StreamDelta::Reasoning(text) => self.reasoning(&text).await,
+        }
+    }
+
+    /// Announce that a failed call will be retried after `delay`
+    pub async fn retrying(&self, attempt: u32, max_retries: u32, delay: Duration, reason: &str) {
+        let event = AgentEvent::RetryingLlmCall {
+            attempt,
+            max_retries,
+            delay_ms: delay.as_millis() as u64,
+            reason: reason.to_string(),
+            run_id: Some(self.run_id.clone()),
+        };
+        self.events.send(event).await;
+    }
+
+    /// Announce that the next call waits `delay` for a rate limit to reset
+    pub async fn waiting(&self, delay: Duration, reason: &str) {
+        let event = AgentEvent::Waiting {
+            delay_ms: delay.as_millis() as u64,
+            reason: reason.to_string(),
+            run_id: Some(self.run_id.clone()),
+        };
+        self.events.send(event).await;
+    }
+}
+
+/// Something the agent loop can hold a conversation with.
+///
+/// [`LlmClient`] is the real implementation; tests substitute scripted responses.
+pub trait LlmChat: Send + Sync {
+    /// Send the conversation (and available tools) and return the model's reply.
+    /// When `sink` is given, partial text may be streamed to it as it arrives and
+    /// retries are announced through it.
+    fn chat<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: Option<&'a [Tool]>,
+        sink: Option<&'a ChatSink>,
+    ) -> ChatFuture<'a>;
+}
+
+/// Request limit for hosted providers
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long an idle pooled connection is kept for the next call
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Multi-provider LLM client. One HTTP client (and its connection pool) is shared
+/// by every call the instance makes.
+pub struct LlmClient {
+    client: Client,
+    config: AgentConfig,
+    provider: Box<dyn ChatProvider>,
+    /// Set once the model has rejected tools; later calls are chat-only
+    tools_rejected: AtomicBool,
+    /// What the provider last said about its rate limits, shared across runs
+    rate_limits: SharedRateLimits,
+}
+
+/// Outcome of a single HTTP exchange with the provider
+enum Attempt {
+    Done(LlmResponse),
+    Rejected {
+        status: StatusCode,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+    TimedOut(String),
+}
+
+/// First retry delay; doubles with each further attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Statuses worth retrying: rate limits, transient server errors, and
+/// Anthropic's `overloaded_error` (529)
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 500 | 502 | 503 | 529)
+}
+
+/// Parse a `Retry-After` value: delay seconds or an HTTP date
+fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&chrono::Utc) - now)
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Delay before retry number `attempt` (1-based): the server's `Retry-After` when
+/// given, otherwise exponential backoff with jitter in [50%, 100%] of the step.
+/// Never more than `max`.
+fn retry_delay(
+    attempt: u32,
+    retry_after: Option<Duration>,
+    max: Duration,
+    jitter: f64,
+) -> Duration {
+    if let Some(delay) = retry_after {
+        return delay.min(max);
+    }
+    let step = RETRY_BASE_DELAY
+        .saturating_mul(1u32 << attempt.saturating_sub(1).min(16))
+        .min(max);
+    step.mul_f64(0.5 + 0.5 * jitter.clamp(0.0, 1.0))
+}
+
+/// The server's requested delay, from `retry-after-ms` (OpenAI) or `Retry-After`
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers
+        .get("retry-after-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|ms| ms.is_finite() && *ms >= 0.0)
+    {
+        return Some(Duration::from_secs_f64(ms / 1000.0));
+    }
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, chrono::Utc::now())
+}
+
+/// An HTTP client with the given limits and connection pooling
+fn http_client(connect_timeout: Duration, request_timeout: Option<Duration>) -> Client {
+    let mut builder = Client::builder()
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT);
+    if let Some(timeout) = request_timeout {
+        builder = builder.timeout(timeout);
+    }
+    builder.build().unwrap_or_else(|e| {
+        log::warn!("Failed to build LLM HTTP client ({}); using defaults", e);
+        Client::new()
+    })
+}
+
+/// Error for a reply that failed part way through being read
+fn read_failed(name: &str, what: &str, e: reqwest::Error) -> AgentError {
+    if e.is_timeout() {
+        AgentError::Timeout(format!("{} {} timed out: {}", name, what, e))
+    } else {
+        AgentError::LlmError(format!("Failed to read {} {}: {}", name, what, e))
+    }
+}
+
+/// A random fraction in [0, 1)
+fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl LlmClient {
+    /// Create a new LLM client
+    pub fn new(config: AgentConfig) -> Self {
+        let provider = provider_for(config.provider);
+        let request_timeout = config
+            .request_timeout_secs
+            .map(Duration::from_secs)
+            .or_else(|| provider.default_request_timeout());
+        LlmClient {
+            client: http_client(
+                Duration::from_secs(config.connect_timeout_secs),
+                request_timeout,
+            ),
+            provider,
+            config,
+            tools_rejected: AtomicBool::new(false),
+            rate_limits: SharedRateLimits::default(),
+        }
+    }
+
+    /// Track rate limits in `rate_limits`, shared with other clients, instead of
+    /// on this client alone
+    pub fn with_rate_limits(mut self, rate_limits: SharedRateLimits) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Wait out a rate limit the provider is about to hit, announcing it to `sink`
+    async fn pace(&self, sink: Option<&ChatSink>) {
+        let Some(snapshot) = self
+            .rate_limits
+            .current(self.config.provider, chrono::Utc::now())
+        else {
+            return;
+        };
+        let Some((delay, reason)) = rate_limits::pacing_delay(&snapshot, chrono::Utc::now()) else {
+            return;
+        };
+        log::info!(
+            "{}: {}; waiting {} ms",
+            self.provider.name(),
+            reason,
+            delay.as_millis()
+        );
+        if let Some(sink) = sink {
+            sink.waiting(delay, &reason).await;
+        }
+        tokio::time::sleep(delay).await;
+    }
+
+    /// Make a chat completion request to the configured provider, streaming partial
+    /// text to `sink` when the provider supports it and `AgentConfig::stream` is set.
+    ///
+    /// Transient failures are retried up to `AgentConfig::max_retries` times. If the
+    /// model turns out not to support tools, the request is repeated without them
+    /// and the response carries a warning; later calls skip tools.
+    pub async fn chat(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        sink: Option<&ChatSink>,
+    ) -> Result<LlmResponse, AgentError> {
+        let provider = self.provider.as_ref();
+        let name = provider.name();
+
+        if provider.requires_api_key() && self.config.api_key.is_empty() {
+            return Err(AgentError::MissingApiKey(name.to_string()));
+        }
+
+        let max_retries = self.config.max_retries;
+        let max_backoff = Duration::from_millis(self.config.max_retry_backoff_ms);
+        let mut tools = tools.filter(|_| !self.tools_rejected.load(Ordering::Relaxed));
+        let mut warnings = Vec::new();
+        let mut retries = 0;
+        loop {
+            self.pace(sink).await;
+            let (reason, retry_after) = match self.send(messages, tools, sink).await? {
+                Attempt::Done(mut response) => {
+                    response.warnings.splice(0..0, warnings);
+                    return Ok(response);
+                }
+                Attempt::Rejected { status, body, .. }
+                    if tools.is_some() && provider.tools_unsupported(status, &body) =>
+                {
+                    log::warn!(
+                        "{} model '{}' does not support tools; retrying in chat-only mode",
+                        name,
+                        self.config.model
+                    );
+                    self.tools_rejected.store(true, Ordering::Relaxed);
+                    warnings.push(format!(
+                        "Model '{}' does not support tool calling; continuing in chat-only mode without file access.",
+                        self.config.model
+                    ));
+                    tools = None;
+                    continue;
+                }
+                Attempt::Rejected {
+                    status,
+                    retry_after,
+                    ..
+                } if is_retryable_status(status) && retries < max_retries => {
+                    (format!("{} returned {}", name, status), retry_after)
+                }
+                Attempt::Rejected { status, body, .. } => {
+                    return Err(provider.parse_error(status, &body, &self.config));
+                }
+                Attempt::TimedOut(message) if retries < max_retries => (message, None),
+                Attempt::TimedOut(message) => return Err(AgentError::Timeout(message)),
+            };
+
+            retries += 1;
+            let delay = retry_delay(retries, retry_after, max_backoff, jitter());
+            log::warn!(
+                "{}; retrying in {} ms (attempt {}/{})",
+                reason,
+                delay.as_millis(),
+                retries,
+                max_retries
+            );
+            if let Some(sink) = sink {
+                sink.retrying(retries, max_retries, delay, &reason).await;
+            }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Send one request and read the reply
+    async fn send(
+        &self,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+        sink: Option<&ChatSink>,
+    ) -> Result<Attempt, AgentError> {
+        let provider = self.provider.as_ref();
+        let name = provider.name();
+
+        let url = provider.endpoint(&self.config);
+        let mut prepared = provider.build_request(&self.config, messages, tools)?;
+        let stream = match sink {
+            Some(sink) if self.config.stream && sink.wants_text() => provider
+                .start_stream(&mut prepared.body)
+                .map(|accumulator| (accumulator, sink)),
+            _ => None,
+        };
+
+        log::debug!("{} request to {}: model={}", name, url, self.config.model);
+
+        let mut request = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json");
+        for (header, value) in provider.headers(&self.config) {
+            request = request.header(header, value);
+        }
+
+        let mut response = match request.json(&prepared.body).send().await {
+            Ok(response) => response,
+            Err(e) if e.is_timeout() => {
+                return Ok(Attempt::TimedOut(format!(
+                    "{} request timed out: {}",
+                    name, e
+                )));
+            }
+            Err(e) => {
+                return Err(AgentError::LlmError(format!(
+                    "{} request failed: {}",
+                    name, e
+                )))
+            }
+        };
+
+        let status = response.status();
+        if let Some(snapshot) = provider.rate_limits(response.headers(), chrono::Utc::now()) {
+            self.rate_limits.record(self.config.provider, snapshot);
+        }
+
+        if !status.is_success() {
+            let retry_after = retry_after(response.headers());
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            // Some providers echo the request's credentials back
+            return Ok(Attempt::Rejected {
+                status,
+                body: redact_secret(&error_text, &self.config.api_key),
+                retry_after,
+            });
+        }
+
+        if let Some((mut accumulator, sink)) = stream {
+            let mut decoder = SseDecoder::default();
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|e| read_failed(name, "stream", e))?
+            {
+                for event in decoder.feed(&chunk) {
+                    if let Some(delta) = accumulator.on_event(&event)? {
+                        sink.delta(delta).await;
+                    }
+                }
+            }
+            if let Some(event) = decoder.finish() {
+                if let Some(delta) = accumulator.on_event(&event)? {
+                    sink.delta(delta).await;
+                }
+            }
+            return accumulator.finish(&prepared).map(Attempt::Done);
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| read_failed(name, "response", e))?;
+
+        let response = provider.parse_response(&body, &prepared)?;
+        if let (Some(sink), Some(reasoning)) = (sink, response.reasoning.as_deref()) {
+            sink.reasoning(reasoning).await;
+        }
+        Ok(Attempt::Done(response))
+    }
+}
+
+impl LlmChat for LlmClient {
+    fn chat<'a>(
+        &'a self,
+        messages: &'a [Message],
+        tools: Option<&'a [Tool]>,
+        sink: Option<&'a ChatSink>,
+    ) -> ChatFuture<'a> {
+        Box::pin(LlmClient::chat(self, messages, tools, sink))
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::types::{FunctionCall, JsonSchema};
+    use tokio::sync::mpsc;
+
+    fn conversation() -> Vec<Message> {
+        vec![
+            Message::system("You are helpful"),
+            Message::user("Read notes.md"),
+            Message::assistant_with_tools(
+                None,
+                vec![ToolCall {
+                    id: "call_1".to_string(),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "my-ext:read notes".to_string(),
+                        arguments: "{\"path\":\"notes.md\"}".to_string(),
+                    },
+                }],
+            ),
+            Message::tool_result("call_1", "Chapter one"),
+        ]
+    }
+
+    fn tools() -> Vec<Tool> {
+        vec![Tool::new(
+            "my-ext:read notes",
+            "Read notes",
+            JsonSchema {
+                schema_type: "object".to_string(),
+                properties: None,
+                required: None,
+            },
+        )]
+    }
+
+    fn config(provider: LlmProvider, model: &str) -> AgentConfig {
+        AgentConfig {
+            provider,
+            api_key: "sk-test".to_string(),
+            model: model.to_string(),
+            ..AgentConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_openrouter_and_openai_bodies_match() {
+        let messages = conversation();
+        let tools = tools();
+
+        for model in ["gpt-4o-mini", "gpt-5-mini", "o3-mini"] {
+            let openai_config = config(LlmProvider::OpenAI, model);
+            let openrouter_config = config(LlmProvider::OpenRouter, model);
+            let openai = provider_for(LlmProvider::OpenAI);
+            let openrouter = provider_for(LlmProvider::OpenRouter);
+
+            let openai_request = openai
+                .build_request(&openai_config, &messages, Some(&tools))
+                .unwrap();
+            let openrouter_request = openrouter
+                .build_request(&openrouter_config, &messages, Some(&tools))
+                .unwrap();
+
+            assert_eq!(
+                openai_request.body, openrouter_request.body,
+                "model {}",
+                model
+            );
+            assert_eq!(openai_request.tool_names, openrouter_request.tool_names);
+        }
+    }
+
+    #[test]
+    fn test_openrouter_and_openai_headers_differ_only_by_attribution() {
+        let openai_config = config(LlmProvider::OpenAI, "gpt-4o-mini");
+        let openrouter_config = config(LlmProvider::OpenRouter, "gpt-4o-mini");
+
+        let openai_headers = provider_for(LlmProvider::OpenAI).headers(&openai_config);
+        let openrouter_headers = provider_for(LlmProvider::OpenRouter).headers(&openrouter_config);
+
+        assert_eq!(
+            openai_headers,
+            vec![("Authorization", "Bearer sk-test".to_string())]
+        );
+        assert_eq!(&openrouter_headers[..1], &openai_headers[..]);
+        let extra: Vec<_> = openrouter_headers[1..].iter().map(|(h, _)| *h).collect();
+        assert_eq!(extra, vec!["HTTP-Referer", "X-Title"]);
+    }
+
+    #[test]
+    fn test_developer_role_mapping() {
+        let messages = vec![Message::developer("Be brief"), Message::user("Hi")];
+
+        let openai = provider_for(LlmProvider::OpenAI)
+            .build_request(&config(LlmProvider::OpenAI, "gpt-5-mini"), &messages, None)
+            .unwrap();
+        let openrouter = provider_for(LlmProvider::OpenRouter)
+            .build_request(
+                &config(LlmProvider::OpenRouter, "gpt-5-mini"),
+                &messages,
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(openai.body["messages"][0]["role"], "developer");
+        assert_eq!(openrouter.body["messages"][0]["role"], "system");
+    }
+
+    #[test]
+    fn test_provider_names() {
+        assert_eq!(provider_for(LlmProvider::OpenAI).name(), "OpenAI");
+        assert_eq!(provider_for(LlmProvider::OpenRouter).name(), "OpenRouter");
+        assert_eq!(provider_for(LlmProvider::Claude).name(), "Claude");
+        assert_eq!(provider_for(LlmProvider::Ollama).name(), "Ollama");
+        assert!(!provider_for(LlmProvider::Ollama).requires_api_key());
+    }
+
+    // ========================================================================
+    // Retries
+    // ========================================================================
+
+    /// Answer one request per connection with the given raw HTTP responses, in
+    /// order. Returns the base URL and a counter of requests served.
+    async fn serve_responses(
+        responses: Vec<String>,
+    ) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = served.clone();
+
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                // Read until the end of the body so the client isn't reset mid-send
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    let Some(end) = text.find("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|l| {
+                            let (name, value) = l.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())
+                                .flatten()
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + length {
+                        break;
+                    }
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.ok();
+            }
+        });
+
+        (format!("http://{}", addr), served)
+    }
+
+    fn http_response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+            status,
+            body.len(),
+            headers,
+            body
+        )
+    }
+
+    fn ollama_client(base_url: String, max_retries: u32) -> LlmClient {
+        LlmClient::new(AgentConfig {
+            provider: LlmProvider::Ollama,
+            model: "llama3.1".to_string(),
+            base_url: Some(base_url),
+            max_retries,
+            max_retry_backoff_ms: 20,
+            ..AgentConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_chat_retries_transient_errors() {
+        let ok = r#"{"message":{"role":"assistant","content":"Hi"},"done":true}"#;
+        let (base_url, served) = serve_responses(vec![
+            http_response("429 Too Many Requests", "Retry-After: 0\r\n", "{}"),
+            http_response("503 Service Unavailable", "", "{}"),
+            http_response("200 OK", "", ok),
+        ])
+        .await;
+        let client = ollama_client(base_url, 3);
+        let (tx, mut rx) = mpsc::channel(8);
+        let sink = ChatSink::new(EventSender::new(tx), "run-1", EventDetail::Full);
+
+        let response = client
+            .chat(&[Message::user("hello")], None, Some(&sink))
+            .await
+            .unwrap();
+        assert_eq!(response.content.as_deref(), Some("Hi"));
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+
+        let mut attempts = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let AgentEvent::RetryingLlmCall {
+                attempt,
+                max_retries,
+                reason,
+                ..
+            } = event
+            {
+                assert_eq!(max_retries, 3);
+                attempts.push((attempt, reason));
+            }
+        }
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].0, 1);
+        assert!(attempts[0].1.contains("429"));
+        assert!(attempts[1].1.contains("503"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_does_not_retry_client_errors() {
+        let (base_url, served) = serve_responses(vec![
+            http_response("401 Unauthorized", "", r#"{"error":"bad key"}"#),
+            http_response("200 OK", "", "{}"),
+        ])
+        .await;
+        let client = ollama_client(base_url, 3);
+
+        let err = client
+            .chat(&[Message::user("hello")], None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidApiKey);
+        assert!(err.to_string().contains("401"), "{}", err);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejection_does_not_echo_the_key() {
+        let key = "live-key-0123456789abcdef";
+        let body = format!(
+            r#"{{"error":{{"message":"Incorrect API key provided: {}. Sent Authorization: Bearer {}","code":"invalid_api_key"}}}}"#,
+            key, key
+        );
+        let (base_url, _) =
+            serve_responses(vec![http_response("401 Unauthorized", "", &body)]).await;
+        let client = LlmClient::new(AgentConfig {
+            api_key: key.to_string(),
+            base_url: Some(base_url),
+            max_retries: 0,
+            ..config(LlmProvider::OpenAI, "gpt-4o-mini")
+        });
+
+        let err = client
+            .chat(&[Message::user("hello")], None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::InvalidApiKey);
+        let message = err.to_string();
+        assert!(!message.contains(key), "{}", message);
+        assert!(
+            message.contains("Incorrect API key provided"),
+            "{}",
+            message
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_gives_up_after_max_retries() {
+        let (base_url, served) = serve_responses(vec![
+            http_response("500 Internal Server Error", "", "{}"),
+            http_response("502 Bad Gateway", "", "{}"),
+        ])
+        .await;
+        let client = ollama_client(base_url, 1);
+
+        let err = client
+            .chat(&[Message::user("hello")], None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::ProviderError);
+        assert!(err.to_string().contains("502"), "{}", err);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chat_times_out() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+        let client = LlmClient::new(AgentConfig {
+            request_timeout_secs: Some(1),
+            ..ollama_client(base_url, 0).config
+        });
+
+        let err = client
+            .chat(&[Message::user("hello")], None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AgentError::Timeout(ref m) if m.contains("Ollama")));
+        assert!(err.to_string().starts_with("Timeout:"));
+    }
+
+    #[test]
+    fn test_default_request_timeouts() {
+        for provider in [
+            LlmProvider::OpenAI,
+            LlmProvider::Claude,
+            LlmProvider::OpenRouter,
+        ] {
+            assert_eq!(
+                provider_for(provider).default_request_timeout(),
+                Some(DEFAULT_REQUEST_TIMEOUT)
+            );
+        }
+        assert_eq!(
+            provider_for(LlmProvider::Ollama).default_request_timeout(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let max = Duration::from_secs(30);
+        // Exponential steps, jittered into [50%, 100%]
+        assert_eq!(retry_delay(1, None, max, 1.0), Duration::from_millis(500));
+        assert_eq!(retry_delay(3, None, max, 1.0), Duration::from_millis(2000));
+        assert_eq!(retry_delay(3, None, max, 0.0), Duration::from_millis(1000));
+        // Capped by the maximum, even with a long Retry-After
+        assert_eq!(retry_delay(20, None, max, 1.0), max);
+        assert_eq!(
+            retry_delay(1, Some(Duration::from_secs(120)), max, 0.3),
+            max
+        );
+        assert_eq!(
+            retry_delay(1, Some(Duration::from_secs(2)), max, 0.3),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = chrono::DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        assert_eq!(parse_retry_after("7", now), Some(Duration::from_secs(7)));
+        assert_eq!(
+            parse_retry_after("1.5", now),
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 01 Jan 2025 00:00:10 GMT", now),
+            Some(Duration::from_secs(10))
+        );
+        // A date in the past means "now"
+        assert_eq!(
+            parse_retry_after("Tue, 31 Dec 2024 23:59:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-1", now), None);
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        for code in [429, 500, 502, 503, 529] {
+            assert!(is_retryable_status(StatusCode::from_u16(code).unwrap()));
+        }
+        for code in [400, 401, 403, 404, 422] {
+            assert!(!is_retryable_status(StatusCode::from_u16(code).unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_classify_rejection() {
+        let cases = [
+            (
+                401,
+                r#"{"error":{"message":"Incorrect API key provided","code":"invalid_api_key"}}"#,
+                ErrorCode::InvalidApiKey,
+            ),
+            (
+                401,
+                r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+                ErrorCode::InvalidApiKey,
+            ),
+            (
+                429,
+                r#"{"error":{"message":"Rate limit reached","code":"rate_limit_exceeded"}}"#,
+                ErrorCode::RateLimited,
+            ),
+            // OpenAI reports an empty balance as a 429 too
+            (
+                429,
+                r#"{"error":{"message":"You exceeded your current quota","code":"insufficient_quota"}}"#,
+                ErrorCode::InsufficientQuota,
+            ),
+            (
+                400,
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"Your credit balance is too low"}}"#,
+                ErrorCode::InsufficientQuota,
+            ),
+            (
+                404,
+                r#"{"error":{"message":"The model `gpt-9` does not exist","code":"model_not_found"}}"#,
+                ErrorCode::ModelNotFound,
+            ),
+            (
+                404,
+                r#"{"type":"error","error":{"type":"not_found_error","message":"model: claude-x"}}"#,
+                ErrorCode::ModelNotFound,
+            ),
+            (
+                400,
+                r#"{"error":{"message":"This model's maximum context length is 128000 tokens","code":"context_length_exceeded"}}"#,
+                ErrorCode::ContextLengthExceeded,
+            ),
+            (
+                400,
+                r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210000 tokens > 200000 maximum"}}"#,
+                ErrorCode::ContextLengthExceeded,
+            ),
+            // A routing failure isn't a missing model
+            (
+                404,
+                r#"{"error":{"code":404,"message":"No endpoints found for model x"}}"#,
+                ErrorCode::ProviderError,
+            ),
+            (502, "Bad Gateway", ErrorCode::ProviderError),
+        ];
+        for (status, body, code) in cases {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert_eq!(classify_rejection(status, body), code, "{}", body);
+        }
+
+        let err = request_failed("OpenAI", StatusCode::TOO_MANY_REQUESTS, "slow down");
+        let info = err.info();
+        assert_eq!(info.code, ErrorCode::RateLimited);
+        assert!(info.retryable);
+        assert_eq!(info.provider.as_deref(), Some("OpenAI"));
+        assert!(info.message.contains("slow down"), "{}", info.message);
+    }
+
+    #[tokio::test]
+    async fn test_chat_reports_missing_api_key() {
+        let client = LlmClient::new(AgentConfig {
+            provider: LlmProvider::Claude,
+            api_key: String::new(),
+            ..AgentConfig::default()
+        });
+        let err = client
+            .chat(&[Message::user("hello")], None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::MissingApiKey);
+        assert_eq!(err.provider(), Some("Claude"));
+    }
+}
@@ -0,0 +1,431 @@
+//! Ollama chat provider.
+//!
+//! Ollama 0.4+ accepts OpenAI-style tool schemas for models that support them
+//! (llama3.1, qwen2.5, mistral-nemo, ...). Models without tool support reject the
+//! `tools` field; [`LlmClient`](super::LlmClient) then retries without tools.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::{parse_failed, request_failed, ChatProvider, LlmResponse, PreparedRequest};
+use crate::agent::ollama;
+use crate::agent::types::{
+    AgentConfig, AgentError, FunctionCall, Message, MessageRole, Tool, ToolCall, Usage,
+};
+
+// ============================================================================
+// Ollama Types
+// ============================================================================
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+    /// Same shape as OpenAI's function tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OllamaToolCall>>,
+    /// Name of the tool a `tool` message answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+}
+
+impl OllamaMessage {
+    fn text(role: &str, content: Option<&String>) -> Self {
+        OllamaMessage {
+            role: role.to_string(),
+            content: content.cloned().unwrap_or_default(),
+            tool_calls: None,
+            tool_name: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaToolCall {
+    /// Only sent by newer Ollama versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    function: OllamaFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    /// A JSON object, not an encoded string as in OpenAI's format
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+    num_predict: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    message: OllamaResponseMessage,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    eval_count: Option<u32>,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponseMessage {
+    #[allow(dead_code)]
+    role: String,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+// ============================================================================
+// Ollama Provider
+// ============================================================================
+
+/// A local Ollama server's `/api/chat` endpoint
+#[derive(Debug, Clone, Copy)]
+pub struct OllamaProvider;
+
+impl ChatProvider for OllamaProvider {
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    fn requires_api_key(&self) -> bool {
+        false
+    }
+
+    /// Local models can take minutes to load before the first token
+    fn default_request_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn endpoint(&self, config: &AgentConfig) -> String {
+        format!("{}/api/chat", config.effective_base_url())
+    }
+
+    fn headers(&self, _config: &AgentConfig) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    fn build_request(
+        &self,
+        config: &AgentConfig,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+    ) -> Result<PreparedRequest, AgentError> {
+        let tools = tools.filter(|t| !t.is_empty());
+        let ollama_messages = if tools.is_some() {
+            convert_messages(messages)
+        } else {
+            // Chat-only: flatten to simple role/content and drop tool traffic
+            messages
+                .iter()
+                .filter_map(|m| {
+                    let role = match m.role {
+                        MessageRole::Developer | MessageRole::System => "system",
+                        MessageRole::User => "user",
+                        MessageRole::Assistant => "assistant",
+                        MessageRole::Tool => return None,
+                    };
+                    Some(OllamaMessage::text(role, m.content.as_ref()))
+                })
+                .collect()
+        };
+
+        let request = OllamaRequest {
+            model: config.model.clone(),
+            messages: ollama_messages,
+            stream: false,
+            options: Some(OllamaOptions {
+                temperature: config.temperature,
+                num_predict: config.max_tokens,
+            }),
+            tools: tools.map(<[Tool]>::to_vec),
+        };
+
+        let body = serde_json::to_value(&request)
+            .map_err(|e| AgentError::LlmError(format!("Failed to encode Ollama request: {}", e)))?;
+
+        Ok(PreparedRequest::new(body))
+    }
+
+    fn parse_response(
+        &self,
+        body: &str,
+        _request: &PreparedRequest,
+    ) -> Result<LlmResponse, AgentError> {
+        let ollama_response: OllamaResponse =
+            serde_json::from_str(body).map_err(|e| parse_failed(self.name(), e))?;
+
+        let tool_calls = ollama_response
+            .message
+            .tool_calls
+            .into_iter()
+            .map(|tc| ToolCall {
+                // Older Ollama versions don't assign call IDs
+                id: tc
+                    .id
+                    .unwrap_or_else(|| format!("call_{}", uuid::Uuid::new_v4().simple())),
+                call_type: "function".to_string(),
+                function: FunctionCall {
+                    name: tc.function.name,
+                    arguments: match tc.function.arguments {
+                        Value::String(encoded) => encoded,
+                        Value::Null => "{}".to_string(),
+                        args => args.to_string(),
+                    },
+                },
+            })
+            .collect();
+
+        let usage = match (
+            ollama_response.prompt_eval_count,
+            ollama_response.eval_count,
+        ) {
+            (Some(prompt), Some(completion)) => Some(Usage {
+                prompt_tokens: prompt,
+                completion_tokens: completion,
+                total_tokens: prompt + completion,
+            }),
+            _ => None,
+        };
+
+        Ok(LlmResponse {
+            content: Some(ollama_response.message.content).filter(|c| !c.is_empty()),
+            tool_calls,
+            usage,
+            finish_reason: if ollama_response.done {
+                Some("stop".to_string())
+            } else {
+                None
+            },
+            warnings: Vec::new(),
+            reasoning: None,
+            thinking: Vec::new(),
+            served_model: None,
+        })
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str, config: &AgentConfig) -> AgentError {
+        if ollama::is_model_not_found(status, body) {
+            return ollama::model_not_found_error(&config.model);
+        }
+        request_failed(self.name(), status, body)
+    }
+
+    fn tools_unsupported(&self, status: StatusCode, body: &str) -> bool {
+        ollama::is_tools_unsupported(status, body)
+    }
+}
+
+/// Convert the conversation, keeping tool calls and results
+fn convert_messages(messages: &[Message]) -> Vec<OllamaMessage> {
+    // Ollama matches results to calls by tool name rather than call ID
+    let mut call_names: HashMap<&str, &str> = HashMap::new();
+
+    messages
+        .iter()
+        .map(|m| match m.role {
+            MessageRole::Developer | MessageRole::System => {
+                OllamaMessage::text("system", m.content.as_ref())
+            }
+            MessageRole::User => OllamaMessage::text("user", m.content.as_ref()),
+            MessageRole::Assistant => {
+                let mut message = OllamaMessage::text("assistant", m.content.as_ref());
+                if let Some(calls) = m.tool_calls.as_ref().filter(|c| !c.is_empty()) {
+                    for tc in calls {
+                        call_names.insert(tc.id.as_str(), tc.function.name.as_str());
+                    }
+                    message.tool_calls = Some(
+                        calls
+                            .iter()
+                            .map(|tc| OllamaToolCall {
+                                id: None,
+                                function: OllamaFunctionCall {
+                                    name: tc.function.name.clone(),
+                                    arguments: serde_json::from_str(&tc.function.arguments)
+                                        .unwrap_or(serde_json::json!({})),
+                                },
+                            })
+                            .collect(),
+                    );
+                }
+                message
+            }
+            MessageRole::Tool => {
+                let mut message = OllamaMessage::text("tool", m.content.as_ref());
+                message.tool_name = m
+                    .tool_call_id
+                    .as_deref()
+                    .and_then(|id| call_names.get(id))
+                    .map(|name| name.to_string());
+                message
+            }
+        })
+        .collect()
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ollama_request_serialization() {
+        let request = OllamaRequest {
+            model: "llama3.2".to_string(),
+            messages: vec![OllamaMessage::text("user", Some(&"Hello".to_string()))],
+            stream: false,
+            options: Some(OllamaOptions {
+                temperature: 0.7,
+                num_predict: 1000,
+            }),
+            tools: None,
+        };
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("llama3.2"));
+        assert!(json.contains("Hello"));
+        assert!(json.contains("\"stream\":false"));
+        assert!(!json.contains("tools"));
+    }
+
+    #[test]
+    fn test_ollama_response_parsing() {
+        let json = r#"{
+            "message": {
+                "role": "assistant",
+                "content": "Hello!"
+            },
+            "done": true,
+            "eval_count": 5,
+            "prompt_eval_count": 10
+        }"#;
+
+        let response: OllamaResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.message.content, "Hello!");
+        assert!(response.done);
+    }
+
+    fn tool_conversation() -> Vec<Message> {
+        vec![
+            Message::system("You are helpful"),
+            Message::user("Read notes.md"),
+            Message::assistant_with_tools(
+                None,
+                vec![ToolCall {
+                    id: "call_1".to_string(),
+                    call_type: "function".to_string(),
+                    function: FunctionCall {
+                        name: "read_file".to_string(),
+                        arguments: "{\"path\":\"notes.md\"}".to_string(),
+                    },
+                }],
+            ),
+            Message::tool_result("call_1", "Chapter one"),
+        ]
+    }
+
+    fn config() -> AgentConfig {
+        AgentConfig {
+            provider: crate::agent::types::LlmProvider::Ollama,
+            model: "llama3.1".to_string(),
+            ..AgentConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_ollama_request_with_tools() {
+        let tools = vec![Tool::new(
+            "read_file",
+            "Read a file",
+            crate::agent::types::JsonSchema {
+                schema_type: "object".to_string(),
+                properties: None,
+                required: None,
+            },
+        )];
+
+        let request = OllamaProvider
+            .build_request(&config(), &tool_conversation(), Some(&tools))
+            .unwrap();
+        let body = request.body;
+
+        assert_eq!(body["tools"][0]["type"], "function");
+        assert_eq!(body["tools"][0]["function"]["name"], "read_file");
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 4);
+        // Arguments go over the wire as an object, not an encoded string
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"]["path"],
+            "notes.md"
+        );
+        assert_eq!(messages[3]["role"], "tool");
+        assert_eq!(messages[3]["tool_name"], "read_file");
+        assert_eq!(messages[3]["content"], "Chapter one");
+    }
+
+    #[test]
+    fn test_ollama_request_without_tools_drops_tool_traffic() {
+        let request = OllamaProvider
+            .build_request(&config(), &tool_conversation(), None)
+            .unwrap();
+        let body = request.body;
+
+        assert!(body.get("tools").is_none());
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|m| m.get("tool_calls").is_none()));
+    }
+
+    #[test]
+    fn test_ollama_tool_call_parsing() {
+        let json = r#"{
+            "message": {
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [
+                    {"function": {"name": "read_file", "arguments": {"path": "notes.md"}}},
+                    {"id": "call_abc", "function": {"name": "list_dir", "arguments": {}}}
+                ]
+            },
+            "done": true
+        }"#;
+
+        let request = PreparedRequest::new(Value::Null);
+        let response = OllamaProvider.parse_response(json, &request).unwrap();
+
+        assert_eq!(response.tool_calls.len(), 2);
+        let first = &response.tool_calls[0];
+        assert!(first.id.starts_with("call_"));
+        assert_eq!(first.function.name, "read_file");
+        let args: Value = serde_json::from_str(&first.function.arguments).unwrap();
+        assert_eq!(args["path"], "notes.md");
+        assert_eq!(response.tool_calls[1].id, "call_abc");
+        assert_eq!(response.tool_calls[1].function.arguments, "{}");
+    }
+}
@@ -0,0 +1,13 @@
+//! OpenAI chat completions provider.
+
+use super::openai_compat::OpenAiCompatible;
+
+/// OpenAI's API, which accepts the `developer` role used for GPT-5+ system prompts
+pub fn provider() -> OpenAiCompatible {
+    OpenAiCompatible {
+        name: "OpenAI",
+        developer_role: "developer",
+        extra_headers: &[],
+        routing: false,
+    }
+}
@@ -0,0 +1,1353 @@
+//! Shared plumbing for OpenAI-compatible chat completion APIs.
+//!
+//! OpenAI, OpenRouter, and other `/chat/completions` endpoints (Groq, local
+//! OpenAI-compatible servers) share one wire format. They differ only in display
+//! name, how the developer role is spelled, extra headers, and whether
+//! OpenRouter's routing fields apply, which [`OpenAiCompatible`] captures so each
+//! provider module is a few lines.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::rate_limits::{openai_rate_limits, RateLimitSnapshot};
+use super::sse::SseEvent;
+use super::{
+    parse_failed, rejected, request_failed, ChatProvider, LlmResponse, PreparedRequest,
+    StreamAccumulator, StreamDelta,
+};
+use crate::agent::text::truncate_to_char_boundary;
+use crate::agent::types::{
+    AgentConfig, AgentError, FunctionCall, Message, MessageRole, OpenRouterRouting, ServedModel,
+    Tool, ToolCall, Usage,
+};
+
+// ============================================================================
+// OpenAI Types
+// ============================================================================
+
+#[derive(Debug, Serialize)]
+struct OpenAiRequest {
+    model: String,
+    messages: Vec<OpenAiMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<OpenAiTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    /// Used by most models (gpt-4o, gpt-4o-mini, gpt-4-turbo, etc.)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    /// Used by o-series models (o1, o1-mini, o3-mini, etc.)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    /// low, medium, or high; o-series and GPT-5 models only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiMessage {
+    role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<OpenAiToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpenAiTool {
+    #[serde(rename = "type")]
+    tool_type: String,
+    function: OpenAiFunction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct OpenAiFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAiToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    call_type: String,
+    function: OpenAiFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAiFunctionCall {
+    name: String,
+    arguments: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiResponse {
+    #[allow(dead_code)]
+    id: String,
+    choices: Vec<OpenAiChoice>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+    /// The model that answered (may differ from the one requested)
+    #[serde(default)]
+    model: Option<String>,
+    /// Upstream provider that served the request (OpenRouter only)
+    #[serde(default)]
+    provider: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiChoice {
+    #[allow(dead_code)]
+    index: u32,
+    message: OpenAiResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiResponseMessage {
+    #[allow(dead_code)]
+    role: String,
+    #[serde(default)]
+    content: Option<Value>,
+    #[serde(default)]
+    tool_calls: Option<Vec<OpenAiToolCall>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiError {
+    error: OpenAiErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiErrorDetail {
+    message: String,
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    error_type: Option<String>,
+    /// A string for OpenAI, the HTTP status as a number for OpenRouter
+    #[allow(dead_code)]
+    code: Option<Value>,
+    #[serde(default)]
+    metadata: Option<OpenAiErrorMetadata>,
+}
+
+/// Extra detail OpenRouter attaches to moderation and upstream provider errors
+#[derive(Debug, Deserialize)]
+struct OpenAiErrorMetadata {
+    /// Moderation categories the input was flagged for
+    #[serde(default)]
+    reasons: Vec<String>,
+    /// The part of the input that was flagged
+    #[serde(default)]
+    flagged_input: Option<String>,
+    /// Upstream provider that produced the error
+    #[serde(default)]
+    provider_name: Option<String>,
+    /// The upstream provider's own error body
+    #[serde(default)]
+    raw: Option<Value>,
+}
+
+/// Longest flagged input or upstream error quoted in an error message
+const ERROR_EXCERPT_BYTES: usize = 300;
+
+impl OpenAiErrorDetail {
+    /// The error message plus whatever metadata explains it
+    fn describe(&self) -> String {
+        let mut message = self.message.clone();
+        let Some(meta) = &self.metadata else {
+            return message;
+        };
+        if !meta.reasons.is_empty() {
+            message.push_str(&format!(
+                "; input flagged by moderation for: {}",
+                meta.reasons.join(", ")
+            ));
+        }
+        if let Some(input) = meta.flagged_input.as_deref().filter(|s| !s.is_empty()) {
+            message.push_str(&format!(
+                "; flagged text: \"{}\"",
+                truncate_to_char_boundary(input, ERROR_EXCERPT_BYTES)
+            ));
+        }
+        if let Some(provider) = &meta.provider_name {
+            message.push_str(&format!("; upstream provider: {}", provider));
+        }
+        let raw = match &meta.raw {
+            Some(Value::String(raw)) => raw.clone(),
+            Some(Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        if !raw.is_empty() {
+            message.push_str(&format!(
+                "; provider said: {}",
+                truncate_to_char_boundary(&raw, ERROR_EXCERPT_BYTES)
+            ));
+        }
+        message
+    }
+}
+
+/// One `chat.completion.chunk` from a streamed response
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamChunk {
+    #[serde(default)]
+    choices: Vec<OpenAiStreamChoice>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    provider: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiStreamChoice {
+    #[serde(default)]
+    delta: OpenAiStreamDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct OpenAiStreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<OpenAiToolCallDelta>>,
+}
+
+/// A fragment of a tool call; `index` ties fragments of the same call together
+#[derive(Debug, Deserialize)]
+struct OpenAiToolCallDelta {
+    index: u32,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<OpenAiFunctionCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiFunctionCallDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+fn openai_content_to_text(content: Option<Value>) -> Option<String> {
+    match content {
+        Some(Value::String(text)) => Some(text),
+        Some(Value::Array(parts)) => {
+            let mut combined = String::new();
+            for part in parts {
+                if let Value::Object(map) = part {
+                    if let Some(Value::String(text)) = map.get("text") {
+                        combined.push_str(text);
+                    }
+                }
+            }
+            if combined.is_empty() {
+                None
+            } else {
+                Some(combined)
+            }
+        }
+        _ => None,
+    }
+}
+
+const OPENAI_TOOL_NAME_MAX_LEN: usize = 64;
+
+fn is_openai_tool_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_openai_tool_name_valid(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= OPENAI_TOOL_NAME_MAX_LEN
+        && name.chars().all(is_openai_tool_name_char)
+}
+
+fn fnv1a64(input: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET;
+    for b in input.as_bytes() {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn sanitize_openai_tool_name(original: &str) -> String {
+    let mut out = String::with_capacity(original.len());
+    for c in original.chars() {
+        if is_openai_tool_name_char(c) {
+            out.push(c);
+        } else {
+            out.push('_');
+        }
+    }
+    if out.is_empty() {
+        out.push_str("tool");
+    }
+    out
+}
+
+fn openai_safe_tool_name(original: &str, attempt: u32) -> String {
+    if attempt == 0 && is_openai_tool_name_valid(original) {
+        return original.to_string();
+    }
+
+    let sanitized = sanitize_openai_tool_name(original);
+    let salt = if attempt == 0 {
+        original.to_string()
+    } else {
+        format!("{}#{}", original, attempt)
+    };
+    let hash = fnv1a64(&salt);
+    let suffix = format!("__{:016x}", hash);
+
+    // Keep within OpenAI max tool name length.
+    let max_base = OPENAI_TOOL_NAME_MAX_LEN.saturating_sub(suffix.len());
+    let mut base = sanitized;
+    if base.len() > max_base {
+        base.truncate(max_base);
+    }
+
+    let candidate = format!("{}{}", base, suffix);
+    debug_assert!(is_openai_tool_name_valid(&candidate));
+    candidate
+}
+
+fn openai_tool_name_maps(
+    tools: Option<&[Tool]>,
+) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut original_to_openai: HashMap<String, String> = HashMap::new();
+    let mut openai_to_original: HashMap<String, String> = HashMap::new();
+    let mut used: HashSet<String> = HashSet::new();
+
+    let Some(ts) = tools else {
+        return (original_to_openai, openai_to_original);
+    };
+
+    for tool in ts {
+        let original = tool.function.name.clone();
+
+        // Generate a valid, unique OpenAI tool name (OpenAI rejects names containing ':' and other chars).
+        let mut attempt: u32 = 0;
+        let openai_name = loop {
+            let candidate = openai_safe_tool_name(&original, attempt);
+            if used.insert(candidate.clone()) {
+                break candidate;
+            }
+            attempt = attempt.saturating_add(1);
+        };
+
+        original_to_openai.insert(original.clone(), openai_name.clone());
+        openai_to_original.insert(openai_name, original);
+    }
+
+    (original_to_openai, openai_to_original)
+}
+
+/// Returns true if the model is an o-series reasoning model (o1, o3, o4, etc.)
+fn is_o_series_model(model: &str) -> bool {
+    let base = model.rsplit('/').next().unwrap_or(model);
+    // o-series reasoning models: o1, o1-mini, o1-pro, o3, o3-mini, o4-mini, etc.
+    // Match "o" followed by a digit at the start
+    let chars: Vec<char> = base.chars().collect();
+    chars.len() >= 2 && chars[0] == 'o' && chars[1].is_ascii_digit()
+}
+
+/// Returns true if the model is a GPT-5 series model.
+/// GPT-5 models have different parameter requirements (no max_tokens, no temperature).
+fn is_gpt5_model(model: &str) -> bool {
+    let base = model.rsplit('/').next().unwrap_or(model);
+    // GPT-5 series: gpt-5, gpt-5-mini, gpt-5-nano, gpt-5.1, gpt-5.2, gpt-5.2-pro, etc.
+    base.starts_with("gpt-5")
+}
+
+/// Returns true if the model supports temperature parameter.
+/// O-series and GPT-5 models do not support temperature.
+fn supports_temperature(model: &str) -> bool {
+    !is_o_series_model(model) && !is_gpt5_model(model)
+}
+
+/// Returns true if the model uses max_completion_tokens instead of max_tokens.
+/// O-series and GPT-5 models require max_completion_tokens.
+fn uses_max_completion_tokens(model: &str) -> bool {
+    is_o_series_model(model) || is_gpt5_model(model)
+}
+
+/// Returns true if the model accepts reasoning_effort (o-series and GPT-5).
+/// Other models reject the parameter.
+fn supports_reasoning_effort(model: &str) -> bool {
+    is_o_series_model(model) || is_gpt5_model(model)
+}
+
+/// Whether routing settings can leave a model with no provider to serve it
+fn restricts_providers(routing: &OpenRouterRouting) -> bool {
+    (!routing.order.is_empty() && routing.allow_fallbacks == Some(false))
+        || !routing.only.is_empty()
+        || !routing.ignore.is_empty()
+        || routing.require_parameters == Some(true)
+        || routing.data_collection.as_deref() == Some("deny")
+}
+
+/// The reported model and provider, if the response named a model
+fn served_model(model: Option<String>, provider: Option<String>) -> Option<ServedModel> {
+    model
+        .filter(|m| !m.is_empty())
+        .map(|model| ServedModel { model, provider })
+}
+
+// ============================================================================
+// OpenAI-Compatible Provider
+// ============================================================================
+
+/// A provider speaking the OpenAI chat completions format
+#[derive(Debug, Clone)]
+pub struct OpenAiCompatible {
+    /// Display name used in logs and error messages
+    pub(super) name: &'static str,
+    /// Role sent for `MessageRole::Developer` ("developer" where supported, else "system")
+    pub(super) developer_role: &'static str,
+    /// Headers sent with every request in addition to the bearer token
+    pub(super) extra_headers: &'static [(&'static str, &'static str)],
+    /// Send `AgentConfig::openrouter_routing` with requests
+    pub(super) routing: bool,
+}
+
+impl OpenAiCompatible {
+    fn role(&self, role: MessageRole) -> &'static str {
+        match role {
+            MessageRole::Developer => self.developer_role,
+            MessageRole::System => "system",
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            MessageRole::Tool => "tool",
+        }
+    }
+}
+
+impl ChatProvider for OpenAiCompatible {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn endpoint(&self, config: &AgentConfig) -> String {
+        format!("{}/chat/completions", config.effective_base_url())
+    }
+
+    fn headers(&self, config: &AgentConfig) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("Authorization", format!("Bearer {}", config.api_key))];
+        headers.extend(
+            self.extra_headers
+                .iter()
+                .map(|(header, value)| (*header, value.to_string())),
+        );
+        headers
+    }
+
+    fn rate_limits(
+        &self,
+        headers: &reqwest::header::HeaderMap,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Option<RateLimitSnapshot> {
+        openai_rate_limits(headers, now)
+    }
+
+    fn build_request(
+        &self,
+        config: &AgentConfig,
+        messages: &[Message],
+        tools: Option<&[Tool]>,
+    ) -> Result<PreparedRequest, AgentError> {
+        let (tool_name_to_openai, openai_to_tool_name) = openai_tool_name_maps(tools);
+
+        // Convert messages to OpenAI format
+        let openai_messages: Vec<OpenAiMessage> = messages
+            .iter()
+            .map(|m| OpenAiMessage {
+                role: self.role(m.role).to_string(),
+                content: m.content.clone(),
+                tool_calls: m.tool_calls.as_ref().map(|tcs| {
+                    tcs.iter()
+                        .map(|tc| OpenAiToolCall {
+                            id: tc.id.clone(),
+                            call_type: "function".to_string(),
+                            function: OpenAiFunctionCall {
+                                name: tool_name_to_openai
+                                    .get(&tc.function.name)
+                                    .cloned()
+                                    .unwrap_or_else(|| openai_safe_tool_name(&tc.function.name, 0)),
+                                arguments: tc.function.arguments.clone(),
+                            },
+                        })
+                        .collect()
+                }),
+                tool_call_id: m.tool_call_id.clone(),
+            })
+            .collect();
+
+        // Convert tools to OpenAI format
+        let openai_tools: Option<Vec<OpenAiTool>> = tools.map(|ts| {
+            ts.iter()
+                .map(|t| OpenAiTool {
+                    tool_type: "function".to_string(),
+                    function: OpenAiFunction {
+                        name: tool_name_to_openai
+                            .get(&t.function.name)
+                            .cloned()
+                            .unwrap_or_else(|| openai_safe_tool_name(&t.function.name, 0)),
+                        description: t.function.description.clone(),
+                        parameters: serde_json::to_value(&t.function.parameters)
+                            .unwrap_or(serde_json::json!({})),
+                    },
+                })
+                .collect()
+        });
+
+        // Determine which max tokens parameter to use based on model
+        let (max_tokens, max_completion_tokens) = if uses_max_completion_tokens(&config.model) {
+            (None, Some(config.max_tokens))
+        } else {
+            (Some(config.max_tokens), None)
+        };
+
+        let request = OpenAiRequest {
+            model: config.model.clone(),
+            messages: openai_messages,
+            tool_choice: openai_tools.as_ref().map(|_| "auto".to_string()),
+            tools: openai_tools,
+            temperature: if supports_temperature(&config.model) {
+                Some(config.temperature)
+            } else {
+                None
+            },
+            max_tokens,
+            max_completion_tokens,
+            reasoning_effort: config
+                .reasoning_effort
+                .clone()
+                .filter(|_| supports_reasoning_effort(&config.model)),
+        };
+
+        let mut body = serde_json::to_value(&request).map_err(|e| {
+            AgentError::LlmError(format!("Failed to encode {} request: {}", self.name, e))
+        })?;
+        if let Some(routing) = config.openrouter_routing.as_ref().filter(|_| self.routing) {
+            if let Some(preferences) = routing.provider_preferences() {
+                body["provider"] = preferences;
+            }
+            if !routing.fallback_models.is_empty() {
+                body["models"] = serde_json::json!(routing.fallback_models);
+            }
+        }
+
+        Ok(PreparedRequest {
+            body,
+            tool_names: openai_to_tool_name,
+        })
+    }
+
+    fn parse_response(
+        &self,
+        body: &str,
+        request: &PreparedRequest,
+    ) -> Result<LlmResponse, AgentError> {
+        let openai_response: OpenAiResponse =
+            serde_json::from_str(body).map_err(|e| parse_failed(self.name, e))?;
+        let served_model = served_model(openai_response.model, openai_response.provider);
+
+        let choice =
+            openai_response.choices.into_iter().next().ok_or_else(|| {
+                AgentError::LlmError(format!("No choices in {} response", self.name))
+            })?;
+
+        // Convert tool calls
+        let tool_calls = choice
+            .message
+            .tool_calls
+            .unwrap_or_default()
+            .into_iter()
+            .map(|tc| {
+                let OpenAiToolCall {
+                    id,
+                    call_type,
+                    function: OpenAiFunctionCall { name, arguments },
+                } = tc;
+
+                ToolCall {
+                    id,
+                    call_type,
+                    function: FunctionCall {
+                        name: request.original_tool_name(name),
+                        arguments,
+                    },
+                }
+            })
+            .collect();
+
+        let usage = openai_response.usage.map(|u| Usage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+        });
+
+        Ok(LlmResponse {
+            content: openai_content_to_text(choice.message.content),
+            tool_calls,
+            usage,
+            finish_reason: choice.finish_reason,
+            warnings: Vec::new(),
+            reasoning: None,
+            thinking: Vec::new(),
+            served_model,
+        })
+    }
+
+    fn parse_error(&self, status: StatusCode, body: &str, config: &AgentConfig) -> AgentError {
+        let Ok(api_error) = serde_json::from_str::<OpenAiError>(body) else {
+            return request_failed(self.name, status, body);
+        };
+        let mut message = format!(
+            "{} API error ({}): {}",
+            self.name,
+            status,
+            api_error.error.describe()
+        );
+        let lower = api_error.error.message.to_lowercase();
+        let no_provider = status == StatusCode::NOT_FOUND
+            || lower.contains("no endpoints")
+            || lower.contains("no allowed providers");
+        if no_provider
+            && self.routing
+            && config
+                .openrouter_routing
+                .as_ref()
+                .is_some_and(restricts_providers)
+        {
+            message.push_str(
+                " (the openrouter_routing settings may exclude every provider serving this model)",
+            );
+        }
+        rejected(self.name, status, body, message)
+    }
+
+    fn start_stream(&self, body: &mut Value) -> Option<Box<dyn StreamAccumulator>> {
+        body["stream"] = Value::Bool(true);
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+        Some(Box::new(OpenAiStream::new(self.name)))
+    }
+}
+
+// ============================================================================
+// Streaming
+// ============================================================================
+
+/// A tool call being assembled from stream fragments
+#[derive(Debug, Default)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Accumulates `chat.completion.chunk` events into a response
+struct OpenAiStream {
+    name: &'static str,
+    content: String,
+    /// Keyed by the delta's `index`; arguments arrive split across many chunks
+    tool_calls: BTreeMap<u32, PartialToolCall>,
+    usage: Option<Usage>,
+    finish_reason: Option<String>,
+    model: Option<String>,
+    provider: Option<String>,
+}
+
+impl OpenAiStream {
+    fn new(name: &'static str) -> Self {
+        OpenAiStream {
+            name,
+            content: String::new(),
+            tool_calls: BTreeMap::new(),
+            usage: None,
+            finish_reason: None,
+            model: None,
+            provider: None,
+        }
+    }
+}
+
+impl StreamAccumulator for OpenAiStream {
+    fn on_event(&mut self, event: &SseEvent) -> Result<Option<StreamDelta>, AgentError> {
+        if event.data == "[DONE]" {
+            return Ok(None);
+        }
+        if let Ok(api_error) = serde_json::from_str::<OpenAiError>(&event.data) {
+            return Err(AgentError::LlmError(format!(
+                "{} API error: {}",
+                self.name,
+                api_error.error.describe()
+            )));
+        }
+
+        let chunk: OpenAiStreamChunk =
+            serde_json::from_str(&event.data).map_err(|e| parse_failed(self.name, e))?;
+
+        if chunk.model.is_some() {
+            self.model = chunk.model;
+        }
+        if chunk.provider.is_some() {
+            self.provider = chunk.provider;
+        }
+
+        if let Some(u) = chunk.usage {
+            self.usage = Some(Usage {
+                prompt_tokens: u.prompt_tokens,
+                completion_tokens: u.completion_tokens,
+                total_tokens: u.total_tokens,
+            });
+        }
+
+        let Some(choice) = chunk.choices.into_iter().next() else {
+            return Ok(None);
+        };
+        if choice.finish_reason.is_some() {
+            self.finish_reason = choice.finish_reason;
+        }
+        for delta in choice.delta.tool_calls.unwrap_or_default() {
+            let call = self.tool_calls.entry(delta.index).or_default();
+            if let Some(id) = delta.id {
+                call.id = id;
+            }
+            if let Some(function) = delta.function {
+                if let Some(name) = function.name {
+                    call.name.push_str(&name);
+                }
+                if let Some(arguments) = function.arguments {
+                    call.arguments.push_str(&arguments);
+                }
+            }
+        }
+
+        match choice.delta.content {
+            Some(text) if !text.is_empty() => {
+                self.content.push_str(&text);
+                Ok(Some(StreamDelta::Text(text)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn finish(self: Box<Self>, request: &PreparedRequest) -> Result<LlmResponse, AgentError> {
+        let tool_calls = self
+            .tool_calls
+            .into_values()
+            .map(|call| ToolCall {
+                id: call.id,
+                call_type: "function".to_string(),
+                function: FunctionCall {
+                    name: request.original_tool_name(call.name),
+                    arguments: call.arguments,
+                },
+            })
+            .collect();
+
+        Ok(LlmResponse {
+            content: if self.content.is_empty() {
+                None
+            } else {
+                Some(self.content)
+            },
+            tool_calls,
+            usage: self.usage,
+            finish_reason: self.finish_reason,
+            warnings: Vec::new(),
+            reasoning: None,
+            thinking: Vec::new(),
+            served_model: served_model(self.model, self.provider),
+        })
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openai_request_serialization() {
+        let request = OpenAiRequest {
+            model: "gpt-4o-mini".to_string(),
+            messages: vec![OpenAiMessage {
+                role: "user".to_string(),
+                content: Some("Hello".to_string()),
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: Some(0.7),
+            max_tokens: Some(1000),
+            max_completion_tokens: None,
+            reasoning_effort: None,
+        };
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("gpt-4o-mini"));
+        assert!(json.contains("Hello"));
+        assert!(!json.contains("tools")); // tools should be omitted when None
+        assert!(json.contains("max_tokens"));
+        assert!(!json.contains("max_completion_tokens")); // should be omitted when None
+    }
+
+    #[test]
+    fn test_openai_request_o_series_serialization() {
+        let request = OpenAiRequest {
+            model: "o1-mini".to_string(),
+            messages: vec![OpenAiMessage {
+                role: "user".to_string(),
+                content: Some("Hello".to_string()),
+                tool_calls: None,
+                tool_call_id: None,
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: None, // o-series doesn't support temperature
+            max_tokens: None,
+            max_completion_tokens: Some(1000),
+            reasoning_effort: None,
+        };
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(json.contains("o1-mini"));
+        assert!(!json.contains("\"max_tokens\"")); // should be omitted when None
+        assert!(json.contains("max_completion_tokens"));
+    }
+
+    #[test]
+    fn test_is_o_series_model() {
+        // o-series reasoning models
+        assert!(is_o_series_model("o1"));
+        assert!(is_o_series_model("o1-mini"));
+        assert!(is_o_series_model("o1-preview"));
+        assert!(is_o_series_model("o1-pro"));
+        assert!(is_o_series_model("o3"));
+        assert!(is_o_series_model("o3-mini"));
+        assert!(is_o_series_model("o4-mini"));
+        assert!(is_o_series_model("openai/o1-mini")); // with provider prefix
+        assert!(is_o_series_model("openai/o3-mini"));
+        assert!(is_o_series_model("openai/o4-mini"));
+
+        // GPT models are NOT o-series
+        assert!(!is_o_series_model("gpt-4o"));
+        assert!(!is_o_series_model("gpt-4o-mini"));
+        assert!(!is_o_series_model("gpt-5"));
+        assert!(!is_o_series_model("gpt-5-mini"));
+    }
+
+    #[test]
+    fn test_is_gpt5_model() {
+        // GPT-5 series models
+        assert!(is_gpt5_model("gpt-5"));
+        assert!(is_gpt5_model("gpt-5-mini"));
+        assert!(is_gpt5_model("gpt-5-nano"));
+        assert!(is_gpt5_model("gpt-5.1"));
+        assert!(is_gpt5_model("gpt-5.2"));
+        assert!(is_gpt5_model("gpt-5.2-pro"));
+        assert!(is_gpt5_model("gpt-5.2-chat-latest"));
+        assert!(is_gpt5_model("gpt-5.2-codex"));
+        assert!(is_gpt5_model("openai/gpt-5-mini")); // with provider prefix
+
+        // NOT GPT-5 models
+        assert!(!is_gpt5_model("gpt-4o"));
+        assert!(!is_gpt5_model("gpt-4o-mini"));
+        assert!(!is_gpt5_model("gpt-4.1-mini"));
+        assert!(!is_gpt5_model("o1-mini"));
+        assert!(!is_gpt5_model("o3-mini"));
+    }
+
+    #[test]
+    fn test_uses_max_completion_tokens() {
+        // O-series models use max_completion_tokens
+        assert!(uses_max_completion_tokens("o1"));
+        assert!(uses_max_completion_tokens("o1-mini"));
+        assert!(uses_max_completion_tokens("o3-mini"));
+        assert!(uses_max_completion_tokens("o4-mini"));
+        assert!(uses_max_completion_tokens("openai/o1-mini"));
+
+        // GPT-5 models also use max_completion_tokens
+        assert!(uses_max_completion_tokens("gpt-5"));
+        assert!(uses_max_completion_tokens("gpt-5-mini"));
+        assert!(uses_max_completion_tokens("gpt-5.2"));
+        assert!(uses_max_completion_tokens("gpt-5.2-pro"));
+
+        // GPT-4 models use max_tokens (NOT max_completion_tokens)
+        assert!(!uses_max_completion_tokens("gpt-4o"));
+        assert!(!uses_max_completion_tokens("gpt-4o-mini"));
+        assert!(!uses_max_completion_tokens("gpt-4.1-mini"));
+    }
+
+    #[test]
+    fn test_supports_temperature() {
+        // GPT-4 models support temperature
+        assert!(supports_temperature("gpt-4o"));
+        assert!(supports_temperature("gpt-4o-mini"));
+        assert!(supports_temperature("gpt-4.1-mini"));
+        assert!(supports_temperature("gpt-3.5-turbo"));
+
+        // O-series models do NOT support temperature
+        assert!(!supports_temperature("o1"));
+        assert!(!supports_temperature("o1-mini"));
+        assert!(!supports_temperature("o3-mini"));
+        assert!(!supports_temperature("o4-mini"));
+
+        // GPT-5 models do NOT support temperature
+        assert!(!supports_temperature("gpt-5"));
+        assert!(!supports_temperature("gpt-5-mini"));
+        assert!(!supports_temperature("gpt-5.2"));
+        assert!(!supports_temperature("gpt-5.2-pro"));
+    }
+
+    #[test]
+    fn test_reasoning_effort_only_for_reasoning_models() {
+        let provider = super::super::openai::provider();
+        let messages = [Message::user("Hi")];
+        let body = |model: &str| {
+            let config = AgentConfig {
+                model: model.to_string(),
+                reasoning_effort: Some("high".to_string()),
+                ..AgentConfig::default()
+            };
+            provider
+                .build_request(&config, &messages, None)
+                .unwrap()
+                .body
+        };
+
+        assert_eq!(body("gpt-5-mini")["reasoning_effort"], "high");
+        assert_eq!(body("o3-mini")["reasoning_effort"], "high");
+        assert_eq!(body("openai/o4-mini")["reasoning_effort"], "high");
+        assert!(body("gpt-4.1").get("reasoning_effort").is_none());
+        assert!(body("gpt-4o-mini").get("reasoning_effort").is_none());
+
+        // Unset by default
+        let config = AgentConfig::default();
+        let body = provider
+            .build_request(&config, &messages, None)
+            .unwrap()
+            .body;
+        assert!(body.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn test_openai_response_parsing() {
+        let json = r#"{
+            "id": "chatcmpl-123",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "Hello!"
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": {
+                "prompt_tokens": 10,
+                "completion_tokens": 5,
+                "total_tokens": 15
+            }
+        }"#;
+
+        let response: OpenAiResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.choices.len(), 1);
+        assert_eq!(
+            openai_content_to_text(response.choices[0].message.content.clone()),
+            Some("Hello!".to_string())
+        );
+        assert!(response.usage.is_some());
+    }
+
+    #[test]
+    fn test_openai_response_parsing_content_parts() {
+        let json = r#"{
+            "id": "chatcmpl-123",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": [
+                        {"type": "text", "text": "Hello"},
+                        {"type": "text", "text": " world!"}
+                    ]
+                },
+                "finish_reason": "stop"
+            }]
+        }"#;
+
+        let response: OpenAiResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            openai_content_to_text(response.choices[0].message.content.clone()),
+            Some("Hello world!".to_string())
+        );
+    }
+
+    #[test]
+    fn test_openai_tool_call_parsing() {
+        let json = r#"{
+            "id": "chatcmpl-123",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_abc123",
+                        "type": "function",
+                        "function": {
+                            "name": "read_file",
+                            "arguments": "{\"path\": \"test.txt\"}"
+                        }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+
+        let response: OpenAiResponse = serde_json::from_str(json).unwrap();
+        let tool_calls = response.choices[0].message.tool_calls.as_ref().unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].function.name, "read_file");
+    }
+
+    #[test]
+    fn test_openai_error_parsing() {
+        let json = r#"{
+            "error": {
+                "message": "Invalid API key",
+                "type": "invalid_request_error",
+                "code": "invalid_api_key"
+            }
+        }"#;
+
+        let error: OpenAiError = serde_json::from_str(json).unwrap();
+        assert_eq!(error.error.message, "Invalid API key");
+    }
+
+    #[test]
+    fn test_openrouter_error_metadata() {
+        let provider = super::super::openrouter::provider();
+        let config = AgentConfig::default();
+
+        let moderation = r#"{"error":{"code":403,"message":"Your chosen model requires moderation and your input was flagged","metadata":{"reasons":["violence","harassment"],"flagged_input":"the duel scene","provider_name":"OpenAI","model_slug":"openai/gpt-4o"}}}"#;
+        let err = provider
+            .parse_error(StatusCode::FORBIDDEN, moderation, &config)
+            .to_string();
+        assert!(
+            err.contains("OpenRouter API error (403 Forbidden)"),
+            "{}",
+            err
+        );
+        assert!(err.contains("flagged by moderation for: violence, harassment"));
+        assert!(err.contains("\"the duel scene\""));
+        assert!(err.contains("upstream provider: OpenAI"));
+
+        let upstream = r#"{"error":{"code":502,"message":"Provider returned error","metadata":{"provider_name":"Together","raw":"context length exceeded"}}}"#;
+        let err = provider
+            .parse_error(StatusCode::BAD_GATEWAY, upstream, &config)
+            .to_string();
+        assert!(
+            err.contains("provider said: context length exceeded"),
+            "{}",
+            err
+        );
+
+        // Restrictive routing gets a hint when no provider could take the request
+        let no_endpoints =
+            r#"{"error":{"code":404,"message":"No endpoints found matching your data policy"}}"#;
+        let routed = AgentConfig {
+            openrouter_routing: Some(OpenRouterRouting {
+                only: vec!["Anthropic".to_string()],
+                ..Default::default()
+            }),
+            ..AgentConfig::default()
+        };
+        let err = provider
+            .parse_error(StatusCode::NOT_FOUND, no_endpoints, &routed)
+            .to_string();
+        assert!(err.contains("openrouter_routing"), "{}", err);
+        let err = provider
+            .parse_error(StatusCode::NOT_FOUND, no_endpoints, &config)
+            .to_string();
+        assert!(!err.contains("openrouter_routing"), "{}", err);
+    }
+
+    #[test]
+    fn test_openrouter_routing_in_request_body() {
+        let config = AgentConfig {
+            model: "anthropic/claude-sonnet-4".to_string(),
+            openrouter_routing: Some(OpenRouterRouting {
+                order: vec!["Anthropic".to_string(), "Amazon Bedrock".to_string()],
+                allow_fallbacks: Some(false),
+                sort: Some("throughput".to_string()),
+                fallback_models: vec!["openai/gpt-4.1".to_string()],
+                ..Default::default()
+            }),
+            ..AgentConfig::default()
+        };
+        let messages = [Message::user("Hi")];
+
+        let body = super::super::openrouter::provider()
+            .build_request(&config, &messages, None)
+            .unwrap()
+            .body;
+        assert_eq!(
+            body["provider"],
+            serde_json::json!({
+                "order": ["Anthropic", "Amazon Bedrock"],
+                "allow_fallbacks": false,
+                "sort": "throughput",
+            })
+        );
+        assert_eq!(body["models"], serde_json::json!(["openai/gpt-4.1"]));
+
+        // Other OpenAI-compatible providers would reject the unknown fields
+        let body = super::super::openai::provider()
+            .build_request(&config, &messages, None)
+            .unwrap()
+            .body;
+        assert!(body.get("provider").is_none());
+        assert!(body.get("models").is_none());
+    }
+
+    #[test]
+    fn test_served_model_is_captured() {
+        let provider = super::super::openrouter::provider();
+        let request = PreparedRequest::new(serde_json::json!({}));
+        let body = r#"{
+            "id": "gen-1",
+            "model": "anthropic/claude-3.5-haiku",
+            "provider": "Amazon Bedrock",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}]
+        }"#;
+        let response = provider.parse_response(body, &request).unwrap();
+        assert_eq!(
+            response.served_model,
+            Some(ServedModel {
+                model: "anthropic/claude-3.5-haiku".to_string(),
+                provider: Some("Amazon Bedrock".to_string()),
+            })
+        );
+
+        let stream = stream_events(
+            &provider,
+            &[
+                r#"{"model":"openai/gpt-4.1","provider":"Azure","choices":[{"index":0,"delta":{"content":"Hi"}}]}"#,
+                "[DONE]",
+            ],
+        );
+        let served = stream.finish(&request).unwrap().served_model.unwrap();
+        assert_eq!(served.model, "openai/gpt-4.1");
+        assert_eq!(served.provider.as_deref(), Some("Azure"));
+    }
+
+    #[test]
+    fn test_openai_tool_name_sanitization() {
+        let original = "test-ext:greet";
+        let safe = openai_safe_tool_name(original, 0);
+        assert!(is_openai_tool_name_valid(&safe));
+        assert_ne!(safe, original);
+        assert!(safe.contains("test-ext_greet"));
+        assert!(safe.len() <= OPENAI_TOOL_NAME_MAX_LEN);
+    }
+
+    #[test]
+    fn test_openai_tool_name_maps_roundtrip() {
+        let tools = vec![
+            Tool::new(
+                "read_file",
+                "Read file",
+                crate::agent::types::JsonSchema {
+                    schema_type: "object".to_string(),
+                    properties: None,
+                    required: None,
+                },
+            ),
+            Tool::new(
+                "my-ext:do thing",
+                "Does a thing",
+                crate::agent::types::JsonSchema {
+                    schema_type: "object".to_string(),
+                    properties: None,
+                    required: None,
+                },
+            ),
+        ];
+
+        let (to_openai, to_original) = openai_tool_name_maps(Some(&tools));
+
+        let read_safe = to_openai.get("read_file").unwrap();
+        assert_eq!(read_safe, "read_file");
+
+        let ext_original = "my-ext:do thing".to_string();
+        let ext_safe = to_openai.get(&ext_original).unwrap();
+        assert!(is_openai_tool_name_valid(ext_safe));
+        assert_eq!(to_original.get(ext_safe).unwrap(), &ext_original);
+    }
+
+    #[test]
+    fn test_parse_response_restores_tool_names() {
+        let provider = OpenAiCompatible {
+            name: "OpenAI",
+            developer_role: "developer",
+            extra_headers: &[],
+            routing: false,
+        };
+        let mut request = PreparedRequest::new(serde_json::json!({}));
+        request
+            .tool_names
+            .insert("my-ext_greet__abc".to_string(), "my-ext:greet".to_string());
+
+        let body = r#"{
+            "id": "chatcmpl-123",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "my-ext_greet__abc", "arguments": "{}"}
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }]
+        }"#;
+
+        let response = provider.parse_response(body, &request).unwrap();
+        assert_eq!(response.tool_calls[0].function.name, "my-ext:greet");
+        assert_eq!(response.finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    fn stream_events(provider: &OpenAiCompatible, lines: &[&str]) -> Box<dyn StreamAccumulator> {
+        let mut body = serde_json::json!({});
+        let mut stream = provider.start_stream(&mut body).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        for line in lines {
+            let event = SseEvent {
+                data: line.to_string(),
+            };
+            stream.on_event(&event).unwrap();
+        }
+        stream
+    }
+
+    #[test]
+    fn test_stream_accumulates_text_and_usage() {
+        let provider = super::super::openai::provider();
+        let mut body = serde_json::json!({});
+        let mut stream = provider.start_stream(&mut body).unwrap();
+
+        let mut deltas = Vec::new();
+        for data in [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo!"},"finish_reason":"stop"}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":7,"completion_tokens":2,"total_tokens":9}}"#,
+            "[DONE]",
+        ] {
+            let event = SseEvent {
+                data: data.to_string(),
+            };
+            deltas.extend(stream.on_event(&event).unwrap());
+        }
+        assert_eq!(
+            deltas,
+            [
+                StreamDelta::Text("Hel".to_string()),
+                StreamDelta::Text("lo!".to_string())
+            ]
+        );
+
+        let response = stream
+            .finish(&PreparedRequest::new(serde_json::json!({})))
+            .unwrap();
+        assert_eq!(response.content.as_deref(), Some("Hello!"));
+        assert!(response.tool_calls.is_empty());
+        assert_eq!(response.usage.unwrap().total_tokens, 9);
+        assert_eq!(response.finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn test_stream_accumulates_split_tool_calls() {
+        let provider = super::super::openai::provider();
+        let stream = stream_events(
+            &provider,
+            &[
+                r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_a","type":"function","function":{"name":"my-ext_greet__abc","arguments":""}}]}}]}"#,
+                r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"na"}}]}}]}"#,
+                r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"call_b","type":"function","function":{"name":"read_file","arguments":"{\"path\":"}}]}}]}"#,
+                r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"me\":\"Ada\"}"}}]}}]}"#,
+                r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"function":{"arguments":"\"a.md\"}"}}]},"finish_reason":"tool_calls"}]}"#,
+                "[DONE]",
+            ],
+        );
+
+        let mut request = PreparedRequest::new(serde_json::json!({}));
+        request
+            .tool_names
+            .insert("my-ext_greet__abc".to_string(), "my-ext:greet".to_string());
+        let response = stream.finish(&request).unwrap();
+
+        assert_eq!(response.content, None);
+        assert_eq!(response.tool_calls.len(), 2);
+        assert_eq!(response.tool_calls[0].id, "call_a");
+        assert_eq!(response.tool_calls[0].function.name, "my-ext:greet");
+        assert_eq!(
+            response.tool_calls[0].function.arguments,
+            r#"{"name":"Ada"}"#
+        );
+        assert_eq!(response.tool_calls[1].function.name, "read_file");
+        assert_eq!(
+            response.tool_calls[1].function.arguments,
+            r#"{"path":"a.md"}"#
+        );
+        assert_eq!(response.finish_reason.as_deref(), Some("tool_calls"));
+    }
+
+    #[test]
+    fn test_stream_error_event() {
+        let provider = super::super::openai::provider();
+        let mut body = serde_json::json!({});
+        let mut stream = provider.start_stream(&mut body).unwrap();
+        let event = SseEvent {
+            data: r#"{"error":{"message":"Rate limit reached","type":"requests"}}"#.to_string(),
+        };
+        let err = stream.on_event(&event).unwrap_err();
+        assert!(err.to_string().contains("Rate limit reached"));
+    }
+}
@@ -0,0 +1,21 @@
+//! OpenRouter provider (OpenAI-compatible with attribution headers and provider routing).
+
+use super::openai_compat::OpenAiCompatible;
+
+/// Headers OpenRouter uses to attribute traffic to the app
+const ATTRIBUTION_HEADERS: &[(&str, &str)] = &[
+    ("HTTP-Referer", "https://vswrite.app"),
+    ("X-Title", "VS Write"),
+];
+
+/// OpenRouter's API; developer messages are sent as `system` since not every
+/// routed model understands the `developer` role. Requests carry the configured
+/// provider routing preferences.
+pub fn provider() -> OpenAiCompatible {
+    OpenAiCompatible {
+        name: "OpenRouter",
+        developer_role: "system",
+        extra_headers: ATTRIBUTION_HEADERS,
+        routing: true,
+    }
+}
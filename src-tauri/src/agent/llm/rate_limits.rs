@@ -0,0 +1,374 @@
+//! What providers say about their rate limits, and pacing requests by it.
+//!
+//! OpenAI (`x-ratelimit-*`) and Anthropic (`anthropic-ratelimit-*`) report how
+//! many requests and tokens are left in the current window and when it resets.
+//! Every reply's headers update a [`RateLimitState`] shared by all runs, so
+//! concurrent runs see each other's usage. Before a request, a provider that is
+//! nearly out of either allowance is given until the reset (at most
+//! [`MAX_PACING_DELAY`]) instead of answering with a 429. Figures whose reset has
+//! passed are dropped, since the allowance has refilled since they were read.
+
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::agent::types::LlmProvider;
+
+/// Longest a request is held back waiting for a window to reset
+pub const MAX_PACING_DELAY: Duration = Duration::from_secs(60);
+
+/// How long figures without a reset time are trusted
+const UNTIMED_FIGURES_TTL: Duration = Duration::from_secs(60);
+
+/// An allowance is low at or below this share of its limit (1/20 = 5%)
+const LOW_SHARE_DIVISOR: u64 = 20;
+
+/// One allowance (requests or tokens) in the provider's current window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitWindow {
+    pub limit: Option<u64>,
+    pub remaining: u64,
+    /// When the allowance refills, if the provider said
+    pub resets_at: Option<DateTime<Utc>>,
+}
+
+impl RateLimitWindow {
+    /// Whether little enough is left that the next request should wait
+    fn is_low(&self) -> bool {
+        match self.limit {
+            Some(limit) => self.remaining.saturating_mul(LOW_SHARE_DIVISOR) <= limit,
+            None => self.remaining == 0,
+        }
+    }
+}
+
+/// A provider's rate limits as of its latest reply
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitSnapshot {
+    pub requests: Option<RateLimitWindow>,
+    pub tokens: Option<RateLimitWindow>,
+    /// When the headers were read
+    pub observed_at: DateTime<Utc>,
+}
+
+impl RateLimitSnapshot {
+    /// The snapshot without windows that have reset (or, lacking a reset time,
+    /// gone stale) by `now`; `None` if nothing is left
+    fn fresh(mut self, now: DateTime<Utc>) -> Option<Self> {
+        let stale_at =
+            self.observed_at + chrono::Duration::from_std(UNTIMED_FIGURES_TTL).unwrap_or_default();
+        let current = |window: &RateLimitWindow| window.resets_at.unwrap_or(stale_at) > now;
+        self.requests = self.requests.filter(current);
+        self.tokens = self.tokens.filter(current);
+        (self.requests.is_some() || self.tokens.is_some()).then_some(self)
+    }
+}
+
+/// How long to hold a request back, and why, when an allowance is nearly used
+/// up. Waits until the latest reset among the low windows, capped at
+/// [`MAX_PACING_DELAY`].
+pub fn pacing_delay(
+    snapshot: &RateLimitSnapshot,
+    now: DateTime<Utc>,
+) -> Option<(Duration, String)> {
+    let low = [("requests", snapshot.requests), ("tokens", snapshot.tokens)]
+        .into_iter()
+        .filter_map(|(what, window)| {
+            let window = window.filter(RateLimitWindow::is_low)?;
+            let wait = (window.resets_at? - now).to_std().ok()?;
+            Some((wait, what, window.remaining))
+        })
+        .max_by_key(|(wait, ..)| *wait)?;
+    let (wait, what, remaining) = low;
+    if wait.is_zero() {
+        return None;
+    }
+    let reason = format!("{} {} left until the rate limit resets", remaining, what);
+    Some((wait.min(MAX_PACING_DELAY), reason))
+}
+
+/// Latest rate limits per provider, shared by every run
+#[derive(Debug, Default)]
+pub struct RateLimitState {
+    providers: Mutex<HashMap<LlmProvider, RateLimitSnapshot>>,
+}
+
+/// Rate limit state shared across runs and with the UI
+pub type SharedRateLimits = Arc<RateLimitState>;
+
+impl RateLimitState {
+    fn providers(&self) -> MutexGuard<'_, HashMap<LlmProvider, RateLimitSnapshot>> {
+        self.providers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Remember what a reply from `provider` said
+    pub fn record(&self, provider: LlmProvider, snapshot: RateLimitSnapshot) {
+        self.providers().insert(provider, snapshot);
+    }
+
+    /// What is still current about `provider`'s limits at `now`
+    pub fn current(&self, provider: LlmProvider, now: DateTime<Utc>) -> Option<RateLimitSnapshot> {
+        let mut providers = self.providers();
+        let fresh = providers.get(&provider).cloned()?.fresh(now);
+        if fresh.is_none() {
+            providers.remove(&provider);
+        }
+        fresh
+    }
+
+    /// Every provider's current limits at `now`
+    pub fn all(&self, now: DateTime<Utc>) -> HashMap<LlmProvider, RateLimitSnapshot> {
+        let mut providers = self.providers();
+        let fresh: HashMap<_, _> = providers
+            .iter()
+            .filter_map(|(provider, snapshot)| Some((*provider, snapshot.clone().fresh(now)?)))
+            .collect();
+        providers.retain(|provider, _| fresh.contains_key(provider));
+        fresh
+    }
+}
+
+// ============================================================================
+// Header Parsing
+// ============================================================================
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok().map(str::trim)
+}
+
+fn header_count(headers: &HeaderMap, name: &str) -> Option<u64> {
+    header(headers, name)?.parse().ok()
+}
+
+/// A window from the headers `name` gives for `limit`, `remaining` and `reset`,
+/// reading the reset with `reset`
+fn window(
+    headers: &HeaderMap,
+    name: impl Fn(&str) -> String,
+    reset: impl Fn(&str) -> Option<DateTime<Utc>>,
+) -> Option<RateLimitWindow> {
+    Some(RateLimitWindow {
+        limit: header_count(headers, &name("limit")),
+        remaining: header_count(headers, &name("remaining"))?,
+        resets_at: header(headers, &name("reset")).and_then(&reset),
+    })
+}
+
+fn snapshot(
+    requests: Option<RateLimitWindow>,
+    tokens: Option<RateLimitWindow>,
+    now: DateTime<Utc>,
+) -> Option<RateLimitSnapshot> {
+    (requests.is_some() || tokens.is_some()).then_some(RateLimitSnapshot {
+        requests,
+        tokens,
+        observed_at: now,
+    })
+}
+
+/// OpenAI's `x-ratelimit-{limit,remaining,reset}-{requests,tokens}`, where the
+/// reset is a delay like `6m0s` or `20ms`
+pub fn openai_rate_limits(headers: &HeaderMap, now: DateTime<Utc>) -> Option<RateLimitSnapshot> {
+    let reset = |value: &str| Some(now + chrono::Duration::from_std(parse_delay(value)?).ok()?);
+    let requests = window(headers, |f| format!("x-ratelimit-{}-requests", f), reset);
+    let tokens = window(headers, |f| format!("x-ratelimit-{}-tokens", f), reset);
+    snapshot(requests, tokens, now)
+}
+
+/// Anthropic's `anthropic-ratelimit-{requests,tokens}-{limit,remaining,reset}`,
+/// where the reset is an RFC 3339 time. Input token limits stand in when the
+/// combined token headers are missing.
+pub fn anthropic_rate_limits(headers: &HeaderMap, now: DateTime<Utc>) -> Option<RateLimitSnapshot> {
+    let reset = |value: &str| {
+        DateTime::parse_from_rfc3339(value)
+            .ok()
+            .map(|at| at.with_timezone(&Utc))
+    };
+    let requests = window(
+        headers,
+        |f| format!("anthropic-ratelimit-requests-{}", f),
+        reset,
+    );
+    let tokens = window(
+        headers,
+        |f| format!("anthropic-ratelimit-tokens-{}", f),
+        reset,
+    )
+    .or_else(|| {
+        window(
+            headers,
+            |f| format!("anthropic-ratelimit-input-tokens-{}", f),
+            reset,
+        )
+    });
+    snapshot(requests, tokens, now)
+}
+
+/// Parse a delay in OpenAI's reset format: numbers with `h`, `m`, `s` or `ms`
+/// units, e.g. `1h2m3.5s` or `250ms`
+fn parse_delay(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .filter(|&i| i > 0)?;
+        let amount: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        total += amount * seconds;
+        rest = &rest[unit..];
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_735_689_600 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(parse_delay("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_delay("1h2m3.5s"),
+            Some(Duration::from_secs_f64(3723.5))
+        );
+        assert_eq!(parse_delay("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_delay("soon"), None);
+        assert_eq!(parse_delay("5"), None);
+        assert_eq!(parse_delay(""), None);
+    }
+
+    #[test]
+    fn test_parse_provider_headers() {
+        let now = at(0);
+        let openai = openai_rate_limits(
+            &headers(&[
+                ("x-ratelimit-limit-requests", "500"),
+                ("x-ratelimit-remaining-requests", "499"),
+                ("x-ratelimit-reset-requests", "120ms"),
+                ("x-ratelimit-limit-tokens", "30000"),
+                ("x-ratelimit-remaining-tokens", "1200"),
+                ("x-ratelimit-reset-tokens", "6s"),
+            ]),
+            now,
+        )
+        .unwrap();
+        assert_eq!(openai.requests.unwrap().remaining, 499);
+        let tokens = openai.tokens.unwrap();
+        assert_eq!(tokens.limit, Some(30000));
+        assert_eq!(tokens.resets_at, Some(at(6)));
+
+        let anthropic = anthropic_rate_limits(
+            &headers(&[
+                ("anthropic-ratelimit-requests-remaining", "0"),
+                ("anthropic-ratelimit-requests-reset", "2025-01-01T00:00:30Z"),
+                ("anthropic-ratelimit-input-tokens-remaining", "8000"),
+            ]),
+            now,
+        )
+        .unwrap();
+        let requests = anthropic.requests.unwrap();
+        assert_eq!((requests.limit, requests.remaining), (None, 0));
+        assert_eq!(requests.resets_at, Some(at(30)));
+        assert_eq!(anthropic.tokens.unwrap().remaining, 8000);
+
+        assert_eq!(openai_rate_limits(&HeaderMap::new(), now), None);
+        assert_eq!(
+            anthropic_rate_limits(&headers(&[("x-ratelimit-remaining-requests", "1")]), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pacing_waits_for_low_windows_to_reset() {
+        let window = |limit, remaining, reset| RateLimitWindow {
+            limit: Some(limit),
+            remaining,
+            resets_at: Some(at(reset)),
+        };
+        let mut snapshot = RateLimitSnapshot {
+            requests: Some(window(500, 400, 1)),
+            tokens: Some(window(30000, 20000, 6)),
+            observed_at: at(0),
+        };
+        assert_eq!(pacing_delay(&snapshot, at(0)), None);
+
+        snapshot.tokens = Some(window(30000, 1000, 6));
+        let (delay, reason) = pacing_delay(&snapshot, at(0)).unwrap();
+        assert_eq!(delay, Duration::from_secs(6));
+        assert!(reason.contains("1000 tokens"));
+
+        // Capped, and not at all once the window has reset
+        snapshot.tokens = Some(window(30000, 0, 600));
+        assert_eq!(pacing_delay(&snapshot, at(0)).unwrap().0, MAX_PACING_DELAY);
+        assert_eq!(pacing_delay(&snapshot, at(600)), None);
+    }
+
+    #[test]
+    fn test_state_drops_figures_once_they_reset() {
+        let state = RateLimitState::default();
+        state.record(
+            LlmProvider::OpenAI,
+            RateLimitSnapshot {
+                requests: Some(RateLimitWindow {
+                    limit: None,
+                    remaining: 3,
+                    resets_at: Some(at(10)),
+                }),
+                tokens: Some(RateLimitWindow {
+                    limit: None,
+                    remaining: 100,
+                    resets_at: None,
+                }),
+                observed_at: at(0),
+            },
+        );
+
+        let current = state.current(LlmProvider::OpenAI, at(5)).unwrap();
+        assert!(current.requests.is_some() && current.tokens.is_some());
+        let current = state.current(LlmProvider::OpenAI, at(30)).unwrap();
+        assert_eq!(current.requests, None);
+        assert!(current.tokens.is_some());
+        assert!(state.all(at(30)).contains_key(&LlmProvider::OpenAI));
+
+        // Figures without a reset time expire on their own
+        assert_eq!(state.current(LlmProvider::OpenAI, at(61)), None);
+        assert!(state.all(at(61)).is_empty());
+        assert_eq!(state.current(LlmProvider::Claude, at(0)), None);
+    }
+}
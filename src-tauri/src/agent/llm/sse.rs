@@ -0,0 +1,129 @@
+//! Server-sent events decoding for streamed chat responses.
+//!
+//! Bytes arrive in arbitrary chunks, so a line (or a UTF-8 character) may be split
+//! across several of them. [`SseDecoder`] buffers until a full line is available
+//! and emits an [`SseEvent`] at each blank line.
+
+/// One server-sent event. The `event:` field isn't kept, since Claude (the one
+/// provider that sends it) repeats the event type in the data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// The `data:` lines, joined with newlines
+    pub data: String,
+}
+
+/// Incremental SSE parser
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feed the next chunk of the response body, returning any events it completed
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(event) = self.process_line(line.trim_end_matches(['\n', '\r'])) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flush an event left open when the stream ended without a trailing blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            if let Some(event) = self.process_line(line.trim_end_matches('\r')) {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // Comment (keep-alive)
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        if field == "data" {
+            self.data.push(value.to_string());
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        if self.data.is_empty() {
+            return None;
+        }
+        Some(SseEvent {
+            data: std::mem::take(&mut self.data).join("\n"),
+        })
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_events_split_across_chunks() {
+        let stream = "event: message_start\r\ndata: {\"a\":1}\r\n\r\n: ping\n\ndata: first\ndata: second\n\n";
+        let bytes = stream.as_bytes();
+
+        // Feed one byte at a time to exercise every split point
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        for b in bytes {
+            events.extend(decoder.feed(std::slice::from_ref(b)));
+        }
+        assert_eq!(decoder.finish(), None);
+
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    data: "{\"a\":1}".to_string(),
+                },
+                SseEvent {
+                    data: "first\nsecond".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multibyte_character_split_across_chunks() {
+        let bytes = "data: café\n\n".as_bytes();
+        let split = bytes.len() - 3; // inside the two-byte 'é'
+
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(&bytes[..split]).is_empty());
+        let events = decoder.feed(&bytes[split..]);
+        assert_eq!(events[0].data, "café");
+    }
+
+    #[test]
+    fn test_finish_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.feed(b"data: [DONE]").is_empty());
+        assert_eq!(decoder.finish().unwrap().data, "[DONE]");
+    }
+}
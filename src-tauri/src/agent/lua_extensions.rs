@@ -7,9 +7,44 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-use super::lua_runtime::{call_function, create_lua_runtime, LuaContext};
-use super::types::{JsonSchema, Tool};
+use super::extension_stats::{ExecutionKind, ExtensionStats};
+use super::hook_directives::{run_directives, DirectiveOutcome};
+use super::lua_runtime::{
+    call_function, create_lua_runtime, has_function, load_script, LuaContext, LuaLimits,
+    LUA_INSTRUCTION_BUDGET_EXCEEDED, LUA_OUT_OF_MEMORY,
+};
+use super::signature::{verify_extension, SignaturePolicy, SignatureVerification};
+use super::tools::TOOL_CANCELLED;
+use super::trust_store::{SharedTrustStore, TrustStore};
+use super::types::{JsonSchema, Tool, ToolRisk};
+
+/// Wall-clock limit for an extension tool call when the manifest sets none
+pub const DEFAULT_EXTENSION_TOOL_TIMEOUT_SECS: u64 = 30;
+
+/// Upper bound on a manifest's `timeoutSeconds`
+pub const MAX_EXTENSION_TOOL_TIMEOUT_SECS: u64 = 120;
+
+/// Prefix of the error returned when an extension tool is killed for running too long
+pub const EXTENSION_TOOL_TIMED_OUT: &str = "Extension tool timed out";
+
+/// Prefix of the error a lifecycle hook fails with when stopped for running too long
+pub const EXTENSION_HOOK_TIMED_OUT: &str = "Extension hook timed out";
+
+/// Lua memory limit when the manifest sets none
+pub const DEFAULT_EXTENSION_MEMORY_LIMIT_MB: u64 = 64;
+
+/// Upper bound on a manifest's `memoryLimitMb`
+pub const MAX_EXTENSION_MEMORY_LIMIT_MB: u64 = 512;
+
+/// Lua instruction budget per call when the manifest sets none
+pub const DEFAULT_EXTENSION_INSTRUCTION_LIMIT: u64 = 200_000_000;
+
+/// Upper bound on a manifest's `instructionLimit`
+pub const MAX_EXTENSION_INSTRUCTION_LIMIT: u64 = 2_000_000_000;
 
 // ============================================================================
 // Lifecycle Hook Types
@@ -42,23 +77,32 @@ impl LifecycleHook {
 }
 
 /// Lifecycle configuration in manifest
+///
+/// Disabled hooks are omitted when serializing, matching how manifests are written.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LifecycleConfig {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_activate: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_deactivate: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_project_open: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_project_close: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_section_save: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_section_delete: bool,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "is_false")]
     pub on_entity_change: bool,
+    /// Fields not modelled above, preserved on serialization
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 impl LifecycleConfig {
@@ -81,6 +125,30 @@ pub struct HookResult {
     pub success: bool,
     pub result: Option<String>,
     pub error: Option<String>,
+    /// What became of the `directives` the hook returned, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directives: Vec<DirectiveOutcome>,
+}
+
+impl HookResult {
+    fn failure(error: String) -> Self {
+        HookResult {
+            success: false,
+            result: None,
+            error: Some(error),
+            directives: Vec::new(),
+        }
+    }
+
+    /// A hook that didn't run, with the reason
+    fn not_run(reason: String) -> Self {
+        HookResult {
+            success: true,
+            result: None,
+            error: Some(reason),
+            directives: Vec::new(),
+        }
+    }
 }
 
 // ============================================================================
@@ -88,18 +156,45 @@ pub struct HookResult {
 // ============================================================================
 
 /// Extension manifest (manifest.json in extension directory)
+///
+/// Fields the backend doesn't use (author, license, icon, signing fields, ...) are kept
+/// in `extras` so a manifest survives a parse/serialize round trip intact.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExtensionManifest {
     pub id: String,
     pub name: String,
     pub version: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     #[serde(default)]
     pub tools: Vec<LuaToolDefinition>,
     /// Lifecycle hooks configuration
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub lifecycle: Option<LifecycleConfig>,
+    /// Wall-clock limit for each tool call, capped at `MAX_EXTENSION_TOOL_TIMEOUT_SECS`
+    #[serde(
+        rename = "timeoutSeconds",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timeout_seconds: Option<u64>,
+    /// Lua memory limit in megabytes, capped at `MAX_EXTENSION_MEMORY_LIMIT_MB`
+    #[serde(
+        rename = "memoryLimitMb",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub memory_limit_mb: Option<u64>,
+    /// Lua instructions per call, capped at `MAX_EXTENSION_INSTRUCTION_LIMIT`
+    #[serde(
+        rename = "instructionLimit",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub instruction_limit: Option<u64>,
+    /// Fields not modelled above, preserved on serialization
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Tool definition within an extension
@@ -109,26 +204,175 @@ pub struct LuaToolDefinition {
     pub description: String,
     /// Path to Lua script file (relative to extension directory)
     #[serde(rename = "luaScript")]
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub lua_script: Option<String>,
     /// Function name to call in the script
     #[serde(rename = "luaFunction")]
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub lua_function: Option<String>,
     /// Path to Python module (legacy, for dual support)
     #[serde(rename = "pythonModule")]
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub python_module: Option<String>,
     /// Python function name (legacy)
     #[serde(rename = "pythonFunction")]
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub python_function: Option<String>,
     /// JSON schema for tool parameters
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub parameters: Option<serde_json::Value>,
     /// Alternative schema field name
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub schema: Option<serde_json::Value>,
+    /// Declared risk level (`low`, `medium`, `high`). Only extensions signed by a
+    /// trusted publisher may declare `low`; undeclared tools are `high`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<ToolRisk>,
+    /// Fields not modelled above, preserved on serialization
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+// ============================================================================
+// Manifest Linting
+// ============================================================================
+
+/// Top-level manifest fields, including metadata the backend passes through untouched
+const MANIFEST_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "version",
+    "description",
+    "tools",
+    "lifecycle",
+    "timeoutSeconds",
+    "memoryLimitMb",
+    "instructionLimit",
+    "author",
+    "license",
+    "homepage",
+    "repository",
+    "categories",
+    "keywords",
+    "icon",
+    "permissions",
+    "signature",
+    "signatureAlgorithm",
+    "publicKeyId",
+    "publicKey",
+    "files",
+];
+
+/// Fields of a tool definition
+const TOOL_FIELDS: &[&str] = &[
+    "name",
+    "description",
+    "luaScript",
+    "luaFunction",
+    "pythonModule",
+    "pythonFunction",
+    "parameters",
+    "schema",
+    "risk",
+];
+
+/// Fields of the lifecycle section
+const LIFECYCLE_FIELDS: &[&str] = &[
+    "onActivate",
+    "onDeactivate",
+    "onProjectOpen",
+    "onProjectClose",
+    "onSectionSave",
+    "onSectionDelete",
+    "onEntityChange",
+    "hooksScript",
+];
+
+/// Check a raw manifest for fields that look like misspellings of known ones
+/// (e.g. `luascript`, `lua_script`, `licence`).
+///
+/// Unknown fields are legal and preserved, so this only reports near-misses that
+/// would otherwise be silently ignored. Returns one human-readable warning per field.
+pub fn lint_manifest(manifest: &serde_json::Value) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    lint_fields(manifest, MANIFEST_FIELDS, "manifest", &mut warnings);
+
+    if let Some(tools) = manifest.get("tools").and_then(|t| t.as_array()) {
+        for (i, tool) in tools.iter().enumerate() {
+            lint_fields(tool, TOOL_FIELDS, &format!("tools[{}]", i), &mut warnings);
+        }
+    }
+
+    if let Some(lifecycle) = manifest.get("lifecycle") {
+        lint_fields(lifecycle, LIFECYCLE_FIELDS, "lifecycle", &mut warnings);
+    }
+
+    warnings
+}
+
+fn lint_fields(value: &serde_json::Value, known: &[&str], location: &str, out: &mut Vec<String>) {
+    let Some(obj) = value.as_object() else {
+        return;
+    };
+
+    for key in obj.keys() {
+        if known.contains(&key.as_str()) {
+            continue;
+        }
+        if let Some(suggestion) = closest_field(key, known) {
+            out.push(format!(
+                "{}: unknown field \"{}\" (did you mean \"{}\"?)",
+                location, key, suggestion
+            ));
+        }
+    }
+}
+
+/// Find the known field an unknown key was probably meant to be
+fn closest_field<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    let normalized = normalize_field(key);
+
+    // Same field with different casing or separators: luascript, lua_script, LUA-SCRIPT
+    if let Some(exact) = known.iter().find(|k| normalize_field(k) == normalized) {
+        return Some(exact);
+    }
+
+    // Small typos; short keys are too ambiguous to guess at
+    if normalized.len() < 4 {
+        return None;
+    }
+    known
+        .iter()
+        .map(|k| (k, edit_distance(&normalize_field(k), &normalized)))
+        .filter(|(_, d)| *d <= 2)
+        .min_by_key(|(_, d)| *d)
+        .map(|(k, _)| *k)
+}
+
+fn normalize_field(key: &str) -> String {
+    key.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            current[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+
+    prev[b.len()]
 }
 
 /// A loaded extension with its tools and hooks
@@ -139,13 +383,100 @@ pub struct LoadedExtension {
     pub directory: PathBuf,
     pub scripts: HashMap<String, String>, // tool_name -> script content
     pub hooks_script: Option<String>,     // hooks.lua content if present
+    /// Signature check of the manifest, done at load time
+    pub signature: SignatureVerification,
+}
+
+/// Everything the extensions panel shows about one loaded extension
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionDetails {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub directory: PathBuf,
+    /// Manifest signature status, checked when the extension was loaded
+    pub signature: SignatureVerification,
+    /// Enabled lifecycle hooks, by function name
+    pub hooks: Vec<String>,
+    /// Tools with a Lua implementation
+    pub tools: Vec<ExtensionToolDetails>,
+}
+
+/// One extension tool, as the agent sees it
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtensionToolDetails {
+    /// Name the agent calls it by (`extension_id:tool`)
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the parameters, as declared in the manifest
+    pub parameters: serde_json::Value,
+    /// Risk the tool runs at (undeclared is high; untrusted extensions can't declare low)
+    pub risk: ToolRisk,
+    /// Risk the manifest declared
+    pub declared_risk: Option<ToolRisk>,
+}
+
+impl LoadedExtension {
+    /// Lua resource limits from the manifest, clamped to the registry maximums
+    fn limits(&self) -> LuaLimits {
+        let memory_mb = self
+            .manifest
+            .memory_limit_mb
+            .unwrap_or(DEFAULT_EXTENSION_MEMORY_LIMIT_MB)
+            .clamp(1, MAX_EXTENSION_MEMORY_LIMIT_MB);
+        let instructions = self
+            .manifest
+            .instruction_limit
+            .unwrap_or(DEFAULT_EXTENSION_INSTRUCTION_LIMIT)
+            .clamp(1, MAX_EXTENSION_INSTRUCTION_LIMIT);
+        LuaLimits {
+            memory_bytes: Some(memory_mb as usize * 1024 * 1024),
+            instructions: Some(instructions),
+        }
+    }
+}
+
+/// Risk an extension tool runs at: what it declares (high if nothing), but never
+/// below medium unless its extension is trusted
+fn effective_risk(declared: Option<ToolRisk>, trusted: bool) -> ToolRisk {
+    match declared {
+        None => ToolRisk::High,
+        Some(risk) if trusted => risk,
+        Some(risk) => risk.max(ToolRisk::Medium),
+    }
+}
+
+/// Replace a Lua error caused by a resource limit with one naming the extension
+/// and the limit it hit
+fn describe_limit_error(extension_id: &str, limits: &LuaLimits, error: String) -> String {
+    if error.contains(LUA_OUT_OF_MEMORY) {
+        let mb = limits.memory_bytes.unwrap_or_default() / (1024 * 1024);
+        format!(
+            "Extension '{}' exceeded its memory limit of {} MB",
+            extension_id, mb
+        )
+    } else if error.contains(LUA_INSTRUCTION_BUDGET_EXCEEDED) {
+        format!(
+            "Extension '{}' exceeded its instruction limit of {}",
+            extension_id,
+            limits.instructions.unwrap_or_default()
+        )
+    } else {
+        error
+    }
 }
 
 /// Registry of loaded extensions and their tools
+///
+/// Clones (such as the snapshot an agent run takes) share the execution stats.
 #[derive(Debug, Clone)]
 pub struct ExtensionRegistry {
     extensions: HashMap<String, LoadedExtension>,
     tool_to_extension: HashMap<String, String>, // tool_name -> extension_id
+    stats: Arc<ExtensionStats>,
+    signature_policy: SignaturePolicy,
+    trust_store: SharedTrustStore,
 }
 
 impl ExtensionRegistry {
@@ -153,9 +484,37 @@ impl ExtensionRegistry {
         ExtensionRegistry {
             extensions: HashMap::new(),
             tool_to_extension: HashMap::new(),
+            stats: Arc::new(ExtensionStats::new()),
+            signature_policy: SignaturePolicy::default(),
+            trust_store: Arc::new(TrustStore::default()),
         }
     }
 
+    /// Verify signatures against `trust_store` instead of the built-in keys alone
+    pub fn with_trust_store(mut self, trust_store: SharedTrustStore) -> Self {
+        self.trust_store = trust_store;
+        self
+    }
+
+    /// Publisher keys signatures are verified against
+    pub fn trust_store(&self) -> &TrustStore {
+        &self.trust_store
+    }
+
+    /// Signature policy applied to extensions loaded from now on
+    pub fn signature_policy(&self) -> SignaturePolicy {
+        self.signature_policy
+    }
+
+    pub fn set_signature_policy(&mut self, policy: SignaturePolicy) {
+        self.signature_policy = policy;
+    }
+
+    /// Execution stats for the loaded extensions
+    pub fn stats(&self) -> &ExtensionStats {
+        &self.stats
+    }
+
     /// Load an extension from a directory
     pub fn load_extension(&mut self, extension_dir: &Path) -> Result<(), String> {
         let manifest_path = extension_dir.join("manifest.json");
@@ -170,8 +529,31 @@ impl ExtensionRegistry {
         let manifest_content = fs::read_to_string(&manifest_path)
             .map_err(|e| format!("Failed to read manifest: {}", e))?;
 
-        let manifest: ExtensionManifest = serde_json::from_str(&manifest_content)
+        let raw_manifest: serde_json::Value = serde_json::from_str(&manifest_content)
+            .map_err(|e| format!("Failed to parse manifest: {}", e))?;
+        for warning in lint_manifest(&raw_manifest) {
+            log::warn!("{}: {}", manifest_path.display(), warning);
+        }
+
+        let signature = verify_extension(extension_dir, &raw_manifest, &self.trust_store);
+        let trusted = signature.is_verified();
+
+        let manifest: ExtensionManifest = serde_json::from_value(raw_manifest)
             .map_err(|e| format!("Failed to parse manifest: {}", e))?;
+        self.signature_policy.check(&manifest.id, &signature)?;
+        if !trusted {
+            for tool in manifest
+                .tools
+                .iter()
+                .filter(|t| t.risk == Some(ToolRisk::Low))
+            {
+                log::warn!(
+                    "Extension '{}' is not from a trusted publisher; tool '{}' runs as medium risk, not low",
+                    manifest.id,
+                    tool.name
+                );
+            }
+        }
 
         // Load all Lua scripts for tools
         let mut scripts = HashMap::new();
@@ -227,6 +609,7 @@ impl ExtensionRegistry {
             directory: extension_dir.to_path_buf(),
             scripts,
             hooks_script,
+            signature,
         };
 
         self.extensions.insert(manifest.id.clone(), loaded);
@@ -249,6 +632,7 @@ impl ExtensionRegistry {
                 let full_name = format!("{}:{}", extension_id, tool.name);
                 self.tool_to_extension.remove(&full_name);
             }
+            self.stats.remove(extension_id);
             Ok(())
         } else {
             Err(format!("Extension '{}' not found", extension_id))
@@ -305,6 +689,7 @@ impl ExtensionRegistry {
         args: &serde_json::Value,
         workspace: &Path,
         shell_timeout: u64,
+        cancel: Option<&CancellationToken>,
     ) -> Result<String, String> {
         // Parse tool name (format: "extension_id:tool_name")
         let parts: Vec<&str> = tool_name.splitn(2, ':').collect();
@@ -344,16 +729,89 @@ impl ExtensionRegistry {
             .map(|s| s.as_str())
             .unwrap_or(local_tool_name);
 
-        // Create Lua runtime
-        let ctx = LuaContext::new(workspace, shell_timeout);
-        let lua =
-            create_lua_runtime(&ctx).map_err(|e| format!("Failed to create Lua runtime: {}", e))?;
+        // Create Lua runtime and execute the tool function
+        let started = Instant::now();
+        let limits = extension.limits();
+        let ctx = LuaContext::new(workspace, shell_timeout)
+            .with_cancel(cancel.cloned())
+            .with_limits(limits);
+        let result = create_lua_runtime(&ctx)
+            .map_err(|e| format!("Failed to create Lua runtime: {}", e))
+            .and_then(|lua| call_function(&lua, script, function_name, args.clone()))
+            .map_err(|e| describe_limit_error(ext_id, &limits, e));
+        self.stats.record(
+            ext_id,
+            ExecutionKind::Tool,
+            local_tool_name,
+            started.elapsed(),
+            result.as_ref().err().map(|e| e.as_str()),
+        );
+        result
+    }
+
+    /// Wall-clock limit for calls to `tool_name`, from its extension's manifest
+    pub fn tool_timeout(&self, tool_name: &str) -> Duration {
+        let ext_id = tool_name.split_once(':').map_or("", |(ext_id, _)| ext_id);
+        self.extension_timeout(ext_id)
+    }
+
+    /// Wall-clock limit from an extension's manifest, also applied to its hooks
+    fn extension_timeout(&self, extension_id: &str) -> Duration {
+        let secs = self
+            .extensions
+            .get(extension_id)
+            .and_then(|ext| ext.manifest.timeout_seconds)
+            .unwrap_or(DEFAULT_EXTENSION_TOOL_TIMEOUT_SECS)
+            .clamp(1, MAX_EXTENSION_TOOL_TIMEOUT_SECS);
+        Duration::from_secs(secs)
+    }
+
+    /// Execute an extension tool on the blocking thread pool.
+    ///
+    /// The script is interrupted when `cancel` fires or the extension's timeout
+    /// elapses; in both cases the result is abandoned without waiting for the
+    /// script to unwind. A timeout fails with an error starting with
+    /// `EXTENSION_TOOL_TIMED_OUT`.
+    pub async fn run_tool(
+        self: Arc<Self>,
+        tool_name: String,
+        args: serde_json::Value,
+        workspace: PathBuf,
+        shell_timeout: u64,
+        cancel: Option<CancellationToken>,
+    ) -> Result<String, String> {
+        let timeout = self.tool_timeout(&tool_name);
+        // Stops the script's instruction hook (and any shell it started) on cancel or timeout
+        let stop = cancel.map(|t| t.child_token()).unwrap_or_default();
+
+        let task = {
+            let stop = stop.clone();
+            let tool_name = tool_name.clone();
+            tokio::task::spawn_blocking(move || {
+                self.execute_tool(&tool_name, &args, &workspace, shell_timeout, Some(&stop))
+            })
+        };
 
-        // Execute the tool function
-        call_function(&lua, script, function_name, args.clone())
+        tokio::select! {
+            joined = task => {
+                joined.unwrap_or_else(|e| Err(format!("Extension tool '{}' failed: {}", tool_name, e)))
+            }
+            _ = stop.cancelled() => Err(TOOL_CANCELLED.to_string()),
+            _ = tokio::time::sleep(timeout) => {
+                stop.cancel();
+                log::warn!("Killed extension tool '{}' after {}s", tool_name, timeout.as_secs());
+                Err(format!(
+                    "{}: '{}' was stopped after {}s. Try smaller inputs or a different approach.",
+                    EXTENSION_TOOL_TIMED_OUT,
+                    tool_name,
+                    timeout.as_secs()
+                ))
+            }
+        }
     }
 
-    /// Execute a lifecycle hook for an extension
+    /// Execute a lifecycle hook for an extension, then carry out any directives
+    /// it returned (see [`hook_directives`](super::hook_directives))
     pub fn execute_hook(
         &self,
         extension_id: &str,
@@ -361,6 +819,7 @@ impl ExtensionRegistry {
         args: serde_json::Value,
         workspace: &Path,
         shell_timeout: u64,
+        cancel: Option<&CancellationToken>,
     ) -> Result<HookResult, String> {
         let extension = self
             .extensions
@@ -371,18 +830,15 @@ impl ExtensionRegistry {
         let lifecycle = extension.manifest.lifecycle.as_ref();
         if let Some(lc) = lifecycle {
             if !lc.is_enabled(hook) {
-                return Ok(HookResult {
-                    success: true,
-                    result: None,
-                    error: Some(format!("Hook {:?} not enabled for extension", hook)),
-                });
+                return Ok(HookResult::not_run(format!(
+                    "Hook {:?} not enabled for extension",
+                    hook
+                )));
             }
         } else {
-            return Ok(HookResult {
-                success: true,
-                result: None,
-                error: Some("No lifecycle hooks configured".to_string()),
-            });
+            return Ok(HookResult::not_run(
+                "No lifecycle hooks configured".to_string(),
+            ));
         }
 
         // Check if hooks.lua exists
@@ -393,51 +849,126 @@ impl ExtensionRegistry {
             )
         })?;
 
-        // Create Lua runtime
-        let ctx = LuaContext::new(workspace, shell_timeout);
-        let lua =
-            create_lua_runtime(&ctx).map_err(|e| format!("Failed to create Lua runtime: {}", e))?;
-
-        // Execute the hook function
+        // Create Lua runtime and execute the hook function
+        let started = Instant::now();
         let function_name = hook.function_name();
-        match call_function(&lua, script, function_name, args) {
-            Ok(result) => Ok(HookResult {
-                success: true,
-                result: Some(result),
-                error: None,
-            }),
-            Err(e) => Ok(HookResult {
-                success: false,
-                result: None,
-                error: Some(e),
+        let limits = extension.limits();
+        let ctx = LuaContext::new(workspace, shell_timeout)
+            .with_cancel(cancel.cloned())
+            .with_limits(limits);
+        let result = create_lua_runtime(&ctx)
+            .map_err(|e| format!("Failed to create Lua runtime: {}", e))
+            .and_then(|lua| call_function(&lua, script, function_name, args))
+            .map_err(|e| describe_limit_error(extension_id, &limits, e));
+        self.stats.record(
+            extension_id,
+            ExecutionKind::Hook,
+            function_name,
+            started.elapsed(),
+            result.as_ref().err().map(|e| e.as_str()),
+        );
+
+        match result {
+            Ok(result) => {
+                let directives = run_directives(extension_id, &result, workspace, cancel);
+                Ok(HookResult {
+                    success: true,
+                    result: Some(result),
+                    error: None,
+                    directives,
+                })
+            }
+            Err(e) => Ok(HookResult::failure(e)),
+        }
+    }
+
+    /// Execute a lifecycle hook on the blocking thread pool.
+    ///
+    /// A hook still running after the extension's timeout is interrupted and
+    /// abandoned, and fails with an error starting with `EXTENSION_HOOK_TIMED_OUT`.
+    pub async fn run_hook(
+        self: Arc<Self>,
+        extension_id: String,
+        hook: LifecycleHook,
+        args: serde_json::Value,
+        workspace: PathBuf,
+        shell_timeout: u64,
+    ) -> Result<HookResult, String> {
+        let timeout = self.extension_timeout(&extension_id);
+        // Stops the script's instruction hook (and any shell it started) on timeout
+        let stop = CancellationToken::new();
+
+        let task = {
+            let stop = stop.clone();
+            let extension_id = extension_id.clone();
+            tokio::task::spawn_blocking(move || {
+                self.execute_hook(
+                    &extension_id,
+                    hook,
+                    args,
+                    &workspace,
+                    shell_timeout,
+                    Some(&stop),
+                )
+            })
+        };
+
+        tokio::select! {
+            joined = task => joined.unwrap_or_else(|e| {
+                Err(format!(
+                    "{} hook of extension '{}' failed: {}",
+                    hook.function_name(),
+                    extension_id,
+                    e
+                ))
             }),
+            _ = tokio::time::sleep(timeout) => {
+                stop.cancel();
+                log::warn!(
+                    "Killed {} hook of extension '{}' after {}s",
+                    hook.function_name(),
+                    extension_id,
+                    timeout.as_secs()
+                );
+                Ok(HookResult::failure(format!(
+                    "{}: {} in '{}' was stopped after {}s",
+                    EXTENSION_HOOK_TIMED_OUT,
+                    hook.function_name(),
+                    extension_id,
+                    timeout.as_secs()
+                )))
+            }
         }
     }
 
-    /// Execute a lifecycle hook for all extensions that have it enabled
-    pub fn execute_hook_all(
-        &self,
+    /// Execute a lifecycle hook for all extensions that have it enabled, one
+    /// after another. Each hook has its extension's timeout, so one that hangs
+    /// fails on its own and the others still report.
+    pub async fn run_hook_all(
+        self: Arc<Self>,
         hook: LifecycleHook,
         args: serde_json::Value,
-        workspace: &Path,
+        workspace: PathBuf,
         shell_timeout: u64,
     ) -> Vec<(String, HookResult)> {
-        let mut results = Vec::new();
+        let mut ext_ids: Vec<String> = self.extensions.keys().cloned().collect();
+        ext_ids.sort();
 
-        for ext_id in self.extensions.keys() {
-            match self.execute_hook(ext_id, hook, args.clone(), workspace, shell_timeout) {
-                Ok(result) => results.push((ext_id.clone(), result)),
-                Err(e) => results.push((
+        let mut results = Vec::new();
+        for ext_id in ext_ids {
+            let result = self
+                .clone()
+                .run_hook(
                     ext_id.clone(),
-                    HookResult {
-                        success: false,
-                        result: None,
-                        error: Some(e),
-                    },
-                )),
-            }
+                    hook,
+                    args.clone(),
+                    workspace.clone(),
+                    shell_timeout,
+                )
+                .await
+                .unwrap_or_else(HookResult::failure);
+            results.push((ext_id, result));
         }
-
         results
     }
 
@@ -468,6 +999,15 @@ impl ExtensionRegistry {
             .collect()
     }
 
+    /// Risk level of an extension tool, `None` if no loaded extension provides it
+    pub fn tool_risk(&self, tool_name: &str) -> Option<ToolRisk> {
+        let ext_id = self.tool_to_extension.get(tool_name)?;
+        let ext = self.extensions.get(ext_id)?;
+        let short_name = tool_name.strip_prefix(ext_id.as_str())?.strip_prefix(':')?;
+        let tool = ext.manifest.tools.iter().find(|t| t.name == short_name)?;
+        Some(effective_risk(tool.risk, ext.signature.is_verified()))
+    }
+
     /// Check if a tool name is an extension tool
     pub fn is_extension_tool(&self, tool_name: &str) -> bool {
         tool_name.contains(':') && self.tool_to_extension.contains_key(tool_name)
@@ -478,6 +1018,58 @@ impl ExtensionRegistry {
         self.extensions.keys().map(|s| s.as_str()).collect()
     }
 
+    /// A loaded extension by ID
+    pub fn get_extension(&self, extension_id: &str) -> Option<&LoadedExtension> {
+        self.extensions.get(extension_id)
+    }
+
+    /// Details of every loaded extension, sorted by ID
+    pub fn get_extension_details(&self) -> Vec<ExtensionDetails> {
+        let mut details: Vec<ExtensionDetails> = self
+            .extensions
+            .iter()
+            .map(|(id, ext)| {
+                let manifest = &ext.manifest;
+                let tools = manifest
+                    .tools
+                    .iter()
+                    .filter(|t| t.lua_script.is_some())
+                    .map(|t| ExtensionToolDetails {
+                        name: format!("{}:{}", id, t.name),
+                        description: t.description.clone(),
+                        parameters: t
+                            .parameters
+                            .as_ref()
+                            .or(t.schema.as_ref())
+                            .cloned()
+                            .unwrap_or_else(
+                                || serde_json::json!({"type": "object", "properties": {}}),
+                            ),
+                        risk: effective_risk(t.risk, ext.signature.is_verified()),
+                        declared_risk: t.risk,
+                    })
+                    .collect();
+
+                ExtensionDetails {
+                    id: id.clone(),
+                    name: manifest.name.clone(),
+                    version: manifest.version.clone(),
+                    description: manifest.description.clone(),
+                    directory: ext.directory.clone(),
+                    signature: ext.signature.clone(),
+                    hooks: self
+                        .get_enabled_hooks(id)
+                        .iter()
+                        .map(|h| h.function_name().to_string())
+                        .collect(),
+                    tools,
+                }
+            })
+            .collect();
+        details.sort_by(|a, b| a.id.cmp(&b.id));
+        details
+    }
+
     /// Get extension directories for signature verification
     /// Returns a list of (extension_id, manifest_path) pairs
     pub fn get_extension_manifest_paths(&self) -> Vec<(String, PathBuf)> {
@@ -486,6 +1078,81 @@ impl ExtensionRegistry {
             .map(|(id, ext)| (id.clone(), ext.directory.join("manifest.json")))
             .collect()
     }
+
+    /// Load every extension's tool scripts and hooks.lua, each in a fresh runtime
+    /// rooted at a scratch workspace, and check they define the functions the
+    /// manifest names. Top-level code runs; no tool or hook function is called.
+    /// A script still loading after `script_timeout` is stopped and fails.
+    pub fn smoke_test(&self, script_timeout: Duration) -> Result<Vec<ExtensionSmokeTest>, String> {
+        let workspace =
+            std::env::temp_dir().join(format!("vswrite-extension-check-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&workspace)
+            .map_err(|e| format!("Failed to create scratch workspace: {}", e))?;
+
+        let mut ids: Vec<&String> = self.extensions.keys().collect();
+        ids.sort();
+        let results = ids
+            .into_iter()
+            .map(|id| {
+                let ext = &self.extensions[id];
+                let load = |script: &str, functions: &[&str]| {
+                    load_in_scratch(ext, &workspace, script, functions, script_timeout)
+                };
+
+                let tools: Vec<ScriptCheck> = ext
+                    .manifest
+                    .tools
+                    .iter()
+                    .filter(|t| t.lua_script.is_some())
+                    .map(|tool| {
+                        let function = tool.lua_function.as_deref().unwrap_or(&tool.name);
+                        let result = match ext.scripts.get(&tool.name) {
+                            Some(script) => load(script, &[function]),
+                            None => Err("Script was not loaded".to_string()),
+                        };
+                        ScriptCheck::new(
+                            &tool.name,
+                            function,
+                            result.map(|missing| missing.is_empty()),
+                        )
+                    })
+                    .collect();
+
+                let enabled: Vec<&str> = self
+                    .get_enabled_hooks(id)
+                    .iter()
+                    .map(|h| h.function_name())
+                    .collect();
+                let loaded = match (&ext.hooks_script, enabled.is_empty()) {
+                    (_, true) => Ok(Vec::new()),
+                    (None, false) => Err("Hooks are enabled but there is no hooks.lua".to_string()),
+                    (Some(script), false) => load(script, &enabled),
+                };
+                let hooks = enabled
+                    .iter()
+                    .map(|name| {
+                        let result = match &loaded {
+                            Ok(missing) => Ok(!missing.iter().any(|m| m == name)),
+                            Err(e) => Err(e.clone()),
+                        };
+                        ScriptCheck::new(name, name, result)
+                    })
+                    .collect::<Vec<_>>();
+
+                ExtensionSmokeTest {
+                    extension_id: id.clone(),
+                    passed: tools.iter().chain(&hooks).all(|c| c.passed),
+                    tools,
+                    hooks,
+                }
+            })
+            .collect();
+
+        if let Err(e) = fs::remove_dir_all(&workspace) {
+            log::warn!("Could not remove {}: {}", workspace.display(), e);
+        }
+        Ok(results)
+    }
 }
 
 impl Default for ExtensionRegistry {
@@ -494,6 +1161,99 @@ impl Default for ExtensionRegistry {
     }
 }
 
+// ============================================================================
+// Smoke Tests
+// ============================================================================
+
+/// Outcome of checking that one tool's or hook's function loads
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptCheck {
+    /// Tool name, or hook function name
+    pub name: String,
+    pub passed: bool,
+    /// The Lua error, or what is missing, when the check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ScriptCheck {
+    /// `result` is whether `function` is defined, or why the script didn't load
+    fn new(name: &str, function: &str, result: Result<bool, String>) -> Self {
+        let error = match result {
+            Ok(true) => None,
+            Ok(false) => Some(format!(
+                "Function '{}' is not defined after loading the script",
+                function
+            )),
+            Err(e) => Some(e),
+        };
+        ScriptCheck {
+            name: name.to_string(),
+            passed: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// Results of loading one extension's scripts without running them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionSmokeTest {
+    pub extension_id: String,
+    /// Whether every tool and hook check passed
+    pub passed: bool,
+    pub tools: Vec<ScriptCheck>,
+    /// One check per enabled lifecycle hook
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<ScriptCheck>,
+}
+
+/// Load `script` on its own thread under the extension's limits and return the
+/// names in `functions` it leaves undefined. The thread is stopped and abandoned
+/// if it's still going after `timeout`.
+fn load_in_scratch(
+    extension: &LoadedExtension,
+    workspace: &Path,
+    script: &str,
+    functions: &[&str],
+    timeout: Duration,
+) -> Result<Vec<String>, String> {
+    let limits = extension.limits();
+    let stop = CancellationToken::new();
+    let (tx, rx) = std::sync::mpsc::channel();
+    {
+        let workspace = workspace.to_path_buf();
+        let script = script.to_string();
+        let functions: Vec<String> = functions.iter().map(|f| f.to_string()).collect();
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            let ctx = LuaContext::new(&workspace, timeout.as_secs().max(1))
+                .with_cancel(Some(stop))
+                .with_limits(limits);
+            let result = create_lua_runtime(&ctx)
+                .map_err(|e| format!("Failed to create Lua runtime: {}", e))
+                .and_then(|lua| {
+                    load_script(&lua, &script)?;
+                    Ok(functions
+                        .into_iter()
+                        .filter(|f| !has_function(&lua, f))
+                        .collect())
+                });
+            let _ = tx.send(result);
+        });
+    }
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result.map_err(|e| describe_limit_error(&extension.manifest.id, &limits, e)),
+        Err(_) => {
+            stop.cancel();
+            Err(format!(
+                "Loading the script took longer than {}s",
+                timeout.as_secs_f32()
+            ))
+        }
+    }
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -501,6 +1261,9 @@ impl Default for ExtensionRegistry {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::entity_api::EntityStore;
+    use crate::agent::extension_stats::ErrorClass;
+    use crate::agent::hook_directives::{DirectiveStatus, HookDirective, NotifyLevel};
     use std::fs;
     use tempfile::TempDir;
 
@@ -550,6 +1313,23 @@ mod tests {
         assert_eq!(registry.list_extensions(), vec!["test-ext"]);
     }
 
+    #[test]
+    fn test_load_extension_applies_signature_policy() {
+        let dir = TempDir::new().unwrap();
+        create_test_extension(dir.path());
+
+        let mut registry = ExtensionRegistry::new();
+        registry.set_signature_policy(SignaturePolicy::RequireTrusted);
+        let err = registry.load_extension(dir.path()).unwrap_err();
+        assert!(err.contains("'test-ext' is not signed"), "{}", err);
+        assert!(registry.list_extensions().is_empty());
+        assert!(registry.get_extension_tool_schemas().is_empty());
+
+        registry.set_signature_policy(SignaturePolicy::WarnUnsigned);
+        registry.load_extension(dir.path()).unwrap();
+        assert!(!registry.extensions["test-ext"].signature.is_signed);
+    }
+
     #[test]
     fn test_get_tool_schemas() {
         let dir = TempDir::new().unwrap();
@@ -575,12 +1355,452 @@ mod tests {
 
         let args = serde_json::json!({"name": "World"});
         let result = registry
-            .execute_tool("test-ext:greet", &args, workspace.path(), 30)
+            .execute_tool("test-ext:greet", &args, workspace.path(), 30, None)
             .unwrap();
 
         assert_eq!(result, "Hello, World!");
     }
 
+    /// An extension whose `spin` tool never returns
+    fn create_spinning_extension(dir: &Path, timeout_seconds: u64) {
+        let manifest = serde_json::json!({
+            "id": "spin-ext",
+            "name": "Spin",
+            "version": "1.0.0",
+            "timeoutSeconds": timeout_seconds,
+            // High enough that the timeout, not the budget, stops the loop
+            "instructionLimit": MAX_EXTENSION_INSTRUCTION_LIMIT,
+            "tools": [{"name": "spin", "description": "Loop forever", "luaScript": "spin.lua"}]
+        });
+        fs::write(dir.join("manifest.json"), manifest.to_string()).unwrap();
+        fs::write(
+            dir.join("spin.lua"),
+            "function spin(args) while true do end end",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_tool_timeout_from_manifest() {
+        let ext_dir = TempDir::new().unwrap();
+        create_spinning_extension(ext_dir.path(), 5000);
+        let greet_dir = TempDir::new().unwrap();
+        create_test_extension(greet_dir.path());
+
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(ext_dir.path()).unwrap();
+        registry.load_extension(greet_dir.path()).unwrap();
+
+        assert_eq!(
+            registry.tool_timeout("spin-ext:spin"),
+            Duration::from_secs(MAX_EXTENSION_TOOL_TIMEOUT_SECS)
+        );
+        assert_eq!(
+            registry.tool_timeout("test-ext:greet"),
+            Duration::from_secs(DEFAULT_EXTENSION_TOOL_TIMEOUT_SECS)
+        );
+    }
+
+    #[test]
+    fn test_resource_limits_name_the_extension() {
+        let ext_dir = TempDir::new().unwrap();
+        let manifest = serde_json::json!({
+            "id": "greedy",
+            "name": "Greedy",
+            "version": "1.0.0",
+            "memoryLimitMb": 8,
+            "instructionLimit": 1_000_000,
+            "tools": [
+                {"name": "hog", "description": "Allocate forever", "luaScript": "greedy.lua"},
+                {"name": "spin", "description": "Loop forever", "luaScript": "greedy.lua"}
+            ]
+        });
+        fs::write(ext_dir.path().join("manifest.json"), manifest.to_string()).unwrap();
+        fs::write(
+            ext_dir.path().join("greedy.lua"),
+            r#"
+            function hog(args)
+                local t = {}
+                while true do t[#t+1] = string.rep("x", 1e6) .. #t end
+            end
+            function spin(args) while true do end end
+            "#,
+        )
+        .unwrap();
+        let workspace = TempDir::new().unwrap();
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(ext_dir.path()).unwrap();
+
+        let err = registry
+            .execute_tool(
+                "greedy:hog",
+                &serde_json::json!({}),
+                workspace.path(),
+                30,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err, "Extension 'greedy' exceeded its memory limit of 8 MB");
+
+        let err = registry
+            .execute_tool(
+                "greedy:spin",
+                &serde_json::json!({}),
+                workspace.path(),
+                30,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Extension 'greedy' exceeded its instruction limit of 1000000"
+        );
+
+        // The process survives and other calls still work
+        let greet_dir = TempDir::new().unwrap();
+        create_test_extension(greet_dir.path());
+        registry.load_extension(greet_dir.path()).unwrap();
+        let args = serde_json::json!({"name": "World"});
+        assert!(registry
+            .execute_tool("test-ext:greet", &args, workspace.path(), 30, None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_resource_limits_are_capped() {
+        let dir = TempDir::new().unwrap();
+        create_spinning_extension(dir.path(), 1);
+        let mut manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.path().join("manifest.json")).unwrap())
+                .unwrap();
+        manifest["memoryLimitMb"] = serde_json::json!(1_000_000);
+        manifest["instructionLimit"] = serde_json::json!(u64::MAX);
+        fs::write(dir.path().join("manifest.json"), manifest.to_string()).unwrap();
+
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(dir.path()).unwrap();
+        let limits = registry.extensions["spin-ext"].limits();
+        assert_eq!(
+            limits.memory_bytes,
+            Some(MAX_EXTENSION_MEMORY_LIMIT_MB as usize * 1024 * 1024)
+        );
+        assert_eq!(limits.instructions, Some(MAX_EXTENSION_INSTRUCTION_LIMIT));
+    }
+
+    #[tokio::test]
+    async fn test_run_tool_kills_runaway_script() {
+        let ext_dir = TempDir::new().unwrap();
+        create_spinning_extension(ext_dir.path(), 1);
+        let workspace = TempDir::new().unwrap();
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(ext_dir.path()).unwrap();
+        let registry = Arc::new(registry);
+
+        let started = Instant::now();
+        let err = registry
+            .clone()
+            .run_tool(
+                "spin-ext:spin".to_string(),
+                serde_json::json!({}),
+                workspace.path().to_path_buf(),
+                30,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.starts_with(EXTENSION_TOOL_TIMED_OUT), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // The instruction hook stops the abandoned script, which records its failure
+        for _ in 0..50 {
+            if registry.stats().summary("spin-ext").is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(registry.stats().summary("spin-ext").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_run_tool_abandoned_on_cancel() {
+        let ext_dir = TempDir::new().unwrap();
+        create_spinning_extension(ext_dir.path(), 60);
+        let workspace = TempDir::new().unwrap();
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(ext_dir.path()).unwrap();
+
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                token.cancel();
+            })
+        };
+
+        let started = Instant::now();
+        let result = Arc::new(registry)
+            .run_tool(
+                "spin-ext:spin".to_string(),
+                serde_json::json!({}),
+                workspace.path().to_path_buf(),
+                30,
+                Some(token),
+            )
+            .await;
+        canceller.await.unwrap();
+
+        assert_eq!(result.unwrap_err(), TOOL_CANCELLED);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    fn create_hook_extension(dir: &Path, id: &str, body: &str) {
+        let manifest = serde_json::json!({
+            "id": id,
+            "name": id,
+            "version": "1.0.0",
+            "timeoutSeconds": 1,
+            "instructionLimit": MAX_EXTENSION_INSTRUCTION_LIMIT,
+            "lifecycle": {"onProjectOpen": true}
+        });
+        fs::write(dir.join("manifest.json"), manifest.to_string()).unwrap();
+        fs::write(
+            dir.join("hooks.lua"),
+            format!("function on_project_open(args) {} end", body),
+        )
+        .unwrap();
+    }
+
+    /// Broken scripts are reported per tool and hook without any being called
+    #[test]
+    fn test_smoke_test_reports_broken_scripts() {
+        let dir = TempDir::new().unwrap();
+        let manifest = serde_json::json!({
+            "id": "broken-ext",
+            "name": "Broken",
+            "version": "1.0.0",
+            "tools": [
+                {"name": "ok", "description": "", "luaScript": "ok.lua"},
+                {"name": "typo", "description": "", "luaScript": "typo.lua"},
+                {"name": "renamed", "description": "", "luaScript": "ok.lua", "luaFunction": "gone"},
+                {"name": "spin", "description": "", "luaScript": "spin.lua"}
+            ],
+            "lifecycle": {"onProjectOpen": true, "onSectionSave": true}
+        });
+        fs::write(dir.path().join("manifest.json"), manifest.to_string()).unwrap();
+        fs::write(
+            dir.path().join("ok.lua"),
+            "function ok() error('must not be called') end",
+        )
+        .unwrap();
+        fs::write(dir.path().join("typo.lua"), "function typo( return 1 end").unwrap();
+        fs::write(dir.path().join("spin.lua"), "while true do end").unwrap();
+        fs::write(
+            dir.path().join("hooks.lua"),
+            "function on_project_open() end",
+        )
+        .unwrap();
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(dir.path()).unwrap();
+
+        let started = Instant::now();
+        let results = registry.smoke_test(Duration::from_millis(300)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        assert_eq!(results.len(), 1);
+        let test = &results[0];
+        assert_eq!(test.extension_id, "broken-ext");
+        assert!(!test.passed);
+        let error = |name: &str| {
+            test.tools
+                .iter()
+                .chain(&test.hooks)
+                .find(|c| c.name == name)
+                .unwrap()
+                .error
+                .clone()
+        };
+        assert_eq!(error("ok"), None);
+        assert!(error("typo").unwrap().starts_with("Syntax error"));
+        assert!(error("renamed").unwrap().contains("'gone' is not defined"));
+        assert!(error("spin").unwrap().contains("took longer"));
+        assert_eq!(error("on_project_open"), None);
+        assert!(error("on_section_save").unwrap().contains("not defined"));
+    }
+
+    /// A hook that hangs is stopped at its timeout; the others still report
+    #[tokio::test]
+    async fn test_run_hook_all_survives_a_hung_hook() {
+        let hung_dir = TempDir::new().unwrap();
+        create_hook_extension(hung_dir.path(), "hung-ext", "while true do end");
+        let quick_dir = TempDir::new().unwrap();
+        create_hook_extension(quick_dir.path(), "quick-ext", "return 'opened'");
+        let workspace = TempDir::new().unwrap();
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(hung_dir.path()).unwrap();
+        registry.load_extension(quick_dir.path()).unwrap();
+
+        let started = Instant::now();
+        let results = Arc::new(registry)
+            .run_hook_all(
+                LifecycleHook::OnProjectOpen,
+                serde_json::json!({}),
+                workspace.path().to_path_buf(),
+                30,
+            )
+            .await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        assert_eq!(results.len(), 2);
+        let (hung_id, hung) = &results[0];
+        assert_eq!(hung_id, "hung-ext");
+        assert!(!hung.success);
+        let error = hung.error.as_deref().unwrap();
+        assert!(error.starts_with(EXTENSION_HOOK_TIMED_OUT), "{}", error);
+
+        let (quick_id, quick) = &results[1];
+        assert_eq!(quick_id, "quick-ext");
+        assert!(quick.success);
+        assert_eq!(quick.result.as_deref(), Some("opened"));
+    }
+
+    /// Each directive type a hook can return, carried out against a workspace
+    #[test]
+    fn test_hook_directives() {
+        const ANNA: &str = "11111111-1111-4111-8111-111111111111";
+        const SECTION: &str = "44444444-4444-4444-8444-444444444444";
+        let workspace = TempDir::new().unwrap();
+        fs::create_dir(workspace.path().join("entities")).unwrap();
+        fs::create_dir(workspace.path().join("sections")).unwrap();
+        fs::write(
+            workspace.path().join("entities/anna.yaml"),
+            format!("id: {}\nname: Anna\ntype: fact\n", ANNA),
+        )
+        .unwrap();
+        fs::write(
+            workspace.path().join("sections/01.md"),
+            format!(
+                "---\nid: {}\ntitle: One\norder: 0\n---\nAnna waved.",
+                SECTION
+            ),
+        )
+        .unwrap();
+        fs::write(workspace.path().join("notes.md"), "remember the lighthouse").unwrap();
+
+        let ext_dir = TempDir::new().unwrap();
+        let body = format!(
+            r#"return {{ directives = {{
+                {{ type = "notify", level = "warning", message = "Chapter one has no POV" }},
+                {{ type = "add_tag", section_id = "{section}", entity_id = "{anna}", from = 0, to = 4 }},
+                {{ type = "add_tag", section_id = "{section}", entity_id = "{anna}", from = 2, to = 6 }},
+                {{ type = "run_tool", name = "read_file", args = {{ path = "notes.md" }} }},
+                {{ type = "run_tool", name = "write_file", args = {{ path = "notes.md", content = "" }} }},
+                {{ type = "delete_section", section_id = "{section}" }},
+            }} }}"#,
+            section = SECTION,
+            anna = ANNA
+        );
+        create_hook_extension(ext_dir.path(), "directive-ext", &body);
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(ext_dir.path()).unwrap();
+
+        let result = registry
+            .execute_hook(
+                "directive-ext",
+                LifecycleHook::OnProjectOpen,
+                serde_json::json!({}),
+                workspace.path(),
+                30,
+                None,
+            )
+            .unwrap();
+        assert!(result.success);
+        assert!(result.result.as_deref().unwrap().contains("directives"));
+
+        use DirectiveStatus::*;
+        let outcomes: Vec<(&str, DirectiveStatus)> = result
+            .directives
+            .iter()
+            .map(|o| (o.kind.as_str(), o.status))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("notify", Executed),
+                ("add_tag", Executed),
+                ("add_tag", Failed),
+                ("run_tool", Executed),
+                ("run_tool", Skipped),
+                ("delete_section", Invalid),
+            ]
+        );
+        assert_eq!(
+            result.directives[0].directive,
+            Some(HookDirective::Notify {
+                level: NotifyLevel::Warning,
+                message: "Chapter one has no POV".to_string()
+            })
+        );
+        let detail = |i: usize| result.directives[i].detail.as_deref().unwrap();
+        assert!(detail(2).contains("overlaps"), "{}", detail(2));
+        assert!(
+            detail(3).contains("remember the lighthouse"),
+            "{}",
+            detail(3)
+        );
+        assert!(detail(4).contains("needs approval"), "{}", detail(4));
+        assert!(
+            detail(5).contains("Unknown directive type"),
+            "{}",
+            detail(5)
+        );
+
+        // The tag was written; the write was not
+        let tags = EntityStore::new(workspace.path())
+            .get_tags(SECTION)
+            .unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!((tags[0].from, tags[0].to), (0, 4));
+        assert_eq!(
+            fs::read_to_string(workspace.path().join("notes.md")).unwrap(),
+            "remember the lighthouse"
+        );
+    }
+
+    #[test]
+    fn test_execution_stats_shared_across_clones() {
+        let ext_dir = TempDir::new().unwrap();
+        create_test_extension(ext_dir.path());
+        let workspace = TempDir::new().unwrap();
+
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(ext_dir.path()).unwrap();
+        // Agent runs execute tools on a clone of the registry
+        let run_registry = registry.clone();
+
+        for i in 0..5 {
+            // Without a name the script concatenates nil and fails
+            let args = if i % 2 == 0 {
+                serde_json::json!({"name": "World"})
+            } else {
+                serde_json::json!({})
+            };
+            let _ = run_registry.execute_tool("test-ext:greet", &args, workspace.path(), 30, None);
+        }
+
+        let summary = registry.stats().summary("test-ext").unwrap();
+        assert_eq!(summary.executions, 5);
+        assert_eq!(summary.tool_executions, 5);
+        assert_eq!(summary.failures, 2);
+        assert!((summary.failure_rate - 0.4).abs() < 1e-9);
+        assert_eq!(summary.errors[&ErrorClass::Runtime], 2);
+        assert_eq!(summary.window, 5);
+        assert!(summary.p50_ms <= summary.p95_ms);
+
+        registry.unload_extension("test-ext").unwrap();
+        assert!(run_registry.stats().summary("test-ext").is_none());
+    }
+
     #[test]
     fn test_is_extension_tool() {
         let dir = TempDir::new().unwrap();
@@ -594,6 +1814,137 @@ mod tests {
         assert!(!registry.is_extension_tool("unknown:tool"));
     }
 
+    /// Tools declaring low, high, and no risk
+    fn create_risk_extension(dir: &Path) {
+        let tool = |name: &str, risk: Option<&str>| {
+            let mut tool = serde_json::json!({
+                "name": name,
+                "description": name,
+                "luaScript": "tools.lua",
+                "luaFunction": name,
+            });
+            if let Some(risk) = risk {
+                tool["risk"] = serde_json::json!(risk);
+            }
+            tool
+        };
+        let manifest = serde_json::json!({
+            "id": "risk-ext",
+            "name": "Risk",
+            "version": "1.0.0",
+            "tools": [tool("count", Some("low")), tool("backup", Some("high")), tool("tidy", None)],
+        });
+        fs::write(dir.join("manifest.json"), manifest.to_string()).unwrap();
+        fs::write(
+            dir.join("tools.lua"),
+            "function count(args) return 'ok' end",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_extension_details() {
+        let dir = TempDir::new().unwrap();
+        create_risk_extension(dir.path());
+        let manifest_path = dir.path().join("manifest.json");
+        let mut manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+        manifest["description"] = serde_json::json!("Risky tools");
+        manifest["lifecycle"] = serde_json::json!({"onActivate": true, "onSectionSave": true});
+        manifest["tools"][0]["parameters"] = serde_json::json!({
+            "type": "object",
+            "properties": {"path": {"type": "string"}},
+            "required": ["path"]
+        });
+        fs::write(&manifest_path, manifest.to_string()).unwrap();
+        let dir2 = TempDir::new().unwrap();
+        create_test_extension(dir2.path());
+
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(dir.path()).unwrap();
+        registry.load_extension(dir2.path()).unwrap();
+
+        let details = registry.get_extension_details();
+        let ids: Vec<_> = details.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["risk-ext", "test-ext"]);
+
+        let risk = &details[0];
+        assert_eq!(risk.version, "1.0.0");
+        assert_eq!(risk.description.as_deref(), Some("Risky tools"));
+        assert_eq!(risk.directory, dir.path());
+        assert!(!risk.signature.is_verified());
+        assert_eq!(risk.hooks, vec!["on_activate", "on_section_save"]);
+
+        let count = &risk.tools[0];
+        assert_eq!(count.name, "risk-ext:count");
+        assert_eq!(count.parameters["required"], serde_json::json!(["path"]));
+        assert_eq!(count.declared_risk, Some(ToolRisk::Low));
+        assert_eq!(count.risk, ToolRisk::Medium);
+        assert_eq!(risk.tools[2].declared_risk, None);
+        assert_eq!(risk.tools[2].risk, ToolRisk::High);
+        assert_eq!(risk.tools[2].parameters["type"], "object");
+
+        assert!(details[1].hooks.is_empty());
+        let json = serde_json::to_value(&details[1]).unwrap();
+        assert_eq!(
+            json["tools"][0]["parameters"]["properties"]["name"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn test_declared_tool_risk() {
+        let dir = TempDir::new().unwrap();
+        create_risk_extension(dir.path());
+        let mut registry = ExtensionRegistry::new();
+        registry.load_extension(dir.path()).unwrap();
+
+        let tools = &registry.extensions["risk-ext"].manifest.tools;
+        assert_eq!(tools[0].risk, Some(ToolRisk::Low));
+        assert_eq!(tools[1].risk, Some(ToolRisk::High));
+        assert_eq!(tools[2].risk, None);
+        assert!(lint_manifest(
+            &serde_json::to_value(&registry.extensions["risk-ext"].manifest).unwrap()
+        )
+        .is_empty());
+
+        // Unsigned, so low is raised to medium
+        assert!(!registry.extensions["risk-ext"].signature.is_signed);
+        assert_eq!(registry.tool_risk("risk-ext:count"), Some(ToolRisk::Medium));
+        assert_eq!(registry.tool_risk("risk-ext:backup"), Some(ToolRisk::High));
+        assert_eq!(registry.tool_risk("risk-ext:tidy"), Some(ToolRisk::High));
+        assert_eq!(registry.tool_risk("risk-ext:missing"), None);
+        assert_eq!(registry.tool_risk("read_file"), None);
+
+        let signature = &mut registry.extensions.get_mut("risk-ext").unwrap().signature;
+        signature.is_signed = true;
+        signature.is_valid = true;
+        signature.is_trusted = true;
+        assert_eq!(registry.tool_risk("risk-ext:count"), Some(ToolRisk::Low));
+        assert_eq!(registry.tool_risk("risk-ext:tidy"), Some(ToolRisk::High));
+
+        let err = serde_json::from_value::<LuaToolDefinition>(serde_json::json!({
+            "name": "x",
+            "description": "x",
+            "risk": "harmless",
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown variant"), "{}", err);
+    }
+
+    #[test]
+    fn test_effective_risk_clamping() {
+        assert_eq!(effective_risk(None, false), ToolRisk::High);
+        assert_eq!(effective_risk(None, true), ToolRisk::High);
+        assert_eq!(effective_risk(Some(ToolRisk::Low), false), ToolRisk::Medium);
+        assert_eq!(effective_risk(Some(ToolRisk::Low), true), ToolRisk::Low);
+        assert_eq!(
+            effective_risk(Some(ToolRisk::Medium), false),
+            ToolRisk::Medium
+        );
+        assert_eq!(effective_risk(Some(ToolRisk::High), false), ToolRisk::High);
+    }
+
     #[test]
     fn test_unload_extension() {
         let dir = TempDir::new().unwrap();
@@ -609,4 +1960,104 @@ mod tests {
         assert_eq!(registry.list_extensions().len(), 0);
         assert!(!registry.is_extension_tool("test-ext:greet"));
     }
+
+    const MANIFEST_WITH_EXTRAS: &str = r#"{
+        "id": "glossary",
+        "name": "Glossary",
+        "version": "1.2.0",
+        "description": "Keeps a glossary of terms",
+        "author": "VS Write Team",
+        "license": "MIT",
+        "categories": ["reference", "entities"],
+        "icon": "icon.png",
+        "signature": "c2lnbmF0dXJl",
+        "signatureAlgorithm": "ed25519",
+        "publicKeyId": "vswrite-official",
+        "tools": [
+            {
+                "name": "define",
+                "description": "Define a term",
+                "luaScript": "tools.lua",
+                "luaFunction": "define",
+                "riskLevel": "low",
+                "parameters": {"type": "object", "properties": {}}
+            }
+        ],
+        "lifecycle": {
+            "onActivate": true,
+            "hooksScript": "hooks.lua"
+        }
+    }"#;
+
+    #[test]
+    fn test_manifest_round_trips_unknown_fields() {
+        let original: serde_json::Value = serde_json::from_str(MANIFEST_WITH_EXTRAS).unwrap();
+        let manifest: ExtensionManifest = serde_json::from_value(original.clone()).unwrap();
+
+        assert_eq!(manifest.extras["license"], "MIT");
+        assert_eq!(manifest.tools[0].extras["riskLevel"], "low");
+        assert_eq!(
+            manifest.lifecycle.as_ref().unwrap().extras["hooksScript"],
+            "hooks.lua"
+        );
+
+        // serde_json maps are key-ordered, so this compares modulo key order
+        let serialized = serde_json::to_string(&manifest).unwrap();
+        let round_tripped: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(round_tripped, original);
+    }
+
+    #[test]
+    fn test_minimal_manifest_round_trip() {
+        let original = serde_json::json!({
+            "id": "min",
+            "name": "Minimal",
+            "version": "0.1.0",
+            "tools": []
+        });
+        let manifest: ExtensionManifest = serde_json::from_value(original.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&manifest).unwrap(), original);
+    }
+
+    #[test]
+    fn test_lint_catches_typos() {
+        let manifest = serde_json::json!({
+            "id": "typos",
+            "name": "Typos",
+            "version": "1.0.0",
+            "licence": "MIT",
+            "tools": [
+                {"name": "a", "description": "A", "luascript": "a.lua"},
+                {"name": "b", "description": "B", "lua_function": "b", "luaScript": "b.lua"}
+            ],
+            "lifecycle": {"onActivte": true}
+        });
+
+        let warnings = lint_manifest(&manifest);
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert!(warnings
+            .iter()
+            .any(|w| w.contains("tools[0]") && w.contains("\"luaScript\"")));
+        assert!(warnings
+            .iter()
+            .any(|w| w.contains("tools[1]") && w.contains("\"luaFunction\"")));
+        assert!(warnings.iter().any(|w| w.contains("\"license\"")));
+        assert!(warnings.iter().any(|w| w.contains("\"onActivate\"")));
+    }
+
+    #[test]
+    fn test_lint_ignores_clean_and_unrelated_fields() {
+        let mut manifest: serde_json::Value = serde_json::from_str(MANIFEST_WITH_EXTRAS).unwrap();
+        // An unrelated custom field is legal and not a likely typo
+        manifest["marketplaceRank"] = serde_json::json!(3);
+
+        assert!(lint_manifest(&manifest).is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("license", "licence"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
 }
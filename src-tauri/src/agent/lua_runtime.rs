@@ -3,26 +3,70 @@
 //! This module provides a sandboxed Lua environment for running extension tools.
 //! Extensions can access a limited set of safe functions for file I/O and searching.
 
-use mlua::{Function, Lua, LuaSerdeExt, Result as LuaResult, Table, Value};
+use mlua::{Function, HookTriggers, Lua, LuaSerdeExt, Result as LuaResult, Table, Value, VmState};
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-
-use super::entity_api::EntityStore;
-use super::tools;
+use tokio_util::sync::CancellationToken;
+
+use super::entity_api::{Entity, EntityStore, NewSection, SectionMetadata};
+use super::entity_graph::{self, GraphFormat, GraphOptions};
+use super::entity_tags;
+use super::ids;
+use super::section_scenes::{self, HeadingRef};
+use super::tools::{self, PathValidator, ShellSettings};
+
+/// Number of VM instructions between cancellation and budget checks
+const LUA_CANCEL_CHECK_INSTRUCTIONS: u32 = 10_000;
+
+/// Error raised when a script runs through its instruction budget
+pub const LUA_INSTRUCTION_BUDGET_EXCEEDED: &str = "instruction budget exceeded";
+
+/// How Lua reports an allocation refused by the memory limit
+pub const LUA_OUT_OF_MEMORY: &str = "not enough memory";
+
+/// Resource limits for one Lua state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LuaLimits {
+    /// Bytes the state may allocate, including the standard library
+    pub memory_bytes: Option<usize>,
+    /// VM instructions a script may execute, checked every
+    /// `LUA_CANCEL_CHECK_INSTRUCTIONS`
+    pub instructions: Option<u64>,
+}
 
 /// Context passed to Lua scripts with access to safe operations
 pub struct LuaContext {
     workspace: Arc<Path>,
+    paths: Arc<PathValidator>,
     shell_timeout: u64,
+    cancel: Option<CancellationToken>,
+    limits: LuaLimits,
 }
 
 impl LuaContext {
     pub fn new(workspace: &Path, shell_timeout: u64) -> Self {
         LuaContext {
             workspace: Arc::from(workspace),
+            paths: Arc::new(PathValidator::new(workspace)),
             shell_timeout,
+            cancel: None,
+            limits: LuaLimits::default(),
         }
     }
+
+    /// Abort scripts (and the tools they call) when `token` is cancelled
+    pub fn with_cancel(mut self, token: Option<CancellationToken>) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Cap the memory and instructions scripts may use
+    pub fn with_limits(mut self, limits: LuaLimits) -> Self {
+        self.limits = limits;
+        self
+    }
 }
 
 /// Create a new sandboxed Lua runtime with tool functions exposed
@@ -33,6 +77,32 @@ pub fn create_lua_runtime(ctx: &LuaContext) -> LuaResult<Lua> {
     // Remove dangerous globals
     sandbox_lua(&lua)?;
 
+    if let Some(bytes) = ctx.limits.memory_bytes {
+        lua.set_memory_limit(bytes)?;
+    }
+
+    // Stop runaway scripts when the agent run is cancelled or the budget runs out
+    let budget = ctx.limits.instructions;
+    if ctx.cancel.is_some() || budget.is_some() {
+        let token = ctx.cancel.clone();
+        let executed = AtomicU64::new(0);
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(LUA_CANCEL_CHECK_INSTRUCTIONS),
+            move |_, _| {
+                if token.as_ref().is_some_and(|t| t.is_cancelled()) {
+                    return Err(mlua::Error::runtime(tools::TOOL_CANCELLED));
+                }
+                let executed = executed
+                    .fetch_add(u64::from(LUA_CANCEL_CHECK_INSTRUCTIONS), Ordering::Relaxed)
+                    + u64::from(LUA_CANCEL_CHECK_INSTRUCTIONS);
+                if budget.is_some_and(|budget| executed > budget) {
+                    return Err(mlua::Error::runtime(LUA_INSTRUCTION_BUDGET_EXCEEDED));
+                }
+                Ok(VmState::Continue)
+            },
+        );
+    }
+
     // Create the 'tools' table with our safe functions
     let tools_table = create_tools_table(&lua, ctx)?;
     lua.globals().set("tools", tools_table)?;
@@ -86,25 +156,43 @@ fn create_tools_table(lua: &Lua, ctx: &LuaContext) -> LuaResult<Table> {
     let tools_table = lua.create_table()?;
 
     // read_file(path, [offset], [limit]) -> string
-    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
     tools_table.set(
         "read_file",
         lua.create_function(move |_, args: (String, Option<usize>, Option<usize>)| {
             let (path, offset, limit) = args;
-            match tools::read_file(&workspace, &path, offset, limit) {
+            match tools::read_file(&paths, &path, offset, limit).map(String::from) {
                 Ok(content) => Ok(content),
                 Err(e) => Err(mlua::Error::runtime(e)),
             }
         })?,
     )?;
 
+    // read_many_files(paths, [limit], [max_bytes]) -> string (JSON object)
+    let paths = ctx.paths.clone();
+    let cancel = ctx.cancel.clone();
+    tools_table.set(
+        "read_many_files",
+        lua.create_function(
+            move |_, args: (Vec<String>, Option<usize>, Option<usize>)| {
+                let (files, limit, max_bytes) = args;
+                match tools::read_many_files(&paths, &files, limit, max_bytes, cancel.as_ref())
+                    .map(String::from)
+                {
+                    Ok(result) => Ok(result),
+                    Err(e) => Err(mlua::Error::runtime(e)),
+                }
+            },
+        )?,
+    )?;
+
     // write_file(path, content) -> string
-    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
     tools_table.set(
         "write_file",
         lua.create_function(move |_, args: (String, String)| {
             let (path, content) = args;
-            match tools::write_file(&workspace, &path, &content) {
+            match tools::write_file(&paths, &path, &content).map(String::from) {
                 Ok(msg) => Ok(msg),
                 Err(e) => Err(mlua::Error::runtime(e)),
             }
@@ -112,37 +200,46 @@ fn create_tools_table(lua: &Lua, ctx: &LuaContext) -> LuaResult<Table> {
     )?;
 
     // delete_file(path) -> string
-    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
     tools_table.set(
         "delete_file",
-        lua.create_function(
-            move |_, path: String| match tools::delete_file(&workspace, &path) {
+        lua.create_function(move |_, path: String| {
+            match tools::delete_file(&paths, &path).map(String::from) {
                 Ok(msg) => Ok(msg),
                 Err(e) => Err(mlua::Error::runtime(e)),
-            },
-        )?,
+            }
+        })?,
     )?;
 
     // append_file(path, content) -> string
-    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
     tools_table.set(
         "append_file",
         lua.create_function(move |_, args: (String, String)| {
             let (path, content) = args;
-            match tools::append_file(&workspace, &path, &content) {
+            match tools::append_file(&paths, &path, &content).map(String::from) {
                 Ok(msg) => Ok(msg),
                 Err(e) => Err(mlua::Error::runtime(e)),
             }
         })?,
     )?;
 
-    // list_dir(path) -> string (JSON array)
-    let workspace = ctx.workspace.clone();
+    // list_dir([path], [{include_ignored, recursive, flat}]) -> string (JSON array)
+    let paths = ctx.paths.clone();
     tools_table.set(
         "list_dir",
-        lua.create_function(move |_, path: Option<String>| {
+        lua.create_function(move |_, args: (Option<String>, Option<mlua::Table>)| {
+            let (path, opts) = args;
             let path = path.unwrap_or_else(|| ".".to_string());
-            match tools::list_dir(&workspace, &path) {
+            let mut options = tools::ListDirOptions::default();
+            if let Some(opts) = opts {
+                options.include_ignored = opts
+                    .get::<Option<bool>>("include_ignored")?
+                    .unwrap_or(false);
+                options.recursive = opts.get::<Option<bool>>("recursive")?.unwrap_or(false);
+                options.flat = opts.get::<Option<bool>>("flat")?.unwrap_or(false);
+            }
+            match tools::list_dir(&paths, &path, options).map(String::from) {
                 Ok(result) => Ok(result),
                 Err(e) => Err(mlua::Error::runtime(e)),
             }
@@ -150,42 +247,80 @@ fn create_tools_table(lua: &Lua, ctx: &LuaContext) -> LuaResult<Table> {
     )?;
 
     // glob(pattern, [base_path]) -> string (JSON array)
-    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
+    let cancel = ctx.cancel.clone();
     tools_table.set(
         "glob",
         lua.create_function(move |_, args: (String, Option<String>)| {
             let (pattern, base_path) = args;
             let base = base_path.unwrap_or_else(|| ".".to_string());
-            match tools::glob_files(&workspace, &pattern, &base) {
+            match tools::glob_files(&paths, &pattern, &base, cancel.as_ref()).map(String::from) {
                 Ok(result) => Ok(result),
                 Err(e) => Err(mlua::Error::runtime(e)),
             }
         })?,
     )?;
 
-    // grep(pattern, [path]) -> string (JSON array of matches)
-    let workspace = ctx.workspace.clone();
+    // grep(pattern, [path], [{context_before, context_after, files_only}]) -> string (JSON array)
+    // The options table may also take the place of `path`, with the path under `path`.
+    let paths = ctx.paths.clone();
+    let cancel = ctx.cancel.clone();
     tools_table.set(
         "grep",
-        lua.create_function(move |_, args: (String, Option<String>)| {
-            let (pattern, path) = args;
-            let search_path = path.unwrap_or_else(|| ".".to_string());
-            match tools::grep_files(&workspace, &pattern, &search_path) {
-                Ok(result) => Ok(result),
-                Err(e) => Err(mlua::Error::runtime(e)),
-            }
-        })?,
+        lua.create_function(
+            move |_, args: (String, Option<mlua::Value>, Option<mlua::Table>)| {
+                let (pattern, path_or_opts, opts) = args;
+                let (path, opts) = match path_or_opts {
+                    Some(mlua::Value::Table(table)) => {
+                        (table.get::<Option<String>>("path")?, Some(table))
+                    }
+                    Some(mlua::Value::String(path)) => (Some(path.to_str()?.to_string()), opts),
+                    Some(mlua::Value::Nil) | None => (None, opts),
+                    Some(other) => {
+                        return Err(mlua::Error::runtime(format!(
+                            "grep: expected a path or an options table, got {}",
+                            other.type_name()
+                        )))
+                    }
+                };
+                let search_path = path.unwrap_or_else(|| ".".to_string());
+                let mut options = tools::GrepOptions::default();
+                if let Some(opts) = opts {
+                    options.context_before =
+                        opts.get::<Option<usize>>("context_before")?.unwrap_or(0);
+                    options.context_after =
+                        opts.get::<Option<usize>>("context_after")?.unwrap_or(0);
+                    options.files_only = opts.get::<Option<bool>>("files_only")?.unwrap_or(false);
+                }
+                match tools::grep_files(&paths, &pattern, &search_path, options, cancel.as_ref())
+                    .map(String::from)
+                {
+                    Ok(result) => Ok(result),
+                    Err(e) => Err(mlua::Error::runtime(e)),
+                }
+            },
+        )?,
     )?;
 
     // run_shell(command, [cwd], [timeout]) -> string (JSON with exit_code and output)
-    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
     let shell_timeout = ctx.shell_timeout;
+    let cancel = ctx.cancel.clone();
     tools_table.set(
         "run_shell",
         lua.create_function(move |_, args: (String, Option<String>, Option<u64>)| {
             let (command, cwd, timeout) = args;
             let timeout = timeout.unwrap_or(shell_timeout).min(60);
-            match tools::run_shell(&workspace, &command, cwd.as_deref(), Some(timeout)) {
+            match tools::run_shell(
+                &paths,
+                &command,
+                cwd.as_deref(),
+                Some(timeout),
+                &ShellSettings::default(),
+                cancel.as_ref(),
+            )
+            .map(String::from)
+            {
                 Ok(result) => Ok(result),
                 Err(e) => Err(mlua::Error::runtime(e)),
             }
@@ -272,6 +407,75 @@ fn create_entities_table(lua: &Lua, ctx: &LuaContext) -> LuaResult<Table> {
         })?,
     )?;
 
+    // entities.create(table) -> created entity (as JSON), with `metadataWarnings`
+    // when its metadata doesn't match the type's schema
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "create",
+        lua.create_function(move |lua, value: Value| {
+            let entity: LuaEntity = lua.from_value(value)?;
+            let store = EntityStore::new(&workspace);
+            match store.create_entity(entity.into()) {
+                Ok(entity) => {
+                    let json = serde_json::to_string_pretty(&entity)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.update(entity_id, table) -> updated entity (as JSON), with
+    // `metadataWarnings` as for create
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "update",
+        lua.create_function(move |lua, args: (String, Value)| {
+            let (entity_id, updates) = args;
+            let updates: serde_json::Value = lua.from_value(updates)?;
+            let store = EntityStore::new(&workspace);
+            match store.update_entity(&entity_id, updates) {
+                Ok(entity) => {
+                    let json = serde_json::to_string_pretty(&entity)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.delete(entity_id) -> true/false
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "delete",
+        lua.create_function(move |_, entity_id: String| {
+            let store = EntityStore::new(&workspace);
+            store
+                .delete_entity(&entity_id)
+                .map_err(mlua::Error::runtime)
+        })?,
+    )?;
+
+    // entities.merge(source_id, target_id) -> { entity, sectionsUpdated, tagsUpdated } (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "merge",
+        lua.create_function(move |_, args: (String, String)| {
+            let (source_id, target_id) = args;
+            let store = EntityStore::new(&workspace);
+            match store.merge_entities(&source_id, &target_id) {
+                Ok(summary) => {
+                    let json = serde_json::to_string_pretty(&summary)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
     // entities.get_relationships(entity_id) -> { entity, sections } (as JSON)
     let workspace = ctx.workspace.clone();
     entities.set(
@@ -289,6 +493,23 @@ fn create_entities_table(lua: &Lua, ctx: &LuaContext) -> LuaResult<Table> {
         })?,
     )?;
 
+    // entities.find_mentions(entity_id) -> { entity_id, sections, total_matches, truncated } (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "find_mentions",
+        lua.create_function(move |_, entity_id: String| {
+            let store = EntityStore::new(&workspace);
+            match entity_tags::find_entity_mentions(&store, &entity_id) {
+                Ok(report) => {
+                    let json = serde_json::to_string_pretty(&report)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
     // entities.add_tag(section_id, entity_id, from, to) -> tag (as JSON)
     let workspace = ctx.workspace.clone();
     entities.set(
@@ -373,9 +594,233 @@ fn create_entities_table(lua: &Lua, ctx: &LuaContext) -> LuaResult<Table> {
         })?,
     )?;
 
+    // entities.create_section(table) -> created section (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "create_section",
+        lua.create_function(move |lua, value: Value| {
+            let section: NewSection = lua.from_value(value)?;
+            let store = EntityStore::new(&workspace);
+            match store.create_section(section) {
+                Ok(section) => {
+                    let json = serde_json::to_string_pretty(&section)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.update_section_content(section_id, content) -> updated section (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "update_section_content",
+        lua.create_function(move |_, args: (String, String)| {
+            let (section_id, content) = args;
+            let store = EntityStore::new(&workspace);
+            match store.update_section_content(&section_id, &content) {
+                Ok(section) => {
+                    let json = serde_json::to_string_pretty(&section)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.update_section_scene(section_id, heading, content, [until]) -> summary (as JSON)
+    // `heading` and `until` are a heading's text or its 1-based index
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "update_section_scene",
+        lua.create_function(move |_, args: (String, Value, String, Option<Value>)| {
+            let (section_id, heading, content, until) = args;
+            let heading = lua_heading_ref(heading)?;
+            let until = until
+                .filter(|v| !v.is_nil())
+                .map(lua_heading_ref)
+                .transpose()?;
+            let store = EntityStore::new(&workspace);
+            match section_scenes::update_section_scene(
+                &store,
+                &section_id,
+                &heading,
+                until.as_ref(),
+                &content,
+            ) {
+                Ok(update) => {
+                    let json = serde_json::to_string_pretty(&update)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.update_section_metadata(section_id, table) -> updated section (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "update_section_metadata",
+        lua.create_function(move |lua, args: (String, Value)| {
+            let (section_id, metadata) = args;
+            let metadata: SectionMetadata = lua.from_value(metadata)?;
+            let store = EntityStore::new(&workspace);
+            match store.update_section_metadata(&section_id, metadata) {
+                Ok(section) => {
+                    let json = serde_json::to_string_pretty(&section)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.insert_into_section(section_id, offset, text) -> insert (as JSON)
+    // `offset` is in UTF-16 units, like tag ranges; tags move with their text
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "insert_into_section",
+        lua.create_function(move |_, args: (String, i64, String)| {
+            let (section_id, offset, text) = args;
+            let store = EntityStore::new(&workspace);
+            match store.insert_into_section(&section_id, offset, &text) {
+                Ok(insert) => {
+                    let json = serde_json::to_string_pretty(&insert)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.append_to_section(section_id, text) -> insert (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "append_to_section",
+        lua.create_function(move |_, args: (String, String)| {
+            let (section_id, text) = args;
+            let store = EntityStore::new(&workspace);
+            match store.append_to_section(&section_id, &text) {
+                Ok(insert) => {
+                    let json = serde_json::to_string_pretty(&insert)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.validate_tags(section_id) -> tags that no longer fit the content (as JSON)
+    let workspace = ctx.workspace.clone();
+    entities.set(
+        "validate_tags",
+        lua.create_function(move |_, section_id: String| {
+            let store = EntityStore::new(&workspace);
+            match store.validate_tags(&section_id) {
+                Ok(tags) => {
+                    let json = serde_json::to_string_pretty(&tags)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
+    // entities.export_graph(format, [{entity_types, min_weight, path}]) -> export (as JSON)
+    // Without `path` the rendered graph is returned in the export's `content`
+    let workspace = ctx.workspace.clone();
+    let paths = ctx.paths.clone();
+    entities.set(
+        "export_graph",
+        lua.create_function(move |lua, args: (String, Option<Value>)| {
+            let (format, opts) = args;
+            let format = GraphFormat::parse(&format).map_err(mlua::Error::runtime)?;
+            let opts: LuaGraphExport = match opts {
+                Some(value) => lua.from_value(value)?,
+                None => LuaGraphExport::default(),
+            };
+            let output = match opts.path.as_deref() {
+                Some(path) => Some(paths.resolve(path).map_err(mlua::Error::runtime)?),
+                None => None,
+            };
+
+            let store = EntityStore::new(&workspace);
+            match entity_graph::export_graph(&store, format, &opts.options, output.as_deref()) {
+                Ok(export) => {
+                    if let Some(target) = &output {
+                        paths.invalidate(target);
+                    }
+                    let json = serde_json::to_string_pretty(&export)
+                        .map_err(|e| mlua::Error::runtime(e.to_string()))?;
+                    Ok(json)
+                }
+                Err(e) => Err(mlua::Error::runtime(e)),
+            }
+        })?,
+    )?;
+
     Ok(entities)
 }
 
+/// Entity table accepted by `tools.entities.create`
+#[derive(Debug, serde::Deserialize)]
+struct LuaEntity {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    entity_type: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    aliases: Vec<String>,
+    #[serde(default)]
+    metadata: HashMap<String, serde_json::Value>,
+}
+
+impl From<LuaEntity> for Entity {
+    fn from(e: LuaEntity) -> Self {
+        Entity {
+            id: e.id,
+            name: e.name,
+            entity_type: e.entity_type,
+            description: e.description,
+            aliases: e.aliases,
+            metadata: e.metadata,
+        }
+    }
+}
+
+/// Options table accepted by `tools.entities.export_graph`
+#[derive(Debug, Default, serde::Deserialize)]
+struct LuaGraphExport {
+    #[serde(flatten)]
+    options: GraphOptions,
+    /// Workspace-relative output file
+    #[serde(default)]
+    path: Option<String>,
+}
+
+/// A heading given to `tools.entities.update_section_scene`: its text or 1-based index
+fn lua_heading_ref(value: Value) -> LuaResult<HeadingRef> {
+    match value {
+        Value::String(text) => Ok(HeadingRef::Text(text.to_str()?.to_string())),
+        Value::Integer(index) if index > 0 => Ok(HeadingRef::Index(index as usize)),
+        other => Err(mlua::Error::runtime(format!(
+            "update_section_scene: expected a heading's text or a positive index, got {}",
+            other.type_name()
+        ))),
+    }
+}
+
 /// Add utility functions to the Lua environment
 fn add_utilities(lua: &Lua) -> LuaResult<()> {
     let globals = lua.globals();
@@ -399,6 +844,12 @@ fn add_utilities(lua: &Lua) -> LuaResult<()> {
         })?,
     )?;
 
+    // util.uuid() / util.ulid() -> string, shared with EntityStore's ID generation
+    let util = lua.create_table()?;
+    util.set("uuid", lua.create_function(|_, ()| Ok(ids::new_uuid()))?)?;
+    util.set("ulid", lua.create_function(|_, ()| Ok(ids::new_ulid()))?)?;
+    globals.set("util", util)?;
+
     // print() - safe version that just collects output (we'll capture it)
     // For now, just make it a no-op. In the future, we could collect prints.
     globals.set(
@@ -503,6 +954,23 @@ pub fn call_function(
     }
 }
 
+/// Compile a script, then run its top level so the functions it defines exist;
+/// none of them is called
+pub fn load_script(lua: &Lua, script: &str) -> Result<(), String> {
+    let chunk = lua
+        .load(script)
+        .into_function()
+        .map_err(|e| format!("Syntax error: {}", e))?;
+    chunk
+        .call::<()>(())
+        .map_err(|e| format!("Failed to load script: {}", e))
+}
+
+/// Whether `name` is a global function
+pub fn has_function(lua: &Lua, name: &str) -> bool {
+    matches!(lua.globals().get::<Value>(name), Ok(Value::Function(_)))
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -591,6 +1059,21 @@ mod tests {
         assert!(result.contains("hello world"));
     }
 
+    #[test]
+    fn test_read_many_files() {
+        let dir = setup_test_workspace();
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local result = json_decode(tools.read_many_files({"test.txt", "missing.md"}, 1))
+            return result["test.txt"].content .. "|" .. tostring(result["test.txt"].truncated)
+                .. "|" .. result["missing.md"].error
+        "#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert_eq!(result, "hello world\n|true|File not found: missing.md");
+    }
+
     #[test]
     fn test_list_dir() {
         let dir = setup_test_workspace();
@@ -601,6 +1084,10 @@ mod tests {
         let result = execute_script(&lua, script, None).unwrap();
         assert!(result.contains("test.txt"));
         assert!(result.contains("subdir"));
+
+        let script = r#"return tools.list_dir(".", { recursive = true, flat = true })"#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert!(result.contains("subdir/nested.md"), "{}", result);
     }
 
     #[test]
@@ -614,6 +1101,26 @@ mod tests {
         assert!(result.contains("nested.md"));
     }
 
+    #[test]
+    fn test_grep_options() {
+        let dir = setup_test_workspace();
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"return tools.grep("line", "test.txt")"#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert!(result.contains("\"line\": 2"), "{}", result);
+
+        let script = r#"return tools.grep("line", ".", { context_before = 1 })"#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert!(result.contains("1-hello world\\n2:line 2"), "{}", result);
+
+        let script = r#"return tools.grep("title", { path = "subdir", files_only = true })"#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert!(result.contains("\"matches\": 1"), "{}", result);
+        assert!(!result.contains("test.txt"), "{}", result);
+    }
+
     #[test]
     fn test_json_utilities() {
         let ctx = LuaContext::new(Path::new("/tmp"), 30);
@@ -629,6 +1136,26 @@ mod tests {
         assert_eq!(result, "42");
     }
 
+    #[test]
+    fn test_util_ids() {
+        let ctx = LuaContext::new(Path::new("/tmp"), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local seen = {}
+            for i = 1, 100 do
+                local id = util.uuid()
+                if seen[id] then return "duplicate" end
+                seen[id] = true
+            end
+            return util.uuid() .. " " .. util.ulid()
+        "#;
+        let result = execute_script(&lua, script, None).unwrap();
+        let (uuid, ulid) = result.split_once(' ').unwrap();
+        assert!(ids::is_uuid(uuid));
+        assert!(ids::is_ulid(ulid));
+    }
+
     #[test]
     fn test_call_function() {
         let dir = setup_test_workspace();
@@ -660,4 +1187,283 @@ mod tests {
         let result = execute_script(&lua, script, None).unwrap();
         assert!(result.contains("created by lua"));
     }
+
+    #[test]
+    fn test_write_file_size_limit() {
+        let dir = setup_test_workspace();
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        // Scripts go through the same checks as the agent, with the default caps
+        let script = format!(
+            r#"
+            local ok, err = pcall(tools.write_file, "big.txt", string.rep("x", {}))
+            return tostring(ok) .. ": " .. tostring(err)
+        "#,
+            tools::DEFAULT_MAX_FILE_BYTES + 1
+        );
+        let result = execute_script(&lua, &script, None).unwrap();
+        assert!(result.starts_with("false: "), "{}", result);
+        assert!(result.contains("limit is 10.0 MB"), "{}", result);
+        assert!(!dir.path().join("big.txt").exists());
+    }
+
+    #[test]
+    fn test_entities_export_graph() {
+        let dir = setup_test_workspace();
+        std::fs::create_dir(dir.path().join("entities")).unwrap();
+        for id in ["alice", "bob"] {
+            std::fs::write(
+                dir.path().join("entities").join(format!("{}.yaml", id)),
+                format!("id: {}\nname: {}\ntype: concept\n", id, id),
+            )
+            .unwrap();
+        }
+        std::fs::create_dir(dir.path().join("sections")).unwrap();
+        std::fs::write(
+            dir.path().join("sections").join("ch1.md"),
+            "---\nid: ch1\ntitle: One\norder: 1\nentity_ids: [alice, bob]\n---\nText",
+        )
+        .unwrap();
+
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local inline = json_decode(tools.entities.export_graph("dot"))
+            local written = json_decode(tools.entities.export_graph("json", {
+                min_weight = 1,
+                path = "graph.json",
+            }))
+            return inline.content .. "|" .. written.edgeCount
+        "#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert!(result.contains("\"alice\" -- \"bob\""));
+        assert!(result.ends_with("|1"));
+        assert!(dir.path().join("graph.json").exists());
+
+        let escape = r#"return tools.entities.export_graph("dot", { path = "../graph.dot" })"#;
+        assert!(execute_script(&lua, escape, None).is_err());
+    }
+
+    #[test]
+    fn test_entities_find_mentions() {
+        let dir = setup_test_workspace();
+        std::fs::create_dir(dir.path().join("entities")).unwrap();
+        std::fs::write(
+            dir.path().join("entities").join("kat.yaml"),
+            "id: kat\nname: Katherine\ntype: fact\naliases: [Dr. K]\n",
+        )
+        .unwrap();
+        std::fs::create_dir(dir.path().join("sections")).unwrap();
+        std::fs::write(
+            dir.path().join("sections").join("ch1.md"),
+            "---\nid: ch1\ntitle: One\norder: 1\n---\nKatherine waved.\nEveryone called her Dr. K.",
+        )
+        .unwrap();
+
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local report = json_decode(tools.entities.find_mentions("kat"))
+            local found = {}
+            for _, m in ipairs(report.sections[1].matches) do
+                table.insert(found, m.alias_matched .. "@" .. m.line .. ":" .. m.char_offset)
+            end
+            return report.total_matches .. "|" .. table.concat(found, ",")
+        "#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert_eq!(result, "2|Katherine@1:0,Dr. K@2:37");
+
+        let missing = r#"return tools.entities.find_mentions("nobody")"#;
+        let err = execute_script(&lua, missing, None).unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_entities_create_update_delete() {
+        let dir = setup_test_workspace();
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local first = json_decode(tools.entities.create({
+                name = "John Smith",
+                type = "character",
+                aliases = { "John" },
+            }))
+            local second = json_decode(tools.entities.create({ name = "john smith", type = "fact" }))
+            local updated = json_decode(tools.entities.update(first.id, { description = "The baker" }))
+            local deleted = tools.entities.delete(second.id)
+            local again = tools.entities.delete(second.id)
+            return first.type .. "|" .. updated.description .. "|" .. updated.aliases[1]
+                .. "|" .. tostring(deleted) .. "|" .. tostring(again)
+        "#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert_eq!(result, "custom|The baker|John|true|false");
+
+        let entities = dir.path().join("entities");
+        assert!(entities.join("john-smith.yaml").exists());
+        assert!(!entities.join("john-smith-2.yaml").exists());
+
+        let empty = r#"return tools.entities.create({ name = "  ", type = "fact" })"#;
+        let err = execute_script(&lua, empty, None).unwrap_err();
+        assert!(err.contains("name cannot be empty"));
+    }
+
+    #[test]
+    fn test_sections_round_trip() {
+        let dir = setup_test_workspace();
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local one = json_decode(tools.entities.create_section({
+                title = "Chapter One: The Start",
+                content = "It was a dark night.",
+            }))
+            local two = json_decode(tools.entities.create_section({
+                title = "Interlude",
+                parentId = one.id,
+                entityIds = { "alice" },
+            }))
+            tools.entities.update_section_content(two.id, "A short pause.")
+            tools.entities.update_section_metadata(two.id, { title = "Intermission", collapsed = true })
+            return json_encode({ one = one, two = json_decode(tools.entities.get_section(two.id)) })
+        "#;
+        let result: serde_json::Value =
+            serde_json::from_str(&execute_script(&lua, script, None).unwrap()).unwrap();
+        let (one, two) = (&result["one"], &result["two"]);
+
+        assert_eq!(one["order"], 0);
+        assert_eq!(one["alignment"], "left");
+        assert_eq!(two["order"], 1);
+        assert_eq!(two["title"], "Intermission");
+        assert_eq!(two["content"], "A short pause.");
+        assert_eq!(two["parentId"], one["id"]);
+        assert_eq!(two["collapsed"], true);
+        assert_eq!(two["entityIds"][0], "alice");
+
+        let id = one["id"].as_str().unwrap();
+        let file = dir
+            .path()
+            .join("sections")
+            .join(format!("000-chapter-one-the-start-{}.md", &id[..8]));
+        let text = std::fs::read_to_string(file).unwrap();
+        assert!(text.starts_with("---\n"));
+        assert!(text.ends_with("---\nIt was a dark night."));
+
+        let unknown = r#"return tools.entities.update_section_metadata("x", { colour = "red" })"#;
+        assert!(execute_script(&lua, unknown, None).is_err());
+    }
+
+    #[test]
+    fn test_entities_update_section_scene() {
+        let dir = setup_test_workspace();
+        std::fs::create_dir(dir.path().join("sections")).unwrap();
+        let file = dir.path().join("sections").join("ch1.md");
+        std::fs::write(
+            &file,
+            "---\nid: ch1\ntitle: One\norder: 1\n---\nMorning\n=======\nShe woke.\n\nNight\n=====\nShe slept.\n",
+        )
+        .unwrap();
+
+        let ctx = LuaContext::new(dir.path(), 30);
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"
+            local first = json_decode(tools.entities.update_section_scene("ch1", "morning", "She woke late and ran."))
+            local second = json_decode(tools.entities.update_section_scene("ch1", 2, "Sleep."))
+            return first.heading .. ":" .. first.words_before .. ">" .. first.words_after
+                .. "|" .. second.heading .. ":" .. second.words_before .. ">" .. second.words_after
+        "#;
+        let result = execute_script(&lua, script, None).unwrap();
+        assert_eq!(result, "Morning:2>5|Night:2>1");
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "---\nid: ch1\ntitle: One\norder: 1\n---\nMorning\n=======\nShe woke late and ran.\n\nNight\n=====\nSleep.\n"
+        );
+
+        let bad = r#"return tools.entities.update_section_scene("ch1", true, "x")"#;
+        let err = execute_script(&lua, bad, None).unwrap_err();
+        assert!(err.contains("expected a heading"), "{}", err);
+    }
+
+    #[test]
+    fn test_cancel_interrupts_script() {
+        let token = CancellationToken::new();
+        let ctx = LuaContext::new(Path::new("/tmp"), 30).with_cancel(Some(token.clone()));
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                token.cancel();
+            })
+        };
+
+        let started = std::time::Instant::now();
+        let result = execute_script(&lua, "while true do end", None);
+        canceller.join().unwrap();
+
+        let err = result.unwrap_err();
+        assert!(
+            err.contains(tools::TOOL_CANCELLED),
+            "unexpected error: {}",
+            err
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_memory_limit_stops_allocation_bomb() {
+        let ctx = LuaContext::new(Path::new("/tmp"), 30).with_limits(LuaLimits {
+            memory_bytes: Some(16 * 1024 * 1024),
+            instructions: None,
+        });
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let script = r#"local t = {} while true do t[#t+1] = string.rep("x", 1e6) .. #t end"#;
+        let err = execute_script(&lua, script, None).unwrap_err();
+        assert!(err.contains(LUA_OUT_OF_MEMORY), "unexpected error: {}", err);
+
+        // The state is still usable once the garbage is gone
+        lua.gc_collect().unwrap();
+        assert_eq!(execute_script(&lua, "return 1 + 1", None).unwrap(), "2");
+    }
+
+    #[test]
+    fn test_instruction_budget_stops_busy_loop() {
+        let ctx = LuaContext::new(Path::new("/tmp"), 30).with_limits(LuaLimits {
+            memory_bytes: None,
+            instructions: Some(1_000_000),
+        });
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let started = std::time::Instant::now();
+        let err = execute_script(&lua, "while true do end", None).unwrap_err();
+        assert!(
+            err.contains(LUA_INSTRUCTION_BUDGET_EXCEEDED),
+            "unexpected error: {}",
+            err
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_uncancelled_script_runs_to_completion() {
+        let ctx =
+            LuaContext::new(Path::new("/tmp"), 30).with_cancel(Some(CancellationToken::new()));
+        let lua = create_lua_runtime(&ctx).unwrap();
+
+        let result = execute_script(
+            &lua,
+            "local n = 0; for i = 1, 100000 do n = n + 1 end; return n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(result, "100000");
+    }
 }
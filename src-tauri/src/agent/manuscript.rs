@@ -0,0 +1,536 @@
+//! Manuscript export.
+//!
+//! `compile_manuscript` joins the workspace's sections into one Markdown (or HTML)
+//! file. Sections are walked as an outline: top-level sections by `order`, each
+//! followed by its children, so a nested section's heading sits one level below its
+//! parent's. Frontmatter is never copied; a section contributes its title as a
+//! heading and its prose.
+//!
+//! Section files that don't parse are listed in the report rather than left out
+//! quietly, so an export can't lose a chapter without saying so.
+
+use pulldown_cmark::{html, Parser};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use super::atomic_write::write_atomic;
+use super::entity_api::{EntityStore, Section};
+use super::tools::{safe_path, PathValidator};
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Name of the manuscript tool
+pub const COMPILE_MANUSCRIPT_TOOL: &str = "compile_manuscript";
+
+/// Deepest Markdown heading
+const MAX_HEADING_LEVEL: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManuscriptFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+/// How to compile a manuscript
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompileOptions {
+    /// Workspace-relative file to write; replaced if it exists
+    pub output_path: String,
+    pub format: ManuscriptFormat,
+    /// Placed between sections
+    pub separator: String,
+    /// Heading level of top-level section titles, 0 for no titles
+    pub heading_level: u8,
+    /// Leave out collapsed sections, and everything nested under them, when false
+    pub include_collapsed: bool,
+    /// Only compile the sections nested under this one
+    pub parent_id: Option<String>,
+    /// Start with the project name from `project.yaml`
+    pub title_page: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            output_path: String::new(),
+            format: ManuscriptFormat::Markdown,
+            separator: "\n\n".to_string(),
+            heading_level: 1,
+            include_collapsed: true,
+            parent_id: None,
+            title_page: false,
+        }
+    }
+}
+
+/// A section file that couldn't be compiled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedSection {
+    /// Workspace-relative path
+    pub path: String,
+    pub error: String,
+}
+
+/// What `compile_manuscript` wrote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompileReport {
+    pub output_path: String,
+    pub format: ManuscriptFormat,
+    pub bytes: usize,
+    /// IDs of the compiled sections, in manuscript order
+    pub sections: Vec<String>,
+    /// IDs of collapsed sections (and their children) that were left out
+    pub excluded: Vec<String>,
+    /// Section files that failed to parse
+    pub failed: Vec<FailedSection>,
+}
+
+/// Compile the sections of `workspace` and write the result to
+/// `options.output_path`
+pub fn compile_manuscript(
+    workspace: &Path,
+    options: &CompileOptions,
+) -> Result<CompileReport, String> {
+    if options.output_path.trim().is_empty() {
+        return Err("Missing 'output_path' parameter".to_string());
+    }
+    let target = safe_path(workspace, &options.output_path)?;
+    let (text, report) = render(workspace, &EntityStore::new(workspace), options)?;
+    write_output(&target, &text)?;
+    Ok(report)
+}
+
+/// The compiled text and its report, without writing anything
+fn render(
+    workspace: &Path,
+    store: &EntityStore,
+    options: &CompileOptions,
+) -> Result<(String, CompileReport), String> {
+    if options.heading_level > MAX_HEADING_LEVEL {
+        return Err(format!(
+            "heading_level must be between 0 and {}",
+            MAX_HEADING_LEVEL
+        ));
+    }
+
+    let sections = store.list_all_sections()?;
+    let failed = store
+        .list_section_failures()?
+        .into_iter()
+        .map(|(path, error)| FailedSection {
+            path: path
+                .strip_prefix(workspace)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/"),
+            error,
+        })
+        .collect();
+
+    let parent = options.parent_id.as_deref().filter(|p| !p.is_empty());
+    if let Some(parent_id) = parent {
+        if !sections.iter().any(|s| s.id == parent_id) {
+            return Err(format!("Section {} not found", parent_id));
+        }
+    }
+
+    let mut parts = Vec::new();
+    if options.title_page {
+        let level = options.heading_level.max(1) as usize;
+        parts.push(format!(
+            "{} {}",
+            "#".repeat(level),
+            project_name(workspace)?
+        ));
+    }
+
+    let mut compiled = Vec::new();
+    let mut excluded = Vec::new();
+    for (section, depth) in outline(&sections, parent) {
+        if excluded
+            .iter()
+            .any(|id| Some(id) == section.parent_id.as_ref())
+            || (section.collapsed && !options.include_collapsed)
+        {
+            excluded.push(section.id.clone());
+            continue;
+        }
+        let text = section_text(section, depth, options.heading_level);
+        if !text.is_empty() {
+            parts.push(text);
+        }
+        compiled.push(section.id.clone());
+    }
+
+    let mut markdown = parts.join(&options.separator);
+    markdown.push('\n');
+    let text = match options.format {
+        ManuscriptFormat::Markdown => markdown,
+        ManuscriptFormat::Html => {
+            let mut out = String::new();
+            html::push_html(&mut out, Parser::new(&markdown));
+            out
+        }
+    };
+
+    let report = CompileReport {
+        output_path: options.output_path.clone(),
+        format: options.format,
+        bytes: text.len(),
+        sections: compiled,
+        excluded,
+        failed,
+    };
+    Ok((text, report))
+}
+
+/// Sections in reading order with their depth below `parent` (or the top
+/// level). A section whose parent is missing counts as top level, and one
+/// caught in a parent cycle is placed after the rest rather than dropped.
+fn outline<'a>(sections: &'a [Section], parent: Option<&str>) -> Vec<(&'a Section, usize)> {
+    let ids: HashSet<&str> = sections.iter().map(|s| s.id.as_str()).collect();
+    let mut children: HashMap<Option<&str>, Vec<&Section>> = HashMap::new();
+    for section in sections {
+        let parent_id = section
+            .parent_id
+            .as_deref()
+            .filter(|p| !p.is_empty() && ids.contains(p));
+        children.entry(parent_id).or_default().push(section);
+    }
+
+    let mut ordered = Vec::new();
+    let mut seen = HashSet::new();
+    match parent.and_then(|p| sections.iter().find(|s| s.id == p)) {
+        Some(parent) => {
+            seen.insert(parent.id.as_str());
+            walk(&children, Some(&parent.id), 0, &mut seen, &mut ordered);
+        }
+        None => {
+            walk(&children, None, 0, &mut seen, &mut ordered);
+            for section in sections {
+                if !seen.contains(section.id.as_str()) {
+                    seen.insert(&section.id);
+                    ordered.push((section, 0));
+                    walk(&children, Some(&section.id), 1, &mut seen, &mut ordered);
+                }
+            }
+        }
+    }
+    ordered
+}
+
+fn walk<'a>(
+    children: &HashMap<Option<&'a str>, Vec<&'a Section>>,
+    parent: Option<&'a str>,
+    depth: usize,
+    seen: &mut HashSet<&'a str>,
+    ordered: &mut Vec<(&'a Section, usize)>,
+) {
+    // Sections arrive sorted by order, so siblings keep that order
+    for &section in children.get(&parent).into_iter().flatten() {
+        if seen.insert(&section.id) {
+            ordered.push((section, depth));
+            walk(children, Some(&section.id), depth + 1, seen, ordered);
+        }
+    }
+}
+
+/// One section as Markdown: its title as a heading, then its prose
+fn section_text(section: &Section, depth: usize, heading_level: u8) -> String {
+    let body = section.content.trim_end();
+    if heading_level == 0 {
+        return body.to_string();
+    }
+    let level = (heading_level as usize + depth).min(MAX_HEADING_LEVEL as usize);
+    let heading = format!("{} {}", "#".repeat(level), section.title);
+    if body.is_empty() {
+        heading
+    } else {
+        format!("{}\n\n{}", heading, body)
+    }
+}
+
+/// The project name from `project.yaml`
+fn project_name(workspace: &Path) -> Result<String, String> {
+    let raw = fs::read_to_string(workspace.join("project.yaml"))
+        .map_err(|e| format!("Failed to read project.yaml for the title page: {}", e))?;
+    let project: serde_yaml::Value =
+        serde_yaml::from_str(&raw).map_err(|e| format!("Failed to parse project.yaml: {}", e))?;
+    project
+        .get("name")
+        .and_then(|n| n.as_str())
+        .filter(|n| !n.trim().is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| "project.yaml has no name for the title page".to_string())
+}
+
+fn write_output(target: &Path, text: &str) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directories: {}", e))?;
+        }
+    }
+    write_atomic(target, text.as_bytes()).map_err(|e| format!("Failed to write manuscript: {}", e))
+}
+
+// ============================================================================
+// Tool Interface
+// ============================================================================
+
+/// Run a `compile_manuscript` tool call
+pub fn dispatch(paths: &PathValidator, args: &serde_json::Value) -> Result<String, String> {
+    let options: CompileOptions = serde_json::from_value(args.clone())
+        .map_err(|e| format!("Invalid compile_manuscript arguments: {}", e))?;
+    if options.output_path.trim().is_empty() {
+        return Err("Missing 'output_path' parameter".to_string());
+    }
+    let target = paths.resolve(&options.output_path)?;
+    let (text, report) = render(paths.workspace(), &paths.entity_store(), &options)?;
+    write_output(&target, &text)?;
+    paths.invalidate(&target);
+
+    serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Failed to serialize manuscript report: {}", e))
+}
+
+fn property(
+    prop_type: &str,
+    description: &str,
+    default: Option<serde_json::Value>,
+) -> PropertySchema {
+    PropertySchema {
+        prop_type: prop_type.to_string(),
+        description: Some(description.to_string()),
+        default,
+        items: None,
+    }
+}
+
+/// Schema for the manuscript tool
+pub fn compile_manuscript_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "output_path".to_string(),
+        property(
+            "string",
+            "Workspace-relative file to write, e.g. exports/manuscript.md. Replaced if it exists.",
+            None,
+        ),
+    );
+    properties.insert(
+        "format".to_string(),
+        property(
+            "string",
+            "\"markdown\" or \"html\"",
+            Some(serde_json::json!("markdown")),
+        ),
+    );
+    properties.insert(
+        "separator".to_string(),
+        property(
+            "string",
+            "Text placed between sections",
+            Some(serde_json::json!("\n\n")),
+        ),
+    );
+    properties.insert(
+        "heading_level".to_string(),
+        property(
+            "integer",
+            "Heading level for top-level section titles; nested sections go one level deeper. 0 leaves titles out.",
+            Some(serde_json::json!(1)),
+        ),
+    );
+    properties.insert(
+        "include_collapsed".to_string(),
+        property(
+            "boolean",
+            "Whether collapsed sections (and the sections nested under them) are included",
+            Some(serde_json::json!(true)),
+        ),
+    );
+    properties.insert(
+        "parent_id".to_string(),
+        property(
+            "string",
+            "Only compile the sections nested under this section",
+            None,
+        ),
+    );
+    properties.insert(
+        "title_page".to_string(),
+        property(
+            "boolean",
+            "Start with the project name from project.yaml",
+            Some(serde_json::json!(false)),
+        ),
+    );
+
+    Tool::new(
+        COMPILE_MANUSCRIPT_TOOL,
+        "Join the workspace's sections, in outline order, into one Markdown or HTML file. Frontmatter is left out; each section's title becomes a heading. Returns the compiled section IDs and any section files that failed to parse.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["output_path".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn section(dir: &Path, file: &str, id: &str, order: i64, extra: &str, content: &str) {
+        fs::write(
+            dir.join("sections").join(file),
+            format!(
+                "---\nid: {}\ntitle: {}\norder: {}\n{}---\n{}",
+                id,
+                id.to_uppercase(),
+                order,
+                extra,
+                content
+            ),
+        )
+        .unwrap();
+    }
+
+    /// part-one (chapter-b, chapter-a > scene), part-two (collapsed), and a broken file
+    fn setup_workspace() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir(root.join("sections")).unwrap();
+        fs::write(root.join("project.yaml"), "name: The Long Way\n").unwrap();
+        section(root, "a.md", "part-two", 1, "collapsed: true\n", "Later.\n");
+        section(root, "b.md", "chapter-a", 1, "parent_id: part-one\n", "A.");
+        section(root, "c.md", "scene", 0, "parent_id: chapter-a\n", "Scene.");
+        section(root, "d.md", "part-one", 0, "", "");
+        section(root, "e.md", "chapter-b", 0, "parent_id: part-one\n", "B.");
+        section(root, "f.md", "epilogue", 0, "parent_id: part-two\n", "End.");
+        fs::write(root.join("sections/g.md"), "---\nid: [unclosed\n---\nLost.").unwrap();
+        dir
+    }
+
+    fn options(output_path: &str) -> CompileOptions {
+        CompileOptions {
+            output_path: output_path.to_string(),
+            ..CompileOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_nested_sections_in_outline_order() {
+        let dir = setup_workspace();
+        let report = compile_manuscript(dir.path(), &options("out/book.md")).unwrap();
+
+        assert_eq!(
+            report.sections,
+            [
+                "part-one",
+                "chapter-b",
+                "chapter-a",
+                "scene",
+                "part-two",
+                "epilogue"
+            ]
+        );
+        let text = fs::read_to_string(dir.path().join("out/book.md")).unwrap();
+        assert_eq!(report.bytes, text.len());
+        assert!(text.starts_with(
+            "# PART-ONE\n\n## CHAPTER-B\n\nB.\n\n## CHAPTER-A\n\nA.\n\n### SCENE\n\nScene.\n\n# PART-TWO\n\nLater.\n\n## EPILOGUE"
+        ), "{}", text);
+        assert!(!text.contains("order:"), "{}", text);
+    }
+
+    #[test]
+    fn test_unparseable_section_is_reported() {
+        let dir = setup_workspace();
+        let report = compile_manuscript(dir.path(), &options("book.md")).unwrap();
+
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].path, "sections/g.md");
+        assert!(
+            report.failed[0].error.contains("frontmatter"),
+            "{}",
+            report.failed[0].error
+        );
+        let text = fs::read_to_string(dir.path().join("book.md")).unwrap();
+        assert!(!text.contains("Lost."));
+    }
+
+    #[test]
+    fn test_options_filter_and_render() {
+        let dir = setup_workspace();
+
+        let report = compile_manuscript(
+            dir.path(),
+            &CompileOptions {
+                include_collapsed: false,
+                heading_level: 0,
+                separator: "\n\n* * *\n\n".to_string(),
+                title_page: true,
+                ..options("book.md")
+            },
+        )
+        .unwrap();
+        assert_eq!(report.excluded, ["part-two", "epilogue"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("book.md")).unwrap(),
+            "# The Long Way\n\n* * *\n\nB.\n\n* * *\n\nA.\n\n* * *\n\nScene.\n"
+        );
+
+        let report = compile_manuscript(
+            dir.path(),
+            &CompileOptions {
+                parent_id: Some("chapter-a".to_string()),
+                format: ManuscriptFormat::Html,
+                ..options("book.html")
+            },
+        )
+        .unwrap();
+        assert_eq!(report.sections, ["scene"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("book.html")).unwrap(),
+            "<h1>SCENE</h1>\n<p>Scene.</p>\n"
+        );
+
+        let missing = CompileOptions {
+            parent_id: Some("nope".to_string()),
+            ..options("book.md")
+        };
+        assert!(compile_manuscript(dir.path(), &missing).is_err());
+        assert!(compile_manuscript(dir.path(), &options("../outside.md")).is_err());
+        assert!(compile_manuscript(dir.path(), &options("")).is_err());
+    }
+
+    #[test]
+    fn test_dispatch_writes_through_validator() {
+        let dir = setup_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let output = dispatch(
+            &paths,
+            &serde_json::json!({"output_path": "book.md", "heading_level": 2}),
+        )
+        .unwrap();
+        let report: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(report["sections"][0], "part-one");
+        assert_eq!(report["failed"][0]["path"], "sections/g.md");
+        let text = fs::read_to_string(dir.path().join("book.md")).unwrap();
+        assert!(text.starts_with("## PART-ONE\n\n### CHAPTER-B"), "{}", text);
+
+        let err = dispatch(&paths, &serde_json::json!({"format": "pdf"})).unwrap_err();
+        assert!(err.contains("Invalid"), "{}", err);
+    }
+}
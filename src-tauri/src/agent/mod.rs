@@ -3,18 +3,61 @@
 //! This module implements a tool-calling LLM agent with multi-provider support.
 //! It provides file operations, shell execution, and LLM integration for the writing assistant.
 
+pub mod approvals;
+pub mod atomic_write;
+pub mod backups;
+pub mod context;
 pub mod core;
 pub mod credentials;
 pub mod doctor;
 pub mod entity_api;
+pub mod entity_graph;
+pub mod entity_schemas;
+pub mod entity_tags;
+pub mod event_channel;
+pub mod extension_stats;
+pub mod git_tools;
+pub mod global_config;
+pub mod hook_directives;
+pub mod idle;
+pub mod ids;
+pub mod ignore_rules;
+pub mod injection_guard;
 pub mod llm;
 pub mod lua_extensions;
 pub mod lua_runtime;
+pub mod manuscript;
+pub mod ollama;
+pub mod preview;
+pub mod pricing;
+pub mod prompt_templates;
+pub mod redact;
+pub mod scaffold;
+pub mod section_edits;
+pub mod section_meta;
+pub mod section_scenes;
+pub mod semantic_index;
+pub mod sensitive_paths;
 pub mod session;
+pub mod session_report;
+pub mod shell_env;
+pub mod shell_policy;
+pub mod shell_program;
+pub mod shutdown;
+pub mod signature;
+pub mod staged_writes;
+pub mod text;
+pub mod tool_args;
+pub mod tool_gating;
+pub mod tool_output;
 pub mod tools;
+pub mod transcripts;
+pub mod trust_store;
 pub mod types;
+pub mod watcher;
+pub mod workspaces;
 
 // Re-export main types and functions for convenience
-pub use core::run_agent;
-pub use core::ToolApprovalStore;
+pub use approvals::{ApprovalAnswer, ToolApprovalStore};
+pub use core::{apply_plan, resume_agent, run_agent, RunContext};
 pub use types::{AgentConfig, AgentEvent, LlmProvider, Message, MessageRole};
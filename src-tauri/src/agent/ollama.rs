@@ -0,0 +1,653 @@
+//! Ollama model management.
+//!
+//! Local-model users often configure a model that hasn't been pulled yet. This module
+//! lists installed models through `/api/tags`, pulls models through Ollama's
+//! `/api/pull` endpoint, forwarding streamed progress, and recognises the "model not
+//! found" error that `/api/chat` returns for them (and the error for models that
+//! can't take tools).
+
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use super::types::{AgentError, ErrorCode};
+
+/// Tauri event name for pull progress updates
+pub const PULL_PROGRESS_EVENT: &str = "ollama-pull-progress";
+
+/// Error returned when a pull is cancelled
+pub const PULL_CANCELLED: &str = "Pull cancelled";
+
+/// How long listing models waits for the server to answer
+const LIST_MODELS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// An installed model, as listed by `/api/tags`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OllamaModel {
+    pub name: String,
+    /// Size on disk in bytes
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<String>,
+}
+
+/// What a local Ollama server has installed. A server that doesn't answer is
+/// reported here as unreachable rather than as an error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OllamaStatus {
+    pub base_url: String,
+    /// Whether the server answered at all
+    pub reachable: bool,
+    /// Installed models, in the server's order
+    pub models: Vec<OllamaModel>,
+    /// The configured model that was asked about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Whether `model` is installed; unknown if the models couldn't be listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_installed: Option<bool>,
+    /// Why the models couldn't be listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What the user can do about it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+/// `/api/tags` response body
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<OllamaModel>,
+}
+
+/// Parse the model list returned by `/api/tags`
+pub fn parse_tags(body: &str) -> Result<Vec<OllamaModel>, String> {
+    serde_json::from_str::<TagsResponse>(body)
+        .map(|tags| tags.models)
+        .map_err(|e| format!("Failed to parse Ollama model list: {}", e))
+}
+
+/// Whether `model` is among `models`, treating an untagged name as `:latest`
+pub fn has_model(models: &[OllamaModel], model: &str) -> bool {
+    let key = pull_key(model);
+    models.iter().any(|m| pull_key(&m.name) == key)
+}
+
+/// List the models installed on the server at `base_url`, and whether `model`
+/// (the configured one, if given) is among them
+pub async fn list_models(client: &Client, base_url: &str, model: Option<&str>) -> OllamaStatus {
+    let mut status = OllamaStatus {
+        base_url: base_url.to_string(),
+        reachable: false,
+        models: Vec::new(),
+        model: model.map(str::to_string),
+        model_installed: None,
+        error: None,
+        hint: None,
+    };
+
+    let url = format!("{}/api/tags", base_url.trim_end_matches('/'));
+    let response = match client.get(&url).timeout(LIST_MODELS_TIMEOUT).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            status.error = Some(if e.is_timeout() {
+                format!("No response within {} s", LIST_MODELS_TIMEOUT.as_secs())
+            } else {
+                format!("Not reachable: {}", e)
+            });
+            status.hint =
+                Some("Start Ollama (`ollama serve`) or check its base URL in Settings".to_string());
+            return status;
+        }
+    };
+    status.reachable = true;
+
+    let listed = if response.status().is_success() {
+        match response.text().await {
+            Ok(body) => parse_tags(&body),
+            Err(e) => Err(format!("Failed to read Ollama model list: {}", e)),
+        }
+    } else {
+        Err(format!("HTTP {}", response.status()))
+    };
+    match listed {
+        Ok(models) => {
+            if let Some(model) = model {
+                let installed = has_model(&models, model);
+                if !installed {
+                    status.hint = Some(format!(
+                        "Pull it from the app (pull_ollama_model) or run `ollama pull {}`",
+                        model
+                    ));
+                }
+                status.model_installed = Some(installed);
+            }
+            status.models = models;
+        }
+        Err(error) => status.error = Some(error),
+    }
+    status
+}
+
+/// A progress update from an in-flight model pull
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PullProgress {
+    /// The model being pulled
+    pub model: String,
+    /// Ollama's status line (e.g. "pulling manifest", "success")
+    pub status: String,
+    /// Layer digest being downloaded, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Bytes downloaded so far for this layer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+    /// Total bytes for this layer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+}
+
+impl PullProgress {
+    /// Whether this is the final update of a successful pull
+    pub fn is_success(&self) -> bool {
+        self.status == "success"
+    }
+}
+
+/// One line of Ollama's streamed `/api/pull` response
+#[derive(Debug, Deserialize)]
+struct PullLine {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    digest: Option<String>,
+    #[serde(default)]
+    total: Option<u64>,
+    #[serde(default)]
+    completed: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Parse one NDJSON line from `/api/pull`.
+///
+/// Blank lines yield `Ok(None)`; lines carrying an `error` field become `Err`.
+pub fn parse_pull_line(model: &str, line: &str) -> Result<Option<PullProgress>, String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let parsed: PullLine = serde_json::from_str(line)
+        .map_err(|e| format!("Failed to parse Ollama pull progress: {}", e))?;
+
+    if let Some(error) = parsed.error {
+        return Err(format!("Ollama pull failed: {}", error));
+    }
+
+    Ok(Some(PullProgress {
+        model: model.to_string(),
+        status: parsed.status.unwrap_or_default(),
+        digest: parsed.digest,
+        completed: parsed.completed,
+        total: parsed.total,
+    }))
+}
+
+/// Whether an `/api/chat` error response means the model isn't pulled
+pub fn is_model_not_found(status: StatusCode, body: &str) -> bool {
+    if status != StatusCode::NOT_FOUND {
+        return false;
+    }
+    let body = body.to_lowercase();
+    body.contains("model") && body.contains("not found")
+}
+
+/// Whether an `/api/chat` error says the model can't take a `tools` field
+/// (e.g. `{"error":"... does not support tools"}`)
+pub fn is_tools_unsupported(status: StatusCode, body: &str) -> bool {
+    status == StatusCode::BAD_REQUEST && body.to_lowercase().contains("does not support tools")
+}
+
+/// Model-not-found error pointing the user at the pull command
+pub fn model_not_found_error(model: &str) -> AgentError {
+    AgentError::Provider {
+        code: ErrorCode::ModelNotFound,
+        provider: "Ollama".to_string(),
+        message: format!(
+            "Ollama model '{}' is not installed. Pull it from the app (pull_ollama_model) or run `ollama pull {}`.",
+            model, model
+        ),
+    }
+}
+
+/// Key used to deduplicate pulls: Ollama treats an untagged name as `:latest`
+fn pull_key(model: &str) -> String {
+    let model = model.trim();
+    let name = model.rsplit('/').next().unwrap_or(model);
+    if name.contains(':') {
+        model.to_string()
+    } else {
+        format!("{}:latest", model)
+    }
+}
+
+/// In-flight pulls by model, so each model is pulled at most once at a time
+#[derive(Clone, Default)]
+pub struct OllamaPulls {
+    active: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl OllamaPulls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a pull for `model`, failing if one is already running.
+    ///
+    /// The returned guard unregisters the pull when dropped.
+    pub fn begin(&self, model: &str) -> Result<PullGuard, String> {
+        let key = pull_key(model);
+        let mut active = self
+            .active
+            .lock()
+            .map_err(|e| format!("Failed to lock Ollama pulls: {}", e))?;
+
+        if active.contains_key(&key) {
+            return Err(format!(
+                "A pull for Ollama model '{}' is already in progress",
+                model
+            ));
+        }
+
+        let token = CancellationToken::new();
+        active.insert(key.clone(), token.clone());
+
+        Ok(PullGuard {
+            pulls: self.clone(),
+            key,
+            token,
+        })
+    }
+
+    /// Cancel the pull for `model`. Returns false if none was running.
+    pub fn cancel(&self, model: &str) -> bool {
+        let active = match self.active.lock() {
+            Ok(a) => a,
+            Err(_) => return false,
+        };
+        match active.get(&pull_key(model)) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Registration of a running pull; unregisters on drop
+pub struct PullGuard {
+    pulls: OllamaPulls,
+    key: String,
+    token: CancellationToken,
+}
+
+impl PullGuard {
+    /// Token that is cancelled when the pull should stop
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for PullGuard {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.pulls.active.lock() {
+            active.remove(&self.key);
+        }
+    }
+}
+
+/// Pull `model` from the Ollama server at `base_url`, calling `on_progress` for each update.
+///
+/// Returns `Err(PULL_CANCELLED)` if `cancel` fires before the pull finishes.
+pub async fn pull_model<F>(
+    client: &Client,
+    base_url: &str,
+    model: &str,
+    cancel: &CancellationToken,
+    mut on_progress: F,
+) -> Result<(), String>
+where
+    F: FnMut(PullProgress),
+{
+    let url = format!("{}/api/pull", base_url.trim_end_matches('/'));
+    let request = serde_json::json!({ "model": model, "stream": true });
+
+    let send = client.post(&url).json(&request).send();
+    let mut response = tokio::select! {
+        _ = cancel.cancelled() => return Err(PULL_CANCELLED.to_string()),
+        res = send => res.map_err(|e| format!("Ollama pull request failed: {}", e))?,
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(format!("Ollama pull failed ({}): {}", status, error_text));
+    }
+
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut succeeded = false;
+
+    loop {
+        let chunk = tokio::select! {
+            _ = cancel.cancelled() => return Err(PULL_CANCELLED.to_string()),
+            chunk = response.chunk() => {
+                chunk.map_err(|e| format!("Ollama pull stream failed: {}", e))?
+            }
+        };
+
+        let Some(chunk) = chunk else {
+            break;
+        };
+        buffer.extend_from_slice(&chunk);
+
+        // Progress is newline-delimited JSON; a line may span several chunks
+        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            if let Some(progress) = parse_pull_line(model, &String::from_utf8_lossy(&line))? {
+                succeeded |= progress.is_success();
+                on_progress(progress);
+            }
+        }
+    }
+
+    if let Some(progress) = parse_pull_line(model, &String::from_utf8_lossy(&buffer))? {
+        succeeded |= progress.is_success();
+        on_progress(progress);
+    }
+
+    if succeeded {
+        Ok(())
+    } else {
+        Err(format!(
+            "Ollama pull for '{}' ended without reporting success",
+            model
+        ))
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::llm::LlmClient;
+    use crate::agent::types::{AgentConfig, LlmProvider, Message};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve a single HTTP request, replying with `head` followed by `body_parts`
+    /// written one at a time. Returns the base URL of the server.
+    async fn serve_once(head: &'static str, body_parts: Vec<&'static str>, hang: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+
+            // Read the request headers and body so the client isn't reset mid-send
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let content_length = text[..end]
+                        .lines()
+                        .find_map(|l| {
+                            let (name, value) = l.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())
+                                .flatten()
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= end + 4 + content_length {
+                        break;
+                    }
+                }
+            }
+
+            socket.write_all(head.as_bytes()).await.unwrap();
+            for part in body_parts {
+                socket.write_all(part.as_bytes()).await.unwrap();
+                socket.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            if hang {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    const STREAM_HEAD: &str =
+        "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n";
+
+    #[test]
+    fn test_parse_pull_line() {
+        let progress = parse_pull_line(
+            "llama3",
+            r#"{"status":"pulling 6a0746a1ec1a","digest":"sha256:6a07","total":4661211424,"completed":241970}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(progress.model, "llama3");
+        assert_eq!(progress.status, "pulling 6a0746a1ec1a");
+        assert_eq!(progress.digest.as_deref(), Some("sha256:6a07"));
+        assert_eq!(progress.completed, Some(241970));
+        assert_eq!(progress.total, Some(4661211424));
+        assert!(!progress.is_success());
+
+        assert!(parse_pull_line("llama3", "  \n").unwrap().is_none());
+        assert!(parse_pull_line("llama3", r#"{"status":"success"}"#)
+            .unwrap()
+            .unwrap()
+            .is_success());
+
+        let err = parse_pull_line(
+            "nope",
+            r#"{"error":"pull model manifest: file does not exist"}"#,
+        )
+        .unwrap_err();
+        assert!(err.contains("file does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_list_models_checks_configured_model() {
+        let body = r#"{"models":[{"name":"llama3.2:latest","model":"llama3.2:latest","modified_at":"2024-10-01T12:00:00Z","size":2019393189,"digest":"a80c4f17"},{"name":"mistral:7b","size":4113301824}]}"#;
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n";
+        let base_url = serve_once(head, vec![body], false).await;
+
+        let status = list_models(&Client::new(), &base_url, Some("llama3.2")).await;
+        assert!(status.reachable);
+        assert!(status.error.is_none());
+        assert_eq!(status.models.len(), 2);
+        assert_eq!(status.models[0].size, 2019393189);
+        assert_eq!(status.model_installed, Some(true));
+
+        assert!(has_model(&status.models, "mistral:7b"));
+        assert!(!has_model(&status.models, "mistral"));
+    }
+
+    #[tokio::test]
+    async fn test_list_models_unreachable_is_a_status() {
+        // Bind then drop a listener so nothing answers on the port
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let status = list_models(&Client::new(), &base_url, Some("llama3")).await;
+        assert!(!status.reachable);
+        assert!(status.models.is_empty());
+        assert_eq!(status.model_installed, None);
+        assert!(status.error.is_some());
+        assert!(status.hint.unwrap().contains("ollama serve"));
+    }
+
+    #[test]
+    fn test_is_model_not_found() {
+        let body = r#"{"error":"model \"llama3\" not found, try pulling it first"}"#;
+        assert!(is_model_not_found(StatusCode::NOT_FOUND, body));
+        assert!(!is_model_not_found(StatusCode::INTERNAL_SERVER_ERROR, body));
+        assert!(!is_model_not_found(
+            StatusCode::NOT_FOUND,
+            "404 page not found"
+        ));
+    }
+
+    #[test]
+    fn test_is_tools_unsupported() {
+        let body = r#"{"error":"registry.ollama.ai/library/gemma2:latest does not support tools"}"#;
+        assert!(is_tools_unsupported(StatusCode::BAD_REQUEST, body));
+        assert!(!is_tools_unsupported(StatusCode::NOT_FOUND, body));
+        assert!(!is_tools_unsupported(
+            StatusCode::BAD_REQUEST,
+            r#"{"error":"invalid options"}"#
+        ));
+    }
+
+    #[test]
+    fn test_one_pull_per_model() {
+        let pulls = OllamaPulls::new();
+        let guard = pulls.begin("llama3").unwrap();
+
+        // The untagged name and its :latest form are the same model
+        assert!(pulls.begin("llama3:latest").is_err());
+        let other = pulls.begin("mistral").unwrap();
+
+        assert!(pulls.cancel("llama3"));
+        assert!(guard.token().is_cancelled());
+        assert!(!other.token().is_cancelled());
+
+        drop(guard);
+        assert!(!pulls.cancel("llama3"));
+        assert!(pulls.begin("llama3").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pull_model_streams_progress() {
+        // Lines are split across writes to exercise buffering
+        let base_url = serve_once(
+            STREAM_HEAD,
+            vec![
+                "{\"status\":\"pulling manifest\"}\n{\"status\":\"pulling ab",
+                "c\",\"digest\":\"sha256:abc\",\"total\":100,\"completed\":40}\n",
+                "{\"status\":\"pulling abc\",\"digest\":\"sha256:abc\",\"total\":100,\"completed\":100}\n",
+                "{\"status\":\"success\"}",
+            ],
+            false,
+        )
+        .await;
+
+        let mut updates = Vec::new();
+        let cancel = CancellationToken::new();
+        pull_model(&Client::new(), &base_url, "llama3", &cancel, |p| {
+            updates.push(p)
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[0].status, "pulling manifest");
+        assert_eq!(updates[1].completed, Some(40));
+        assert_eq!(updates[1].total, Some(100));
+        assert_eq!(updates[2].completed, Some(100));
+        assert!(updates[3].is_success());
+    }
+
+    #[tokio::test]
+    async fn test_pull_model_reports_stream_error() {
+        let base_url = serve_once(
+            STREAM_HEAD,
+            vec!["{\"status\":\"pulling manifest\"}\n{\"error\":\"pull model manifest: file does not exist\"}\n"],
+            false,
+        )
+        .await;
+
+        let cancel = CancellationToken::new();
+        let err = pull_model(&Client::new(), &base_url, "missing", &cancel, |_| {})
+            .await
+            .unwrap_err();
+        assert!(err.contains("file does not exist"));
+    }
+
+    #[tokio::test]
+    async fn test_pull_model_cancel() {
+        let base_url = serve_once(
+            STREAM_HEAD,
+            vec!["{\"status\":\"pulling manifest\"}\n"],
+            true,
+        )
+        .await;
+
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        let mut updates = 0;
+        let result = pull_model(&Client::new(), &base_url, "llama3", &cancel, |_| {
+            updates += 1;
+            canceller.cancel();
+        })
+        .await;
+
+        assert_eq!(result.unwrap_err(), PULL_CANCELLED);
+        assert_eq!(updates, 1);
+    }
+
+    #[tokio::test]
+    async fn test_chat_ollama_model_not_found_is_config_error() {
+        let base_url = serve_once(
+            "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: 60\r\nConnection: close\r\n\r\n",
+            vec![r#"{"error":"model \"llama3\" not found, try pulling it first"}"#],
+            false,
+        )
+        .await;
+
+        let config = AgentConfig {
+            provider: LlmProvider::Ollama,
+            model: "llama3".to_string(),
+            base_url: Some(base_url),
+            ..AgentConfig::default()
+        };
+        let client = LlmClient::new(config);
+
+        let err = client
+            .chat(&[Message::user("hello")], None, None)
+            .await
+            .unwrap_err();
+        match err {
+            AgentError::Provider { code, message, .. } => {
+                assert_eq!(code, ErrorCode::ModelNotFound);
+                assert!(message.contains("llama3"));
+                assert!(message.contains("ollama pull"));
+            }
+            other => panic!("expected ModelNotFound, got {:?}", other),
+        }
+    }
+}
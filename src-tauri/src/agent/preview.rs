@@ -0,0 +1,390 @@
+//! Previews of file changes for tool approval prompts.
+//!
+//! Approving a `write_file` call from its raw arguments means reading the whole new
+//! content with no idea what it replaces. For the file-changing tools this builds a
+//! unified diff against what's on disk (or a short summary when there is nothing
+//! sensible to diff), capped so a huge paste can't blow up the approval event.
+
+use std::fs;
+
+use super::text::truncate_to_char_boundary;
+use super::tools::{apply_edit, PathValidator};
+
+/// Largest preview sent with an approval request
+pub const MAX_PREVIEW_BYTES: usize = 16 * 1024;
+
+/// Files or contents larger than this are summarized instead of diffed
+const MAX_DIFF_INPUT_BYTES: usize = 1024 * 1024;
+
+/// Largest changed region (old lines x new lines) diffed line by line; anything
+/// bigger is shown as a full removal followed by a full insertion
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Lines of a file shown when approving its deletion
+const DELETE_PREVIEW_LINES: usize = 20;
+
+/// Preview of what a file-changing tool call would do, if `name` is one
+pub fn approval_preview(
+    paths: &PathValidator,
+    name: &str,
+    args: &serde_json::Value,
+) -> Option<String> {
+    if !matches!(
+        name,
+        "write_file" | "append_file" | "edit_file" | "delete_file"
+    ) {
+        return None;
+    }
+    let path = args.get("path").and_then(|v| v.as_str())?;
+    let str_arg = |key: &str| args.get(key).and_then(|v| v.as_str());
+
+    let preview = match (name, read_existing(paths, path)) {
+        ("write_file", existing) => {
+            let content = str_arg("content")?;
+            match existing {
+                Existing::Missing => format!("new file, {} bytes", content.len()),
+                existing => diff_or_summary(path, existing, content),
+            }
+        }
+        ("append_file", existing) => {
+            let content = str_arg("content")?;
+            match existing {
+                Existing::Missing => format!("new file, {} bytes", content.len()),
+                Existing::Text(old) => {
+                    let appended = format!("{}{}", old, content);
+                    diff_or_summary(path, Existing::Text(old), &appended)
+                }
+                Existing::TooLarge(size) => format!(
+                    "{}: appends {} bytes to {} bytes",
+                    path,
+                    content.len(),
+                    size
+                ),
+            }
+        }
+        ("edit_file", Existing::Text(old)) => {
+            let (updated, _) = apply_edit(
+                &old,
+                path,
+                str_arg("old_string").filter(|s| !s.is_empty())?,
+                str_arg("new_string")?,
+                args.get("replace_all")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+            )
+            .ok()?;
+            diff_or_summary(path, Existing::Text(old), &updated)
+        }
+        ("delete_file", Existing::Text(old)) => delete_preview(&old),
+        ("delete_file", Existing::TooLarge(size)) => format!("deletes {} bytes", size),
+        _ => return None,
+    };
+    Some(cap(preview))
+}
+
+/// What's on disk at the path a tool call would change
+enum Existing {
+    Missing,
+    /// Over `MAX_DIFF_INPUT_BYTES`; only the size is read
+    TooLarge(u64),
+    Text(String),
+}
+
+fn read_existing(paths: &PathValidator, path: &str) -> Existing {
+    let Some(safe) = paths.resolve(path).ok().filter(|p| p.is_file()) else {
+        return Existing::Missing;
+    };
+    match fs::metadata(&safe) {
+        Ok(meta) if meta.len() > MAX_DIFF_INPUT_BYTES as u64 => Existing::TooLarge(meta.len()),
+        _ => fs::read_to_string(&safe).map_or(Existing::Missing, Existing::Text),
+    }
+}
+
+fn diff_or_summary(path: &str, old: Existing, new: &str) -> String {
+    let old = match old {
+        Existing::Text(old) if new.len() <= MAX_DIFF_INPUT_BYTES => old,
+        Existing::Text(old) => return too_large(path, old.len() as u64, new),
+        Existing::TooLarge(size) => return too_large(path, size, new),
+        Existing::Missing => String::new(),
+    };
+    if old == new {
+        return format!("{}: no changes", path);
+    }
+    unified_diff(path, &old, new)
+}
+
+fn too_large(path: &str, old_size: u64, new: &str) -> String {
+    format!(
+        "{}: {} bytes -> {} bytes (too large to diff)",
+        path,
+        old_size,
+        new.len()
+    )
+}
+
+fn delete_preview(content: &str) -> String {
+    let total = content.lines().count();
+    let mut preview = format!("deletes {} lines, {} bytes\n", total, content.len());
+    for line in content.lines().take(DELETE_PREVIEW_LINES) {
+        preview.push_str(line);
+        preview.push('\n');
+    }
+    if total > DELETE_PREVIEW_LINES {
+        preview.push_str(&format!(
+            "... ({} more lines)\n",
+            total - DELETE_PREVIEW_LINES
+        ));
+    }
+    preview
+}
+
+/// Cut a preview down to `MAX_PREVIEW_BYTES`
+fn cap(preview: String) -> String {
+    if preview.len() <= MAX_PREVIEW_BYTES {
+        return preview;
+    }
+    format!(
+        "{}\n... [preview truncated, {} bytes total]",
+        truncate_to_char_boundary(&preview, MAX_PREVIEW_BYTES),
+        preview.len()
+    )
+}
+
+// ============================================================================
+// Line Diff
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Unified diff of `old` against `new`, with `CONTEXT_LINES` of context
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&a, &b);
+
+    // Line numbers (0-based) in old and new before each op
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut ai, mut bi) = (0, 0);
+    for (op, _) in &ops {
+        positions.push((ai, bi));
+        match op {
+            Op::Equal => {
+                ai += 1;
+                bi += 1;
+            }
+            Op::Delete => ai += 1,
+            Op::Insert => bi += 1,
+        }
+    }
+    positions.push((ai, bi));
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i].0 == Op::Equal {
+            i += 1;
+            continue;
+        }
+        let start = i.saturating_sub(CONTEXT_LINES);
+
+        // Extend the hunk while the next change is close enough to share context
+        let mut end = i;
+        loop {
+            while end < ops.len() && ops[end].0 != Op::Equal {
+                end += 1;
+            }
+            let mut next = end;
+            while next < ops.len() && ops[next].0 == Op::Equal {
+                next += 1;
+            }
+            if next < ops.len() && next - end <= 2 * CONTEXT_LINES {
+                end = next;
+            } else {
+                break;
+            }
+        }
+        let stop = (end + CONTEXT_LINES).min(ops.len());
+
+        let (a_start, b_start) = positions[start];
+        let (a_stop, b_stop) = positions[stop];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(a_start, a_stop - a_start),
+            hunk_range(b_start, b_stop - b_start)
+        ));
+        for (op, line) in &ops[start..stop] {
+            let marker = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            out.push(marker);
+            out.push_str(line);
+            out.push('\n');
+        }
+        i = stop;
+    }
+    out
+}
+
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
+
+/// Line-level edit script from `a` to `b`: common prefix and suffix are matched
+/// directly, the region between by longest common subsequence
+fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<(Op, &str)> = a[..prefix].iter().map(|l| (Op::Equal, *l)).collect();
+    let (n, m) = (a_mid.len(), b_mid.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        ops.extend(a_mid.iter().map(|l| (Op::Delete, *l)));
+        ops.extend(b_mid.iter().map(|l| (Op::Insert, *l)));
+    } else {
+        // lcs[i][j]: length of the LCS of a_mid[i..] and b_mid[j..]
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if a_mid[i] == b_mid[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n && j < m {
+            if a_mid[i] == b_mid[j] {
+                ops.push((Op::Equal, a_mid[i]));
+                i += 1;
+                j += 1;
+            } else if lcs[(i + 1) * width + j] >= lcs[i * width + j + 1] {
+                ops.push((Op::Delete, a_mid[i]));
+                i += 1;
+            } else {
+                ops.push((Op::Insert, b_mid[j]));
+                j += 1;
+            }
+        }
+        ops.extend(a_mid[i..].iter().map(|l| (Op::Delete, *l)));
+        ops.extend(b_mid[j..].iter().map(|l| (Op::Insert, *l)));
+    }
+    ops.extend(a[a.len() - suffix..].iter().map(|l| (Op::Equal, *l)));
+    ops
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn numbered(range: std::ops::Range<usize>) -> String {
+        range.map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let old = numbered(1..21);
+        let new = old
+            .replace("line 2\n", "line two\n")
+            .replace("line 15\n", "");
+
+        let diff = unified_diff("a.md", &old, &new);
+        assert_eq!(
+            diff,
+            "--- a/a.md\n+++ b/a.md\n\
+             @@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n line 4\n line 5\n\
+             @@ -12,7 +12,6 @@\n line 12\n line 13\n line 14\n-line 15\n line 16\n line 17\n line 18\n"
+        );
+    }
+
+    #[test]
+    fn test_nearby_changes_share_a_hunk() {
+        let old = numbered(1..11);
+        let new = old.replace("line 3\n", "3\n").replace("line 7\n", "7\n");
+        let diff = unified_diff("a.md", &old, &new);
+        assert_eq!(diff.matches("@@ -").count(), 1);
+        assert!(diff.contains("@@ -1,10 +1,10 @@"), "{}", diff);
+    }
+
+    #[test]
+    fn test_write_and_delete_previews() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        std::fs::write(dir.path().join("ch1.md"), numbered(1..31)).unwrap();
+
+        let new_file = approval_preview(
+            &paths,
+            "write_file",
+            &json!({"path": "ch2.md", "content": "x".repeat(2300)}),
+        );
+        assert_eq!(new_file.as_deref(), Some("new file, 2300 bytes"));
+
+        let edit = approval_preview(
+            &paths,
+            "edit_file",
+            &json!({"path": "ch1.md", "old_string": "line 9\n", "new_string": "line nine\n"}),
+        )
+        .unwrap();
+        assert!(edit.contains("-line 9\n+line nine\n"), "{}", edit);
+
+        let append = approval_preview(
+            &paths,
+            "append_file",
+            &json!({"path": "ch1.md", "content": "the end\n"}),
+        )
+        .unwrap();
+        assert!(append.ends_with(" line 30\n+the end\n"), "{}", append);
+
+        let delete = approval_preview(&paths, "delete_file", &json!({"path": "ch1.md"})).unwrap();
+        assert!(delete.starts_with("deletes 30 lines"));
+        assert!(delete.contains("line 20\n"));
+        assert!(!delete.contains("line 21\n"));
+        assert!(delete.ends_with("... (10 more lines)\n"));
+
+        assert!(approval_preview(&paths, "read_file", &json!({"path": "ch1.md"})).is_none());
+    }
+
+    #[test]
+    fn test_preview_is_capped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        std::fs::write(dir.path().join("big.md"), "old\n").unwrap();
+
+        let content = numbered(0..20_000);
+        let preview = approval_preview(
+            &paths,
+            "write_file",
+            &json!({"path": "big.md", "content": content}),
+        )
+        .unwrap();
+        assert!(preview.len() < MAX_PREVIEW_BYTES + 100);
+        assert!(preview.contains("[preview truncated"));
+    }
+}
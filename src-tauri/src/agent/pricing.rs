@@ -0,0 +1,127 @@
+//! Rough cost estimates for LLM usage.
+//!
+//! Prices are list prices in USD per million tokens, keyed on model-name prefixes.
+//! The longest matching prefix wins, so `gpt-4.1-mini` isn't billed as `gpt-4.1`.
+//! OpenRouter-style ids (`anthropic/claude-sonnet-4`) are matched on the part after
+//! the vendor, and dots and dashes are treated alike (`claude-3.5-sonnet`). Unknown
+//! models, including local Ollama models, have no estimate.
+
+use super::types::Usage;
+
+/// (model prefix, input price, output price), USD per million tokens
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-5", 1.25, 10.0),
+    ("gpt-5-mini", 0.25, 2.0),
+    ("gpt-5-nano", 0.05, 0.4),
+    ("gpt-4.1", 2.0, 8.0),
+    ("gpt-4.1-mini", 0.4, 1.6),
+    ("gpt-4.1-nano", 0.1, 0.4),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("o3", 2.0, 8.0),
+    ("o3-mini", 1.1, 4.4),
+    ("o4-mini", 1.1, 4.4),
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-haiku", 0.25, 1.25),
+];
+
+/// (embedding model prefix, input price), USD per million tokens
+const EMBEDDING_PRICES: &[(&str, f64)] = &[
+    ("text-embedding-3-small", 0.02),
+    ("text-embedding-3-large", 0.13),
+    ("text-embedding-ada-002", 0.1),
+];
+
+/// Estimated cost in USD of `usage` on `model`, if the model's price is known
+pub fn estimate_cost(model: &str, usage: &Usage) -> Option<f64> {
+    let (input, output) = price_for(model)?;
+    Some(
+        (usage.prompt_tokens as f64 * input + usage.completion_tokens as f64 * output)
+            / 1_000_000.0,
+    )
+}
+
+/// Estimated cost in USD of embedding `tokens` with `model`, if its price is known
+pub fn estimate_embedding_cost(model: &str, tokens: u32) -> Option<f64> {
+    let &(_, input) = match_model(model, EMBEDDING_PRICES, |entry| entry.0)?;
+    Some(tokens as f64 * input / 1_000_000.0)
+}
+
+/// Input and output price per million tokens for `model`
+fn price_for(model: &str) -> Option<(f64, f64)> {
+    match_model(model, PRICES, |entry| entry.0).map(|&(_, input, output)| (input, output))
+}
+
+/// The entry of a model-prefix table that best matches `model`, by the rules
+/// above. Shared with other per-model tables.
+pub(crate) fn match_model<'t, T>(
+    model: &str,
+    table: &'t [T],
+    prefix: impl Fn(&T) -> &str,
+) -> Option<&'t T> {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    let name = canonical(name);
+    table
+        .iter()
+        .filter(|entry| matches_prefix(&name, &canonical(prefix(entry))))
+        .max_by_key(|entry| prefix(entry).len())
+}
+
+/// A prefix only matches at a name-segment boundary, so `o3` doesn't match `o3x`
+fn matches_prefix(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['-', ':', '@']))
+}
+
+fn canonical(name: &str) -> String {
+    name.to_ascii_lowercase().replace('.', "-")
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt: u32, completion: u32) -> Usage {
+        Usage {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+            total_tokens: prompt + completion,
+        }
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        assert_eq!(price_for("gpt-4.1"), Some((2.0, 8.0)));
+        assert_eq!(price_for("gpt-4.1-mini-2025-04-14"), Some((0.4, 1.6)));
+        assert_eq!(price_for("gpt-5-mini"), Some((0.25, 2.0)));
+        assert_eq!(price_for("claude-sonnet-4-20250514"), Some((3.0, 15.0)));
+        assert_eq!(price_for("anthropic/claude-3.5-sonnet"), Some((3.0, 15.0)));
+        assert_eq!(price_for("openai/GPT-4o-mini"), Some((0.15, 0.6)));
+    }
+
+    #[test]
+    fn test_unknown_models_have_no_estimate() {
+        assert!(estimate_cost("llama3.1", &usage(1000, 1000)).is_none());
+        assert!(estimate_cost("o3x", &usage(1000, 1000)).is_none());
+        assert!(estimate_cost("", &usage(1000, 1000)).is_none());
+    }
+
+    #[test]
+    fn test_estimate_cost() {
+        let cost = estimate_cost("claude-sonnet-4-20250514", &usage(1_000_000, 100_000)).unwrap();
+        assert!((cost - 4.5).abs() < 1e-9);
+        assert_eq!(estimate_cost("gpt-5-mini", &usage(0, 0)), Some(0.0));
+
+        let cost = estimate_embedding_cost("text-embedding-3-small", 500_000).unwrap();
+        assert!((cost - 0.01).abs() < 1e-9);
+        assert!(estimate_embedding_cost("nomic-embed-text", 1000).is_none());
+    }
+}
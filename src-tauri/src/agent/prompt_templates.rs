@@ -0,0 +1,301 @@
+//! System prompt templates stored with a project.
+//!
+//! Templates are markdown files in `.vswrite/prompts/`, named by their file stem
+//! (`copy-editor.md` is template `copy-editor`). Optional YAML frontmatter gives a
+//! display name, a description, and the variables the template expects:
+//!
+//! ```markdown
+//! ---
+//! name: Copy editor
+//! description: Line edits for grammar and rhythm
+//! variables: [style_guide]
+//! ---
+//! You are a copy editor. Follow {{style_guide}}.
+//! ```
+//!
+//! `{{name}}` placeholders are filled from the run's variables; every declared or
+//! referenced variable must be supplied.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::tools::safe_path;
+
+/// Workspace-relative directory holding prompt templates
+pub const PROMPTS_DIR: &str = ".vswrite/prompts";
+
+/// Metadata for one template, as listed for the UI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    /// File stem, passed back as `template_id`
+    pub id: String,
+    /// Display name (the id when the frontmatter has none)
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Variables the template needs, declared and referenced, sorted
+    pub variables: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TemplateFrontmatter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    variables: Vec<String>,
+}
+
+/// A parsed template file
+struct LoadedTemplate {
+    meta: PromptTemplate,
+    body: String,
+}
+
+/// `{{ name }}` placeholders
+fn placeholder_regex() -> &'static Regex {
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| {
+        Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("valid placeholder regex")
+    })
+}
+
+/// List the workspace's templates, sorted by id. Files that fail to parse are
+/// logged and left out.
+pub fn list_templates(workspace: &Path) -> Result<Vec<PromptTemplate>, String> {
+    let dir = workspace.join(PROMPTS_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries =
+        fs::read_dir(&dir).map_err(|e| format!("Failed to read {}: {}", PROMPTS_DIR, e))?;
+    let mut templates = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_file() || path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        match load_file(id, &path) {
+            Ok(template) => templates.push(template.meta),
+            Err(e) => log::warn!("Skipping prompt template {}: {}", path.display(), e),
+        }
+    }
+
+    templates.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(templates)
+}
+
+/// Render template `id` with `variables` into a system prompt
+pub fn render_template(
+    workspace: &Path,
+    id: &str,
+    variables: &HashMap<String, String>,
+) -> Result<String, String> {
+    let template = load_template(workspace, id)?;
+
+    let missing: Vec<&str> = template
+        .meta
+        .variables
+        .iter()
+        .filter(|v| !variables.contains_key(v.as_str()))
+        .map(String::as_str)
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Prompt template '{}' is missing variables: {}",
+            id,
+            missing.join(", ")
+        ));
+    }
+
+    Ok(placeholder_regex()
+        .replace_all(&template.body, |caps: &regex::Captures| {
+            variables[&caps[1]].clone()
+        })
+        .into_owned())
+}
+
+/// A template file's full text, with `name` and `description` in its frontmatter
+pub fn template_file(name: &str, description: &str, body: &str) -> Result<String, String> {
+    let frontmatter = TemplateFrontmatter {
+        name: Some(name.to_string()),
+        description: Some(description.to_string()),
+        variables: Vec::new(),
+    };
+    let yaml = serde_yaml::to_string(&frontmatter)
+        .map_err(|e| format!("Failed to serialize template frontmatter: {}", e))?;
+    Ok(format!("---\n{}---\n{}\n", yaml, body.trim()))
+}
+
+/// Find and parse template `id`, which must name a file directly in `PROMPTS_DIR`
+fn load_template(workspace: &Path, id: &str) -> Result<LoadedTemplate, String> {
+    if id.is_empty() || id.starts_with('.') || id.contains(['/', '\\']) {
+        return Err(format!("Invalid prompt template id: '{}'", id));
+    }
+
+    let path = safe_path(workspace, &format!("{}/{}.md", PROMPTS_DIR, id))?;
+    let dir = safe_path(workspace, PROMPTS_DIR)?;
+    if path.parent() != Some(dir.as_path()) {
+        return Err(format!("Invalid prompt template id: '{}'", id));
+    }
+    if !path.is_file() {
+        return Err(format!(
+            "Prompt template '{}' not found in {}",
+            id, PROMPTS_DIR
+        ));
+    }
+
+    load_file(id, &path)
+}
+
+fn load_file(id: &str, path: &Path) -> Result<LoadedTemplate, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read template: {}", e))?;
+    let (frontmatter, body) = split_frontmatter(&content)?;
+
+    let mut variables: BTreeSet<String> = frontmatter.variables.into_iter().collect();
+    variables.extend(
+        placeholder_regex()
+            .captures_iter(&body)
+            .map(|caps| caps[1].to_string()),
+    );
+
+    Ok(LoadedTemplate {
+        meta: PromptTemplate {
+            id: id.to_string(),
+            name: frontmatter.name.unwrap_or_else(|| id.to_string()),
+            description: frontmatter.description,
+            variables: variables.into_iter().collect(),
+        },
+        body,
+    })
+}
+
+/// Separate optional `---` YAML frontmatter from the template body
+fn split_frontmatter(content: &str) -> Result<(TemplateFrontmatter, String), String> {
+    if !content.starts_with("---") {
+        return Ok((TemplateFrontmatter::default(), content.trim().to_string()));
+    }
+
+    let parts: Vec<&str> = content.splitn(3, "---").collect();
+    if parts.len() < 3 {
+        return Err("Invalid frontmatter format".to_string());
+    }
+    let frontmatter = serde_yaml::from_str(parts[1].trim())
+        .map_err(|e| format!("Failed to parse template frontmatter: {}", e))?;
+    Ok((frontmatter, parts[2].trim().to_string()))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn workspace_with(templates: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let prompts = dir.path().join(PROMPTS_DIR);
+        fs::create_dir_all(&prompts).unwrap();
+        for (file, content) in templates {
+            fs::write(prompts.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    const COPY_EDITOR: &str = "---\nname: Copy editor\ndescription: Line edits\nvariables: [style_guide]\n---\nYou edit {{ style_guide }} for {{author}}.\n";
+
+    #[test]
+    fn test_list_templates() {
+        let dir = workspace_with(&[
+            ("copy-editor.md", COPY_EDITOR),
+            ("continuity.md", "Check {{book}} for continuity errors."),
+            ("broken.md", "---\nname: [unclosed\n---\nbody"),
+            ("notes.txt", "not a template"),
+        ]);
+
+        let templates = list_templates(dir.path()).unwrap();
+        assert_eq!(
+            templates,
+            vec![
+                PromptTemplate {
+                    id: "continuity".to_string(),
+                    name: "continuity".to_string(),
+                    description: None,
+                    variables: vec!["book".to_string()],
+                },
+                PromptTemplate {
+                    id: "copy-editor".to_string(),
+                    name: "Copy editor".to_string(),
+                    description: Some("Line edits".to_string()),
+                    variables: vec!["author".to_string(), "style_guide".to_string()],
+                },
+            ]
+        );
+
+        let empty = TempDir::new().unwrap();
+        assert!(list_templates(empty.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let dir = workspace_with(&[("copy-editor.md", COPY_EDITOR)]);
+        let variables = HashMap::from([
+            ("style_guide".to_string(), "Chicago".to_string()),
+            ("author".to_string(), "Ada".to_string()),
+            ("unused".to_string(), "ignored".to_string()),
+        ]);
+
+        let prompt = render_template(dir.path(), "copy-editor", &variables).unwrap();
+        assert_eq!(prompt, "You edit Chicago for Ada.");
+    }
+
+    #[test]
+    fn test_missing_variables_are_listed() {
+        let dir = workspace_with(&[("copy-editor.md", COPY_EDITOR)]);
+        let err = render_template(dir.path(), "copy-editor", &HashMap::new()).unwrap_err();
+        assert_eq!(
+            err,
+            "Prompt template 'copy-editor' is missing variables: author, style_guide"
+        );
+    }
+
+    #[test]
+    fn test_template_id_cannot_escape() {
+        let dir = workspace_with(&[("ok.md", "fine")]);
+        fs::write(dir.path().join("outside.md"), "not a template").unwrap();
+
+        for id in [
+            "../../outside",
+            "../prompts/ok",
+            "/etc/passwd",
+            "",
+            ".hidden",
+        ] {
+            let err = render_template(dir.path(), id, &HashMap::new()).unwrap_err();
+            assert!(
+                err.contains("Invalid prompt template id"),
+                "{}: {}",
+                id,
+                err
+            );
+        }
+        let err = render_template(dir.path(), "missing", &HashMap::new()).unwrap_err();
+        assert!(err.contains("not found"));
+        assert_eq!(
+            render_template(dir.path(), "ok", &HashMap::new()).unwrap(),
+            "fine"
+        );
+    }
+}
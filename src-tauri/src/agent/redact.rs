@@ -0,0 +1,141 @@
+//! Masking credentials before text is logged, stored, or shown.
+//!
+//! [`redact`] masks anything shaped like a provider API key or a bearer token, and
+//! the values of `api_key`, `password`, `secret`, and similar fields. It runs over
+//! provider error bodies before they become an [`AgentError`](super::types::AgentError),
+//! over audit entries, and over exported session reports. [`mask_key`] is what
+//! `Debug` output shows in place of a key.
+
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Replaces a value that looks like an API key
+pub const REDACTED_API_KEY: &str = "[REDACTED_API_KEY]";
+
+/// Replaces any other secret
+pub const REDACTED: &str = "[REDACTED]";
+
+/// (pattern, replacement) pairs, applied in order
+fn patterns() -> &'static [(Regex, String)] {
+    static PATTERNS: OnceLock<Vec<(Regex, String)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // OpenAI (sk-, sk-proj-), Anthropic (sk-ant-), OpenRouter (sk-or-v1-)
+            (r"\bsk-[A-Za-z0-9_\-]{16,}", REDACTED_API_KEY.to_string()),
+            (r"\bAIza[0-9A-Za-z_\-]{30,}", REDACTED_API_KEY.to_string()),
+            (r"\bxai-[A-Za-z0-9]{20,}", REDACTED_API_KEY.to_string()),
+            (r"\bgh[pousr]_[A-Za-z0-9]{30,}", REDACTED_API_KEY.to_string()),
+            (
+                r"(?i)\b(bearer|basic)\s+[A-Za-z0-9._~+/=\-]{8,}",
+                format!("$1 {}", REDACTED),
+            ),
+            // `key: value`, `"key": "value"`, and `key=value`
+            (
+                r#"(?i)\b(x-api-key|api[_-]?key|access[_-]?token|password|passwd|secret)(["']?\s*[:=]\s*["']?)[^"'\s,;&}]+"#,
+                format!("$1$2{}", REDACTED),
+            ),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| {
+            (
+                Regex::new(pattern).expect("valid redaction pattern"),
+                replacement,
+            )
+        })
+        .collect()
+    })
+}
+
+/// `text` with anything that looks like a credential masked
+pub fn redact(text: &str) -> String {
+    let mut result = text.to_string();
+    for (pattern, replacement) in patterns() {
+        if pattern.is_match(&result) {
+            result = pattern
+                .replace_all(&result, replacement.as_str())
+                .into_owned();
+        }
+    }
+    result
+}
+
+/// [`redact`], also masking every occurrence of `secret`, the key a request
+/// was actually sent with, whatever its shape
+pub fn redact_secret(text: &str, secret: &str) -> String {
+    let text = if secret.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(secret, REDACTED_API_KEY)
+    };
+    redact(&text)
+}
+
+/// How a key appears in `Debug` output: its `sk-` prefix if it has one, then `***`
+pub fn mask_key(key: &str) -> String {
+    if key.is_empty() {
+        String::new()
+    } else if key.starts_with("sk-") {
+        "sk-***".to_string()
+    } else {
+        "***".to_string()
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_key_shapes() {
+        for key in [
+            "sk-abc123456789012345678901234567890",
+            "sk-proj-Ab_cD-123456789012345678",
+            "sk-ant-REDACTED",
+            "sk-or-v1-0123456789abcdef0123456789abcdef",
+            "AIzaSyA1234567890abcdefghijklmnopqrstu",
+        ] {
+            let redacted = redact(&format!("API key: {} was rejected", key));
+            assert!(!redacted.contains(key), "{}", redacted);
+            assert!(redacted.contains(REDACTED_API_KEY), "{}", redacted);
+            assert!(redacted.ends_with(" was rejected"), "{}", redacted);
+        }
+        // Short sk- words and error codes are left alone
+        assert_eq!(redact("sk-learn"), "sk-learn");
+        assert_eq!(
+            redact(r#"{"code":"invalid_api_key"}"#),
+            r#"{"code":"invalid_api_key"}"#
+        );
+    }
+
+    #[test]
+    fn test_redact_headers_and_fields() {
+        let echoed = redact("Authorization: Bearer abcdef.ghijkl-123 was invalid");
+        assert_eq!(echoed, "Authorization: Bearer [REDACTED] was invalid");
+
+        assert_eq!(
+            redact(r#"{"api_key": "hunter2", "model": "gpt"}"#),
+            r#"{"api_key": "[REDACTED]", "model": "gpt"}"#
+        );
+        assert_eq!(
+            redact("x-api-key: abc123 password=pw&user=me"),
+            "x-api-key: [REDACTED] password=[REDACTED]&user=me"
+        );
+    }
+
+    #[test]
+    fn test_redact_secret_and_mask_key() {
+        assert_eq!(
+            redact_secret("key ollama-local-42 refused", "ollama-local-42"),
+            format!("key {} refused", REDACTED_API_KEY)
+        );
+        assert_eq!(redact_secret("nothing here", ""), "nothing here");
+
+        assert_eq!(mask_key("sk-ant-api03-secret"), "sk-***");
+        assert_eq!(mask_key("secret"), "***");
+        assert_eq!(mask_key(""), "");
+    }
+}
@@ -0,0 +1,300 @@
+//! Workspace templates for new projects.
+//!
+//! `scaffold_workspace` lays out a project from a built-in template, so a run
+//! doesn't spend its first iterations creating `sections/` and `entities/` by hand.
+//! Templates are data in `scaffold_templates.yaml`. Sections are written through
+//! [`EntityStore`] and prompts through [`prompt_templates::template_file`], so the
+//! result reads back like files the app wrote. `project.yaml` is left to the app's
+//! project creation.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::atomic_write::write_atomic;
+use super::entity_api::{EntityStore, NewSection};
+use super::prompt_templates::{self, PROMPTS_DIR};
+use super::tools::safe_path;
+use super::workspaces::canonical_workspace;
+
+/// Built-in template definitions
+const TEMPLATES_YAML: &str = include_str!("scaffold_templates.yaml");
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceTemplate {
+    id: String,
+    /// Workspace-relative directories to create
+    #[serde(default)]
+    directories: Vec<String>,
+    /// Section stubs, created in this order
+    #[serde(default)]
+    sections: Vec<SectionStub>,
+    /// Prompt templates for `PROMPTS_DIR`
+    #[serde(default)]
+    prompts: Vec<PromptStub>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectionStub {
+    title: String,
+    #[serde(default)]
+    content: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptStub {
+    id: String,
+    name: String,
+    description: String,
+    body: String,
+}
+
+/// What `scaffold_workspace` created
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScaffoldManifest {
+    pub template: String,
+    /// Canonical workspace root
+    pub root: String,
+    /// Workspace-relative paths in creation order; directories end in `/`
+    pub created: Vec<String>,
+}
+
+fn templates() -> &'static [WorkspaceTemplate] {
+    static TEMPLATES: OnceLock<Vec<WorkspaceTemplate>> = OnceLock::new();
+    TEMPLATES.get_or_init(|| {
+        serde_yaml::from_str(TEMPLATES_YAML).expect("valid built-in workspace templates")
+    })
+}
+
+/// Lay out a workspace at `path` from template `template_id`. The folder is
+/// created if it doesn't exist; one that has anything in it is refused unless
+/// `force` is set, in which case existing files are kept and section stubs go
+/// after the sections already there.
+pub fn scaffold_workspace(
+    path: &Path,
+    template_id: &str,
+    force: bool,
+) -> Result<ScaffoldManifest, String> {
+    let template = templates()
+        .iter()
+        .find(|t| t.id == template_id)
+        .ok_or_else(|| {
+            let ids: Vec<&str> = templates().iter().map(|t| t.id.as_str()).collect();
+            format!(
+                "Unknown workspace template '{}' (available: {})",
+                template_id,
+                ids.join(", ")
+            )
+        })?;
+    let root = prepare_root(path, force)?;
+    let mut created = Vec::new();
+
+    for dir in &template.directories {
+        create_dir(&root, dir, &mut created)?;
+    }
+
+    if !template.sections.is_empty() {
+        create_dir(&root, "sections", &mut created)?;
+    }
+    let store = EntityStore::new(&root);
+    for stub in &template.sections {
+        let section = store.create_section(NewSection {
+            title: stub.title.clone(),
+            content: stub.content.clone(),
+            ..NewSection::default()
+        })?;
+        let file = store
+            .section_path(&section.id)?
+            .ok_or_else(|| format!("Section '{}' was not written", stub.title))?;
+        created.push(relative(&root, &file));
+    }
+
+    if !template.prompts.is_empty() {
+        create_dir(&root, PROMPTS_DIR, &mut created)?;
+    }
+    for prompt in &template.prompts {
+        let relative = format!("{}/{}.md", PROMPTS_DIR, prompt.id);
+        let file = safe_path(&root, &relative)?;
+        // A prompt the user already has wins over the template's
+        if file.exists() {
+            continue;
+        }
+        let text =
+            prompt_templates::template_file(&prompt.name, &prompt.description, &prompt.body)?;
+        write_atomic(&file, text.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", relative, e))?;
+        created.push(relative);
+    }
+
+    log::info!(
+        "Scaffolded '{}' workspace at {} ({} paths created)",
+        template.id,
+        root.display(),
+        created.len()
+    );
+    Ok(ScaffoldManifest {
+        template: template.id.clone(),
+        root: root.to_string_lossy().to_string(),
+        created,
+    })
+}
+
+/// Check `path` and create it if needed, returning its canonical form
+fn prepare_root(path: &Path, force: bool) -> Result<PathBuf, String> {
+    if !path.is_absolute() {
+        return Err(format!(
+            "Workspace path must be absolute: {}",
+            path.display()
+        ));
+    }
+    if path.exists() {
+        if !path.is_dir() {
+            return Err(format!(
+                "Workspace path is not a directory: {}",
+                path.display()
+            ));
+        }
+        let mut entries =
+            fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if entries.next().is_some() && !force {
+            return Err(format!(
+                "{} is not empty; pass force to scaffold into it anyway",
+                path.display()
+            ));
+        }
+    } else {
+        if !path.parent().is_some_and(Path::is_dir) {
+            return Err(format!(
+                "Parent folder of {} does not exist",
+                path.display()
+            ));
+        }
+        fs::create_dir(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    }
+    canonical_workspace(path)
+}
+
+/// Create a workspace directory, recording it if it wasn't there yet
+fn create_dir(root: &Path, dir: &str, created: &mut Vec<String>) -> Result<(), String> {
+    let path = safe_path(root, dir)?;
+    if path.is_dir() {
+        return Ok(());
+    }
+    fs::create_dir_all(&path).map_err(|e| format!("Failed to create {}: {}", dir, e))?;
+    created.push(format!("{}/", dir));
+    Ok(())
+}
+
+/// `path` relative to `root`, with forward slashes
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_builtin_templates_parse() {
+        let ids: Vec<&str> = templates().iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["blank", "novel"]);
+    }
+
+    #[test]
+    fn test_novel_round_trips_through_entity_store() {
+        let parent = TempDir::new().unwrap();
+        let root = parent.path().join("my-novel");
+
+        let manifest = scaffold_workspace(&root, "novel", false).unwrap();
+        assert_eq!(manifest.template, "novel");
+        assert_eq!(
+            &manifest.created[..3],
+            ["sections/", "entities/", ".vswrite/prompts/"]
+        );
+        assert!(manifest
+            .created
+            .contains(&".vswrite/prompts/co-writer.md".to_string()));
+        for path in &manifest.created {
+            assert!(Path::new(&manifest.root).join(path).exists(), "{}", path);
+        }
+
+        let root = PathBuf::from(&manifest.root);
+        let sections = EntityStore::new(&root).list_all_sections().unwrap();
+        let titles: Vec<&str> = sections.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(titles, ["Chapter 1", "Chapter 2"]);
+        assert_eq!(sections[0].order, 0);
+        assert_eq!(sections[1].order, 1);
+        assert_eq!(sections[0].content, "Start the story here.");
+        assert!(EntityStore::new(&root).list_all().unwrap().is_empty());
+
+        let prompts = prompt_templates::list_templates(&root).unwrap();
+        let names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Co-writer", "Continuity check"]);
+    }
+
+    #[test]
+    fn test_blank_creates_only_directories() {
+        let dir = TempDir::new().unwrap();
+        let manifest = scaffold_workspace(dir.path(), "blank", false).unwrap();
+        assert_eq!(manifest.created, ["sections/", "entities/"]);
+        assert!(EntityStore::new(dir.path())
+            .list_all_sections()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_non_empty_directory_needs_force() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("notes.md"), "keep me").unwrap();
+
+        let err = scaffold_workspace(dir.path(), "novel", false).unwrap_err();
+        assert!(err.contains("not empty"), "{}", err);
+        assert!(!dir.path().join("sections").exists());
+
+        // Forced twice: the second pass keeps the prompts and appends the chapters
+        scaffold_workspace(dir.path(), "novel", true).unwrap();
+        let again = scaffold_workspace(dir.path(), "novel", true).unwrap();
+        assert!(again.created.iter().all(|p| p.starts_with("sections/")));
+        let orders: Vec<i64> = EntityStore::new(dir.path())
+            .list_all_sections()
+            .unwrap()
+            .iter()
+            .map(|s| s.order)
+            .collect();
+        assert_eq!(orders, [0, 1, 2, 3]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.md")).unwrap(),
+            "keep me"
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_paths_and_templates() {
+        let dir = TempDir::new().unwrap();
+        let err = scaffold_workspace(Path::new("relative/novel"), "novel", false).unwrap_err();
+        assert!(err.contains("absolute"), "{}", err);
+
+        let err =
+            scaffold_workspace(&dir.path().join("missing/novel"), "novel", false).unwrap_err();
+        assert!(err.contains("Parent folder"), "{}", err);
+
+        let file = dir.path().join("file.txt");
+        fs::write(&file, "").unwrap();
+        let err = scaffold_workspace(&file, "novel", false).unwrap_err();
+        assert!(err.contains("not a directory"), "{}", err);
+
+        let err = scaffold_workspace(&dir.path().join("new"), "screenplay", false).unwrap_err();
+        assert!(err.contains("available: blank, novel"), "{}", err);
+        assert!(!dir.path().join("new").exists());
+    }
+}
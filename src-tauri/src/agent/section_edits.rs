@@ -0,0 +1,243 @@
+//! Section text insertion tools that keep entity tags in place.
+//!
+//! Tags mark ranges of a section's content by UTF-16 offset, so rewriting the
+//! section with `write_file` leaves every tag after the edit pointing at the wrong
+//! text. `insert_into_section(section_id, offset, text)` and
+//! `append_to_section(section_id, text)` go through the
+//! [`EntityStore`](super::entity_api::EntityStore), which moves the tags along
+//! with the text; `validate_tags(section_id)` lists tags that no longer fit the
+//! content.
+
+use std::collections::HashMap;
+
+use super::entity_api::SectionInsert;
+use super::entity_tags::utf16_len;
+use super::tools::PathValidator;
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Names of the section insertion tools
+pub const SECTION_EDIT_TOOLS: &[&str] =
+    &["insert_into_section", "append_to_section", "validate_tags"];
+
+/// Whether a tool name is one of the section insertion tools
+pub fn is_section_edit_tool(name: &str) -> bool {
+    SECTION_EDIT_TOOLS.contains(&name)
+}
+
+/// One line saying what an insertion did
+fn describe(insert: &SectionInsert, text: &str) -> String {
+    let mut summary = format!(
+        "Inserted {} characters into section {} at offset {} (now {} characters); moved {} tag(s)",
+        utf16_len(text),
+        insert.section.id,
+        insert.offset,
+        utf16_len(&insert.section.content),
+        insert.tags_shifted
+    );
+    if !insert.tags_expanded.is_empty() {
+        summary.push_str(&format!(
+            "; widened tag(s) {} to include the new text",
+            insert.tags_expanded.join(", ")
+        ));
+    }
+    summary
+}
+
+// ============================================================================
+// Tool Interface
+// ============================================================================
+
+/// Dispatch a section insertion tool call
+pub fn dispatch(
+    paths: &PathValidator,
+    name: &str,
+    args: &serde_json::Value,
+) -> Result<String, String> {
+    let section_id = args
+        .get("section_id")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing 'section_id' parameter")?;
+    let store = paths.entity_store();
+    if name == "validate_tags" {
+        let invalid = store.validate_tags(section_id)?;
+        if invalid.is_empty() {
+            return Ok(format!(
+                "All tags in section {} fit its content",
+                section_id
+            ));
+        }
+        return serde_json::to_string_pretty(&invalid)
+            .map_err(|e| format!("Failed to serialize tags: {}", e));
+    }
+
+    let text = args
+        .get("text")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing 'text' parameter")?;
+    let insert = match name {
+        "insert_into_section" => {
+            let offset = args
+                .get("offset")
+                .and_then(|v| v.as_i64())
+                .ok_or("Missing 'offset' parameter")?;
+            store.insert_into_section(section_id, offset, text)?
+        }
+        "append_to_section" => store.append_to_section(section_id, text)?,
+        _ => return Err(format!("Unknown section edit tool: {}", name)),
+    };
+    Ok(describe(&insert, text))
+}
+
+/// Schemas for the section insertion tools
+pub fn section_edit_tool_schemas() -> Vec<Tool> {
+    vec![
+        insert_into_section_schema(),
+        append_to_section_schema(),
+        validate_tags_schema(),
+    ]
+}
+
+fn string_property(description: &str) -> PropertySchema {
+    PropertySchema {
+        prop_type: "string".to_string(),
+        description: Some(description.to_string()),
+        default: None,
+        items: None,
+    }
+}
+
+fn insert_into_section_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "section_id".to_string(),
+        string_property("ID of the section"),
+    );
+    properties.insert(
+        "offset".to_string(),
+        PropertySchema {
+            prop_type: "integer".to_string(),
+            description: Some(
+                "Where to insert, in UTF-16 code units from the start of the section's content (the units tag ranges and find_entity_mentions use)"
+                    .to_string(),
+            ),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert("text".to_string(), string_property("Text to insert"));
+
+    Tool::new(
+        "insert_into_section",
+        "Insert text into a section's content at an offset, moving entity tags so they still mark the same words. Prefer this to rewriting a tagged section with write_file or edit_file.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec![
+                "section_id".to_string(),
+                "offset".to_string(),
+                "text".to_string(),
+            ]),
+        },
+    )
+}
+
+fn append_to_section_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "section_id".to_string(),
+        string_property("ID of the section"),
+    );
+    properties.insert(
+        "text".to_string(),
+        string_property("Text to add to the end, including any leading blank line"),
+    );
+
+    Tool::new(
+        "append_to_section",
+        "Add text to the end of a section's content, keeping its entity tags intact.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string(), "text".to_string()]),
+        },
+    )
+}
+
+fn validate_tags_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "section_id".to_string(),
+        string_property("ID of the section"),
+    );
+
+    Tool::new(
+        "validate_tags",
+        "List a section's entity tags whose ranges no longer fit its content (e.g. after it was edited as a file).",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::entity_api::EntityStore;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dispatch_inserts_and_validates() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        fs::write(
+            dir.path().join("sections/01.md"),
+            "---\nid: s1\ntitle: One\norder: 0\ntags:\n- { id: t1, entity_id: e1, from: 5, to: 9 }\n---\nThen Anna left.\n",
+        )
+        .unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let summary = dispatch(
+            &paths,
+            "insert_into_section",
+            &serde_json::json!({"section_id": "s1", "offset": 0, "text": "And "}),
+        )
+        .unwrap();
+        assert!(summary.contains("moved 1 tag(s)"));
+        let summary = dispatch(
+            &paths,
+            "append_to_section",
+            &serde_json::json!({"section_id": "s1", "text": " Rain fell."}),
+        )
+        .unwrap();
+        assert!(summary.contains("now 30 characters"));
+
+        let section = EntityStore::new(dir.path())
+            .get_section("s1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(section.content, "And Then Anna left. Rain fell.");
+        assert_eq!((section.tags[0].from, section.tags[0].to), (9, 13));
+
+        let report = dispatch(
+            &paths,
+            "validate_tags",
+            &serde_json::json!({"section_id": "s1"}),
+        )
+        .unwrap();
+        assert!(report.starts_with("All tags"));
+        assert!(dispatch(
+            &paths,
+            "insert_into_section",
+            &serde_json::json!({"section_id": "s1", "text": "x"}),
+        )
+        .unwrap_err()
+        .contains("offset"));
+    }
+}
@@ -0,0 +1,393 @@
+//! Section frontmatter tools.
+//!
+//! `get_section_meta(section_id)` returns a section's frontmatter without its prose;
+//! `update_section_meta(section_id, meta)` changes only the fields it is given. Writes
+//! go through [`EntityStore::update_section_metadata`], which rewrites just the changed
+//! keys, so tags, entity links, and fields the app doesn't know survive byte for byte.
+//!
+//! Sibling sections (same parent) shouldn't share an `order`; workspace diagnostics
+//! flag it. An update that would put a section on a sibling's order is refused unless
+//! `on_order_conflict` is `"shift"`, which makes room the way the editor does: every
+//! sibling at that order or later moves down by one.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::entity_api::{EntityStore, Section, SectionMetadata, Tag};
+use super::tools::PathValidator;
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Names of the section frontmatter tools
+pub const SECTION_META_TOOLS: &[&str] = &["get_section_meta", "update_section_meta"];
+
+/// Whether a tool name is one of the section frontmatter tools
+pub fn is_section_meta_tool(name: &str) -> bool {
+    SECTION_META_TOOLS.contains(&name)
+}
+
+/// A section's frontmatter, without its content
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionMeta {
+    pub id: String,
+    pub title: String,
+    pub order: i64,
+    pub alignment: String,
+    pub parent_id: Option<String>,
+    pub collapsed: bool,
+    pub entity_ids: Vec<String>,
+    pub tags: Vec<Tag>,
+}
+
+impl From<Section> for SectionMeta {
+    fn from(section: Section) -> Self {
+        Self {
+            id: section.id,
+            title: section.title,
+            order: section.order,
+            alignment: section.alignment,
+            parent_id: section.parent_id,
+            collapsed: section.collapsed,
+            entity_ids: section.entity_ids,
+            tags: section.tags,
+        }
+    }
+}
+
+/// What to do when the new order is already taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderConflict {
+    Reject,
+    Shift,
+}
+
+impl OrderConflict {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "reject" => Ok(Self::Reject),
+            "shift" => Ok(Self::Shift),
+            other => Err(format!(
+                "Unknown on_order_conflict '{}'. Use reject or shift.",
+                other
+            )),
+        }
+    }
+}
+
+// ============================================================================
+// Store Operations
+// ============================================================================
+
+/// A section's frontmatter
+pub fn get_section_meta(store: &EntityStore, section_id: &str) -> Result<SectionMeta, String> {
+    store
+        .get_section(section_id)?
+        .map(SectionMeta::from)
+        .ok_or_else(|| format!("Section {} not found", section_id))
+}
+
+/// Change the given frontmatter fields of a section, resolving an order clash as
+/// `on_conflict` says. Nothing is written if the update is refused.
+pub fn update_section_meta(
+    store: &EntityStore,
+    section_id: &str,
+    meta: SectionMetadata,
+    on_conflict: OrderConflict,
+) -> Result<SectionMeta, String> {
+    let sections = store.list_all_sections()?;
+    let current = sections
+        .iter()
+        .find(|s| s.id == section_id)
+        .ok_or_else(|| format!("Section {} not found", section_id))?;
+
+    if let Some(parent_id) = meta.parent_id.as_deref().filter(|p| !p.is_empty()) {
+        if parent_id != section_id && !sections.iter().any(|s| s.id == parent_id) {
+            return Err(format!("Parent section {} not found", parent_id));
+        }
+    }
+
+    // Where the section ends up; a clash only matters among its new siblings
+    let parent = match meta.parent_id.as_deref() {
+        Some("") => None,
+        Some(parent_id) => Some(parent_id),
+        None => current.parent_id.as_deref(),
+    };
+    let order = meta.order.unwrap_or(current.order);
+    let siblings: Vec<&Section> = sections
+        .iter()
+        .filter(|s| s.id != section_id && s.parent_id.as_deref() == parent)
+        .collect();
+
+    // Sections to move down one place, applied only once the update itself succeeds
+    let mut shifted = Vec::new();
+    let moved = order != current.order || parent != current.parent_id.as_deref();
+    if let Some(clash) = siblings.iter().find(|s| s.order == order).filter(|_| moved) {
+        if on_conflict == OrderConflict::Reject {
+            return Err(format!(
+                "Order {} is already used by sibling section {} (\"{}\"). Pick a free order or pass on_order_conflict: \"shift\".",
+                order, clash.id, clash.title
+            ));
+        }
+        shifted = siblings
+            .iter()
+            .filter(|s| s.order >= order)
+            .map(|s| (s.id.clone(), s.order + 1))
+            .collect();
+    }
+
+    let updated = store.update_section_metadata(section_id, meta)?;
+    for (id, order) in shifted {
+        store.update_section_metadata(
+            &id,
+            SectionMetadata {
+                order: Some(order),
+                ..Default::default()
+            },
+        )?;
+    }
+    Ok(updated.into())
+}
+
+// ============================================================================
+// Tool Interface
+// ============================================================================
+
+/// Dispatch a section frontmatter tool call
+pub fn dispatch(
+    paths: &PathValidator,
+    name: &str,
+    args: &serde_json::Value,
+) -> Result<String, String> {
+    let section_id = args
+        .get("section_id")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing 'section_id' parameter")?;
+    let store = paths.entity_store();
+
+    let meta = match name {
+        "get_section_meta" => get_section_meta(&store, section_id)?,
+        "update_section_meta" => {
+            let meta: SectionMetadata = serde_json::from_value(
+                args.get("meta")
+                    .cloned()
+                    .ok_or("Missing 'meta' parameter")?,
+            )
+            .map_err(|e| format!("Invalid 'meta' parameter: {}", e))?;
+            let on_conflict = match args.get("on_order_conflict").and_then(|v| v.as_str()) {
+                Some(value) => OrderConflict::parse(value)?,
+                None => OrderConflict::Reject,
+            };
+            update_section_meta(&store, section_id, meta, on_conflict)?
+        }
+        _ => return Err(format!("Unknown section meta tool: {}", name)),
+    };
+    serde_json::to_string_pretty(&meta)
+        .map_err(|e| format!("Failed to serialize section metadata: {}", e))
+}
+
+/// Schemas for the section frontmatter tools
+pub fn section_meta_tool_schemas() -> Vec<Tool> {
+    vec![get_section_meta_schema(), update_section_meta_schema()]
+}
+
+fn section_id_property() -> PropertySchema {
+    PropertySchema {
+        prop_type: "string".to_string(),
+        description: Some("ID of the section".to_string()),
+        default: None,
+        items: None,
+    }
+}
+
+fn get_section_meta_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("section_id".to_string(), section_id_property());
+
+    Tool::new(
+        "get_section_meta",
+        "Read a section's frontmatter without its content: id, title, order, alignment, parentId, collapsed, entityIds, and tags.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string()]),
+        },
+    )
+}
+
+fn update_section_meta_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("section_id".to_string(), section_id_property());
+    properties.insert(
+        "meta".to_string(),
+        PropertySchema {
+            prop_type: "object".to_string(),
+            description: Some(
+                "Fields to change, any of {\"title\", \"order\", \"alignment\" (left, center, right), \"parentId\" (\"\" for top level), \"collapsed\", \"entityIds\"}. Omitted fields are left alone."
+                    .to_string(),
+            ),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "on_order_conflict".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some(
+                "When a sibling section already has the new order: \"reject\" fails, \"shift\" moves that sibling and every later one down by one"
+                    .to_string(),
+            ),
+            default: Some(serde_json::json!("reject")),
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "update_section_meta",
+        "Change a section's frontmatter without touching its content or tags. Returns the updated frontmatter.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string(), "meta".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    const ONE: &str = "11111111-1111-4111-8111-111111111111";
+    const TWO: &str = "22222222-2222-4222-8222-222222222222";
+    const THREE: &str = "33333333-3333-4333-8333-333333333333";
+    const ANNA: &str = "44444444-4444-4444-8444-444444444444";
+
+    const TAGGED_FRONTMATTER: &str = "entity_ids:\n- 44444444-4444-4444-8444-444444444444\ntags:\n    -   id: t1\n        entity_id: \"44444444-4444-4444-8444-444444444444\"\n        from: 0\n        to: 4   # hand-edited\nsubtitle: 'kept as written'\n";
+
+    fn setup_workspace() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        fs::write(
+            dir.path().join("sections/01.md"),
+            format!(
+                "---\nid: {}\ntitle: One\norder: 0\n{}---\nAnna waved.\n",
+                ONE, TAGGED_FRONTMATTER
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("sections/02.md"),
+            format!("---\nid: {}\ntitle: Two\norder: 1\n---\nSecond.", TWO),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("sections/03.md"),
+            format!("---\nid: {}\ntitle: Three\norder: 2\n---\nThird.", THREE),
+        )
+        .unwrap();
+        dir
+    }
+
+    fn order_of(paths: &PathValidator, id: &str) -> i64 {
+        let meta = dispatch(
+            paths,
+            "get_section_meta",
+            &serde_json::json!({"section_id": id}),
+        );
+        let meta: serde_json::Value = serde_json::from_str(&meta.unwrap()).unwrap();
+        meta["order"].as_i64().unwrap()
+    }
+
+    fn read(dir: &Path, file: &str) -> String {
+        fs::read_to_string(dir.join("sections").join(file)).unwrap()
+    }
+
+    #[test]
+    fn test_update_leaves_other_frontmatter_untouched() {
+        let dir = setup_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let meta = dispatch(
+            &paths,
+            "get_section_meta",
+            &serde_json::json!({"section_id": ONE}),
+        )
+        .unwrap();
+        let meta: serde_json::Value = serde_json::from_str(&meta).unwrap();
+        assert_eq!(meta["title"], "One");
+        assert_eq!(meta["entityIds"], serde_json::json!([ANNA]));
+        assert_eq!(meta["tags"][0]["entityId"], ANNA);
+        assert!(meta.get("content").is_none());
+
+        dispatch(
+            &paths,
+            "update_section_meta",
+            &serde_json::json!({
+                "section_id": ONE,
+                "meta": {"title": "Chapter One", "alignment": "center"}
+            }),
+        )
+        .unwrap();
+
+        let raw = read(dir.path(), "01.md");
+        let expected_start = format!(
+            "---\nid: {}\ntitle: Chapter One\norder: 0\n{}alignment: center\nmodified_at: ",
+            ONE, TAGGED_FRONTMATTER
+        );
+        assert!(raw.starts_with(&expected_start), "{}", raw);
+        assert!(raw.ends_with("---\nAnna waved.\n"), "{}", raw);
+
+        let store = EntityStore::new(dir.path());
+        let section = store.get_section(ONE).unwrap().unwrap();
+        assert_eq!(section.title, "Chapter One");
+        assert_eq!(section.tags.len(), 1);
+        assert_eq!(section.content, "Anna waved.");
+
+        // Bad values are refused before anything is written
+        for meta in [
+            serde_json::json!({"alignment": "justify"}),
+            serde_json::json!({"order": -1}),
+            serde_json::json!({"parentId": "55555555-5555-4555-8555-555555555555"}),
+            serde_json::json!({"colour": "red"}),
+        ] {
+            let args = serde_json::json!({"section_id": ONE, "meta": meta});
+            assert!(dispatch(&paths, "update_section_meta", &args).is_err());
+        }
+        assert_eq!(read(dir.path(), "01.md"), raw);
+    }
+
+    #[test]
+    fn test_order_conflict_rejects_or_shifts() {
+        let dir = setup_workspace();
+        let paths = PathValidator::new(dir.path());
+        let untouched = read(dir.path(), "02.md");
+
+        let move_three = |on_conflict: Option<&str>| {
+            let mut args = serde_json::json!({"section_id": THREE, "meta": {"order": 1}});
+            if let Some(value) = on_conflict {
+                args["on_order_conflict"] = value.into();
+            }
+            dispatch(&paths, "update_section_meta", &args)
+        };
+
+        let err = move_three(None).unwrap_err();
+        assert!(err.contains(TWO), "{}", err);
+        assert_eq!(read(dir.path(), "02.md"), untouched);
+        assert!(move_three(Some("sideways")).is_err());
+
+        move_three(Some("shift")).unwrap();
+        assert_eq!(order_of(&paths, ONE), 0);
+        assert_eq!(order_of(&paths, THREE), 1);
+        assert_eq!(order_of(&paths, TWO), 2);
+
+        // Keeping its own order is not a conflict
+        move_three(None).unwrap();
+    }
+}
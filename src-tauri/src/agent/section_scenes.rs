@@ -0,0 +1,527 @@
+//! Scene-level section edits.
+//!
+//! `update_section_scene` replaces the text under one markdown heading of a section
+//! instead of the whole section, so changing a scene doesn't mean resending (and
+//! risking) the rest of the chapter. The heading line stays, and so do the
+//! frontmatter and every byte outside the replaced span.
+//!
+//! Headings are found with pulldown-cmark, so ATX (`## Scene`) and setext
+//! (underlined) headings both count, and a `#` line inside a code block doesn't.
+//! A heading is picked by its text, which must be unambiguous, or by its 1-based
+//! position. The replaced text runs to the next heading of the same or a higher
+//! level, to an explicit `until` heading, or to the end of the section.
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::entity_api::EntityStore;
+use super::tools::PathValidator;
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Name of the scene update tool
+pub const UPDATE_SECTION_SCENE_TOOL: &str = "update_section_scene";
+
+/// A heading, by its text or its 1-based position in the section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadingRef {
+    Text(String),
+    Index(usize),
+}
+
+/// One heading in a section's content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    /// Byte range of the heading's lines, underline and line ending included
+    pub span: Range<usize>,
+}
+
+/// What `update_section_scene` changed
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneUpdate {
+    pub section_id: String,
+    pub heading: String,
+    pub heading_index: usize,
+    /// The heading the replaced text ran up to; `None` when it ran to the end
+    pub until_heading: Option<String>,
+    /// Words in the replaced text
+    pub words_before: usize,
+    /// Words in the text that replaced it
+    pub words_after: usize,
+}
+
+// ============================================================================
+// Headings
+// ============================================================================
+
+/// The headings in `markdown`, in order
+pub fn parse_headings(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut open: Option<(u8, usize, String)> = None;
+    for (event, range) in Parser::new(markdown).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                open = Some((level as u8, range.start, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, _, heading)) = open.as_mut() {
+                    heading.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, _, heading)) = open.as_mut() {
+                    heading.push(' ');
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, start, text)) = open.take() {
+                    let start = line_start(markdown, start);
+                    headings.push(Heading {
+                        level,
+                        text: text.trim().to_string(),
+                        span: start..heading_end(markdown, start, range.end),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    headings
+}
+
+fn line_start(text: &str, at: usize) -> usize {
+    text[..at].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(text: &str, at: usize) -> usize {
+    text[at..].find('\n').map_or(text.len(), |i| at + i + 1)
+}
+
+/// End of a heading's span: the end of the line pulldown-cmark's range stops on,
+/// plus a setext underline if the range left it out
+fn heading_end(text: &str, start: usize, end: usize) -> usize {
+    let end = if end == 0 || text[..end].ends_with('\n') {
+        end
+    } else {
+        line_end(text, end)
+    };
+    let atx = text[start..]
+        .trim_start_matches(|c: char| c == '>' || c.is_whitespace())
+        .starts_with('#');
+    let is_underline = |line: &str| {
+        let line = line.trim();
+        !line.is_empty() && (line.chars().all(|c| c == '=') || line.chars().all(|c| c == '-'))
+    };
+    let last_line = &text[line_start(text, end.saturating_sub(1))..end];
+    if atx || end >= text.len() || is_underline(last_line) {
+        return end;
+    }
+    let next = line_end(text, end);
+    if is_underline(&text[end..next]) {
+        next
+    } else {
+        end
+    }
+}
+
+/// Position in `headings` of the one `target` names
+fn find_heading(
+    headings: &[Heading],
+    target: &HeadingRef,
+    section_id: &str,
+) -> Result<usize, String> {
+    match target {
+        HeadingRef::Index(index) => {
+            if *index == 0 || *index > headings.len() {
+                return Err(format!(
+                    "Heading index {} is out of range: section {} has {} heading(s)",
+                    index,
+                    section_id,
+                    headings.len()
+                ));
+            }
+            Ok(index - 1)
+        }
+        HeadingRef::Text(text) => {
+            let wanted = text.trim().to_lowercase();
+            let matches: Vec<usize> = headings
+                .iter()
+                .enumerate()
+                .filter(|(_, h)| h.text.to_lowercase() == wanted)
+                .map(|(i, _)| i)
+                .collect();
+            match matches.as_slice() {
+                [index] => Ok(*index),
+                [] => Err(format!(
+                    "No heading \"{}\" in section {}. Headings: {}",
+                    text.trim(),
+                    section_id,
+                    list_headings(headings)
+                )),
+                _ => Err(format!(
+                    "Heading \"{}\" appears {} times in section {} (headings {}). Pass its index instead.",
+                    text.trim(),
+                    matches.len(),
+                    section_id,
+                    matches
+                        .iter()
+                        .map(|i| (i + 1).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            }
+        }
+    }
+}
+
+fn list_headings(headings: &[Heading]) -> String {
+    if headings.is_empty() {
+        return "none".to_string();
+    }
+    headings
+        .iter()
+        .enumerate()
+        .map(|(i, h)| format!("{}. {}", i + 1, h.text))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// ============================================================================
+// Replacement
+// ============================================================================
+
+/// `body` with the text under `heading` replaced by `content`, and what changed.
+/// The replaced span keeps its leading and trailing blank lines so the
+/// surrounding layout is unchanged.
+pub fn replace_scene(
+    section_id: &str,
+    body: &str,
+    heading: &HeadingRef,
+    until: Option<&HeadingRef>,
+    content: &str,
+) -> Result<(String, SceneUpdate), String> {
+    let headings = parse_headings(body);
+    let start = find_heading(&headings, heading, section_id)?;
+    let target = &headings[start];
+
+    let end = match until {
+        Some(until) => {
+            let end = find_heading(&headings, until, section_id)?;
+            if end <= start {
+                return Err(format!(
+                    "The until heading {} (\"{}\") must come after heading {} (\"{}\")",
+                    end + 1,
+                    headings[end].text,
+                    start + 1,
+                    target.text
+                ));
+            }
+            Some(end)
+        }
+        None => headings[start + 1..]
+            .iter()
+            .position(|h| h.level <= target.level)
+            .map(|offset| start + 1 + offset),
+    };
+    let span = target.span.end..end.map_or(body.len(), |i| headings[i].span.start);
+    let old = &body[span.clone()];
+
+    let (lead, trail) = if old.trim().is_empty() {
+        ("\n", if end.is_some() { "\n\n" } else { "\n" })
+    } else {
+        (
+            &old[..old.len() - old.trim_start().len()],
+            &old[old.trim_end().len()..],
+        )
+    };
+    let content = content.trim();
+    let mut updated = String::with_capacity(body.len() - old.len() + content.len() + 4);
+    updated.push_str(&body[..span.start]);
+    updated.push_str(lead);
+    updated.push_str(content);
+    updated.push_str(trail);
+    updated.push_str(&body[span.end..]);
+
+    let update = SceneUpdate {
+        section_id: section_id.to_string(),
+        heading: target.text.clone(),
+        heading_index: start + 1,
+        until_heading: until.and(end).map(|i| headings[i].text.clone()),
+        words_before: old.split_whitespace().count(),
+        words_after: content.split_whitespace().count(),
+    };
+    Ok((updated, update))
+}
+
+/// Replace the text under one heading of a section; see the module docs
+pub fn update_section_scene(
+    store: &EntityStore,
+    section_id: &str,
+    heading: &HeadingRef,
+    until: Option<&HeadingRef>,
+    content: &str,
+) -> Result<SceneUpdate, String> {
+    let mut update = None;
+    store.edit_section_body(section_id, |body| {
+        let (body, changed) = replace_scene(section_id, body, heading, until, content)?;
+        update = Some(changed);
+        Ok(body)
+    })?;
+    update.ok_or_else(|| format!("Section {} was not updated", section_id))
+}
+
+// ============================================================================
+// Tool Interface
+// ============================================================================
+
+/// A heading given as `<key>` (text) or `<key>_index`, but not both
+fn heading_arg(args: &serde_json::Value, key: &str) -> Result<Option<HeadingRef>, String> {
+    let index_key = format!("{}_index", key);
+    let text = args.get(key).and_then(|v| v.as_str());
+    let index = args.get(&index_key).and_then(|v| v.as_u64());
+    match (text, index) {
+        (Some(_), Some(_)) => Err(format!("Pass '{}' or '{}', not both", key, index_key)),
+        (Some(text), None) => Ok(Some(HeadingRef::Text(text.to_string()))),
+        (None, Some(index)) => Ok(Some(HeadingRef::Index(index as usize))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Dispatch an `update_section_scene` call
+pub fn dispatch(paths: &PathValidator, args: &serde_json::Value) -> Result<String, String> {
+    let section_id = args
+        .get("section_id")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing 'section_id' parameter")?;
+    let content = args
+        .get("content")
+        .and_then(|v| v.as_str())
+        .ok_or("Missing 'content' parameter")?;
+    let heading =
+        heading_arg(args, "heading")?.ok_or("Missing 'heading' or 'heading_index' parameter")?;
+    let until = heading_arg(args, "until_heading")?;
+
+    let store = paths.entity_store();
+    let update = update_section_scene(&store, section_id, &heading, until.as_ref(), content)?;
+    serde_json::to_string_pretty(&update)
+        .map_err(|e| format!("Failed to serialize scene update: {}", e))
+}
+
+fn property(prop_type: &str, description: &str) -> PropertySchema {
+    PropertySchema {
+        prop_type: prop_type.to_string(),
+        description: Some(description.to_string()),
+        default: None,
+        items: None,
+    }
+}
+
+/// Schema for the scene update tool
+pub fn update_section_scene_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "section_id".to_string(),
+        property("string", "ID of the section"),
+    );
+    properties.insert(
+        "heading".to_string(),
+        property(
+            "string",
+            "Text of the heading whose scene to replace (case-insensitive). Must match exactly one heading.",
+        ),
+    );
+    properties.insert(
+        "heading_index".to_string(),
+        property(
+            "integer",
+            "1-based position of the heading among the section's headings, instead of 'heading'",
+        ),
+    );
+    properties.insert(
+        "until_heading".to_string(),
+        property(
+            "string",
+            "Replace up to this later heading instead of the next heading of the same or a higher level",
+        ),
+    );
+    properties.insert(
+        "until_heading_index".to_string(),
+        property("integer", "1-based position of the until heading"),
+    );
+    properties.insert(
+        "content".to_string(),
+        property("string", "New text for the scene, without its heading line"),
+    );
+
+    Tool::new(
+        UPDATE_SECTION_SCENE_TOOL,
+        "Replace the text under one markdown heading of a section, keeping the heading, the frontmatter, and the rest of the section exactly as they are. Use this instead of rewriting a whole section to change one scene. Returns before/after word counts.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["section_id".to_string(), "content".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const CHAPTER: &str = "\nOpening lines.\n\n## The Ball\n\nThey danced all night.\nThe music stopped.\n\n### Aside\n\nA footnote.\n\n## The Road\n\nDawn came.\n";
+
+    fn text(heading: &str) -> HeadingRef {
+        HeadingRef::Text(heading.to_string())
+    }
+
+    #[test]
+    fn test_parse_atx_and_setext_headings() {
+        let markdown = "Part One\n========\n\nIntro.\n\nThe Ball\n--------\nDancing.\n\n```\n# not a heading\n```\n\n## The *Road* ##\nDawn.\n";
+        let headings = parse_headings(markdown);
+        let summary: Vec<(u8, &str)> = headings
+            .iter()
+            .map(|h| (h.level, h.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(1, "Part One"), (2, "The Ball"), (2, "The Road")]
+        );
+
+        assert_eq!(&markdown[headings[0].span.clone()], "Part One\n========\n");
+        assert_eq!(&markdown[headings[1].span.clone()], "The Ball\n--------\n");
+        assert_eq!(&markdown[headings[2].span.clone()], "## The *Road* ##\n");
+    }
+
+    #[test]
+    fn test_replace_scene_keeps_everything_else() {
+        let (updated, update) = replace_scene(
+            "ch1",
+            CHAPTER,
+            &text("the ball"),
+            None,
+            "They left early.\n",
+        )
+        .unwrap();
+        assert_eq!(
+            updated,
+            "\nOpening lines.\n\n## The Ball\n\nThey left early.\n\n## The Road\n\nDawn came.\n"
+        );
+        assert_eq!(update.heading, "The Ball");
+        assert_eq!(update.heading_index, 1);
+        assert_eq!(update.until_heading, None);
+        assert_eq!((update.words_before, update.words_after), (11, 3));
+
+        // A subsection ends at the next heading of any level no deeper than its own
+        let (updated, _) =
+            replace_scene("ch1", CHAPTER, &HeadingRef::Index(2), None, "Nothing.").unwrap();
+        assert!(
+            updated.contains("### Aside\n\nNothing.\n\n## The Road"),
+            "{}",
+            updated
+        );
+
+        // The last scene runs to the end of the section
+        let (updated, _) = replace_scene("ch1", CHAPTER, &text("The Road"), None, "Dusk.").unwrap();
+        assert!(updated.ends_with("## The Road\n\nDusk.\n"), "{}", updated);
+
+        let (updated, update) = replace_scene(
+            "ch1",
+            CHAPTER,
+            &text("The Ball"),
+            Some(&text("Aside")),
+            "Short.",
+        )
+        .unwrap();
+        assert!(updated.contains("## The Ball\n\nShort.\n\n### Aside\n\nA footnote."));
+        assert_eq!(update.until_heading.as_deref(), Some("Aside"));
+    }
+
+    #[test]
+    fn test_setext_scene() {
+        let body = "Arrival\n=======\nThe train was late.\n\nDeparture\n=========\nGone.";
+        let (updated, update) =
+            replace_scene("ch1", body, &text("Arrival"), None, "It was on time.").unwrap();
+        assert_eq!(
+            updated,
+            "Arrival\n=======\nIt was on time.\n\nDeparture\n=========\nGone."
+        );
+        assert_eq!(update.words_before, 4);
+    }
+
+    #[test]
+    fn test_ambiguous_and_missing_headings() {
+        let body = "## Scene\nOne.\n\n## Interlude\nTwo.\n\n## Scene\nThree.\n";
+        let err = replace_scene("ch1", body, &text("scene"), None, "x").unwrap_err();
+        assert!(err.contains("appears 2 times"), "{}", err);
+        assert!(err.contains("(headings 1, 3)"), "{}", err);
+
+        let (updated, _) = replace_scene("ch1", body, &HeadingRef::Index(3), None, "3.").unwrap();
+        assert_eq!(
+            updated,
+            "## Scene\nOne.\n\n## Interlude\nTwo.\n\n## Scene\n3.\n"
+        );
+
+        let err = replace_scene("ch1", body, &text("Epilogue"), None, "x").unwrap_err();
+        assert!(err.contains("1. Scene, 2. Interlude, 3. Scene"), "{}", err);
+        let err = replace_scene("ch1", body, &HeadingRef::Index(4), None, "x").unwrap_err();
+        assert!(err.contains("out of range"), "{}", err);
+        let err = replace_scene(
+            "ch1",
+            body,
+            &text("Interlude"),
+            Some(&HeadingRef::Index(1)),
+            "x",
+        )
+        .unwrap_err();
+        assert!(err.contains("must come after"), "{}", err);
+    }
+
+    #[test]
+    fn test_update_section_scene_preserves_file() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        let frontmatter =
+            "---\nid: ch1\ntitle: One\norder: 0\nmodified_at: '2024-01-01'   # by hand\n---";
+        let path = dir.path().join("sections/01.md");
+        fs::write(&path, format!("{}{}", frontmatter, CHAPTER)).unwrap();
+
+        let paths = PathValidator::new(dir.path());
+        let args = serde_json::json!({
+            "section_id": "ch1",
+            "heading_index": 3,
+            "content": "Dusk came instead.",
+        });
+        let result: serde_json::Value =
+            serde_json::from_str(&dispatch(&paths, &args).unwrap()).unwrap();
+        assert_eq!(result["heading"], "The Road");
+        assert_eq!(result["words_before"], 2);
+        assert_eq!(result["words_after"], 3);
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            written,
+            format!(
+                "{}{}",
+                frontmatter,
+                CHAPTER.replace("Dawn came.", "Dusk came instead.")
+            )
+        );
+        let section = EntityStore::new(dir.path())
+            .get_section("ch1")
+            .unwrap()
+            .unwrap();
+        assert!(section.content.ends_with("Dusk came instead."));
+
+        let both = serde_json::json!({"section_id": "ch1", "heading": "x", "heading_index": 1, "content": ""});
+        assert!(dispatch(&paths, &both).unwrap_err().contains("not both"));
+    }
+}
@@ -0,0 +1,798 @@
+//! Semantic search over the workspace's sections.
+//!
+//! `build_semantic_index` splits each section into chunks of whole paragraphs,
+//! embeds them with the OpenAI embeddings endpoint, and saves the vectors with the
+//! chunk text in `.vswrite/index/semantic.idx`. A rebuild only re-embeds section
+//! files whose modification time changed. The `semantic_search` tool embeds the
+//! query the same way and returns the closest chunks by cosine similarity; with no
+//! index, or on a provider without an embeddings endpoint, it says so instead of
+//! failing.
+//!
+//! The index file is a magic line, the length of a JSON header (`u32`, little
+//! endian), the header (model, dimensions, and each file's chunks), then every
+//! chunk's vector as little-endian `f32`s, in header order.
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+use super::atomic_write::write_atomic;
+use super::entity_api::parse_section_file;
+use super::redact::redact_secret;
+use super::session::SessionAudit;
+use super::tools::TOOL_CANCELLED;
+use super::types::{AgentConfig, JsonSchema, LlmProvider, PropertySchema, Tool};
+
+/// Name of the search tool
+pub const SEMANTIC_SEARCH_TOOL: &str = "semantic_search";
+
+/// Event carrying [`IndexProgress`] while an index builds
+pub const INDEX_PROGRESS_EVENT: &str = "semantic-index-progress";
+
+/// Index file, relative to the workspace
+pub const INDEX_PATH: &str = ".vswrite/index/semantic.idx";
+
+/// Embedding model used for both the index and queries
+pub const EMBEDDING_MODEL: &str = "text-embedding-3-small";
+
+const INDEX_MAGIC: &[u8] = b"VSWIDX1\n";
+const INDEX_VERSION: u32 = 1;
+
+/// Chunks grow paragraph by paragraph up to about this many characters
+const CHUNK_CHARS: usize = 1_500;
+
+/// A single paragraph longer than this is split, to stay under the model's input limit
+const MAX_CHUNK_CHARS: usize = 6_000;
+
+/// Inputs sent per embeddings request
+const EMBED_BATCH: usize = 64;
+
+const DEFAULT_TOP_K: usize = 5;
+const MAX_TOP_K: usize = 20;
+
+const EMBEDDING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One chunk of a section, as stored in the index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chunk {
+    section_id: String,
+    title: String,
+    text: String,
+}
+
+/// The chunks of one section file and their vectors
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct IndexedFile {
+    /// Modification time the file was indexed at, in ms since the epoch
+    modified_ms: u64,
+    chunks: Vec<Chunk>,
+    /// One per chunk; stored after the header, not in it
+    #[serde(skip)]
+    vectors: Vec<Vec<f32>>,
+}
+
+/// The index of a workspace's sections
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SemanticIndex {
+    version: u32,
+    model: String,
+    dimensions: usize,
+    /// Keyed on the path relative to the workspace
+    files: BTreeMap<String, IndexedFile>,
+}
+
+impl SemanticIndex {
+    fn new(model: &str) -> Self {
+        SemanticIndex {
+            version: INDEX_VERSION,
+            model: model.to_string(),
+            dimensions: 0,
+            files: BTreeMap::new(),
+        }
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.files.values().map(|f| f.chunks.len()).sum()
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, String> {
+        let header = serde_json::to_vec(self)
+            .map_err(|e| format!("Failed to encode semantic index: {}", e))?;
+        let header_len = u32::try_from(header.len())
+            .map_err(|_| "Semantic index header is too large".to_string())?;
+
+        let mut bytes = Vec::with_capacity(
+            INDEX_MAGIC.len() + 4 + header.len() + self.chunk_count() * self.dimensions * 4,
+        );
+        bytes.extend_from_slice(INDEX_MAGIC);
+        bytes.extend_from_slice(&header_len.to_le_bytes());
+        bytes.extend_from_slice(&header);
+        for vector in self.files.values().flat_map(|f| &f.vectors) {
+            for value in vector {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let rest = bytes
+            .strip_prefix(INDEX_MAGIC)
+            .ok_or_else(|| "Not a semantic index file".to_string())?;
+        if rest.len() < 4 {
+            return Err("Semantic index is truncated".to_string());
+        }
+        let (len, rest) = rest.split_at(4);
+        let header_len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if rest.len() < header_len {
+            return Err("Semantic index is truncated".to_string());
+        }
+        let (header, mut vectors) = rest.split_at(header_len);
+        let mut index: SemanticIndex = serde_json::from_slice(header)
+            .map_err(|e| format!("Failed to parse semantic index: {}", e))?;
+        if index.version != INDEX_VERSION {
+            return Err(format!(
+                "Semantic index version {} is not supported",
+                index.version
+            ));
+        }
+
+        let vector_bytes = index.dimensions * 4;
+        if vectors.len() != index.chunk_count() * vector_bytes {
+            return Err("Semantic index vectors don't match its chunks".to_string());
+        }
+        for file in index.files.values_mut() {
+            for _ in 0..file.chunks.len() {
+                let (vector, rest) = vectors.split_at(vector_bytes);
+                file.vectors.push(
+                    vector
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                );
+                vectors = rest;
+            }
+        }
+        Ok(index)
+    }
+
+    fn load(workspace: &Path) -> Result<Option<Self>, String> {
+        let path = workspace.join(INDEX_PATH);
+        match fs::read(&path) {
+            Ok(bytes) => Self::decode(&bytes).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    fn save(&self, workspace: &Path) -> Result<(), String> {
+        let path = workspace.join(INDEX_PATH);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        write_atomic(&path, &self.encode()?)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// The `top_k` chunks closest to `query`, best first
+    fn search(&self, query: &[f32], top_k: usize) -> Vec<SearchHit> {
+        let mut hits: Vec<SearchHit> = self
+            .files
+            .values()
+            .flat_map(|file| file.chunks.iter().zip(&file.vectors))
+            .map(|(chunk, vector)| SearchHit {
+                section_id: chunk.section_id.clone(),
+                title: chunk.title.clone(),
+                score: cosine_similarity(query, vector),
+                text: chunk.text.clone(),
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(top_k);
+        hits
+    }
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+/// Split section text into chunks of whole paragraphs of about [`CHUNK_CHARS`]
+/// each. A paragraph over [`MAX_CHUNK_CHARS`] is cut into pieces of that size.
+fn chunk_text(text: &str) -> Vec<String> {
+    let text = text.replace("\r\n", "\n");
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_chars = 0;
+
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let chars = paragraph.chars().count();
+        if current_chars > 0 && current_chars + 2 + chars > CHUNK_CHARS {
+            chunks.push(std::mem::take(&mut current));
+            current_chars = 0;
+        }
+        if chars > MAX_CHUNK_CHARS {
+            let pieces: Vec<char> = paragraph.chars().collect();
+            chunks.extend(
+                pieces
+                    .chunks(MAX_CHUNK_CHARS)
+                    .map(|piece| piece.iter().collect::<String>()),
+            );
+            continue;
+        }
+        if current_chars > 0 {
+            current.push_str("\n\n");
+            current_chars += 2;
+        }
+        current.push_str(paragraph);
+        current_chars += chars;
+    }
+    if current_chars > 0 {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// The workspace's section files as (relative path, absolute path, mtime in ms)
+fn section_files(workspace: &Path) -> Result<Vec<(String, std::path::PathBuf, u64)>, String> {
+    let dir = workspace.join("sections");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        let modified_ms = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let relative = format!("sections/{}", entry.file_name().to_string_lossy());
+        files.push((relative, path, modified_ms));
+    }
+    files.sort();
+    Ok(files)
+}
+
+// ============================================================================
+// Embeddings
+// ============================================================================
+
+/// Client for an embeddings endpoint
+#[derive(Clone)]
+pub struct EmbeddingClient {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+impl std::fmt::Debug for EmbeddingClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbeddingClient")
+            .field("base_url", &self.base_url)
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+    #[serde(default)]
+    usage: Option<EmbeddingUsage>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+}
+
+impl EmbeddingClient {
+    /// OpenAI's embeddings endpoint at `base_url` (the public API when `None`)
+    pub fn openai(api_key: String, base_url: Option<String>) -> Self {
+        let base_url = base_url
+            .filter(|url| !url.trim().is_empty())
+            .unwrap_or_else(|| LlmProvider::OpenAI.default_base_url().to_string());
+        let client = Client::builder()
+            .timeout(EMBEDDING_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        EmbeddingClient {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model: EMBEDDING_MODEL.to_string(),
+        }
+    }
+
+    /// A client for the run's provider, `None` when it has no embeddings endpoint
+    pub fn for_config(config: &AgentConfig) -> Option<Self> {
+        match config.provider {
+            LlmProvider::OpenAI => Some(Self::openai(
+                config.api_key.clone(),
+                Some(config.effective_base_url()),
+            )),
+            LlmProvider::Claude | LlmProvider::Ollama | LlmProvider::OpenRouter => None,
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Embed `inputs`, returning one vector per input and the tokens used
+    async fn embed(&self, inputs: &[String]) -> Result<(Vec<Vec<f32>>, u32), String> {
+        let response = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&serde_json::json!({"model": self.model, "input": inputs}))
+            .send()
+            .await
+            .map_err(|e| format!("Embeddings request failed: {}", e))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read embeddings response: {}", e))?;
+        if !status.is_success() {
+            return Err(format!(
+                "Embeddings request failed ({}): {}",
+                status,
+                redact_secret(&body, &self.api_key)
+            ));
+        }
+
+        let mut parsed: EmbeddingResponse = serde_json::from_str(&body)
+            .map_err(|e| format!("Failed to parse embeddings response: {}", e))?;
+        if parsed.data.len() != inputs.len() {
+            return Err(format!(
+                "Embeddings response has {} vectors for {} inputs",
+                parsed.data.len(),
+                inputs.len()
+            ));
+        }
+        parsed.data.sort_by_key(|d| d.index);
+        let tokens = parsed.usage.map(|u| u.prompt_tokens).unwrap_or(0);
+        Ok((
+            parsed.data.into_iter().map(|d| d.embedding).collect(),
+            tokens,
+        ))
+    }
+}
+
+// ============================================================================
+// Building
+// ============================================================================
+
+/// Progress of an index build, sent as [`INDEX_PROGRESS_EVENT`]
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexProgress {
+    pub workspace: String,
+    /// Section files looked at so far
+    pub files_done: usize,
+    pub files_total: usize,
+    /// The file just looked at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Whether it had to be embedded (false when it was unchanged)
+    pub embedded: bool,
+}
+
+/// What a build did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexReport {
+    /// Section files embedded this time (new or changed)
+    pub files_indexed: usize,
+    /// Section files kept from the previous build
+    pub files_unchanged: usize,
+    /// Section files dropped because they no longer exist
+    pub files_removed: usize,
+    /// Section files left out because they don't parse
+    pub files_failed: Vec<String>,
+    /// Chunks in the index
+    pub chunks: usize,
+    pub model: String,
+    /// Tokens sent to the embeddings endpoint
+    pub tokens: u32,
+}
+
+/// Build or refresh the index of `workspace`'s sections. Files whose modification
+/// time is unchanged keep their vectors; everything is re-embedded when the index
+/// was built with another model or can't be read.
+pub async fn build_index(
+    workspace: &Path,
+    client: &EmbeddingClient,
+    mut on_progress: impl FnMut(IndexProgress),
+) -> Result<IndexReport, String> {
+    let previous = match SemanticIndex::load(workspace) {
+        Ok(Some(index)) if index.model == client.model => index,
+        Ok(_) => SemanticIndex::new(&client.model),
+        Err(e) => {
+            log::warn!("Rebuilding the semantic index from scratch: {}", e);
+            SemanticIndex::new(&client.model)
+        }
+    };
+    let mut previous_files = previous.files;
+    let mut index = SemanticIndex {
+        dimensions: previous.dimensions,
+        ..SemanticIndex::new(&client.model)
+    };
+    let mut report = IndexReport {
+        model: client.model.clone(),
+        ..IndexReport::default()
+    };
+
+    let files = section_files(workspace)?;
+    let files_total = files.len();
+    for (done, (relative, path, modified_ms)) in files.into_iter().enumerate() {
+        let kept = previous_files
+            .remove(&relative)
+            .filter(|file| file.modified_ms == modified_ms);
+        let embedded = kept.is_none();
+        let file = match kept {
+            Some(file) => {
+                report.files_unchanged += 1;
+                Some(file)
+            }
+            None => match parse_section_file(&path) {
+                Ok((frontmatter, content)) => {
+                    let (file, tokens) = embed_section(
+                        client,
+                        frontmatter.id,
+                        frontmatter.title,
+                        &content,
+                        modified_ms,
+                    )
+                    .await?;
+                    report.tokens += tokens;
+                    report.files_indexed += 1;
+                    Some(file)
+                }
+                Err(e) => {
+                    log::warn!("Not indexing {}: {}", relative, e);
+                    report.files_failed.push(relative.clone());
+                    None
+                }
+            },
+        };
+        if let Some(file) = file {
+            if let Some(dimensions) = file.vectors.first().map(Vec::len) {
+                if index.dimensions != 0 && index.dimensions != dimensions {
+                    return Err(format!(
+                        "Embeddings changed size ({} to {}); delete {} and rebuild",
+                        index.dimensions, dimensions, INDEX_PATH
+                    ));
+                }
+                index.dimensions = dimensions;
+            }
+            index.files.insert(relative.clone(), file);
+        }
+        on_progress(IndexProgress {
+            workspace: workspace.to_string_lossy().to_string(),
+            files_done: done + 1,
+            files_total,
+            path: Some(relative),
+            embedded,
+        });
+    }
+    report.files_removed = previous_files.len();
+    report.chunks = index.chunk_count();
+
+    index.save(workspace)?;
+    log::info!(
+        "Semantic index for {}: {} files embedded, {} unchanged, {} removed, {} tokens",
+        workspace.display(),
+        report.files_indexed,
+        report.files_unchanged,
+        report.files_removed,
+        report.tokens
+    );
+    Ok(report)
+}
+
+/// Chunk and embed one section
+async fn embed_section(
+    client: &EmbeddingClient,
+    section_id: String,
+    title: String,
+    content: &str,
+    modified_ms: u64,
+) -> Result<(IndexedFile, u32), String> {
+    let texts = chunk_text(content);
+    let mut vectors = Vec::with_capacity(texts.len());
+    let mut tokens = 0;
+    for batch in texts.chunks(EMBED_BATCH) {
+        let (embedded, used) = client.embed(batch).await?;
+        vectors.extend(embedded);
+        tokens += used;
+    }
+    let chunks = texts
+        .into_iter()
+        .map(|text| Chunk {
+            section_id: section_id.clone(),
+            title: title.clone(),
+            text,
+        })
+        .collect();
+    Ok((
+        IndexedFile {
+            modified_ms,
+            chunks,
+            vectors,
+        },
+        tokens,
+    ))
+}
+
+// ============================================================================
+// Searching
+// ============================================================================
+
+/// A chunk matching a query
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub section_id: String,
+    pub title: String,
+    /// Cosine similarity to the query, higher is closer
+    pub score: f32,
+    pub text: String,
+}
+
+/// What a run needs for semantic search: the embeddings client, and the session
+/// the query tokens are charged to
+#[derive(Clone)]
+pub struct SemanticSearch {
+    client: EmbeddingClient,
+    usage: Option<SessionAudit>,
+}
+
+impl SemanticSearch {
+    pub fn new(client: EmbeddingClient, usage: Option<SessionAudit>) -> Self {
+        SemanticSearch { client, usage }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    query: String,
+    #[serde(default)]
+    top_k: Option<usize>,
+}
+
+/// Run a `semantic_search` tool call. A missing index or embeddings endpoint is
+/// reported in the result, for the model to fall back on `grep`.
+pub async fn run_search_tool(
+    workspace: &Path,
+    search: Option<&SemanticSearch>,
+    args: &serde_json::Value,
+    cancel: Option<&CancellationToken>,
+) -> Result<String, String> {
+    let args: SearchArgs = serde_json::from_value(args.clone())
+        .map_err(|e| format!("Invalid semantic_search arguments: {}", e))?;
+    if args.query.trim().is_empty() {
+        return Err("Missing 'query' parameter".to_string());
+    }
+    let top_k = args.top_k.unwrap_or(DEFAULT_TOP_K).clamp(1, MAX_TOP_K);
+
+    let Some(search) = search else {
+        return Ok(
+            "Semantic search isn't available with this provider (it needs the OpenAI embeddings endpoint). Use grep instead."
+                .to_string(),
+        );
+    };
+    let index = match SemanticIndex::load(workspace)? {
+        Some(index) if index.chunk_count() > 0 => index,
+        _ => {
+            return Ok(
+                "The semantic index hasn't been built for this workspace. Use grep instead, or ask the user to build the index."
+                    .to_string(),
+            )
+        }
+    };
+    if index.model != search.client.model {
+        return Ok(format!(
+            "The semantic index was built with {}, not {}; it needs rebuilding. Use grep instead.",
+            index.model, search.client.model
+        ));
+    }
+
+    let query = [args.query.clone()];
+    let embedded = search.client.embed(&query);
+    let (vectors, tokens) = match cancel {
+        Some(token) => tokio::select! {
+            _ = token.cancelled() => return Err(TOOL_CANCELLED.to_string()),
+            result = embedded => result?,
+        },
+        None => embedded.await?,
+    };
+    if let Some(usage) = &search.usage {
+        usage.record_embedding_usage(&search.client.model, tokens);
+    }
+
+    let hits = index.search(&vectors[0], top_k);
+    serde_json::to_string_pretty(&serde_json::json!({
+        "query": args.query,
+        "results": hits,
+    }))
+    .map_err(|e| format!("Failed to serialize search results: {}", e))
+}
+
+/// Schema for the search tool
+pub fn semantic_search_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "query".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some(
+                "What to look for, in plain words, e.g. \"where the betrayal is foreshadowed\""
+                    .to_string(),
+            ),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "top_k".to_string(),
+        PropertySchema {
+            prop_type: "integer".to_string(),
+            description: Some(format!("Number of passages to return (1-{})", MAX_TOP_K)),
+            default: Some(serde_json::json!(DEFAULT_TOP_K)),
+            items: None,
+        },
+    );
+
+    Tool::new(
+        SEMANTIC_SEARCH_TOOL,
+        "Find the passages in the sections closest in meaning to a query, for questions keyword search can't answer. Returns section IDs, titles, similarity scores, and the passage text. Needs the workspace's semantic index; says so if it hasn't been built.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["query".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn file(section_id: &str, texts: &[&str], vectors: Vec<Vec<f32>>) -> IndexedFile {
+        IndexedFile {
+            modified_ms: 42,
+            chunks: texts
+                .iter()
+                .map(|text| Chunk {
+                    section_id: section_id.to_string(),
+                    title: section_id.to_uppercase(),
+                    text: text.to_string(),
+                })
+                .collect(),
+            vectors,
+        }
+    }
+
+    #[test]
+    fn test_chunk_text_keeps_paragraphs_whole() {
+        let paragraph = "word ".repeat(96);
+        let text = [paragraph.trim(); 8].join("\r\n\r\n");
+        let chunks = chunk_text(&text);
+        // 479 chars each: three fit under the target, the fourth starts a new chunk
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|c| c.chars().count() <= CHUNK_CHARS));
+        assert_eq!(chunks[0].matches("\n\n").count(), 2);
+
+        let long = "x".repeat(MAX_CHUNK_CHARS + 10);
+        let chunks = chunk_text(&format!("Short.\n\n{}\n\nEnd.", long));
+        let sizes: Vec<usize> = chunks.iter().map(|c| c.chars().count()).collect();
+        assert_eq!(sizes, vec![6, MAX_CHUNK_CHARS, 10, 4]);
+        assert!(chunk_text(" \n\n \n").is_empty());
+    }
+
+    #[test]
+    fn test_index_round_trip_and_search() {
+        let dir = TempDir::new().unwrap();
+        let mut index = SemanticIndex::new(EMBEDDING_MODEL);
+        index.dimensions = 3;
+        index.files.insert(
+            "sections/a.md".to_string(),
+            file(
+                "a",
+                &["The dagger", "The feast"],
+                vec![vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]],
+            ),
+        );
+        index.files.insert(
+            "sections/b.md".to_string(),
+            file("b", &["The letter"], vec![vec![0.6, 0.8, 0.0]]),
+        );
+        index.save(dir.path()).unwrap();
+
+        let loaded = SemanticIndex::load(dir.path()).unwrap().unwrap();
+        assert_eq!(loaded.chunk_count(), 3);
+        assert_eq!(
+            loaded.files["sections/b.md"].vectors,
+            vec![vec![0.6, 0.8, 0.0]]
+        );
+
+        let hits = loaded.search(&[0.9, 0.1, 0.0], 2);
+        let found: Vec<(&str, &str)> = hits
+            .iter()
+            .map(|h| (h.section_id.as_str(), h.text.as_str()))
+            .collect();
+        assert_eq!(found, vec![("a", "The dagger"), ("b", "The letter")]);
+        assert!(hits[0].score > hits[1].score);
+
+        // A cut-off file is an error, not an empty index
+        let bytes = fs::read(dir.path().join(INDEX_PATH)).unwrap();
+        assert!(SemanticIndex::decode(&bytes[..bytes.len() - 2]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_tool_degrades_without_index_or_endpoint() {
+        let dir = TempDir::new().unwrap();
+        let args = serde_json::json!({"query": "the betrayal"});
+
+        let result = run_search_tool(dir.path(), None, &args, None)
+            .await
+            .unwrap();
+        assert!(result.contains("isn't available"), "{}", result);
+
+        let search = SemanticSearch::new(
+            EmbeddingClient::openai(
+                "sk-test".to_string(),
+                Some("http://127.0.0.1:9".to_string()),
+            ),
+            None,
+        );
+        let result = run_search_tool(dir.path(), Some(&search), &args, None)
+            .await
+            .unwrap();
+        assert!(result.contains("hasn't been built"), "{}", result);
+
+        let err = run_search_tool(dir.path(), Some(&search), &serde_json::json!({}), None)
+            .await
+            .unwrap_err();
+        assert!(err.contains("Invalid semantic_search arguments"), "{}", err);
+    }
+}
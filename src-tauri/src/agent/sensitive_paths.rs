@@ -0,0 +1,456 @@
+//! Which workspace files the file tools refuse to touch.
+//!
+//! A built-in list covers secrets: `.env` files, SSH and private keys, cloud and
+//! package-manager credentials. A workspace can adjust it in `.vswrite/security.toml`:
+//!
+//! ```toml
+//! [sensitive_files]
+//! deny = ["private/", "*.secret"]
+//! allow = ["secrets.json", "research/credentials"]
+//! ```
+//!
+//! Patterns use gitignore syntax relative to the workspace root. `allow` exempts
+//! paths from both the built-in list and `deny`, but never from the security file
+//! itself, and patterns that would allow everything are rejected. The file is
+//! loaded on first use and re-read whenever it changes.
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+/// Workspace-relative path of the policy file
+pub const SECURITY_FILE: &str = ".vswrite/security.toml";
+
+/// Built-in sensitive file names, matched case-insensitively against the file
+/// name alone (`name` and `name.*`)
+const SENSITIVE_FILE_PATTERNS: &[&str] = &[
+    // Environment and secrets
+    ".env",
+    ".env.local",
+    ".env.development",
+    ".env.production",
+    ".env.test",
+    ".envrc",
+    // Credentials and keys
+    "credentials",
+    "credentials.json",
+    ".credentials",
+    "secrets",
+    ".secrets",
+    // SSH keys
+    "id_rsa",
+    "id_rsa.pub",
+    "id_dsa",
+    "id_dsa.pub",
+    "id_ecdsa",
+    "id_ecdsa.pub",
+    "id_ed25519",
+    "id_ed25519.pub",
+    "authorized_keys",
+    "known_hosts",
+    // Private keys
+    "private.pem",
+    "private.key",
+    "server.key",
+    "client.key",
+    // Git credentials
+    ".git-credentials",
+    ".gitconfig",
+    // NPM tokens
+    ".npmrc",
+    // Docker secrets
+    ".docker/config.json",
+    // Cloud provider credentials
+    ".aws/credentials",
+    ".azure/credentials",
+    ".gcloud/credentials",
+    // Password stores
+    ".password-store",
+    ".gnupg",
+    // Keychain
+    "keychain.db",
+    "keychain-db.sqlite",
+];
+
+/// Built-in sensitive file extensions
+const SENSITIVE_EXTENSIONS: &[&str] = &[".pem", ".key", ".p12", ".pfx", ".keystore", ".jks"];
+
+/// Built-in directories whose contents are sensitive
+const SENSITIVE_DIRS: &[&str] = &[".ssh", ".gnupg", ".password-store"];
+
+/// The rule that makes a path sensitive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SensitiveRule {
+    /// A built-in file name
+    FileName(&'static str),
+    /// A built-in file extension
+    Extension(&'static str),
+    /// A built-in directory
+    Directory(&'static str),
+    /// A `deny` pattern from the security file
+    Denied(String),
+    /// The security file itself, which can't be allowed
+    SecurityFile,
+}
+
+impl SensitiveRule {
+    /// Whether an `allow` pattern can lift this rule
+    fn can_allow(&self) -> bool {
+        *self != SensitiveRule::SecurityFile
+    }
+}
+
+impl fmt::Display for SensitiveRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensitiveRule::FileName(pattern) => {
+                write!(f, "matches sensitive file pattern '{}'", pattern)
+            }
+            SensitiveRule::Extension(ext) => write!(f, "has sensitive extension '{}'", ext),
+            SensitiveRule::Directory(dir) => write!(f, "is in sensitive directory '{}'", dir),
+            SensitiveRule::Denied(pattern) => {
+                write!(f, "matches deny pattern '{}' in {}", pattern, SECURITY_FILE)
+            }
+            SensitiveRule::SecurityFile => write!(f, "is the workspace security policy"),
+        }
+    }
+}
+
+/// The built-in rule `path` matches, if any
+pub fn is_sensitive_path(path: &Path) -> Option<SensitiveRule> {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    for pattern in SENSITIVE_FILE_PATTERNS {
+        if file_name == *pattern || file_name.starts_with(&format!("{}.", pattern)) {
+            return Some(SensitiveRule::FileName(pattern));
+        }
+    }
+
+    for ext in SENSITIVE_EXTENSIONS {
+        if file_name.ends_with(ext) {
+            return Some(SensitiveRule::Extension(ext));
+        }
+    }
+
+    let path_str = path.to_string_lossy().to_lowercase();
+    for dir in SENSITIVE_DIRS {
+        if path_str.contains(&format!("{}/", dir)) || path_str.contains(&format!("{}\\", dir)) {
+            return Some(SensitiveRule::Directory(dir));
+        }
+    }
+
+    None
+}
+
+/// Refuse `path` (canonical, inside `canonical_workspace`) if it is sensitive
+/// under the workspace's policy
+pub fn check(canonical_workspace: &Path, path: &Path) -> Result<(), String> {
+    let policy = policy_for(canonical_workspace);
+    let Some(rule) = policy.matching_rule(canonical_workspace, path) else {
+        return Ok(());
+    };
+
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let mut message = format!("Access denied: '{}' {}", name, rule);
+    if rule.can_allow() {
+        message.push_str(&format!(
+            ". If the agent may use it, add it to `allow` under [sensitive_files] in {}",
+            SECURITY_FILE
+        ));
+    }
+    if let Some(error) = &policy.error {
+        message.push_str(&format!(" ({} was ignored: {})", SECURITY_FILE, error));
+    }
+    Err(message)
+}
+
+// ============================================================================
+// Workspace Policy
+// ============================================================================
+
+#[derive(Debug, Default, Deserialize)]
+struct SecurityFile {
+    #[serde(default)]
+    sensitive_files: SensitiveFiles,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SensitiveFiles {
+    #[serde(default)]
+    deny: Vec<String>,
+    #[serde(default)]
+    allow: Vec<String>,
+}
+
+/// The built-in rules plus a workspace's overrides
+#[derive(Default)]
+struct SensitivePolicy {
+    deny: Option<Gitignore>,
+    allow: Option<Gitignore>,
+    /// Why the security file couldn't be used; only built-in rules apply then
+    error: Option<String>,
+}
+
+impl SensitivePolicy {
+    /// Read the security file in `workspace`. An invalid file is reported in
+    /// `error` and leaves only the built-in rules in force.
+    fn load(workspace: &Path) -> Self {
+        let file = workspace.join(SECURITY_FILE);
+        let Ok(text) = fs::read_to_string(&file) else {
+            return SensitivePolicy::default();
+        };
+        Self::parse(workspace, &text).unwrap_or_else(|error| {
+            log::warn!("Ignoring {}: {}", SECURITY_FILE, error);
+            SensitivePolicy {
+                error: Some(error),
+                ..Default::default()
+            }
+        })
+    }
+
+    fn parse(workspace: &Path, text: &str) -> Result<Self, String> {
+        let config: SecurityFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let SensitiveFiles { deny, allow } = config.sensitive_files;
+        for pattern in &allow {
+            check_allow_pattern(pattern)?;
+        }
+
+        Ok(SensitivePolicy {
+            deny: build_matcher(workspace, &deny)?,
+            allow: build_matcher(workspace, &allow)?,
+            error: None,
+        })
+    }
+
+    /// The rule that denies `path`, after `allow` exceptions
+    fn matching_rule(&self, workspace: &Path, path: &Path) -> Option<SensitiveRule> {
+        let relative = path.strip_prefix(workspace).ok();
+        if relative.is_some_and(|relative| is_security_file(relative, IGNORE_CASE)) {
+            return Some(SensitiveRule::SecurityFile);
+        }
+
+        let matches = |matcher: &Option<Gitignore>| {
+            let (matcher, relative) = (matcher.as_ref()?, relative?);
+            if relative.as_os_str().is_empty() {
+                return None;
+            }
+            let matched = matcher.matched_path_or_any_parents(relative, path.is_dir());
+            matched
+                .is_ignore()
+                .then(|| matched.inner().map(|glob| glob.original().to_string()))
+                .flatten()
+        };
+
+        let rule = is_sensitive_path(relative.unwrap_or(path))
+            .or_else(|| matches(&self.deny).map(SensitiveRule::Denied))?;
+        if matches(&self.allow).is_some() {
+            return None;
+        }
+        Some(rule)
+    }
+}
+
+/// Whether file names are usually case-insensitive here (macOS and Windows)
+const IGNORE_CASE: bool = cfg!(any(target_os = "macos", windows));
+
+/// Whether a workspace-relative path names the security file
+fn is_security_file(relative: &Path, ignore_case: bool) -> bool {
+    let fold = |c: std::path::Component| {
+        let name = c.as_os_str().to_string_lossy();
+        if ignore_case {
+            name.to_lowercase()
+        } else {
+            name.into_owned()
+        }
+    };
+    relative
+        .components()
+        .map(fold)
+        .eq(Path::new(SECURITY_FILE).components().map(fold))
+}
+
+/// Reject allow patterns that are empty, negated, or nothing but wildcards
+fn check_allow_pattern(pattern: &str) -> Result<(), String> {
+    let trimmed = pattern.trim();
+    if trimmed.is_empty() {
+        return Err("allow patterns can't be empty".to_string());
+    }
+    if trimmed.starts_with('!') {
+        return Err(format!("allow pattern '{}' can't be negated", pattern));
+    }
+    let literal = trimmed
+        .chars()
+        .any(|c| !matches!(c, '*' | '?' | '/' | '\\' | '.' | '[' | ']'));
+    if !literal {
+        return Err(format!(
+            "allow pattern '{}' is too broad; name the files to allow",
+            pattern
+        ));
+    }
+    Ok(())
+}
+
+fn build_matcher(workspace: &Path, patterns: &[String]) -> Result<Option<Gitignore>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GitignoreBuilder::new(workspace);
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+/// Modification time and size of the security file, `None` when it's missing
+type FileStamp = Option<(Option<SystemTime>, u64)>;
+
+fn stamp(path: &Path) -> FileStamp {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok(), meta.len()))
+}
+
+/// A loaded policy and the stamp of the security file it came from
+type CachedPolicy = (FileStamp, Arc<SensitivePolicy>);
+
+/// The policy for `workspace`, reloaded when its security file changes
+fn policy_for(workspace: &Path) -> Arc<SensitivePolicy> {
+    static POLICIES: OnceLock<Mutex<HashMap<PathBuf, CachedPolicy>>> = OnceLock::new();
+
+    let current = stamp(&workspace.join(SECURITY_FILE));
+    let mut policies = POLICIES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some((cached, policy)) = policies.get(workspace) {
+        if *cached == current {
+            return policy.clone();
+        }
+    }
+
+    let policy = Arc::new(SensitivePolicy::load(workspace));
+    policies.insert(workspace.to_path_buf(), (current, policy.clone()));
+    policy
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn workspace(security: Option<&str>) -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        if let Some(text) = security {
+            fs::create_dir_all(root.join(".vswrite")).unwrap();
+            fs::write(root.join(SECURITY_FILE), text).unwrap();
+        }
+        (dir, root)
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        let (_dir, root) = workspace(None);
+        assert!(check(&root, &root.join("chapters/one.md")).is_ok());
+
+        let err = check(&root, &root.join("secrets.json")).unwrap_err();
+        assert!(err.contains("sensitive file pattern 'secrets'"), "{}", err);
+        assert!(err.contains(SECURITY_FILE));
+        assert_eq!(
+            is_sensitive_path(&root.join("keys/server.PEM")),
+            Some(SensitiveRule::Extension(".pem"))
+        );
+        assert_eq!(
+            is_sensitive_path(&root.join(".ssh/config")),
+            Some(SensitiveRule::Directory(".ssh"))
+        );
+    }
+
+    #[test]
+    fn test_workspace_allow_and_deny() {
+        let (_dir, root) = workspace(Some(
+            "[sensitive_files]\ndeny = [\"private/\", \"*.secret\"]\nallow = [\"secrets.json\", \"research/credentials\", \"private/ok.md\"]\n",
+        ));
+
+        assert!(check(&root, &root.join("secrets.json")).is_ok());
+        assert!(check(&root, &root.join("research/credentials")).is_ok());
+        assert!(check(&root, &root.join("credentials")).is_err());
+        assert!(check(&root, &root.join(".env")).is_err());
+
+        let err = check(&root, &root.join("notes/plot.secret")).unwrap_err();
+        assert!(err.contains("deny pattern '*.secret'"));
+        assert!(check(&root, &root.join("private/diary.md")).is_err());
+        assert!(check(&root, &root.join("private/ok.md")).is_ok());
+
+        // The policy file can't be opened up
+        let err = check(&root, &root.join(SECURITY_FILE)).unwrap_err();
+        assert!(err.contains("security policy"));
+    }
+
+    #[test]
+    fn test_security_file_case() {
+        let upper = Path::new(".VSWrite/Security.TOML");
+        assert!(is_security_file(upper, true));
+        assert!(!is_security_file(upper, false));
+        assert!(is_security_file(Path::new(SECURITY_FILE), false));
+        assert!(!is_security_file(
+            Path::new(".vswrite/security.toml.bak"),
+            true
+        ));
+
+        let (_dir, root) = workspace(Some("[sensitive_files]\nallow = [\".vswrite/\"]\n"));
+        let err = check(&root, &root.join(".vswrite").join("security.toml")).unwrap_err();
+        assert!(err.contains("security policy"), "{}", err);
+    }
+
+    #[test]
+    fn test_broad_allow_is_rejected() {
+        for pattern in ["*", "**", "**/*", "*.*", "/", "!secrets.json"] {
+            let (_dir, root) = workspace(Some(&format!(
+                "[sensitive_files]\nallow = [{:?}]\n",
+                pattern
+            )));
+            let err = check(&root, &root.join("secrets.json")).unwrap_err();
+            assert!(err.contains("was ignored"), "{}: {}", pattern, err);
+        }
+
+        let (_dir, root) = workspace(Some("[sensitive_files]\nalow = [\"x\"]\n"));
+        assert!(check(&root, &root.join(".env"))
+            .unwrap_err()
+            .contains("was ignored"));
+    }
+
+    #[test]
+    fn test_policy_reloads_when_file_changes() {
+        let (_dir, root) = workspace(Some("[sensitive_files]\nallow = []\n"));
+        assert!(check(&root, &root.join("secrets.json")).is_err());
+
+        fs::write(
+            root.join(SECURITY_FILE),
+            "[sensitive_files]\nallow = [\"secrets.json\"]\n",
+        )
+        .unwrap();
+        assert!(check(&root, &root.join("secrets.json")).is_ok());
+
+        fs::remove_file(root.join(SECURITY_FILE)).unwrap();
+        assert!(check(&root, &root.join("secrets.json")).is_err());
+    }
+}
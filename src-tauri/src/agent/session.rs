@@ -5,10 +5,18 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
 
-use super::types::{ApprovalMode, LlmProvider};
+use super::pricing;
+use super::redact::redact;
+use super::text::truncate_to_char_boundary;
+use super::tool_output::ToolEffects;
+use super::transcripts::TranscriptWriter;
+use super::types::{
+    ApprovalDecision, ApprovalMode, ApprovalScope, FileChange, LlmProvider, Message, ServedModel,
+    ToolRisk, Usage,
+};
 
 // ============================================================================
 // Session Types
@@ -18,10 +26,10 @@ use super::types::{ApprovalMode, LlmProvider};
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SessionStatus {
-    /// Session is actively running
-    Active,
-    /// Session is paused (waiting for approval)
-    Paused,
+    /// Session is running
+    Running,
+    /// Session is waiting for the user to approve a tool call
+    AwaitingApproval,
     /// Session completed successfully
     Completed,
     /// Session failed with an error
@@ -33,8 +41,11 @@ pub enum SessionStatus {
 /// An agent session tracking a single run
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
-    /// Unique session identifier (same as run_id)
+    /// Unique session identifier
     pub id: String,
+    /// The run this session tracks (the ID its events and cancellation use)
+    #[serde(default)]
+    pub run_id: Option<String>,
     /// When the session was created
     pub created_at: DateTime<Utc>,
     /// When the session was last active
@@ -45,18 +56,89 @@ pub struct Session {
     pub provider: LlmProvider,
     /// Model used
     pub model: String,
+    /// Model (and upstream provider) that actually answered, as last reported
+    #[serde(default)]
+    pub served_model: Option<ServedModel>,
     /// Approval mode for tool execution
     pub approval_mode: ApprovalMode,
     /// Number of tool calls made
     pub tool_call_count: u32,
     /// Total tokens used (prompt + completion)
     pub total_tokens: u32,
+    /// Prompt tokens across every LLM call in the run
+    pub prompt_tokens: u32,
+    /// Completion tokens across every LLM call in the run
+    pub completion_tokens: u32,
+    /// Estimated cost in USD, `None` when the model's price is unknown
+    pub estimated_cost: Option<f64>,
+    /// Tokens sent to the embeddings endpoint (semantic search and indexing)
+    #[serde(default)]
+    pub embedding_tokens: u32,
+    /// Estimated cost of those tokens in USD, included in `estimated_cost`
+    #[serde(default)]
+    pub embedding_cost: f64,
     /// Current status
     pub status: SessionStatus,
     /// Error message if failed
     pub error: Option<String>,
     /// The task that started this session
     pub task: String,
+    /// Files the run created, modified, or deleted (recorded when it completes)
+    #[serde(default)]
+    pub file_changes: Vec<FileChange>,
+    /// Notes the model kept in its scratchpad (recorded when the run completes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scratchpad: Option<String>,
+    /// Iteration the run is on (1-based, 0 before the first model call)
+    #[serde(default)]
+    pub current_iteration: u32,
+    /// Iteration limit of the run
+    #[serde(default)]
+    pub max_iterations: u32,
+    /// The tool most recently started
+    #[serde(default)]
+    pub last_tool: Option<String>,
+    /// When the run last reported progress
+    pub last_event_at: DateTime<Utc>,
+    /// What was changed in the message history the run started with, if anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history_normalization: Option<HistoryNormalization>,
+}
+
+/// Changes made to a run's message history before it was sent to the provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryNormalization {
+    /// Messages with no content
+    pub dropped_empty: usize,
+    /// Oldest messages dropped to fit the history budget
+    pub trimmed: usize,
+    /// User messages folded into the user message before them (or into the task)
+    pub merged: usize,
+    /// Assistant messages dropped from the start, for providers that want the
+    /// conversation to open with a user message
+    pub dropped_leading: usize,
+}
+
+impl HistoryNormalization {
+    /// Whether the history was sent as given
+    pub fn is_unchanged(&self) -> bool {
+        *self == HistoryNormalization::default()
+    }
+}
+
+/// Token usage and estimated cost of one session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionUsage {
+    pub session_id: String,
+    pub model: String,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+    /// Tokens sent to the embeddings endpoint
+    #[serde(default)]
+    pub embedding_tokens: u32,
+    /// Estimated cost in USD, `None` when the model's price is unknown
+    pub estimated_cost: Option<f64>,
 }
 
 impl Session {
@@ -72,33 +154,145 @@ impl Session {
         let now = Utc::now();
         Session {
             id,
+            run_id: None,
             created_at: now,
             last_active: now,
             workspace,
             provider,
             model,
+            served_model: None,
             approval_mode,
             tool_call_count: 0,
             total_tokens: 0,
-            status: SessionStatus::Active,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            estimated_cost: None,
+            embedding_tokens: 0,
+            embedding_cost: 0.0,
+            status: SessionStatus::Running,
             error: None,
             task,
+            file_changes: Vec::new(),
+            scratchpad: None,
+            current_iteration: 0,
+            max_iterations: 0,
+            last_tool: None,
+            last_event_at: now,
+            history_normalization: None,
         }
     }
 
+    /// Whether the run is still going (running or awaiting approval)
+    pub fn is_live(&self) -> bool {
+        matches!(
+            self.status,
+            SessionStatus::Running | SessionStatus::AwaitingApproval
+        )
+    }
+
     /// Update the session after a tool call
-    #[allow(dead_code)]
     pub fn record_tool_call(&mut self) {
         self.tool_call_count += 1;
         self.last_active = Utc::now();
     }
 
-    /// Update token usage
-    pub fn record_tokens(&mut self, tokens: u32) {
-        self.total_tokens += tokens;
+    /// Note that the run started an iteration (0-based, as the loop counts)
+    pub fn start_iteration(&mut self, iteration: u32, max_iterations: u32) {
+        if !self.is_live() {
+            return;
+        }
+        self.current_iteration = iteration + 1;
+        self.max_iterations = max_iterations;
+        self.status = SessionStatus::Running;
+        self.record_event();
+    }
+
+    /// Note that the run started a tool call
+    pub fn start_tool(&mut self, name: &str) {
+        if !self.is_live() {
+            return;
+        }
+        self.last_tool = Some(name.to_string());
+        self.record_tool_call();
+        self.record_event();
+    }
+
+    /// Switch between waiting for an approval and running
+    pub fn set_awaiting_approval(&mut self, waiting: bool) {
+        if !self.is_live() {
+            return;
+        }
+        self.status = if waiting {
+            SessionStatus::AwaitingApproval
+        } else {
+            SessionStatus::Running
+        };
+        self.record_event();
+    }
+
+    fn record_event(&mut self) {
+        let now = Utc::now();
+        self.last_event_at = now;
+        self.last_active = now;
+    }
+
+    /// Add the usage of one LLM call and re-estimate the cost
+    pub fn record_usage(&mut self, usage: &Usage) {
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.total_tokens += usage.total_tokens;
+        self.estimate_cost();
         self.last_active = Utc::now();
     }
 
+    /// Add tokens sent to the embeddings endpoint with `model`
+    pub fn record_embedding_usage(&mut self, model: &str, tokens: u32) {
+        self.embedding_tokens += tokens;
+        self.embedding_cost += pricing::estimate_embedding_cost(model, tokens).unwrap_or(0.0);
+        self.estimate_cost();
+        self.last_active = Utc::now();
+    }
+
+    fn estimate_cost(&mut self) {
+        let totals = Usage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            total_tokens: self.total_tokens,
+        };
+        self.estimated_cost =
+            pricing::estimate_cost(&self.model, &totals).map(|cost| cost + self.embedding_cost);
+    }
+
+    /// Note which model answered the latest LLM call
+    pub fn record_served_model(&mut self, served: &ServedModel) {
+        self.served_model = Some(served.clone());
+        self.last_active = Utc::now();
+    }
+
+    /// Token totals and cost so far
+    pub fn usage(&self) -> SessionUsage {
+        SessionUsage {
+            session_id: self.id.clone(),
+            model: self.model.clone(),
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            total_tokens: self.total_tokens,
+            embedding_tokens: self.embedding_tokens,
+            estimated_cost: self.estimated_cost,
+        }
+    }
+
+    /// Store the files the run changed
+    pub fn record_file_changes(&mut self, changes: Vec<FileChange>) {
+        self.file_changes = changes;
+        self.last_active = Utc::now();
+    }
+
+    /// Store the model's scratchpad notes, if it kept any
+    pub fn record_scratchpad(&mut self, notes: String) {
+        self.scratchpad = Some(notes).filter(|n| !n.is_empty());
+    }
+
     /// Mark session as completed
     pub fn complete(&mut self) {
         self.status = SessionStatus::Completed;
@@ -144,6 +338,52 @@ pub struct AuditEntry {
     pub success: bool,
     /// Duration in milliseconds
     pub duration_ms: u64,
+    /// Size of a tool call's output before truncation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_bytes: Option<u64>,
+    /// What a successful tool call wrote
+    #[serde(default, skip_serializing_if = "ToolEffects::is_empty")]
+    pub effects: ToolEffects,
+    /// Approval details (for approval entries)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approval: Option<ApprovalRecord>,
+}
+
+/// Who settled an approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalActor {
+    /// A person answered the prompt
+    User,
+    /// The agent settled it (timeout or no approval channel)
+    System,
+}
+
+impl ApprovalActor {
+    /// The actor implied by a decision
+    pub fn for_decision(decision: ApprovalDecision) -> Self {
+        match decision {
+            ApprovalDecision::Approved | ApprovalDecision::Denied => ApprovalActor::User,
+            ApprovalDecision::TimedOut | ApprovalDecision::AutoApproved => ApprovalActor::System,
+        }
+    }
+}
+
+/// Details of a single approval decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRecord {
+    /// The approval request ID sent to the UI
+    pub approval_id: String,
+    /// Risk level of the gated tool
+    pub risk: ToolRisk,
+    /// What was decided
+    pub decision: ApprovalDecision,
+    /// Who decided it
+    pub decided_by: ApprovalActor,
+    /// How far the decision reaches
+    pub scope: ApprovalScope,
+    /// Time from request to decision in milliseconds
+    pub latency_ms: u64,
 }
 
 /// Types of audit events
@@ -160,13 +400,14 @@ pub enum AuditEventType {
     ToolCall,
     /// Tool was skipped (dry-run or denied)
     ToolSkipped,
+    /// Tool approval was decided
+    Approval,
     /// Error occurred
     Error,
 }
 
 impl AuditEntry {
     /// Create a new audit entry for a tool call
-    #[allow(dead_code)]
     pub fn tool_call(
         session_id: &str,
         tool_name: &str,
@@ -180,7 +421,7 @@ impl AuditEntry {
         let args_hash = format!("{:x}", md5_hash(&args_str));
 
         // Truncate and redact result summary
-        let result_summary = redact_sensitive(truncate_string(result, 200));
+        let result_summary = redact(&truncate_string(result, 200));
 
         AuditEntry {
             id: uuid::Uuid::new_v4().to_string(),
@@ -192,6 +433,35 @@ impl AuditEntry {
             result_summary: Some(result_summary),
             success,
             duration_ms,
+            output_bytes: None,
+            effects: ToolEffects::default(),
+            approval: None,
+        }
+    }
+
+    /// Create an audit entry for a tool approval decision
+    pub fn approval(
+        session_id: &str,
+        tool_name: &str,
+        args: &serde_json::Value,
+        record: ApprovalRecord,
+    ) -> Self {
+        let args_str = serde_json::to_string(args).unwrap_or_default();
+        let args_hash = format!("{:x}", md5_hash(&args_str));
+
+        AuditEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_id: session_id.to_string(),
+            timestamp: Utc::now(),
+            event_type: AuditEventType::Approval,
+            tool_name: Some(tool_name.to_string()),
+            args_hash: Some(args_hash),
+            result_summary: None,
+            success: record.decision.allows_execution(),
+            duration_ms: record.latency_ms,
+            output_bytes: None,
+            effects: ToolEffects::default(),
+            approval: Some(record),
         }
     }
 
@@ -207,11 +477,13 @@ impl AuditEntry {
             result_summary: None,
             success: true,
             duration_ms: 0,
+            output_bytes: None,
+            effects: ToolEffects::default(),
+            approval: None,
         }
     }
 
     /// Create an audit entry for session end
-    #[allow(dead_code)]
     pub fn session_end(session_id: &str, success: bool) -> Self {
         AuditEntry {
             id: uuid::Uuid::new_v4().to_string(),
@@ -223,6 +495,9 @@ impl AuditEntry {
             result_summary: None,
             success,
             duration_ms: 0,
+            output_bytes: None,
+            effects: ToolEffects::default(),
+            approval: None,
         }
     }
 }
@@ -231,9 +506,15 @@ impl AuditEntry {
 // Session Store
 // ============================================================================
 
-/// In-memory session store
+/// Sessions kept before the oldest finished ones are dropped
+pub const MAX_SESSIONS: usize = 100;
+
+/// In-memory session store.
+///
+/// Each session has its own lock, so a run updating its progress only holds the
+/// map's read lock and doesn't block readers of other sessions.
 pub struct SessionStore {
-    sessions: RwLock<HashMap<String, Session>>,
+    sessions: RwLock<HashMap<String, Arc<RwLock<Session>>>>,
     audit_log: RwLock<Vec<AuditEntry>>,
     max_sessions: usize,
     max_audit_entries: usize,
@@ -245,7 +526,7 @@ impl SessionStore {
         SessionStore {
             sessions: RwLock::new(HashMap::new()),
             audit_log: RwLock::new(Vec::new()),
-            max_sessions: 100,       // Keep last 100 sessions
+            max_sessions: MAX_SESSIONS,
             max_audit_entries: 1000, // Keep last 1000 audit entries
         }
     }
@@ -265,15 +546,17 @@ impl SessionStore {
         // Add session
         {
             let mut sessions = self.sessions.write().unwrap();
-            sessions.insert(id.clone(), session);
+            sessions.insert(id.clone(), Arc::new(RwLock::new(session)));
 
             // Cleanup old sessions if over limit
             if sessions.len() > self.max_sessions {
-                // Find oldest completed sessions to remove
+                // Find oldest finished sessions to remove
                 let mut completed: Vec<_> = sessions
                     .iter()
-                    .filter(|(_, s)| s.status != SessionStatus::Active)
-                    .map(|(id, s)| (id.clone(), s.created_at))
+                    .filter_map(|(id, s)| {
+                        let s = s.read().ok()?;
+                        (!s.is_live()).then(|| (id.clone(), s.created_at))
+                    })
                     .collect();
                 completed.sort_by_key(|(_, created)| *created);
 
@@ -289,9 +572,28 @@ impl SessionStore {
         id
     }
 
+    /// The lock of one session, released from the map's lock
+    fn session_cell(&self, id: &str) -> Option<Arc<RwLock<Session>>> {
+        self.sessions.read().ok()?.get(id).cloned()
+    }
+
+    /// Snapshots of every session
+    fn snapshot(&self) -> Vec<Session> {
+        let cells: Vec<_> = match self.sessions.read() {
+            Ok(s) => s.values().cloned().collect(),
+            Err(_) => return Vec::new(),
+        };
+        cells
+            .iter()
+            .filter_map(|cell| cell.read().ok().map(|s| s.clone()))
+            .collect()
+    }
+
     /// Get a session by ID
     pub fn get_session(&self, id: &str) -> Option<Session> {
-        self.sessions.read().ok()?.get(id).cloned()
+        let cell = self.session_cell(id)?;
+        let session = cell.read().ok()?;
+        Some(session.clone())
     }
 
     /// Update a session
@@ -299,26 +601,77 @@ impl SessionStore {
     where
         F: FnOnce(&mut Session),
     {
-        if let Ok(mut sessions) = self.sessions.write() {
-            if let Some(session) = sessions.get_mut(id) {
-                f(session);
+        if let Some(cell) = self.session_cell(id) {
+            if let Ok(mut session) = cell.write() {
+                f(&mut session);
             }
         }
     }
 
-    /// List all sessions (most recent first)
-    pub fn list_sessions(&self, limit: usize) -> Vec<Session> {
-        let sessions = match self.sessions.read() {
-            Ok(s) => s,
-            Err(_) => return Vec::new(),
-        };
-
-        let mut list: Vec<_> = sessions.values().cloned().collect();
+    /// List sessions (most recent first), only those in `workspace` when given
+    pub fn list_sessions(&self, workspace: Option<&Path>, limit: usize) -> Vec<Session> {
+        let mut list: Vec<_> = self
+            .snapshot()
+            .into_iter()
+            .filter(|s| workspace.map_or(true, |w| s.workspace == w))
+            .collect();
         list.sort_by(|a, b| b.created_at.cmp(&a.created_at));
         list.truncate(limit);
         list
     }
 
+    /// Link a session to the run it tracks
+    pub fn link_run(&self, session_id: &str, run_id: &str) {
+        self.update_session(session_id, |s| s.run_id = Some(run_id.to_string()));
+    }
+
+    /// The session tracking `run_id`
+    pub fn session_for_run(&self, run_id: &str) -> Option<Session> {
+        self.snapshot()
+            .into_iter()
+            .find(|s| s.run_id.as_deref() == Some(run_id))
+    }
+
+    /// Sessions whose run is still going, oldest first
+    pub fn active_sessions(&self) -> Vec<Session> {
+        let mut list: Vec<_> = self
+            .snapshot()
+            .into_iter()
+            .filter(Session::is_live)
+            .collect();
+        list.sort_by_key(|s| s.created_at);
+        list
+    }
+
+    /// Mark every running or awaiting session as cancelled.
+    ///
+    /// Used during shutdown so sessions don't stay "running" forever. Returns the
+    /// number of sessions that were cancelled.
+    pub fn cancel_unfinished_sessions(&self, reason: &str) -> usize {
+        let cells: Vec<_> = match self.sessions.read() {
+            Ok(s) => s.values().cloned().collect(),
+            Err(_) => return 0,
+        };
+
+        let mut cancelled = Vec::new();
+        for cell in cells {
+            let Ok(mut session) = cell.write() else {
+                continue;
+            };
+            if session.is_live() {
+                session.cancel();
+                session.error = Some(reason.to_string());
+                cancelled.push(session.id.clone());
+            }
+        }
+
+        for id in &cancelled {
+            self.log_entry(AuditEntry::session_end(id, false));
+        }
+
+        cancelled.len()
+    }
+
     /// Add an audit entry
     pub fn log_entry(&self, entry: AuditEntry) {
         if let Ok(mut log) = self.audit_log.write() {
@@ -351,8 +704,18 @@ impl SessionStore {
         self.update_session(session_id, |s| s.record_tool_call());
     }
 
-    /// Get audit entries for a session
+    /// Get audit entries for a session, newest first
     pub fn get_session_audit(&self, session_id: &str, limit: usize) -> Vec<AuditEntry> {
+        self.get_session_audit_filtered(session_id, None, limit)
+    }
+
+    /// Get audit entries for a session, optionally restricted to one event type
+    pub fn get_session_audit_filtered(
+        &self,
+        session_id: &str,
+        event_type: Option<AuditEventType>,
+        limit: usize,
+    ) -> Vec<AuditEntry> {
         let log = match self.audit_log.read() {
             Ok(l) => l,
             Err(_) => return Vec::new(),
@@ -360,9 +723,10 @@ impl SessionStore {
 
         log.iter()
             .filter(|e| e.session_id == session_id)
-            .cloned()
+            .filter(|e| event_type.map_or(true, |t| e.event_type == t))
             .rev()
             .take(limit)
+            .cloned()
             .collect()
     }
 
@@ -386,6 +750,141 @@ impl Default for SessionStore {
 /// Shared session store type for Tauri state
 pub type SharedSessionStore = std::sync::Arc<SessionStore>;
 
+/// What an executed tool call produced, for its audit entry
+#[derive(Debug, Clone, Default)]
+pub struct AuditedOutput {
+    /// Size of the output (or error) before truncation
+    pub bytes: u64,
+    pub effects: ToolEffects,
+}
+
+/// A session's handle on the shared store, for recording audit entries (and,
+/// when given a writer, the conversation) during a run
+#[derive(Clone)]
+pub struct SessionAudit {
+    store: SharedSessionStore,
+    session_id: String,
+    transcript: Option<Arc<Mutex<TranscriptWriter>>>,
+}
+
+impl SessionAudit {
+    pub fn new(store: SharedSessionStore, session_id: impl Into<String>) -> Self {
+        SessionAudit {
+            store,
+            session_id: session_id.into(),
+            transcript: None,
+        }
+    }
+
+    /// Also append the run's messages to a transcript
+    pub fn with_transcript(mut self, writer: TranscriptWriter) -> Self {
+        self.transcript = Some(Arc::new(Mutex::new(writer)));
+        self
+    }
+
+    /// Save messages to the transcript, if there is one. Failures are logged; the
+    /// run goes on without them.
+    pub fn record_messages(&self, messages: &[Message]) {
+        let Some(transcript) = &self.transcript else {
+            return;
+        };
+        let result = match transcript.lock() {
+            Ok(mut writer) => writer.append(messages),
+            Err(_) => return,
+        };
+        if let Err(e) = result {
+            log::warn!(
+                "Failed to save transcript for session {}: {}",
+                self.session_id,
+                e
+            );
+        }
+    }
+
+    /// The session entries are recorded against
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// The run the session is linked to, if any
+    pub fn run_id(&self) -> Option<String> {
+        self.store.get_session(&self.session_id)?.run_id
+    }
+
+    /// Add the usage of one LLM call to this session
+    pub fn record_usage(&self, usage: &Usage) {
+        self.store
+            .update_session(&self.session_id, |s| s.record_usage(usage));
+    }
+
+    /// Add tokens sent to the embeddings endpoint to this session
+    pub fn record_embedding_usage(&self, model: &str, tokens: u32) {
+        self.store.update_session(&self.session_id, |s| {
+            s.record_embedding_usage(model, tokens)
+        });
+    }
+
+    /// Note which model answered the latest LLM call
+    pub fn record_served_model(&self, served: &ServedModel) {
+        self.store
+            .update_session(&self.session_id, |s| s.record_served_model(served));
+    }
+
+    /// Note the start of a loop iteration (0-based)
+    pub fn start_iteration(&self, iteration: u32, max_iterations: u32) {
+        self.store.update_session(&self.session_id, |s| {
+            s.start_iteration(iteration, max_iterations)
+        });
+    }
+
+    /// Note the start of a tool call
+    pub fn start_tool(&self, name: &str) {
+        self.store
+            .update_session(&self.session_id, |s| s.start_tool(name));
+    }
+
+    /// Note that the run is (or is no longer) waiting for an approval
+    pub fn set_awaiting_approval(&self, waiting: bool) {
+        self.store
+            .update_session(&self.session_id, |s| s.set_awaiting_approval(waiting));
+    }
+
+    /// Record a tool call for this session, with its output size when it ran
+    pub fn log_tool_call(
+        &self,
+        tool_name: &str,
+        args: &serde_json::Value,
+        result: &str,
+        success: bool,
+        duration_ms: u64,
+        output: Option<AuditedOutput>,
+    ) {
+        let mut entry = AuditEntry::tool_call(
+            &self.session_id,
+            tool_name,
+            args,
+            result,
+            success,
+            duration_ms,
+        );
+        if let Some(output) = output {
+            entry.output_bytes = Some(output.bytes);
+            entry.effects = output.effects;
+        }
+        self.store.log_entry(entry);
+    }
+
+    /// Record an approval decision for this session
+    pub fn log_approval(&self, tool_name: &str, args: &serde_json::Value, record: ApprovalRecord) {
+        self.store.log_entry(AuditEntry::approval(
+            &self.session_id,
+            tool_name,
+            args,
+            record,
+        ));
+    }
+}
+
 // ============================================================================
 // Helper Functions
 // ============================================================================
@@ -407,36 +906,10 @@ fn truncate_string(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
     } else {
-        format!("{}...", &s[..max_len])
+        format!("{}...", truncate_to_char_boundary(s, max_len))
     }
 }
 
-/// Redact sensitive patterns from a string
-#[allow(dead_code)]
-fn redact_sensitive(s: String) -> String {
-    // Patterns to redact (API keys, passwords, etc.)
-    let patterns: &[(&str, &str)] = &[
-        (r"sk-[a-zA-Z0-9]{20,}", "[REDACTED_API_KEY]"),
-        (r"sk-ant-[a-zA-Z0-9\-]{20,}", "[REDACTED_API_KEY]"),
-        (
-            r#"password["']?\s*[:=]\s*["'][^"']+["']"#,
-            "password: [REDACTED]",
-        ),
-        (
-            r#"secret["']?\s*[:=]\s*["'][^"']+["']"#,
-            "secret: [REDACTED]",
-        ),
-    ];
-
-    let mut result = s;
-    for (pattern, replacement) in patterns {
-        if let Ok(re) = regex::Regex::new(pattern) {
-            result = re.replace_all(&result, *replacement).to_string();
-        }
-    }
-    result
-}
-
 // ============================================================================
 // Tests
 // ============================================================================
@@ -457,7 +930,7 @@ mod tests {
         );
 
         let session = store.get_session(&id).unwrap();
-        assert_eq!(session.status, SessionStatus::Active);
+        assert_eq!(session.status, SessionStatus::Running);
         assert_eq!(session.tool_call_count, 0);
     }
 
@@ -474,7 +947,11 @@ mod tests {
 
         store.update_session(&id, |s| {
             s.record_tool_call();
-            s.record_tokens(100);
+            s.record_usage(&Usage {
+                prompt_tokens: 80,
+                completion_tokens: 20,
+                total_tokens: 100,
+            });
         });
 
         let session = store.get_session(&id).unwrap();
@@ -482,6 +959,156 @@ mod tests {
         assert_eq!(session.total_tokens, 100);
     }
 
+    #[test]
+    fn test_session_usage_accumulates_across_calls() {
+        let store: SharedSessionStore = std::sync::Arc::new(SessionStore::new());
+        let id = store.create_session(
+            PathBuf::from("/tmp"),
+            LlmProvider::Claude,
+            "claude-sonnet-4-20250514".to_string(),
+            ApprovalMode::AutoApprove,
+            "Test".to_string(),
+        );
+        let audit = SessionAudit::new(store.clone(), id.clone());
+        for _ in 0..2 {
+            audit.record_usage(&Usage {
+                prompt_tokens: 500_000,
+                completion_tokens: 50_000,
+                total_tokens: 550_000,
+            });
+        }
+
+        let usage = store.get_session(&id).unwrap().usage();
+        assert_eq!(usage.prompt_tokens, 1_000_000);
+        assert_eq!(usage.completion_tokens, 100_000);
+        assert_eq!(usage.total_tokens, 1_100_000);
+        assert!((usage.estimated_cost.unwrap() - 4.5).abs() < 1e-9);
+
+        // Embedding tokens are counted and priced on their own model
+        audit.record_embedding_usage("text-embedding-3-small", 1_000_000);
+        let usage = store.get_session(&id).unwrap().usage();
+        assert_eq!(usage.embedding_tokens, 1_000_000);
+        assert_eq!(usage.prompt_tokens, 1_000_000);
+        assert!((usage.estimated_cost.unwrap() - 4.52).abs() < 1e-9);
+
+        store.update_session(&id, |s| s.model = "llama3.1".to_string());
+        audit.record_usage(&Usage {
+            prompt_tokens: 1,
+            completion_tokens: 1,
+            total_tokens: 2,
+        });
+        assert!(store.get_session(&id).unwrap().estimated_cost.is_none());
+    }
+
+    #[test]
+    fn test_live_progress_and_active_sessions() {
+        let store: SharedSessionStore = std::sync::Arc::new(SessionStore::new());
+        let mut ids = Vec::new();
+        for task in ["a", "b", "c"] {
+            ids.push(store.create_session(
+                PathBuf::from("/tmp"),
+                LlmProvider::OpenAI,
+                "gpt-5-mini".to_string(),
+                ApprovalMode::ApproveWrites,
+                task.to_string(),
+            ));
+        }
+
+        let audit = SessionAudit::new(store.clone(), ids[0].clone());
+        audit.start_iteration(2, 25);
+        audit.start_tool("write_file");
+        audit.set_awaiting_approval(true);
+
+        let session = store.get_session(&ids[0]).unwrap();
+        assert_eq!(session.status, SessionStatus::AwaitingApproval);
+        assert_eq!(session.current_iteration, 3);
+        assert_eq!(session.max_iterations, 25);
+        assert_eq!(session.last_tool.as_deref(), Some("write_file"));
+        assert_eq!(session.tool_call_count, 1);
+        assert!(session.last_event_at >= session.created_at);
+
+        audit.set_awaiting_approval(false);
+        assert_eq!(
+            store.get_session(&ids[0]).unwrap().status,
+            SessionStatus::Running
+        );
+
+        store.update_session(&ids[1], |s| s.complete());
+        // Late progress doesn't revive a finished session
+        SessionAudit::new(store.clone(), ids[1].clone()).start_iteration(4, 25);
+        assert_eq!(
+            store.get_session(&ids[1]).unwrap().status,
+            SessionStatus::Completed
+        );
+
+        let active: Vec<_> = store.active_sessions().into_iter().map(|s| s.id).collect();
+        assert_eq!(active.len(), 2);
+        assert!(active.contains(&ids[0]) && active.contains(&ids[2]));
+
+        let json = serde_json::to_value(store.get_session(&ids[0]).unwrap()).unwrap();
+        assert_eq!(json["status"], "running");
+        assert_eq!(json["current_iteration"], 3);
+    }
+
+    #[test]
+    fn test_session_for_run_survives_failure_and_cancel() {
+        let store = SessionStore::new();
+        let failed = store.create_session(
+            PathBuf::from("/tmp"),
+            LlmProvider::OpenAI,
+            "gpt-5-mini".to_string(),
+            ApprovalMode::AutoApprove,
+            "a".to_string(),
+        );
+        let cancelled = store.create_session(
+            PathBuf::from("/tmp"),
+            LlmProvider::OpenAI,
+            "gpt-5-mini".to_string(),
+            ApprovalMode::AutoApprove,
+            "b".to_string(),
+        );
+        store.link_run(&failed, "run-1");
+        store.link_run(&cancelled, "run-2");
+
+        store.update_session(&failed, |s| s.fail("boom".to_string()));
+        assert_eq!(store.cancel_unfinished_sessions("shutdown"), 1);
+
+        let session = store.session_for_run("run-1").unwrap();
+        assert_eq!(session.id, failed);
+        assert_eq!(session.status, SessionStatus::Failed);
+        let session = store.session_for_run("run-2").unwrap();
+        assert_eq!(session.id, cancelled);
+        assert_eq!(session.status, SessionStatus::Cancelled);
+        assert!(store.session_for_run("run-3").is_none());
+    }
+
+    #[test]
+    fn test_list_sessions_by_workspace() {
+        let store = SessionStore::new();
+        for (workspace, task) in [
+            ("/tmp/novel", "a"),
+            ("/tmp/essays", "b"),
+            ("/tmp/novel", "c"),
+        ] {
+            store.create_session(
+                PathBuf::from(workspace),
+                LlmProvider::OpenAI,
+                "gpt-5-mini".to_string(),
+                ApprovalMode::AutoApprove,
+                task.to_string(),
+            );
+        }
+
+        assert_eq!(store.list_sessions(None, 10).len(), 3);
+        let novel = store.list_sessions(Some(Path::new("/tmp/novel")), 10);
+        assert_eq!(novel.len(), 2);
+        assert!(novel.iter().all(|s| s.workspace == Path::new("/tmp/novel")));
+        assert_eq!(
+            store.list_sessions(Some(Path::new("/tmp/novel")), 1).len(),
+            1
+        );
+    }
+
     #[test]
     fn test_audit_logging() {
         let store = SessionStore::new();
@@ -503,11 +1130,63 @@ mod tests {
     }
 
     #[test]
-    fn test_redact_sensitive() {
-        let input = "API key: sk-abc123456789012345678901234567890".to_string();
-        let redacted = redact_sensitive(input);
-        assert!(redacted.contains("[REDACTED_API_KEY]"));
-        assert!(!redacted.contains("sk-abc"));
+    fn test_audit_filter_by_event_type() {
+        let store = SessionStore::new();
+        let session_id = "approval-session";
+
+        store.log_tool_call(
+            session_id,
+            "write_file",
+            &serde_json::json!({"path": "a.md"}),
+            "ok",
+            true,
+            5,
+        );
+        store.log_entry(AuditEntry::approval(
+            session_id,
+            "write_file",
+            &serde_json::json!({"path": "a.md"}),
+            ApprovalRecord {
+                approval_id: "approval-1".to_string(),
+                risk: ToolRisk::Medium,
+                decision: ApprovalDecision::Denied,
+                decided_by: ApprovalActor::User,
+                scope: ApprovalScope::Call,
+                latency_ms: 1200,
+            },
+        ));
+
+        let all = store.get_session_audit_filtered(session_id, None, 10);
+        assert_eq!(all.len(), 2);
+
+        let approvals =
+            store.get_session_audit_filtered(session_id, Some(AuditEventType::Approval), 10);
+        assert_eq!(approvals.len(), 1);
+        assert!(!approvals[0].success);
+        assert_eq!(approvals[0].duration_ms, 1200);
+        let record = approvals[0].approval.as_ref().unwrap();
+        assert_eq!(record.approval_id, "approval-1");
+        assert_eq!(record.decision, ApprovalDecision::Denied);
+
+        let json = serde_json::to_value(&approvals[0]).unwrap();
+        assert_eq!(json["event_type"], "approval");
+        assert_eq!(json["approval"]["decided_by"], "user");
+        assert_eq!(json["approval"]["scope"], "call");
+    }
+
+    #[test]
+    fn test_tool_call_audit_is_redacted() {
+        let entry = AuditEntry::tool_call(
+            "session-1",
+            "run_shell",
+            &serde_json::json!({}),
+            "OPENAI_API_KEY=sk-abc123456789012345678901234567890",
+            true,
+            5,
+        );
+        let summary = entry.result_summary.unwrap();
+        assert!(summary.contains("[REDACTED_API_KEY]"), "{}", summary);
+        assert!(!summary.contains("sk-abc"), "{}", summary);
     }
 
     #[test]
@@ -0,0 +1,514 @@
+//! Shareable session reports, for attaching to bug reports.
+//!
+//! A report gathers what the app knows about one run: the session record, its
+//! config (never the API key), the audit log, the files it changed, token usage,
+//! and the conversation when a transcript was saved. Free text goes through
+//! [`redact`] first, and long messages are cut short with a note
+//! saying how much was left out. Reports render as JSON or Markdown.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::atomic_write::write_atomic;
+use super::injection_guard::fence_for;
+use super::redact::redact;
+use super::session::{AuditEntry, Session, SessionStatus, SessionUsage};
+use super::text::truncate_to_char_boundary;
+use super::transcripts::Transcript;
+use super::types::{ApprovalMode, FileChange, LlmProvider, Message, ServedModel};
+
+/// Longest message, tool argument, or error text kept in a report
+pub const MAX_REPORT_TEXT_BYTES: usize = 4_000;
+
+/// Output format for a session report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Json,
+    Markdown,
+}
+
+impl ReportFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "json" => Ok(ReportFormat::Json),
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            other => Err(format!(
+                "Unknown report format '{}'. Use \"json\" or \"markdown\".",
+                other
+            )),
+        }
+    }
+}
+
+// ============================================================================
+// Report Types
+// ============================================================================
+
+/// Everything a report says about one session
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionReport {
+    pub generated_at: DateTime<Utc>,
+    pub app_version: String,
+    pub session_id: String,
+    pub task: String,
+    pub workspace: PathBuf,
+    pub created_at: DateTime<Utc>,
+    /// The session this one resumed, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resumed_from: Option<String>,
+    /// `None` when the app restarted after the run and only its transcript is left
+    pub status: Option<SessionStatus>,
+    pub error: Option<String>,
+    pub served_model: Option<ServedModel>,
+    pub config: ReportConfig,
+    pub usage: Option<SessionUsage>,
+    pub file_changes: Vec<FileChange>,
+    /// Audit entries, oldest first
+    pub audit: Vec<AuditEntry>,
+    /// The saved conversation, without the system prompt
+    pub conversation: Vec<Message>,
+    /// Whether a transcript was found for the session
+    pub has_transcript: bool,
+}
+
+/// The run's config as far as a report shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct ReportConfig {
+    pub provider: LlmProvider,
+    pub model: String,
+    pub approval_mode: ApprovalMode,
+    /// The remaining settings from the transcript, without the API key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<serde_json::Value>,
+}
+
+// ============================================================================
+// Building and Rendering
+// ============================================================================
+
+/// Assemble a report from whatever is still known about a session. `audit` is
+/// newest first, as the session store returns it.
+pub fn build_report(
+    session_id: &str,
+    session: Option<Session>,
+    audit: Vec<AuditEntry>,
+    transcript: Option<Transcript>,
+) -> Result<SessionReport, String> {
+    let (header, messages) = match transcript {
+        Some(t) => (Some(t.header), t.messages),
+        None => (None, Vec::new()),
+    };
+    // `api_key` is never serialized
+    let settings = header
+        .as_ref()
+        .and_then(|h| serde_json::to_value(&h.config).ok());
+
+    let mut report = match (&session, &header) {
+        (Some(s), _) => SessionReport {
+            generated_at: Utc::now(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            session_id: session_id.to_string(),
+            task: s.task.clone(),
+            workspace: s.workspace.clone(),
+            created_at: s.created_at,
+            resumed_from: None,
+            status: Some(s.status),
+            error: s.error.as_deref().map(clip),
+            served_model: s.served_model.clone(),
+            config: ReportConfig {
+                provider: s.provider,
+                model: s.model.clone(),
+                approval_mode: s.approval_mode,
+                settings,
+            },
+            usage: Some(s.usage()),
+            file_changes: s.file_changes.clone(),
+            audit: Vec::new(),
+            conversation: Vec::new(),
+            has_transcript: false,
+        },
+        (None, Some(h)) => SessionReport {
+            generated_at: Utc::now(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            session_id: session_id.to_string(),
+            task: h.task.clone(),
+            workspace: h.workspace.clone(),
+            created_at: h.created_at,
+            resumed_from: None,
+            status: None,
+            error: None,
+            served_model: None,
+            config: ReportConfig {
+                provider: h.config.provider,
+                model: h.config.model.clone(),
+                approval_mode: h.config.approval_mode,
+                settings,
+            },
+            usage: None,
+            file_changes: Vec::new(),
+            audit: Vec::new(),
+            conversation: Vec::new(),
+            has_transcript: false,
+        },
+        (None, None) => return Err(format!("Session not found: {}", session_id)),
+    };
+
+    report.task = clip(&report.task);
+    report.resumed_from = header.as_ref().and_then(|h| h.resumed_from.clone());
+    report.has_transcript = header.is_some();
+    report.audit = audit.into_iter().rev().collect();
+    report.conversation = messages.into_iter().map(clip_message).collect();
+    Ok(report)
+}
+
+/// Render a report as pretty JSON or Markdown
+pub fn render(report: &SessionReport, format: ReportFormat) -> Result<String, String> {
+    match format {
+        ReportFormat::Json => serde_json::to_string_pretty(report)
+            .map_err(|e| format!("Failed to serialize report: {}", e)),
+        ReportFormat::Markdown => Ok(to_markdown(report)),
+    }
+}
+
+/// Write a rendered report to `path`, which must be absolute and in an existing
+/// folder. An existing file is only replaced when `overwrite` is set.
+pub fn write_report(path: &Path, content: &str, overwrite: bool) -> Result<(), String> {
+    if !path.is_absolute() {
+        return Err(format!("Report path must be absolute: {}", path.display()));
+    }
+    if path.is_dir() {
+        return Err(format!("Report path is a directory: {}", path.display()));
+    }
+    match path.parent() {
+        Some(parent) if parent.is_dir() => {}
+        _ => {
+            return Err(format!(
+                "Folder for the report does not exist: {}",
+                path.display()
+            ))
+        }
+    }
+
+    if overwrite {
+        return write_atomic(path, content.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e));
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => format!(
+                "{} already exists; set overwrite to replace it",
+                path.display()
+            ),
+            _ => format!("Failed to create {}: {}", path.display(), e),
+        })?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Redact `text`, then cut it to [`MAX_REPORT_TEXT_BYTES`] with a note
+fn clip(text: &str) -> String {
+    let text = redact(text);
+    if text.len() <= MAX_REPORT_TEXT_BYTES {
+        return text;
+    }
+    let kept = truncate_to_char_boundary(&text, MAX_REPORT_TEXT_BYTES);
+    format!(
+        "{}\n[... {} more bytes truncated]",
+        kept,
+        text.len() - kept.len()
+    )
+}
+
+fn clip_message(mut message: Message) -> Message {
+    message.content = message.content.as_deref().map(clip);
+    for call in message.tool_calls.iter_mut().flatten() {
+        call.function.arguments = clip(&call.function.arguments);
+    }
+    message
+}
+
+/// The serde name of an enum value, as the UI shows it
+fn label<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => "unknown".to_string(),
+    }
+}
+
+/// A Markdown table cell: one line, with pipes escaped
+fn cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
+
+fn fenced(out: &mut String, info: &str, text: &str) {
+    let fence = fence_for(text);
+    let _ = writeln!(out, "{}{}", fence, info);
+    out.push_str(text);
+    if !text.ends_with('\n') {
+        out.push('\n');
+    }
+    let _ = writeln!(out, "{}\n", fence);
+}
+
+fn to_markdown(report: &SessionReport) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Session report: {}\n", report.session_id);
+    let _ = writeln!(
+        out,
+        "Generated {} by VS Write {}.\n",
+        report.generated_at.to_rfc3339(),
+        report.app_version
+    );
+
+    out.push_str("## Session\n\n");
+    let status = report
+        .status
+        .map(|s| label(&s))
+        .unwrap_or_else(|| "unknown (the app restarted after this run)".to_string());
+    let _ = writeln!(out, "- **Status:** {}", status);
+    let _ = writeln!(out, "- **Started:** {}", report.created_at.to_rfc3339());
+    let _ = writeln!(out, "- **Workspace:** `{}`", report.workspace.display());
+    if let Some(ref from) = report.resumed_from {
+        let _ = writeln!(out, "- **Resumed from:** {}", from);
+    }
+    if let Some(ref served) = report.served_model {
+        let _ = match served.provider {
+            Some(ref provider) => writeln!(out, "- **Served by:** {} ({})", served.model, provider),
+            None => writeln!(out, "- **Served by:** {}", served.model),
+        };
+    }
+    out.push('\n');
+    if let Some(ref error) = report.error {
+        out.push_str("**Error:**\n\n");
+        fenced(&mut out, "text", error);
+    }
+
+    out.push_str("## Task\n\n");
+    fenced(&mut out, "text", &report.task);
+
+    out.push_str("## Configuration\n\n");
+    let _ = writeln!(out, "- **Provider:** {}", label(&report.config.provider));
+    let _ = writeln!(out, "- **Model:** {}", report.config.model);
+    let _ = writeln!(
+        out,
+        "- **Approval mode:** {}\n",
+        label(&report.config.approval_mode)
+    );
+    if let Some(ref settings) = report.config.settings {
+        let json = serde_json::to_string_pretty(settings).unwrap_or_default();
+        fenced(&mut out, "json", &json);
+    }
+
+    out.push_str("## Token usage\n\n");
+    match report.usage {
+        Some(ref usage) => {
+            let _ = writeln!(out, "- **Prompt tokens:** {}", usage.prompt_tokens);
+            let _ = writeln!(out, "- **Completion tokens:** {}", usage.completion_tokens);
+            let _ = writeln!(out, "- **Total tokens:** {}", usage.total_tokens);
+            let _ = match usage.estimated_cost {
+                Some(cost) => writeln!(out, "- **Estimated cost:** ${:.4}\n", cost),
+                None => writeln!(out, "- **Estimated cost:** unknown\n"),
+            };
+        }
+        None => out.push_str("Not recorded.\n\n"),
+    }
+
+    out.push_str("## Changed files\n\n");
+    if report.file_changes.is_empty() {
+        out.push_str("None recorded.\n\n");
+    } else {
+        out.push_str("| File | Change | Bytes |\n|---|---|---|\n");
+        for change in &report.file_changes {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {:+} |",
+                cell(&change.path),
+                label(&change.operation),
+                change.byte_delta
+            );
+        }
+        out.push('\n');
+    }
+
+    out.push_str("## Audit log\n\n");
+    if report.audit.is_empty() {
+        out.push_str("No entries.\n\n");
+    } else {
+        out.push_str("| Time | Event | Tool | OK | ms | Details |\n|---|---|---|---|---|---|\n");
+        for entry in &report.audit {
+            let details = match entry.approval {
+                Some(ref approval) => format!(
+                    "{} by {} ({}, {} risk)",
+                    label(&approval.decision),
+                    label(&approval.decided_by),
+                    label(&approval.scope),
+                    label(&approval.risk)
+                ),
+                None => entry.result_summary.clone().unwrap_or_default(),
+            };
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} |",
+                entry.timestamp.format("%H:%M:%S"),
+                label(&entry.event_type),
+                entry.tool_name.as_deref().unwrap_or(""),
+                if entry.success { "yes" } else { "no" },
+                entry.duration_ms,
+                cell(&details)
+            );
+        }
+        out.push('\n');
+    }
+
+    out.push_str("## Conversation\n\n");
+    if !report.has_transcript {
+        out.push_str("No transcript was saved for this session.\n");
+    }
+    for (index, message) in report.conversation.iter().enumerate() {
+        match message.tool_call_id {
+            Some(ref id) => {
+                let _ = writeln!(out, "### {}. Tool result ({})\n", index + 1, id);
+            }
+            None => {
+                let _ = writeln!(out, "### {}. {}\n", index + 1, label(&message.role));
+            }
+        }
+        if let Some(ref content) = message.content {
+            fenced(&mut out, "text", content);
+        }
+        for call in message.tool_calls.iter().flatten() {
+            let _ = writeln!(out, "Called `{}` ({}):\n", call.function.name, call.id);
+            fenced(&mut out, "json", &call.function.arguments);
+        }
+    }
+    out
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::transcripts::TranscriptHeader;
+    use crate::agent::types::{AgentConfig, FileOperation, FunctionCall, ToolCall};
+    use tempfile::TempDir;
+
+    const KEY: &str = "sk-abcdefghijklmnopqrstuvwxyz123456";
+
+    fn session() -> Session {
+        let mut session = Session::new(
+            "session-1".to_string(),
+            PathBuf::from("/novel"),
+            LlmProvider::OpenRouter,
+            "openai/gpt-4o".to_string(),
+            ApprovalMode::ApproveWrites,
+            "Tighten chapter 2".to_string(),
+        );
+        session.record_file_changes(vec![FileChange {
+            path: "chapters/02.md".to_string(),
+            operation: FileOperation::Modified,
+            byte_delta: -1200,
+        }]);
+        session.fail(format!("LLM error: bad key {}", KEY));
+        session
+    }
+
+    fn transcript() -> Transcript {
+        let call = ToolCall {
+            id: "call-0".to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: "write_file".to_string(),
+                arguments: r#"{"path":"chapters/02.md","content":"```"}"#.to_string(),
+            },
+        };
+        Transcript {
+            header: TranscriptHeader {
+                session_id: "session-1".to_string(),
+                created_at: Utc::now(),
+                workspace: PathBuf::from("/novel"),
+                task: "Tighten chapter 2".to_string(),
+                config: AgentConfig::new(""),
+                resumed_from: None,
+            },
+            messages: vec![
+                Message::user("Tighten chapter 2"),
+                Message::assistant_with_tools(None, vec![call]),
+                Message::tool_result("call-0", &"x".repeat(MAX_REPORT_TEXT_BYTES + 500)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_markdown_report_redacts_and_truncates() {
+        let audit = vec![AuditEntry::tool_call(
+            "session-1",
+            "write_file",
+            &serde_json::json!({}),
+            "Wrote | 3 lines",
+            true,
+            12,
+        )];
+        let report = build_report("session-1", Some(session()), audit, Some(transcript())).unwrap();
+        let markdown = render(&report, ReportFormat::Markdown).unwrap();
+
+        assert!(!markdown.contains(KEY), "{}", markdown);
+        assert!(markdown.contains("[REDACTED_API_KEY]"));
+        assert!(markdown.contains("- **Status:** failed"));
+        assert!(markdown.contains("| chapters/02.md | modified | -1200 |"));
+        assert!(markdown.contains("| write_file | yes | 12 | Wrote \\| 3 lines |"));
+        assert!(markdown.contains("[... 500 more bytes truncated]"));
+        // Backticks in tool arguments don't close the fence around them
+        assert!(markdown.contains("````json\n{\"path\""));
+        assert!(!markdown.contains("api_key"));
+    }
+
+    #[test]
+    fn test_json_report_from_transcript_only() {
+        let report = build_report("session-1", None, Vec::new(), Some(transcript())).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&report, ReportFormat::Json).unwrap()).unwrap();
+
+        assert_eq!(json["status"], serde_json::Value::Null);
+        assert_eq!(json["config"]["model"], AgentConfig::default().model);
+        assert!(json["config"]["settings"]["max_iterations"].is_number());
+        assert!(json["config"]["settings"].get("api_key").is_none());
+        assert_eq!(json["conversation"].as_array().unwrap().len(), 3);
+
+        let err = build_report("missing", None, Vec::new(), None).unwrap_err();
+        assert!(err.contains("Session not found"));
+        assert!(ReportFormat::parse("pdf").is_err());
+    }
+
+    #[test]
+    fn test_write_report_needs_overwrite_to_replace() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("report.md");
+
+        write_report(&path, "first", false).unwrap();
+        let err = write_report(&path, "second", false).unwrap_err();
+        assert!(err.contains("already exists"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first");
+
+        write_report(&path, "second", true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+
+        assert!(write_report(Path::new("report.md"), "x", false).is_err());
+        assert!(write_report(&dir.path().join("missing/report.md"), "x", false).is_err());
+        assert!(write_report(dir.path(), "x", false).is_err());
+    }
+}
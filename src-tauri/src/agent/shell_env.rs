@@ -0,0 +1,308 @@
+//! The environment `run_shell` commands start with.
+//!
+//! Commands don't inherit the app's environment, which on a dev machine holds API
+//! keys and tokens any command could read. They get PATH (see
+//! [`augmented_path`](super::tools::augmented_path)), HOME, LANG and TMPDIR (plus
+//! what `cmd` needs on Windows), and the variables `shell_env_allowlist` names.
+//! An entry is a variable name or a prefix ending in `*` (`CARGO_*`).
+//!
+//! Names that look like credentials (`*_KEY`, `*_TOKEN`, `*_SECRET`, `*_PASSWORD`,
+//! ...) are held back even when allow-listed, unless the entry starts with `!`
+//! (`!NPM_TOKEN`).
+
+/// Variables every command gets when the app has them, besides PATH
+const BASE_ENV: &[&str] = &["HOME", "LANG", "TMPDIR"];
+
+/// Variables `cmd` and most Windows tools need to work at all
+const WINDOWS_BASE_ENV: &[&str] = &[
+    "SYSTEMROOT",
+    "SYSTEMDRIVE",
+    "COMSPEC",
+    "PATHEXT",
+    "TEMP",
+    "TMP",
+    "USERPROFILE",
+    "WINDIR",
+];
+
+/// Name endings of variables that usually hold credentials
+const SECRET_SUFFIXES: &[&str] = &[
+    "_KEY",
+    "_TOKEN",
+    "_SECRET",
+    "_PASSWORD",
+    "_PASSWD",
+    "_CREDENTIALS",
+];
+
+/// Prefix of an allow-list entry that passes a variable even if it looks secret
+const FORCE_PREFIX: char = '!';
+
+/// Maximum number of allow-list entries
+const MAX_ENTRIES: usize = 100;
+
+/// Maximum length of a single entry
+const MAX_ENTRY_LEN: usize = 200;
+
+/// Check entry counts, lengths and characters
+pub fn validate_allowlist(allowlist: &[String]) -> Result<(), String> {
+    if allowlist.len() > MAX_ENTRIES {
+        return Err(format!(
+            "shell_env_allowlist cannot have more than {} entries",
+            MAX_ENTRIES
+        ));
+    }
+    for entry in allowlist {
+        if entry.len() > MAX_ENTRY_LEN {
+            return Err(format!(
+                "shell_env_allowlist entry too long (max {} characters)",
+                MAX_ENTRY_LEN
+            ));
+        }
+        let name = entry.strip_prefix(FORCE_PREFIX).unwrap_or(entry);
+        let name = name.strip_suffix('*').unwrap_or(name);
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!(
+                "shell_env_allowlist entry '{}' must be a variable name, optionally ending in '*' or starting with '!'",
+                entry
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` looks like it holds a credential
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// The variables a command is started with, and what was left out
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShellEnv {
+    /// Variables passed to the command, PATH aside
+    pub vars: Vec<(String, String)>,
+    /// How many of the app's variables weren't passed
+    pub filtered: usize,
+    /// Allow-listed names held back because they look like credentials
+    pub scrubbed: Vec<String>,
+}
+
+impl ShellEnv {
+    /// Filter the app's own environment
+    pub fn inherited(allowlist: &[String]) -> Self {
+        let mut unreadable = 0;
+        let vars: Vec<(String, String)> = std::env::vars_os()
+            .filter_map(
+                |(name, value)| match (name.into_string(), value.into_string()) {
+                    (Ok(name), Ok(value)) => Some((name, value)),
+                    _ => {
+                        unreadable += 1;
+                        None
+                    }
+                },
+            )
+            .collect();
+        let mut env = Self::from_vars(vars, allowlist);
+        env.filtered += unreadable;
+        env
+    }
+
+    /// Filter `vars`. PATH is left out without being counted; run_shell builds its own.
+    pub fn from_vars(
+        vars: impl IntoIterator<Item = (String, String)>,
+        allowlist: &[String],
+    ) -> Self {
+        let mut env = ShellEnv::default();
+        for (name, value) in vars {
+            if same_name(&name, "PATH") {
+                continue;
+            }
+            let allowed = allowlist_match(allowlist, &name);
+            // Likely secrets stay out before anything else is considered,
+            // unless an entry forces them through
+            if is_secret_name(&name) && allowed != Some(true) {
+                env.filtered += 1;
+                if allowed.is_some() {
+                    env.scrubbed.push(name);
+                }
+                continue;
+            }
+            if allowed.is_none() && !is_base_var(&name) {
+                env.filtered += 1;
+                continue;
+            }
+            env.vars.push((name, value));
+        }
+        env.vars.sort();
+        env.scrubbed.sort();
+        env
+    }
+
+    /// Names of the variables passed, PATH included
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.vars.iter().map(|(name, _)| name.clone()).collect();
+        names.push("PATH".to_string());
+        names.sort();
+        names
+    }
+
+    /// A note for the tool result saying what was left out, `None` if nothing was
+    pub fn note(&self) -> Option<String> {
+        if self.filtered == 0 {
+            return None;
+        }
+        let mut note = format!(
+            "{} environment variable(s) were not passed to the command; add names to shell_env_allowlist to pass them.",
+            self.filtered
+        );
+        if !self.scrubbed.is_empty() {
+            note.push_str(&format!(
+                " Held back as likely secrets though allow-listed: {} (prefix the entry with '{}' to pass it anyway).",
+                self.scrubbed.join(", "),
+                FORCE_PREFIX
+            ));
+        }
+        Some(note)
+    }
+}
+
+fn is_base_var(name: &str) -> bool {
+    BASE_ENV.iter().any(|base| same_name(name, base))
+        || (cfg!(target_os = "windows")
+            && WINDOWS_BASE_ENV.iter().any(|base| same_name(name, base)))
+}
+
+/// Whether the allow list covers `name`: `Some(forced)` if it does
+fn allowlist_match(allowlist: &[String], name: &str) -> Option<bool> {
+    let mut matched = None;
+    for entry in allowlist {
+        let (forced, pattern) = match entry.strip_prefix(FORCE_PREFIX) {
+            Some(pattern) => (true, pattern),
+            None => (false, entry.as_str()),
+        };
+        let matches = match pattern.strip_suffix('*') {
+            Some(prefix) => name
+                .get(..prefix.len())
+                .is_some_and(|head| same_name(head, prefix)),
+            None => same_name(name, pattern),
+        };
+        if matches {
+            matched = Some(forced || matched == Some(true));
+        }
+    }
+    matched
+}
+
+/// Variable names compare case-insensitively on Windows only
+fn same_name(a: &str, b: &str) -> bool {
+    if cfg!(target_os = "windows") {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(names: &[&str]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), format!("{}-value", name)))
+            .collect()
+    }
+
+    fn list(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_filters_to_base_and_allowlisted_vars() {
+        let app = vars(&[
+            "PATH",
+            "HOME",
+            "LANG",
+            "OPENAI_API_KEY",
+            "GITHUB_TOKEN",
+            "AWS_SECRET",
+            "CARGO_HOME",
+            "CARGO_TARGET_DIR",
+            "CARGO_REGISTRY_TOKEN",
+            "EDITOR",
+            "NPM_TOKEN",
+        ]);
+        let env = ShellEnv::from_vars(app.clone(), &[]);
+        assert_eq!(env.names(), vec!["HOME", "LANG", "PATH"]);
+        assert_eq!(env.filtered, 8);
+        assert!(env.scrubbed.is_empty());
+        assert!(env.note().unwrap().starts_with("8 environment variable(s)"));
+
+        let allowlist = list(&["CARGO_*", "EDITOR", "GITHUB_TOKEN", "!NPM_TOKEN"]);
+        let env = ShellEnv::from_vars(app, &allowlist);
+        assert_eq!(
+            env.names(),
+            vec![
+                "CARGO_HOME",
+                "CARGO_TARGET_DIR",
+                "EDITOR",
+                "HOME",
+                "LANG",
+                "NPM_TOKEN",
+                "PATH"
+            ]
+        );
+        assert_eq!(env.scrubbed, vec!["CARGO_REGISTRY_TOKEN", "GITHUB_TOKEN"]);
+        assert_eq!(env.filtered, 4);
+        let note = env.note().unwrap();
+        assert!(
+            note.contains("CARGO_REGISTRY_TOKEN, GITHUB_TOKEN"),
+            "{}",
+            note
+        );
+
+        assert_eq!(
+            ShellEnv::from_vars(vars(&["PATH", "HOME"]), &[]).note(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scrubs_keys_and_passwords() {
+        let app = vars(&[
+            "AWS_ACCESS_KEY_ID",
+            "AWS_REGION",
+            "AWS_SECRET_ACCESS_KEY",
+            "DB_PASSWORD",
+            "FTP_PASSWD",
+            "GOOGLE_APPLICATION_CREDENTIALS",
+        ]);
+        let allowlist = list(&["AWS_*", "DB_*", "FTP_PASSWD", "GOOGLE_*"]);
+        let env = ShellEnv::from_vars(app, &allowlist);
+        assert_eq!(env.names(), vec!["AWS_ACCESS_KEY_ID", "AWS_REGION", "PATH"]);
+        assert_eq!(
+            env.scrubbed,
+            vec![
+                "AWS_SECRET_ACCESS_KEY",
+                "DB_PASSWORD",
+                "FTP_PASSWD",
+                "GOOGLE_APPLICATION_CREDENTIALS"
+            ]
+        );
+        assert!(is_secret_name("openai_api_key"));
+    }
+
+    #[test]
+    fn test_validate_allowlist() {
+        assert!(validate_allowlist(&list(&["EDITOR", "CARGO_*", "!NPM_TOKEN"])).is_ok());
+        assert!(validate_allowlist(&list(&[""])).is_err());
+        assert!(validate_allowlist(&list(&["*"])).is_err());
+        assert!(validate_allowlist(&list(&["FOO=bar"])).is_err());
+        assert!(validate_allowlist(&list(&["X"; 101])).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! The shell run_shell hands commands to.
+//!
+//! `shell` in the run config names it: `sh`, `bash`, `zsh`, `dash`, `ksh`, `fish`,
+//! `pwsh`, `powershell` or `cmd`, or a path to one of them. Unset, commands run
+//! under `sh` on Unix and PowerShell on Windows (`pwsh` when it is on PATH,
+//! Windows PowerShell otherwise).
+//!
+//! Each shell gets the command in a form it won't mangle. PowerShell takes it as
+//! `-EncodedCommand` (base64 UTF-16), so quotes, `&&` and non-ASCII paths arrive
+//! intact; `cmd` takes it verbatim after `/S /C`, without the backslash escaping
+//! Rust applies to ordinary arguments, which `cmd` doesn't understand.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use std::path::Path;
+use std::process::Command;
+
+/// Shells that run `-c <command>`
+const POSIX_SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "fish"];
+
+/// Makes PowerShell write UTF-8, so non-ASCII output survives the pipe
+const POWERSHELL_PRELUDE: &str =
+    "$OutputEncoding = [Console]::OutputEncoding = [System.Text.UTF8Encoding]::new($false)\n";
+
+/// Maximum length of a configured shell
+const MAX_SHELL_LEN: usize = 500;
+
+/// How a shell expects to be given a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellKind {
+    /// `sh` and friends: `-c <command>`
+    Posix,
+    /// `pwsh` or Windows PowerShell: `-EncodedCommand <base64>`
+    PowerShell,
+    /// `cmd /S /C "<command>"`
+    Cmd,
+}
+
+/// A shell binary and how to pass it a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellProgram {
+    /// Name or path the binary is started by
+    pub program: String,
+    pub kind: ShellKind,
+}
+
+impl ShellProgram {
+    /// The shell `configured` names, or the platform default if it is `None`.
+    /// `path` is the PATH commands run with, searched for `pwsh`.
+    pub fn resolve(configured: Option<&str>, path: &[String]) -> Result<Self, String> {
+        let Some(program) = configured else {
+            return Ok(Self::platform_default(path));
+        };
+        if program.trim().is_empty() || program.len() > MAX_SHELL_LEN {
+            return Err(format!(
+                "shell must be a shell name or path of at most {} characters",
+                MAX_SHELL_LEN
+            ));
+        }
+        let name = Path::new(program)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let name = name.strip_suffix(".exe").unwrap_or(&name);
+        let kind = match name {
+            "pwsh" | "powershell" => ShellKind::PowerShell,
+            "cmd" => ShellKind::Cmd,
+            name if POSIX_SHELLS.contains(&name) => ShellKind::Posix,
+            _ => {
+                return Err(format!(
+                    "Unsupported shell '{}'; use one of {}, pwsh, powershell or cmd",
+                    program,
+                    POSIX_SHELLS.join(", ")
+                ))
+            }
+        };
+        Ok(ShellProgram {
+            program: program.to_string(),
+            kind,
+        })
+    }
+
+    fn platform_default(path: &[String]) -> Self {
+        if !cfg!(target_os = "windows") {
+            return ShellProgram {
+                program: "sh".to_string(),
+                kind: ShellKind::Posix,
+            };
+        }
+        let has_pwsh = path
+            .iter()
+            .any(|dir| Path::new(dir).join("pwsh.exe").is_file());
+        ShellProgram {
+            program: if has_pwsh { "pwsh" } else { "powershell" }.to_string(),
+            kind: ShellKind::PowerShell,
+        }
+    }
+
+    /// The flags that come before the command, as shown to the user
+    pub fn flags(&self) -> &'static [&'static str] {
+        match self.kind {
+            ShellKind::Posix => &["-c"],
+            ShellKind::PowerShell => &["-NoProfile", "-NonInteractive", "-EncodedCommand"],
+            ShellKind::Cmd => &["/S", "/C"],
+        }
+    }
+
+    /// A `Command` that runs `command` in this shell
+    pub fn command(&self, command: &str) -> Command {
+        let mut cmd = Command::new(&self.program);
+        match self.kind {
+            ShellKind::Posix => {
+                cmd.args(self.flags()).arg(command);
+            }
+            ShellKind::PowerShell => {
+                cmd.args(self.flags()).arg(encode_powershell(command));
+            }
+            ShellKind::Cmd => {
+                #[cfg(windows)]
+                {
+                    use std::os::windows::process::CommandExt;
+                    cmd.raw_arg(format!("/S /C \"{}\"", command));
+                }
+                #[cfg(not(windows))]
+                cmd.args(self.flags()).arg(command);
+            }
+        }
+        cmd
+    }
+}
+
+/// Check a configured shell without resolving the default
+pub fn validate_shell(shell: &str) -> Result<(), String> {
+    ShellProgram::resolve(Some(shell), &[]).map(|_| ())
+}
+
+/// `command` in the form `-EncodedCommand` takes: UTF-16LE, base64
+fn encode_powershell(command: &str) -> String {
+    let script = format!("{}{}", POWERSHELL_PRELUDE, command);
+    let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    BASE64.encode(utf16)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_configured_shells() {
+        let kind = |shell: &str| ShellProgram::resolve(Some(shell), &[]).map(|s| s.kind);
+        assert_eq!(kind("bash"), Ok(ShellKind::Posix));
+        assert_eq!(kind("/usr/local/bin/fish"), Ok(ShellKind::Posix));
+        assert_eq!(kind("pwsh"), Ok(ShellKind::PowerShell));
+        assert_eq!(kind("PowerShell.exe"), Ok(ShellKind::PowerShell));
+        assert_eq!(kind("cmd.exe"), Ok(ShellKind::Cmd));
+        assert!(kind("python").is_err());
+        assert!(kind("").is_err());
+        assert!(validate_shell("nu").is_err());
+
+        let default = ShellProgram::resolve(None, &[]).unwrap();
+        if cfg!(target_os = "windows") {
+            assert_eq!(default.program, "powershell");
+        } else {
+            assert_eq!(default.program, "sh");
+            assert_eq!(default.flags(), ["-c"]);
+        }
+    }
+
+    #[test]
+    fn test_powershell_command_is_encoded() {
+        let encoded = encode_powershell("echo \"ünïcode\" && exit 3");
+        let bytes = BASE64.decode(encoded).unwrap();
+        let utf16: Vec<u16> = bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        let script = String::from_utf16(&utf16).unwrap();
+        assert!(script.starts_with(POWERSHELL_PRELUDE));
+        assert!(script.ends_with("echo \"ünïcode\" && exit 3"));
+    }
+}
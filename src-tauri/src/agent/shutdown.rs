@@ -0,0 +1,244 @@
+//! Graceful shutdown for in-flight agent runs.
+//!
+//! When the app exits (or a caller invokes `prepare_shutdown` ahead of time), every
+//! running agent is cancelled and given a short grace period to observe the cancellation.
+//! Shell processes that are still alive afterwards are killed, and sessions that never
+//! reached a terminal state are marked cancelled.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::session::SessionStore;
+use super::tools;
+
+/// How long to wait for agent loops to observe cancellation before giving up on them
+pub const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+/// How often to re-check the running-task map while waiting
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Error recorded on sessions that were interrupted by shutdown
+const SHUTDOWN_REASON: &str = "Cancelled because the application is shutting down";
+
+/// Outcome of a shutdown sequence
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    /// Runs that observed cancellation and exited within the grace period
+    pub stopped_runs: Vec<String>,
+    /// Runs that were still registered when the grace period expired
+    pub unresponsive_runs: Vec<String>,
+    /// Number of shell processes that had to be killed
+    pub killed_processes: usize,
+    /// Number of sessions marked cancelled
+    pub cancelled_sessions: usize,
+}
+
+/// Cancel every registered run and wait up to `grace` for them to deregister.
+///
+/// Runs remove themselves from `running_tasks` when their loop exits (see
+/// `RunningTaskGuard`), so an entry that is still present after the grace period
+/// belongs to a run that did not respond to cancellation.
+///
+/// Returns `(stopped, unresponsive)` run IDs.
+pub async fn cancel_running_tasks(
+    running_tasks: &RwLock<HashMap<String, CancellationToken>>,
+    grace: Duration,
+) -> (Vec<String>, Vec<String>) {
+    let run_ids: Vec<String> = match running_tasks.read() {
+        Ok(tasks) => {
+            for token in tasks.values() {
+                token.cancel();
+            }
+            tasks.keys().cloned().collect()
+        }
+        Err(e) => {
+            log::error!("Failed to read running tasks during shutdown: {}", e);
+            return (Vec::new(), Vec::new());
+        }
+    };
+
+    if run_ids.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+
+    log::info!(
+        "Cancelling {} running agent task(s) for shutdown",
+        run_ids.len()
+    );
+
+    let deadline = Instant::now() + grace;
+    loop {
+        let remaining = running_tasks.read().map(|t| t.len()).unwrap_or(0);
+        if remaining == 0 || Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+
+    let still_running: Vec<String> = running_tasks
+        .read()
+        .map(|t| t.keys().cloned().collect())
+        .unwrap_or_default();
+
+    let (unresponsive, stopped): (Vec<String>, Vec<String>) = run_ids
+        .into_iter()
+        .partition(|id| still_running.contains(id));
+
+    if !unresponsive.is_empty() {
+        log::warn!(
+            "{} agent task(s) did not stop within {}ms: {}",
+            unresponsive.len(),
+            grace.as_millis(),
+            unresponsive.join(", ")
+        );
+    }
+
+    (stopped, unresponsive)
+}
+
+/// Run the full shutdown sequence: cancel runs, kill surviving shells, close sessions.
+pub async fn shutdown(
+    running_tasks: &RwLock<HashMap<String, CancellationToken>>,
+    sessions: &SessionStore,
+    grace: Duration,
+) -> ShutdownReport {
+    let (stopped_runs, unresponsive_runs) = cancel_running_tasks(running_tasks, grace).await;
+    let killed_processes = tools::kill_running_shells();
+    let cancelled_sessions = sessions.cancel_unfinished_sessions(SHUTDOWN_REASON);
+
+    log::info!(
+        "Shutdown complete: {} stopped, {} unresponsive, {} shell(s) killed, {} session(s) cancelled",
+        stopped_runs.len(),
+        unresponsive_runs.len(),
+        killed_processes,
+        cancelled_sessions
+    );
+
+    ShutdownReport {
+        stopped_runs,
+        unresponsive_runs,
+        killed_processes,
+        cancelled_sessions,
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use crate::agent::session::SessionStatus;
+    use crate::agent::types::{ApprovalMode, LlmProvider};
+
+    type Tasks = Arc<RwLock<HashMap<String, CancellationToken>>>;
+
+    /// Register a fake run that deregisters itself once it observes cancellation.
+    fn spawn_responsive_run(tasks: &Tasks, run_id: &str) {
+        let token = CancellationToken::new();
+        tasks
+            .write()
+            .unwrap()
+            .insert(run_id.to_string(), token.clone());
+
+        let tasks = tasks.clone();
+        let run_id = run_id.to_string();
+        tokio::spawn(async move {
+            token.cancelled().await;
+            tasks.write().unwrap().remove(&run_id);
+        });
+    }
+
+    /// Register a fake run that ignores cancellation entirely.
+    fn register_stuck_run(tasks: &Tasks, run_id: &str) {
+        tasks
+            .write()
+            .unwrap()
+            .insert(run_id.to_string(), CancellationToken::new());
+    }
+
+    #[tokio::test]
+    async fn test_no_running_tasks() {
+        let tasks: Tasks = Arc::new(RwLock::new(HashMap::new()));
+        let (stopped, unresponsive) =
+            cancel_running_tasks(&tasks, Duration::from_millis(100)).await;
+        assert!(stopped.is_empty());
+        assert!(unresponsive.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_responsive_runs_stop() {
+        let tasks: Tasks = Arc::new(RwLock::new(HashMap::new()));
+        spawn_responsive_run(&tasks, "run-a");
+        spawn_responsive_run(&tasks, "run-b");
+
+        let started = Instant::now();
+        let (mut stopped, unresponsive) =
+            cancel_running_tasks(&tasks, Duration::from_secs(2)).await;
+        stopped.sort();
+
+        assert_eq!(stopped, vec!["run-a".to_string(), "run-b".to_string()]);
+        assert!(unresponsive.is_empty());
+        // Should not wait for the full grace period when everything stops promptly
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_runs_reported() {
+        let tasks: Tasks = Arc::new(RwLock::new(HashMap::new()));
+        spawn_responsive_run(&tasks, "run-ok");
+        register_stuck_run(&tasks, "run-stuck");
+
+        let (stopped, unresponsive) =
+            cancel_running_tasks(&tasks, Duration::from_millis(200)).await;
+
+        assert_eq!(stopped, vec!["run-ok".to_string()]);
+        assert_eq!(unresponsive, vec!["run-stuck".to_string()]);
+
+        // The stuck run's token was still cancelled
+        assert!(tasks.read().unwrap()["run-stuck"].is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_cancels_sessions() {
+        let tasks: Tasks = Arc::new(RwLock::new(HashMap::new()));
+        let sessions = SessionStore::new();
+
+        let active = sessions.create_session(
+            PathBuf::from("/tmp"),
+            LlmProvider::OpenAI,
+            "gpt-5-mini".to_string(),
+            ApprovalMode::AutoApprove,
+            "Active task".to_string(),
+        );
+        let done = sessions.create_session(
+            PathBuf::from("/tmp"),
+            LlmProvider::OpenAI,
+            "gpt-5-mini".to_string(),
+            ApprovalMode::AutoApprove,
+            "Finished task".to_string(),
+        );
+        sessions.update_session(&done, |s| s.complete());
+
+        spawn_responsive_run(&tasks, "run-a");
+        let report = shutdown(&tasks, &sessions, Duration::from_secs(1)).await;
+
+        assert_eq!(report.stopped_runs, vec!["run-a".to_string()]);
+        assert_eq!(report.cancelled_sessions, 1);
+        assert_eq!(
+            sessions.get_session(&active).unwrap().status,
+            SessionStatus::Cancelled
+        );
+        assert_eq!(
+            sessions.get_session(&done).unwrap().status,
+            SessionStatus::Completed
+        );
+    }
+}
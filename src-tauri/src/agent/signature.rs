@@ -0,0 +1,659 @@
+//! Extension manifest signatures.
+//!
+//! Verifies the Ed25519 signature a manifest carries against the trusted publisher
+//! keys in the [`TrustStore`], checks the extension's script files against the SHA-256 hashes in the
+//! manifest's `files` section, and decides whether an extension may load under the
+//! configured `SignaturePolicy`. Used both by the extension registry at load time and by the
+//! packaging commands in `extensions.rs`.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ed25519_dalek::{Signature, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Component, Path};
+
+use super::trust_store::{decode_public_key, TrustStore};
+
+/// Result of signature verification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureVerification {
+    /// Whether the extension is signed
+    pub is_signed: bool,
+    /// Whether the signature is valid (only meaningful if is_signed is true)
+    pub is_valid: bool,
+    /// The publisher/key ID that signed the extension
+    pub publisher_id: Option<String>,
+    /// Whether the publisher is trusted
+    pub is_trusted: bool,
+    /// Human-readable status message
+    pub status: String,
+    /// Error message if verification failed
+    pub error: Option<String>,
+    /// Script files checked against the manifest's `files` hashes
+    #[serde(default)]
+    pub files: Vec<FileVerification>,
+}
+
+/// Result of checking one script file against the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileVerification {
+    /// Path relative to the extension directory
+    pub path: String,
+    pub status: FileStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// Hash matches the manifest
+    Ok,
+    /// Listed in the manifest but not on disk
+    Missing,
+    /// Contents differ from the hash in the manifest
+    Mismatch,
+    /// Script the extension uses that the manifest doesn't list
+    Unlisted,
+}
+
+impl FileStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            FileStatus::Ok => "ok",
+            FileStatus::Missing => "missing",
+            FileStatus::Mismatch => "mismatch",
+            FileStatus::Unlisted => "unlisted",
+        }
+    }
+}
+
+/// Get the canonical manifest content for signing
+/// This removes signature-related fields and produces deterministic JSON. The
+/// `files` hashes stay in, so the signature covers the script files too.
+fn get_signable_content(manifest: &serde_json::Value) -> String {
+    let mut manifest_copy = manifest.clone();
+
+    // Remove signature fields before hashing
+    if let Some(obj) = manifest_copy.as_object_mut() {
+        obj.remove("signature");
+        obj.remove("signatureAlgorithm");
+        obj.remove("publicKeyId");
+    }
+
+    // Produce deterministic JSON (sorted keys, no extra whitespace)
+    serde_json::to_string(&manifest_copy).unwrap_or_default()
+}
+
+/// Verify an extension's signature
+fn verify_signature(
+    manifest: &serde_json::Value,
+    signature_b64: &str,
+    public_key_id: &str,
+    trusted: &TrustStore,
+) -> Result<SignatureVerification, String> {
+    // Find the public key for this publisher
+    let public_key_b64 = trusted.key_for(public_key_id);
+
+    let is_trusted = public_key_b64.is_some();
+
+    // If publisher not in trusted list, try to get key from manifest
+    // (for self-signed extensions)
+    let public_key_b64 = public_key_b64.or_else(|| {
+        manifest
+            .get("publicKey")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    });
+
+    let public_key_b64 = match public_key_b64 {
+        Some(key) => key,
+        None => {
+            return Ok(SignatureVerification {
+                is_signed: true,
+                is_valid: false,
+                publisher_id: Some(public_key_id.to_string()),
+                is_trusted: false,
+                status: "Unknown publisher - public key not found".to_string(),
+                error: Some("Public key not found for publisher".to_string()),
+                files: Vec::new(),
+            });
+        }
+    };
+
+    let verifying_key = decode_public_key(&public_key_b64)?;
+
+    // Decode the signature
+    let signature_bytes = BASE64
+        .decode(signature_b64)
+        .map_err(|e| format!("Invalid signature encoding: {}", e))?;
+
+    let signature =
+        Signature::from_slice(&signature_bytes).map_err(|e| format!("Invalid signature: {}", e))?;
+
+    // Get the content that was signed
+    let signable_content = get_signable_content(manifest);
+
+    // Hash the content (we sign the SHA-256 hash)
+    let mut hasher = Sha256::new();
+    hasher.update(signable_content.as_bytes());
+    let hash = hasher.finalize();
+
+    // Verify the signature
+    match verifying_key.verify(&hash, &signature) {
+        Ok(_) => Ok(SignatureVerification {
+            is_signed: true,
+            is_valid: true,
+            publisher_id: Some(public_key_id.to_string()),
+            is_trusted,
+            status: if is_trusted {
+                format!("Verified - signed by trusted publisher '{}'", public_key_id)
+            } else {
+                format!(
+                    "Valid signature from untrusted publisher '{}'",
+                    public_key_id
+                )
+            },
+            error: None,
+            files: Vec::new(),
+        }),
+        Err(e) => Ok(SignatureVerification {
+            is_signed: true,
+            is_valid: false,
+            publisher_id: Some(public_key_id.to_string()),
+            is_trusted,
+            status: "Signature verification failed".to_string(),
+            error: Some(format!("Signature verification failed: {}", e)),
+            files: Vec::new(),
+        }),
+    }
+}
+
+/// Verify the signature of a parsed manifest
+pub fn verify_manifest(
+    manifest: &serde_json::Value,
+    trusted: &TrustStore,
+) -> Result<SignatureVerification, String> {
+    // Check if the extension is signed
+    let signature = manifest.get("signature").and_then(|v| v.as_str());
+    let public_key_id = manifest.get("publicKeyId").and_then(|v| v.as_str());
+
+    match (signature, public_key_id) {
+        (Some(sig), Some(key_id)) => verify_signature(manifest, sig, key_id, trusted),
+        (Some(_), None) => Ok(SignatureVerification {
+            is_signed: true,
+            is_valid: false,
+            publisher_id: None,
+            is_trusted: false,
+            status: "Signed but missing publicKeyId".to_string(),
+            error: Some("Extension has signature but no publicKeyId".to_string()),
+            files: Vec::new(),
+        }),
+        _ => Ok(SignatureVerification {
+            is_signed: false,
+            is_valid: false,
+            publisher_id: None,
+            is_trusted: false,
+            status: "Not signed".to_string(),
+            error: None,
+            files: Vec::new(),
+        }),
+    }
+}
+
+impl SignatureVerification {
+    /// Verification result for a signed manifest whose signature couldn't be checked
+    fn failed(publisher_id: Option<String>, error: String) -> Self {
+        SignatureVerification {
+            is_signed: true,
+            is_valid: false,
+            publisher_id,
+            is_trusted: false,
+            status: "Signature verification failed".to_string(),
+            error: Some(error),
+            files: Vec::new(),
+        }
+    }
+
+    /// Valid signature from a trusted publisher, with every script file intact
+    pub fn is_verified(&self) -> bool {
+        self.is_signed && self.is_valid && self.is_trusted && !self.is_tampered()
+    }
+
+    /// Some script file is missing, changed, or not covered by the manifest
+    pub fn is_tampered(&self) -> bool {
+        self.files.iter().any(|f| f.status != FileStatus::Ok)
+    }
+
+    /// Script files that failed the check, as `path (status)`
+    fn file_problems(&self) -> String {
+        self.files
+            .iter()
+            .filter(|f| f.status != FileStatus::Ok)
+            .map(|f| format!("{} ({})", f.path, f.status.as_str()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn publisher(&self) -> &str {
+        self.publisher_id.as_deref().unwrap_or("unknown")
+    }
+}
+
+/// Verify an extension directory: the manifest signature, then the script files.
+/// A malformed key or signature counts as a failed verification.
+pub fn verify_extension(
+    dir: &Path,
+    manifest: &serde_json::Value,
+    trusted: &TrustStore,
+) -> SignatureVerification {
+    let mut verification = verify_manifest_lenient(manifest, trusted);
+    verify_files(dir, manifest, &mut verification);
+    verification
+}
+
+/// Verify a manifest, treating a malformed key or signature as a failed verification
+fn verify_manifest_lenient(
+    manifest: &serde_json::Value,
+    trusted: &TrustStore,
+) -> SignatureVerification {
+    verify_manifest(manifest, trusted).unwrap_or_else(|e| {
+        let publisher = manifest
+            .get("publicKeyId")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        SignatureVerification::failed(publisher, e)
+    })
+}
+
+/// Manifest path as a relative path inside the extension, or `None` if it
+/// would leave the extension directory
+fn normalize_script_path(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// Scripts an extension runs: each tool's `luaScript` or `pythonModule`, plus
+/// `hooks.lua` and `hooks.py` if present
+fn script_files(dir: &Path, manifest: &serde_json::Value) -> Vec<String> {
+    let mut scripts: Vec<String> = manifest
+        .get("tools")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+        .flat_map(|tool| ["luaScript", "pythonModule"].map(|key| tool.get(key)))
+        .filter_map(|script| script.and_then(|s| s.as_str()))
+        .map(|script| normalize_script_path(script).unwrap_or_else(|| script.to_string()))
+        .collect();
+    for hooks in ["hooks.lua", "hooks.py"] {
+        if dir.join(hooks).is_file() {
+            scripts.push(hooks.to_string());
+        }
+    }
+    scripts.sort();
+    scripts.dedup();
+    scripts
+}
+
+/// SHA-256 of a file as lowercase hex
+pub fn hash_file(path: &Path) -> Result<String, String> {
+    let content =
+        fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", Sha256::digest(&content)))
+}
+
+/// Check the script files in `dir` against the manifest's `files` section
+/// (`{"tools.lua": "<sha256 hex>", ...}`). Missing, changed, or unlisted scripts
+/// mark the verification invalid. A signed manifest without a `files` section
+/// vouches for none of its scripts, so each counts as unlisted; unsigned
+/// manifests without one are left as they are.
+pub fn verify_files(
+    dir: &Path,
+    manifest: &serde_json::Value,
+    verification: &mut SignatureVerification,
+) {
+    let unlisted = serde_json::Map::new();
+    let listed = match manifest.get("files").and_then(|f| f.as_object()) {
+        Some(listed) => listed,
+        None if !verification.is_signed => return,
+        None => &unlisted,
+    };
+
+    let mut files = Vec::new();
+    for (path, expected) in listed {
+        let on_disk = normalize_script_path(path).map(|p| dir.join(p));
+        let status = match on_disk.filter(|p| p.is_file()) {
+            None => FileStatus::Missing,
+            Some(file) => match hash_file(&file) {
+                Ok(hash)
+                    if expected
+                        .as_str()
+                        .is_some_and(|e| e.eq_ignore_ascii_case(&hash)) =>
+                {
+                    FileStatus::Ok
+                }
+                Ok(_) => FileStatus::Mismatch,
+                Err(_) => FileStatus::Missing,
+            },
+        };
+        files.push(FileVerification {
+            path: path.clone(),
+            status,
+        });
+    }
+
+    let listed_paths: Vec<Option<String>> =
+        listed.keys().map(|p| normalize_script_path(p)).collect();
+    for script in script_files(dir, manifest) {
+        if !listed_paths.contains(&Some(script.clone())) {
+            files.push(FileVerification {
+                path: script,
+                status: FileStatus::Unlisted,
+            });
+        }
+    }
+
+    verification.files = files;
+    if verification.is_tampered() {
+        verification.is_valid = false;
+        verification.status = "Tampered - script files don't match the manifest".to_string();
+        verification.error = Some(format!(
+            "Script files don't match the manifest: {}",
+            verification.file_problems()
+        ));
+    }
+}
+
+/// Which extensions may load, judged by their manifest signature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    /// Load everything, including manifests whose signature or files don't verify
+    AllowUnsigned,
+    /// Load unsigned and untrusted extensions with a warning; refuse invalid
+    /// signatures and tampered script files
+    #[default]
+    WarnUnsigned,
+    /// Only load extensions with a valid signature from a trusted publisher
+    RequireTrusted,
+}
+
+impl SignaturePolicy {
+    /// Check whether an extension may load. Returns the reason it was refused, or
+    /// logs a warning for anything short of a trusted signature that is let through.
+    pub fn check(
+        self,
+        extension_id: &str,
+        verification: &SignatureVerification,
+    ) -> Result<(), String> {
+        if verification.is_verified() {
+            return Ok(());
+        }
+
+        let problem = if verification.is_tampered() {
+            let publisher = if verification.is_signed {
+                format!(" (publisher '{}')", verification.publisher())
+            } else {
+                String::new()
+            };
+            format!(
+                "has script files that don't match its manifest{}: {}",
+                publisher,
+                verification.file_problems()
+            )
+        } else if !verification.is_signed {
+            "is not signed".to_string()
+        } else if !verification.is_valid {
+            format!(
+                "has an invalid signature for publisher '{}': {}",
+                verification.publisher(),
+                verification
+                    .error
+                    .as_deref()
+                    .unwrap_or(&verification.status)
+            )
+        } else {
+            format!(
+                "is signed by untrusted publisher '{}'",
+                verification.publisher()
+            )
+        };
+
+        let refused = match self {
+            SignaturePolicy::AllowUnsigned => false,
+            SignaturePolicy::WarnUnsigned => {
+                verification.is_tampered() || (verification.is_signed && !verification.is_valid)
+            }
+            SignaturePolicy::RequireTrusted => true,
+        };
+        if refused {
+            return Err(format!(
+                "Extension '{}' {} and the signature policy is {}",
+                extension_id,
+                problem,
+                self.name()
+            ));
+        }
+
+        if self == SignaturePolicy::WarnUnsigned || verification.is_signed {
+            log::warn!("Extension '{}' {}", extension_id, problem);
+        }
+        Ok(())
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SignaturePolicy::AllowUnsigned => "allow_unsigned",
+            SignaturePolicy::WarnUnsigned => "warn_unsigned",
+            SignaturePolicy::RequireTrusted => "require_trusted",
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::lua_extensions::ExtensionManifest;
+
+    #[test]
+    fn test_signable_content_survives_manifest_round_trip() {
+        let original = serde_json::json!({
+            "id": "signed-ext",
+            "name": "Signed",
+            "version": "1.0.0",
+            "author": "VS Write Team",
+            "license": "MIT",
+            "categories": ["writing"],
+            "tools": [{"name": "t", "description": "T", "luaScript": "t.lua"}],
+            "signature": "c2ln",
+            "signatureAlgorithm": "ed25519",
+            "publicKeyId": "vswrite-official"
+        });
+
+        let manifest: ExtensionManifest = serde_json::from_value(original.clone()).unwrap();
+        let rewritten = serde_json::to_value(&manifest).unwrap();
+
+        assert_eq!(rewritten["signature"], "c2ln");
+        assert_eq!(
+            get_signable_content(&rewritten),
+            get_signable_content(&original)
+        );
+    }
+
+    #[test]
+    fn test_signature_policy() {
+        let keys = TrustStore::default();
+        let unsigned = verify_manifest_lenient(&serde_json::json!({"id": "plain"}), &keys);
+        assert!(!unsigned.is_signed);
+
+        let tampered = verify_manifest_lenient(
+            &serde_json::json!({
+                "id": "tampered",
+                "signature": "c2ln",
+                "publicKeyId": "vswrite-official"
+            }),
+            &keys,
+        );
+        assert!(tampered.is_signed && !tampered.is_valid);
+        assert_eq!(tampered.publisher_id.as_deref(), Some("vswrite-official"));
+
+        let untrusted = SignatureVerification {
+            is_signed: true,
+            is_valid: true,
+            publisher_id: Some("someone".to_string()),
+            is_trusted: false,
+            status: String::new(),
+            error: None,
+            files: Vec::new(),
+        };
+        let trusted = SignatureVerification {
+            is_trusted: true,
+            ..untrusted.clone()
+        };
+
+        for policy in [
+            SignaturePolicy::AllowUnsigned,
+            SignaturePolicy::WarnUnsigned,
+            SignaturePolicy::RequireTrusted,
+        ] {
+            assert!(policy.check("ext", &trusted).is_ok());
+        }
+
+        let allow = SignaturePolicy::AllowUnsigned;
+        assert!(allow.check("ext", &unsigned).is_ok());
+        assert!(allow.check("ext", &tampered).is_ok());
+
+        let warn = SignaturePolicy::default();
+        assert!(warn.check("ext", &unsigned).is_ok());
+        assert!(warn.check("ext", &untrusted).is_ok());
+        let err = warn.check("ext", &tampered).unwrap_err();
+        assert!(
+            err.contains("invalid signature for publisher 'vswrite-official'"),
+            "{}",
+            err
+        );
+
+        let require = SignaturePolicy::RequireTrusted;
+        let err = require.check("ext", &unsigned).unwrap_err();
+        assert!(err.contains("'ext' is not signed"), "{}", err);
+        let err = require.check("ext", &untrusted).unwrap_err();
+        assert!(err.contains("untrusted publisher 'someone'"), "{}", err);
+        assert!(err.contains("require_trusted"), "{}", err);
+    }
+
+    #[test]
+    fn test_verify_files() {
+        let keys = TrustStore::default();
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("tools.lua"), "function greet() end").unwrap();
+        let hash = hash_file(&dir.path().join("tools.lua")).unwrap();
+        assert_eq!(hash.len(), 64);
+
+        let mut manifest = serde_json::json!({
+            "id": "files-ext",
+            "tools": [{"name": "greet", "luaScript": "./tools.lua"}],
+            "files": {"tools.lua": hash.clone()},
+        });
+        let verification = verify_extension(dir.path(), &manifest, &keys);
+        assert_eq!(verification.files.len(), 1);
+        assert!(!verification.is_tampered());
+        assert_eq!(verification.status, "Not signed");
+
+        // Manifests without a files section aren't checked
+        let unlisted = serde_json::json!({"id": "plain", "tools": [{"luaScript": "tools.lua"}]});
+        assert!(verify_extension(dir.path(), &unlisted, &keys)
+            .files
+            .is_empty());
+
+        fs::write(dir.path().join("tools.lua"), "os.execute('rm -rf /')").unwrap();
+        fs::write(dir.path().join("hooks.lua"), "function on_activate() end").unwrap();
+        manifest["files"]["gone.lua"] = serde_json::json!(hash.clone());
+        let verification = verify_extension(dir.path(), &manifest, &keys);
+        assert!(verification.is_tampered());
+        assert!(!verification.is_valid);
+        let problems = verification.error.clone().unwrap();
+        assert!(problems.contains("tools.lua (mismatch)"), "{}", problems);
+        assert!(problems.contains("gone.lua (missing)"), "{}", problems);
+        assert!(problems.contains("hooks.lua (unlisted)"), "{}", problems);
+
+        let err = SignaturePolicy::default()
+            .check("files-ext", &verification)
+            .unwrap_err();
+        assert!(err.contains("don't match its manifest"), "{}", err);
+        assert!(SignaturePolicy::AllowUnsigned
+            .check("files-ext", &verification)
+            .is_ok());
+
+        // Listed paths can't point outside the extension
+        let escaping = serde_json::json!({"id": "x", "files": {"../tools.lua": hash}});
+        let verification = verify_extension(dir.path(), &escaping, &keys);
+        assert_eq!(verification.files[0].status, FileStatus::Missing);
+    }
+
+    #[test]
+    fn test_user_publisher_key_is_trusted() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[9; 32]);
+        let mut manifest = serde_json::json!({"id": "acme-ext", "publicKeyId": "acme"});
+        let hash = Sha256::digest(get_signable_content(&manifest).as_bytes());
+        manifest["signature"] = BASE64.encode(signing_key.sign(&hash).to_bytes()).into();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let keys = TrustStore::load(dir.path().join("trusted_publishers.json"));
+        let unknown = verify_manifest(&manifest, &keys).unwrap();
+        assert!(!unknown.is_valid && !unknown.is_trusted);
+
+        let public_key = BASE64.encode(signing_key.verifying_key().to_bytes());
+        keys.add("acme", &public_key).unwrap();
+        let verification = verify_manifest(&manifest, &keys).unwrap();
+        assert!(verification.is_valid && verification.is_trusted);
+        assert!(verification.is_verified());
+    }
+
+    #[test]
+    fn test_signed_manifest_without_files_is_not_verified() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing_key = SigningKey::from_bytes(&[9; 32]);
+        let mut manifest = serde_json::json!({
+            "id": "acme-ext",
+            "publicKeyId": "acme",
+            "tools": [{"name": "greet", "pythonModule": "./tools.py"}],
+        });
+        let hash = Sha256::digest(get_signable_content(&manifest).as_bytes());
+        manifest["signature"] = BASE64.encode(signing_key.sign(&hash).to_bytes()).into();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("tools.py"), "import os").unwrap();
+        fs::write(dir.path().join("hooks.lua"), "function on_activate() end").unwrap();
+        let keys = TrustStore::load(dir.path().join("trusted_publishers.json"));
+        keys.add(
+            "acme",
+            &BASE64.encode(signing_key.verifying_key().to_bytes()),
+        )
+        .unwrap();
+        assert!(verify_manifest(&manifest, &keys).unwrap().is_verified());
+
+        // The signature holds, but nothing vouches for the scripts
+        let verification = verify_extension(dir.path(), &manifest, &keys);
+        assert!(verification.is_tampered());
+        assert!(!verification.is_verified());
+        let problems = verification.error.clone().unwrap();
+        assert!(problems.contains("tools.py (unlisted)"), "{}", problems);
+        assert!(problems.contains("hooks.lua (unlisted)"), "{}", problems);
+        assert!(SignaturePolicy::default()
+            .check("acme-ext", &verification)
+            .is_err());
+    }
+}
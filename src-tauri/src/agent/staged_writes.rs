@@ -0,0 +1,535 @@
+//! Chunked file writes for content too large for a single tool call.
+//!
+//! A model drafting a long chapter can't always fit the whole file into one `write_file`
+//! argument. Instead it calls `begin_write(path)` to get a handle, sends the content in
+//! pieces with `write_chunk(handle, content)`, and finishes with `commit_write(handle)`,
+//! which moves the staged file into place in one rename. `abort_write(handle)` discards it.
+//!
+//! Chunks are staged in a temp file under the workspace staging area so the rename stays
+//! on one filesystem. Handles still open when the run ends are expired and their temp
+//! files removed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::tools::PathValidator;
+use super::types::{JsonSchema, PropertySchema, Tool};
+
+/// Staging area for in-progress writes, relative to the workspace root
+pub const STAGING_DIR: &str = ".vswrite/staging";
+
+/// Maximum size of a single chunk
+pub const MAX_CHUNK_BYTES: usize = 256 * 1024;
+
+/// Maximum total size of a staged file
+pub const MAX_STAGED_BYTES: usize = 10 * 1024 * 1024;
+
+/// Maximum number of writes a run may have open at once
+const MAX_OPEN_WRITES: usize = 8;
+
+/// Names of the chunked write tools
+pub const STAGED_WRITE_TOOLS: &[&str] =
+    &["begin_write", "write_chunk", "commit_write", "abort_write"];
+
+/// An in-progress chunked write
+struct StagedWrite {
+    /// Path as requested by the model (for messages)
+    path: String,
+    /// Validated destination
+    target: PathBuf,
+    /// Temp file accumulating chunks
+    temp: PathBuf,
+    /// Bytes written so far
+    bytes: usize,
+}
+
+/// Open chunked writes for one agent run.
+///
+/// Dropping the store (at the end of a run) expires every handle that was never
+/// committed or aborted.
+#[derive(Default)]
+pub struct StagedWrites {
+    pending: Mutex<HashMap<String, StagedWrite>>,
+}
+
+impl StagedWrites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `name` is one of the chunked write tools
+    pub fn is_staged_write_tool(name: &str) -> bool {
+        STAGED_WRITE_TOOLS.contains(&name)
+    }
+
+    /// The path an open write was started for, as the model named it
+    pub fn pending_path(&self, handle: &str) -> Option<String> {
+        self.lock().get(handle).map(|write| write.path.clone())
+    }
+
+    /// Start a chunked write to `path`, returning the handle for subsequent chunks
+    pub fn begin(&self, paths: &PathValidator, path: &str) -> Result<String, String> {
+        let target = paths.resolve(path)?;
+        if target.is_dir() {
+            return Err(format!("Not a file: {}", path));
+        }
+
+        let staging = paths.canonical_workspace()?.join(STAGING_DIR);
+        if target.starts_with(&staging) {
+            return Err("Cannot write into the staging area".to_string());
+        }
+
+        let mut pending = self.lock();
+        if pending.len() >= MAX_OPEN_WRITES {
+            return Err(format!(
+                "Too many open writes (max {}). Commit or abort one first.",
+                MAX_OPEN_WRITES
+            ));
+        }
+
+        fs::create_dir_all(&staging)
+            .map_err(|e| format!("Failed to create staging directory: {}", e))?;
+
+        let handle = uuid::Uuid::new_v4().to_string();
+        let temp = staging.join(format!("{}.part", handle));
+        fs::File::create(&temp).map_err(|e| format!("Failed to create staging file: {}", e))?;
+
+        pending.insert(
+            handle.clone(),
+            StagedWrite {
+                path: path.to_string(),
+                target,
+                temp,
+                bytes: 0,
+            },
+        );
+
+        Ok(serde_json::json!({ "handle": handle, "path": path }).to_string())
+    }
+
+    /// Append a chunk to an open write
+    pub fn write_chunk(&self, handle: &str, content: &str) -> Result<String, String> {
+        if content.len() > MAX_CHUNK_BYTES {
+            return Err(format!(
+                "Chunk too large ({} bytes, max {})",
+                content.len(),
+                MAX_CHUNK_BYTES
+            ));
+        }
+
+        let mut pending = self.lock();
+        let write = pending
+            .get_mut(handle)
+            .ok_or_else(|| format!("Unknown or closed write handle: {}", handle))?;
+
+        if write.bytes + content.len() > MAX_STAGED_BYTES {
+            return Err(format!(
+                "Staged file would exceed {} bytes; commit or abort the write",
+                MAX_STAGED_BYTES
+            ));
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&write.temp)
+            .map_err(|e| format!("Failed to open staging file: {}", e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| format!("Failed to write chunk: {}", e))?;
+
+        write.bytes += content.len();
+        Ok(format!(
+            "Staged {} bytes for {} ({} total)",
+            content.len(),
+            write.path,
+            write.bytes
+        ))
+    }
+
+    /// Move a staged write into place
+    pub fn commit(&self, paths: &PathValidator, handle: &str) -> Result<String, String> {
+        let write = self
+            .lock()
+            .remove(handle)
+            .ok_or_else(|| format!("Unknown or closed write handle: {}", handle))?;
+
+        // Re-validate: the destination may have changed since begin_write
+        let target = match paths.resolve(&write.path) {
+            Ok(target) if target == write.target => target,
+            Ok(_) => {
+                remove_temp(&write.temp);
+                return Err(format!("Destination changed during write: {}", write.path));
+            }
+            Err(e) => {
+                remove_temp(&write.temp);
+                return Err(e);
+            }
+        };
+
+        if let Some(parent) = target.parent() {
+            if !parent.exists() {
+                if let Err(e) = fs::create_dir_all(parent) {
+                    remove_temp(&write.temp);
+                    return Err(format!("Failed to create directories: {}", e));
+                }
+            }
+        }
+
+        if let Err(e) = fs::rename(&write.temp, &target) {
+            remove_temp(&write.temp);
+            return Err(format!("Failed to move staged file into place: {}", e));
+        }
+        paths.invalidate(&target);
+
+        Ok(format!("Wrote {} bytes to {}", write.bytes, write.path))
+    }
+
+    /// Discard a staged write
+    pub fn abort(&self, handle: &str) -> Result<String, String> {
+        let write = self
+            .lock()
+            .remove(handle)
+            .ok_or_else(|| format!("Unknown or closed write handle: {}", handle))?;
+
+        remove_temp(&write.temp);
+        Ok(format!("Aborted write to {}", write.path))
+    }
+
+    /// Discard every open write, returning how many were expired
+    pub fn expire_all(&self) -> usize {
+        let expired: Vec<StagedWrite> = self.lock().drain().map(|(_, w)| w).collect();
+        for write in &expired {
+            log::info!("Expiring uncommitted write to {}", write.path);
+            remove_temp(&write.temp);
+        }
+        expired.len()
+    }
+
+    /// Dispatch one of the chunked write tools
+    pub fn dispatch(
+        &self,
+        paths: &PathValidator,
+        name: &str,
+        args: &serde_json::Value,
+    ) -> Result<String, String> {
+        let handle = || {
+            args.get("handle")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'handle' parameter")
+        };
+
+        match name {
+            "begin_write" => {
+                let path = args
+                    .get("path")
+                    .and_then(|v| v.as_str())
+                    .ok_or("Missing 'path' parameter")?;
+                self.begin(paths, path)
+            }
+            "write_chunk" => {
+                let content = args
+                    .get("content")
+                    .and_then(|v| v.as_str())
+                    .ok_or("Missing 'content' parameter")?;
+                self.write_chunk(handle()?, content)
+            }
+            "commit_write" => self.commit(paths, handle()?),
+            "abort_write" => self.abort(handle()?),
+            _ => Err(format!("Unknown tool: {}", name)),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, StagedWrite>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for StagedWrites {
+    fn drop(&mut self) {
+        self.expire_all();
+    }
+}
+
+fn remove_temp(temp: &Path) {
+    if let Err(e) = fs::remove_file(temp) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Failed to remove staging file {}: {}", temp.display(), e);
+        }
+    }
+}
+
+// ============================================================================
+// Tool Schemas
+// ============================================================================
+
+/// Schemas for the chunked write tools
+pub fn staged_write_schemas() -> Vec<Tool> {
+    vec![
+        begin_write_schema(),
+        write_chunk_schema(),
+        commit_write_schema(),
+        abort_write_schema(),
+    ]
+}
+
+fn handle_property() -> PropertySchema {
+    PropertySchema {
+        prop_type: "string".to_string(),
+        description: Some("Handle returned by begin_write".to_string()),
+        default: None,
+        items: None,
+    }
+}
+
+fn begin_write_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "path".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Path to write to (relative to workspace)".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "begin_write",
+        &format!(
+            "Start writing a large file in chunks. Returns a handle for write_chunk and commit_write. \
+             Use this instead of write_file when content is long. Chunks are limited to {} bytes.",
+            MAX_CHUNK_BYTES
+        ),
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["path".to_string()]),
+        },
+    )
+}
+
+fn write_chunk_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("handle".to_string(), handle_property());
+    properties.insert(
+        "content".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Next piece of content, appended as-is".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "write_chunk",
+        "Append the next chunk of content to a write started with begin_write.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["handle".to_string(), "content".to_string()]),
+        },
+    )
+}
+
+fn commit_write_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("handle".to_string(), handle_property());
+
+    Tool::new(
+        "commit_write",
+        "Finish a chunked write, replacing the destination file with the staged content.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["handle".to_string()]),
+        },
+    )
+}
+
+fn abort_write_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert("handle".to_string(), handle_property());
+
+    Tool::new(
+        "abort_write",
+        "Discard a chunked write without touching the destination file.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec!["handle".to_string()]),
+        },
+    )
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn begin(staged: &StagedWrites, paths: &PathValidator, path: &str) -> String {
+        let result: serde_json::Value =
+            serde_json::from_str(&staged.begin(paths, path).unwrap()).unwrap();
+        result["handle"].as_str().unwrap().to_string()
+    }
+
+    fn staging_files(dir: &Path) -> usize {
+        fs::read_dir(dir.join(STAGING_DIR))
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_multi_chunk_commit() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+
+        let handle = begin(&staged, &paths, "chapters/one.md");
+        staged.write_chunk(&handle, "It was a dark ").unwrap();
+        staged.write_chunk(&handle, "and stormy ").unwrap();
+        staged.write_chunk(&handle, "night.").unwrap();
+
+        // Nothing is visible until commit
+        assert!(!dir.path().join("chapters/one.md").exists());
+
+        let result = staged.commit(&paths, &handle).unwrap();
+        assert!(result.contains("31 bytes"));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("chapters/one.md")).unwrap(),
+            "It was a dark and stormy night."
+        );
+        assert_eq!(staging_files(dir.path()), 0);
+
+        // The handle is closed after commit
+        assert!(staged.write_chunk(&handle, "more").is_err());
+    }
+
+    #[test]
+    fn test_commit_replaces_existing_file() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("draft.md"), "old").unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+
+        let handle = begin(&staged, &paths, "draft.md");
+        staged.write_chunk(&handle, "new").unwrap();
+        staged.commit(&paths, &handle).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("draft.md")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn test_abort_leaves_nothing() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+
+        let handle = begin(&staged, &paths, "scrap.md");
+        staged.write_chunk(&handle, "discard me").unwrap();
+        staged.abort(&handle).unwrap();
+
+        assert!(!dir.path().join("scrap.md").exists());
+        assert_eq!(staging_files(dir.path()), 0);
+        assert!(staged.commit(&paths, &handle).is_err());
+    }
+
+    #[test]
+    fn test_expiry_cleans_temp_files() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        {
+            let staged = StagedWrites::new();
+            let a = begin(&staged, &paths, "a.md");
+            let b = begin(&staged, &paths, "b.md");
+            staged.write_chunk(&a, "aaa").unwrap();
+            staged.write_chunk(&b, "bbb").unwrap();
+            assert_eq!(staging_files(dir.path()), 2);
+            // Dropped at "run end" without committing
+        }
+
+        assert_eq!(staging_files(dir.path()), 0);
+        assert!(!dir.path().join("a.md").exists());
+        assert!(!dir.path().join("b.md").exists());
+    }
+
+    #[test]
+    fn test_size_caps() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+        let handle = begin(&staged, &paths, "big.md");
+
+        let too_big = "x".repeat(MAX_CHUNK_BYTES + 1);
+        assert!(staged
+            .write_chunk(&handle, &too_big)
+            .unwrap_err()
+            .contains("Chunk too large"));
+
+        let chunk = "x".repeat(MAX_CHUNK_BYTES);
+        for _ in 0..(MAX_STAGED_BYTES / MAX_CHUNK_BYTES) {
+            staged.write_chunk(&handle, &chunk).unwrap();
+        }
+        assert!(staged
+            .write_chunk(&handle, "x")
+            .unwrap_err()
+            .contains("exceed"));
+    }
+
+    #[test]
+    fn test_begin_validates_path() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+
+        assert!(staged.begin(&paths, "../outside.md").is_err());
+        assert!(staged.begin(&paths, ".env").is_err());
+        assert!(staged.begin(&paths, ".vswrite/staging/x.part").is_err());
+        assert_eq!(staging_files(dir.path()), 0);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let dir = TempDir::new().unwrap();
+        let paths = PathValidator::new(dir.path());
+        let staged = StagedWrites::new();
+
+        let begun = staged
+            .dispatch(&paths, "begin_write", &serde_json::json!({"path": "d.md"}))
+            .unwrap();
+        let handle = serde_json::from_str::<serde_json::Value>(&begun).unwrap()["handle"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        staged
+            .dispatch(
+                &paths,
+                "write_chunk",
+                &serde_json::json!({"handle": handle, "content": "hi"}),
+            )
+            .unwrap();
+        staged
+            .dispatch(
+                &paths,
+                "commit_write",
+                &serde_json::json!({"handle": handle}),
+            )
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(dir.path().join("d.md")).unwrap(), "hi");
+        assert!(staged
+            .dispatch(&paths, "write_chunk", &serde_json::json!({"content": "x"}))
+            .is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! String helpers shared across the agent.
+
+use unicode_normalization::UnicodeNormalization;
+
+/// The longest prefix of `s` that is at most `max_bytes` long and ends on a
+/// char boundary. Slicing with `&s[..max_bytes]` panics when the cut lands
+/// inside a multi-byte character; this backs off to the previous boundary.
+pub fn truncate_to_char_boundary(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Size caps for one free-text input checked by [`normalize_input`]
+#[derive(Debug, Clone, Copy)]
+pub struct InputLimits {
+    pub max_chars: usize,
+    pub max_bytes: usize,
+}
+
+/// Zero-width characters that render as nothing. They have legitimate uses
+/// (emoji ZWJ sequences, word joiners) but never more than one in a row.
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+    )
+}
+
+/// Control characters (C0, DEL, C1) other than newlines and tabs
+fn is_disallowed_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\n' | '\r' | '\t')
+}
+
+/// How far past `max_bytes` raw input may run before it is rejected without
+/// being normalized. Stripping can shrink text, but not by more than this in
+/// any input worth keeping.
+const RAW_INPUT_FACTOR: usize = 4;
+
+/// Normalize user-supplied text before it is sent to a provider.
+///
+/// The text is NFC-normalized, disallowed control characters are removed, and
+/// runs of zero-width characters are collapsed to their first character. The
+/// result is then checked against `limits`. Text that was not blank but is blank
+/// after normalization is rejected. Errors start with `label` and say what was
+/// stripped. Input more than [`RAW_INPUT_FACTOR`] times `max_bytes` is rejected
+/// before any of this. (Unpaired surrogates never get this far: they fail IPC
+/// deserialization.)
+pub fn normalize_input(label: &str, text: &str, limits: InputLimits) -> Result<String, String> {
+    let max_raw_bytes = limits.max_bytes.saturating_mul(RAW_INPUT_FACTOR);
+    if text.len() > max_raw_bytes {
+        return Err(format!(
+            "{} too large ({} bytes before normalization, max {})",
+            label,
+            text.len(),
+            max_raw_bytes
+        ));
+    }
+
+    let mut normalized = String::with_capacity(text.len());
+    let mut controls = 0;
+    let mut zero_width = 0;
+    let mut prev_zero_width = false;
+
+    for c in text.nfc() {
+        if is_disallowed_control(c) {
+            controls += 1;
+            continue;
+        }
+        let zw = is_zero_width(c);
+        if zw && prev_zero_width {
+            zero_width += 1;
+            continue;
+        }
+        prev_zero_width = zw;
+        normalized.push(c);
+    }
+
+    let stripped = match (controls, zero_width) {
+        (0, 0) => String::new(),
+        (c, 0) => format!(" (stripped {} control characters)", c),
+        (0, z) => format!(" (stripped {} repeated zero-width characters)", z),
+        (c, z) => format!(
+            " (stripped {} control characters and {} repeated zero-width characters)",
+            c, z
+        ),
+    };
+
+    let is_blank = |s: &str| s.chars().all(|c| c.is_whitespace() || is_zero_width(c));
+    if is_blank(&normalized) && !text.trim().is_empty() {
+        return Err(format!(
+            "{} is empty after normalization{}",
+            label, stripped
+        ));
+    }
+
+    if normalized.len() > limits.max_bytes {
+        return Err(format!(
+            "{} too large ({} bytes, max {}){}",
+            label,
+            normalized.len(),
+            limits.max_bytes,
+            stripped
+        ));
+    }
+    let chars = normalized.chars().count();
+    if chars > limits.max_chars {
+        return Err(format!(
+            "{} too long ({} characters, max {}){}",
+            label, chars, limits.max_chars, stripped
+        ));
+    }
+
+    Ok(normalized)
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_to_char_boundary() {
+        assert_eq!(truncate_to_char_boundary("hello", 10), "hello");
+        assert_eq!(truncate_to_char_boundary("hello", 3), "hel");
+        // 'é' is two bytes; cutting through it backs off to before it
+        assert_eq!(truncate_to_char_boundary("café", 4), "caf");
+        assert_eq!(truncate_to_char_boundary("日本語", 5), "日");
+        assert_eq!(truncate_to_char_boundary("日本語", 0), "");
+    }
+
+    const LIMITS: InputLimits = InputLimits {
+        max_chars: 10,
+        max_bytes: 20,
+    };
+
+    #[test]
+    fn test_normalize_input_nfc() {
+        // "e" + combining acute composes to a single "é"
+        let text = normalize_input("Task", "cafe\u{0301}", LIMITS).unwrap();
+        assert_eq!(text, "caf\u{00E9}");
+    }
+
+    #[test]
+    fn test_normalize_input_strips_control_characters() {
+        let text = normalize_input("Task", "a\u{0000}b\u{0007}\tc\r\nd\u{009B}", LIMITS).unwrap();
+        assert_eq!(text, "ab\tc\r\nd");
+
+        let err = normalize_input("Task", "\u{0001}\u{0002}", LIMITS).unwrap_err();
+        assert_eq!(
+            err,
+            "Task is empty after normalization (stripped 2 control characters)"
+        );
+    }
+
+    #[test]
+    fn test_normalize_input_zero_width_flood() {
+        // A single joiner inside an emoji sequence survives
+        let family = "\u{1F468}\u{200D}\u{1F469}";
+        assert_eq!(normalize_input("Task", family, LIMITS).unwrap(), family);
+
+        // A flood collapses to one character and fits the limits again
+        let flood = format!("hi{}there", "\u{200D}".repeat(20));
+        assert_eq!(
+            normalize_input("Task", &flood, LIMITS).unwrap(),
+            "hi\u{200D}there"
+        );
+
+        // A flood with nothing else is empty
+        let err = normalize_input("Task", &"\u{200B}".repeat(25), LIMITS).unwrap_err();
+        assert!(err.contains("empty after normalization"));
+        assert!(err.contains("stripped 24 repeated zero-width characters"));
+
+        // One far past the caps is refused before it is normalized at all
+        let flood = format!("hi{}there", "\u{200D}".repeat(100_000));
+        let err = normalize_input("Task", &flood, LIMITS).unwrap_err();
+        assert_eq!(
+            err,
+            "Task too large (300007 bytes before normalization, max 80)"
+        );
+    }
+
+    #[test]
+    fn test_normalize_input_boundary_sizes() {
+        assert!(normalize_input("Task", &"x".repeat(10), LIMITS).is_ok());
+        let err = normalize_input("Task", &"x".repeat(11), LIMITS).unwrap_err();
+        assert_eq!(err, "Task too long (11 characters, max 10)");
+
+        // Ten 2-byte characters are exactly 20 bytes; the char cap also holds
+        assert!(normalize_input("Task", &"é".repeat(10), LIMITS).is_ok());
+        // Seven 3-byte characters pass the char cap but not the byte cap
+        let err = normalize_input("Task", &"日".repeat(7), LIMITS).unwrap_err();
+        assert_eq!(err, "Task too large (21 bytes, max 20)");
+
+        // Raw input may run up to four times the byte cap
+        let err = normalize_input("Task", &"\u{0000}".repeat(80), LIMITS).unwrap_err();
+        assert!(err.contains("empty after normalization"), "{}", err);
+        let err = normalize_input("Task", &"\u{0000}".repeat(81), LIMITS).unwrap_err();
+        assert!(err.contains("before normalization"), "{}", err);
+
+        // Caps apply after stripping, and the error still says what was removed
+        let err = normalize_input("Task", &"x\u{0000}".repeat(11), LIMITS).unwrap_err();
+        assert_eq!(
+            err,
+            "Task too long (11 characters, max 10) (stripped 11 control characters)"
+        );
+    }
+
+    #[test]
+    fn test_normalize_input_blank_passthrough() {
+        // Blank input is left for the caller to judge
+        assert_eq!(normalize_input("System prompt", "", LIMITS).unwrap(), "");
+        assert_eq!(
+            normalize_input("System prompt", "  \n", LIMITS).unwrap(),
+            "  \n"
+        );
+    }
+}
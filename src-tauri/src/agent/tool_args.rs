@@ -0,0 +1,261 @@
+//! Parsing of the argument JSON models send with tool calls.
+//!
+//! Models don't always send valid JSON: trailing commas, single-quoted strings,
+//! comments, raw newlines inside strings, and replies cut off before the closing
+//! brace all turn up. [`parse_tool_arguments`] repairs those before giving up.
+
+use serde_json::{Map, Value};
+
+/// Parse a tool call's arguments into a JSON object.
+///
+/// Blank arguments and `null` are an empty object. Strict JSON is tried first,
+/// then a repaired copy; the error describes why the original didn't parse.
+pub fn parse_tool_arguments(raw: &str) -> Result<Value, String> {
+    let trimmed = strip_code_fence(raw.trim());
+    if trimmed.is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+
+    let strict_error = match serde_json::from_str::<Value>(trimmed) {
+        Ok(value) => return into_object(value),
+        Err(e) => e,
+    };
+    match serde_json::from_str::<Value>(&repair(trimmed)) {
+        Ok(value) => {
+            log::warn!("Repaired malformed tool arguments ({})", strict_error);
+            into_object(value)
+        }
+        Err(_) => Err(strict_error.to_string()),
+    }
+}
+
+/// Accept an object (or `null`); unwrap arguments that were encoded twice
+fn into_object(value: Value) -> Result<Value, String> {
+    match value {
+        Value::Object(_) => Ok(value),
+        Value::Null => Ok(Value::Object(Map::new())),
+        Value::String(inner) => match parse_tool_arguments(&inner) {
+            Ok(value) => Ok(value),
+            Err(_) => Err("expected a JSON object, got a string".to_string()),
+        },
+        Value::Array(_) => Err("expected a JSON object, got an array".to_string()),
+        _ => Err("expected a JSON object".to_string()),
+    }
+}
+
+/// Drop a Markdown code fence wrapped around the JSON
+fn strip_code_fence(s: &str) -> &str {
+    let Some(rest) = s.strip_prefix("```") else {
+        return s;
+    };
+    let body = rest.split_once('\n').map_or("", |(_, body)| body);
+    body.trim_end().trim_end_matches("```").trim()
+}
+
+/// Rewrite the common near-JSON mistakes into JSON
+fn repair(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::with_capacity(s.len() + 8);
+    let mut open: Vec<char> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                i = copy_string(&chars, i, &mut out);
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+            '{' => {
+                open.push('}');
+                out.push(c);
+            }
+            '[' => {
+                open.push(']');
+                out.push(c);
+            }
+            '}' | ']' => {
+                trim_trailing_comma(&mut out);
+                // Close anything the model forgot to close first
+                if open.contains(&c) {
+                    while let Some(closer) = open.pop() {
+                        if closer == c {
+                            break;
+                        }
+                        out.push(closer);
+                    }
+                }
+                out.push(c);
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let is_key = chars[i..].iter().find(|c| !c.is_whitespace()) == Some(&':');
+                if is_key {
+                    out.push('"');
+                    out.push_str(&word);
+                    out.push('"');
+                } else {
+                    out.push_str(match word.as_str() {
+                        "True" => "true",
+                        "False" => "false",
+                        "None" => "null",
+                        other => other,
+                    });
+                }
+                continue;
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+
+    // A reply cut off part way: close whatever is still open
+    trim_trailing_comma(&mut out);
+    while let Some(closer) = open.pop() {
+        out.push(closer);
+    }
+    out
+}
+
+/// Copy the string starting at `chars[start]` as a double-quoted JSON string,
+/// escaping raw control characters. Returns the index after the closing quote.
+fn copy_string(chars: &[char], start: usize, out: &mut String) -> usize {
+    let quote = chars[start];
+    out.push('"');
+    let mut i = start + 1;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                match chars.get(i + 1).copied() {
+                    // \' is only an escape inside single quotes, and JSON has no \'
+                    Some('\'') => out.push('\''),
+                    Some(next) => {
+                        out.push('\\');
+                        out.push(next);
+                    }
+                    None => {}
+                }
+                i += 2;
+                continue;
+            }
+            c if c == quote => {
+                out.push('"');
+                return i + 1;
+            }
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    // Unterminated: the reply was cut off inside the string
+    out.push('"');
+    i
+}
+
+fn trim_trailing_comma(out: &mut String) {
+    let len = out.trim_end().len();
+    if out[..len].ends_with(',') {
+        out.truncate(len - 1);
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_valid_and_empty_arguments() {
+        assert_eq!(
+            parse_tool_arguments(r#"{"path": "a.md"}"#).unwrap(),
+            json!({"path": "a.md"})
+        );
+        assert_eq!(parse_tool_arguments("").unwrap(), json!({}));
+        assert_eq!(parse_tool_arguments("  null ").unwrap(), json!({}));
+    }
+
+    #[test]
+    fn test_repairs_malformed_arguments() {
+        let cases = [
+            // Trailing commas
+            (
+                r#"{"path": "a.md", "limit": 20,}"#,
+                json!({"path": "a.md", "limit": 20}),
+            ),
+            (
+                r#"{"paths": ["a.md", "b.md",], }"#,
+                json!({"paths": ["a.md", "b.md"]}),
+            ),
+            // Single quotes and bare keys
+            (
+                r#"{'path': 'notes/it\'s.md', recursive: True}"#,
+                json!({"path": "notes/it's.md", "recursive": true}),
+            ),
+            // Raw newline inside a string
+            (
+                "{\"path\": \"a.md\", \"content\": \"line one\nline two\"}",
+                json!({"path": "a.md", "content": "line one\nline two"}),
+            ),
+            // Comments
+            (
+                "{\n  // where to look\n  \"pattern\": \"**/*.md\" /* all */\n}",
+                json!({"pattern": "**/*.md"}),
+            ),
+            // Cut off before the end
+            (
+                r#"{"path": "a.md", "content": "Chapter 1"#,
+                json!({"path": "a.md", "content": "Chapter 1"}),
+            ),
+            (
+                r#"{"paths": ["a.md", "b.md"}"#,
+                json!({"paths": ["a.md", "b.md"]}),
+            ),
+            // Wrapped in a code fence
+            (
+                "```json\n{\"path\": \"a.md\"}\n```",
+                json!({"path": "a.md"}),
+            ),
+            // Encoded twice
+            (r#""{\"path\": \"a.md\"}""#, json!({"path": "a.md"})),
+        ];
+
+        for (raw, expected) in cases {
+            assert_eq!(parse_tool_arguments(raw).unwrap(), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_unrepairable_arguments() {
+        for raw in [r#"{"path" "a.md"}"#, "[1, 2]", r#""a.md""#, "{: }"] {
+            assert!(parse_tool_arguments(raw).is_err(), "{}", raw);
+        }
+        let err = parse_tool_arguments(r#"{"path" "a.md"}"#).unwrap_err();
+        assert!(err.contains("line 1"), "{}", err);
+    }
+}
@@ -0,0 +1,120 @@
+//! Per-tool enable/disable.
+//!
+//! `disabled_tools` in the run config lists tools the agent may not use at all,
+//! whatever the approval mode. An entry is a built-in tool name (`"run_shell"`), an
+//! extension tool's full name (`"word-count:count"`), or an extension ID
+//! (`"word-count"`), which turns off every tool that extension provides.
+//!
+//! Disabled tools are left out of the schemas the model is sent, and a call to one
+//! anyway is refused before any approval is asked for.
+
+use super::types::Tool;
+
+/// Prefix of the error returned for a call to a disabled tool
+pub const TOOL_DISABLED: &str = "tool disabled by settings";
+
+/// Maximum number of `disabled_tools` entries
+const MAX_DISABLED_TOOLS: usize = 200;
+
+/// Maximum length of a single entry
+const MAX_ENTRY_LEN: usize = 200;
+
+/// Check entry counts and lengths
+pub fn validate_disabled_tools(disabled: &[String]) -> Result<(), String> {
+    if disabled.len() > MAX_DISABLED_TOOLS {
+        return Err(format!(
+            "disabled_tools cannot have more than {} entries",
+            MAX_DISABLED_TOOLS
+        ));
+    }
+    for entry in disabled {
+        if entry.trim().is_empty() {
+            return Err("disabled_tools entries cannot be empty".to_string());
+        }
+        if entry.len() > MAX_ENTRY_LEN {
+            return Err(format!(
+                "disabled_tools entries cannot be longer than {} characters",
+                MAX_ENTRY_LEN
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` is disabled, by its own name or (for an extension tool) its
+/// extension's ID
+pub fn is_tool_disabled(disabled: &[String], name: &str) -> bool {
+    let extension = name.split_once(':').map(|(id, _)| id);
+    disabled
+        .iter()
+        .any(|entry| entry == name || Some(entry.as_str()) == extension)
+}
+
+/// Refuse a call to a disabled tool
+pub fn check_tool_enabled(disabled: &[String], name: &str) -> Result<(), String> {
+    if is_tool_disabled(disabled, name) {
+        return Err(format!(
+            "{}: {} is turned off in the agent settings. Do without it, or tell the user it is unavailable.",
+            TOOL_DISABLED, name
+        ));
+    }
+    Ok(())
+}
+
+/// Drop the schemas of disabled tools
+pub fn retain_enabled(tools: &mut Vec<Tool>, disabled: &[String]) {
+    if !disabled.is_empty() {
+        tools.retain(|tool| !is_tool_disabled(disabled, &tool.function.name));
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::tools::get_tool_schemas;
+
+    fn disabled(entries: &[&str]) -> Vec<String> {
+        entries.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_matches_names_and_extension_ids() {
+        let list = disabled(&["run_shell", "word-count", "notes:export"]);
+
+        assert!(is_tool_disabled(&list, "run_shell"));
+        assert!(!is_tool_disabled(&list, "read_file"));
+        // A whole extension, or one of its tools by full name
+        assert!(is_tool_disabled(&list, "word-count:count"));
+        assert!(is_tool_disabled(&list, "notes:export"));
+        assert!(!is_tool_disabled(&list, "notes:import"));
+        // Built-in names don't reach extension tools of the same name
+        assert!(!is_tool_disabled(&list, "shell-ext:run_shell"));
+
+        let err = check_tool_enabled(&list, "run_shell").unwrap_err();
+        assert!(err.starts_with(TOOL_DISABLED), "{}", err);
+        assert!(check_tool_enabled(&list, "grep").is_ok());
+    }
+
+    #[test]
+    fn test_retain_enabled_filters_schemas() {
+        let mut tools = get_tool_schemas();
+        let count = tools.len();
+        retain_enabled(&mut tools, &disabled(&["run_shell", "delete_file"]));
+
+        assert_eq!(tools.len(), count - 2);
+        assert!(!tools.iter().any(|t| t.function.name == "run_shell"));
+        assert!(tools.iter().any(|t| t.function.name == "read_file"));
+    }
+
+    #[test]
+    fn test_validate_disabled_tools() {
+        assert!(validate_disabled_tools(&disabled(&["run_shell"])).is_ok());
+        assert!(validate_disabled_tools(&disabled(&[" "])).is_err());
+        assert!(validate_disabled_tools(&["x".repeat(MAX_ENTRY_LEN + 1)]).is_err());
+        assert!(validate_disabled_tools(&vec!["x".to_string(); MAX_DISABLED_TOOLS + 1]).is_err());
+    }
+}
@@ -0,0 +1,168 @@
+//! What a tool call produces.
+//!
+//! Built-in tools return a [`ToolOutcome`]: the output in its own form plus what
+//! the call did to the workspace. The loop hands the effects to events, the
+//! audit log, and the run summary, and renders the output to text only for the
+//! model's tool message and for callers that want a string, like Lua scripts.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A tool's output before it is rendered for the model
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutput {
+    /// Sent as is
+    Text(String),
+    /// Sent pretty-printed
+    Json(serde_json::Value),
+    /// A file whose contents aren't shown, only described
+    Binary { mime_type: String, size: u64 },
+}
+
+impl ToolOutput {
+    /// The text the model is sent
+    pub fn render(&self) -> String {
+        match self {
+            ToolOutput::Text(text) => text.clone(),
+            ToolOutput::Json(value) => {
+                serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+            }
+            ToolOutput::Binary { mime_type, size } => format!(
+                "Binary file ({}, {} bytes) - not displaying contents",
+                mime_type, size
+            ),
+        }
+    }
+}
+
+/// What a call did to the workspace besides producing output
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolEffects {
+    /// Bytes written to workspace files; a file rewritten in full counts in full
+    #[serde(default)]
+    pub bytes_written: u64,
+    /// Workspace files the call created, changed, or deleted, as it was given them
+    #[serde(default)]
+    pub files_affected: Vec<String>,
+}
+
+impl ToolEffects {
+    pub fn is_empty(&self) -> bool {
+        self.bytes_written == 0 && self.files_affected.is_empty()
+    }
+}
+
+/// A tool's output and its effects
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolOutcome {
+    pub output: ToolOutput,
+    pub effects: ToolEffects,
+}
+
+impl ToolOutcome {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(ToolOutput::Text(text.into()))
+    }
+
+    pub fn json(value: impl Into<serde_json::Value>) -> Self {
+        Self::new(ToolOutput::Json(value.into()))
+    }
+
+    pub fn binary(mime_type: impl Into<String>, size: u64) -> Self {
+        Self::new(ToolOutput::Binary {
+            mime_type: mime_type.into(),
+            size,
+        })
+    }
+
+    fn new(output: ToolOutput) -> Self {
+        ToolOutcome {
+            output,
+            effects: ToolEffects::default(),
+        }
+    }
+
+    /// Record that the call wrote `bytes` to `path`
+    pub fn wrote(mut self, path: &str, bytes: u64) -> Self {
+        self.effects.bytes_written += bytes;
+        self.touched(path)
+    }
+
+    /// Record that the call changed `path` without writing to it, as a delete or
+    /// a rename does
+    pub fn touched(mut self, path: &str) -> Self {
+        if !self.effects.files_affected.iter().any(|p| p == path) {
+            self.effects.files_affected.push(path.to_string());
+        }
+        self
+    }
+
+    /// The rendered output and the effects, apart
+    pub fn into_parts(self) -> (String, ToolEffects) {
+        let text = match self.output {
+            ToolOutput::Text(text) => text,
+            output => output.render(),
+        };
+        (text, self.effects)
+    }
+}
+
+impl From<String> for ToolOutcome {
+    fn from(text: String) -> Self {
+        ToolOutcome::text(text)
+    }
+}
+
+impl From<ToolOutcome> for String {
+    fn from(outcome: ToolOutcome) -> Self {
+        outcome.into_parts().0
+    }
+}
+
+impl fmt::Display for ToolOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.output {
+            ToolOutput::Text(text) => f.write_str(text),
+            output => f.write_str(&output.render()),
+        }
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outputs_render_for_the_model() {
+        let json = ToolOutcome::json(serde_json::json!({"exit_code": 0, "output": "hi"}));
+        assert_eq!(
+            json.to_string(),
+            "{\n  \"exit_code\": 0,\n  \"output\": \"hi\"\n}"
+        );
+        assert_eq!(
+            String::from(ToolOutcome::binary("image/png", 42)),
+            "Binary file (image/png, 42 bytes) - not displaying contents"
+        );
+        assert_eq!(ToolOutcome::from("Done".to_string()).to_string(), "Done");
+    }
+
+    #[test]
+    fn test_effects_accumulate_per_file() {
+        let outcome = ToolOutcome::text("Moved")
+            .touched("a.md")
+            .wrote("b.md", 10)
+            .wrote("b.md", 5);
+        assert_eq!(
+            outcome.effects,
+            ToolEffects {
+                bytes_written: 15,
+                files_affected: vec!["a.md".to_string(), "b.md".to_string()],
+            }
+        );
+        assert!(ToolOutcome::text("Read").effects.is_empty());
+    }
+}
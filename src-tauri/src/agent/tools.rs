@@ -5,115 +5,32 @@
 //! - Returns a Result with string output or error
 //! - Validates paths to prevent workspace escape
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
+use ignore::WalkBuilder;
+use tokio_util::sync::CancellationToken;
+
+use crate::agent::atomic_write::{temp_path, write_atomic};
+use crate::agent::entity_api::{EntityCache, EntityStore};
+use crate::agent::ignore_rules::IgnoreRules;
+use crate::agent::sensitive_paths;
+use crate::agent::shell_env::ShellEnv;
+use crate::agent::shell_policy::ShellPolicy;
+use crate::agent::shell_program::ShellProgram;
+use crate::agent::text::truncate_to_char_boundary;
+use crate::agent::tool_output::ToolOutcome;
 use crate::agent::types::{JsonSchema, PropertySchema, Tool};
 
 // ============================================================================
 // Path Safety
 // ============================================================================
 
-/// Patterns for sensitive files that should never be accessed by the agent
-/// These patterns are checked against the file name (not the full path)
-const SENSITIVE_FILE_PATTERNS: &[&str] = &[
-    // Environment and secrets
-    ".env",
-    ".env.local",
-    ".env.development",
-    ".env.production",
-    ".env.test",
-    ".envrc",
-    // Credentials and keys
-    "credentials",
-    "credentials.json",
-    ".credentials",
-    "secrets",
-    "secrets.json",
-    ".secrets",
-    // SSH keys
-    "id_rsa",
-    "id_rsa.pub",
-    "id_dsa",
-    "id_dsa.pub",
-    "id_ecdsa",
-    "id_ecdsa.pub",
-    "id_ed25519",
-    "id_ed25519.pub",
-    "authorized_keys",
-    "known_hosts",
-    // Private keys
-    "private.pem",
-    "private.key",
-    "server.key",
-    "client.key",
-    // Git credentials
-    ".git-credentials",
-    ".gitconfig",
-    // NPM tokens
-    ".npmrc",
-    // Docker secrets
-    ".docker/config.json",
-    // Cloud provider credentials
-    ".aws/credentials",
-    ".azure/credentials",
-    ".gcloud/credentials",
-    // Password stores
-    ".password-store",
-    ".gnupg",
-    // Keychain
-    "keychain.db",
-    "keychain-db.sqlite",
-];
-
-/// Patterns for sensitive file extensions
-const SENSITIVE_EXTENSIONS: &[&str] = &[".pem", ".key", ".p12", ".pfx", ".keystore", ".jks"];
-
-/// Check if a path points to a sensitive file that should not be accessed
-fn is_sensitive_path(path: &Path) -> Option<String> {
-    // Get the file name
-    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-
-    // Check against sensitive file patterns (case-insensitive)
-    let file_name_lower = file_name.to_lowercase();
-    for pattern in SENSITIVE_FILE_PATTERNS {
-        if file_name_lower == *pattern || file_name_lower.starts_with(&format!("{}.", pattern)) {
-            return Some(format!(
-                "Access denied: '{}' matches sensitive file pattern '{}'",
-                file_name, pattern
-            ));
-        }
-    }
-
-    // Check against sensitive extensions
-    for ext in SENSITIVE_EXTENSIONS {
-        if file_name_lower.ends_with(ext) {
-            return Some(format!(
-                "Access denied: '{}' has sensitive extension '{}'",
-                file_name, ext
-            ));
-        }
-    }
-
-    // Check if path contains sensitive directories
-    let path_str = path.to_string_lossy().to_lowercase();
-    let sensitive_dirs = [".ssh", ".gnupg", ".password-store"];
-    for dir in sensitive_dirs {
-        if path_str.contains(&format!("{}/", dir)) || path_str.contains(&format!("{}\\", dir)) {
-            return Some(format!(
-                "Access denied: path contains sensitive directory '{}'",
-                dir
-            ));
-        }
-    }
-
-    None
-}
-
 /// Check that no component of the path is a symlink.
 /// This prevents TOCTOU vulnerabilities where symlink targets could change
 /// between validation and actual file operation.
@@ -181,12 +98,56 @@ fn symlink_check_base<'a>(
     }
 }
 
+/// Filesystem canonicalization, abstracted so path resolution can be observed in tests.
+pub trait Canonicalize: Send + Sync {
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf>;
+}
+
+/// Canonicalizes paths against the real filesystem
+pub struct FsCanonicalize;
+
+impl Canonicalize for FsCanonicalize {
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        path.canonicalize()
+    }
+}
+
 /// Validate that a path is within the workspace and return the canonical path.
 /// This prevents directory traversal attacks and access outside the workspace.
 ///
 /// Security: This function rejects symlinks to prevent TOCTOU (time-of-check-time-of-use)
 /// vulnerabilities where a symlink target could change between validation and use.
+///
+/// This canonicalizes the workspace on every call. Tool code running within an agent
+/// run should use a shared [`PathValidator`] instead.
 pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
+    let canonical_workspace = workspace
+        .canonicalize()
+        .map_err(|e| format!("Failed to canonicalize workspace: {}", e))?;
+
+    resolve_safe_path(workspace, &canonical_workspace, requested, &FsCanonicalize)
+}
+
+/// Core of `safe_path`, given an already-canonicalized workspace. Refuses paths
+/// the workspace's sensitive-file policy denies.
+fn resolve_safe_path(
+    workspace: &Path,
+    canonical_workspace: &Path,
+    requested: &str,
+    canonicalizer: &dyn Canonicalize,
+) -> Result<PathBuf, String> {
+    let resolved = resolve_in_workspace(workspace, canonical_workspace, requested, canonicalizer)?;
+    sensitive_paths::check(canonical_workspace, &resolved)?;
+    Ok(resolved)
+}
+
+/// Resolve `requested` to a canonical path inside the workspace
+fn resolve_in_workspace(
+    workspace: &Path,
+    canonical_workspace: &Path,
+    requested: &str,
+    canonicalizer: &dyn Canonicalize,
+) -> Result<PathBuf, String> {
     // Handle empty path as workspace root
     let requested = if requested.is_empty() || requested == "." {
         workspace.to_path_buf()
@@ -200,19 +161,9 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
         path
     };
 
-    // Canonicalize workspace first
-    let canonical_workspace = workspace
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize workspace: {}", e))?;
-
-    // Security: Check for sensitive files that should never be accessed
-    if let Some(error) = is_sensitive_path(&requested) {
-        return Err(error);
-    }
-
     // Security: Check for symlinks in the path to prevent TOCTOU attacks
     // A symlink's target could change between our check and actual file operation
-    if let Some(base) = symlink_check_base(&requested, workspace, &canonical_workspace) {
+    if let Some(base) = symlink_check_base(&requested, workspace, canonical_workspace) {
         if requested.exists() {
             check_no_symlinks_in_path(&requested, base)?;
         } else {
@@ -233,15 +184,15 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
 
     // For paths that might not exist yet (write operations), we need to check parent
     let canonical_requested = if requested.exists() {
-        requested
-            .canonicalize()
+        canonicalizer
+            .canonicalize(&requested)
             .map_err(|e| format!("Failed to canonicalize path: {}", e))?
     } else {
         // Path doesn't exist - check if parent is valid
-        let parent = requested.parent().unwrap_or(&canonical_workspace);
+        let parent = requested.parent().unwrap_or(canonical_workspace);
         let canonical_parent = if parent.exists() {
-            parent
-                .canonicalize()
+            canonicalizer
+                .canonicalize(parent)
                 .map_err(|e| format!("Failed to canonicalize parent: {}", e))?
         } else {
             // Parent doesn't exist either - construct manually and verify
@@ -249,10 +200,10 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
             // Use strip_prefix on the original workspace to handle Windows path differences
             let relative = requested
                 .strip_prefix(workspace)
-                .or_else(|_| requested.strip_prefix(&canonical_workspace))
+                .or_else(|_| requested.strip_prefix(canonical_workspace))
                 .unwrap_or(&requested);
 
-            let mut current = canonical_workspace.clone();
+            let mut current = canonical_workspace.to_path_buf();
             for component in relative.components() {
                 match component {
                     std::path::Component::Normal(c) => current.push(c),
@@ -266,7 +217,7 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
                     _ => {}
                 }
             }
-            return if current.starts_with(&canonical_workspace) {
+            return if current.starts_with(canonical_workspace) {
                 Ok(current)
             } else {
                 Err(format!(
@@ -286,7 +237,7 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
     };
 
     // Verify the path is within workspace
-    if canonical_requested.starts_with(&canonical_workspace) {
+    if canonical_requested.starts_with(canonical_workspace) {
         Ok(canonical_requested)
     } else {
         Err(format!(
@@ -297,6 +248,304 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
     }
 }
 
+/// Default size cap for reading or writing one file (10 MB)
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Size caps for the file tools, so a hallucinated 200 MB string or an append
+/// loop can't fill the disk and a huge log isn't read line by line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLimits {
+    /// Largest file `read_file` opens
+    pub max_read_bytes: u64,
+    /// Largest content `write_file` writes, and largest file `append_file` leaves
+    pub max_write_bytes: u64,
+}
+
+impl Default for FileLimits {
+    fn default() -> Self {
+        FileLimits {
+            max_read_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_write_bytes: DEFAULT_MAX_FILE_BYTES,
+        }
+    }
+}
+
+/// Output caps for the built-in tools. A tool that leaves something out says
+/// so in its result with a [`truncation_note`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ToolLimits {
+    /// Longest line `read_file` shows, in bytes
+    pub read_line_bytes: usize,
+    /// Combined output `run_shell` returns, in bytes
+    pub shell_output_bytes: usize,
+    /// Most matches `grep` returns
+    pub grep_max_matches: usize,
+    /// Most paths `glob` returns
+    pub glob_max_entries: usize,
+    /// Longest result of any tool the model is sent, in bytes
+    pub result_bytes: usize,
+}
+
+impl Default for ToolLimits {
+    fn default() -> Self {
+        ToolLimits {
+            read_line_bytes: READ_FILE_MAX_LINE_BYTES,
+            shell_output_bytes: SHELL_OUTPUT_BYTES,
+            grep_max_matches: GREP_MAX_MATCHES,
+            glob_max_entries: GLOB_MAX_ENTRIES,
+            result_bytes: TOOL_RESULT_BYTES,
+        }
+    }
+}
+
+/// Allowed range for the byte caps in `ToolLimits`
+const TOOL_LIMIT_RANGE_BYTES: std::ops::RangeInclusive<usize> = 100..=1024 * 1024;
+
+/// Allowed range for the count caps in `ToolLimits`
+const TOOL_LIMIT_RANGE_COUNT: std::ops::RangeInclusive<usize> = 1..=10_000;
+
+impl ToolLimits {
+    /// Check every cap is within its allowed range
+    pub fn validate(&self) -> Result<(), String> {
+        let (bytes, count) = (&TOOL_LIMIT_RANGE_BYTES, &TOOL_LIMIT_RANGE_COUNT);
+        let caps = [
+            ("read_line_bytes", self.read_line_bytes, bytes),
+            ("shell_output_bytes", self.shell_output_bytes, bytes),
+            ("grep_max_matches", self.grep_max_matches, count),
+            ("glob_max_entries", self.glob_max_entries, count),
+            ("result_bytes", self.result_bytes, bytes),
+        ];
+        for (name, value, range) in caps {
+            if !range.contains(&value) {
+                return Err(format!(
+                    "tool_limits.{} must be between {} and {} (got {})",
+                    name,
+                    range.start(),
+                    range.end(),
+                    value
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Longest result of any tool the model is sent, in bytes
+pub const TOOL_RESULT_BYTES: usize = 8000;
+
+/// Start of the note a tool adds to its result when it leaves something out
+const TRUNCATION_PREFIX: &str = "[Truncated: ";
+
+/// The note a tool adds to its result when it leaves something out, saying how
+/// much, e.g. `[Truncated: 37 matches omitted]`
+pub fn truncation_note(omitted: usize, unit: &str) -> String {
+    format!("{}{} {} omitted]", TRUNCATION_PREFIX, omitted, unit)
+}
+
+/// Whether a tool result carries a [`truncation_note`]
+pub fn reports_truncation(output: &str) -> bool {
+    output.match_indices(TRUNCATION_PREFIX).any(|(start, _)| {
+        let rest = &output[start + TRUNCATION_PREFIX.len()..];
+        rest.split_once(' ').is_some_and(|(count, rest)| {
+            count.parse::<usize>().is_ok()
+                && rest
+                    .split_once(']')
+                    .is_some_and(|(unit, _)| unit.ends_with(" omitted"))
+        })
+    })
+}
+
+/// A byte count as the model should read it ("18.0 MB", "1.5 KB", "80 bytes")
+fn describe_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
+        format!("{:.1} KB", bytes as f64 / KB as f64)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// Maximum number of resolved paths remembered by a `PathValidator`
+const PATH_CACHE_CAPACITY: usize = 64;
+
+/// Resolved paths remembered by a `PathValidator`
+#[derive(Default)]
+struct PathCache {
+    canonical_workspace: Option<PathBuf>,
+    /// (requested, resolved) pairs, least recently used first
+    entries: VecDeque<(String, PathBuf)>,
+}
+
+/// Per-run path validation with caching.
+///
+/// Canonicalizes the workspace once and remembers the last few validated paths, so
+/// repeated tool calls don't pay for full validation each time (expensive on network
+/// filesystems). Validation itself is identical to [`safe_path`].
+///
+/// A cached path is re-checked on every use (one canonicalization), since anything
+/// outside the tools may have swapped part of it for a symlink. Entries are also
+/// invalidated when a tool writes or deletes the path, and the whole cache is dropped
+/// after shell commands since they can change the filesystem arbitrarily.
+pub struct PathValidator {
+    workspace: PathBuf,
+    canonicalizer: Arc<dyn Canonicalize>,
+    cache: Mutex<PathCache>,
+    file_limits: FileLimits,
+    tool_limits: ToolLimits,
+    /// Parsed entity files, the open workspace's when the run was given it
+    entities: EntityCache,
+}
+
+impl PathValidator {
+    /// Create a validator for a workspace
+    pub fn new(workspace: &Path) -> Self {
+        Self::with_canonicalizer(workspace, Arc::new(FsCanonicalize))
+    }
+
+    /// Create a validator that canonicalizes through a custom implementation
+    pub fn with_canonicalizer(workspace: &Path, canonicalizer: Arc<dyn Canonicalize>) -> Self {
+        PathValidator {
+            workspace: workspace.to_path_buf(),
+            canonicalizer,
+            cache: Mutex::new(PathCache::default()),
+            file_limits: FileLimits::default(),
+            tool_limits: ToolLimits::default(),
+            entities: EntityCache::default(),
+        }
+    }
+
+    /// Cap the sizes the file tools read and write (the defaults otherwise)
+    pub fn with_file_limits(mut self, limits: FileLimits) -> Self {
+        self.file_limits = limits;
+        self
+    }
+
+    /// The size caps the file tools enforce
+    pub fn file_limits(&self) -> FileLimits {
+        self.file_limits
+    }
+
+    /// Cap the output the tools return (the defaults otherwise). The validator
+    /// carries them into `dispatch_tool` along with the workspace.
+    pub fn with_tool_limits(mut self, limits: ToolLimits) -> Self {
+        self.tool_limits = limits;
+        self
+    }
+
+    /// The output caps the tools enforce
+    pub fn tool_limits(&self) -> ToolLimits {
+        self.tool_limits
+    }
+
+    /// Share an open workspace's entity cache with the entity tools
+    pub fn with_entity_cache(mut self, cache: EntityCache) -> Self {
+        self.entities = cache;
+        self
+    }
+
+    /// The entity cache the entity tools share
+    pub fn entity_cache(&self) -> EntityCache {
+        self.entities.clone()
+    }
+
+    /// An entity store on the workspace, sharing the validator's cache
+    pub fn entity_store(&self) -> EntityStore {
+        EntityStore::new(&self.workspace).with_cache(self.entity_cache())
+    }
+
+    /// The workspace path as given
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// The canonical workspace path (computed once, then cached)
+    pub fn canonical_workspace(&self) -> Result<PathBuf, String> {
+        let mut cache = self.lock_cache();
+        if let Some(ref canonical) = cache.canonical_workspace {
+            return Ok(canonical.clone());
+        }
+
+        let canonical = self
+            .canonicalizer
+            .canonicalize(&self.workspace)
+            .map_err(|e| format!("Failed to canonicalize workspace: {}", e))?;
+        cache.canonical_workspace = Some(canonical.clone());
+        Ok(canonical)
+    }
+
+    /// Validate a requested path, equivalent to `safe_path(workspace, requested)`
+    pub fn resolve(&self, requested: &str) -> Result<PathBuf, String> {
+        {
+            let mut cache = self.lock_cache();
+            if let Some(pos) = cache.entries.iter().position(|(r, _)| r == requested) {
+                if let Some(entry) = cache.entries.remove(pos) {
+                    if self.still_resolves(&entry.1) {
+                        let resolved = entry.1.clone();
+                        cache.entries.push_back(entry);
+                        // The policy file may have changed since this path was cached
+                        if let Some(ref canonical) = cache.canonical_workspace {
+                            sensitive_paths::check(canonical, &resolved)?;
+                        }
+                        return Ok(resolved);
+                    }
+                }
+            }
+        }
+
+        let canonical_workspace = self.canonical_workspace()?;
+        let resolved = resolve_safe_path(
+            &self.workspace,
+            &canonical_workspace,
+            requested,
+            self.canonicalizer.as_ref(),
+        )?;
+
+        let mut cache = self.lock_cache();
+        if cache.entries.len() >= PATH_CACHE_CAPACITY {
+            cache.entries.pop_front();
+        }
+        cache
+            .entries
+            .push_back((requested.to_string(), resolved.clone()));
+
+        Ok(resolved)
+    }
+
+    /// Whether a cached resolution still holds: its nearest existing ancestor (the
+    /// path itself, once it exists) still canonicalizes to itself, so nothing on the
+    /// way has become a symlink
+    fn still_resolves(&self, resolved: &Path) -> bool {
+        resolved
+            .ancestors()
+            .find(|path| path.symlink_metadata().is_ok())
+            .is_some_and(|path| {
+                self.canonicalizer
+                    .canonicalize(path)
+                    .is_ok_and(|canonical| canonical == path)
+            })
+    }
+
+    /// Forget cached entries at or beneath a resolved path
+    pub fn invalidate(&self, path: &Path) {
+        self.lock_cache()
+            .entries
+            .retain(|(_, resolved)| !resolved.starts_with(path));
+    }
+
+    /// Forget all cached path entries (the canonical workspace is kept)
+    pub fn clear(&self) {
+        self.lock_cache().entries.clear();
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, PathCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
 // ============================================================================
 // Tool Schemas
 // ============================================================================
@@ -305,12 +554,17 @@ pub fn safe_path(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
 pub fn get_tool_schemas() -> Vec<Tool> {
     vec![
         read_file_schema(),
+        read_many_files_schema(),
         write_file_schema(),
         delete_file_schema(),
         append_file_schema(),
+        edit_file_schema(),
+        move_file_schema(),
+        copy_file_schema(),
         list_dir_schema(),
         glob_schema(),
         grep_schema(),
+        workspace_stats_schema(),
         run_shell_schema(),
     ]
 }
@@ -323,6 +577,7 @@ fn read_file_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Path to the file (relative to workspace)".to_string()),
             default: None,
+            items: None,
         },
     );
     properties.insert(
@@ -331,6 +586,7 @@ fn read_file_schema() -> Tool {
             prop_type: "integer".to_string(),
             description: Some("Line number to start reading from (1-based)".to_string()),
             default: Some(serde_json::json!(1)),
+            items: None,
         },
     );
     properties.insert(
@@ -339,12 +595,13 @@ fn read_file_schema() -> Tool {
             prop_type: "integer".to_string(),
             description: Some("Maximum number of lines to read".to_string()),
             default: Some(serde_json::json!(4000)),
+            items: None,
         },
     );
 
     Tool::new(
         "read_file",
-        "Read a file with optional line offset and limit.",
+        "Read a text file with optional line offset and limit. Output ends with the file's line count and notes when the limit cut it short; binary files are described, not shown.",
         JsonSchema {
             schema_type: "object".to_string(),
             properties: Some(properties),
@@ -353,6 +610,71 @@ fn read_file_schema() -> Tool {
     )
 }
 
+fn read_many_files_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "paths".to_string(),
+        PropertySchema {
+            prop_type: "array".to_string(),
+            description: Some(
+                "Files to read (relative to workspace); give this or 'glob'".to_string(),
+            ),
+            default: None,
+            items: Some(Box::new(PropertySchema {
+                prop_type: "string".to_string(),
+                description: None,
+                default: None,
+                items: None,
+            })),
+        },
+    );
+    properties.insert(
+        "glob".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some(
+                "Glob pattern selecting the files to read, e.g. 'sections/*.md'".to_string(),
+            ),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "limit".to_string(),
+        PropertySchema {
+            prop_type: "integer".to_string(),
+            description: Some("Maximum number of lines to read from each file".to_string()),
+            default: Some(serde_json::json!(READ_MANY_DEFAULT_LINES)),
+            items: None,
+        },
+    );
+    properties.insert(
+        "max_bytes".to_string(),
+        PropertySchema {
+            prop_type: "integer".to_string(),
+            description: Some(format!(
+                "Total bytes of content to return across all files (at most {})",
+                READ_MANY_MAX_BYTES
+            )),
+            default: Some(serde_json::json!(READ_MANY_MAX_BYTES)),
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "read_many_files",
+        &format!(
+            "Read the start of several text files in one call, e.g. every chapter opening. Returns a JSON object mapping each path to {{content, truncated, error}}; a file that can't be read gets an error without failing the others. Reads at most {} files.",
+            READ_MANY_MAX_FILES
+        ),
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: None,
+        },
+    )
+}
+
 fn write_file_schema() -> Tool {
     let mut properties = HashMap::new();
     properties.insert(
@@ -361,6 +683,7 @@ fn write_file_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Path to write to (relative to workspace)".to_string()),
             default: None,
+            items: None,
         },
     );
     properties.insert(
@@ -369,6 +692,7 @@ fn write_file_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Content to write".to_string()),
             default: None,
+            items: None,
         },
     );
 
@@ -391,6 +715,7 @@ fn delete_file_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Path to the file to delete (relative to workspace)".to_string()),
             default: None,
+            items: None,
         },
     );
 
@@ -413,6 +738,7 @@ fn append_file_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Path to append to (relative to workspace)".to_string()),
             default: None,
+            items: None,
         },
     );
     properties.insert(
@@ -421,6 +747,7 @@ fn append_file_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Content to append".to_string()),
             default: None,
+            items: None,
         },
     );
 
@@ -435,6 +762,121 @@ fn append_file_schema() -> Tool {
     )
 }
 
+fn edit_file_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "path".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Path to the file to edit (relative to workspace)".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "old_string".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some(
+                "Exact text to replace; include enough context to match only once".to_string(),
+            ),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "new_string".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Replacement text".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "replace_all".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some("Replace every occurrence instead of exactly one".to_string()),
+            default: Some(serde_json::json!(false)),
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "edit_file",
+        "Replace text in an existing file without rewriting it. Fails if old_string is not found, or is found more than once unless replace_all is true.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec![
+                "path".to_string(),
+                "old_string".to_string(),
+                "new_string".to_string(),
+            ]),
+        },
+    )
+}
+
+/// Parameters shared by move_file and copy_file
+fn transfer_properties(verb: &str) -> HashMap<String, PropertySchema> {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "source".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some(format!("File to {} (relative to workspace)", verb)),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "destination".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some(
+                "New file path (relative to workspace); parent directories are created".to_string(),
+            ),
+            default: None,
+            items: None,
+        },
+    );
+    properties.insert(
+        "overwrite".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some("Replace the destination if it already exists".to_string()),
+            default: Some(serde_json::json!(false)),
+            items: None,
+        },
+    );
+    properties
+}
+
+fn move_file_schema() -> Tool {
+    Tool::new(
+        "move_file",
+        "Move or rename a file in one step. Fails if the destination exists unless overwrite is true. Does not move directories.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(transfer_properties("move")),
+            required: Some(vec!["source".to_string(), "destination".to_string()]),
+        },
+    )
+}
+
+fn copy_file_schema() -> Tool {
+    Tool::new(
+        "copy_file",
+        "Copy a file. Fails if the destination exists unless overwrite is true. Does not copy directories.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(transfer_properties("copy")),
+            required: Some(vec!["source".to_string(), "destination".to_string()]),
+        },
+    )
+}
+
 fn list_dir_schema() -> Tool {
     let mut properties = HashMap::new();
     properties.insert(
@@ -445,12 +887,52 @@ fn list_dir_schema() -> Tool {
                 "Directory path (relative to workspace, defaults to '.')".to_string(),
             ),
             default: Some(serde_json::json!(".")),
+            items: None,
+        },
+    );
+    properties.insert(
+        "include_ignored".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some(
+                "Also list entries hidden by the workspace's .vswriteignore (default: false)"
+                    .to_string(),
+            ),
+            default: Some(serde_json::json!(false)),
+            items: None,
+        },
+    );
+    properties.insert(
+        "recursive".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some(format!(
+                "Also list the contents of subdirectories, up to {} levels deep (default: false)",
+                LIST_DIR_MAX_DEPTH
+            )),
+            default: Some(serde_json::json!(false)),
+            items: None,
+        },
+    );
+    properties.insert(
+        "flat".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some(
+                "Return plain names (directories end in '/') instead of objects (default: false)"
+                    .to_string(),
+            ),
+            default: Some(serde_json::json!(false)),
+            items: None,
         },
     );
 
     Tool::new(
         "list_dir",
-        "List files and directories at a path.",
+        &format!(
+            "List files and directories at a path, directories first. Each entry is {{name, type ('file' or 'dir'), size (bytes; for a directory, everything beneath it), modified (ISO 8601), extension}}. Returns at most {} entries.",
+            LIST_DIR_MAX_ENTRIES
+        ),
         JsonSchema {
             schema_type: "object".to_string(),
             properties: Some(properties),
@@ -467,6 +949,7 @@ fn glob_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Glob pattern (e.g., '**/*.md', '*.txt')".to_string()),
             default: None,
+            items: None,
         },
     );
     properties.insert(
@@ -475,6 +958,7 @@ fn glob_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Base path to search from (relative to workspace)".to_string()),
             default: Some(serde_json::json!(".")),
+            items: None,
         },
     );
 
@@ -497,6 +981,7 @@ fn grep_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Search pattern (substring match)".to_string()),
             default: None,
+            items: None,
         },
     );
     properties.insert(
@@ -505,12 +990,44 @@ fn grep_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Path to search in (file or directory)".to_string()),
             default: Some(serde_json::json!(".")),
+            items: None,
+        },
+    );
+    for (key, description) in [
+        (
+            "context_before",
+            "Lines of context to show before each match (0-5)",
+        ),
+        (
+            "context_after",
+            "Lines of context to show after each match (0-5)",
+        ),
+    ] {
+        properties.insert(
+            key.to_string(),
+            PropertySchema {
+                prop_type: "integer".to_string(),
+                description: Some(description.to_string()),
+                default: Some(serde_json::json!(0)),
+                items: None,
+            },
+        );
+    }
+    properties.insert(
+        "files_only".to_string(),
+        PropertySchema {
+            prop_type: "boolean".to_string(),
+            description: Some(
+                "Only list the matching files with their match counts (default: false)".to_string(),
+            ),
+            default: Some(serde_json::json!(false)),
+            items: None,
         },
     );
 
     Tool::new(
         "grep",
-        "Search file contents for a pattern.",
+        "Search file contents for a case-insensitive substring. Use context_before/context_after to see the lines around each match instead of reading the file, and files_only to find which files mention something.",
         JsonSchema {
             schema_type: "object".to_string(),
             properties: Some(properties),
@@ -519,14 +1036,41 @@ fn grep_schema() -> Tool {
     )
 }
 
-fn run_shell_schema() -> Tool {
+fn workspace_stats_schema() -> Tool {
     let mut properties = HashMap::new();
     properties.insert(
-        "command".to_string(),
+        "path".to_string(),
         PropertySchema {
             prop_type: "string".to_string(),
-            description: Some("Shell command to execute".to_string()),
-            default: None,
+            description: Some(
+                "File, directory, or glob pattern to count (e.g. 'sections/03-*.md'); defaults to every markdown file in the workspace"
+                    .to_string(),
+            ),
+            default: Some(serde_json::json!(".")),
+            items: None,
+        },
+    );
+
+    Tool::new(
+        "workspace_stats",
+        "Count words, characters, and lines per file, with totals. YAML frontmatter is not counted. Use this instead of reading files to measure their length.",
+        JsonSchema {
+            schema_type: "object".to_string(),
+            properties: Some(properties),
+            required: Some(vec![]),
+        },
+    )
+}
+
+fn run_shell_schema() -> Tool {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "command".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("Shell command to execute".to_string()),
+            default: None,
+            items: None,
         },
     );
     properties.insert(
@@ -535,6 +1079,7 @@ fn run_shell_schema() -> Tool {
             prop_type: "string".to_string(),
             description: Some("Working directory (relative to workspace)".to_string()),
             default: Some(serde_json::json!(".")),
+            items: None,
         },
     );
     properties.insert(
@@ -543,6 +1088,7 @@ fn run_shell_schema() -> Tool {
             prop_type: "integer".to_string(),
             description: Some("Timeout in seconds (max 60)".to_string()),
             default: Some(serde_json::json!(30)),
+            items: None,
         },
     );
 
@@ -561,14 +1107,86 @@ fn run_shell_schema() -> Tool {
 // Tool Implementations
 // ============================================================================
 
-/// Read file contents with optional offset and limit
+/// Error returned by tools that stop early because the agent run was cancelled
+pub const TOOL_CANCELLED: &str = "Tool cancelled";
+
+/// Number of lines grep reads between cancellation checks
+const GREP_CANCEL_CHECK_LINES: usize = 1000;
+
+/// Fail with `TOOL_CANCELLED` if the run's token has been cancelled
+fn check_cancelled(cancel: Option<&CancellationToken>) -> Result<(), String> {
+    match cancel {
+        Some(token) if token.is_cancelled() => Err(TOOL_CANCELLED.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Bytes inspected to decide whether a file is binary
+const BINARY_SNIFF_BYTES: usize = 8192;
+
+/// Longest line `read_file` shows by default before cutting it off
+const READ_FILE_MAX_LINE_BYTES: usize = 2000;
+
+/// Whether `chunk` (the start of a file) looks like binary data: it holds a NUL
+/// byte, or more than a tenth of it decodes to control or replacement characters.
+/// Text in a legacy encoding such as latin-1 has only a few undecodable bytes.
+fn looks_binary(chunk: &[u8]) -> bool {
+    if chunk.contains(&0) {
+        return true;
+    }
+    let text = String::from_utf8_lossy(chunk);
+    let mut total = 0usize;
+    let mut odd = 0usize;
+    for c in text.chars() {
+        total += 1;
+        if c == char::REPLACEMENT_CHARACTER || (c.is_control() && !c.is_whitespace()) {
+            odd += 1;
+        }
+    }
+    // A multi-byte character cut off by the chunk boundary isn't evidence
+    odd = odd.saturating_sub(text.ends_with(char::REPLACEMENT_CHARACTER) as usize);
+    odd * 10 > total
+}
+
+/// Best-effort MIME type from a file extension
+fn guess_mime_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    match ext.as_str() {
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "doc" => "application/msword",
+        "pdf" => "application/pdf",
+        "epub" => "application/epub+zip",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Read file contents with optional offset and limit.
+///
+/// Lines are numbered and followed by a footer giving the file's line count and
+/// whether `limit` cut the output short. Invalid UTF-8 is replaced rather than
+/// failing, binary files are described instead of shown, and text files over the
+/// validator's read limit are refused.
 pub fn read_file(
-    workspace: &Path,
+    paths: &PathValidator,
     path: &str,
     offset: Option<usize>,
     limit: Option<usize>,
-) -> Result<String, String> {
-    let safe = safe_path(workspace, path)?;
+) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
 
     if !safe.exists() {
         return Err(format!("File not found: {}", path));
@@ -578,36 +1196,78 @@ pub fn read_file(
         return Err(format!("Not a file: {}", path));
     }
 
+    let size = fs::metadata(&safe)
+        .map_err(|e| format!("Failed to read file: {}", e))?
+        .len();
     let file = fs::File::open(&safe).map_err(|e| format!("Failed to open file: {}", e))?;
-    let reader = BufReader::new(file);
+    let mut reader = BufReader::new(file);
+
+    let sniffed = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    if looks_binary(&sniffed[..sniffed.len().min(BINARY_SNIFF_BYTES)]) {
+        return Ok(ToolOutcome::binary(guess_mime_type(&safe), size));
+    }
+
+    // Checked before reading lines, so an enormous file isn't streamed just to count them
+    let max_read_bytes = paths.file_limits().max_read_bytes;
+    let max_line_bytes = paths.tool_limits().read_line_bytes;
+    if size > max_read_bytes {
+        return Err(format!(
+            "{} is {}, over the {} read limit. Use grep to find the part you need, \
+             or run_shell with head or tail.",
+            path,
+            describe_size(size),
+            describe_size(max_read_bytes)
+        ));
+    }
 
     let offset = offset.unwrap_or(1).max(1);
     let limit = limit.unwrap_or(4000);
 
     let mut result = String::new();
     let mut line_num = 0;
+    let mut last_shown = 0;
+    let mut lossy = false;
+    let mut buf = Vec::new();
 
-    for line_result in reader.lines() {
+    loop {
+        buf.clear();
+        let read = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| format!("Error reading line {}: {}", line_num + 1, e))?;
+        if read == 0 {
+            break;
+        }
         line_num += 1;
 
-        if line_num < offset {
+        // Past the window only the line count matters
+        if line_num < offset || line_num >= offset + limit {
             continue;
         }
 
-        if line_num >= offset + limit {
-            break;
+        if buf.ends_with(b"\n") {
+            buf.pop();
+            if buf.ends_with(b"\r") {
+                buf.pop();
+            }
         }
-
-        let line = line_result.map_err(|e| format!("Error reading line {}: {}", line_num, e))?;
+        let line = String::from_utf8_lossy(&buf);
+        lossy |= matches!(line, std::borrow::Cow::Owned(_));
 
         // Truncate very long lines
-        let truncated_line = if line.len() > 2000 {
-            format!("{}...[truncated]", &line[..2000])
+        if line.len() > max_line_bytes {
+            let shown = truncate_to_char_boundary(&line, max_line_bytes);
+            result.push_str(&format!(
+                "{:>6}\t{}...{}\n",
+                line_num,
+                shown,
+                truncation_note(line.len() - shown.len(), "bytes")
+            ));
         } else {
-            line
-        };
-
-        result.push_str(&format!("{:>6}\t{}\n", line_num, truncated_line));
+            result.push_str(&format!("{:>6}\t{}\n", line_num, line));
+        }
+        last_shown = line_num;
     }
 
     if result.is_empty() && line_num < offset {
@@ -617,12 +1277,188 @@ pub fn read_file(
         ));
     }
 
-    Ok(result)
+    if last_shown < line_num {
+        result.push_str(&format!(
+            "\n[Showing lines {}-{} of {}; truncated by limit, continue with offset={}]",
+            offset,
+            last_shown,
+            line_num,
+            last_shown + 1
+        ));
+    } else {
+        result.push_str(&format!(
+            "\n[Showing lines {}-{} of {}]",
+            offset, last_shown, line_num
+        ));
+    }
+    if lossy {
+        result.push_str("\n[Invalid UTF-8 was replaced with \u{FFFD}]");
+    }
+
+    Ok(ToolOutcome::text(result))
+}
+
+/// Most files one `read_many_files` call reads
+pub const READ_MANY_MAX_FILES: usize = 100;
+
+/// Lines `read_many_files` reads from each file unless told otherwise
+pub const READ_MANY_DEFAULT_LINES: usize = 200;
+
+/// Cap on the content one `read_many_files` call returns
+pub const READ_MANY_MAX_BYTES: usize = 200_000;
+
+/// One file's entry in the `read_many_files` result
+#[derive(Debug, serde::Serialize)]
+struct ReadManyEntry {
+    content: String,
+    truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl ReadManyEntry {
+    fn failed(error: String) -> Self {
+        ReadManyEntry {
+            content: String::new(),
+            truncated: false,
+            error: Some(error),
+        }
+    }
+}
+
+/// Read the first `limit` lines of a text file, reporting whether more followed
+fn read_head(paths: &PathValidator, path: &str, limit: usize) -> Result<(String, bool), String> {
+    let safe = paths.resolve(path)?;
+
+    if !safe.is_file() {
+        return Err(if safe.exists() {
+            format!("Not a file: {}", path)
+        } else {
+            format!("File not found: {}", path)
+        });
+    }
+
+    let file = fs::File::open(&safe).map_err(|e| format!("Failed to open file: {}", e))?;
+    let mut reader = BufReader::new(file);
+
+    let sniffed = reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    if looks_binary(&sniffed[..sniffed.len().min(BINARY_SNIFF_BYTES)]) {
+        return Err(format!(
+            "Binary file ({}) - not displaying contents",
+            guess_mime_type(&safe)
+        ));
+    }
+
+    let mut content = String::new();
+    let mut buf = Vec::new();
+    for _ in 0..limit {
+        buf.clear();
+        let read = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        if read == 0 {
+            return Ok((content, false));
+        }
+        content.push_str(&String::from_utf8_lossy(&buf));
+    }
+    let more = !reader
+        .fill_buf()
+        .map_err(|e| format!("Failed to read file: {}", e))?
+        .is_empty();
+    Ok((content, more))
+}
+
+/// Read the start of several files at once, as a JSON object of
+/// path -> `{content, truncated, error}`.
+///
+/// Each file contributes at most `limit` lines, and all content together at most
+/// `max_bytes` (capped at `READ_MANY_MAX_BYTES`). A file that can't be read,
+/// including one refused as sensitive, gets an `error` instead of failing the call.
+pub fn read_many_files(
+    paths: &PathValidator,
+    files: &[String],
+    limit: Option<usize>,
+    max_bytes: Option<usize>,
+    cancel: Option<&CancellationToken>,
+) -> Result<ToolOutcome, String> {
+    if files.is_empty() {
+        return Err("No files to read".to_string());
+    }
+    if files.len() > READ_MANY_MAX_FILES {
+        return Err(format!(
+            "{} files requested; read_many_files reads at most {} at a time",
+            files.len(),
+            READ_MANY_MAX_FILES
+        ));
+    }
+
+    let limit = limit.unwrap_or(READ_MANY_DEFAULT_LINES).max(1);
+    let max_bytes = max_bytes
+        .unwrap_or(READ_MANY_MAX_BYTES)
+        .min(READ_MANY_MAX_BYTES);
+    let budget_note = format!("[total output limit of {} bytes reached]", max_bytes);
+
+    let mut remaining = max_bytes;
+    let mut result = serde_json::Map::new();
+    for path in files {
+        check_cancelled(cancel)?;
+        if result.contains_key(path) {
+            continue;
+        }
+
+        let entry = if remaining == 0 {
+            ReadManyEntry {
+                content: String::new(),
+                truncated: true,
+                error: Some(format!("Not read: {}", budget_note)),
+            }
+        } else {
+            match read_head(paths, path, limit) {
+                Ok((content, _)) if content.len() > remaining => {
+                    let mut content = truncate_to_char_boundary(&content, remaining).to_string();
+                    remaining = 0;
+                    content.push('\n');
+                    content.push_str(&budget_note);
+                    ReadManyEntry {
+                        content,
+                        truncated: true,
+                        error: None,
+                    }
+                }
+                Ok((content, more)) => {
+                    remaining -= content.len();
+                    ReadManyEntry {
+                        content,
+                        truncated: more,
+                        error: None,
+                    }
+                }
+                Err(e) => ReadManyEntry::failed(e),
+            }
+        };
+        result.insert(
+            path.clone(),
+            serde_json::to_value(entry).unwrap_or_default(),
+        );
+    }
+
+    Ok(ToolOutcome::json(result))
 }
 
-/// Write content to a file
-pub fn write_file(workspace: &Path, path: &str, content: &str) -> Result<String, String> {
-    let safe = safe_path(workspace, path)?;
+/// Write content to a file, if it fits the validator's write limit
+pub fn write_file(paths: &PathValidator, path: &str, content: &str) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
+
+    let max_write_bytes = paths.file_limits().max_write_bytes;
+    if content.len() as u64 > max_write_bytes {
+        return Err(format!(
+            "Content is {}, limit is {}; write in smaller pieces or reconsider",
+            describe_size(content.len() as u64),
+            describe_size(max_write_bytes)
+        ));
+    }
 
     // Create parent directories if needed
     if let Some(parent) = safe.parent() {
@@ -632,14 +1468,18 @@ pub fn write_file(workspace: &Path, path: &str, content: &str) -> Result<String,
         }
     }
 
-    fs::write(&safe, content).map_err(|e| format!("Failed to write file: {}", e))?;
+    write_atomic(&safe, content.as_bytes()).map_err(|e| format!("Failed to write file: {}", e))?;
+    paths.invalidate(&safe);
 
-    Ok(format!("Wrote {} bytes to {}", content.len(), path))
+    Ok(
+        ToolOutcome::text(format!("Wrote {} bytes to {}", content.len(), path))
+            .wrote(path, content.len() as u64),
+    )
 }
 
 /// Delete a file (not directories)
-pub fn delete_file(workspace: &Path, path: &str) -> Result<String, String> {
-    let safe = safe_path(workspace, path)?;
+pub fn delete_file(paths: &PathValidator, path: &str) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
 
     if !safe.exists() {
         return Err(format!("File not found: {}", path));
@@ -650,15 +1490,127 @@ pub fn delete_file(workspace: &Path, path: &str) -> Result<String, String> {
     }
 
     fs::remove_file(&safe).map_err(|e| format!("Failed to delete file: {}", e))?;
+    paths.invalidate(&safe);
+
+    Ok(ToolOutcome::text(format!("Deleted {}", path)).touched(path))
+}
+
+/// Validate both ends of a move or copy, returning the resolved (source, destination)
+fn resolve_transfer(
+    paths: &PathValidator,
+    source: &str,
+    destination: &str,
+    overwrite: bool,
+) -> Result<(PathBuf, PathBuf), String> {
+    let from = paths.resolve(source)?;
+    let to = paths.resolve(destination)?;
+
+    if !from.exists() {
+        return Err(format!("File not found: {}", source));
+    }
+    if !from.is_file() {
+        return Err(format!(
+            "Not a file (directories are not supported): {}",
+            source
+        ));
+    }
+    if from == to {
+        return Err(format!(
+            "Source and destination are the same file: {}",
+            source
+        ));
+    }
+    if to.is_dir() {
+        return Err(format!("Destination is a directory: {}", destination));
+    }
+    if to.exists() && !overwrite {
+        return Err(format!(
+            "Destination already exists: {} (pass overwrite=true to replace it)",
+            destination
+        ));
+    }
+
+    if let Some(parent) = to.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directories: {}", e))?;
+        }
+    }
+    Ok((from, to))
+}
+
+/// Move (rename) a file. The rename is atomic on the same filesystem; across
+/// filesystems see [`move_across_filesystems`].
+pub fn move_file(
+    paths: &PathValidator,
+    source: &str,
+    destination: &str,
+    overwrite: bool,
+) -> Result<ToolOutcome, String> {
+    let (from, to) = resolve_transfer(paths, source, destination, overwrite)?;
+
+    if fs::rename(&from, &to).is_err() {
+        move_across_filesystems(&from, &to)?;
+    }
+    paths.invalidate(&from);
+    paths.invalidate(&to);
+
+    Ok(
+        ToolOutcome::text(format!("Moved {} to {}", source, destination))
+            .touched(source)
+            .touched(destination),
+    )
+}
 
-    Ok(format!("Deleted {}", path))
+/// Copy `from` to a temp file next to `to`, remove `from`, and only then rename
+/// the copy into place, so a destination being overwritten keeps its contents
+/// if the source can't be removed
+fn move_across_filesystems(from: &Path, to: &Path) -> Result<(), String> {
+    let temp = temp_path(to).map_err(|e| format!("Failed to move file: {}", e))?;
+    let discard = |e: std::io::Error| {
+        let _ = fs::remove_file(&temp);
+        format!("Failed to move file: {}", e)
+    };
+    fs::copy(from, &temp).map_err(discard)?;
+    fs::remove_file(from).map_err(discard)?;
+    fs::rename(&temp, to).map_err(|e| {
+        format!(
+            "Failed to move file: {} (the contents are in {})",
+            e,
+            temp.display()
+        )
+    })
 }
 
-/// Append content to a file (creates if doesn't exist)
-pub fn append_file(workspace: &Path, path: &str, content: &str) -> Result<String, String> {
-    use std::io::Write;
+/// Copy a file
+pub fn copy_file(
+    paths: &PathValidator,
+    source: &str,
+    destination: &str,
+    overwrite: bool,
+) -> Result<ToolOutcome, String> {
+    let (from, to) = resolve_transfer(paths, source, destination, overwrite)?;
+
+    let bytes = fs::copy(&from, &to).map_err(|e| format!("Failed to copy file: {}", e))?;
+    paths.invalidate(&to);
+
+    Ok(ToolOutcome::text(format!(
+        "Copied {} to {} ({} bytes)",
+        source, destination, bytes
+    ))
+    .wrote(destination, bytes))
+}
 
-    let safe = safe_path(workspace, path)?;
+/// Append content to a file (creates if doesn't exist).
+///
+/// The file is rewritten atomically with the content added, so an interrupted
+/// append can't leave a half-written tail. The result must fit the write limit.
+pub fn append_file(
+    paths: &PathValidator,
+    path: &str,
+    content: &str,
+) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
 
     // Create parent directories if needed
     if let Some(parent) = safe.parent() {
@@ -668,21 +1620,183 @@ pub fn append_file(workspace: &Path, path: &str, content: &str) -> Result<String
         }
     }
 
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&safe)
-        .map_err(|e| format!("Failed to open file for appending: {}", e))?;
+    // The limit is on the resulting file, so appending in a loop can't fill the disk
+    let existing = match fs::metadata(&safe) {
+        Ok(meta) => meta.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(format!("Failed to open file for appending: {}", e)),
+    };
+    let max_write_bytes = paths.file_limits().max_write_bytes;
+    if existing + content.len() as u64 > max_write_bytes {
+        return Err(format!(
+            "Appending {} would make {} {}, over the {} limit; \
+             start a new file or reconsider",
+            describe_size(content.len() as u64),
+            path,
+            describe_size(existing + content.len() as u64),
+            describe_size(max_write_bytes)
+        ));
+    }
+
+    let mut updated = match fs::read(&safe) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("Failed to open file for appending: {}", e)),
+    };
+    updated.extend_from_slice(content.as_bytes());
+
+    write_atomic(&safe, &updated).map_err(|e| format!("Failed to append to file: {}", e))?;
+    paths.invalidate(&safe);
+
+    Ok(
+        ToolOutcome::text(format!("Appended {} bytes to {}", content.len(), path))
+            .wrote(path, updated.len() as u64),
+    )
+}
+
+/// Replace `old_string` with `new_string` in a file.
+///
+/// The match must be unique unless `replace_all` is set. In a file with CRLF line
+/// endings, `\n` in either string is treated as `\r\n`, since read_file shows
+/// lines without their `\r`.
+pub fn edit_file(
+    paths: &PathValidator,
+    path: &str,
+    old_string: &str,
+    new_string: &str,
+    replace_all: bool,
+) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
+
+    if !safe.exists() {
+        return Err(format!("File not found: {}", path));
+    }
+
+    if !safe.is_file() {
+        return Err(format!("Not a file: {}", path));
+    }
+
+    if old_string.is_empty() {
+        return Err("old_string must not be empty".to_string());
+    }
+
+    let content = fs::read_to_string(&safe).map_err(|e| format!("Failed to read file: {}", e))?;
+    let (updated, count) = apply_edit(&content, path, old_string, new_string, replace_all)?;
+
+    write_atomic(&safe, updated.as_bytes()).map_err(|e| format!("Failed to write file: {}", e))?;
+    paths.invalidate(&safe);
+
+    Ok(ToolOutcome::text(format!(
+        "Made {} replacement{} in {}",
+        count,
+        if count == 1 { "" } else { "s" },
+        path
+    ))
+    .wrote(path, updated.len() as u64))
+}
+
+/// The content `edit_file` would write, and how many replacements it makes
+pub fn apply_edit(
+    content: &str,
+    path: &str,
+    old_string: &str,
+    new_string: &str,
+    replace_all: bool,
+) -> Result<(String, usize), String> {
+    let (old, new) = if content.contains("\r\n") && !old_string.contains('\r') {
+        (
+            old_string.replace('\n', "\r\n"),
+            new_string.replace("\r\n", "\n").replace('\n', "\r\n"),
+        )
+    } else {
+        (old_string.to_string(), new_string.to_string())
+    };
+
+    let count = content.matches(old.as_str()).count();
+    if count == 0 {
+        return Err(format!("old_string not found in {}", path));
+    }
+    if count > 1 && !replace_all {
+        return Err(format!(
+            "old_string found {} times in {}; add surrounding context to make it unique or set replace_all",
+            count, path
+        ));
+    }
+
+    let updated = if replace_all {
+        content.replace(old.as_str(), &new)
+    } else {
+        content.replacen(old.as_str(), &new, 1)
+    };
+    Ok((updated, count))
+}
+
+/// Deepest level `list_dir` descends to when `recursive` is set
+pub const LIST_DIR_MAX_DEPTH: usize = 4;
+
+/// Most entries a single `list_dir` call returns
+pub const LIST_DIR_MAX_ENTRIES: usize = 500;
+
+/// Files and directories one `list_dir` call visits to total up directory sizes
+const LIST_DIR_SIZE_SCAN_LIMIT: usize = 20_000;
+
+/// How `list_dir` walks and reports a directory
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListDirOptions {
+    /// Also list entries hidden by `.vswriteignore`
+    pub include_ignored: bool,
+    /// Descend into subdirectories, down to `LIST_DIR_MAX_DEPTH` levels
+    pub recursive: bool,
+    /// The old output: a JSON array of names, directories marked with a trailing `/`
+    pub flat: bool,
+}
+
+impl ListDirOptions {
+    /// Options from `list_dir` tool-call arguments
+    pub fn from_args(args: &serde_json::Value) -> Self {
+        let flag = |key: &str| args.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        ListDirOptions {
+            include_ignored: flag("include_ignored"),
+            recursive: flag("recursive"),
+            flat: flag("flat"),
+        }
+    }
+}
 
-    file.write_all(content.as_bytes())
-        .map_err(|e| format!("Failed to append to file: {}", e))?;
+/// One entry of a structured `list_dir` listing
+#[derive(Debug, serde::Serialize)]
+struct DirEntryInfo {
+    /// Path relative to the listed directory
+    name: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Bytes; for a directory, the files beneath it (null when too many to total)
+    size: Option<u64>,
+    /// Last modification time, ISO 8601 UTC
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extension: Option<String>,
+}
 
-    Ok(format!("Appended {} bytes to {}", content.len(), path))
+/// Entries collected so far by one `list_dir` call
+struct Listing {
+    options: ListDirOptions,
+    rules: IgnoreRules,
+    entries: Vec<DirEntryInfo>,
+    truncated: bool,
+    size_budget: usize,
 }
 
-/// List directory contents
-pub fn list_dir(workspace: &Path, path: &str) -> Result<String, String> {
-    let safe = safe_path(workspace, path)?;
+/// List a directory as JSON objects `{name, type, size, modified, extension}`,
+/// directories first. Entries hidden by `.vswriteignore` are left out unless
+/// `include_ignored` is set; notes about hidden entries or the entry limit
+/// follow the entries as `{"note": ...}` objects.
+pub fn list_dir(
+    paths: &PathValidator,
+    path: &str,
+    options: ListDirOptions,
+) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
 
     if !safe.exists() {
         return Err(format!("Directory not found: {}", path));
@@ -692,36 +1806,180 @@ pub fn list_dir(workspace: &Path, path: &str) -> Result<String, String> {
         return Err(format!("Not a directory: {}", path));
     }
 
-    let entries = fs::read_dir(&safe).map_err(|e| format!("Failed to read directory: {}", e))?;
+    let mut listing = Listing {
+        options,
+        rules: IgnoreRules::load(&paths.canonical_workspace()?),
+        entries: Vec::new(),
+        truncated: false,
+        size_budget: LIST_DIR_SIZE_SCAN_LIMIT,
+    };
+    collect_dir(&safe, "", 1, &mut listing)?;
+
+    let mut notes = Vec::new();
+    if listing.truncated {
+        notes.push(format!(
+            "stopped at {} entries; list a subdirectory to see more",
+            LIST_DIR_MAX_ENTRIES
+        ));
+    }
+    if let Some(note) = listing.rules.skipped_note() {
+        notes.push(note);
+    }
 
-    let mut files: Vec<String> = Vec::new();
-    let mut dirs: Vec<String> = Vec::new();
+    let listing = if options.flat {
+        let mut names: Vec<String> = listing
+            .entries
+            .into_iter()
+            .map(|e| match e.kind {
+                "dir" => format!("{}/", e.name),
+                _ => e.name,
+            })
+            .collect();
+        names.extend(notes.into_iter().map(|note| format!("({})", note)));
+        ToolOutcome::json(names)
+    } else {
+        let mut items: Vec<serde_json::Value> = listing
+            .entries
+            .iter()
+            .map(|e| serde_json::to_value(e).unwrap_or_default())
+            .collect();
+        items.extend(
+            notes
+                .into_iter()
+                .map(|note| serde_json::json!({ "note": note })),
+        );
+        ToolOutcome::json(items)
+    };
+    Ok(listing)
+}
 
+/// Add the entries of `dir` (directories first, each followed by its own
+/// entries when recursive) to `listing`
+fn collect_dir(
+    dir: &Path,
+    prefix: &str,
+    depth: usize,
+    listing: &mut Listing,
+) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read directory: {}", e))?;
+
+    let mut children = Vec::new();
     for entry in entries {
         let entry = entry.map_err(|e| format!("Error reading entry: {}", e))?;
+        let path = entry.path();
+        let is_link = entry.file_type().map(|t| t.is_symlink()).unwrap_or(false);
+        let metadata = fs::metadata(&path).or_else(|_| fs::symlink_metadata(&path));
+        let is_dir = metadata.as_ref().map(|m| m.is_dir()).unwrap_or(false);
+
+        if !listing.options.include_ignored && listing.rules.skip(&path, is_dir) {
+            continue;
+        }
         let name = entry.file_name().to_string_lossy().to_string();
+        children.push((name, path, is_dir, is_link, metadata.ok()));
+    }
+    children.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+    for (name, path, is_dir, is_link, metadata) in children {
+        if listing.entries.len() >= LIST_DIR_MAX_ENTRIES {
+            listing.truncated = true;
+            return Ok(());
+        }
 
-        if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
-            dirs.push(format!("{}/", name));
+        let name = format!("{}{}", prefix, name);
+        if listing.options.flat {
+            listing.entries.push(DirEntryInfo {
+                name: name.clone(),
+                kind: if is_dir { "dir" } else { "file" },
+                size: None,
+                modified: None,
+                extension: None,
+            });
         } else {
-            files.push(name);
+            let size = if is_dir {
+                dir_size(&path, &mut listing.size_budget)
+            } else {
+                metadata.as_ref().map(|m| m.len())
+            };
+            listing.entries.push(DirEntryInfo {
+                name: name.clone(),
+                kind: if is_dir { "dir" } else { "file" },
+                size,
+                modified: metadata.as_ref().and_then(|m| m.modified().ok()).map(|t| {
+                    chrono::DateTime::<chrono::Utc>::from(t)
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+                }),
+                extension: (!is_dir)
+                    .then(|| path.extension().map(|e| e.to_string_lossy().to_string()))
+                    .flatten(),
+            });
+        }
+
+        // Symlinked directories are listed but never followed
+        if is_dir && !is_link && listing.options.recursive && depth < LIST_DIR_MAX_DEPTH {
+            if let Err(e) = collect_dir(&path, &format!("{}/", name), depth + 1, listing) {
+                log::warn!("list_dir skipped {}: {}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Total size of the files beneath `dir` without following symlinks, or `None`
+/// once `budget` entries have been visited
+fn dir_size(dir: &Path, budget: &mut usize) -> Option<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+        let Ok(metadata) = fs::symlink_metadata(entry.path()) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            total += dir_size(&entry.path(), budget)?;
+        } else if metadata.is_file() {
+            total += metadata.len();
         }
     }
+    Some(total)
+}
+
+/// Most paths glob returns by default
+pub const GLOB_MAX_ENTRIES: usize = 500;
 
-    // Sort for consistent output
-    dirs.sort();
-    files.sort();
+/// Find files matching a glob pattern
+pub fn glob_files(
+    paths: &PathValidator,
+    pattern: &str,
+    base_path: &str,
+    cancel: Option<&CancellationToken>,
+) -> Result<ToolOutcome, String> {
+    let (mut matches, rules) = glob_matches(paths, pattern, base_path, cancel)?;
 
-    // Combine: directories first, then files
-    let mut result: Vec<String> = dirs;
-    result.extend(files);
+    // Limit results to prevent overwhelming output
+    let max_entries = paths.tool_limits().glob_max_entries;
+    if matches.len() > max_entries {
+        let omitted = matches.len() - max_entries;
+        matches.truncate(max_entries);
+        matches.push(truncation_note(omitted, "files"));
+    }
+    if let Some(note) = rules.skipped_note() {
+        matches.push(format!("({})", note));
+    }
 
-    Ok(serde_json::to_string_pretty(&result).unwrap_or_else(|_| format!("{:?}", result)))
+    Ok(ToolOutcome::json(matches))
 }
 
-/// Find files matching a glob pattern
-pub fn glob_files(workspace: &Path, pattern: &str, base_path: &str) -> Result<String, String> {
-    let safe_base = safe_path(workspace, base_path)?;
+/// Sorted workspace-relative paths matching `pattern` under `base_path`, leaving
+/// out `.vswriteignore`d paths (counted in the returned rules)
+fn glob_matches(
+    paths: &PathValidator,
+    pattern: &str,
+    base_path: &str,
+    cancel: Option<&CancellationToken>,
+) -> Result<(Vec<String>, IgnoreRules), String> {
+    let safe_base = paths.resolve(base_path)?;
 
     if !safe_base.exists() {
         return Err(format!("Base path not found: {}", base_path));
@@ -732,16 +1990,18 @@ pub fn glob_files(workspace: &Path, pattern: &str, base_path: &str) -> Result<St
     let pattern_str = full_pattern.to_string_lossy();
 
     let mut matches: Vec<String> = Vec::new();
-    let canonical_workspace = workspace
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize workspace: {}", e))?;
+    let canonical_workspace = paths.canonical_workspace()?;
+    let rules = IgnoreRules::load(&canonical_workspace);
 
     for entry in glob::glob(&pattern_str).map_err(|e| format!("Invalid glob pattern: {}", e))? {
+        check_cancelled(cancel)?;
         match entry {
             Ok(path) => {
                 // Ensure path is within workspace
                 if let Ok(canonical) = path.canonicalize() {
-                    if canonical.starts_with(&canonical_workspace) {
+                    if canonical.starts_with(&canonical_workspace)
+                        && !rules.skip(&canonical, canonical.is_dir())
+                    {
                         // Return relative path
                         if let Ok(relative) = canonical.strip_prefix(&canonical_workspace) {
                             matches.push(relative.to_string_lossy().to_string());
@@ -756,173 +2016,669 @@ pub fn glob_files(workspace: &Path, pattern: &str, base_path: &str) -> Result<St
     }
 
     matches.sort();
+    Ok((matches, rules))
+}
 
-    // Limit results to prevent overwhelming output
-    if matches.len() > 500 {
-        let total = matches.len();
-        matches.truncate(500);
-        matches.push(format!("... and {} more files", total - 500));
-    }
-
-    Ok(serde_json::to_string_pretty(&matches).unwrap_or_else(|_| format!("{:?}", matches)))
+/// Text-like files grep searches: known text extensions, or no extension at all
+fn is_searchable_file(path: &Path) -> bool {
+    let Some(ext) = path.extension() else {
+        return true;
+    };
+    matches!(
+        ext.to_string_lossy().to_lowercase().as_str(),
+        "txt"
+            | "md"
+            | "rs"
+            | "py"
+            | "js"
+            | "ts"
+            | "tsx"
+            | "jsx"
+            | "json"
+            | "yaml"
+            | "yml"
+            | "toml"
+            | "html"
+            | "css"
+            | "scss"
+            | "vue"
+            | "svelte"
+    )
 }
 
-/// Search file contents for a pattern
-pub fn grep_files(workspace: &Path, pattern: &str, path: &str) -> Result<String, String> {
-    let safe = safe_path(workspace, path)?;
+/// Most matches grep returns in total by default
+pub const GREP_MAX_MATCHES: usize = 100;
 
-    if !safe.exists() {
-        return Err(format!("Path not found: {}", path));
-    }
+/// Most matches grep shows from one file; the rest are counted in a note
+pub const GREP_MAX_FILE_MATCHES: usize = 25;
 
-    let canonical_workspace = workspace
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize workspace: {}", e))?;
+/// Bytes of line content (matches and context) grep returns in total
+pub const GREP_MAX_BYTES: usize = 20_000;
 
-    let mut results: Vec<serde_json::Value> = Vec::new();
-    let pattern_lower = pattern.to_lowercase();
+/// Most files a `files_only` grep lists
+pub const GREP_MAX_FILES: usize = 500;
 
-    fn search_file(
-        file_path: &Path,
-        pattern: &str,
-        workspace: &Path,
-        results: &mut Vec<serde_json::Value>,
-    ) -> Result<(), String> {
-        let file = match fs::File::open(file_path) {
-            Ok(f) => f,
-            Err(_) => return Ok(()), // Skip files we can't open
-        };
+/// Most context lines grep shows on either side of a match
+pub const GREP_MAX_CONTEXT: usize = 5;
 
-        let reader = BufReader::new(file);
+/// Longest line grep shows before cutting it off
+const GREP_LINE_CHARS: usize = 200;
+
+/// Options for `grep_files`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GrepOptions {
+    /// Lines shown before each match (capped at `GREP_MAX_CONTEXT`)
+    pub context_before: usize,
+    /// Lines shown after each match (capped at `GREP_MAX_CONTEXT`)
+    pub context_after: usize,
+    /// List matching files with match counts instead of the matches
+    pub files_only: bool,
+}
+
+impl GrepOptions {
+    /// Options from `grep` tool-call arguments
+    pub fn from_args(args: &serde_json::Value) -> Self {
+        let lines = |key: &str| args.get(key).and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+        GrepOptions {
+            context_before: lines("context_before"),
+            context_after: lines("context_after"),
+            files_only: args
+                .get("files_only")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+        }
+        .clamped()
+    }
+
+    fn clamped(self) -> Self {
+        GrepOptions {
+            context_before: self.context_before.min(GREP_MAX_CONTEXT),
+            context_after: self.context_after.min(GREP_MAX_CONTEXT),
+            ..self
+        }
+    }
+
+    fn has_context(&self) -> bool {
+        self.context_before > 0 || self.context_after > 0
+    }
+}
+
+/// A run of consecutive lines around one or more matches
+struct GrepBlock {
+    start: usize,
+    lines: Vec<String>,
+    matches: Vec<usize>,
+}
+
+impl GrepBlock {
+    fn end(&self) -> usize {
+        self.start + self.lines.len() - 1
+    }
+}
+
+/// Results collected so far by one `grep_files` call
+struct GrepSearch {
+    pattern: String,
+    options: GrepOptions,
+    max_matches: usize,
+    results: Vec<serde_json::Value>,
+    matches: usize,
+    files: usize,
+    bytes: usize,
+    /// Why the search stopped early
+    truncated: Option<String>,
+    /// Matches (or with `files_only`, files) found but not shown
+    omitted: usize,
+}
+
+impl GrepSearch {
+    fn new(pattern: &str, options: GrepOptions, max_matches: usize) -> Self {
+        GrepSearch {
+            pattern: pattern.to_lowercase(),
+            options: options.clamped(),
+            max_matches,
+            results: Vec::new(),
+            matches: 0,
+            files: 0,
+            bytes: 0,
+            truncated: None,
+            omitted: 0,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.truncated.is_some()
+    }
+
+    fn search_file(
+        &mut self,
+        file_path: &Path,
+        workspace: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<(), String> {
+        let file = match fs::File::open(file_path) {
+            Ok(f) => f,
+            Err(_) => return Ok(()), // Skip files we can't open
+        };
         let relative_path = file_path
             .strip_prefix(workspace)
             .unwrap_or(file_path)
             .to_string_lossy()
             .to_string();
 
-        for (line_num, line_result) in reader.lines().enumerate() {
-            if let Ok(line) = line_result {
-                if line.to_lowercase().contains(pattern) {
-                    results.push(serde_json::json!({
-                        "file": relative_path,
-                        "line": line_num + 1,
-                        "content": if line.len() > 200 {
-                            format!("{}...", &line[..200])
-                        } else {
-                            line
-                        }
-                    }));
+        let context_before = self.options.context_before;
+        let mut before: VecDeque<(usize, String)> = VecDeque::new();
+        let mut block: Option<GrepBlock> = None;
+        let mut after_left = 0;
+        let mut file_matches = 0;
+        let mut shown = 0;
+
+        for (index, line_result) in BufReader::new(file).lines().enumerate() {
+            if index % GREP_CANCEL_CHECK_LINES == 0 {
+                check_cancelled(cancel)?;
+            }
+            let Ok(line) = line_result else { continue };
+            let line_num = index + 1;
+            let is_match = line.to_lowercase().contains(&self.pattern);
+            if is_match {
+                file_matches += 1;
+            }
+            if self.options.files_only {
+                continue;
+            }
 
-                    // Limit matches per file
-                    if results.len() >= 100 {
-                        return Ok(());
+            let showing = is_match && !self.is_full() && shown < GREP_MAX_FILE_MATCHES;
+            if showing {
+                // A match within reach of the open block extends it
+                let adjoins = self.options.has_context()
+                    && block.as_ref().is_some_and(|b| {
+                        b.end() + 1 == before.front().map_or(line_num, |(n, _)| *n)
+                    });
+                if !adjoins {
+                    if let Some(done) = block.take() {
+                        self.push_block(&relative_path, done);
                     }
                 }
+                let open = block.get_or_insert_with(|| GrepBlock {
+                    start: before.front().map_or(line_num, |(n, _)| *n),
+                    lines: Vec::new(),
+                    matches: Vec::new(),
+                });
+                open.lines.extend(before.drain(..).map(|(_, text)| text));
+                open.lines.push(line);
+                open.matches.push(line_num);
+                after_left = self.options.context_after;
+                shown += 1;
+                self.matches += 1;
+                if self.matches >= self.max_matches {
+                    self.truncated =
+                        Some(format!("Results truncated at {} matches", self.max_matches));
+                }
+            } else if after_left > 0 && block.is_some() {
+                if let Some(open) = block.as_mut() {
+                    open.lines.push(line);
+                }
+                after_left -= 1;
+            } else if context_before > 0 && !self.is_full() && shown < GREP_MAX_FILE_MATCHES {
+                before.push_back((line_num, line));
+                if before.len() > context_before {
+                    before.pop_front();
+                }
             }
+
+            // Close a block the next match can no longer reach
+            let stale = block
+                .as_ref()
+                .is_some_and(|b| after_left == 0 && line_num > b.end() + context_before);
+            if stale {
+                if let Some(done) = block.take() {
+                    self.push_block(&relative_path, done);
+                }
+            }
+        }
+        if let Some(done) = block.take() {
+            self.push_block(&relative_path, done);
         }
 
+        if self.options.files_only {
+            if file_matches > 0 && self.is_full() {
+                self.omitted += 1;
+            } else if file_matches > 0 {
+                self.results.push(serde_json::json!({
+                    "file": relative_path,
+                    "matches": file_matches,
+                }));
+                self.files += 1;
+                if self.files >= GREP_MAX_FILES {
+                    self.truncated = Some(format!("Results truncated at {} files", GREP_MAX_FILES));
+                }
+            }
+        } else if file_matches > shown {
+            self.omitted += file_matches - shown;
+            if shown > 0 {
+                self.results.push(serde_json::json!({
+                    "file": relative_path,
+                    "note": format!("{} more matches in this file not shown", file_matches - shown),
+                }));
+            }
+        }
         Ok(())
     }
 
-    fn search_dir(
-        dir_path: &Path,
-        pattern: &str,
-        workspace: &Path,
-        results: &mut Vec<serde_json::Value>,
-    ) -> Result<(), String> {
-        if results.len() >= 100 {
-            return Ok(());
+    /// Add a finished block, as a plain match when there's no context
+    fn push_block(&mut self, file: &str, block: GrepBlock) {
+        let shorten = |line: &str| {
+            if line.len() > GREP_LINE_CHARS {
+                format!("{}...", truncate_to_char_boundary(line, GREP_LINE_CHARS))
+            } else {
+                line.to_string()
+            }
+        };
+
+        if !self.options.has_context() {
+            let content = shorten(&block.lines[0]);
+            self.bytes += content.len();
+            self.results.push(serde_json::json!({
+                "file": file,
+                "line": block.start,
+                "content": content,
+            }));
+        } else {
+            let end = block.end();
+            let content = block
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(offset, line)| {
+                    let line_num = block.start + offset;
+                    let marker = if block.matches.contains(&line_num) {
+                        ':'
+                    } else {
+                        '-'
+                    };
+                    format!("{}{}{}", line_num, marker, shorten(line))
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            self.bytes += content.len();
+            self.results.push(serde_json::json!({
+                "file": file,
+                "start": block.start,
+                "end": end,
+                "matches": block.matches,
+                "content": content,
+            }));
         }
 
-        let entries = match fs::read_dir(dir_path) {
-            Ok(e) => e,
-            Err(_) => return Ok(()), // Skip directories we can't read
-        };
+        if self.bytes >= GREP_MAX_BYTES && self.truncated.is_none() {
+            self.truncated = Some(format!(
+                "Results truncated at {} bytes; search a narrower path or use files_only",
+                GREP_MAX_BYTES
+            ));
+        }
+    }
+}
 
-        for entry in entries {
-            if results.len() >= 100 {
-                break;
-            }
+/// Search file contents for a pattern (case-insensitive substring).
+///
+/// Matches are `{file, line, content}` objects. With context lines they are
+/// `{file, start, end, matches, content}` blocks instead, where overlapping
+/// windows are merged and each content line is prefixed `N:` for a match or
+/// `N-` for context. `files_only` returns `{file, matches}` counts. Notes about
+/// caps and hidden paths follow as `{"note": ...}` objects.
+pub fn grep_files(
+    paths: &PathValidator,
+    pattern: &str,
+    path: &str,
+    options: GrepOptions,
+    cancel: Option<&CancellationToken>,
+) -> Result<ToolOutcome, String> {
+    let safe = paths.resolve(path)?;
 
-            if let Ok(entry) = entry {
-                let path = entry.path();
-
-                // Skip hidden files and common non-text directories
-                let name = entry.file_name().to_string_lossy().to_string();
-                if name.starts_with('.')
-                    || name == "node_modules"
-                    || name == "target"
-                    || name == "__pycache__"
-                    || name == ".git"
-                {
-                    continue;
-                }
+    if !safe.exists() {
+        return Err(format!("Path not found: {}", path));
+    }
 
-                if path.is_dir() {
-                    search_dir(&path, pattern, workspace, results)?;
-                } else if path.is_file() {
-                    // Only search text-like files
-                    if let Some(ext) = path.extension() {
-                        let ext = ext.to_string_lossy().to_lowercase();
-                        if matches!(
-                            ext.as_str(),
-                            "txt"
-                                | "md"
-                                | "rs"
-                                | "py"
-                                | "js"
-                                | "ts"
-                                | "tsx"
-                                | "jsx"
-                                | "json"
-                                | "yaml"
-                                | "yml"
-                                | "toml"
-                                | "html"
-                                | "css"
-                                | "scss"
-                                | "vue"
-                                | "svelte"
-                        ) {
-                            search_file(&path, pattern, workspace, results)?;
-                        }
-                    } else {
-                        // No extension - might be a text file, try it
-                        search_file(&path, pattern, workspace, results)?;
-                    }
-                }
+    let canonical_workspace = paths.canonical_workspace()?;
+    let mut search = GrepSearch::new(pattern, options, paths.tool_limits().grep_max_matches);
+
+    if safe.is_file() {
+        search.search_file(&safe, &canonical_workspace, cancel)?;
+    } else {
+        let rules = Arc::new(IgnoreRules::load(&canonical_workspace));
+        let walk_rules = rules.clone();
+        let walker = WalkBuilder::new(&safe)
+            .standard_filters(false)
+            .hidden(true)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .filter_entry(move |entry| {
+                let name = entry.file_name().to_string_lossy();
+                let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+                // Skip common non-text directories, then the workspace's own rules
+                (!is_dir || !matches!(name.as_ref(), "node_modules" | "target" | "__pycache__"))
+                    && !walk_rules.skip(entry.path(), is_dir)
+            })
+            .build();
+
+        // A full search keeps walking to count what it leaves out
+        for entry in walker {
+            check_cancelled(cancel)?;
+
+            // Skip entries we can't read
+            let Ok(entry) = entry else { continue };
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            if is_searchable_file(entry.path()) {
+                search.search_file(entry.path(), &canonical_workspace, cancel)?;
             }
         }
 
-        Ok(())
+        if let Some(note) = rules.skipped_note() {
+            search.results.push(serde_json::json!({ "note": note }));
+        }
     }
 
-    if safe.is_file() {
-        search_file(&safe, &pattern_lower, &canonical_workspace, &mut results)?;
+    let mut results = search.results;
+    let unit = if search.options.files_only {
+        "files"
     } else {
-        search_dir(&safe, &pattern_lower, &canonical_workspace, &mut results)?;
+        "matches"
+    };
+    let omitted = (search.omitted > 0).then(|| truncation_note(search.omitted, unit));
+    match (search.truncated, omitted) {
+        (Some(reason), Some(omitted)) => {
+            results.push(serde_json::json!({ "note": format!("{}. {}", reason, omitted) }))
+        }
+        (Some(note), None) | (None, Some(note)) => {
+            results.push(serde_json::json!({ "note": note }))
+        }
+        (None, None) => {}
+    }
+
+    Ok(ToolOutcome::json(results))
+}
+
+/// Files listed individually by workspace_stats; totals still cover everything matched
+const STATS_MAX_FILES: usize = 200;
+
+/// Files larger than this are left out of workspace_stats
+const STATS_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Word, character, and line counts for one piece of text
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct TextStats {
+    pub words: usize,
+    pub characters: usize,
+    pub lines: usize,
+}
+
+impl TextStats {
+    /// Count `text`, leaving out a leading YAML frontmatter block
+    pub fn of(text: &str) -> Self {
+        let body = strip_frontmatter(text);
+        TextStats {
+            words: body.split_whitespace().count(),
+            characters: body.chars().count(),
+            lines: body.lines().count(),
+        }
     }
 
-    if results.len() >= 100 {
-        results.push(serde_json::json!({
-            "note": "Results truncated at 100 matches"
-        }));
+    fn add(&mut self, other: TextStats) {
+        self.words += other.words;
+        self.characters += other.characters;
+        self.lines += other.lines;
     }
+}
 
-    Ok(serde_json::to_string_pretty(&results).unwrap_or_else(|_| format!("{:?}", results)))
+/// `text` without a leading `---` frontmatter block
+fn strip_frontmatter(text: &str) -> &str {
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return text;
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim_end() == "---" {
+            return &rest[offset..];
+        }
+    }
+    // Unterminated: not frontmatter after all
+    text
 }
 
-/// Execute a shell command
+/// Word, character, and line counts for the files `target` names: a file, a
+/// directory (its markdown files), or a glob relative to the workspace
+pub fn workspace_stats(
+    paths: &PathValidator,
+    target: &str,
+    cancel: Option<&CancellationToken>,
+) -> Result<ToolOutcome, String> {
+    let canonical_workspace = paths.canonical_workspace()?;
+    let rules = IgnoreRules::load(&canonical_workspace);
+
+    let is_glob = target.contains(['*', '?', '[']);
+    let files: Vec<PathBuf> = if is_glob {
+        // Matches outside the workspace are dropped below
+        let pattern = paths.workspace().join(target);
+        glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| format!("Invalid glob pattern: {}", e))?
+            .filter_map(Result::ok)
+            .filter_map(|p| p.canonicalize().ok())
+            .filter(|p| p.starts_with(&canonical_workspace) && p.is_file())
+            .collect()
+    } else {
+        let safe = paths.resolve(target)?;
+        if safe.is_file() {
+            vec![safe]
+        } else if safe.is_dir() {
+            let pattern = safe.join("**").join("*.md");
+            glob::glob(&pattern.to_string_lossy())
+                .map_err(|e| format!("Invalid glob pattern: {}", e))?
+                .filter_map(Result::ok)
+                .filter(|p| p.is_file())
+                .collect()
+        } else {
+            return Err(format!("Path not found: {}", target));
+        }
+    };
+
+    let mut entries = Vec::new();
+    let mut totals = TextStats::default();
+    let mut skipped_unreadable = 0;
+    for file in files {
+        check_cancelled(cancel)?;
+        if rules.skip(&file, false) {
+            continue;
+        }
+        let too_big = fs::metadata(&file).map_or(true, |m| m.len() > STATS_MAX_FILE_BYTES);
+        let content = if too_big {
+            None
+        } else {
+            fs::read_to_string(&file).ok()
+        };
+        let Some(content) = content else {
+            skipped_unreadable += 1;
+            continue;
+        };
+
+        let stats = TextStats::of(&content);
+        totals.add(stats);
+        let relative = file
+            .strip_prefix(&canonical_workspace)
+            .unwrap_or(&file)
+            .to_string_lossy()
+            .to_string();
+        entries.push((relative, stats));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let file_count = entries.len();
+    let listed: Vec<serde_json::Value> = entries
+        .into_iter()
+        .take(STATS_MAX_FILES)
+        .map(|(path, stats)| {
+            serde_json::json!({
+                "path": path,
+                "words": stats.words,
+                "characters": stats.characters,
+                "lines": stats.lines,
+            })
+        })
+        .collect();
+
+    let mut result = serde_json::json!({
+        "files": listed,
+        "totals": {
+            "files": file_count,
+            "words": totals.words,
+            "characters": totals.characters,
+            "lines": totals.lines,
+        },
+    });
+    let mut notes = Vec::new();
+    if file_count > STATS_MAX_FILES {
+        notes.push(format!(
+            "Listed the first {} of {} files; totals include all of them",
+            STATS_MAX_FILES, file_count
+        ));
+    }
+    if skipped_unreadable > 0 {
+        notes.push(format!(
+            "{} file(s) skipped as binary, unreadable, or over {} MB",
+            skipped_unreadable,
+            STATS_MAX_FILE_BYTES / (1024 * 1024)
+        ));
+    }
+    notes.extend(rules.skipped_note());
+    if !notes.is_empty() {
+        result["notes"] = serde_json::json!(notes);
+    }
+
+    Ok(ToolOutcome::json(result))
+}
+
+// ============================================================================
+// Shell Process Tracking
+// ============================================================================
+
+/// PIDs of shell commands that are currently running.
+///
+/// On Unix each command is started in its own process group (pgid == pid), so killing
+/// the group also takes down anything the command spawned.
+static RUNNING_SHELLS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Removes a shell PID from `RUNNING_SHELLS` when the command finishes.
+struct ShellProcessGuard {
+    pid: u32,
+}
+
+impl ShellProcessGuard {
+    fn register(pid: u32) -> Self {
+        if let Ok(mut pids) = RUNNING_SHELLS.lock() {
+            pids.push(pid);
+        }
+        Self { pid }
+    }
+}
+
+impl Drop for ShellProcessGuard {
+    fn drop(&mut self) {
+        if let Ok(mut pids) = RUNNING_SHELLS.lock() {
+            pids.retain(|p| *p != self.pid);
+        }
+    }
+}
+
+/// Kill a shell process together with its children
+fn kill_process_tree(pid: u32) -> bool {
+    let status = if cfg!(target_os = "windows") {
+        let mut taskkill = Command::new("taskkill");
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+            taskkill.creation_flags(CREATE_NO_WINDOW);
+        }
+        taskkill
+            .args(["/F", "/T", "/PID", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    } else {
+        Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", pid)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    };
+
+    status.map(|s| s.success()).unwrap_or(false)
+}
+
+/// Kill every shell command that is still running.
+///
+/// Called during app shutdown after agent runs have had a chance to stop on their own.
+/// Returns the number of process trees that were killed.
+pub fn kill_running_shells() -> usize {
+    let pids: Vec<u32> = match RUNNING_SHELLS.lock() {
+        Ok(pids) => pids.clone(),
+        Err(_) => return 0,
+    };
+
+    let mut killed = 0;
+    for pid in pids {
+        if kill_process_tree(pid) {
+            log::info!("Killed shell process {} during shutdown", pid);
+            killed += 1;
+        }
+    }
+    killed
+}
+
+/// Execute a shell command under `settings` (its own timeout aside). Output
+/// lines are passed to `settings.output` as they arrive.
 pub fn run_shell(
-    workspace: &Path,
+    paths: &PathValidator,
     command: &str,
     cwd: Option<&str>,
     timeout_secs: Option<u64>,
-) -> Result<String, String> {
+    settings: &ShellSettings,
+    cancel: Option<&CancellationToken>,
+) -> Result<ToolOutcome, String> {
+    check_cancelled(cancel)?;
+    settings.policy.check(command)?;
+
+    let working_dir = shell_working_dir(paths, cwd)?;
+    let vars = ShellEnv::inherited(&settings.env_allowlist);
+    let child = shell_command(command, &working_dir, &vars, settings.shell.as_deref())?;
+
+    let timeout = Duration::from_secs(timeout_secs.unwrap_or(30).min(60));
+    let max_output_bytes = paths.tool_limits().shell_output_bytes;
+    let result = execute_shell(
+        child,
+        vars.note(),
+        timeout,
+        max_output_bytes,
+        cancel,
+        settings.output.as_ref(),
+    );
+
+    // The command may have created, removed or re-linked anything in the workspace
+    paths.clear();
+
+    result
+}
+
+/// Directory run_shell runs in: `cwd` inside the workspace, or the workspace itself
+pub fn shell_working_dir(paths: &PathValidator, cwd: Option<&str>) -> Result<PathBuf, String> {
     let working_dir = if let Some(c) = cwd {
-        safe_path(workspace, c)?
+        paths.resolve(c)?
     } else {
-        workspace.to_path_buf()
+        paths.workspace().to_path_buf()
     };
 
     if !working_dir.exists() || !working_dir.is_dir() {
@@ -932,143 +2688,662 @@ pub fn run_shell(
         ));
     }
 
-    let timeout = Duration::from_secs(timeout_secs.unwrap_or(30).min(60));
+    Ok(working_dir)
+}
+
+/// Separator between entries of the PATH variable
+const PATH_LIST_SEPARATOR: &str = if cfg!(target_os = "windows") {
+    ";"
+} else {
+    ":"
+};
+
+/// The environment run_shell starts commands in
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ShellEnvironment {
+    /// Shell binary commands are passed to
+    pub shell: String,
+    /// Flags that make the shell run the next argument as a command, space-separated
+    pub shell_arg: String,
+    /// PATH entries in lookup order
+    pub path: Vec<String>,
+    /// The home directory as seen by the agent (USERPROFILE on Windows), if set
+    pub home: Option<String>,
+    /// Names of the variables commands get
+    pub env: Vec<String>,
+    /// Allow-listed variables held back because they look like credentials
+    pub scrubbed_env: Vec<String>,
+    /// Directory commands run in
+    pub working_dir: String,
+}
 
-    // Use appropriate shell based on platform
-    let (shell, shell_arg) = if cfg!(target_os = "windows") {
-        ("cmd", "/C")
+impl ShellEnvironment {
+    /// The environment for a command run by `shell` (the platform default if
+    /// `None`) in `working_dir` with the variables in `vars`, PATH built from
+    /// the app's own
+    pub fn for_working_dir(
+        working_dir: &Path,
+        vars: &ShellEnv,
+        shell: Option<&str>,
+    ) -> Result<Self, String> {
+        let home = shell_home();
+        let path = shell_path(home.as_deref());
+        let program = ShellProgram::resolve(shell, &path)?;
+
+        Ok(ShellEnvironment {
+            shell: program.program.clone(),
+            shell_arg: program.flags().join(" "),
+            path,
+            home,
+            env: vars.names(),
+            scrubbed_env: vars.scrubbed.clone(),
+            working_dir: working_dir.display().to_string(),
+        })
+    }
+}
+
+/// The home directory commands see: HOME, or USERPROFILE on Windows
+fn shell_home() -> Option<String> {
+    let name = if cfg!(target_os = "windows") {
+        "USERPROFILE"
     } else {
-        ("sh", "-c")
+        "HOME"
     };
+    std::env::var(name).ok()
+}
+
+/// PATH entries commands run with, from the app's PATH
+fn shell_path(home: Option<&str>) -> Vec<String> {
+    augmented_path(&std::env::var("PATH").unwrap_or_default(), home)
+}
 
-    let mut cmd = Command::new(shell);
-    cmd.arg(shell_arg)
-        .arg(command)
-        .current_dir(&working_dir)
+/// The process run_shell starts: `command` in `shell` (the platform default if
+/// `None`), in `working_dir`, with only the variables in `vars` and PATH
+fn shell_command(
+    command: &str,
+    working_dir: &Path,
+    vars: &ShellEnv,
+    shell: Option<&str>,
+) -> Result<Command, String> {
+    let path = shell_path(shell_home().as_deref());
+    let program = ShellProgram::resolve(shell, &path)?;
+
+    let mut cmd = program.command(command);
+    cmd.current_dir(working_dir)
+        .env_clear()
+        .envs(vars.vars.iter().map(|(name, value)| (name, value)))
+        .env("PATH", path.join(PATH_LIST_SEPARATOR))
+        .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
 
-    // On macOS (especially when the app is launched from Finder), PATH is often minimal and
-    // won't include Homebrew locations like /opt/homebrew/bin. Add common locations to improve
-    // cross-platform usability without relying on shell init files.
-    if !cfg!(target_os = "windows") {
-        let mut entries: Vec<String> = std::env::var("PATH")
-            .unwrap_or_default()
-            .split(':')
-            .filter(|s| !s.is_empty())
-            .map(|s| s.to_string())
-            .collect();
+    // Run in a dedicated process group so a timeout, cancel, or shutdown can kill
+    // the whole tree, not just the shell
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+    // No console window, and no Ctrl+C from the app's console
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        cmd.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW);
+    }
+
+    Ok(cmd)
+}
 
-        let mut extra: Vec<String> = Vec::new();
+/// Well-known tool locations run_shell adds to the inherited PATH, in order
+fn shell_path_extras(home: Option<&str>) -> Vec<String> {
+    let mut extra: Vec<String> = Vec::new();
 
-        if let Ok(home) = std::env::var("HOME") {
-            extra.push(format!("{}/.cargo/bin", home));
-            extra.push(format!("{}/.local/bin", home));
+    if cfg!(target_os = "windows") {
+        if let Some(home) = home {
+            extra.push(format!(r"{}\.cargo\bin", home));
+            extra.push(format!(r"{}\scoop\shims", home));
+            extra.push(format!(r"{}\AppData\Local\Microsoft\WindowsApps", home));
         }
+        extra.push(r"C:\Program Files\PowerShell\7".to_string());
+        extra.push(r"C:\Program Files\Git\cmd".to_string());
+        extra.push(r"C:\Program Files\nodejs".to_string());
+        return extra;
+    }
 
-        if cfg!(target_os = "macos") {
-            extra.push("/opt/homebrew/bin".to_string());
-            extra.push("/opt/homebrew/sbin".to_string());
-            extra.push("/usr/local/bin".to_string());
-            extra.push("/usr/local/sbin".to_string());
+    if let Some(home) = home {
+        extra.push(format!("{}/.cargo/bin", home));
+        extra.push(format!("{}/.local/bin", home));
+    }
+
+    if cfg!(target_os = "macos") {
+        extra.push("/opt/homebrew/bin".to_string());
+        extra.push("/opt/homebrew/sbin".to_string());
+    }
+    extra.push("/usr/local/bin".to_string());
+    extra.push("/usr/local/sbin".to_string());
+
+    // Always include standard system locations as a fallback.
+    extra.push("/usr/bin".to_string());
+    extra.push("/bin".to_string());
+    extra.push("/usr/sbin".to_string());
+    extra.push("/sbin".to_string());
+
+    extra
+}
+
+/// PATH entries run_shell uses, given the inherited PATH and home directory.
+///
+/// On macOS (especially when the app is launched from Finder), PATH is often minimal and
+/// won't include Homebrew locations like /opt/homebrew/bin. Common locations missing from
+/// PATH are prepended, in order, to improve cross-platform usability without relying on
+/// shell init files. Entries already on PATH keep their position; duplicates are dropped.
+/// On Windows, where PATH comes from the registry and is rarely this sparse, the
+/// missing locations go after it instead, as a fallback, and entries compare
+/// case-insensitively.
+pub fn augmented_path(path_var: &str, home: Option<&str>) -> Vec<String> {
+    let same = |a: &str, b: &str| {
+        if cfg!(target_os = "windows") {
+            a.eq_ignore_ascii_case(b)
         } else {
-            extra.push("/usr/local/bin".to_string());
-            extra.push("/usr/local/sbin".to_string());
+            a == b
+        }
+    };
+    let mut entries: Vec<String> = Vec::new();
+    for entry in path_var.split(PATH_LIST_SEPARATOR) {
+        if !entry.is_empty() && !entries.iter().any(|p| same(p, entry)) {
+            entries.push(entry.to_string());
+        }
+    }
+
+    let missing: Vec<String> = shell_path_extras(home)
+        .into_iter()
+        .filter(|extra| !entries.iter().any(|p| same(p, extra)))
+        .collect();
+
+    if cfg!(target_os = "windows") {
+        entries.into_iter().chain(missing).collect()
+    } else {
+        missing.into_iter().chain(entries).collect()
+    }
+}
+
+/// Lines of stdout run_shell keeps
+const SHELL_STDOUT_LINES: usize = 500;
+
+/// Lines of stderr run_shell keeps
+const SHELL_STDERR_LINES: usize = 100;
+
+/// Bytes of combined output run_shell returns by default
+const SHELL_OUTPUT_BYTES: usize = 10_000;
+
+/// Longest line read from a pipe at once; longer lines arrive in pieces
+const SHELL_LINE_BYTES: u64 = 8 * 1024;
+
+/// How long to keep reading output still in the pipes after the command exits
+/// (a background process can hold them open indefinitely)
+const SHELL_DRAIN_GRACE: Duration = Duration::from_secs(2);
+
+/// How often run_shell checks for exit, cancellation, and timeout
+const SHELL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Receives run_shell output line by line while the command runs
+#[derive(Clone)]
+pub struct OutputSink(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl OutputSink {
+    pub fn new(f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        OutputSink(Arc::new(f))
+    }
+
+    fn send(&self, text: &str) {
+        (self.0)(text)
+    }
+}
+
+impl std::fmt::Debug for OutputSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OutputSink")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellStream {
+    Stdout,
+    Stderr,
+}
+
+/// What a pipe reader thread reports
+enum PipeEvent {
+    Line(ShellStream, String),
+    Closed,
+}
+
+/// Read `pipe` to EOF on a background thread, sending each line. The pipe is
+/// drained even when nobody is listening so the child never blocks on a full pipe.
+fn spawn_pipe_reader(
+    pipe: impl std::io::Read + Send + 'static,
+    stream: ShellStream,
+    tx: std::sync::mpsc::Sender<PipeEvent>,
+) {
+    use std::io::Read;
+
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match (&mut reader)
+                .take(SHELL_LINE_BYTES)
+                .read_until(b'\n', &mut buf)
+            {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf);
+                    let line = line.trim_end_matches(['\n', '\r']).to_string();
+                    let _ = tx.send(PipeEvent::Line(stream, line));
+                }
+            }
+        }
+        let _ = tx.send(PipeEvent::Closed);
+    });
+}
+
+/// run_shell output, kept within the line and byte caps as it arrives
+struct ShellOutput {
+    max_bytes: usize,
+    stdout: String,
+    stdout_lines: usize,
+    stderr: Vec<String>,
+    /// Lines past the line caps, dropped
+    dropped_lines: usize,
+    /// Bytes forwarded to the sink so far
+    streamed: usize,
+}
+
+impl ShellOutput {
+    fn new(max_bytes: usize) -> Self {
+        ShellOutput {
+            max_bytes,
+            stdout: String::new(),
+            stdout_lines: 0,
+            stderr: Vec::new(),
+            dropped_lines: 0,
+            streamed: 0,
         }
+    }
 
-        // Always include standard system locations as a fallback.
-        extra.push("/usr/bin".to_string());
-        extra.push("/bin".to_string());
-        extra.push("/usr/sbin".to_string());
-        extra.push("/sbin".to_string());
+    fn push(&mut self, stream: ShellStream, line: String, sink: Option<&OutputSink>) {
+        match stream {
+            ShellStream::Stdout if self.stdout_lines < SHELL_STDOUT_LINES => {
+                self.stdout_lines += 1;
+                self.stdout.push_str(&line);
+                self.stdout.push('\n');
+            }
+            ShellStream::Stderr if self.stderr.len() < SHELL_STDERR_LINES => {
+                self.stderr.push(line.clone());
+            }
+            _ => {
+                self.dropped_lines += 1;
+                return;
+            }
+        }
 
-        for path in extra.into_iter().rev() {
-            if !entries.iter().any(|p| p == &path) {
-                entries.insert(0, path);
+        if let Some(sink) = sink {
+            if self.streamed < self.max_bytes {
+                let chunk = format!("{}\n", line);
+                self.streamed += chunk.len();
+                sink.send(&chunk);
             }
         }
+    }
+
+    /// Stdout, then stderr under a separator, cut to the byte cap. Anything
+    /// left out is noted at the end.
+    fn finish(self) -> String {
+        let mut output = self.stdout;
+        if !self.stderr.is_empty() {
+            output.push_str("\n--- stderr ---\n");
+            output.push_str(&self.stderr.join("\n"));
+        }
 
-        cmd.env("PATH", entries.join(":"));
+        if output.len() > self.max_bytes {
+            let kept = truncate_to_char_boundary(&output, self.max_bytes).len();
+            let omitted = output.len() - kept;
+            output.truncate(kept);
+            output.push_str("...");
+            output.push_str(&truncation_note(omitted, "bytes"));
+        }
+        if self.dropped_lines > 0 {
+            output.push('\n');
+            output.push_str(&truncation_note(self.dropped_lines, "lines"));
+        }
+        output
     }
+}
 
+/// Spawn a command built by [`shell_command`] and collect its output, forwarding
+/// it to `sink` as it arrives. `env_note` (what the environment left out) is
+/// added to the result. On timeout or cancel the command's whole process tree
+/// is killed.
+fn execute_shell(
+    mut cmd: Command,
+    env_note: Option<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+    cancel: Option<&CancellationToken>,
+    sink: Option<&OutputSink>,
+) -> Result<ToolOutcome, String> {
     let mut child = cmd
         .spawn()
         .map_err(|e| format!("Failed to spawn command: {}", e))?;
+    let _process_guard = ShellProcessGuard::register(child.id());
+
+    // Both pipes are read while the command runs; reading them only after exit
+    // deadlocks once the child fills a pipe buffer
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut open_pipes = 0;
+    if let Some(stdout) = child.stdout.take() {
+        spawn_pipe_reader(stdout, ShellStream::Stdout, tx.clone());
+        open_pipes += 1;
+    }
+    if let Some(stderr) = child.stderr.take() {
+        spawn_pipe_reader(stderr, ShellStream::Stderr, tx.clone());
+        open_pipes += 1;
+    }
+    drop(tx);
 
-    // Wait with timeout using a simple polling approach
+    let mut output = ShellOutput::new(max_output_bytes);
     let start = std::time::Instant::now();
-    loop {
-        match child.try_wait() {
-            Ok(Some(status)) => {
-                // Process completed
-                let stdout = child.stdout.take();
-                let stderr = child.stderr.take();
-
-                let mut output = String::new();
-
-                if let Some(out) = stdout {
-                    let reader = BufReader::new(out);
-                    for line in reader.lines().take(500) {
-                        if let Ok(l) = line {
-                            output.push_str(&l);
-                            output.push('\n');
-                        }
-                    }
-                }
-
-                if let Some(err) = stderr {
-                    let reader = BufReader::new(err);
-                    let stderr_lines: Vec<String> =
-                        reader.lines().take(100).filter_map(|l| l.ok()).collect();
-
-                    if !stderr_lines.is_empty() {
-                        output.push_str("\n--- stderr ---\n");
-                        output.push_str(&stderr_lines.join("\n"));
-                    }
-                }
-
-                let result = serde_json::json!({
-                    "exit_code": status.code().unwrap_or(-1),
-                    "output": if output.len() > 10000 {
-                        format!("{}...[truncated]", &output[..10000])
-                    } else {
-                        output
-                    }
-                });
-
-                return Ok(serde_json::to_string_pretty(&result)
-                    .unwrap_or_else(|_| format!("{:?}", result)));
+    let status = loop {
+        if open_pipes > 0 {
+            match rx.recv_timeout(SHELL_POLL_INTERVAL) {
+                Ok(PipeEvent::Line(stream, line)) => output.push(stream, line, sink),
+                Ok(PipeEvent::Closed) => open_pipes -= 1,
+                Err(_) => {}
             }
+        } else {
+            std::thread::sleep(SHELL_POLL_INTERVAL);
+        }
+
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
             Ok(None) => {
                 // Still running
+                if cancel.is_some_and(|t| t.is_cancelled()) {
+                    kill_process_tree(child.id());
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(TOOL_CANCELLED.to_string());
+                }
                 if start.elapsed() > timeout {
+                    kill_process_tree(child.id());
                     let _ = child.kill();
+                    let _ = child.wait();
                     return Err(format!(
                         "Command timed out after {} seconds",
                         timeout.as_secs()
                     ));
                 }
-                std::thread::sleep(Duration::from_millis(100));
             }
             Err(e) => {
                 return Err(format!("Error waiting for command: {}", e));
             }
         }
+    };
+
+    // Collect what is still buffered in the pipes
+    let deadline = std::time::Instant::now() + SHELL_DRAIN_GRACE;
+    while open_pipes > 0 {
+        let wait = deadline.saturating_duration_since(std::time::Instant::now());
+        match rx.recv_timeout(wait) {
+            Ok(PipeEvent::Line(stream, line)) => output.push(stream, line, sink),
+            Ok(PipeEvent::Closed) => open_pipes -= 1,
+            Err(_) => break,
+        }
+    }
+
+    let mut result = serde_json::json!({
+        "exit_code": status.code().unwrap_or(-1),
+        "output": output.finish(),
+    });
+    if let Some(note) = env_note {
+        result["env_note"] = serde_json::Value::String(note);
+    }
+
+    Ok(ToolOutcome::json(result))
+}
+
+// ============================================================================
+// Scratchpad
+// ============================================================================
+
+/// Scratchpad tool names
+pub const SCRATCHPAD_TOOLS: &[&str] = &["scratchpad_write", "scratchpad_read"];
+
+/// Most bytes of notes the scratchpad holds
+pub const SCRATCHPAD_MAX_BYTES: usize = 16_000;
+
+/// Most bytes of the first note quoted in the scratchpad summary
+const SCRATCHPAD_SUMMARY_BYTES: usize = 80;
+
+/// Check if a tool is a scratchpad tool
+pub fn is_scratchpad_tool(name: &str) -> bool {
+    SCRATCHPAD_TOOLS.contains(&name)
+}
+
+/// Notes the model keeps for itself during one run. They live in memory only;
+/// nothing is written to the workspace.
+#[derive(Debug, Default)]
+pub struct Scratchpad {
+    notes: Mutex<Vec<String>>,
+}
+
+impl Scratchpad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a note, refusing one that would take the pad over its cap
+    pub fn write(&self, note: &str) -> Result<String, String> {
+        let note = note.trim();
+        if note.is_empty() {
+            return Err("Note cannot be empty".to_string());
+        }
+        let mut notes = self.notes();
+        let used: usize = notes.iter().map(String::len).sum();
+        if used + note.len() > SCRATCHPAD_MAX_BYTES {
+            return Err(format!(
+                "Scratchpad is full: {} of {} bytes used, and this note is {} bytes. Keep notes short.",
+                used,
+                SCRATCHPAD_MAX_BYTES,
+                note.len()
+            ));
+        }
+        notes.push(note.to_string());
+        Ok(format!(
+            "Noted ({} of {} bytes used)",
+            used + note.len(),
+            SCRATCHPAD_MAX_BYTES
+        ))
+    }
+
+    /// Every note, numbered, oldest first
+    pub fn read(&self) -> String {
+        let notes = self.notes();
+        if notes.is_empty() {
+            return "The scratchpad is empty.".to_string();
+        }
+        notes
+            .iter()
+            .enumerate()
+            .map(|(i, note)| format!("[{}] {}", i + 1, note))
+            .collect::<Vec<_>>()
+            .join("\n\n")
     }
+
+    /// Every note, oldest first, separated by blank lines
+    pub fn contents(&self) -> String {
+        self.notes().join("\n\n")
+    }
+
+    /// One line saying what the pad holds, `None` if it is empty
+    pub fn summary(&self) -> Option<String> {
+        let notes = self.notes();
+        let first = notes.first()?.lines().next().unwrap_or_default();
+        let quoted = truncate_to_char_boundary(first, SCRATCHPAD_SUMMARY_BYTES);
+        let ellipsis = if quoted.len() < first.len() {
+            "..."
+        } else {
+            ""
+        };
+        Some(format!(
+            "{} scratchpad note(s), {} bytes; first: \"{}{}\"",
+            notes.len(),
+            notes.iter().map(String::len).sum::<usize>(),
+            quoted,
+            ellipsis
+        ))
+    }
+
+    fn notes(&self) -> MutexGuard<'_, Vec<String>> {
+        self.notes.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Execute a scratchpad tool call
+pub fn dispatch_scratchpad(
+    scratchpad: &Scratchpad,
+    name: &str,
+    args: &serde_json::Value,
+) -> Result<String, String> {
+    match name {
+        "scratchpad_write" => {
+            let note = args
+                .get("note")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'note' parameter")?;
+            scratchpad.write(note)
+        }
+        "scratchpad_read" => Ok(scratchpad.read()),
+        _ => Err(format!("Unknown scratchpad tool: {}", name)),
+    }
+}
+
+/// Schemas for the scratchpad tools
+pub fn scratchpad_tool_schemas() -> Vec<Tool> {
+    let mut properties = HashMap::new();
+    properties.insert(
+        "note".to_string(),
+        PropertySchema {
+            prop_type: "string".to_string(),
+            description: Some("The note to append".to_string()),
+            default: None,
+            items: None,
+        },
+    );
+
+    vec![
+        Tool::new(
+            "scratchpad_write",
+            &format!(
+                "Append a note to your scratchpad: private working memory for this run, not saved to the project. Use it for plans, findings, and reminders instead of writing notes files. Holds {} bytes in all.",
+                SCRATCHPAD_MAX_BYTES
+            ),
+            JsonSchema {
+                schema_type: "object".to_string(),
+                properties: Some(properties),
+                required: Some(vec!["note".to_string()]),
+            },
+        ),
+        Tool::new(
+            "scratchpad_read",
+            "Read back every note in your scratchpad (private working memory for this run, not saved to the project).",
+            JsonSchema {
+                schema_type: "object".to_string(),
+                properties: Some(HashMap::new()),
+                required: Some(vec![]),
+            },
+        ),
+    ]
 }
 
 // ============================================================================
 // Tool Dispatcher
 // ============================================================================
 
-/// Dispatch a tool call to the appropriate implementation
+/// How run_shell runs commands for an agent run
+#[derive(Debug, Clone)]
+pub struct ShellSettings {
+    /// Timeout in seconds when the model doesn't ask for one (capped at 60)
+    pub timeout: u64,
+    /// Which commands may run at all
+    pub policy: ShellPolicy,
+    /// Variables commands get beyond the minimal set (see [`ShellEnv`])
+    pub env_allowlist: Vec<String>,
+    /// Shell commands run in; the platform default if `None` (see [`ShellProgram`])
+    pub shell: Option<String>,
+    /// Where output goes while a command runs
+    pub output: Option<OutputSink>,
+}
+
+impl Default for ShellSettings {
+    fn default() -> Self {
+        ShellSettings {
+            timeout: 30,
+            policy: ShellPolicy::default(),
+            env_allowlist: Vec::new(),
+            shell: None,
+            output: None,
+        }
+    }
+}
+
+/// Dispatch a tool call to the appropriate implementation. The outcome renders
+/// to the string the model is sent; callers that only want that string can
+/// convert it with `String::from`.
 pub fn dispatch_tool(
-    workspace: &Path,
+    paths: &PathValidator,
     name: &str,
     args: &serde_json::Value,
-    shell_timeout: u64,
-) -> Result<String, String> {
+    shell: &ShellSettings,
+    cancel: Option<&CancellationToken>,
+) -> Result<ToolOutcome, String> {
+    check_cancelled(cancel)?;
+
     match name {
+        "read_many_files" => {
+            let files: Vec<String> = match args.get("glob").and_then(|v| v.as_str()) {
+                Some(pattern) => {
+                    let (matches, _) = glob_matches(paths, pattern, ".", cancel)?;
+                    matches
+                        .into_iter()
+                        .filter(|m| paths.resolve(m).map(|p| p.is_file()).unwrap_or(true))
+                        .collect()
+                }
+                None => args
+                    .get("paths")
+                    .and_then(|v| v.as_array())
+                    .ok_or("Missing 'paths' or 'glob' parameter")?
+                    .iter()
+                    .map(|v| {
+                        v.as_str()
+                            .map(str::to_string)
+                            .ok_or("'paths' must be strings")
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            let limit = args
+                .get("limit")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            let max_bytes = args
+                .get("max_bytes")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize);
+            read_many_files(paths, &files, limit, max_bytes, cancel)
+        }
         "read_file" => {
             let path = args
                 .get("path")
@@ -1082,7 +3357,7 @@ pub fn dispatch_tool(
                 .get("limit")
                 .and_then(|v| v.as_u64())
                 .map(|v| v as usize);
-            read_file(workspace, path, offset, limit)
+            read_file(paths, path, offset, limit)
         }
 
         "write_file" => {
@@ -1094,7 +3369,7 @@ pub fn dispatch_tool(
                 .get("content")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing 'content' parameter")?;
-            write_file(workspace, path, content)
+            write_file(paths, path, content)
         }
 
         "delete_file" => {
@@ -1102,7 +3377,7 @@ pub fn dispatch_tool(
                 .get("path")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing 'path' parameter")?;
-            delete_file(workspace, path)
+            delete_file(paths, path)
         }
 
         "append_file" => {
@@ -1114,12 +3389,52 @@ pub fn dispatch_tool(
                 .get("content")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing 'content' parameter")?;
-            append_file(workspace, path, content)
+            append_file(paths, path, content)
+        }
+
+        "edit_file" => {
+            let path = args
+                .get("path")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'path' parameter")?;
+            let old_string = args
+                .get("old_string")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'old_string' parameter")?;
+            let new_string = args
+                .get("new_string")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'new_string' parameter")?;
+            let replace_all = args
+                .get("replace_all")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            edit_file(paths, path, old_string, new_string, replace_all)
+        }
+
+        "move_file" | "copy_file" => {
+            let source = args
+                .get("source")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'source' parameter")?;
+            let destination = args
+                .get("destination")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing 'destination' parameter")?;
+            let overwrite = args
+                .get("overwrite")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            if name == "move_file" {
+                move_file(paths, source, destination, overwrite)
+            } else {
+                copy_file(paths, source, destination, overwrite)
+            }
         }
 
         "list_dir" => {
             let path = args.get("path").and_then(|v| v.as_str()).unwrap_or(".");
-            list_dir(workspace, path)
+            list_dir(paths, path, ListDirOptions::from_args(args))
         }
 
         "glob" => {
@@ -1128,7 +3443,7 @@ pub fn dispatch_tool(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing 'pattern' parameter")?;
             let path = args.get("path").and_then(|v| v.as_str()).unwrap_or(".");
-            glob_files(workspace, pattern, path)
+            glob_files(paths, pattern, path, cancel)
         }
 
         "grep" => {
@@ -1137,7 +3452,12 @@ pub fn dispatch_tool(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing 'pattern' parameter")?;
             let path = args.get("path").and_then(|v| v.as_str()).unwrap_or(".");
-            grep_files(workspace, pattern, path)
+            grep_files(paths, pattern, path, GrepOptions::from_args(args), cancel)
+        }
+
+        "workspace_stats" => {
+            let path = args.get("path").and_then(|v| v.as_str()).unwrap_or(".");
+            workspace_stats(paths, path, cancel)
         }
 
         "run_shell" => {
@@ -1149,9 +3469,9 @@ pub fn dispatch_tool(
             let timeout = args
                 .get("timeout")
                 .and_then(|v| v.as_u64())
-                .unwrap_or(shell_timeout)
+                .unwrap_or(shell.timeout)
                 .min(60);
-            run_shell(workspace, command, cwd, Some(timeout))
+            run_shell(paths, command, cwd, Some(timeout), shell, cancel)
         }
 
         _ => Err(format!("Unknown tool: {}", name)),
@@ -1165,6 +3485,7 @@ pub fn dispatch_tool(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::shell_policy::POLICY_BLOCKED;
     use std::fs;
     use tempfile::TempDir;
 
@@ -1180,91 +3501,951 @@ mod tests {
         )
         .unwrap();
 
-        dir
+        dir
+    }
+
+    /// Resolve through both `safe_path` and a `PathValidator` (cold and cached) and
+    /// check they agree, so every path-safety test covers both implementations.
+    fn resolve_both(workspace: &Path, requested: &str) -> Result<PathBuf, String> {
+        let expected = safe_path(workspace, requested);
+        let validator = PathValidator::new(workspace);
+        assert_eq!(
+            validator.resolve(requested),
+            expected,
+            "cold: {}",
+            requested
+        );
+        assert_eq!(
+            validator.resolve(requested),
+            expected,
+            "cached: {}",
+            requested
+        );
+        expected
+    }
+
+    /// Counts canonicalize calls while delegating to the filesystem
+    #[derive(Default)]
+    struct CountingCanonicalize {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl CountingCanonicalize {
+        fn calls(&self) -> usize {
+            self.calls.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    impl Canonicalize for CountingCanonicalize {
+        fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            path.canonicalize()
+        }
+    }
+
+    #[test]
+    fn test_safe_path_valid() {
+        let dir = setup_test_workspace();
+        let result = resolve_both(dir.path(), "test.txt");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_safe_path_traversal_blocked() {
+        let dir = setup_test_workspace();
+        let result = resolve_both(dir.path(), "../../../etc/passwd");
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        // Either error message is valid - path traversal or escapes workspace
+        assert!(err.contains("traversal") || err.contains("escapes workspace"));
+    }
+
+    #[test]
+    fn test_read_file() {
+        let dir = setup_test_workspace();
+        let result =
+            read_file(&PathValidator::new(dir.path()), "test.txt", None, None).map(String::from);
+        assert!(result.is_ok());
+        let content = result.unwrap();
+        assert!(content.contains("line 1"));
+        assert!(content.contains("line 2"));
+    }
+
+    #[test]
+    fn test_read_file_not_found() {
+        let dir = setup_test_workspace();
+        let result = read_file(
+            &PathValidator::new(dir.path()),
+            "nonexistent.txt",
+            None,
+            None,
+        )
+        .map(String::from);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("not found"));
+    }
+
+    #[test]
+    fn test_read_file_reports_line_count_and_truncation() {
+        let dir = setup_test_workspace();
+        let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        fs::write(dir.path().join("ten.txt"), text).unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let full = read_file(&paths, "ten.txt", None, None)
+            .map(String::from)
+            .unwrap();
+        assert!(full.ends_with("[Showing lines 1-10 of 10]"), "{}", full);
+
+        let part = read_file(&paths, "ten.txt", Some(3), Some(4))
+            .map(String::from)
+            .unwrap();
+        assert!(part.contains("     3\tline 3\n"));
+        assert!(!part.contains("line 7"));
+        assert!(
+            part.ends_with("[Showing lines 3-6 of 10; truncated by limit, continue with offset=7]"),
+            "{}",
+            part
+        );
+    }
+
+    #[test]
+    fn test_read_file_binary() {
+        let dir = setup_test_workspace();
+        // PNG signature and the start of an IHDR chunk
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend(std::iter::repeat(0xAB).take(100));
+        fs::write(dir.path().join("cover.png"), &png).unwrap();
+
+        let result = read_file(&PathValidator::new(dir.path()), "cover.png", None, None)
+            .map(String::from)
+            .unwrap();
+        assert_eq!(
+            result,
+            "Binary file (image/png, 116 bytes) - not displaying contents"
+        );
+    }
+
+    #[test]
+    fn test_read_file_latin1_is_lossy() {
+        let dir = setup_test_workspace();
+        // "café au lait" and "naïve" in latin-1
+        fs::write(
+            dir.path().join("notes.txt"),
+            b"caf\xe9 au lait\r\nna\xefve\nplain\n",
+        )
+        .unwrap();
+
+        let result = read_file(&PathValidator::new(dir.path()), "notes.txt", None, None)
+            .map(String::from)
+            .unwrap();
+        assert!(
+            result.contains("     1\tcaf\u{FFFD} au lait\n"),
+            "{}",
+            result
+        );
+        assert!(result.contains("     2\tna\u{FFFD}ve\n"));
+        assert!(result.contains("     3\tplain\n"));
+        assert!(result.contains("[Showing lines 1-3 of 3]"));
+        assert!(result.contains("Invalid UTF-8 was replaced"));
+    }
+
+    #[test]
+    fn test_read_file_long_multibyte_line() {
+        let dir = setup_test_workspace();
+        fs::write(dir.path().join("long.txt"), "é".repeat(1500)).unwrap();
+
+        let result = read_file(&PathValidator::new(dir.path()), "long.txt", None, None)
+            .map(String::from)
+            .unwrap();
+        assert!(result.contains("...[Truncated: 1000 bytes omitted]"));
+        assert!(!result.contains("Invalid UTF-8"));
+    }
+
+    #[test]
+    fn test_read_file_honors_line_limit() {
+        let dir = setup_test_workspace();
+        fs::write(
+            dir.path().join("long.txt"),
+            format!("short\n{}\n", "x".repeat(150)),
+        )
+        .unwrap();
+        let paths = PathValidator::new(dir.path()).with_tool_limits(ToolLimits {
+            read_line_bytes: 100,
+            ..ToolLimits::default()
+        });
+
+        let result = read_file(&paths, "long.txt", None, None)
+            .map(String::from)
+            .unwrap();
+        assert!(result.contains("     1\tshort\n"));
+        assert!(result.contains(&format!(
+            "     2\t{}...[Truncated: 50 bytes omitted]\n",
+            "x".repeat(100)
+        )));
+        assert!(reports_truncation(&result));
+
+        let result = read_file(&paths, "test.txt", None, None)
+            .map(String::from)
+            .unwrap();
+        assert!(!reports_truncation(&result));
+    }
+
+    #[test]
+    fn test_move_file_across_directories() {
+        let dir = setup_test_workspace();
+        fs::create_dir_all(dir.path().join("drafts")).unwrap();
+        fs::write(dir.path().join("drafts/ch1.md"), "Chapter one").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let result = move_file(&paths, "drafts/ch1.md", "book/part1/ch1.md", false)
+            .map(String::from)
+            .unwrap();
+        assert_eq!(result, "Moved drafts/ch1.md to book/part1/ch1.md");
+        assert!(!dir.path().join("drafts/ch1.md").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("book/part1/ch1.md")).unwrap(),
+            "Chapter one"
+        );
+        // The validator's cache doesn't hide the move
+        assert!(read_file(&paths, "drafts/ch1.md", None, None).is_err());
+    }
+
+    #[test]
+    fn test_move_and_copy_refuse_to_overwrite() {
+        let dir = setup_test_workspace();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+        fs::write(dir.path().join("b.md"), "B").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        for transfer in [move_file, copy_file] {
+            let err = transfer(&paths, "a.md", "b.md", false).unwrap_err();
+            assert!(err.contains("already exists"), "{}", err);
+            assert_eq!(fs::read_to_string(dir.path().join("b.md")).unwrap(), "B");
+        }
+
+        copy_file(&paths, "a.md", "b.md", true).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("b.md")).unwrap(), "A");
+        assert!(dir.path().join("a.md").exists());
+
+        fs::write(dir.path().join("c.md"), "C").unwrap();
+        move_file(&paths, "c.md", "b.md", true).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("b.md")).unwrap(), "C");
+        assert!(!dir.path().join("c.md").exists());
+    }
+
+    #[test]
+    fn test_move_across_filesystems_keeps_destination_on_failure() {
+        let dir = setup_test_workspace();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+        fs::write(dir.path().join("b.md"), "B").unwrap();
+        let entries = || fs::read_dir(dir.path()).unwrap().count();
+        let before = entries();
+
+        let err = move_across_filesystems(&dir.path().join("missing.md"), &dir.path().join("b.md"))
+            .unwrap_err();
+        assert!(err.contains("Failed to move file"), "{}", err);
+        assert_eq!(fs::read_to_string(dir.path().join("b.md")).unwrap(), "B");
+        assert_eq!(entries(), before);
+
+        move_across_filesystems(&dir.path().join("a.md"), &dir.path().join("b.md")).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("b.md")).unwrap(), "A");
+        assert!(!dir.path().join("a.md").exists());
+        assert_eq!(entries(), before - 1);
+    }
+
+    #[test]
+    fn test_move_file_rejects_directories_and_missing_sources() {
+        let dir = setup_test_workspace();
+        fs::create_dir_all(dir.path().join("drafts")).unwrap();
+        fs::write(dir.path().join("a.md"), "A").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let err = move_file(&paths, "drafts", "archive", false)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("directories are not supported"), "{}", err);
+        let err = move_file(&paths, "a.md", "drafts", true)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("is a directory"), "{}", err);
+        let err = copy_file(&paths, "missing.md", "b.md", false)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+        let err = move_file(&paths, "a.md", "./a.md", true)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("same file"), "{}", err);
+    }
+
+    #[test]
+    fn test_move_and_copy_block_sensitive_paths() {
+        let dir = setup_test_workspace();
+        fs::write(dir.path().join("notes.md"), "KEY=1").unwrap();
+        fs::write(dir.path().join(".env"), "SECRET=1").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        for transfer in [move_file, copy_file] {
+            let err = transfer(&paths, "notes.md", ".env.local", false).unwrap_err();
+            assert!(err.contains("Access denied"), "{}", err);
+            let err = transfer(&paths, ".env", "leak.md", false).unwrap_err();
+            assert!(err.contains("Access denied"), "{}", err);
+            let err = transfer(&paths, "notes.md", "../outside.md", false).unwrap_err();
+            assert!(
+                err.contains("traversal") || err.contains("escapes workspace"),
+                "{}",
+                err
+            );
+        }
+        assert!(dir.path().join("notes.md").exists());
+        assert!(!dir.path().join("leak.md").exists());
+    }
+
+    #[test]
+    fn test_write_file() {
+        let dir = setup_test_workspace();
+        let result = write_file(
+            &PathValidator::new(dir.path()),
+            "new_file.txt",
+            "hello world",
+        )
+        .map(String::from);
+        assert!(result.is_ok());
+
+        // Verify file was written
+        let content = fs::read_to_string(dir.path().join("new_file.txt")).unwrap();
+        assert_eq!(content, "hello world");
+    }
+
+    #[test]
+    fn test_write_file_creates_dirs() {
+        let dir = setup_test_workspace();
+        let result = write_file(
+            &PathValidator::new(dir.path()),
+            "deep/nested/file.txt",
+            "content",
+        )
+        .map(String::from);
+        assert!(result.is_ok());
+
+        // Verify directory structure was created
+        assert!(dir.path().join("deep/nested/file.txt").exists());
+    }
+
+    #[test]
+    fn test_file_tools_report_effects() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let written = write_file(&paths, "a.md", "hello").unwrap();
+        assert_eq!(written.to_string(), "Wrote 5 bytes to a.md");
+        assert_eq!(written.effects.bytes_written, 5);
+        assert_eq!(written.effects.files_affected, vec!["a.md"]);
+
+        let appended = append_file(&paths, "a.md", " world").unwrap();
+        assert_eq!(appended.effects.bytes_written, 11);
+
+        let moved = move_file(&paths, "a.md", "b.md", false).unwrap();
+        assert_eq!(moved.effects.bytes_written, 0);
+        assert_eq!(moved.effects.files_affected, vec!["a.md", "b.md"]);
+
+        let read = read_file(&paths, "b.md", None, None).unwrap();
+        assert!(read.effects.is_empty());
+    }
+
+    #[test]
+    fn test_append_file() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        fs::create_dir(dir.path().join("notes")).unwrap();
+
+        append_file(&paths, "notes/log.md", "one\n").unwrap();
+        append_file(&paths, "notes/log.md", "two\n").unwrap();
+
+        let content = fs::read_to_string(dir.path().join("notes/log.md")).unwrap();
+        assert_eq!(content, "one\ntwo\n");
+        // No temp files are left next to the target
+        assert_eq!(fs::read_dir(dir.path().join("notes")).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_file_tools_enforce_size_limits() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path()).with_file_limits(FileLimits {
+            max_read_bytes: 2048,
+            max_write_bytes: 1024,
+        });
+
+        let err = write_file(&paths, "big.md", &"x".repeat(1500))
+            .map(String::from)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Content is 1.5 KB, limit is 1.0 KB; write in smaller pieces or reconsider"
+        );
+        assert!(!dir.path().join("big.md").exists());
+
+        // The cap is on the file append_file leaves, not each piece
+        append_file(&paths, "log.md", &"x".repeat(600)).unwrap();
+        let err = append_file(&paths, "log.md", &"x".repeat(600))
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("over the 1.0 KB limit"), "{}", err);
+        assert_eq!(fs::metadata(dir.path().join("log.md")).unwrap().len(), 600);
+
+        fs::write(dir.path().join("huge.log"), "line\n".repeat(1000)).unwrap();
+        let err = read_file(&paths, "huge.log", None, None)
+            .map(String::from)
+            .unwrap_err();
+        assert!(
+            err.contains("huge.log is 4.9 KB, over the 2.0 KB read limit"),
+            "{}",
+            err
+        );
+        assert!(read_file(&paths, "log.md", None, None).is_ok());
+        assert_eq!(describe_size(18 * 1024 * 1024), "18.0 MB");
+    }
+
+    #[test]
+    fn test_edit_file_unique_match() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        fs::write(
+            dir.path().join("ch1.md"),
+            "It was a dark night.\nThe end.\n",
+        )
+        .unwrap();
+
+        let result = edit_file(&paths, "ch1.md", "dark", "stormy", false)
+            .map(String::from)
+            .unwrap();
+        assert_eq!(result, "Made 1 replacement in ch1.md");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ch1.md")).unwrap(),
+            "It was a stormy night.\nThe end.\n"
+        );
+
+        let err = edit_file(&paths, "ch1.md", "dark", "stormy", false)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("not found"));
+    }
+
+    #[test]
+    fn test_edit_file_ambiguous_match() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        fs::write(dir.path().join("ch1.md"), "Anna ran. Anna hid. Anna won.").unwrap();
+
+        let err = edit_file(&paths, "ch1.md", "Anna", "Ada", false)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.contains("found 3 times"));
+        // The file is untouched
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ch1.md")).unwrap(),
+            "Anna ran. Anna hid. Anna won."
+        );
+
+        let result = edit_file(&paths, "ch1.md", "Anna", "Ada", true)
+            .map(String::from)
+            .unwrap();
+        assert_eq!(result, "Made 3 replacements in ch1.md");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ch1.md")).unwrap(),
+            "Ada ran. Ada hid. Ada won."
+        );
+    }
+
+    #[test]
+    fn test_edit_file_unicode() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        fs::write(dir.path().join("ch1.md"), "Zoë ordered crème brûlée 🍮.").unwrap();
+
+        edit_file(
+            &paths,
+            "ch1.md",
+            "crème brûlée 🍮",
+            "café au lait ☕",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ch1.md")).unwrap(),
+            "Zoë ordered café au lait ☕."
+        );
+    }
+
+    #[test]
+    fn test_edit_file_crlf() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        fs::write(dir.path().join("ch1.md"), "First line.\r\nSecond line.\r\n").unwrap();
+
+        // The model sees lines without \r, so it sends \n
+        edit_file(
+            &paths,
+            "ch1.md",
+            "First line.\nSecond",
+            "Opening line.\nNext",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ch1.md")).unwrap(),
+            "Opening line.\r\nNext line.\r\n"
+        );
+
+        // Exact CRLF input still matches
+        let args = serde_json::json!({
+            "path": "ch1.md",
+            "old_string": "line.\r\nNext",
+            "new_string": "line.\r\nThen",
+        });
+        dispatch_tool(&paths, "edit_file", &args, &ShellSettings::default(), None).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("ch1.md")).unwrap(),
+            "Opening line.\r\nThen line.\r\n"
+        );
+    }
+
+    #[test]
+    fn test_list_dir() {
+        let dir = setup_test_workspace();
+        let result = list_dir(
+            &PathValidator::new(dir.path()),
+            ".",
+            ListDirOptions::default(),
+        )
+        .map(String::from);
+        let entries: Vec<serde_json::Value> = serde_json::from_str(&result.unwrap()).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        // Directories first, sized by their contents
+        assert_eq!(entries[0]["name"], "subdir");
+        assert_eq!(entries[0]["type"], "dir");
+        assert_eq!(entries[0]["size"], 20);
+        assert!(entries[0].get("extension").is_none());
+
+        assert_eq!(entries[1]["name"], "test.txt");
+        assert_eq!(entries[1]["type"], "file");
+        assert_eq!(entries[1]["size"], 21);
+        assert_eq!(entries[1]["extension"], "txt");
+        let modified = entries[1]["modified"].as_str().unwrap();
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(modified).is_ok(),
+            "{}",
+            modified
+        );
+
+        let flat = ListDirOptions {
+            flat: true,
+            ..Default::default()
+        };
+        let names: Vec<String> = serde_json::from_str(
+            &list_dir(&PathValidator::new(dir.path()), ".", flat)
+                .map(String::from)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(names, vec!["subdir/", "test.txt"]);
+    }
+
+    #[test]
+    fn test_list_dir_recursive_limits() {
+        let dir = setup_test_workspace();
+        let deep = dir.path().join("a/b/c/d/e");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(deep.join("deep.md"), "deep").unwrap();
+        let paths = PathValidator::new(dir.path());
+        let recursive = ListDirOptions {
+            recursive: true,
+            ..Default::default()
+        };
+
+        let entries: Vec<serde_json::Value> =
+            serde_json::from_str(&list_dir(&paths, ".", recursive).map(String::from).unwrap())
+                .unwrap();
+        let names: Vec<&str> = entries.iter().filter_map(|e| e["name"].as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "a",
+                "a/b",
+                "a/b/c",
+                "a/b/c/d",
+                "subdir",
+                "subdir/nested.md",
+                "test.txt"
+            ]
+        );
+        // The depth cap stops the listing, not the size totals
+        assert_eq!(entries[3]["size"], 4);
+
+        for i in 0..LIST_DIR_MAX_ENTRIES {
+            fs::write(dir.path().join(format!("many-{:03}.md", i)), "").unwrap();
+        }
+        let entries: Vec<serde_json::Value> =
+            serde_json::from_str(&list_dir(&paths, ".", recursive).map(String::from).unwrap())
+                .unwrap();
+        assert_eq!(entries.len(), LIST_DIR_MAX_ENTRIES + 1);
+        let note = entries.last().unwrap()["note"].as_str().unwrap();
+        assert!(note.contains("stopped at 500 entries"), "{}", note);
+    }
+
+    #[test]
+    fn test_read_many_files() {
+        let dir = setup_test_workspace();
+        fs::write(dir.path().join(".env"), "SECRET=1\n").unwrap();
+        let paths = PathValidator::new(dir.path());
+        let files: Vec<String> = ["test.txt", "subdir/nested.md", "missing.md", ".env"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let result: serde_json::Value = serde_json::from_str(
+            &read_many_files(&paths, &files, Some(2), None, None)
+                .map(String::from)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(result["test.txt"]["content"], "line 1\nline 2\n");
+        assert_eq!(result["test.txt"]["truncated"], true);
+        assert_eq!(
+            result["subdir/nested.md"]["content"],
+            "# Title\nSome content"
+        );
+        assert_eq!(result["subdir/nested.md"]["truncated"], false);
+        assert!(result["subdir/nested.md"].get("error").is_none());
+        assert_eq!(result["missing.md"]["error"], "File not found: missing.md");
+        assert!(result[".env"]["error"].is_string());
+        assert_eq!(result[".env"]["content"], "");
+
+        // The byte budget cuts the first file short and skips the rest
+        let result: serde_json::Value = serde_json::from_str(
+            &read_many_files(&paths, &files, None, Some(10), None)
+                .map(String::from)
+                .unwrap(),
+        )
+        .unwrap();
+        let content = result["test.txt"]["content"].as_str().unwrap();
+        assert!(
+            content.starts_with("line 1\nlin\n[total output limit of 10 bytes"),
+            "{}",
+            content
+        );
+        assert_eq!(result["subdir/nested.md"]["truncated"], true);
+        assert!(result["subdir/nested.md"]["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Not read"));
+
+        let args = serde_json::json!({"glob": "**/*.md"});
+        let result = dispatch_tool(
+            &paths,
+            "read_many_files",
+            &args,
+            &ShellSettings::default(),
+            None,
+        )
+        .map(String::from)
+        .unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result.as_object().unwrap().len(), 1);
+        assert!(result["subdir/nested.md"]["content"].is_string());
+
+        let too_many: Vec<String> = (0..=READ_MANY_MAX_FILES)
+            .map(|i| format!("{}.md", i))
+            .collect();
+        assert!(read_many_files(&paths, &too_many, None, None, None).is_err());
+        assert!(read_many_files(&paths, &[], None, None, None).is_err());
     }
 
     #[test]
-    fn test_safe_path_valid() {
+    fn test_glob_files() {
         let dir = setup_test_workspace();
-        let result = safe_path(dir.path(), "test.txt");
+        let result =
+            glob_files(&PathValidator::new(dir.path()), "**/*.txt", ".", None).map(String::from);
         assert!(result.is_ok());
+        let content = result.unwrap();
+        assert!(content.contains("test.txt"));
     }
 
     #[test]
-    fn test_safe_path_traversal_blocked() {
-        let dir = setup_test_workspace();
-        let result = safe_path(dir.path(), "../../../etc/passwd");
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        // Either error message is valid - path traversal or escapes workspace
-        assert!(err.contains("traversal") || err.contains("escapes workspace"));
+    fn test_glob_honors_entry_limit() {
+        let dir = TempDir::new().unwrap();
+        for n in 1..=5 {
+            fs::write(dir.path().join(format!("ch{}.md", n)), "").unwrap();
+        }
+        let paths = PathValidator::new(dir.path()).with_tool_limits(ToolLimits {
+            glob_max_entries: 3,
+            ..ToolLimits::default()
+        });
+
+        let result = glob_files(&paths, "*.md", ".", None)
+            .map(String::from)
+            .unwrap();
+        let entries: Vec<String> = serde_json::from_str(&result).unwrap();
+        assert_eq!(
+            entries,
+            ["ch1.md", "ch2.md", "ch3.md", "[Truncated: 2 files omitted]"]
+        );
+        assert!(reports_truncation(&result));
+
+        let result = glob_files(&paths, "ch1.*", ".", None)
+            .map(String::from)
+            .unwrap();
+        assert!(!reports_truncation(&result));
     }
 
     #[test]
-    fn test_read_file() {
+    fn test_grep_files() {
         let dir = setup_test_workspace();
-        let result = read_file(dir.path(), "test.txt", None, None);
+        let result = grep_files(
+            &PathValidator::new(dir.path()),
+            "line",
+            ".",
+            GrepOptions::default(),
+            None,
+        )
+        .map(String::from);
         assert!(result.is_ok());
         let content = result.unwrap();
-        assert!(content.contains("line 1"));
-        assert!(content.contains("line 2"));
+        assert!(content.contains("test.txt"));
     }
 
     #[test]
-    fn test_read_file_not_found() {
-        let dir = setup_test_workspace();
-        let result = read_file(dir.path(), "nonexistent.txt", None, None);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("not found"));
+    fn test_grep_context_merges_nearby_matches() {
+        let dir = TempDir::new().unwrap();
+        let text: String = (1..=20)
+            .map(|n| match n {
+                5 | 7 | 16 => format!("Anna {}\n", n),
+                _ => format!("line {}\n", n),
+            })
+            .collect();
+        fs::write(dir.path().join("ch1.md"), text).unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let options = GrepOptions {
+            context_before: 1,
+            context_after: 2,
+            files_only: false,
+        };
+        let result = grep_files(&paths, "anna", ".", options, None)
+            .map(String::from)
+            .unwrap();
+        let blocks: Vec<serde_json::Value> = serde_json::from_str(&result).unwrap();
+        assert_eq!(blocks.len(), 2, "{}", result);
+        // Windows 4-7 and 6-9 overlap, so they are one block
+        assert_eq!(blocks[0]["start"], 4);
+        assert_eq!(blocks[0]["end"], 9);
+        assert_eq!(blocks[0]["matches"], serde_json::json!([5, 7]));
+        assert_eq!(
+            blocks[0]["content"],
+            "4-line 4\n5:Anna 5\n6-line 6\n7:Anna 7\n8-line 8\n9-line 9"
+        );
+        assert_eq!(blocks[1]["start"], 15);
+        assert_eq!(blocks[1]["end"], 18);
+
+        // Context is capped at 5 lines
+        let options = GrepOptions::from_args(&serde_json::json!({"context_before": 50}));
+        assert_eq!(options.context_before, GREP_MAX_CONTEXT);
+
+        // Without context, adjacent matches stay separate results
+        let result = grep_files(&paths, "anna", ".", GrepOptions::default(), None)
+            .map(String::from)
+            .unwrap();
+        let matches: Vec<serde_json::Value> = serde_json::from_str(&result).unwrap();
+        let lines: Vec<u64> = matches
+            .iter()
+            .map(|m| m["line"].as_u64().unwrap())
+            .collect();
+        assert_eq!(lines, vec![5, 7, 16]);
     }
 
     #[test]
-    fn test_write_file() {
-        let dir = setup_test_workspace();
-        let result = write_file(dir.path(), "new_file.txt", "hello world");
-        assert!(result.is_ok());
-
-        // Verify file was written
-        let content = fs::read_to_string(dir.path().join("new_file.txt")).unwrap();
-        assert_eq!(content, "hello world");
+    fn test_grep_files_only_and_per_file_cap() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("ch1.md"), "Anna\n".repeat(30)).unwrap();
+        fs::write(dir.path().join("ch2.md"), "Bob\n").unwrap();
+        fs::write(dir.path().join("ch3.md"), "Anna and Bob\n").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let options = GrepOptions {
+            files_only: true,
+            ..GrepOptions::default()
+        };
+        let result = grep_files(&paths, "anna", ".", options, None)
+            .map(String::from)
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&result).unwrap(),
+            serde_json::json!([
+                {"file": "ch1.md", "matches": 30},
+                {"file": "ch3.md", "matches": 1},
+            ])
+        );
+
+        let result = grep_files(&paths, "anna", ".", GrepOptions::default(), None)
+            .map(String::from)
+            .unwrap();
+        let results: Vec<serde_json::Value> = serde_json::from_str(&result).unwrap();
+        let ch1 = results.iter().filter(|r| r["file"] == "ch1.md");
+        assert_eq!(ch1.clone().filter(|r| r.get("line").is_some()).count(), 25);
+        assert!(ch1
+            .filter_map(|r| r["note"].as_str())
+            .any(|note| note == "5 more matches in this file not shown"));
+        assert!(results.iter().any(|r| r["file"] == "ch3.md"));
     }
 
     #[test]
-    fn test_write_file_creates_dirs() {
-        let dir = setup_test_workspace();
-        let result = write_file(dir.path(), "deep/nested/file.txt", "content");
-        assert!(result.is_ok());
-
-        // Verify directory structure was created
-        assert!(dir.path().join("deep/nested/file.txt").exists());
+    fn test_grep_honors_match_limit() {
+        let dir = TempDir::new().unwrap();
+        for n in 1..=3 {
+            fs::write(dir.path().join(format!("ch{}.md", n)), "Anna\nAnna\n").unwrap();
+        }
+        let paths = PathValidator::new(dir.path()).with_tool_limits(ToolLimits {
+            grep_max_matches: 3,
+            ..ToolLimits::default()
+        });
+
+        let result = grep_files(&paths, "anna", ".", GrepOptions::default(), None)
+            .map(String::from)
+            .unwrap();
+        let results: Vec<serde_json::Value> = serde_json::from_str(&result).unwrap();
+        assert_eq!(
+            results.iter().filter(|r| r.get("line").is_some()).count(),
+            3
+        );
+        // The rest of ch2 and all of ch3 are counted, not shown
+        assert_eq!(
+            results.last().unwrap()["note"],
+            "Results truncated at 3 matches. [Truncated: 3 matches omitted]"
+        );
+        assert!(reports_truncation(&result));
+
+        let options = GrepOptions {
+            files_only: true,
+            ..GrepOptions::default()
+        };
+        let result = grep_files(&paths, "anna", ".", options, None)
+            .map(String::from)
+            .unwrap();
+        assert!(!reports_truncation(&result));
     }
 
     #[test]
-    fn test_list_dir() {
+    fn test_vswriteignore_applies_to_search_tools() {
         let dir = setup_test_workspace();
-        let result = list_dir(dir.path(), ".");
-        assert!(result.is_ok());
-        let content = result.unwrap();
-        assert!(content.contains("test.txt"));
-        assert!(content.contains("subdir/"));
+        fs::create_dir(dir.path().join("exports")).unwrap();
+        fs::write(dir.path().join("exports").join("book.md"), "line export\n").unwrap();
+        fs::write(dir.path().join("notes.bak"), "line backup\n").unwrap();
+        fs::write(dir.path().join(".vswriteignore"), "exports/\n*.bak\n").unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let globbed = glob_files(&paths, "**/*", ".", None)
+            .map(String::from)
+            .unwrap();
+        assert!(globbed.contains("test.txt"));
+        assert!(!globbed.contains("book.md"));
+        assert!(!globbed.contains("notes.bak"));
+        assert!(globbed.contains("hidden by .vswriteignore"));
+
+        let grepped = grep_files(&paths, "line", ".", GrepOptions::default(), None)
+            .map(String::from)
+            .unwrap();
+        assert!(grepped.contains("test.txt"));
+        assert!(!grepped.contains("export"));
+        assert!(!grepped.contains("backup"));
+        assert!(
+            grepped.contains("2 paths hidden by .vswriteignore"),
+            "{}",
+            grepped
+        );
+
+        let flat = ListDirOptions {
+            flat: true,
+            ..Default::default()
+        };
+        let listed = list_dir(&paths, ".", flat).map(String::from).unwrap();
+        assert!(!listed.contains("exports/"));
+        assert!(listed.contains("2 paths hidden"));
+        let listed = list_dir(
+            &paths,
+            ".",
+            ListDirOptions {
+                include_ignored: true,
+                ..flat
+            },
+        )
+        .map(String::from)
+        .unwrap();
+        assert!(listed.contains("exports/"));
+        assert!(listed.contains("notes.bak"));
+        assert!(!listed.contains("hidden by"));
     }
 
     #[test]
-    fn test_glob_files() {
-        let dir = setup_test_workspace();
-        let result = glob_files(dir.path(), "**/*.txt", ".");
-        assert!(result.is_ok());
-        let content = result.unwrap();
-        assert!(content.contains("test.txt"));
+    fn test_text_stats_skip_frontmatter() {
+        let section = "---\nid: s1\ntitle: Chapter 3\n---\nIt was a dark night.\nThe end.\n";
+        assert_eq!(
+            TextStats::of(section),
+            TextStats {
+                words: 7,
+                characters: 30,
+                lines: 2
+            }
+        );
+        // No closing marker: count everything
+        assert_eq!(TextStats::of("---\nnot frontmatter").words, 3);
+        assert_eq!(TextStats::of("").lines, 0);
     }
 
     #[test]
-    fn test_grep_files() {
+    fn test_workspace_stats() {
         let dir = setup_test_workspace();
-        let result = grep_files(dir.path(), "line", ".");
-        assert!(result.is_ok());
-        let content = result.unwrap();
-        assert!(content.contains("test.txt"));
+        fs::create_dir(dir.path().join("sections")).unwrap();
+        fs::write(
+            dir.path().join("sections").join("03-storm.md"),
+            "---\ntitle: Storm\n---\nRain fell hard.\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("cover.png"), [0xff, 0xfe, 0x00, 0x81]).unwrap();
+        let paths = PathValidator::new(dir.path());
+
+        let stats = |target: &str| -> serde_json::Value {
+            serde_json::from_str(
+                &workspace_stats(&paths, target, None)
+                    .map(String::from)
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+
+        // Default: every markdown file
+        let all = stats(".");
+        assert_eq!(all["totals"]["files"], 2);
+        assert_eq!(all["files"][0]["path"], "sections/03-storm.md");
+        assert_eq!(all["files"][0]["words"], 3);
+        assert_eq!(all["files"][1]["path"], "subdir/nested.md");
+
+        let one = stats("sections/03-*.md");
+        assert_eq!(one["totals"]["files"], 1);
+        assert_eq!(one["totals"]["words"], 3);
+
+        let txt = stats("test.txt");
+        assert_eq!(txt["totals"]["lines"], 3);
+
+        let mixed = stats("*");
+        assert!(mixed["notes"][0]
+            .as_str()
+            .unwrap()
+            .contains("1 file(s) skipped"));
+
+        assert!(workspace_stats(&paths, "missing.md", None).is_err());
+        assert!(workspace_stats(&paths, "../outside", None).is_err());
     }
 
     #[test]
@@ -1274,10 +4455,15 @@ mod tests {
 
         let names: Vec<&str> = schemas.iter().map(|t| t.function.name.as_str()).collect();
         assert!(names.contains(&"read_file"));
+        assert!(names.contains(&"read_many_files"));
         assert!(names.contains(&"write_file"));
+        assert!(names.contains(&"edit_file"));
+        assert!(names.contains(&"move_file"));
+        assert!(names.contains(&"copy_file"));
         assert!(names.contains(&"list_dir"));
         assert!(names.contains(&"glob"));
         assert!(names.contains(&"grep"));
+        assert!(names.contains(&"workspace_stats"));
         assert!(names.contains(&"run_shell"));
     }
 
@@ -1285,7 +4471,14 @@ mod tests {
     fn test_dispatch_read_file() {
         let dir = setup_test_workspace();
         let args = serde_json::json!({"path": "test.txt"});
-        let result = dispatch_tool(dir.path(), "read_file", &args, 30);
+        let result = dispatch_tool(
+            &PathValidator::new(dir.path()),
+            "read_file",
+            &args,
+            &ShellSettings::default(),
+            None,
+        )
+        .map(String::from);
         assert!(result.is_ok());
     }
 
@@ -1293,11 +4486,45 @@ mod tests {
     fn test_dispatch_unknown_tool() {
         let dir = setup_test_workspace();
         let args = serde_json::json!({});
-        let result = dispatch_tool(dir.path(), "unknown_tool", &args, 30);
+        let result = dispatch_tool(
+            &PathValidator::new(dir.path()),
+            "unknown_tool",
+            &args,
+            &ShellSettings::default(),
+            None,
+        )
+        .map(String::from);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Unknown tool"));
     }
 
+    #[test]
+    fn test_run_shell_blocked_by_policy() {
+        let dir = setup_test_workspace();
+        let shell = ShellSettings {
+            timeout: 30,
+            policy: ShellPolicy {
+                allow: vec!["echo *".to_string()],
+                ..ShellPolicy::default()
+            },
+            ..ShellSettings::default()
+        };
+        let paths = PathValidator::new(dir.path());
+
+        let args = serde_json::json!({"command": "touch made.txt"});
+        let err = dispatch_tool(&paths, "run_shell", &args, &shell, None)
+            .map(String::from)
+            .unwrap_err();
+        assert!(err.starts_with(POLICY_BLOCKED), "{}", err);
+        assert!(!dir.path().join("made.txt").exists());
+
+        let args = serde_json::json!({"command": "echo allowed"});
+        let output = dispatch_tool(&paths, "run_shell", &args, &shell, None)
+            .map(String::from)
+            .unwrap();
+        assert!(output.contains("allowed"));
+    }
+
     /// Test that symlinks are rejected for security (TOCTOU prevention)
     #[cfg(unix)]
     #[test]
@@ -1311,7 +4538,7 @@ mod tests {
         symlink("/etc/passwd", &symlink_path).unwrap();
 
         // Should reject the symlink
-        let result = safe_path(dir.path(), "evil_link");
+        let result = resolve_both(dir.path(), "evil_link");
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert!(err.contains("Symlinks not allowed"));
@@ -1333,7 +4560,7 @@ mod tests {
         symlink(outside_dir.path(), &symlink_dir).unwrap();
 
         // Trying to access file through symlink directory should fail
-        let result = safe_path(dir.path(), "linked_dir/secret.txt");
+        let result = resolve_both(dir.path(), "linked_dir/secret.txt");
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert!(err.contains("Symlinks not allowed"));
@@ -1348,7 +4575,7 @@ mod tests {
         fs::write(dir.path().join(".env"), "SECRET=value").unwrap();
 
         // Should reject .env file
-        let result = safe_path(dir.path(), ".env");
+        let result = resolve_both(dir.path(), ".env");
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert!(err.contains("Access denied"));
@@ -1371,7 +4598,7 @@ mod tests {
 
         for file_name in sensitive_files {
             fs::write(dir.path().join(file_name), "sensitive data").unwrap();
-            let result = safe_path(dir.path(), file_name);
+            let result = resolve_both(dir.path(), file_name);
             assert!(result.is_err(), "Should block {}", file_name);
             assert!(result.unwrap_err().contains("Access denied"));
         }
@@ -1387,7 +4614,7 @@ mod tests {
 
         for file_name in sensitive_extensions {
             fs::write(dir.path().join(file_name), "key data").unwrap();
-            let result = safe_path(dir.path(), file_name);
+            let result = resolve_both(dir.path(), file_name);
             assert!(result.is_err(), "Should block {}", file_name);
             let err = result.unwrap_err();
             assert!(
@@ -1399,6 +4626,30 @@ mod tests {
         }
     }
 
+    /// Test that the workspace security file can allow and deny paths
+    #[test]
+    fn test_sensitive_policy_from_workspace() {
+        let dir = setup_test_workspace();
+        fs::write(dir.path().join("secrets.json"), "{}").unwrap();
+        fs::write(dir.path().join("notes.md"), "notes").unwrap();
+
+        let validator = PathValidator::new(dir.path());
+        assert!(validator.resolve("secrets.json").is_err());
+        assert!(validator.resolve("notes.md").is_ok());
+
+        fs::create_dir_all(dir.path().join(".vswrite")).unwrap();
+        fs::write(
+            dir.path().join(sensitive_paths::SECURITY_FILE),
+            "[sensitive_files]\ndeny = [\"notes.md\"]\nallow = [\"secrets.json\"]\n",
+        )
+        .unwrap();
+
+        assert!(resolve_both(dir.path(), "secrets.json").is_ok());
+        // A cached resolution is re-checked against the new policy
+        let err = validator.resolve("notes.md").unwrap_err();
+        assert!(err.contains("deny pattern 'notes.md'"));
+    }
+
     /// Test that regular files are still allowed
     #[test]
     fn test_regular_files_allowed() {
@@ -1420,8 +4671,655 @@ mod tests {
                 fs::create_dir_all(parent).unwrap();
             }
             fs::write(dir.path().join(file_name), "content").unwrap();
-            let result = safe_path(dir.path(), file_name);
+            let result = resolve_both(dir.path(), file_name);
             assert!(result.is_ok(), "Should allow {}", file_name);
         }
     }
+
+    #[test]
+    fn test_validator_canonicalizes_workspace_once() {
+        let dir = setup_test_workspace();
+        let counter = Arc::new(CountingCanonicalize::default());
+        let paths = PathValidator::with_canonicalizer(dir.path(), counter.clone());
+
+        for _ in 0..10 {
+            paths.resolve("test.txt").unwrap();
+            paths.resolve("subdir/nested.md").unwrap();
+            paths.canonical_workspace().unwrap();
+        }
+
+        // Workspace once, one call per distinct path, then one re-check per cached use
+        assert_eq!(counter.calls(), 3 + 9 * 2);
+    }
+
+    #[test]
+    fn test_validator_reduces_canonicalize_calls() {
+        let dir = setup_test_workspace();
+        let requests = ["test.txt", "subdir/nested.md", "subdir", "new.txt"];
+
+        // Uncached: workspace and path are canonicalized on every call, as `safe_path` does
+        let uncached = CountingCanonicalize::default();
+        let canonical_workspace = dir.path().canonicalize().unwrap();
+        for _ in 0..25 {
+            for requested in requests {
+                let workspace = uncached.canonicalize(dir.path()).unwrap();
+                assert_eq!(workspace, canonical_workspace);
+                resolve_safe_path(dir.path(), &workspace, requested, &uncached).unwrap();
+            }
+        }
+
+        let cached = Arc::new(CountingCanonicalize::default());
+        let paths = PathValidator::with_canonicalizer(dir.path(), cached.clone());
+        for _ in 0..25 {
+            for requested in requests {
+                paths.resolve(requested).unwrap();
+            }
+        }
+
+        assert_eq!(uncached.calls(), 200);
+        assert_eq!(cached.calls(), 5 + 24 * 4);
+    }
+
+    fn is_cached(paths: &PathValidator, requested: &str) -> bool {
+        paths
+            .lock_cache()
+            .entries
+            .iter()
+            .any(|(r, _)| r == requested)
+    }
+
+    #[test]
+    fn test_validator_lru_eviction() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        paths.resolve("test.txt").unwrap();
+
+        // Fill the cache with other entries; "test.txt" is least recently used
+        for i in 0..PATH_CACHE_CAPACITY {
+            paths.resolve(&format!("missing_{}.txt", i)).unwrap();
+        }
+        assert!(!is_cached(&paths, "test.txt"));
+        assert!(is_cached(&paths, "missing_1.txt"));
+
+        // Re-resolving it evicts the next least recently used entry
+        paths.resolve("test.txt").unwrap();
+        assert!(is_cached(&paths, "test.txt"));
+        assert!(!is_cached(&paths, "missing_0.txt"));
+        assert!(is_cached(
+            &paths,
+            &format!("missing_{}.txt", PATH_CACHE_CAPACITY - 1)
+        ));
+    }
+
+    #[test]
+    fn test_validator_invalidated_by_writes_and_deletes() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        paths.resolve("new.txt").unwrap();
+        write_file(&paths, "new.txt", "hello").unwrap();
+        assert!(!is_cached(&paths, "new.txt"));
+        let resolved = paths.resolve("new.txt").unwrap();
+        assert_eq!(resolved, dir.path().canonicalize().unwrap().join("new.txt"));
+
+        delete_file(&paths, "new.txt").unwrap();
+        assert!(!is_cached(&paths, "new.txt"));
+    }
+
+    /// A directory swapped for a symlink after its path was cached is caught on reuse
+    #[cfg(unix)]
+    #[test]
+    fn test_validator_rechecks_cached_paths() {
+        let dir = setup_test_workspace();
+        let outside = TempDir::new().unwrap();
+        fs::write(outside.path().join("nested.md"), "outside").unwrap();
+        let paths = PathValidator::new(dir.path());
+        paths.resolve("subdir/nested.md").unwrap();
+        paths.resolve("subdir/new.md").unwrap();
+
+        fs::remove_dir_all(dir.path().join("subdir")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("subdir")).unwrap();
+
+        assert!(paths.resolve("subdir/nested.md").is_err());
+        assert!(paths.resolve("subdir/new.md").is_err());
+    }
+
+    /// A symlink created by a shell command must not be masked by a cached entry
+    #[cfg(unix)]
+    #[test]
+    fn test_validator_cleared_after_shell() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        assert!(paths.resolve("late_link").is_ok());
+        run_shell(
+            &paths,
+            "ln -s /etc/passwd late_link",
+            None,
+            Some(10),
+            &ShellSettings::default(),
+            None,
+        )
+        .unwrap();
+
+        let err = paths.resolve("late_link").unwrap_err();
+        assert!(err.contains("Symlinks not allowed"));
+    }
+
+    #[test]
+    fn test_dispatch_write_then_read_with_shared_validator() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let args = serde_json::json!({"path": "notes/today.md", "content": "draft"});
+        dispatch_tool(&paths, "write_file", &args, &ShellSettings::default(), None).unwrap();
+
+        let args = serde_json::json!({"path": "notes/today.md"});
+        let content = dispatch_tool(&paths, "read_file", &args, &ShellSettings::default(), None)
+            .map(String::from)
+            .unwrap();
+        assert!(content.contains("draft"));
+    }
+
+    /// Cancel `token` from another thread after `delay`
+    fn cancel_after(token: &CancellationToken, delay: Duration) -> std::thread::JoinHandle<()> {
+        let token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(delay);
+            token.cancel();
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_shell_cancelled_mid_command() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        let token = CancellationToken::new();
+        let canceller = cancel_after(&token, Duration::from_millis(200));
+
+        let started = std::time::Instant::now();
+        let result = run_shell(
+            &paths,
+            "sleep 30",
+            None,
+            Some(60),
+            &ShellSettings::default(),
+            Some(&token),
+        )
+        .map(String::from);
+        canceller.join().unwrap();
+
+        assert_eq!(result.unwrap_err(), TOOL_CANCELLED);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// A timeout kills what the command started in the background too
+    #[cfg(unix)]
+    #[test]
+    fn test_run_shell_timeout_kills_process_group() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let result = run_shell(
+            &paths,
+            "sleep 30 & echo $! > bg.pid; wait",
+            None,
+            Some(1),
+            &ShellSettings::default(),
+            None,
+        )
+        .map(String::from);
+        assert_eq!(result.unwrap_err(), "Command timed out after 1 seconds");
+
+        let pid = fs::read_to_string(dir.path().join("bg.pid")).unwrap();
+        let alive = || {
+            let stat = Command::new("ps")
+                .args(["-o", "stat=", "-p", pid.trim()])
+                .output()
+                .unwrap();
+            let stat = String::from_utf8_lossy(&stat.stdout);
+            // A killed child nobody has reaped yet shows as a zombie
+            !stat.trim().is_empty() && !stat.trim().starts_with('Z')
+        };
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while alive() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!alive(), "background sleep {} survived", pid.trim());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_run_shell_powershell_keeps_quotes_and_unicode() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let result = run_shell(
+            &paths,
+            r#"Write-Output "a b"; Write-Output 'café'; exit 3"#,
+            None,
+            Some(30),
+            &ShellSettings::default(),
+            None,
+        )
+        .map(String::from)
+        .unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["exit_code"], 3);
+        let lines: Vec<&str> = result["output"].as_str().unwrap().lines().collect();
+        assert_eq!(lines, vec!["a b", "café"]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_run_shell_cmd_keeps_quotes() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        let settings = ShellSettings {
+            shell: Some("cmd".to_string()),
+            ..ShellSettings::default()
+        };
+
+        let result = run_shell(
+            &paths,
+            r#"echo "quoted arg" && echo second"#,
+            None,
+            Some(30),
+            &settings,
+            None,
+        )
+        .map(String::from)
+        .unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["exit_code"], 0);
+        let lines: Vec<&str> = result["output"]
+            .as_str()
+            .unwrap()
+            .lines()
+            .map(str::trim_end)
+            .collect();
+        assert_eq!(lines, vec!["\"quoted arg\"", "second"]);
+    }
+
+    /// A command that fills the pipe buffers before exiting must not deadlock
+    #[cfg(unix)]
+    #[test]
+    fn test_run_shell_large_output_does_not_deadlock() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let started = std::time::Instant::now();
+        let result = run_shell(
+            &paths,
+            "seq -f '%0100g' 1 5000; seq -f '%0100g' 1 5000 >&2",
+            None,
+            Some(20),
+            &ShellSettings::default(),
+            None,
+        )
+        .map(String::from)
+        .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["exit_code"], 0);
+        let output = result["output"].as_str().unwrap();
+        assert!(output.starts_with(&format!("{:0100}\n{:0100}\n", 1, 2)));
+        let (kept, _) = output.split_once("...[Truncated: ").unwrap();
+        assert!(kept.len() <= SHELL_OUTPUT_BYTES);
+        // 4500 stdout and 4900 stderr lines are past the line caps
+        assert!(output.ends_with("\n[Truncated: 9400 lines omitted]"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_shell_honors_output_limit() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path()).with_tool_limits(ToolLimits {
+            shell_output_bytes: 100,
+            ..ToolLimits::default()
+        });
+        let run = |command: &str| {
+            let result = run_shell(&paths, command, None, None, &ShellSettings::default(), None)
+                .map(String::from)
+                .unwrap();
+            let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+            result["output"].as_str().unwrap().to_string()
+        };
+
+        // `seq 1 100` prints 292 bytes
+        let output = run("seq 1 100");
+        assert!(output.starts_with("1\n2\n"));
+        assert!(output.ends_with("...[Truncated: 192 bytes omitted]"));
+        assert_eq!(
+            output.len(),
+            100 + "...[Truncated: 192 bytes omitted]".len()
+        );
+        assert!(reports_truncation(&output));
+
+        assert!(!reports_truncation(&run("echo done")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_shell_streams_output() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+        let started = std::time::Instant::now();
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let chunks = chunks.clone();
+            OutputSink::new(move |text| {
+                chunks
+                    .lock()
+                    .unwrap()
+                    .push((text.to_string(), started.elapsed()))
+            })
+        };
+
+        let result = run_shell(
+            &paths,
+            "echo first; sleep 1; echo second; echo oops >&2",
+            None,
+            Some(10),
+            &ShellSettings {
+                output: Some(sink),
+                ..ShellSettings::default()
+            },
+            None,
+        )
+        .map(String::from)
+        .unwrap();
+        let total = started.elapsed();
+
+        let chunks = chunks.lock().unwrap();
+        let texts: Vec<&str> = chunks.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(texts[0], "first\n");
+        // stdout and stderr are read separately, so their relative order can vary
+        let mut rest = texts[1..].to_vec();
+        rest.sort();
+        assert_eq!(rest, vec!["oops\n", "second\n"]);
+        // The first line arrived while the command was still sleeping
+        assert!(chunks[0].1 + Duration::from_millis(500) < total);
+
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(
+            result["output"].as_str().unwrap(),
+            "first\nsecond\n\n--- stderr ---\noops"
+        );
+    }
+
+    #[test]
+    fn test_shell_output_caps() {
+        let streamed = Arc::new(Mutex::new(0usize));
+        let sink = {
+            let streamed = streamed.clone();
+            OutputSink::new(move |text| *streamed.lock().unwrap() += text.len())
+        };
+
+        let mut output = ShellOutput::new(SHELL_OUTPUT_BYTES);
+        for i in 0..1000 {
+            output.push(ShellStream::Stdout, format!("{:040}", i), Some(&sink));
+            output.push(ShellStream::Stderr, format!("err {}", i), Some(&sink));
+        }
+        assert_eq!(output.stdout_lines, SHELL_STDOUT_LINES);
+        assert_eq!(output.stderr.len(), SHELL_STDERR_LINES);
+        let streamed = *streamed.lock().unwrap();
+        assert!((SHELL_OUTPUT_BYTES..SHELL_OUTPUT_BYTES + 50).contains(&streamed));
+
+        // 500 stdout lines of 41 bytes, the separator, and 100 stderr lines
+        // make 21205 bytes; 500 + 900 lines were dropped
+        let finished = output.finish();
+        assert!(finished
+            .ends_with("...[Truncated: 11205 bytes omitted]\n[Truncated: 1400 lines omitted]"));
+    }
+
+    #[test]
+    fn test_grep_cancelled_during_traversal() {
+        let dir = setup_test_workspace();
+        for i in 0..200 {
+            let sub = dir.path().join(format!("dir_{}", i));
+            fs::create_dir(&sub).unwrap();
+            fs::write(sub.join("notes.md"), "nothing to see\n".repeat(500)).unwrap();
+        }
+        let paths = PathValidator::new(dir.path());
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = grep_files(&paths, "needle", ".", GrepOptions::default(), Some(&token))
+            .map(String::from);
+        assert_eq!(result.unwrap_err(), TOOL_CANCELLED);
+
+        // The same search completes when not cancelled
+        let result = grep_files(
+            &paths,
+            "needle",
+            ".",
+            GrepOptions::default(),
+            Some(&CancellationToken::new()),
+        )
+        .map(String::from);
+        assert_eq!(result.unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_glob_cancelled() {
+        let dir = setup_test_workspace();
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = glob_files(&PathValidator::new(dir.path()), "**/*", ".", Some(&token))
+            .map(String::from);
+        assert_eq!(result.unwrap_err(), TOOL_CANCELLED);
+    }
+
+    #[test]
+    fn test_dispatch_refuses_after_cancel() {
+        let dir = setup_test_workspace();
+        let token = CancellationToken::new();
+        token.cancel();
+        let args = serde_json::json!({"path": "never.txt", "content": "x"});
+        let result = dispatch_tool(
+            &PathValidator::new(dir.path()),
+            "write_file",
+            &args,
+            &ShellSettings::default(),
+            Some(&token),
+        )
+        .map(String::from);
+        assert_eq!(result.unwrap_err(), TOOL_CANCELLED);
+        assert!(!dir.path().join("never.txt").exists());
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_augmented_path_order() {
+        let path = augmented_path("/home/me/bin:/usr/bin:/custom/bin", Some("/home/me"));
+        assert_eq!(
+            path,
+            vec![
+                "/home/me/.cargo/bin",
+                "/home/me/.local/bin",
+                "/usr/local/bin",
+                "/usr/local/sbin",
+                "/bin",
+                "/usr/sbin",
+                "/sbin",
+                "/home/me/bin",
+                "/usr/bin",
+                "/custom/bin",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_augmented_path_order() {
+        let path = augmented_path("/usr/bin:/bin", Some("/Users/me"));
+        assert_eq!(
+            path,
+            vec![
+                "/Users/me/.cargo/bin",
+                "/Users/me/.local/bin",
+                "/opt/homebrew/bin",
+                "/opt/homebrew/sbin",
+                "/usr/local/bin",
+                "/usr/local/sbin",
+                "/usr/sbin",
+                "/sbin",
+                "/usr/bin",
+                "/bin",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_augmented_path_dedupes() {
+        let path = augmented_path("/usr/bin::/opt/x:/usr/bin:/opt/x:/usr/local/bin", None);
+
+        let mut seen = std::collections::HashSet::new();
+        assert!(path.iter().all(|p| seen.insert(p.clone())));
+        assert!(!path.iter().any(|p| p.is_empty()));
+        // Without HOME there are no per-user entries
+        assert!(!path.iter().any(|p| p.ends_with(".cargo/bin")));
+        // Entries already on PATH keep their relative order
+        let pos = |e: &str| path.iter().position(|p| p == e).unwrap();
+        assert!(pos("/usr/bin") < pos("/opt/x"));
+        assert!(pos("/opt/x") < pos("/usr/local/bin"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_augmented_path_windows_appends_extras() {
+        let path = augmented_path(
+            r"C:\Windows\system32;;C:\Tools;c:\windows\System32;C:\Program Files\Git\cmd",
+            Some(r"C:\Users\me"),
+        );
+        assert_eq!(
+            path,
+            vec![
+                r"C:\Windows\system32",
+                r"C:\Tools",
+                r"C:\Program Files\Git\cmd",
+                r"C:\Users\me\.cargo\bin",
+                r"C:\Users\me\scoop\shims",
+                r"C:\Users\me\AppData\Local\Microsoft\WindowsApps",
+                r"C:\Program Files\PowerShell\7",
+                r"C:\Program Files\nodejs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shell_environment_for_workspace() {
+        let dir = setup_test_workspace();
+        let paths = PathValidator::new(dir.path());
+
+        let working_dir = shell_working_dir(&paths, Some("subdir")).unwrap();
+        assert!(working_dir.ends_with("subdir"));
+        assert!(shell_working_dir(&paths, Some("missing")).is_err());
+
+        let env = ShellEnvironment::for_working_dir(
+            &shell_working_dir(&paths, None).unwrap(),
+            &ShellEnv::default(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(env.working_dir, dir.path().display().to_string());
+        assert!(!env.path.is_empty());
+        if cfg!(target_os = "windows") {
+            assert!(env.shell == "pwsh" || env.shell == "powershell");
+            assert_eq!(env.shell_arg, "-NoProfile -NonInteractive -EncodedCommand");
+        } else {
+            assert_eq!(env.shell, "sh");
+            assert_eq!(env.shell_arg, "-c");
+        }
+
+        let cmd = ShellEnvironment::for_working_dir(dir.path(), &ShellEnv::default(), Some("cmd"))
+            .unwrap();
+        assert_eq!(
+            (cmd.shell.as_str(), cmd.shell_arg.as_str()),
+            ("cmd", "/S /C")
+        );
+        assert!(ShellEnvironment::for_working_dir(
+            dir.path(),
+            &ShellEnv::default(),
+            Some("python")
+        )
+        .is_err());
+    }
+
+    /// The child sees only the base and allow-listed variables, never a secret
+    /// that wasn't forced
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_child_env_is_filtered() {
+        let dir = setup_test_workspace();
+        let vars = [
+            ("HOME", "/home/writer"),
+            ("OPENAI_API_KEY", "sk-leak"),
+            ("GITHUB_TOKEN", "ghp-leak"),
+            ("EDITOR", "vim"),
+            ("NPM_TOKEN", "npm-forced"),
+            ("UNLISTED", "nope"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+        let allowlist = ["EDITOR", "GITHUB_TOKEN", "!NPM_TOKEN"].map(String::from);
+        let env = ShellEnv::from_vars(vars, &allowlist);
+
+        let result = execute_shell(
+            shell_command("env", dir.path(), &env, None).unwrap(),
+            env.note(),
+            Duration::from_secs(10),
+            SHELL_OUTPUT_BYTES,
+            None,
+            None,
+        )
+        .map(String::from)
+        .unwrap();
+        let result: serde_json::Value = serde_json::from_str(&result).unwrap();
+        let output = result["output"].as_str().unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.contains(&"HOME=/home/writer"), "{}", output);
+        assert!(lines.contains(&"EDITOR=vim"));
+        assert!(lines.contains(&"NPM_TOKEN=npm-forced"));
+        assert!(lines.iter().any(|line| line.starts_with("PATH=")));
+        assert!(!output.contains("leak"));
+        assert!(!output.contains("UNLISTED"));
+
+        let note = result["env_note"].as_str().unwrap();
+        assert!(note.starts_with("3 environment variable(s)"), "{}", note);
+        assert!(note.contains("GITHUB_TOKEN"));
+    }
+
+    #[test]
+    fn test_scratchpad_appends_within_cap() {
+        let pad = Scratchpad::new();
+        assert_eq!(pad.summary(), None);
+        assert!(pad.read().contains("empty"));
+
+        let args = serde_json::json!({"note": "Chapter 3 contradicts the timeline\nCheck dates"});
+        dispatch_scratchpad(&pad, "scratchpad_write", &args).unwrap();
+        pad.write("Rename Bob").unwrap();
+        assert!(pad.write("  ").is_err());
+
+        let read = dispatch_scratchpad(&pad, "scratchpad_read", &serde_json::json!({})).unwrap();
+        assert!(read.starts_with("[1] Chapter 3"), "{}", read);
+        assert!(read.contains("[2] Rename Bob"));
+        let summary = pad.summary().unwrap();
+        assert!(summary.starts_with("2 scratchpad note(s)"), "{}", summary);
+        assert!(
+            summary.ends_with("\"Chapter 3 contradicts the timeline\""),
+            "{}",
+            summary
+        );
+
+        let err = pad.write(&"x".repeat(SCRATCHPAD_MAX_BYTES)).unwrap_err();
+        assert!(err.contains("full"), "{}", err);
+        assert_eq!(
+            pad.contents(),
+            "Chapter 3 contradicts the timeline\nCheck dates\n\nRename Bob"
+        );
+    }
 }
@@ -0,0 +1,327 @@
+//! Conversation transcripts, so a run can be resumed after the app restarts.
+//!
+//! Each session's conversation is appended to `<app data>/transcripts/<session_id>.jsonl`
+//! as the run goes: a header line with what resuming needs (workspace, task, and
+//! config without the API key), then one line per message. The system prompt is
+//! never written; whoever resumes the session supplies it again.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::session::MAX_SESSIONS;
+use super::types::{AgentConfig, Message, MessageRole};
+
+/// Directory under the app data dir that holds transcripts
+pub const TRANSCRIPTS_DIR: &str = "transcripts";
+
+/// Stands in for the API key wherever it turns up in a message
+const REDACTED_KEY: &str = "[REDACTED]";
+
+/// What a transcript records about its run besides the messages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptHeader {
+    pub session_id: String,
+    pub created_at: DateTime<Utc>,
+    pub workspace: PathBuf,
+    pub task: String,
+    /// The run's config; `api_key` is always empty
+    pub config: AgentConfig,
+    /// The session this one continues, for a resumed run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resumed_from: Option<String>,
+}
+
+/// A session's saved conversation, without the system prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transcript {
+    pub header: TranscriptHeader,
+    pub messages: Vec<Message>,
+}
+
+/// One line of a transcript file
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TranscriptLine {
+    Header(Box<TranscriptHeader>),
+    Message(Message),
+}
+
+/// Appends one session's messages to its transcript file
+pub struct TranscriptWriter {
+    file: File,
+    api_key: String,
+}
+
+impl TranscriptWriter {
+    /// Write `messages` (system prompts excepted) and flush them to disk
+    pub fn append(&mut self, messages: &[Message]) -> io::Result<()> {
+        let mut lines = String::new();
+        for message in messages {
+            if matches!(message.role, MessageRole::System | MessageRole::Developer) {
+                continue;
+            }
+            let line = TranscriptLine::Message(self.redact(message.clone()));
+            lines.push_str(&serde_json::to_string(&line).map_err(io::Error::other)?);
+            lines.push('\n');
+        }
+        if lines.is_empty() {
+            return Ok(());
+        }
+        self.file.write_all(lines.as_bytes())?;
+        self.file.sync_data()
+    }
+
+    /// Replace the API key anywhere the model or a tool repeated it
+    fn redact(&self, mut message: Message) -> Message {
+        if self.api_key.is_empty() {
+            return message;
+        }
+        if let Some(content) = message.content.as_mut() {
+            if content.contains(&self.api_key) {
+                *content = content.replace(&self.api_key, REDACTED_KEY);
+            }
+        }
+        for call in message.tool_calls.iter_mut().flatten() {
+            if call.function.arguments.contains(&self.api_key) {
+                call.function.arguments =
+                    call.function.arguments.replace(&self.api_key, REDACTED_KEY);
+            }
+        }
+        message
+    }
+}
+
+/// Transcript files in the app data dir
+pub struct TranscriptStore {
+    dir: PathBuf,
+    max_transcripts: usize,
+}
+
+/// Shared transcript store type for Tauri state
+pub type SharedTranscriptStore = Arc<TranscriptStore>;
+
+impl TranscriptStore {
+    /// Keep as many transcripts as the session store keeps sessions
+    pub fn new(dir: PathBuf) -> Self {
+        TranscriptStore {
+            dir,
+            max_transcripts: MAX_SESSIONS,
+        }
+    }
+
+    /// Start a transcript, pruning the oldest ones past the limit. The header's
+    /// config is stored without its API key.
+    pub fn create(&self, mut header: TranscriptHeader) -> Result<TranscriptWriter, String> {
+        check_session_id(&header.session_id)?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+
+        let path = self.path(&header.session_id);
+        let mut file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let api_key = std::mem::take(&mut header.config.api_key);
+        let line = serde_json::to_string(&TranscriptLine::Header(Box::new(header)))
+            .map_err(|e| format!("Failed to encode transcript header: {}", e))?;
+        writeln!(file, "{}", line)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        self.prune();
+        Ok(TranscriptWriter { file, api_key })
+    }
+
+    /// Read a session's transcript. A final line cut short by a crash is skipped.
+    pub fn load(&self, session_id: &str) -> Result<Transcript, String> {
+        check_session_id(session_id)?;
+        let path = self.path(session_id);
+        let file = File::open(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => format!("No transcript for session {}", session_id),
+            _ => format!("Failed to open {}: {}", path.display(), e),
+        })?;
+        parse_transcript(BufReader::new(file), session_id)
+    }
+
+    fn path(&self, session_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", session_id))
+    }
+
+    /// Delete the oldest transcripts past `max_transcripts`
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(PathBuf, std::time::SystemTime)> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+                    return None;
+                }
+                Some((path, entry.metadata().ok()?.modified().ok()?))
+            })
+            .collect();
+        if files.len() <= self.max_transcripts {
+            return;
+        }
+        files.sort_by_key(|(_, modified)| *modified);
+        let excess = files.len() - self.max_transcripts;
+        for (path, _) in files.into_iter().take(excess) {
+            if let Err(e) = fs::remove_file(&path) {
+                log::warn!("Failed to prune transcript {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+fn parse_transcript(reader: impl BufRead, session_id: &str) -> Result<Transcript, String> {
+    let mut header = None;
+    let mut messages = Vec::new();
+    let mut lines = reader.lines().peekable();
+    while let Some(line) = lines.next() {
+        let line = line.map_err(|e| format!("Failed to read transcript: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<TranscriptLine>(&line) {
+            Ok(TranscriptLine::Header(h)) if header.is_none() => header = Some(*h),
+            Ok(TranscriptLine::Header(_)) => {
+                return Err(format!("Transcript {} has two headers", session_id))
+            }
+            Ok(TranscriptLine::Message(m)) if header.is_some() => messages.push(m),
+            Ok(TranscriptLine::Message(_)) => {
+                return Err(format!("Transcript {} has no header", session_id))
+            }
+            Err(e) if lines.peek().is_none() => {
+                log::warn!(
+                    "Skipping incomplete last line of transcript {}: {}",
+                    session_id,
+                    e
+                );
+            }
+            Err(e) => return Err(format!("Transcript {} is corrupt: {}", session_id, e)),
+        }
+    }
+    let header = header.ok_or_else(|| format!("Transcript {} is empty", session_id))?;
+    Ok(Transcript { header, messages })
+}
+
+/// Session IDs become file names, so only UUID characters are allowed
+fn check_session_id(session_id: &str) -> Result<(), String> {
+    let valid = !session_id.is_empty()
+        && session_id.len() <= 64
+        && session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid session ID: '{}'", session_id))
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::types::{FunctionCall, ToolCall};
+    use tempfile::TempDir;
+
+    const SESSION: &str = "3f2c9a1e-0000-4000-8000-000000000001";
+
+    fn header(session_id: &str) -> TranscriptHeader {
+        TranscriptHeader {
+            session_id: session_id.to_string(),
+            created_at: Utc::now(),
+            workspace: PathBuf::from("/novel"),
+            task: "Tighten chapter 2".to_string(),
+            config: AgentConfig::new("sk-secret-key-123"),
+            resumed_from: None,
+        }
+    }
+
+    #[test]
+    fn test_transcript_roundtrip_without_secrets() {
+        let dir = TempDir::new().unwrap();
+        let store = TranscriptStore::new(dir.path().to_path_buf());
+        let mut writer = store.create(header(SESSION)).unwrap();
+
+        let call = ToolCall {
+            id: "call-1".to_string(),
+            call_type: "function".to_string(),
+            function: FunctionCall {
+                name: "run_shell".to_string(),
+                arguments: r#"{"command":"echo sk-secret-key-123"}"#.to_string(),
+            },
+        };
+        writer
+            .append(&[
+                Message::system("You are a writing assistant."),
+                Message::user("Tighten chapter 2"),
+                Message::assistant_with_tools(None, vec![call]),
+            ])
+            .unwrap();
+        writer
+            .append(&[Message::tool_result("call-1", "sk-secret-key-123")])
+            .unwrap();
+
+        let raw = fs::read_to_string(dir.path().join(format!("{}.jsonl", SESSION))).unwrap();
+        assert!(!raw.contains("sk-secret-key-123"), "{}", raw);
+        assert!(!raw.contains("writing assistant"));
+
+        let transcript = store.load(SESSION).unwrap();
+        assert_eq!(transcript.header.task, "Tighten chapter 2");
+        assert!(transcript.header.config.api_key.is_empty());
+        let roles: Vec<MessageRole> = transcript.messages.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            vec![MessageRole::User, MessageRole::Assistant, MessageRole::Tool]
+        );
+        assert_eq!(
+            transcript.messages[2].content.as_deref(),
+            Some("[REDACTED]")
+        );
+    }
+
+    #[test]
+    fn test_load_skips_torn_last_line() {
+        let dir = TempDir::new().unwrap();
+        let store = TranscriptStore::new(dir.path().to_path_buf());
+        let mut writer = store.create(header(SESSION)).unwrap();
+        writer.append(&[Message::user("Hello")]).unwrap();
+        writer.file.write_all(br#"{"type":"message","ro"#).unwrap();
+
+        let transcript = store.load(SESSION).unwrap();
+        assert_eq!(transcript.messages.len(), 1);
+
+        assert!(store.load("../etc/passwd").is_err());
+        assert!(store
+            .load("00000000-0000-0000-0000-000000000000")
+            .unwrap_err()
+            .contains("No transcript"));
+    }
+
+    #[test]
+    fn test_prune_keeps_newest() {
+        let dir = TempDir::new().unwrap();
+        let store = TranscriptStore {
+            dir: dir.path().to_path_buf(),
+            max_transcripts: 2,
+        };
+        for i in 0..3 {
+            store.create(header(&format!("session-{}", i))).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert!(store.load("session-0").is_err());
+        assert!(store.load("session-1").is_ok());
+        assert!(store.load("session-2").is_ok());
+    }
+}
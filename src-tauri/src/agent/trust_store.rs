@@ -0,0 +1,338 @@
+//! Trusted publisher keys.
+//!
+//! A manifest signed with a trusted publisher's key verifies as trusted (see
+//! [`signature`](super::signature)). The built-in publishers ship with the app;
+//! users can add their own, such as an organization that signs its internal
+//! extensions. User keys are saved in `trusted_publishers.json` in the app data
+//! dir and merged with the built-ins, which always win: a user entry can neither
+//! replace nor remove one.
+//!
+//! Keys are pinned. A publisher keeps the key it was added with until it is
+//! removed, so a different key can't quietly take over an existing publisher ID.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use super::atomic_write::write_atomic;
+
+/// File in the app data dir that holds the user's publisher keys
+pub const TRUSTED_PUBLISHERS_FILE: &str = "trusted_publishers.json";
+
+/// Longest publisher ID accepted
+const MAX_PUBLISHER_ID_LEN: usize = 64;
+
+/// Publishers trusted out of the box, as base64-encoded Ed25519 public keys
+/// (raw 32-byte keys)
+static BUILT_IN_PUBLISHERS: &[(&str, &str)] = &[
+    // VS Write official key - used to sign bundled extensions
+    (
+        "vswrite-official",
+        "Nqh5oHbH6TO6WrAV1r64m0Z8FWhQru7Ku75tDmMNqkA=",
+    ),
+];
+
+/// Where a trusted key came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublisherSource {
+    /// Ships with the app; can't be changed or removed
+    BuiltIn,
+    /// Added by the user
+    User,
+}
+
+/// A publisher whose signatures are trusted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedPublisher {
+    pub id: String,
+    /// Base64-encoded Ed25519 public key
+    pub public_key: String,
+    pub source: PublisherSource,
+}
+
+/// A user key as saved on disk
+#[derive(Serialize, Deserialize)]
+struct SavedPublisher {
+    id: String,
+    public_key: String,
+}
+
+/// The built-in publisher keys plus the user's
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    /// Where user keys are saved; `None` keeps them in memory only
+    path: Option<PathBuf>,
+    /// User keys by publisher ID
+    user: RwLock<BTreeMap<String, String>>,
+}
+
+/// Shared trust store type for Tauri state
+pub type SharedTrustStore = Arc<TrustStore>;
+
+impl TrustStore {
+    /// The built-in keys plus the user keys saved at `path`. A missing file is an
+    /// empty list; entries with a malformed key or a built-in ID are skipped with a
+    /// warning.
+    pub fn load(path: PathBuf) -> Self {
+        let mut user = BTreeMap::new();
+        match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<Vec<SavedPublisher>>(&content) {
+                Ok(saved) => {
+                    for entry in saved {
+                        if let Err(e) = check_user_publisher(&entry.id, &entry.public_key) {
+                            log::warn!("Skipping trusted publisher in {}: {}", path.display(), e);
+                            continue;
+                        }
+                        user.insert(entry.id, entry.public_key);
+                    }
+                }
+                Err(e) => log::warn!("Failed to parse {}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to read {}: {}", path.display(), e),
+        }
+
+        TrustStore {
+            path: Some(path),
+            user: RwLock::new(user),
+        }
+    }
+
+    /// The key trusted for publisher `id`, built-ins first
+    pub fn key_for(&self, id: &str) -> Option<String> {
+        built_in_key(id).map(str::to_string).or_else(|| {
+            self.user
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(id)
+                .cloned()
+        })
+    }
+
+    /// Every trusted publisher, built-ins first
+    pub fn list(&self) -> Vec<TrustedPublisher> {
+        let built_in = BUILT_IN_PUBLISHERS
+            .iter()
+            .map(|(id, key)| TrustedPublisher {
+                id: id.to_string(),
+                public_key: key.to_string(),
+                source: PublisherSource::BuiltIn,
+            });
+        let user = self.user.read().unwrap_or_else(|e| e.into_inner());
+        let user = user.iter().map(|(id, key)| TrustedPublisher {
+            id: id.clone(),
+            public_key: key.clone(),
+            source: PublisherSource::User,
+        });
+        built_in.chain(user).collect()
+    }
+
+    /// Trust `public_key` for publisher `id` and save it. Adding a key that is
+    /// already trusted is a no-op; changing a publisher's key means removing it
+    /// first.
+    pub fn add(&self, id: &str, public_key: &str) -> Result<TrustedPublisher, String> {
+        let public_key = public_key.trim();
+        if let Err(e) = check_user_publisher(id, public_key) {
+            if built_in_key(id).is_some() {
+                log::warn!("Refused to add trusted publisher: {}", e);
+            }
+            return Err(e);
+        }
+
+        let mut user = self.user.write().unwrap_or_else(|e| e.into_inner());
+        match user.get(id) {
+            Some(existing) if existing == public_key => {}
+            Some(_) => {
+                return Err(format!(
+                    "Publisher '{}' is already trusted with a different key; remove it first to change its key",
+                    id
+                ))
+            }
+            None => {
+                user.insert(id.to_string(), public_key.to_string());
+                if let Err(e) = self.save(&user) {
+                    user.remove(id);
+                    return Err(e);
+                }
+                log::info!("Added trusted publisher '{}'", id);
+            }
+        }
+
+        Ok(TrustedPublisher {
+            id: id.to_string(),
+            public_key: public_key.to_string(),
+            source: PublisherSource::User,
+        })
+    }
+
+    /// Stop trusting a user publisher. Returns whether it was trusted.
+    pub fn remove(&self, id: &str) -> Result<bool, String> {
+        if built_in_key(id).is_some() {
+            return Err(format!(
+                "'{}' is a built-in publisher and can't be removed",
+                id
+            ));
+        }
+
+        let mut user = self.user.write().unwrap_or_else(|e| e.into_inner());
+        let Some(key) = user.remove(id) else {
+            return Ok(false);
+        };
+        if let Err(e) = self.save(&user) {
+            user.insert(id.to_string(), key);
+            return Err(e);
+        }
+        log::info!("Removed trusted publisher '{}'", id);
+        Ok(true)
+    }
+
+    fn save(&self, user: &BTreeMap<String, String>) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let saved: Vec<SavedPublisher> = user
+            .iter()
+            .map(|(id, key)| SavedPublisher {
+                id: id.clone(),
+                public_key: key.clone(),
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&saved)
+            .map_err(|e| format!("Failed to encode trusted publishers: {}", e))?;
+        write_atomic(path, json.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+fn built_in_key(id: &str) -> Option<&'static str> {
+    BUILT_IN_PUBLISHERS
+        .iter()
+        .find(|(built_in, _)| *built_in == id)
+        .map(|(_, key)| *key)
+}
+
+/// Check a publisher the user wants to trust: a new ID and a valid Ed25519 key
+fn check_user_publisher(id: &str, public_key: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_PUBLISHER_ID_LEN {
+        return Err(format!(
+            "Publisher ID must be 1-{} characters",
+            MAX_PUBLISHER_ID_LEN
+        ));
+    }
+    if !id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "Publisher ID '{}' may only contain letters, digits, '-', '_' and '.'",
+            id
+        ));
+    }
+    if built_in_key(id).is_some() {
+        return Err(format!(
+            "'{}' is a built-in publisher and can't be replaced",
+            id
+        ));
+    }
+    decode_public_key(public_key).map(|_| ())
+}
+
+/// Decode a base64-encoded Ed25519 public key
+pub fn decode_public_key(public_key_b64: &str) -> Result<VerifyingKey, String> {
+    let bytes = BASE64
+        .decode(public_key_b64)
+        .map_err(|e| format!("Invalid public key encoding: {}", e))?;
+
+    // Ed25519 public keys are 32 bytes
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| "Invalid public key length (expected 32 bytes)")?;
+
+    VerifyingKey::from_bytes(&bytes).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn public_key(seed: u8) -> String {
+        BASE64.encode(
+            SigningKey::from_bytes(&[seed; 32])
+                .verifying_key()
+                .to_bytes(),
+        )
+    }
+
+    #[test]
+    fn test_built_ins_take_precedence_over_saved_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TRUSTED_PUBLISHERS_FILE);
+        let saved = serde_json::json!([
+            {"id": "vswrite-official", "public_key": public_key(1)},
+            {"id": "acme", "public_key": public_key(2)},
+            {"id": "short-key", "public_key": "c2hvcnQ="}
+        ]);
+        fs::write(&path, saved.to_string()).unwrap();
+
+        let store = TrustStore::load(path);
+        assert_eq!(
+            store.key_for("vswrite-official").as_deref(),
+            built_in_key("vswrite-official")
+        );
+        assert_eq!(store.key_for("acme"), Some(public_key(2)));
+        assert_eq!(store.key_for("short-key"), None);
+
+        let sources: Vec<(String, PublisherSource)> =
+            store.list().into_iter().map(|p| (p.id, p.source)).collect();
+        assert_eq!(
+            sources,
+            vec![
+                ("vswrite-official".to_string(), PublisherSource::BuiltIn),
+                ("acme".to_string(), PublisherSource::User),
+            ]
+        );
+
+        assert!(store.add("vswrite-official", &public_key(1)).is_err());
+        assert!(store.remove("vswrite-official").is_err());
+    }
+
+    #[test]
+    fn test_user_keys_persist_and_are_pinned() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join(TRUSTED_PUBLISHERS_FILE);
+
+        let store = TrustStore::load(path.clone());
+        assert!(store.add("acme", "c2hvcnQ=").is_err());
+        let added = store.add("acme", &public_key(2)).unwrap();
+        assert_eq!(added.source, PublisherSource::User);
+        assert!(store.add("acme", &public_key(2)).is_ok());
+        let err = store.add("acme", &public_key(3)).unwrap_err();
+        assert!(err.contains("remove it first"), "{}", err);
+        drop(store);
+
+        // A restart sees the saved key
+        let store = TrustStore::load(path.clone());
+        assert_eq!(store.key_for("acme"), Some(public_key(2)));
+        assert_eq!(store.remove("acme"), Ok(true));
+        assert_eq!(store.remove("acme"), Ok(false));
+        drop(store);
+
+        let store = TrustStore::load(path);
+        assert_eq!(store.key_for("acme"), None);
+        assert_eq!(store.list().len(), BUILT_IN_PUBLISHERS.len());
+    }
+}
@@ -7,7 +7,14 @@
 //! - Event types for streaming to the frontend
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use super::redact::mask_key;
+use super::shell_policy::ShellPolicy;
+use super::text::truncate_to_char_boundary;
+use super::tool_output::ToolEffects;
+use super::tools::{FileLimits, ToolLimits, DEFAULT_MAX_FILE_BYTES};
 
 // ============================================================================
 // Tool Risk & Approval Types
@@ -17,9 +24,9 @@ use std::collections::HashMap;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ToolRisk {
-    /// Read-only operations: read_file, list_dir, glob, grep
+    /// Read-only operations: read_file, read_many_files, list_dir, glob, grep
     Low,
-    /// Write operations: write_file, append_file
+    /// Write operations: write_file, append_file, edit_file
     Medium,
     /// Destructive or arbitrary execution: delete_file, run_shell
     High,
@@ -31,15 +38,33 @@ impl ToolRisk {
         // Extract the base tool name (strip extension prefix if present)
         let base_name = if tool_name.contains(':') {
             // Extension tools can execute arbitrary logic (including shell/file ops),
-            // so default to High unless explicit per-tool risk metadata is added later.
+            // so default to High; ExtensionRegistry::tool_risk applies declared risks.
             return ToolRisk::High;
         } else {
             tool_name
         };
 
         match base_name {
-            "read_file" | "list_dir" | "glob" | "grep" => ToolRisk::Low,
-            "write_file" | "append_file" => ToolRisk::Medium,
+            "read_file" | "read_many_files" | "list_dir" | "glob" | "grep" | "workspace_stats" => {
+                ToolRisk::Low
+            }
+            "write_file" | "append_file" | "edit_file" | "begin_write" => ToolRisk::Medium,
+            // An overwriting move or copy must be asked for with `overwrite: true`
+            "move_file" | "copy_file" => ToolRisk::Medium,
+            // Chunked writes are approved once, at begin_write
+            "write_chunk" | "commit_write" | "abort_write" => ToolRisk::Low,
+            "git_status" | "git_diff" => ToolRisk::Low,
+            // Private notes in memory; nothing leaves the run
+            "scratchpad_write" | "scratchpad_read" => ToolRisk::Low,
+            "git_commit" => ToolRisk::Medium,
+            "suggest_entity_tags" | "find_entity_mentions" => ToolRisk::Low,
+            // Reads the index; the query goes to the run's own provider
+            "semantic_search" => ToolRisk::Low,
+            "apply_suggested_tags" => ToolRisk::Medium,
+            "get_section_meta" | "validate_tags" => ToolRisk::Low,
+            "update_section_meta" | "update_section_scene" => ToolRisk::Medium,
+            "insert_into_section" | "append_to_section" => ToolRisk::Medium,
+            "compile_manuscript" => ToolRisk::Medium,
             "delete_file" | "run_shell" => ToolRisk::High,
             _ => ToolRisk::Medium, // Unknown tools default to Medium
         }
@@ -59,7 +84,8 @@ pub enum ApprovalMode {
     ApproveWrites,
     /// Pause for approval on all tools
     ApproveAll,
-    /// Never execute - just show what would happen (for testing)
+    /// Run read-only (Low risk) tools; record everything else in a plan instead
+    /// of executing it
     DryRun,
 }
 
@@ -71,9 +97,55 @@ impl ApprovalMode {
             ApprovalMode::ApproveDangerous => risk >= ToolRisk::High,
             ApprovalMode::ApproveWrites => risk >= ToolRisk::Medium,
             ApprovalMode::ApproveAll => true,
-            ApprovalMode::DryRun => true,
+            // Calls above Low risk are planned, not run, so nothing waits on a user
+            ApprovalMode::DryRun => false,
         }
     }
+
+    /// Check if a tool is recorded in the plan instead of executed
+    pub fn plans_instead(&self, risk: ToolRisk) -> bool {
+        *self == ApprovalMode::DryRun && risk > ToolRisk::Low
+    }
+}
+
+/// Outcome of a tool approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    /// The user approved the call
+    Approved,
+    /// The user denied the call
+    Denied,
+    /// Nobody answered before the approval timeout
+    TimedOut,
+    /// No approval channel was available, so the call was let through
+    AutoApproved,
+}
+
+impl ApprovalDecision {
+    /// Whether the tool is allowed to run
+    pub fn allows_execution(self) -> bool {
+        matches!(
+            self,
+            ApprovalDecision::Approved | ApprovalDecision::AutoApproved
+        )
+    }
+}
+
+/// How far an approval decision reaches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalScope {
+    /// Applies to this tool call only
+    #[serde(alias = "once")]
+    Call,
+    /// Applies to later calls of the same tool in this run
+    Tool,
+    /// Approves every gated call no riskier than this one for the rest of this
+    /// run. A denial only covers the tool it was given for.
+    Run,
+    /// Granted for the rest of the session
+    Session,
 }
 
 // ============================================================================
@@ -100,6 +172,9 @@ pub struct PropertySchema {
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default: Option<serde_json::Value>,
+    /// Element schema for `array` properties
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<PropertySchema>>,
 }
 
 /// Function definition within a tool
@@ -190,7 +265,7 @@ impl ToolResult {
 // ============================================================================
 
 /// LLM provider selection
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum LlmProvider {
     #[default]
@@ -223,25 +298,247 @@ impl LlmProvider {
     }
 
     /// Check if this provider supports tool calling
-    #[allow(dead_code)]
+    ///
+    /// For Ollama this depends on the model; models without tool support fall back
+    /// to chat-only mode at request time.
     pub fn supports_tools(&self) -> bool {
         match self {
             LlmProvider::OpenAI => true,
             LlmProvider::Claude => true,
-            LlmProvider::Ollama => false, // Ollama doesn't support tools reliably
+            LlmProvider::Ollama => true,
             LlmProvider::OpenRouter => true,
         }
     }
 }
 
+/// How much file and conversation content tool/text events carry to the frontend.
+///
+/// Only affects emitted events; the conversation sent to the LLM and the audit
+/// log are unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EventDetail {
+    /// Full tool arguments, results, and text
+    #[default]
+    Full,
+    /// Long strings cut to a short preview
+    Truncated,
+    /// Content replaced by its size; names, paths, flags, and numbers are kept
+    MetadataOnly,
+}
+
+/// How tool output is protected before it goes back to the model.
+///
+/// Only the tool messages the model sees change; events and results keep the raw output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectionGuard {
+    /// Send tool output as is
+    Off,
+    /// Fence output that may carry workspace content, marked as data
+    #[default]
+    Wrap,
+    /// Fence it and emit `InjectionSuspected` when it reads like instructions
+    WrapAndFlag,
+}
+
+/// What a run does when another run is already working in its workspace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RunConcurrency {
+    /// Refuse to start
+    #[default]
+    Reject,
+    /// Wait for the runs ahead of it to finish, up to `queue_timeout_secs`
+    Queue,
+    /// Run alongside other `parallel` runs, up to the workspace's run limit
+    Parallel,
+}
+
+/// Preview length for tool results and text in `EventDetail::Truncated` events
+pub const EVENT_PREVIEW_BYTES: usize = 2000;
+
+/// Preview length for string tool arguments in `EventDetail::Truncated` events
+pub const EVENT_ARG_PREVIEW_BYTES: usize = 200;
+
+/// Tool arguments that name files rather than carry content. They are always sent
+/// in full: the frontend uses them to mark files the agent is about to write.
+const EVENT_PATH_ARGS: &[&str] = &["path", "paths", "cwd"];
+
+impl EventDetail {
+    /// Reduce a string to this detail level. Returns the new string and whether
+    /// anything was removed.
+    fn redact_text(self, text: &str, preview_bytes: usize) -> (String, bool) {
+        match self {
+            EventDetail::Full => (text.to_string(), false),
+            EventDetail::Truncated => {
+                let preview = truncate_to_char_boundary(text, preview_bytes);
+                if preview.len() == text.len() {
+                    (text.to_string(), false)
+                } else {
+                    (format!("{}...[{} bytes total]", preview, text.len()), true)
+                }
+            }
+            EventDetail::MetadataOnly => (format!("[{} bytes omitted]", text.len()), true),
+        }
+    }
+
+    /// Reduce tool arguments to this detail level, keeping path arguments intact
+    fn redact_args(self, args: &mut serde_json::Value) {
+        match args {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if !EVENT_PATH_ARGS.contains(&key.as_str()) {
+                        self.redact_value(value);
+                    }
+                }
+            }
+            other => self.redact_value(other),
+        }
+    }
+
+    /// Reduce every string inside a JSON value to this detail level
+    fn redact_value(self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(s) => {
+                *s = self.redact_text(s, EVENT_ARG_PREVIEW_BYTES).0;
+            }
+            serde_json::Value::Array(items) => {
+                items.iter_mut().for_each(|item| self.redact_value(item));
+            }
+            serde_json::Value::Object(map) => {
+                map.values_mut().for_each(|item| self.redact_value(item));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Provider routing preferences for OpenRouter, sent as the request's `provider`
+/// object (and `models` for fallbacks). Other providers ignore them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenRouterRouting {
+    /// Upstream providers to try first, in order (e.g. "Anthropic", "Together")
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub order: Vec<String>,
+    /// Whether OpenRouter may use providers outside `order` when those fail
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_fallbacks: Option<bool>,
+    /// Only route to these providers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub only: Vec<String>,
+    /// Never route to these providers
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// Prefer the cheapest ("price"), fastest ("throughput"), or quickest to
+    /// respond ("latency") provider
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// Skip providers that don't support every request parameter (tools, etc.)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_parameters: Option<bool>,
+    /// "deny" skips providers that may store or train on prompts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_collection: Option<String>,
+    /// Models to fall back to, in order, when the configured model is unavailable
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_models: Vec<String>,
+}
+
+/// Maximum number of entries in each routing list
+const MAX_ROUTING_ENTRIES: usize = 20;
+
+impl OpenRouterRouting {
+    /// Check list sizes and the values OpenRouter accepts
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, list) in [
+            ("order", &self.order),
+            ("only", &self.only),
+            ("ignore", &self.ignore),
+            ("fallback_models", &self.fallback_models),
+        ] {
+            if list.len() > MAX_ROUTING_ENTRIES {
+                return Err(format!(
+                    "openrouter_routing.{} cannot have more than {} entries",
+                    name, MAX_ROUTING_ENTRIES
+                ));
+            }
+            if list.iter().any(|entry| entry.trim().is_empty()) {
+                return Err(format!(
+                    "openrouter_routing.{} cannot contain empty entries",
+                    name
+                ));
+            }
+        }
+        if let Some(sort) = self.sort.as_deref() {
+            if !matches!(sort, "price" | "throughput" | "latency") {
+                return Err(format!(
+                    "openrouter_routing.sort must be price, throughput, or latency (got {})",
+                    sort
+                ));
+            }
+        }
+        if let Some(policy) = self.data_collection.as_deref() {
+            if !matches!(policy, "allow" | "deny") {
+                return Err(format!(
+                    "openrouter_routing.data_collection must be allow or deny (got {})",
+                    policy
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The request's `provider` object, or `None` when no preference is set
+    pub fn provider_preferences(&self) -> Option<serde_json::Value> {
+        let mut preferences = serde_json::Map::new();
+        for (key, list) in [
+            ("order", &self.order),
+            ("only", &self.only),
+            ("ignore", &self.ignore),
+        ] {
+            if !list.is_empty() {
+                preferences.insert(key.to_string(), serde_json::json!(list));
+            }
+        }
+        for (key, flag) in [
+            ("allow_fallbacks", self.allow_fallbacks),
+            ("require_parameters", self.require_parameters),
+        ] {
+            if let Some(flag) = flag {
+                preferences.insert(key.to_string(), serde_json::Value::Bool(flag));
+            }
+        }
+        for (key, value) in [
+            ("sort", &self.sort),
+            ("data_collection", &self.data_collection),
+        ] {
+            if let Some(value) = value {
+                preferences.insert(key.to_string(), serde_json::json!(value));
+            }
+        }
+        (!preferences.is_empty()).then_some(serde_json::Value::Object(preferences))
+    }
+}
+
+/// The model (and, for routers, the upstream provider) that actually served a reply
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServedModel {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
 /// Configuration for the agent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     /// LLM provider to use
     #[serde(default)]
     pub provider: LlmProvider,
 
-    /// API key for the provider
+    /// API key for the provider; never serialized, and masked in `Debug` output
+    #[serde(default, skip_serializing)]
     pub api_key: String,
 
     /// Model to use (e.g., "gpt-5-mini", "gpt-4.1", "claude-sonnet-4-20250514")
@@ -271,6 +568,175 @@ pub struct AgentConfig {
     /// Tool approval mode
     #[serde(default)]
     pub approval_mode: ApprovalMode,
+
+    /// How much content tool and text events carry
+    #[serde(default)]
+    pub event_detail: EventDetail,
+
+    /// Stream replies as `TextChunk` events where the provider supports it
+    /// (turn off for proxies that don't handle SSE)
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+
+    /// How many times a rate-limited, overloaded, or timed-out LLM call is retried
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    /// Upper bound on the wait before each retry, in milliseconds
+    #[serde(default = "default_max_retry_backoff_ms")]
+    pub max_retry_backoff_ms: u64,
+
+    /// Which commands run_shell may execute
+    #[serde(default)]
+    pub shell_policy: ShellPolicy,
+
+    /// Environment variables run_shell passes on beyond PATH, HOME, LANG and
+    /// TMPDIR: names or `PREFIX_*`; likely secrets need a leading `!`
+    #[serde(default)]
+    pub shell_env_allowlist: Vec<String>,
+
+    /// Shell run_shell hands commands to, by name or path (`sh`, `bash`, `pwsh`,
+    /// `cmd`, ...); `sh` on Unix and PowerShell on Windows if unset
+    #[serde(default)]
+    pub shell: Option<String>,
+
+    /// Run consecutive read-only tool calls from one model turn concurrently
+    /// (turn off to debug tool ordering)
+    #[serde(default = "default_parallel_tools")]
+    pub parallel_tools: bool,
+
+    /// Prompt size in tokens above which old tool output is elided (defaults
+    /// from the model's context window)
+    #[serde(default)]
+    pub context_budget: Option<u32>,
+
+    /// Limit on a whole LLM request in seconds, including reading the reply
+    /// (defaults per provider; Ollama has none)
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    /// Limit on connecting to the provider in seconds
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+
+    /// Provider routing preferences for OpenRouter
+    #[serde(default)]
+    pub openrouter_routing: Option<OpenRouterRouting>,
+
+    /// How tool output is guarded against prompt injection
+    #[serde(default)]
+    pub injection_guard: InjectionGuard,
+
+    /// Largest file read_file opens, in bytes
+    #[serde(default = "default_max_file_bytes")]
+    pub max_read_bytes: u64,
+
+    /// Largest content write_file writes, and largest file append_file leaves, in bytes
+    #[serde(default = "default_max_file_bytes")]
+    pub max_write_bytes: u64,
+
+    /// Output caps for the built-in tools and for any tool result the model is sent
+    #[serde(default)]
+    pub tool_limits: ToolLimits,
+
+    /// Tools the agent may not use at all: built-in names, extension tools by full
+    /// name (`ext-id:tool`), or whole extensions by ID
+    #[serde(default)]
+    pub disabled_tools: Vec<String>,
+
+    /// `reasoning_effort` for OpenAI o-series and GPT-5 models (low, medium, or
+    /// high); not sent to other models
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+
+    /// Token budget for Claude extended thinking; thinking is off when unset
+    #[serde(default)]
+    pub thinking_budget_tokens: Option<u32>,
+
+    /// What happens when another run is working in the same workspace
+    #[serde(default)]
+    pub concurrency: RunConcurrency,
+
+    /// How long a queued run waits for the workspace, in seconds
+    #[serde(default = "default_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+
+    /// Events buffered for the UI before the run starts dropping the
+    /// non-essential ones
+    #[serde(default = "default_event_buffer")]
+    pub event_buffer: usize,
+}
+
+impl fmt::Debug for AgentConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Destructured so a new field can't be left out of the output unnoticed
+        let AgentConfig {
+            provider,
+            api_key,
+            model,
+            temperature,
+            max_tokens,
+            max_iterations,
+            shell_timeout,
+            base_url,
+            approval_mode,
+            event_detail,
+            stream,
+            max_retries,
+            max_retry_backoff_ms,
+            shell_policy,
+            shell_env_allowlist,
+            shell,
+            parallel_tools,
+            context_budget,
+            request_timeout_secs,
+            connect_timeout_secs,
+            openrouter_routing,
+            injection_guard,
+            max_read_bytes,
+            max_write_bytes,
+            tool_limits,
+            disabled_tools,
+            reasoning_effort,
+            thinking_budget_tokens,
+            concurrency,
+            queue_timeout_secs,
+            event_buffer,
+        } = self;
+        f.debug_struct("AgentConfig")
+            .field("provider", provider)
+            .field("api_key", &mask_key(api_key))
+            .field("model", model)
+            .field("temperature", temperature)
+            .field("max_tokens", max_tokens)
+            .field("max_iterations", max_iterations)
+            .field("shell_timeout", shell_timeout)
+            .field("base_url", base_url)
+            .field("approval_mode", approval_mode)
+            .field("event_detail", event_detail)
+            .field("stream", stream)
+            .field("max_retries", max_retries)
+            .field("max_retry_backoff_ms", max_retry_backoff_ms)
+            .field("shell_policy", shell_policy)
+            .field("shell_env_allowlist", shell_env_allowlist)
+            .field("shell", shell)
+            .field("parallel_tools", parallel_tools)
+            .field("context_budget", context_budget)
+            .field("request_timeout_secs", request_timeout_secs)
+            .field("connect_timeout_secs", connect_timeout_secs)
+            .field("openrouter_routing", openrouter_routing)
+            .field("injection_guard", injection_guard)
+            .field("max_read_bytes", max_read_bytes)
+            .field("max_write_bytes", max_write_bytes)
+            .field("tool_limits", tool_limits)
+            .field("disabled_tools", disabled_tools)
+            .field("reasoning_effort", reasoning_effort)
+            .field("thinking_budget_tokens", thinking_budget_tokens)
+            .field("concurrency", concurrency)
+            .field("queue_timeout_secs", queue_timeout_secs)
+            .field("event_buffer", event_buffer)
+            .finish()
+    }
 }
 
 fn default_model() -> String {
@@ -293,6 +759,38 @@ fn default_shell_timeout() -> u64 {
     30
 }
 
+fn default_stream() -> bool {
+    true
+}
+
+fn default_parallel_tools() -> bool {
+    true
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_max_retry_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_max_file_bytes() -> u64 {
+    DEFAULT_MAX_FILE_BYTES
+}
+
+fn default_queue_timeout_secs() -> u64 {
+    300
+}
+
+fn default_event_buffer() -> usize {
+    256
+}
+
 impl Default for AgentConfig {
     fn default() -> Self {
         AgentConfig {
@@ -305,6 +803,28 @@ impl Default for AgentConfig {
             shell_timeout: default_shell_timeout(),
             base_url: None,
             approval_mode: ApprovalMode::default(),
+            event_detail: EventDetail::default(),
+            stream: default_stream(),
+            max_retries: default_max_retries(),
+            max_retry_backoff_ms: default_max_retry_backoff_ms(),
+            shell_policy: ShellPolicy::default(),
+            shell_env_allowlist: Vec::new(),
+            shell: None,
+            parallel_tools: default_parallel_tools(),
+            context_budget: None,
+            request_timeout_secs: None,
+            connect_timeout_secs: default_connect_timeout_secs(),
+            openrouter_routing: None,
+            injection_guard: InjectionGuard::default(),
+            max_read_bytes: default_max_file_bytes(),
+            max_write_bytes: default_max_file_bytes(),
+            tool_limits: ToolLimits::default(),
+            disabled_tools: Vec::new(),
+            reasoning_effort: None,
+            thinking_budget_tokens: None,
+            concurrency: RunConcurrency::default(),
+            queue_timeout_secs: default_queue_timeout_secs(),
+            event_buffer: default_event_buffer(),
         }
     }
 }
@@ -360,6 +880,14 @@ impl AgentConfig {
         self
     }
 
+    /// Size caps for the built-in file tools
+    pub fn file_limits(&self) -> FileLimits {
+        FileLimits {
+            max_read_bytes: self.max_read_bytes,
+            max_write_bytes: self.max_write_bytes,
+        }
+    }
+
     /// Get the effective base URL (custom or provider default)
     pub fn effective_base_url(&self) -> String {
         self.base_url
@@ -393,6 +921,25 @@ pub struct Message {
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    /// Reasoning the model returned with this (assistant) turn, sent back with it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thinking: Vec<ThinkingBlock>,
+}
+
+/// A block of extended thinking from a Claude reply. Claude requires the blocks
+/// (signature included) to be sent back unchanged with the tool results that
+/// follow them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingBlock {
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    /// Thinking the provider encrypted; only `data` is returned
+    RedactedThinking {
+        data: String,
+    },
 }
 
 impl Message {
@@ -403,6 +950,7 @@ impl Message {
             content: Some(content.to_string()),
             tool_calls: None,
             tool_call_id: None,
+            thinking: Vec::new(),
         }
     }
 
@@ -413,6 +961,7 @@ impl Message {
             content: Some(content.to_string()),
             tool_calls: None,
             tool_call_id: None,
+            thinking: Vec::new(),
         }
     }
 
@@ -423,17 +972,18 @@ impl Message {
             content: Some(content.to_string()),
             tool_calls: None,
             tool_call_id: None,
+            thinking: Vec::new(),
         }
     }
 
     /// Create an assistant message
-    #[allow(dead_code)]
     pub fn assistant(content: &str) -> Self {
         Message {
             role: MessageRole::Assistant,
             content: Some(content.to_string()),
             tool_calls: None,
             tool_call_id: None,
+            thinking: Vec::new(),
         }
     }
 
@@ -444,6 +994,7 @@ impl Message {
             content,
             tool_calls: Some(tool_calls),
             tool_call_id: None,
+            thinking: Vec::new(),
         }
     }
 
@@ -454,6 +1005,7 @@ impl Message {
             content: Some(content.to_string()),
             tool_calls: None,
             tool_call_id: Some(tool_call_id.to_string()),
+            thinking: Vec::new(),
         }
     }
 }
@@ -471,12 +1023,28 @@ pub enum AgentEvent {
         task: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
+        /// Session recording the run, when there is one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
     },
 
     /// A tool call is about to be executed
     ToolCallStart {
         name: String,
         args: serde_json::Value,
+        /// Increases with every executed call in the run; the matching
+        /// `ToolCallComplete` carries the same number
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// Output from a tool that is still running (run_shell), as it is produced.
+    /// The full result still arrives in `ToolCallComplete`.
+    ToolOutputChunk {
+        name: String,
+        content: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
@@ -488,6 +1056,21 @@ pub enum AgentEvent {
         result: String,
         success: bool,
         truncated: bool,
+        /// Approval decision that gated this call, if approval was required
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        approval: Option<ApprovalDecision>,
+        /// `seq` of the `ToolCallStart` this completes; unset for calls that never ran
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        /// How long the tool ran, for calls that ran
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+        /// Size of the tool's output (or error) before truncation, for calls that ran
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        output_bytes: Option<u64>,
+        /// What a successful call wrote, for tools that report it
+        #[serde(default, skip_serializing_if = "ToolEffects::is_empty")]
+        effects: ToolEffects,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
@@ -499,11 +1082,75 @@ pub enum AgentEvent {
         run_id: Option<String>,
     },
 
+    /// Reasoning from the model, kept apart from the answer (Claude extended
+    /// thinking); streamed in chunks where the reply is streamed
+    Reasoning {
+        content: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// An LLM call failed transiently and will be retried after `delay_ms`
+    RetryingLlmCall {
+        /// Retry number, starting at 1
+        attempt: u32,
+        max_retries: u32,
+        delay_ms: u64,
+        /// What failed (e.g. "OpenAI returned 429 Too Many Requests")
+        reason: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// The next LLM call waits `delay_ms` because the provider's rate limit is
+    /// nearly used up
+    Waiting {
+        delay_ms: u64,
+        /// What is running low (e.g. "2 requests left until the rate limit resets")
+        reason: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// The calls a dry run recorded instead of executing, in order; sent before
+    /// `Complete`
+    PlanGenerated {
+        steps: Vec<PlannedToolCall>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// Old tool output was replaced with stubs to keep the conversation inside
+    /// the model's context window (estimated tokens)
+    ContextTruncated {
+        elided_messages: usize,
+        tokens_before: usize,
+        tokens_after: usize,
+        budget: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// Something degraded but the run continues (e.g. tools disabled for a model)
+    Warning {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
     /// Agent has completed with a final response
     Complete {
         response: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         usage: Option<Usage>,
+        /// Estimated cost in USD of `usage`, when the model's price is known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        estimated_cost: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        summary: Option<RunSummary>,
+        /// The model that produced the final reply, as reported by the provider
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        served_model: Option<ServedModel>,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
@@ -511,12 +1158,38 @@ pub enum AgentEvent {
     /// An error occurred
     Error {
         error: String,
+        /// Partial summary of the work done before the failure
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        summary: Option<RunSummary>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// Another run is working in the workspace; this one waits its turn and
+    /// sends `Start` once it gets it
+    Queued {
+        /// Place in line, 1 for next
+        position: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// The UI fell behind and `count` events were dropped; sent just before the
+    /// run's final event
+    DroppedEvents {
+        count: u64,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
 
     /// Agent run was cancelled
     Cancelled {
+        /// Partial summary of the work done before cancellation
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        summary: Option<RunSummary>,
+        /// Why the run was cancelled, when known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<CancelReason>,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
@@ -531,18 +1204,104 @@ pub enum AgentEvent {
         args: serde_json::Value,
         /// Risk level of this tool
         risk: ToolRisk,
+        /// Diff or summary of what a file-changing tool would do
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preview: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
 
-    /// Tool was skipped in dry-run mode
+    /// Tool was skipped (recorded in a dry-run plan, or denied for the run)
     ToolSkipped {
         name: String,
         args: serde_json::Value,
         reason: String,
+        /// Approval decision that led to the skip, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        approval: Option<ApprovalDecision>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// Shell command refused by the run's shell policy; it never ran
+    ToolBlocked {
+        name: String,
+        args: serde_json::Value,
+        reason: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+
+    /// An extension tool ran past its time limit and was stopped; its
+    /// `ToolCallComplete` follows with the error
+    ExtensionToolKilled {
+        name: String,
+        reason: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         run_id: Option<String>,
     },
+
+    /// A tool's output contains text that reads like instructions to the model;
+    /// it was still sent, fenced as data
+    InjectionSuspected {
+        name: String,
+        /// The file the output came from, when the tool names one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        phrases: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        run_id: Option<String>,
+    },
+}
+
+impl AgentEvent {
+    /// Strip file and conversation content from tool and text events down to
+    /// `detail`. Other events pass through unchanged; approval requests keep full
+    /// arguments so the user can make an informed decision.
+    pub fn with_detail(mut self, detail: EventDetail) -> AgentEvent {
+        if detail == EventDetail::Full {
+            return self;
+        }
+        match &mut self {
+            AgentEvent::ToolCallStart { args, .. }
+            | AgentEvent::ToolSkipped { args, .. }
+            | AgentEvent::ToolBlocked { args, .. } => {
+                detail.redact_args(args);
+            }
+            AgentEvent::ToolCallComplete {
+                args,
+                result,
+                truncated,
+                ..
+            } => {
+                detail.redact_args(args);
+                let (redacted, cut) = detail.redact_text(result, EVENT_PREVIEW_BYTES);
+                *result = redacted;
+                *truncated |= cut;
+            }
+            AgentEvent::PlanGenerated { steps, .. } => {
+                for step in steps {
+                    detail.redact_args(&mut step.args);
+                    step.effect = detail.redact_text(&step.effect, EVENT_PREVIEW_BYTES).0;
+                }
+            }
+            AgentEvent::TextChunk { content, .. }
+            | AgentEvent::Reasoning { content, .. }
+            | AgentEvent::ToolOutputChunk { content, .. } => {
+                *content = detail.redact_text(content, EVENT_PREVIEW_BYTES).0;
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+/// Why a run was cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancelReason {
+    /// The run saw no activity for longer than the idle timeout
+    Idle,
 }
 
 /// Token usage information
@@ -553,16 +1312,152 @@ pub struct Usage {
     pub total_tokens: u32,
 }
 
+/// Assistant prose produced during one iteration of the agent loop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextSegment {
+    /// Zero-based loop iteration that produced the text
+    pub iteration: u32,
+    pub text: String,
+}
+
+/// What a run did to a file overall
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileOperation {
+    Created,
+    Modified,
+    Deleted,
+}
+
+/// The net effect of a run's tool calls on one workspace file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Workspace-relative path, as the model named it
+    pub path: String,
+    pub operation: FileOperation,
+    /// Size after the run minus size before it
+    pub byte_delta: i64,
+}
+
+/// A tool call a dry run recorded instead of executing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedToolCall {
+    pub tool: String,
+    pub args: serde_json::Value,
+    pub risk: ToolRisk,
+    /// What the call would do: a diff for file edits, otherwise a one-line summary
+    pub effect: String,
+    /// Workspace files the call would write or delete
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// Structured account of an agent run: what was written vs what was done
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Assistant text in the order it was produced (tool-call preambles and the final answer)
+    pub segments: Vec<TextSegment>,
+    /// Number of tool calls requested, by tool name
+    pub tool_calls: BTreeMap<String, u32>,
+    /// Workspace-relative files touched by successful tool calls, in first-touched order
+    pub working_set: Vec<String>,
+    /// Files the run created, modified, or deleted, in first-changed order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_changes: Vec<FileChange>,
+    /// Bytes the run's tool calls wrote to workspace files, as the tools report it
+    #[serde(default)]
+    pub bytes_written: u64,
+    /// Total token usage across all LLM calls
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Number of loop iterations started
+    pub iterations: u32,
+    /// Wall-clock duration of the run in milliseconds
+    pub duration_ms: u64,
+    /// One line on what the model left in its scratchpad, if anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scratchpad: Option<String>,
+}
+
 // ============================================================================
 // Error Types
 // ============================================================================
 
+/// Machine-readable kind of an `AgentError`, so the frontend can offer the right fix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// No API key is configured for the provider
+    MissingApiKey,
+    /// The provider rejected the API key
+    InvalidApiKey,
+    /// The provider is throttling requests
+    RateLimited,
+    /// The account is out of credit or quota
+    InsufficientQuota,
+    /// The provider doesn't serve the configured model
+    ModelNotFound,
+    /// The conversation no longer fits the model's context window
+    ContextLengthExceeded,
+    /// Any other failure talking to the LLM provider
+    ProviderError,
+    /// An LLM request (or connecting to the provider) took too long
+    Timeout,
+    /// A tool failed in a way that ended the run
+    ToolError,
+    /// Path security violation
+    PathViolation,
+    /// Invalid configuration
+    ConfigError,
+    /// The workspace folder doesn't exist
+    WorkspaceMissing,
+    /// The run used all its iterations
+    MaxIterationsReached,
+    /// The run was cancelled
+    Cancelled,
+}
+
+impl ErrorCode {
+    /// Whether the same request may succeed if it is simply tried again later
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorCode::RateLimited | ErrorCode::Timeout)
+    }
+}
+
+/// An `AgentError` in the shape the frontend receives it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorInfo {
+    pub code: ErrorCode,
+    /// The same text that is logged
+    pub message: String,
+    pub retryable: bool,
+    /// Provider display name, for errors that came from (or are about) one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
 /// Errors that can occur during agent execution
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AgentError {
     /// Error calling the LLM API
     LlmError(String),
 
+    /// The LLM provider rejected a request, classified by its status and payload
+    Provider {
+        code: ErrorCode,
+        provider: String,
+        message: String,
+    },
+
+    /// No API key is configured for the named provider
+    MissingApiKey(String),
+
+    /// The workspace folder doesn't exist or isn't a directory
+    WorkspaceMissing(String),
+
+    /// An LLM request (or connecting to the provider) took too long
+    Timeout(String),
+
     /// Error executing a tool
     ToolError(String),
 
@@ -583,6 +1478,14 @@ impl std::fmt::Display for AgentError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AgentError::LlmError(msg) => write!(f, "LLM error: {}", msg),
+            AgentError::Provider { message, .. } => write!(f, "LLM error: {}", message),
+            AgentError::MissingApiKey(provider) => {
+                write!(f, "Config error: {} API key is not configured", provider)
+            }
+            AgentError::WorkspaceMissing(path) => {
+                write!(f, "Workspace folder not found: {}", path)
+            }
+            AgentError::Timeout(msg) => write!(f, "Timeout: {}", msg),
             AgentError::ToolError(msg) => write!(f, "Tool error: {}", msg),
             AgentError::PathViolation(msg) => write!(f, "Path violation: {}", msg),
             AgentError::ConfigError(msg) => write!(f, "Config error: {}", msg),
@@ -594,6 +1497,45 @@ impl std::fmt::Display for AgentError {
 
 impl std::error::Error for AgentError {}
 
+impl AgentError {
+    /// Machine-readable kind of this error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AgentError::LlmError(_) => ErrorCode::ProviderError,
+            AgentError::Provider { code, .. } => *code,
+            AgentError::MissingApiKey(_) => ErrorCode::MissingApiKey,
+            AgentError::WorkspaceMissing(_) => ErrorCode::WorkspaceMissing,
+            AgentError::Timeout(_) => ErrorCode::Timeout,
+            AgentError::ToolError(_) => ErrorCode::ToolError,
+            AgentError::PathViolation(_) => ErrorCode::PathViolation,
+            AgentError::ConfigError(_) => ErrorCode::ConfigError,
+            AgentError::MaxIterationsReached => ErrorCode::MaxIterationsReached,
+            AgentError::Cancelled => ErrorCode::Cancelled,
+        }
+    }
+
+    /// Provider the error came from or is about, if known
+    pub fn provider(&self) -> Option<&str> {
+        match self {
+            AgentError::Provider { provider, .. } | AgentError::MissingApiKey(provider) => {
+                Some(provider)
+            }
+            _ => None,
+        }
+    }
+
+    /// Structured form for the frontend; `message` is the logged text
+    pub fn info(&self) -> ErrorInfo {
+        let code = self.code();
+        ErrorInfo {
+            code,
+            message: self.to_string(),
+            retryable: code.is_retryable(),
+            provider: self.provider().map(str::to_string),
+        }
+    }
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -633,7 +1575,7 @@ mod tests {
     fn test_llm_provider_tool_support() {
         assert!(LlmProvider::OpenAI.supports_tools());
         assert!(LlmProvider::Claude.supports_tools());
-        assert!(!LlmProvider::Ollama.supports_tools());
+        assert!(LlmProvider::Ollama.supports_tools());
     }
 
     #[test]
@@ -660,6 +1602,22 @@ mod tests {
         assert_eq!(config.max_tokens, 2048);
     }
 
+    #[test]
+    fn test_agent_config_api_key_never_leaks() {
+        let config = AgentConfig::for_provider(LlmProvider::Claude, "sk-ant-api03-topsecret");
+
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("topsecret"));
+        assert!(debug.contains(r#"api_key: "sk-***""#));
+        assert!(debug.contains("claude-sonnet-4-20250514"));
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert!(json.get("api_key").is_none());
+        // A config read back without a key gets an empty one
+        let restored: AgentConfig = serde_json::from_value(json).unwrap();
+        assert!(restored.api_key.is_empty());
+    }
+
     #[test]
     fn test_agent_config_for_provider() {
         let config = AgentConfig::for_provider(LlmProvider::Claude, "sk-ant-key");
@@ -725,6 +1683,53 @@ mod tests {
         assert_eq!(ToolRisk::for_tool("my-ext:dangerous_tool"), ToolRisk::High);
     }
 
+    #[test]
+    fn test_edit_file_risk() {
+        assert_eq!(ToolRisk::for_tool("edit_file"), ToolRisk::Medium);
+    }
+
+    #[test]
+    fn test_move_and_copy_file_risk() {
+        assert_eq!(ToolRisk::for_tool("move_file"), ToolRisk::Medium);
+        assert_eq!(ToolRisk::for_tool("copy_file"), ToolRisk::Medium);
+    }
+
+    #[test]
+    fn test_workspace_stats_risk() {
+        assert_eq!(ToolRisk::for_tool("workspace_stats"), ToolRisk::Low);
+    }
+
+    #[test]
+    fn test_entity_tag_tool_risk() {
+        assert_eq!(ToolRisk::for_tool("suggest_entity_tags"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("find_entity_mentions"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("apply_suggested_tags"), ToolRisk::Medium);
+    }
+
+    #[test]
+    fn test_section_meta_tool_risk() {
+        assert_eq!(ToolRisk::for_tool("get_section_meta"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("update_section_meta"), ToolRisk::Medium);
+        assert_eq!(ToolRisk::for_tool("insert_into_section"), ToolRisk::Medium);
+        assert_eq!(ToolRisk::for_tool("validate_tags"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("update_section_scene"), ToolRisk::Medium);
+        assert_eq!(ToolRisk::for_tool("semantic_search"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("compile_manuscript"), ToolRisk::Medium);
+    }
+
+    #[test]
+    fn test_scratchpad_tool_risk() {
+        assert_eq!(ToolRisk::for_tool("scratchpad_write"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("scratchpad_read"), ToolRisk::Low);
+    }
+
+    #[test]
+    fn test_git_tool_risk() {
+        assert_eq!(ToolRisk::for_tool("git_status"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("git_diff"), ToolRisk::Low);
+        assert_eq!(ToolRisk::for_tool("git_commit"), ToolRisk::Medium);
+    }
+
     #[test]
     fn test_agent_event_serialization() {
         let event = AgentEvent::ToolCallComplete {
@@ -733,11 +1738,333 @@ mod tests {
             result: "contents".to_string(),
             success: true,
             truncated: false,
+            approval: None,
+            seq: None,
+            duration_ms: None,
+            output_bytes: None,
+            effects: ToolEffects::default(),
             run_id: None,
         };
 
         let json = serde_json::to_string(&event).unwrap();
         assert!(json.contains("tool_call_complete"));
         assert!(json.contains("read_file"));
+        assert!(!json.contains("approval"));
+        assert!(!json.contains("duration_ms"));
+    }
+
+    #[test]
+    fn test_tool_call_timing_serialization() {
+        let start = AgentEvent::ToolCallStart {
+            name: "grep".to_string(),
+            args: serde_json::json!({"pattern": "Anna"}),
+            seq: Some(3),
+            run_id: None,
+        };
+        let value = serde_json::to_value(&start).unwrap();
+        assert_eq!(value["type"], "tool_call_start");
+        assert_eq!(value["seq"], 3);
+
+        let complete = AgentEvent::ToolCallComplete {
+            name: "grep".to_string(),
+            args: serde_json::json!({"pattern": "Anna"}),
+            result: "chapters/01.md:1: Anna".to_string(),
+            success: true,
+            truncated: false,
+            approval: None,
+            seq: Some(3),
+            duration_ms: Some(8042),
+            output_bytes: Some(46_080),
+            effects: ToolEffects::default(),
+            run_id: None,
+        };
+        let value = serde_json::to_value(&complete).unwrap();
+        assert_eq!(value["type"], "tool_call_complete");
+        assert_eq!(value["seq"], 3);
+        assert_eq!(value["duration_ms"], 8042);
+        assert_eq!(value["output_bytes"], 46_080);
+        assert!(value.get("effects").is_none());
+
+        // Events from before these fields existed still deserialize
+        let legacy: AgentEvent = serde_json::from_str(
+            r#"{"type":"tool_call_complete","name":"grep","args":{},"result":"","success":true,"truncated":false}"#,
+        )
+        .unwrap();
+        match legacy {
+            AgentEvent::ToolCallComplete {
+                seq, duration_ms, ..
+            } => assert_eq!((seq, duration_ms), (None, None)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        let legacy: AgentEvent =
+            serde_json::from_str(r#"{"type":"tool_call_start","name":"grep","args":{}}"#).unwrap();
+        assert!(matches!(
+            legacy,
+            AgentEvent::ToolCallStart { seq: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_complete_event_summary_serialization() {
+        let mut tool_calls = BTreeMap::new();
+        tool_calls.insert("read_file".to_string(), 2);
+        let summary = RunSummary {
+            segments: vec![TextSegment {
+                iteration: 0,
+                text: "Let me check the outline.".to_string(),
+            }],
+            tool_calls,
+            working_set: vec!["outline.md".to_string()],
+            file_changes: Vec::new(),
+            bytes_written: 0,
+            usage: None,
+            iterations: 2,
+            duration_ms: 1500,
+            scratchpad: None,
+        };
+        let event = AgentEvent::Complete {
+            response: "Done".to_string(),
+            usage: None,
+            estimated_cost: None,
+            summary: Some(summary),
+            served_model: None,
+            run_id: None,
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "complete");
+        assert_eq!(json["response"], "Done");
+        assert_eq!(json["summary"]["segments"][0]["iteration"], 0);
+        assert_eq!(json["summary"]["tool_calls"]["read_file"], 2);
+        assert_eq!(json["summary"]["working_set"][0], "outline.md");
+        assert_eq!(json["summary"]["duration_ms"], 1500);
+        assert!(json["summary"].get("usage").is_none());
+
+        // Events from older producers without a summary still deserialize
+        let legacy: AgentEvent =
+            serde_json::from_str(r#"{"type":"complete","response":"Hi"}"#).unwrap();
+        match legacy {
+            AgentEvent::Complete { summary, .. } => assert!(summary.is_none()),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    fn tool_complete(result: &str) -> AgentEvent {
+        AgentEvent::ToolCallComplete {
+            name: "read_file".to_string(),
+            args: serde_json::json!({"path": "chapters/01.md", "offset": 10, "all": true}),
+            result: result.to_string(),
+            success: true,
+            truncated: false,
+            approval: None,
+            seq: None,
+            duration_ms: None,
+            output_bytes: None,
+            effects: ToolEffects::default(),
+            run_id: Some("run-1".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_event_detail_full_is_unchanged() {
+        let result = "x".repeat(EVENT_PREVIEW_BYTES * 2);
+        let event = tool_complete(&result).with_detail(EventDetail::Full);
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["result"], result.as_str());
+        assert_eq!(value["args"]["path"], "chapters/01.md");
+        assert_eq!(value["truncated"], false);
+    }
+
+    #[test]
+    fn test_event_detail_truncated() {
+        // Multi-byte text so the cut has to land on a char boundary
+        let result = format!("a{}", "é".repeat(EVENT_PREVIEW_BYTES));
+        let event = tool_complete(&result).with_detail(EventDetail::Truncated);
+        let value = serde_json::to_value(&event).unwrap();
+
+        let shown = value["result"].as_str().unwrap();
+        let preview = format!("a{}...", "é".repeat(EVENT_PREVIEW_BYTES / 2 - 1));
+        assert!(shown.starts_with(&preview));
+        assert!(shown.ends_with(&format!("...[{} bytes total]", result.len())));
+        assert_eq!(value["truncated"], true);
+        // Short args survive intact
+        assert_eq!(value["args"]["path"], "chapters/01.md");
+
+        let short = tool_complete("ok").with_detail(EventDetail::Truncated);
+        let value = serde_json::to_value(&short).unwrap();
+        assert_eq!(value["result"], "ok");
+        assert_eq!(value["truncated"], false);
+    }
+
+    #[test]
+    fn test_event_detail_metadata_only() {
+        let event = tool_complete("Chapter one text").with_detail(EventDetail::MetadataOnly);
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["name"], "read_file");
+        assert_eq!(value["result"], "[16 bytes omitted]");
+        assert_eq!(value["args"]["path"], "chapters/01.md");
+        assert_eq!(value["args"]["offset"], 10);
+        assert_eq!(value["args"]["all"], true);
+        assert_eq!(value["success"], true);
+
+        let start = AgentEvent::ToolCallStart {
+            name: "write_file".to_string(),
+            args: serde_json::json!({"path": "a.md", "content": ["secret"]}),
+            seq: None,
+            run_id: None,
+        }
+        .with_detail(EventDetail::MetadataOnly);
+        let value = serde_json::to_value(&start).unwrap();
+        assert_eq!(value["args"]["content"][0], "[6 bytes omitted]");
+        assert_eq!(value["args"]["path"], "a.md");
+
+        let chunk = AgentEvent::TextChunk {
+            content: "She said".to_string(),
+            run_id: None,
+        }
+        .with_detail(EventDetail::MetadataOnly);
+        let value = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(value["content"], "[8 bytes omitted]");
+
+        // Approval requests keep their arguments
+        let approval = AgentEvent::ToolApprovalRequired {
+            approval_id: "a1".to_string(),
+            name: "write_file".to_string(),
+            args: serde_json::json!({"content": "secret"}),
+            risk: ToolRisk::Medium,
+            preview: None,
+            run_id: None,
+        }
+        .with_detail(EventDetail::MetadataOnly);
+        let value = serde_json::to_value(&approval).unwrap();
+        assert_eq!(value["args"]["content"], "secret");
+    }
+
+    #[test]
+    fn test_event_detail_deserializes_snake_case() {
+        let config: AgentConfig = serde_json::from_value(
+            serde_json::json!({"api_key": "", "event_detail": "metadata_only"}),
+        )
+        .unwrap();
+        assert_eq!(config.event_detail, EventDetail::MetadataOnly);
+        assert_eq!(AgentConfig::default().event_detail, EventDetail::Full);
+    }
+
+    #[test]
+    fn test_error_codes_serialize_snake_case() {
+        // The frontend switches on these strings
+        let codes = [
+            (ErrorCode::MissingApiKey, "missing_api_key"),
+            (ErrorCode::InvalidApiKey, "invalid_api_key"),
+            (ErrorCode::RateLimited, "rate_limited"),
+            (ErrorCode::InsufficientQuota, "insufficient_quota"),
+            (ErrorCode::ModelNotFound, "model_not_found"),
+            (ErrorCode::ContextLengthExceeded, "context_length_exceeded"),
+            (ErrorCode::ProviderError, "provider_error"),
+            (ErrorCode::Timeout, "timeout"),
+            (ErrorCode::ToolError, "tool_error"),
+            (ErrorCode::PathViolation, "path_violation"),
+            (ErrorCode::ConfigError, "config_error"),
+            (ErrorCode::WorkspaceMissing, "workspace_missing"),
+            (ErrorCode::MaxIterationsReached, "max_iterations_reached"),
+            (ErrorCode::Cancelled, "cancelled"),
+        ];
+        for (code, name) in codes {
+            // Fails to compile when a code is added without a case here
+            match code {
+                ErrorCode::MissingApiKey
+                | ErrorCode::InvalidApiKey
+                | ErrorCode::RateLimited
+                | ErrorCode::InsufficientQuota
+                | ErrorCode::ModelNotFound
+                | ErrorCode::ContextLengthExceeded
+                | ErrorCode::ProviderError
+                | ErrorCode::Timeout
+                | ErrorCode::ToolError
+                | ErrorCode::PathViolation
+                | ErrorCode::ConfigError
+                | ErrorCode::WorkspaceMissing
+                | ErrorCode::MaxIterationsReached
+                | ErrorCode::Cancelled => {}
+            }
+            assert_eq!(serde_json::to_value(code).unwrap(), name);
+            let back: ErrorCode = serde_json::from_value(serde_json::json!(name)).unwrap();
+            assert_eq!(back, code);
+            assert_eq!(
+                code.is_retryable(),
+                matches!(code, ErrorCode::RateLimited | ErrorCode::Timeout)
+            );
+        }
+    }
+
+    #[test]
+    fn test_agent_error_info() {
+        let provider = |code| AgentError::Provider {
+            code,
+            provider: "OpenAI".to_string(),
+            message: "OpenAI API error (429 Too Many Requests): slow down".to_string(),
+        };
+        let text = |s: &str| s.to_string();
+        let cases = [
+            (provider(ErrorCode::RateLimited), ErrorCode::RateLimited),
+            (provider(ErrorCode::InvalidApiKey), ErrorCode::InvalidApiKey),
+            (AgentError::LlmError(text("bad")), ErrorCode::ProviderError),
+            (
+                AgentError::MissingApiKey(text("Claude")),
+                ErrorCode::MissingApiKey,
+            ),
+            (
+                AgentError::WorkspaceMissing(text("/gone")),
+                ErrorCode::WorkspaceMissing,
+            ),
+            (AgentError::Timeout(text("slow")), ErrorCode::Timeout),
+            (AgentError::ToolError(text("x")), ErrorCode::ToolError),
+            (
+                AgentError::PathViolation(text("x")),
+                ErrorCode::PathViolation,
+            ),
+            (AgentError::ConfigError(text("x")), ErrorCode::ConfigError),
+            (
+                AgentError::MaxIterationsReached,
+                ErrorCode::MaxIterationsReached,
+            ),
+            (AgentError::Cancelled, ErrorCode::Cancelled),
+        ];
+        for (error, code) in cases {
+            let value = serde_json::to_value(error.info()).unwrap();
+            assert_eq!(value["code"], serde_json::to_value(code).unwrap());
+            assert_eq!(value["message"], error.to_string());
+            assert_eq!(value["retryable"], code.is_retryable());
+            match error.provider() {
+                Some(name) => assert_eq!(value["provider"], name),
+                None => assert!(value.get("provider").is_none(), "{:?}", error),
+            }
+        }
+        assert_eq!(provider(ErrorCode::RateLimited).provider(), Some("OpenAI"));
+        assert_eq!(
+            AgentError::MissingApiKey(text("Claude")).provider(),
+            Some("Claude")
+        );
+        assert_eq!(AgentError::LlmError(text("bad")).provider(), None);
+
+        // The log string keeps the familiar prefixes
+        assert_eq!(
+            AgentError::MissingApiKey("Claude".to_string()).to_string(),
+            "Config error: Claude API key is not configured"
+        );
+        assert!(provider(ErrorCode::RateLimited)
+            .to_string()
+            .starts_with("LLM error: OpenAI API error"));
+
+        let info: ErrorInfo = serde_json::from_value(serde_json::json!({
+            "code": "workspace_missing",
+            "message": "Workspace folder not found: /gone",
+            "retryable": false
+        }))
+        .unwrap();
+        assert_eq!(
+            info,
+            AgentError::WorkspaceMissing("/gone".to_string()).info()
+        );
     }
 }
@@ -0,0 +1,402 @@
+//! Workspace file watcher that fires extension lifecycle hooks.
+//!
+//! Changes to `.md` and `.yaml` files under `sections/` fire `on_section_save` and
+//! under `entities/` fire `on_entity_change`, whether they come from the agent, the
+//! app, or another editor. Bursts of changes are debounced: a hook fires once a file
+//! has been quiet for [`WATCH_DEBOUNCE`]. Hooks receive `{path, action, source:
+//! "watcher"}`, with `path` relative to the workspace and `action` one of `create`,
+//! `update`, `delete`.
+//!
+//! Hooks may write files themselves. Changes seen while hooks run, and until the
+//! workspace has been quiet for another debounce period afterwards, are dropped so
+//! a hook can't trigger itself in a loop.
+
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use super::lua_extensions::LifecycleHook;
+
+/// How long a file must be quiet before its hook fires
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// How often the worker checks for debounced changes
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What happened to a watched file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Delete,
+}
+
+/// The hook a change to `path` fires, if any
+pub fn hook_for_path(root: &Path, path: &Path) -> Option<LifecycleHook> {
+    let relative = path.strip_prefix(root).ok()?;
+    let name = relative.file_name()?.to_str()?;
+    // Editor swap and backup files
+    if name.starts_with('.') || name.ends_with('~') {
+        return None;
+    }
+    // Only document files below the top-level directory, not the directories themselves
+    let watched = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| WATCHED_EXTENSIONS.contains(&e));
+    if !watched || relative.components().count() < 2 {
+        return None;
+    }
+    match relative.components().next()?.as_os_str().to_str()? {
+        "sections" => Some(LifecycleHook::OnSectionSave),
+        "entities" => Some(LifecycleHook::OnEntityChange),
+        _ => None,
+    }
+}
+
+/// File types whose changes fire hooks
+const WATCHED_EXTENSIONS: &[&str] = &["md", "yaml", "yml"];
+
+/// Hook arguments for a change
+fn hook_args(root: &Path, path: &Path, action: ChangeAction) -> serde_json::Value {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    serde_json::json!({
+        "path": relative.to_string_lossy().replace('\\', "/"),
+        "action": action,
+        "source": "watcher",
+    })
+}
+
+// ============================================================================
+// Debouncing
+// ============================================================================
+
+/// Collects changes and releases them once the workspace has been quiet
+#[derive(Debug)]
+struct Debouncer {
+    delay: Duration,
+    pending: BTreeMap<PathBuf, ChangeAction>,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    fn new(delay: Duration) -> Self {
+        Debouncer {
+            delay,
+            pending: BTreeMap::new(),
+            last_change: None,
+        }
+    }
+
+    /// Record a change, folding it into any pending change to the same file
+    fn record(&mut self, path: PathBuf, action: ChangeAction, now: Instant) {
+        self.last_change = Some(now);
+        let merged = match (self.pending.get(&path), action) {
+            // Created and removed again before anyone looked
+            (Some(ChangeAction::Create), ChangeAction::Delete) => {
+                self.pending.remove(&path);
+                return;
+            }
+            (Some(ChangeAction::Create), ChangeAction::Update) => ChangeAction::Create,
+            (Some(ChangeAction::Delete), ChangeAction::Create) => ChangeAction::Update,
+            (_, action) => action,
+        };
+        self.pending.insert(path, merged);
+    }
+
+    /// The pending changes, if nothing has changed for the debounce delay
+    fn take_ready(&mut self, now: Instant) -> Vec<(PathBuf, ChangeAction)> {
+        match self.last_change {
+            Some(last) if now.duration_since(last) >= self.delay => {
+                self.last_change = None;
+                std::mem::take(&mut self.pending).into_iter().collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+// ============================================================================
+// Watcher
+// ============================================================================
+
+enum WatchMessage {
+    Change(PathBuf, ChangeAction),
+    Stop,
+}
+
+/// Watches one workspace and runs hooks for its changes on a background thread.
+/// Stopped by [`WorkspaceWatcher::stop`] or on drop.
+pub struct WorkspaceWatcher {
+    watcher: Option<RecommendedWatcher>,
+    tx: mpsc::Sender<WatchMessage>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl WorkspaceWatcher {
+    /// Start watching `root`, calling `dispatch` with each debounced hook
+    pub fn start(
+        root: &Path,
+        dispatch: impl Fn(LifecycleHook, serde_json::Value) + Send + 'static,
+    ) -> Result<Self, String> {
+        Self::with_debounce(root, WATCH_DEBOUNCE, dispatch)
+    }
+
+    fn with_debounce(
+        root: &Path,
+        debounce: Duration,
+        dispatch: impl Fn(LifecycleHook, serde_json::Value) + Send + 'static,
+    ) -> Result<Self, String> {
+        let root = root.to_path_buf();
+        let (tx, rx) = mpsc::channel();
+
+        let events_tx = tx.clone();
+        let events_root = root.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let event = match res {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("Workspace watcher error: {}", e);
+                    return;
+                }
+            };
+            for path in event.paths {
+                if hook_for_path(&events_root, &path).is_none() {
+                    continue;
+                }
+                let action = match event.kind {
+                    EventKind::Create(_) => ChangeAction::Create,
+                    EventKind::Remove(_) => ChangeAction::Delete,
+                    // A rename reports both names; each either exists now or doesn't
+                    EventKind::Modify(ModifyKind::Name(_)) if path.exists() => ChangeAction::Create,
+                    EventKind::Modify(ModifyKind::Name(_)) => ChangeAction::Delete,
+                    EventKind::Modify(_) | EventKind::Any => ChangeAction::Update,
+                    EventKind::Access(_) | EventKind::Other => continue,
+                };
+                let _ = events_tx.send(WatchMessage::Change(path, action));
+            }
+        })
+        .map_err(|e| format!("Failed to create workspace watcher: {}", e))?;
+        watcher
+            .watch(&root, RecursiveMode::Recursive)
+            .map_err(|e| format!("Failed to watch {}: {}", root.display(), e))?;
+
+        let worker = std::thread::Builder::new()
+            .name("workspace-watcher".to_string())
+            .spawn(move || run_worker(&root, rx, debounce, dispatch))
+            .map_err(|e| format!("Failed to start workspace watcher: {}", e))?;
+
+        Ok(WorkspaceWatcher {
+            watcher: Some(watcher),
+            tx,
+            worker: Some(worker),
+        })
+    }
+
+    /// Stop watching and wait for any hook that is running to finish
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        // Dropping the notify watcher stops new events first
+        self.watcher.take();
+        let _ = self.tx.send(WatchMessage::Stop);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for WorkspaceWatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Debounce changes and dispatch their hooks until told to stop
+fn run_worker(
+    root: &Path,
+    rx: mpsc::Receiver<WatchMessage>,
+    debounce: Duration,
+    dispatch: impl Fn(LifecycleHook, serde_json::Value),
+) {
+    let mut pending = Debouncer::new(debounce);
+    // Changes before this instant were (probably) made by our own hooks
+    let mut suppress_until: Option<Instant> = None;
+
+    loop {
+        match rx.recv_timeout(WATCH_POLL_INTERVAL) {
+            Ok(WatchMessage::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            Ok(WatchMessage::Change(path, action)) => {
+                let now = Instant::now();
+                match suppress_until {
+                    Some(until) if now < until => {
+                        log::debug!("Ignoring hook-caused change to {}", path.display());
+                        suppress_until = Some(now + debounce);
+                        continue;
+                    }
+                    _ => suppress_until = None,
+                }
+                pending.record(path, action, now);
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+        }
+
+        let ready = pending.take_ready(Instant::now());
+        if ready.is_empty() {
+            continue;
+        }
+        for (path, action) in ready {
+            if let Some(hook) = hook_for_path(root, &path) {
+                log::info!(
+                    "{} {:?}: firing {}",
+                    path.display(),
+                    action,
+                    hook.function_name()
+                );
+                dispatch(hook, hook_args(root, &path, action));
+            }
+        }
+        suppress_until = Some(Instant::now() + debounce);
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_hook_for_path() {
+        let root = Path::new("/work");
+        assert_eq!(
+            hook_for_path(root, Path::new("/work/sections/ch1.md")),
+            Some(LifecycleHook::OnSectionSave)
+        );
+        assert_eq!(
+            hook_for_path(root, Path::new("/work/entities/people/ada.md")),
+            Some(LifecycleHook::OnEntityChange)
+        );
+        assert_eq!(hook_for_path(root, Path::new("/work/notes/a.md")), None);
+        assert_eq!(hook_for_path(root, Path::new("/work/sections")), None);
+        assert_eq!(
+            hook_for_path(root, Path::new("/work/sections/drafts")),
+            None
+        );
+        assert_eq!(
+            hook_for_path(root, Path::new("/work/entities/alice.yaml")),
+            Some(LifecycleHook::OnEntityChange)
+        );
+        assert_eq!(
+            hook_for_path(root, Path::new("/work/sections/.ch1.md.swp")),
+            None
+        );
+        assert_eq!(
+            hook_for_path(root, Path::new("/work/sections/ch1.md~")),
+            None
+        );
+        assert_eq!(
+            hook_for_path(root, Path::new("/elsewhere/sections/a.md")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_debouncer_merges_and_waits() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(Duration::from_millis(100));
+
+        debouncer.record(PathBuf::from("a"), ChangeAction::Create, at(0));
+        debouncer.record(PathBuf::from("a"), ChangeAction::Update, at(10));
+        debouncer.record(PathBuf::from("b"), ChangeAction::Update, at(20));
+        debouncer.record(PathBuf::from("c"), ChangeAction::Create, at(30));
+        debouncer.record(PathBuf::from("c"), ChangeAction::Delete, at(40));
+        assert!(debouncer.take_ready(at(100)).is_empty());
+
+        assert_eq!(
+            debouncer.take_ready(at(140)),
+            vec![
+                (PathBuf::from("a"), ChangeAction::Create),
+                (PathBuf::from("b"), ChangeAction::Update),
+            ]
+        );
+        assert!(debouncer.take_ready(at(500)).is_empty());
+
+        debouncer.record(PathBuf::from("a"), ChangeAction::Delete, at(600));
+        debouncer.record(PathBuf::from("a"), ChangeAction::Create, at(610));
+        assert_eq!(
+            debouncer.take_ready(at(710)),
+            vec![(PathBuf::from("a"), ChangeAction::Update)]
+        );
+    }
+
+    /// Poll `calls` until it has `count` entries or a few seconds pass
+    fn wait_for(calls: &Mutex<Vec<(LifecycleHook, serde_json::Value)>>, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while calls.lock().unwrap().len() < count && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_watcher_fires_hooks_without_looping() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir_all(root.join("sections")).unwrap();
+        fs::create_dir_all(root.join("entities")).unwrap();
+
+        // The hook rewrites the file that triggered it, like a formatter would
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let hook_calls = calls.clone();
+        let hook_root = root.clone();
+        let watcher = WorkspaceWatcher::with_debounce(
+            &root,
+            Duration::from_millis(150),
+            move |hook, args| {
+                let path = hook_root.join(args["path"].as_str().unwrap());
+                if path.exists() {
+                    fs::write(&path, "formatted").unwrap();
+                }
+                hook_calls.lock().unwrap().push((hook, args));
+            },
+        )
+        .unwrap();
+
+        fs::write(root.join("sections/ch1.md"), "draft").unwrap();
+        fs::write(root.join("notes.md"), "ignored").unwrap();
+        wait_for(&calls, 1);
+        // Long enough for a hook-triggered change to have come back around
+        std::thread::sleep(Duration::from_millis(800));
+
+        {
+            let calls = calls.lock().unwrap();
+            assert_eq!(calls.len(), 1, "{:?}", calls);
+            assert_eq!(calls[0].0, LifecycleHook::OnSectionSave);
+            assert_eq!(calls[0].1["path"], "sections/ch1.md");
+            assert_eq!(calls[0].1["source"], "watcher");
+        }
+
+        // Later edits fire again
+        fs::write(root.join("entities/ada.md"), "Ada").unwrap();
+        wait_for(&calls, 2);
+        assert_eq!(calls.lock().unwrap()[1].0, LifecycleHook::OnEntityChange);
+
+        watcher.stop();
+        fs::write(root.join("sections/ch2.md"), "after stop").unwrap();
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+}
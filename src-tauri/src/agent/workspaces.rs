@@ -0,0 +1,870 @@
+//! Per-workspace state, so several projects can be open (in separate windows) at once.
+//!
+//! Everything that belongs to one project lives in a [`WorkspaceState`], looked up
+//! in the [`WorkspaceRegistry`] by canonical workspace path. Two spellings of the
+//! same directory (a symlink, a trailing `..`) resolve to the same state, and a
+//! busy project can't use up another project's run slots.
+//!
+//! The app opens a workspace (`open_workspace`) when it loads a project and closes
+//! it when the project is closed. Runs, hooks, and watchers are only accepted for
+//! an open workspace, so closing one cancels everything still working in it and
+//! drops its parsed-entity cache.
+//!
+//! Staged (chunked) writes are deliberately not kept here: a handle belongs to
+//! the run that opened it and expires when that run ends, so another run can't
+//! commit a half-written file ([`StagedWrites`](super::staged_writes::StagedWrites)).
+//!
+//! Two runs editing the same files clobber each other, so by default a run gets
+//! its workspace to itself ([`RunConcurrency`]): a second run is refused, or waits
+//! in the workspace's queue until the runs ahead of it finish. Only runs that ask
+//! for `parallel` share a workspace, and only with each other.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use super::entity_api::{EntityCache, EntityStore};
+use super::lua_extensions::LifecycleHook;
+use super::types::RunConcurrency;
+use super::watcher::WorkspaceWatcher;
+
+/// Maximum concurrent agent runs in a single workspace
+pub const MAX_RUNS_PER_WORKSPACE: usize = 3;
+
+/// Canonical form of a workspace path, used as the registry key
+pub fn canonical_workspace(path: &Path) -> Result<PathBuf, String> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve workspace path {}: {}", path.display(), e))?;
+    if !canonical.is_dir() {
+        return Err(format!(
+            "Workspace path is not a directory: {}",
+            path.display()
+        ));
+    }
+    Ok(canonical)
+}
+
+// ============================================================================
+// Workspace State
+// ============================================================================
+
+/// A run in progress in a workspace
+struct ActiveRun {
+    cancel: CancellationToken,
+    /// Started as `parallel`, so other parallel runs may join it
+    shared: bool,
+}
+
+/// State belonging to one open workspace
+pub struct WorkspaceState {
+    root: PathBuf,
+    max_runs: usize,
+    /// Runs in this workspace, by run ID
+    runs: RwLock<HashMap<String, ActiveRun>>,
+    /// Runs waiting for the workspace, next in line first
+    queue: Mutex<VecDeque<(String, CancellationToken)>>,
+    /// Signalled when a run finishes or leaves the queue
+    released: Notify,
+    /// File watcher firing extension hooks, while one is running
+    watcher: Mutex<Option<WorkspaceWatcher>>,
+    /// Parsed entity and section files, shared by every store opened here
+    entities: EntityCache,
+    /// Set when the workspace is closed; no run may claim a slot after that
+    closed: AtomicBool,
+}
+
+impl WorkspaceState {
+    fn new(root: PathBuf, max_runs: usize) -> Self {
+        WorkspaceState {
+            root,
+            max_runs,
+            runs: RwLock::new(HashMap::new()),
+            queue: Mutex::new(VecDeque::new()),
+            released: Notify::new(),
+            watcher: Mutex::new(None),
+            entities: EntityCache::default(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Canonical workspace root
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// This workspace's entity cache, for stores opened elsewhere (e.g. in a run)
+    pub fn entity_cache(&self) -> EntityCache {
+        self.entities.clone()
+    }
+
+    /// An entity store sharing this workspace's cache
+    pub fn entity_store(&self) -> EntityStore {
+        EntityStore::new(&self.root).with_cache(self.entity_cache())
+    }
+
+    /// Maximum concurrent runs in this workspace
+    pub fn max_runs(&self) -> usize {
+        self.max_runs
+    }
+
+    /// IDs of the runs currently in progress here
+    pub fn run_ids(&self) -> Vec<String> {
+        self.runs
+            .read()
+            .map(|runs| runs.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Number of runs currently in progress here
+    pub fn run_count(&self) -> usize {
+        self.runs.read().map(|runs| runs.len()).unwrap_or(0)
+    }
+
+    /// Number of runs waiting for this workspace
+    pub fn queued_count(&self) -> usize {
+        self.queue.lock().map(|queue| queue.len()).unwrap_or(0)
+    }
+
+    /// Cancel every run in this workspace, queued ones included, returning how
+    /// many were signalled
+    pub fn cancel_runs(&self) -> usize {
+        let mut cancelled = 0;
+        if let Ok(queue) = self.queue.lock() {
+            for (_, token) in queue.iter() {
+                token.cancel();
+            }
+            cancelled += queue.len();
+        }
+        if let Ok(runs) = self.runs.read() {
+            for run in runs.values() {
+                run.cancel.cancel();
+            }
+            cancelled += runs.len();
+        }
+        cancelled
+    }
+
+    /// Cancel `run_id` if it is waiting in this workspace's queue
+    fn cancel_queued(&self, run_id: &str) -> bool {
+        let Ok(queue) = self.queue.lock() else {
+            return false;
+        };
+        match queue.iter().find(|(id, _)| id == run_id) {
+            Some((_, token)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Mark the workspace closed, then cancel its runs and wake the queued ones
+    /// so they give up their place. Returns how many runs were signalled.
+    fn close(&self) -> usize {
+        self.closed.store(true, Ordering::SeqCst);
+        self.stop_watcher();
+        let cancelled = self.cancel_runs();
+        self.released.notify_waiters();
+        cancelled
+    }
+
+    fn closed_error(&self) -> String {
+        format!("Workspace {} was closed", self.root.display())
+    }
+
+    /// Take a run slot if `run_id` may start now. A queued run may only start
+    /// when it is next in line; any other run only when nobody is queued.
+    fn claim(
+        &self,
+        run_id: &str,
+        cancel_token: &CancellationToken,
+        concurrency: RunConcurrency,
+    ) -> Result<(), String> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(self.closed_error());
+        }
+        let mut queue = self
+            .queue
+            .lock()
+            .map_err(|e| format!("Failed to lock workspace queue: {}", e))?;
+        let queued = queue.iter().any(|(id, _)| id == run_id);
+        if queued && queue.front().map(|(id, _)| id.as_str()) != Some(run_id) {
+            return Err("Waiting for the runs queued ahead of this one".to_string());
+        }
+        if !queued && !queue.is_empty() {
+            return Err(format!(
+                "{} agent run(s) are queued for this workspace. Wait for them to finish or use concurrency \"queue\".",
+                queue.len()
+            ));
+        }
+
+        let mut runs = self
+            .runs
+            .write()
+            .map_err(|e| format!("Failed to write workspace runs: {}", e))?;
+        let shared = concurrency == RunConcurrency::Parallel;
+        if let Some((id, _)) = runs.iter().find(|(_, run)| !shared || !run.shared) {
+            return Err(format!(
+                "Another agent run ({}) is already working in this workspace. Wait for it to finish, cancel it, or use concurrency \"queue\".",
+                id
+            ));
+        }
+        if runs.len() >= self.max_runs {
+            return Err(format!(
+                "Too many concurrent agent runs in this workspace ({}/{}). Please wait for an existing run to complete or cancel one.",
+                runs.len(),
+                self.max_runs
+            ));
+        }
+        runs.insert(
+            run_id.to_string(),
+            ActiveRun {
+                cancel: cancel_token.clone(),
+                shared,
+            },
+        );
+        if queued {
+            queue.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Take `run_id` out of the queue and wake the runs behind it
+    fn leave_queue(&self, run_id: &str) {
+        if let Ok(mut queue) = self.queue.lock() {
+            queue.retain(|(id, _)| id != run_id);
+        }
+        self.released.notify_waiters();
+    }
+
+    /// Start watching this workspace for section and entity changes. Returns
+    /// false if a watcher was already running.
+    pub fn start_watcher(
+        &self,
+        dispatch: impl Fn(LifecycleHook, serde_json::Value) + Send + 'static,
+    ) -> Result<bool, String> {
+        let mut watcher = self
+            .watcher
+            .lock()
+            .map_err(|e| format!("Failed to lock workspace watcher: {}", e))?;
+        if watcher.is_some() {
+            return Ok(false);
+        }
+        *watcher = Some(WorkspaceWatcher::start(&self.root, dispatch)?);
+        Ok(true)
+    }
+
+    /// Stop the workspace's watcher. Returns false if none was running.
+    pub fn stop_watcher(&self) -> bool {
+        // Take it out first so the lock isn't held while hooks finish
+        let watcher = self.watcher.lock().ok().and_then(|mut w| w.take());
+        match watcher {
+            Some(watcher) => {
+                watcher.stop();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Holds a run slot in a workspace; the slot is released on drop
+pub struct WorkspaceRunGuard {
+    state: Arc<WorkspaceState>,
+    run_id: String,
+}
+
+impl WorkspaceRunGuard {
+    /// The entity cache of the workspace the run holds a slot in
+    pub fn entity_cache(&self) -> EntityCache {
+        self.state.entity_cache()
+    }
+}
+
+impl Drop for WorkspaceRunGuard {
+    fn drop(&mut self) {
+        if let Ok(mut runs) = self.state.runs.write() {
+            runs.remove(&self.run_id);
+        }
+        self.state.released.notify_waiters();
+    }
+}
+
+/// Holds a run's place in a workspace queue; it leaves the queue on drop,
+/// including when the waiting future is dropped
+struct QueuePlace<'a> {
+    state: &'a WorkspaceState,
+    run_id: &'a str,
+}
+
+impl Drop for QueuePlace<'_> {
+    fn drop(&mut self) {
+        self.state.leave_queue(self.run_id);
+    }
+}
+
+// ============================================================================
+// Registry
+// ============================================================================
+
+/// Open workspaces, keyed by canonical path
+pub struct WorkspaceRegistry {
+    workspaces: RwLock<HashMap<PathBuf, Arc<WorkspaceState>>>,
+    max_runs_per_workspace: usize,
+}
+
+impl WorkspaceRegistry {
+    pub fn new() -> Self {
+        Self::with_max_runs(MAX_RUNS_PER_WORKSPACE)
+    }
+
+    /// A registry with a different per-workspace run limit
+    pub fn with_max_runs(max_runs_per_workspace: usize) -> Self {
+        WorkspaceRegistry {
+            workspaces: RwLock::new(HashMap::new()),
+            max_runs_per_workspace,
+        }
+    }
+
+    /// Open the workspace at `path`, or return its state if it already is
+    pub fn open(&self, path: &Path) -> Result<Arc<WorkspaceState>, String> {
+        let root = canonical_workspace(path)?;
+        let mut workspaces = self
+            .workspaces
+            .write()
+            .map_err(|e| format!("Failed to write workspace registry: {}", e))?;
+        Ok(workspaces
+            .entry(root.clone())
+            .or_insert_with(|| Arc::new(WorkspaceState::new(root, self.max_runs_per_workspace)))
+            .clone())
+    }
+
+    /// State for the workspace at `path` if it is open
+    pub fn get(&self, path: &Path) -> Option<Arc<WorkspaceState>> {
+        let root = path.canonicalize().ok()?;
+        self.workspaces.read().ok()?.get(&root).cloned()
+    }
+
+    /// State for the workspace at `path`, which must have been opened
+    pub fn require(&self, path: &Path) -> Result<Arc<WorkspaceState>, String> {
+        let root = canonical_workspace(path)?;
+        self.workspaces
+            .read()
+            .map_err(|e| format!("Failed to read workspace registry: {}", e))?
+            .get(&root)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Workspace {} is not open; open it with open_workspace first",
+                    path.display()
+                )
+            })
+    }
+
+    /// Claim a run slot in the open workspace at `path`, failing if the
+    /// workspace is busy for a run of this `concurrency`
+    pub fn begin_run(
+        &self,
+        path: &Path,
+        run_id: &str,
+        cancel_token: CancellationToken,
+        concurrency: RunConcurrency,
+    ) -> Result<WorkspaceRunGuard, String> {
+        let state = self.require(path)?;
+        state.claim(run_id, &cancel_token, concurrency)?;
+        Ok(WorkspaceRunGuard {
+            state,
+            run_id: run_id.to_string(),
+        })
+    }
+
+    /// Claim the open workspace at `path` for a run of its own, waiting in line
+    /// while other runs are working in it. `on_queued` is called with the run's
+    /// place in line if it has to wait. Fails when `cancel_token` fires or
+    /// `timeout` passes first.
+    pub async fn queue_run(
+        &self,
+        path: &Path,
+        run_id: &str,
+        cancel_token: CancellationToken,
+        timeout: Duration,
+        on_queued: impl FnOnce(usize),
+    ) -> Result<WorkspaceRunGuard, String> {
+        let state = self.require(path)?;
+        let guard = |state: Arc<WorkspaceState>| WorkspaceRunGuard {
+            state,
+            run_id: run_id.to_string(),
+        };
+        match state.claim(run_id, &cancel_token, RunConcurrency::Queue) {
+            Ok(()) => return Ok(guard(state)),
+            Err(e) if state.closed.load(Ordering::SeqCst) => return Err(e),
+            Err(_) => {}
+        }
+
+        let position = {
+            let mut queue = state
+                .queue
+                .lock()
+                .map_err(|e| format!("Failed to lock workspace queue: {}", e))?;
+            queue.push_back((run_id.to_string(), cancel_token.clone()));
+            queue.len()
+        };
+        let place = QueuePlace {
+            state: &state,
+            run_id,
+        };
+        on_queued(position);
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            // Registered before checking, so a release in between isn't missed
+            let released = state.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+
+            // A run cancelled or left behind by a closed workspace never starts,
+            // even if a slot came free at the same time
+            if cancel_token.is_cancelled() {
+                return Err("Agent run was cancelled while waiting for the workspace".to_string());
+            }
+            if state.closed.load(Ordering::SeqCst) {
+                return Err(state.closed_error());
+            }
+            if state
+                .claim(run_id, &cancel_token, RunConcurrency::Queue)
+                .is_ok()
+            {
+                drop(place);
+                return Ok(guard(state.clone()));
+            }
+            tokio::select! {
+                _ = &mut released => {}
+                _ = cancel_token.cancelled() => {
+                    return Err("Agent run was cancelled while waiting for the workspace".to_string());
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(format!(
+                        "Timed out after {}s waiting for other agent runs in this workspace to finish",
+                        timeout.as_secs()
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Close the workspace at `path`: cancel its runs, turn away the ones still
+    /// queued for it, stop its watcher, and drop its state. Returns the number of
+    /// runs that were cancelled.
+    pub fn close(&self, path: &Path) -> Result<usize, String> {
+        let root = canonical_workspace(path)?;
+        let state = self
+            .workspaces
+            .write()
+            .map_err(|e| format!("Failed to write workspace registry: {}", e))?
+            .remove(&root);
+        Ok(state.map(|s| s.close()).unwrap_or(0))
+    }
+
+    /// Cancel `run_id` if it is waiting in an open workspace's queue. Returns
+    /// false if no workspace has it queued.
+    pub fn cancel_queued(&self, run_id: &str) -> bool {
+        match self.workspaces.read() {
+            Ok(workspaces) => workspaces.values().any(|s| s.cancel_queued(run_id)),
+            Err(_) => false,
+        }
+    }
+
+    /// Stop every workspace's file watcher, e.g. on app exit
+    pub fn stop_watchers(&self) {
+        let states: Vec<Arc<WorkspaceState>> = match self.workspaces.read() {
+            Ok(workspaces) => workspaces.values().cloned().collect(),
+            Err(_) => return,
+        };
+        for state in states {
+            state.stop_watcher();
+        }
+    }
+}
+
+impl Default for WorkspaceRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shared workspace registry type for Tauri state
+pub type SharedWorkspaceRegistry = Arc<WorkspaceRegistry>;
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_run_limits_are_per_workspace() {
+        let a = TempDir::new().unwrap();
+        let b = TempDir::new().unwrap();
+        let registry = WorkspaceRegistry::with_max_runs(2);
+        registry.open(a.path()).unwrap();
+        registry.open(b.path()).unwrap();
+
+        let _a1 = registry
+            .begin_run(
+                a.path(),
+                "a1",
+                CancellationToken::new(),
+                RunConcurrency::Parallel,
+            )
+            .unwrap();
+        let a2 = registry
+            .begin_run(
+                a.path(),
+                "a2",
+                CancellationToken::new(),
+                RunConcurrency::Parallel,
+            )
+            .unwrap();
+        let err = registry
+            .begin_run(
+                a.path(),
+                "a3",
+                CancellationToken::new(),
+                RunConcurrency::Parallel,
+            )
+            .err()
+            .unwrap();
+        assert!(err.contains("in this workspace (2/2)"));
+
+        // A full workspace doesn't affect another one
+        let _b1 = registry
+            .begin_run(
+                b.path(),
+                "b1",
+                CancellationToken::new(),
+                RunConcurrency::Parallel,
+            )
+            .unwrap();
+        assert_eq!(registry.get(b.path()).unwrap().run_ids(), vec!["b1"]);
+
+        // Finishing a run frees its slot
+        drop(a2);
+        assert_eq!(registry.get(a.path()).unwrap().run_count(), 1);
+        assert!(registry
+            .begin_run(
+                a.path(),
+                "a3",
+                CancellationToken::new(),
+                RunConcurrency::Parallel
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_path_spellings_share_state() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("book")).unwrap();
+        let registry = WorkspaceRegistry::new();
+
+        let direct = registry.open(&dir.path().join("book")).unwrap();
+        let dotted = registry
+            .open(&dir.path().join("book").join("..").join("book"))
+            .unwrap();
+        assert!(Arc::ptr_eq(&direct, &dotted));
+        assert_eq!(
+            direct.root(),
+            dir.path().join("book").canonicalize().unwrap()
+        );
+
+        assert!(registry.open(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_close_cancels_only_that_workspace() {
+        let a = TempDir::new().unwrap();
+        let b = TempDir::new().unwrap();
+        let registry = WorkspaceRegistry::new();
+        let token_a = CancellationToken::new();
+        let token_b = CancellationToken::new();
+        registry.open(a.path()).unwrap();
+        registry.open(b.path()).unwrap();
+
+        let _run_a = registry
+            .begin_run(a.path(), "a", token_a.clone(), RunConcurrency::Reject)
+            .unwrap();
+        let _run_b = registry
+            .begin_run(b.path(), "b", token_b.clone(), RunConcurrency::Reject)
+            .unwrap();
+
+        assert_eq!(registry.close(a.path()).unwrap(), 1);
+        assert!(token_a.is_cancelled());
+        assert!(!token_b.is_cancelled());
+        assert!(registry.get(a.path()).is_none());
+        assert!(registry.get(b.path()).is_some());
+
+        // A closed workspace takes no new runs until it is opened again
+        let err = registry
+            .begin_run(
+                a.path(),
+                "a2",
+                CancellationToken::new(),
+                RunConcurrency::Reject,
+            )
+            .err()
+            .unwrap();
+        assert!(err.contains("is not open"), "{}", err);
+        registry.open(a.path()).unwrap();
+        assert!(registry
+            .begin_run(
+                a.path(),
+                "a2",
+                CancellationToken::new(),
+                RunConcurrency::Reject
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_entity_cache_is_shared_until_close() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("entities")).unwrap();
+        std::fs::write(
+            dir.path().join("entities/alice.yaml"),
+            "id: \"00000000-0000-4000-8000-000000000001\"\nname: Alice\ntype: fact\n",
+        )
+        .unwrap();
+        let registry = WorkspaceRegistry::new();
+        let state = registry.open(dir.path()).unwrap();
+
+        assert_eq!(state.entity_store().list_all().unwrap().len(), 1);
+        let store = registry
+            .open(&dir.path().join(".").join("entities").join(".."))
+            .unwrap()
+            .entity_store();
+        store.list_all().unwrap();
+        assert_eq!(store.parse_count(), 1);
+
+        // Closing drops the cache; reopening starts a new one
+        registry.close(dir.path()).unwrap();
+        let reopened = registry.open(dir.path()).unwrap().entity_store();
+        assert_eq!(reopened.parse_count(), 0);
+        reopened.list_all().unwrap();
+        assert_eq!(reopened.parse_count(), 1);
+    }
+
+    #[test]
+    fn test_require_only_finds_open_workspaces() {
+        let dir = TempDir::new().unwrap();
+        let registry = WorkspaceRegistry::new();
+
+        let err = registry.require(dir.path()).err().unwrap();
+        assert!(err.contains("open_workspace"), "{}", err);
+        assert!(registry.get(dir.path()).is_none());
+
+        let opened = registry.open(dir.path()).unwrap();
+        assert!(Arc::ptr_eq(&registry.require(dir.path()).unwrap(), &opened));
+        assert!(registry.require(&dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn test_second_run_is_refused_unless_parallel() {
+        let dir = TempDir::new().unwrap();
+        let registry = WorkspaceRegistry::new();
+        registry.open(dir.path()).unwrap();
+        let begin = |id: &str, concurrency| {
+            registry.begin_run(dir.path(), id, CancellationToken::new(), concurrency)
+        };
+
+        let first = begin("first", RunConcurrency::Reject).unwrap();
+        let err = begin("second", RunConcurrency::Reject).err().unwrap();
+        assert!(err.contains("(first) is already working"), "{}", err);
+        // A parallel run can't join a run that has the workspace to itself
+        assert!(begin("second", RunConcurrency::Parallel).is_err());
+        drop(first);
+
+        // Parallel runs share the workspace, but only with each other
+        let _p1 = begin("p1", RunConcurrency::Parallel).unwrap();
+        let _p2 = begin("p2", RunConcurrency::Parallel).unwrap();
+        assert!(begin("exclusive", RunConcurrency::Reject).is_err());
+        assert_eq!(registry.get(dir.path()).unwrap().run_count(), 2);
+    }
+
+    /// Stands in for a long-running agent: holds its slot until `token` is cancelled
+    fn hold_until_cancelled(guard: WorkspaceRunGuard, token: CancellationToken) {
+        tokio::spawn(async move {
+            token.cancelled().await;
+            drop(guard);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_queued_runs_start_in_order_when_front_run_ends() {
+        let dir = TempDir::new().unwrap();
+        let registry = Arc::new(WorkspaceRegistry::new());
+        let state = registry.open(dir.path()).unwrap();
+
+        let front = CancellationToken::new();
+        let guard = registry
+            .begin_run(dir.path(), "front", front.clone(), RunConcurrency::Reject)
+            .unwrap();
+        hold_until_cancelled(guard, front.clone());
+
+        let (positions_tx, mut positions) = tokio::sync::mpsc::unbounded_channel();
+        let mut waiting = Vec::new();
+        for id in ["second", "third"] {
+            let registry = registry.clone();
+            let root = dir.path().to_path_buf();
+            let positions_tx = positions_tx.clone();
+            waiting.push(tokio::spawn(async move {
+                let token = CancellationToken::new();
+                let guard = registry
+                    .queue_run(&root, id, token.clone(), Duration::from_secs(5), |p| {
+                        positions_tx.send((id, p)).unwrap();
+                    })
+                    .await;
+                (guard, token)
+            }));
+            // Queue them one at a time so their order is known
+            assert_eq!(positions.recv().await.unwrap().0, id);
+        }
+        assert_eq!(state.queued_count(), 2);
+        // Nobody jumps the queue, even with the front run gone
+        let err = registry
+            .begin_run(
+                dir.path(),
+                "late",
+                CancellationToken::new(),
+                RunConcurrency::Reject,
+            )
+            .err()
+            .unwrap();
+        assert!(err.contains("queued"), "{}", err);
+
+        // Cancelling the front run lets the next one in, and only that one
+        front.cancel();
+        let (second, second_token) = waiting.remove(0).await.unwrap();
+        let second = second.unwrap();
+        assert_eq!(state.run_ids(), vec!["second"]);
+        assert_eq!(state.queued_count(), 1);
+
+        hold_until_cancelled(second, second_token.clone());
+        second_token.cancel();
+        let (third, _) = waiting.remove(0).await.unwrap();
+        assert!(third.is_ok());
+        assert_eq!(state.run_ids(), vec!["third"]);
+        assert_eq!(state.queued_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_queued_run_times_out_or_is_cancelled() {
+        let dir = TempDir::new().unwrap();
+        let registry = WorkspaceRegistry::new();
+        let state = registry.open(dir.path()).unwrap();
+        let _front = registry
+            .begin_run(
+                dir.path(),
+                "front",
+                CancellationToken::new(),
+                RunConcurrency::Reject,
+            )
+            .unwrap();
+
+        let mut queued_at = None;
+        let err = registry
+            .queue_run(
+                dir.path(),
+                "slow",
+                CancellationToken::new(),
+                Duration::from_millis(50),
+                |p| queued_at = Some(p),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert!(err.contains("Timed out"), "{}", err);
+        assert_eq!(queued_at, Some(1));
+        assert_eq!(state.queued_count(), 0);
+
+        // Closing the workspace cancels runs still waiting for it
+        let token = CancellationToken::new();
+        let closing = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            registry.close(dir.path()).unwrap()
+        };
+        let (queued, cancelled) = tokio::join!(
+            registry.queue_run(
+                dir.path(),
+                "waiting",
+                token.clone(),
+                Duration::from_secs(5),
+                |_| {}
+            ),
+            closing
+        );
+        assert!(queued.err().unwrap().contains("cancelled"));
+        assert_eq!(cancelled, 2);
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_queued_runs_can_be_cancelled_and_closing_turns_them_away() {
+        let dir = TempDir::new().unwrap();
+        let registry = Arc::new(WorkspaceRegistry::new());
+        let state = registry.open(dir.path()).unwrap();
+        let front = registry
+            .begin_run(
+                dir.path(),
+                "front",
+                CancellationToken::new(),
+                RunConcurrency::Reject,
+            )
+            .unwrap();
+
+        let queue = |id: &'static str| {
+            let registry = registry.clone();
+            let root = dir.path().to_path_buf();
+            let (queued_tx, queued) = tokio::sync::oneshot::channel();
+            let waiting = tokio::spawn(async move {
+                registry
+                    .queue_run(
+                        &root,
+                        id,
+                        CancellationToken::new(),
+                        Duration::from_secs(5),
+                        |_| queued_tx.send(()).unwrap(),
+                    )
+                    .await
+                    .map(|_| ())
+            });
+            (waiting, queued)
+        };
+
+        // A run still waiting for the workspace is found by its ID
+        let (waiting, queued) = queue("waiting");
+        queued.await.unwrap();
+        assert!(!registry.cancel_queued("nobody"));
+        assert!(registry.cancel_queued("waiting"));
+        assert!(waiting.await.unwrap().unwrap_err().contains("cancelled"));
+        assert_eq!(state.queued_count(), 0);
+
+        // A queued run doesn't take the slot its closed workspace frees up
+        let (waiting, queued) = queue("late");
+        queued.await.unwrap();
+        assert_eq!(registry.close(dir.path()).unwrap(), 2);
+        drop(front);
+        assert!(waiting.await.unwrap().is_err());
+        assert_eq!(state.run_count(), 0);
+        let err = state
+            .claim("after", &CancellationToken::new(), RunConcurrency::Reject)
+            .unwrap_err();
+        assert!(err.contains("was closed"), "{}", err);
+    }
+}
@@ -4,28 +4,104 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
-use tauri::{AppHandle, Emitter, State};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
+use crate::agent::backups::{self, BackupManifest};
 use crate::agent::credentials::{CredentialManager, ProviderStatus, SharedCredentialManager};
-use crate::agent::lua_extensions::{ExtensionRegistry, HookResult, LifecycleHook};
-use crate::agent::session::{AuditEntry, Session, SharedSessionStore};
+use crate::agent::doctor::{
+    self, HealthReport, HealthScope, ProbeTarget, SharedHealthCache, WorkspaceFinding,
+};
+use crate::agent::entity_api::{EntityStore, MergeSummary, Tag};
+use crate::agent::entity_graph::{self, GraphExport, GraphFormat, GraphOptions};
+use crate::agent::entity_tags::{self, SuggestedTag, TagRange};
+use crate::agent::event_channel::{ChannelSink, EventSink};
+use crate::agent::extension_stats::ExtensionStatsSummary;
+use crate::agent::global_config::{GlobalConfig, SharedGlobalConfig, GLOBAL_CONFIG_FILE};
+use crate::agent::idle::SharedRunActivity;
+use crate::agent::llm::{RateLimitSnapshot, SharedRateLimits};
+use crate::agent::lua_extensions::{
+    ExtensionDetails, ExtensionRegistry, HookResult, LifecycleHook, LoadedExtension,
+};
+use crate::agent::manuscript::{self, CompileOptions, CompileReport};
+use crate::agent::ollama::{self, OllamaPulls, OllamaStatus};
+use crate::agent::prompt_templates::{self, PromptTemplate};
+use crate::agent::redact::mask_key;
+use crate::agent::scaffold::{self, ScaffoldManifest};
+use crate::agent::semantic_index::{self, EmbeddingClient, IndexReport};
+use crate::agent::session::{
+    AuditEntry, AuditEventType, HistoryNormalization, Session, SessionAudit, SessionUsage,
+    SharedSessionStore,
+};
+use crate::agent::session_report::{self, ReportFormat};
+use crate::agent::shell_env::{self, ShellEnv};
+use crate::agent::shell_policy::ShellPolicy;
+use crate::agent::shell_program::validate_shell;
+use crate::agent::shutdown::{self, ShutdownReport};
+use crate::agent::signature::{SignaturePolicy, SignatureVerification};
+use crate::agent::text::{normalize_input, InputLimits};
+use crate::agent::tool_gating::validate_disabled_tools;
+use crate::agent::tools::{
+    shell_working_dir, PathValidator, ShellEnvironment, ToolLimits, DEFAULT_MAX_FILE_BYTES,
+};
+use crate::agent::transcripts::{SharedTranscriptStore, Transcript, TranscriptHeader};
+use crate::agent::types::{
+    AgentError, ApprovalMode, ApprovalScope, ErrorInfo, FileChange, InjectionGuard,
+    OpenRouterRouting, PlannedToolCall, RunConcurrency, RunSummary,
+};
+use crate::agent::workspaces::{
+    canonical_workspace, SharedWorkspaceRegistry, WorkspaceRegistry, MAX_RUNS_PER_WORKSPACE,
+};
 use crate::agent::{
-    self, AgentConfig, AgentEvent, LlmProvider, Message, MessageRole, ToolApprovalStore,
+    self, AgentConfig, AgentEvent, ApprovalAnswer, LlmProvider, Message, MessageRole, RunContext,
+    ToolApprovalStore,
 };
 
 /// Protocol version for the native agent API
 pub const PROTOCOL_VERSION: &str = "1.1.0";
 
-/// Maximum concurrent agent runs allowed
-/// This prevents resource exhaustion from too many simultaneous LLM calls
-pub const MAX_CONCURRENT_RUNS: usize = 3;
+/// Maximum number of history messages accepted by run_native_agent
+pub const MAX_HISTORY_MESSAGES: usize = 100;
+
+/// Maximum size of a single history message's content in bytes
+pub const MAX_MESSAGE_BYTES: usize = 100_000;
+
+/// Size caps for the task
+pub const TASK_LIMITS: InputLimits = InputLimits {
+    max_chars: 100_000,
+    max_bytes: 200_000,
+};
+
+/// Size caps for the system prompt
+pub const SYSTEM_PROMPT_LIMITS: InputLimits = InputLimits {
+    max_chars: 50_000,
+    max_bytes: 100_000,
+};
+
+/// Size caps for one history message (a char never takes less than a byte,
+/// so the byte cap is the one that binds)
+const MESSAGE_LIMITS: InputLimits = InputLimits {
+    max_chars: MAX_MESSAGE_BYTES,
+    max_bytes: MAX_MESSAGE_BYTES,
+};
+
+/// Maximum combined size of all history message contents in bytes
+pub const MAX_HISTORY_BYTES: usize = 1_000_000;
 
-/// Shared extension registry state (RwLock allows concurrent reads)
-pub type SharedExtensionRegistry = Arc<RwLock<ExtensionRegistry>>;
+/// Maximum concurrent agent runs allowed across all open workspaces
+/// This prevents resource exhaustion from too many simultaneous LLM calls;
+/// each workspace is further limited to `MAX_RUNS_PER_WORKSPACE`
+pub const MAX_CONCURRENT_RUNS: usize = 8;
+
+/// Shared extension registry state (RwLock allows concurrent reads). Runs and
+/// hooks hold the current registry's `Arc`; loading or unloading an extension
+/// copies it first if any are still using it.
+pub type SharedExtensionRegistry = Arc<RwLock<Arc<ExtensionRegistry>>>;
 
 /// Running agent tasks that can be cancelled
 pub type RunningTasks = Arc<RwLock<HashMap<String, CancellationToken>>>;
@@ -64,33 +140,161 @@ pub struct InputMessage {
     pub content: String,
 }
 
+impl InputMessage {
+    /// Parse the role string, returning None for unrecognized roles
+    fn parse_role(&self) -> Option<MessageRole> {
+        match self.role.as_str() {
+            "developer" => Some(MessageRole::Developer),
+            "system" => Some(MessageRole::System),
+            "user" => Some(MessageRole::User),
+            "assistant" => Some(MessageRole::Assistant),
+            "tool" => Some(MessageRole::Tool),
+            _ => None,
+        }
+    }
+}
+
 impl From<InputMessage> for Message {
     fn from(msg: InputMessage) -> Self {
-        let role = match msg.role.as_str() {
-            "developer" => MessageRole::Developer,
-            "system" => MessageRole::System,
-            "assistant" => MessageRole::Assistant,
-            "tool" => MessageRole::Tool,
-            _ => MessageRole::User,
-        };
+        let role = msg.parse_role().unwrap_or(MessageRole::User);
         Message {
             role,
             content: Some(msg.content),
             tool_calls: None,
             tool_call_id: None,
+            thinking: Vec::new(),
+        }
+    }
+}
+
+/// Validate the message history from the frontend and convert it to agent messages.
+///
+/// Message content goes through [`normalize_input`]. Rejects empty roles and
+/// oversized or garbage-only messages, identifying the offending index. Unknown
+/// roles are treated as `user` with a warning. When the history exceeds the
+/// message-count or total-byte budget, it is rejected unless `trim_history` is set,
+/// in which case the oldest messages are dropped until it fits.
+///
+/// The history is then shaped so providers accept it: messages with no content
+/// are dropped, consecutive user messages are merged (a final one is merged into
+/// the task by the agent loop), and for Claude, assistant messages before the
+/// first user message are dropped. Returns what was changed alongside the messages.
+pub fn prepare_history(
+    mut messages: Vec<InputMessage>,
+    trim_history: bool,
+    provider: LlmProvider,
+) -> Result<(Vec<Message>, HistoryNormalization), String> {
+    for (index, msg) in messages.iter_mut().enumerate() {
+        if msg.role.trim().is_empty() {
+            return Err(format!("messages[{}]: role cannot be empty", index));
+        }
+        msg.content = normalize_input(
+            &format!("messages[{}]: content", index),
+            &msg.content,
+            MESSAGE_LIMITS,
+        )?;
+        if msg.parse_role().is_none() {
+            log::warn!(
+                "messages[{}]: unknown role '{}', treating as user",
+                index,
+                msg.role
+            );
+        }
+    }
+
+    let mut report = HistoryNormalization::default();
+    let before = messages.len();
+    messages.retain(|m| !m.content.trim().is_empty());
+    report.dropped_empty = before - messages.len();
+
+    let mut start = 0;
+    let mut total_bytes: usize = messages.iter().map(|m| m.content.len()).sum();
+
+    if !trim_history {
+        if messages.len() > MAX_HISTORY_MESSAGES {
+            return Err(format!(
+                "Too many messages in history ({}, max {})",
+                messages.len(),
+                MAX_HISTORY_MESSAGES
+            ));
+        }
+        if total_bytes > MAX_HISTORY_BYTES {
+            return Err(format!(
+                "Message history too large ({} bytes, max {})",
+                total_bytes, MAX_HISTORY_BYTES
+            ));
+        }
+    } else {
+        while messages.len() - start > MAX_HISTORY_MESSAGES || total_bytes > MAX_HISTORY_BYTES {
+            total_bytes -= messages[start].content.len();
+            start += 1;
+        }
+        report.trimmed = start;
+    }
+
+    let mut history: Vec<Message> = Vec::with_capacity(messages.len() - start);
+    for msg in messages.into_iter().skip(start).map(Message::from) {
+        match history.last_mut() {
+            Some(prev) if prev.role == MessageRole::User && msg.role == MessageRole::User => {
+                let content = prev.content.get_or_insert_with(String::new);
+                content.push_str("\n\n");
+                content.push_str(msg.content.as_deref().unwrap_or_default());
+                report.merged += 1;
+            }
+            _ => history.push(msg),
+        }
+    }
+    // The task follows the history as a user message
+    if history.last().is_some_and(|m| m.role == MessageRole::User) {
+        report.merged += 1;
+    }
+
+    // Claude rejects a conversation that opens with an assistant message; system
+    // messages don't count, as they move into the system prompt
+    if provider == LlmProvider::Claude {
+        while let Some(first) = history
+            .iter()
+            .position(|m| !matches!(m.role, MessageRole::System | MessageRole::Developer))
+            .filter(|&i| history[i].role == MessageRole::Assistant)
+        {
+            history.remove(first);
+            report.dropped_leading += 1;
         }
     }
+
+    if !report.is_unchanged() {
+        log::info!(
+            "Normalized message history: {} empty dropped, {} oldest trimmed, {} merged, {} leading assistant dropped",
+            report.dropped_empty,
+            report.trimmed,
+            report.merged,
+            report.dropped_leading
+        );
+    }
+    Ok((history, report))
+}
+
+/// Normalize the task and system prompt, rejecting an empty task and anything over
+/// [`TASK_LIMITS`] or [`SYSTEM_PROMPT_LIMITS`]
+pub fn prepare_prompt(task: &str, system_prompt: &str) -> Result<(String, String), String> {
+    if task.trim().is_empty() {
+        return Err("Task cannot be empty".to_string());
+    }
+    let task = normalize_input("Task", task, TASK_LIMITS)?;
+    let system_prompt = normalize_input("System prompt", system_prompt, SYSTEM_PROMPT_LIMITS)?;
+    Ok((task, system_prompt))
 }
 
 /// Configuration input from frontend
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct InputConfig {
     /// LLM provider to use (openai, claude, ollama)
     #[serde(default)]
     pub provider: LlmProvider,
     /// API key for the provider (from frontend Settings UI)
-    /// Falls back to environment variables via CredentialManager if not provided
-    #[serde(default)]
+    /// Falls back to the keychain, then environment variables, via CredentialManager.
+    /// Never serialized, and masked in `Debug` output
+    #[serde(default, skip_serializing)]
     pub api_key: Option<String>,
     /// Model to use
     #[serde(default = "default_model")]
@@ -110,8 +314,162 @@ pub struct InputConfig {
     /// Tool approval mode
     #[serde(default)]
     pub approval_mode: crate::agent::types::ApprovalMode,
+    /// How much file content tool and text events carry (full | truncated | metadata_only)
+    #[serde(default)]
+    pub event_detail: crate::agent::types::EventDetail,
+    /// Stream replies as text_chunk events (turn off for proxies without SSE support)
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    /// Retries for rate-limited, overloaded, or timed-out LLM calls
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Longest wait before a retry in milliseconds
+    #[serde(default = "default_max_retry_backoff_ms")]
+    pub max_retry_backoff_ms: u64,
+    /// Allow/deny patterns for run_shell commands
+    #[serde(default)]
+    pub shell_policy: ShellPolicy,
+    /// Environment variables run_shell passes on beyond the minimal set
+    #[serde(default)]
+    pub shell_env_allowlist: Vec<String>,
+    /// Shell run_shell uses (the platform default if unset)
+    #[serde(default)]
+    pub shell: Option<String>,
+    /// Run consecutive read-only tool calls concurrently (turn off to debug ordering)
+    #[serde(default = "default_parallel_tools")]
+    pub parallel_tools: bool,
+    /// Prompt token budget before old tool output is elided (defaults from the model)
+    #[serde(default)]
+    pub context_budget: Option<u32>,
+    /// Limit on a whole LLM request in seconds (defaults per provider; none for Ollama)
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+    /// Limit on connecting to the provider in seconds
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// OpenRouter provider order, fallbacks, and filters (ignored by other providers)
+    #[serde(default)]
+    pub openrouter_routing: Option<OpenRouterRouting>,
+    /// Guard tool output against prompt injection (off | wrap | wrap_and_flag)
+    #[serde(default)]
+    pub injection_guard: InjectionGuard,
+    /// Largest file read_file opens, in bytes (10 MB by default)
+    #[serde(default)]
+    pub max_read_bytes: Option<u64>,
+    /// Largest file write_file or append_file may produce, in bytes (10 MB by default)
+    #[serde(default)]
+    pub max_write_bytes: Option<u64>,
+    /// Output caps for read_file lines, run_shell, grep, glob, and any tool result
+    #[serde(default)]
+    pub tool_limits: ToolLimits,
+    /// Tools to turn off: built-in names, `ext-id:tool`, or whole extension IDs
+    #[serde(default)]
+    pub disabled_tools: Vec<String>,
+    /// Reasoning effort for OpenAI o-series and GPT-5 models (low | medium | high)
+    #[serde(default)]
+    pub reasoning_effort: Option<String>,
+    /// Token budget for Claude extended thinking (at least 1024, below max_tokens)
+    #[serde(default)]
+    pub thinking_budget_tokens: Option<u32>,
+    /// When another run is working in the workspace (reject | queue | parallel)
+    #[serde(default)]
+    pub concurrency: RunConcurrency,
+    /// How long a queued run waits for the workspace, in seconds (300 by default)
+    #[serde(default)]
+    pub queue_timeout_secs: Option<u64>,
+    /// Events buffered for the UI before non-essential ones are dropped (256 by default)
+    #[serde(default)]
+    pub event_buffer: Option<usize>,
 }
 
+impl fmt::Debug for InputConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Destructured so a new field can't be left out of the output unnoticed
+        let InputConfig {
+            provider,
+            api_key,
+            model,
+            temperature,
+            max_tokens,
+            max_iterations,
+            base_url,
+            approval_mode,
+            event_detail,
+            stream,
+            max_retries,
+            max_retry_backoff_ms,
+            shell_policy,
+            shell_env_allowlist,
+            shell,
+            parallel_tools,
+            context_budget,
+            request_timeout_secs,
+            connect_timeout_secs,
+            openrouter_routing,
+            injection_guard,
+            max_read_bytes,
+            max_write_bytes,
+            tool_limits,
+            disabled_tools,
+            reasoning_effort,
+            thinking_budget_tokens,
+            concurrency,
+            queue_timeout_secs,
+            event_buffer,
+        } = self;
+        f.debug_struct("InputConfig")
+            .field("provider", provider)
+            .field("api_key", &api_key.as_deref().map(mask_key))
+            .field("model", model)
+            .field("temperature", temperature)
+            .field("max_tokens", max_tokens)
+            .field("max_iterations", max_iterations)
+            .field("base_url", base_url)
+            .field("approval_mode", approval_mode)
+            .field("event_detail", event_detail)
+            .field("stream", stream)
+            .field("max_retries", max_retries)
+            .field("max_retry_backoff_ms", max_retry_backoff_ms)
+            .field("shell_policy", shell_policy)
+            .field("shell_env_allowlist", shell_env_allowlist)
+            .field("shell", shell)
+            .field("parallel_tools", parallel_tools)
+            .field("context_budget", context_budget)
+            .field("request_timeout_secs", request_timeout_secs)
+            .field("connect_timeout_secs", connect_timeout_secs)
+            .field("openrouter_routing", openrouter_routing)
+            .field("injection_guard", injection_guard)
+            .field("max_read_bytes", max_read_bytes)
+            .field("max_write_bytes", max_write_bytes)
+            .field("tool_limits", tool_limits)
+            .field("disabled_tools", disabled_tools)
+            .field("reasoning_effort", reasoning_effort)
+            .field("thinking_budget_tokens", thinking_budget_tokens)
+            .field("concurrency", concurrency)
+            .field("queue_timeout_secs", queue_timeout_secs)
+            .field("event_buffer", event_buffer)
+            .finish()
+    }
+}
+
+/// Allowed range for the LLM request and connect timeouts, in seconds
+const LLM_TIMEOUT_RANGE_SECS: std::ops::RangeInclusive<u64> = 5..=600;
+
+/// Allowed range for the file tool size caps, in bytes (1 KB to 1 GB)
+const FILE_LIMIT_RANGE_BYTES: std::ops::RangeInclusive<u64> = 1024..=1024 * 1024 * 1024;
+
+/// Allowed range for the queue timeout, in seconds
+const QUEUE_TIMEOUT_RANGE_SECS: std::ops::RangeInclusive<u64> = 1..=3600;
+
+/// Allowed range for the event buffer, in events
+const EVENT_BUFFER_RANGE: std::ops::RangeInclusive<usize> = 8..=65_536;
+
+/// Values OpenAI accepts for `reasoning_effort`
+const REASONING_EFFORTS: [&str; 3] = ["low", "medium", "high"];
+
+/// Smallest thinking budget Claude accepts, in tokens
+const MIN_THINKING_BUDGET_TOKENS: u32 = 1024;
+
 fn default_model() -> String {
     "gpt-5-mini".to_string()
 }
@@ -124,6 +482,18 @@ fn default_max_tokens() -> u32 {
 fn default_max_iterations() -> u32 {
     8
 }
+fn default_stream() -> bool {
+    true
+}
+fn default_parallel_tools() -> bool {
+    true
+}
+fn default_max_retries() -> u32 {
+    3
+}
+fn default_max_retry_backoff_ms() -> u64 {
+    30_000
+}
 
 impl InputConfig {
     /// Validate the input configuration
@@ -160,6 +530,109 @@ impl InputConfig {
             return Err("max_iterations cannot exceed 100".to_string());
         }
 
+        // Validate retry settings
+        if self.max_retries > 10 {
+            return Err("max_retries cannot exceed 10".to_string());
+        }
+        if self.max_retry_backoff_ms > 300_000 {
+            return Err("max_retry_backoff_ms cannot exceed 300000 (5 minutes)".to_string());
+        }
+
+        self.shell_policy.validate()?;
+        shell_env::validate_allowlist(&self.shell_env_allowlist)?;
+        if let Some(shell) = &self.shell {
+            validate_shell(shell)?;
+        }
+        validate_disabled_tools(&self.disabled_tools)?;
+        self.tool_limits.validate()?;
+        if let Some(routing) = &self.openrouter_routing {
+            routing.validate()?;
+        }
+
+        if let Some(budget) = self.context_budget {
+            if budget < 1000 {
+                return Err("context_budget must be at least 1000 tokens".to_string());
+            }
+        }
+
+        for (name, value) in [
+            ("request_timeout_secs", self.request_timeout_secs),
+            ("connect_timeout_secs", self.connect_timeout_secs),
+        ] {
+            if let Some(secs) = value.filter(|s| !LLM_TIMEOUT_RANGE_SECS.contains(s)) {
+                return Err(format!(
+                    "{} must be between {} and {} seconds (got {})",
+                    name,
+                    LLM_TIMEOUT_RANGE_SECS.start(),
+                    LLM_TIMEOUT_RANGE_SECS.end(),
+                    secs
+                ));
+            }
+        }
+
+        for (name, value) in [
+            ("max_read_bytes", self.max_read_bytes),
+            ("max_write_bytes", self.max_write_bytes),
+        ] {
+            if let Some(bytes) = value.filter(|b| !FILE_LIMIT_RANGE_BYTES.contains(b)) {
+                return Err(format!(
+                    "{} must be between {} and {} bytes (got {})",
+                    name,
+                    FILE_LIMIT_RANGE_BYTES.start(),
+                    FILE_LIMIT_RANGE_BYTES.end(),
+                    bytes
+                ));
+            }
+        }
+
+        if let Some(effort) = self.reasoning_effort.as_deref() {
+            if !REASONING_EFFORTS.contains(&effort) {
+                return Err(format!(
+                    "reasoning_effort must be low, medium, or high (got {})",
+                    effort
+                ));
+            }
+        }
+        if let Some(budget) = self.thinking_budget_tokens {
+            if budget < MIN_THINKING_BUDGET_TOKENS {
+                return Err(format!(
+                    "thinking_budget_tokens must be at least {}",
+                    MIN_THINKING_BUDGET_TOKENS
+                ));
+            }
+            // Thinking counts toward max_tokens, so the answer needs room too
+            if budget >= self.max_tokens {
+                return Err(format!(
+                    "thinking_budget_tokens must be less than max_tokens ({})",
+                    self.max_tokens
+                ));
+            }
+        }
+
+        if let Some(secs) = self
+            .queue_timeout_secs
+            .filter(|s| !QUEUE_TIMEOUT_RANGE_SECS.contains(s))
+        {
+            return Err(format!(
+                "queue_timeout_secs must be between {} and {} seconds (got {})",
+                QUEUE_TIMEOUT_RANGE_SECS.start(),
+                QUEUE_TIMEOUT_RANGE_SECS.end(),
+                secs
+            ));
+        }
+
+        if let Some(size) = self
+            .event_buffer
+            .filter(|s| !EVENT_BUFFER_RANGE.contains(s))
+        {
+            return Err(format!(
+                "event_buffer must be between {} and {} events (got {})",
+                EVENT_BUFFER_RANGE.start(),
+                EVENT_BUFFER_RANGE.end(),
+                size
+            ));
+        }
+
         // Validate base_url if provided
         if let Some(ref url) = self.base_url {
             if url.is_empty() {
@@ -174,21 +647,21 @@ impl InputConfig {
     }
 
     /// Convert to AgentConfig, using CredentialManager as fallback if no frontend key provided
-    pub fn into_agent_config(self, credentials: &CredentialManager) -> Result<AgentConfig, String> {
+    pub fn into_agent_config(
+        self,
+        credentials: &CredentialManager,
+    ) -> Result<AgentConfig, AgentError> {
         // Validate first
-        self.validate()?;
+        self.validate().map_err(AgentError::ConfigError)?;
         // Use frontend-provided key (primary), fall back to environment variables
         let api_key = if let Some(key) = self.api_key.filter(|k| !k.is_empty()) {
             // Frontend provided a key via Settings UI (normal path)
             key
         } else {
-            // Fall back to environment variable via CredentialManager
-            credentials.get_key(self.provider).ok_or_else(|| {
-                format!(
-                    "No API key configured for provider {:?}. Please set your API key in Settings.",
-                    self.provider
-                )
-            })?
+            // Fall back to the keychain, then environment variables, via CredentialManager
+            credentials
+                .get_key(self.provider)
+                .ok_or_else(|| AgentError::MissingApiKey(format!("{:?}", self.provider)))?
         };
 
         Ok(AgentConfig {
@@ -201,6 +674,34 @@ impl InputConfig {
             shell_timeout: 30,
             base_url: self.base_url,
             approval_mode: self.approval_mode,
+            event_detail: self.event_detail,
+            stream: self.stream,
+            max_retries: self.max_retries,
+            max_retry_backoff_ms: self.max_retry_backoff_ms,
+            shell_policy: self.shell_policy,
+            shell_env_allowlist: self.shell_env_allowlist,
+            shell: self.shell,
+            parallel_tools: self.parallel_tools,
+            context_budget: self.context_budget,
+            request_timeout_secs: self.request_timeout_secs,
+            connect_timeout_secs: self
+                .connect_timeout_secs
+                .unwrap_or(AgentConfig::default().connect_timeout_secs),
+            openrouter_routing: self.openrouter_routing,
+            injection_guard: self.injection_guard,
+            max_read_bytes: self.max_read_bytes.unwrap_or(DEFAULT_MAX_FILE_BYTES),
+            max_write_bytes: self.max_write_bytes.unwrap_or(DEFAULT_MAX_FILE_BYTES),
+            tool_limits: self.tool_limits,
+            disabled_tools: self.disabled_tools,
+            reasoning_effort: self.reasoning_effort,
+            thinking_budget_tokens: self.thinking_budget_tokens,
+            concurrency: self.concurrency,
+            queue_timeout_secs: self
+                .queue_timeout_secs
+                .unwrap_or(AgentConfig::default().queue_timeout_secs),
+            event_buffer: self
+                .event_buffer
+                .unwrap_or(AgentConfig::default().event_buffer),
         })
     }
 }
@@ -210,8 +711,82 @@ impl InputConfig {
 pub struct AgentResult {
     pub success: bool,
     pub response: Option<String>,
+    /// Human-readable error, as logged
     pub error: Option<String>,
+    /// The same error with a machine-readable code, for choosing what to offer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_info: Option<ErrorInfo>,
     pub tool_call_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<RunSummary>,
+    /// Files the run created, modified, or deleted
+    #[serde(default)]
+    pub file_changes: Vec<FileChange>,
+    /// Calls a dry run recorded instead of executing; pass to `apply_agent_plan`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plan: Vec<PlannedToolCall>,
+}
+
+impl AgentResult {
+    /// Result of a run that failed with `error`
+    fn failed(error: ErrorInfo) -> Self {
+        AgentResult {
+            success: false,
+            response: None,
+            error: Some(error.message.clone()),
+            error_info: Some(error),
+            tool_call_count: 0,
+            summary: None,
+            file_changes: Vec::new(),
+            plan: Vec::new(),
+        }
+    }
+
+    /// Attach the account of what a failed run did before it stopped
+    fn with_summary(mut self, summary: RunSummary) -> Self {
+        self.file_changes = summary.file_changes.clone();
+        self.summary = Some(summary);
+        self
+    }
+}
+
+/// Report a run that failed before it started. The error event goes out like a
+/// failed run's, so the panel shows it either way.
+fn failed_before_start(app: &AppHandle, error: AgentError) -> AgentResult {
+    log::warn!("Agent run not started: {}", error);
+    let _ = app.emit(
+        "native-agent-event",
+        AgentEvent::Error {
+            error: error.to_string(),
+            summary: None,
+            run_id: None,
+        },
+    );
+    AgentResult::failed(error.info())
+}
+
+/// Forward a run's events to the frontend as `native-agent-event`s. They go
+/// through a channel of `buffer` events that a separate task drains, so a stalled
+/// webview drops events instead of stalling the run (see [`ChannelSink`]). With
+/// `activity`, each event is also shown to the idle reaper watching that run.
+fn app_event_sink(
+    app: &AppHandle,
+    buffer: usize,
+    activity: Option<(SharedRunActivity, String)>,
+) -> Arc<dyn EventSink> {
+    let (tx, mut rx) = mpsc::channel::<AgentEvent>(buffer);
+    let app = app.clone();
+    tokio::spawn(async move {
+        while let Some(mut event) = rx.recv().await {
+            if let Some((activity, run_id)) = &activity {
+                activity.observe_event(run_id, &mut event);
+            }
+            if let Err(e) = app.emit("native-agent-event", &event) {
+                log::warn!("Failed to emit agent event: {}", e);
+            }
+        }
+    });
+    Arc::new(ChannelSink::new(tx))
 }
 
 /// Status of the native agent
@@ -232,46 +807,223 @@ pub struct NativeAgentStatus {
 pub async fn run_native_agent(
     app: AppHandle,
     credentials: State<'_, SharedCredentialManager>,
+    rate_limits: State<'_, SharedRateLimits>,
     extensions: State<'_, SharedExtensionRegistry>,
     running_tasks: State<'_, RunningTasks>,
     session_store: State<'_, SharedSessionStore>,
+    transcripts: State<'_, SharedTranscriptStore>,
     tool_approvals: State<'_, ToolApprovalStore>,
+    run_activity: State<'_, SharedRunActivity>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
     task: String,
     system_prompt: String,
     workspace: String,
     messages: Vec<InputMessage>,
     config: InputConfig,
+    trim_history: Option<bool>,
+    template_id: Option<String>,
+    template_variables: Option<HashMap<String, String>>,
 ) -> Result<AgentResult, String> {
     log::info!("Running native agent with task: {}", task);
 
-    // Input validation
-    if task.is_empty() {
-        return Err("Task cannot be empty".to_string());
-    }
-    if task.len() > 100000 {
-        return Err("Task too long (max 100000 characters)".to_string());
-    }
-    if system_prompt.len() > 50000 {
-        return Err("System prompt too long (max 50000 characters)".to_string());
-    }
-    if messages.len() > 100 {
-        return Err("Too many messages in history (max 100)".to_string());
-    }
-
     // Validate workspace path
     let workspace_path = PathBuf::from(&workspace);
-    if !workspace_path.exists() {
-        return Err(format!("Workspace path does not exist: {}", workspace));
-    }
     if !workspace_path.is_dir() {
-        return Err(format!("Workspace path is not a directory: {}", workspace));
+        return Ok(failed_before_start(
+            &app,
+            AgentError::WorkspaceMissing(workspace),
+        ));
     }
     // Ensure workspace path is absolute to prevent traversal tricks
     let workspace_path = workspace_path
         .canonicalize()
         .map_err(|e| format!("Failed to resolve workspace path: {}", e))?;
 
-    // Rate limiting: check concurrent run count before allowing new runs
+    // A workspace prompt template replaces the system prompt sent by the UI
+    let system_prompt = match template_id {
+        Some(id) => prompt_templates::render_template(
+            &workspace_path,
+            &id,
+            &template_variables.unwrap_or_default(),
+        )?,
+        None => system_prompt,
+    };
+
+    // Input validation
+    let (task, system_prompt) = prepare_prompt(&task, &system_prompt)?;
+    let (conversation, normalization) =
+        prepare_history(messages, trim_history.unwrap_or(false), config.provider)?;
+
+    // Convert inputs - use CredentialManager for API key
+    let agent_config = match config.into_agent_config(&credentials) {
+        Ok(config) => config,
+        Err(e) => return Ok(failed_before_start(&app, e)),
+    };
+
+    let state = RunState {
+        app: &app,
+        extensions: &extensions,
+        running_tasks: &running_tasks,
+        session_store: &session_store,
+        transcripts: &transcripts,
+        tool_approvals: &tool_approvals,
+        run_activity: &run_activity,
+        workspaces: &workspaces,
+        rate_limits: &rate_limits,
+    };
+    let start = RunStart::Task {
+        task,
+        history: conversation,
+        normalization,
+    };
+    execute_run(state, start, &system_prompt, workspace_path, agent_config).await
+}
+
+/// Resume a session from its saved transcript, e.g. after the app restarted
+/// mid-run. Tool calls the run made but never finished are put to the user for
+/// approval again. `task` adds a new instruction, and is required when the
+/// session had already finished. The run gets a new session ID, and uses the
+/// workspace and config the session was started with; `api_key` works as in
+/// `run_native_agent`.
+#[tauri::command]
+pub async fn resume_agent_session(
+    app: AppHandle,
+    credentials: State<'_, SharedCredentialManager>,
+    rate_limits: State<'_, SharedRateLimits>,
+    extensions: State<'_, SharedExtensionRegistry>,
+    running_tasks: State<'_, RunningTasks>,
+    session_store: State<'_, SharedSessionStore>,
+    transcripts: State<'_, SharedTranscriptStore>,
+    tool_approvals: State<'_, ToolApprovalStore>,
+    run_activity: State<'_, SharedRunActivity>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    session_id: String,
+    system_prompt: String,
+    task: Option<String>,
+    api_key: Option<String>,
+) -> Result<AgentResult, String> {
+    if session_store
+        .get_session(&session_id)
+        .is_some_and(|s| s.is_live())
+    {
+        return Err(format!("Session {} is still running", session_id));
+    }
+    let transcript = transcripts.load(&session_id)?;
+    log::info!(
+        "Resuming session {} ({} messages)",
+        session_id,
+        transcript.messages.len()
+    );
+
+    let workspace_path = canonical_workspace(&transcript.header.workspace)?;
+    let (task, system_prompt) = match task {
+        Some(task) => {
+            let (task, system_prompt) = prepare_prompt(&task, &system_prompt)?;
+            (Some(task), system_prompt)
+        }
+        None => (
+            None,
+            normalize_input("System prompt", &system_prompt, SYSTEM_PROMPT_LIMITS)?,
+        ),
+    };
+
+    let mut agent_config = transcript.header.config;
+    agent_config.api_key = match api_key.filter(|k| !k.is_empty()) {
+        Some(key) => key,
+        None => credentials.get_key(agent_config.provider).ok_or_else(|| {
+            format!(
+                "No API key configured for provider {:?}. Please set your API key in Settings.",
+                agent_config.provider
+            )
+        })?,
+    };
+
+    let state = RunState {
+        app: &app,
+        extensions: &extensions,
+        running_tasks: &running_tasks,
+        session_store: &session_store,
+        transcripts: &transcripts,
+        tool_approvals: &tool_approvals,
+        run_activity: &run_activity,
+        workspaces: &workspaces,
+        rate_limits: &rate_limits,
+    };
+    let start = RunStart::Resume {
+        messages: transcript.messages,
+        task,
+        original_task: transcript.header.task,
+        resumed_from: session_id,
+    };
+    execute_run(state, start, &system_prompt, workspace_path, agent_config).await
+}
+
+/// App state a run needs, borrowed from a command's arguments
+struct RunState<'a> {
+    app: &'a AppHandle,
+    extensions: &'a SharedExtensionRegistry,
+    running_tasks: &'a RunningTasks,
+    session_store: &'a SharedSessionStore,
+    transcripts: &'a SharedTranscriptStore,
+    tool_approvals: &'a ToolApprovalStore,
+    run_activity: &'a SharedRunActivity,
+    workspaces: &'a SharedWorkspaceRegistry,
+    rate_limits: &'a SharedRateLimits,
+}
+
+/// How a run begins: a new task, or a saved session picked up again
+enum RunStart {
+    Task {
+        task: String,
+        history: Vec<Message>,
+        normalization: HistoryNormalization,
+    },
+    Resume {
+        messages: Vec<Message>,
+        task: Option<String>,
+        original_task: String,
+        resumed_from: String,
+    },
+}
+
+impl RunStart {
+    /// The task the session is recorded under
+    fn session_task(&self) -> &str {
+        match self {
+            RunStart::Task { task, .. } => task.as_str(),
+            RunStart::Resume {
+                task,
+                original_task,
+                ..
+            } => task.as_deref().unwrap_or(original_task),
+        }
+    }
+}
+
+/// Register a run, record it in a new session and transcript, and drive it to
+/// the end, forwarding its events to the frontend
+async fn execute_run(
+    state: RunState<'_>,
+    start: RunStart,
+    system_prompt: &str,
+    workspace_path: PathBuf,
+    agent_config: AgentConfig,
+) -> Result<AgentResult, String> {
+    let RunState {
+        app,
+        extensions,
+        running_tasks,
+        session_store,
+        transcripts,
+        tool_approvals,
+        run_activity,
+        workspaces,
+        rate_limits,
+    } = state;
+
+    // Rate limiting: refuse early when the app is already at its run limit. The
+    // slot itself is only claimed once the workspace has room for the run, so a
+    // queued run doesn't hold one of them while it waits.
     {
         let tasks = running_tasks
             .read()
@@ -289,13 +1041,43 @@ pub async fn run_native_agent(
     let cancel_token = CancellationToken::new();
     let run_id = uuid::Uuid::new_v4().to_string();
 
-    // Register the task (double-check limit in case of race condition)
+    // One run per workspace unless the run asks otherwise, so runs can't
+    // overwrite each other's changes; a queued run waits here for its turn
+    let workspace_run = match agent_config.concurrency {
+        RunConcurrency::Queue => {
+            let timeout = Duration::from_secs(agent_config.queue_timeout_secs);
+            let on_queued = |position| {
+                log::info!("Run {} is queued at position {}", run_id, position);
+                let _ = app.emit(
+                    "native-agent-event",
+                    AgentEvent::Queued {
+                        position,
+                        run_id: Some(run_id.clone()),
+                    },
+                );
+            };
+            workspaces
+                .queue_run(
+                    &workspace_path,
+                    &run_id,
+                    cancel_token.clone(),
+                    timeout,
+                    on_queued,
+                )
+                .await?
+        }
+        concurrency => {
+            workspaces.begin_run(&workspace_path, &run_id, cancel_token.clone(), concurrency)?
+        }
+    };
+
+    // Register the task now that it can start (re-checking the limit, which other
+    // runs may have reached while this one waited)
     {
         let mut tasks = running_tasks
             .write()
             .map_err(|e| format!("Failed to write running tasks: {}", e))?;
 
-        // Double-check limit inside write lock to prevent race conditions
         if tasks.len() >= MAX_CONCURRENT_RUNS {
             return Err(format!(
                 "Too many concurrent agent runs ({}/{}). Please wait for an existing run to complete or cancel one.",
@@ -306,10 +1088,7 @@ pub async fn run_native_agent(
 
         tasks.insert(run_id.clone(), cancel_token.clone());
     }
-    let _task_guard = RunningTaskGuard::new(running_tasks.inner().clone(), run_id.clone());
-
-    // Convert inputs - use CredentialManager for API key
-    let agent_config: AgentConfig = config.into_agent_config(&credentials)?;
+    let _task_guard = RunningTaskGuard::new(running_tasks.clone(), run_id.clone());
 
     // Create session for tracking this agent run
     let session_id = session_store.create_session(
@@ -317,57 +1096,98 @@ pub async fn run_native_agent(
         agent_config.provider,
         agent_config.model.clone(),
         agent_config.approval_mode,
-        task.clone(),
+        start.session_task().to_string(),
     );
+    session_store.link_run(&session_id, &run_id);
+    if let RunStart::Task { normalization, .. } = &start {
+        if !normalization.is_unchanged() {
+            session_store.update_session(&session_id, |s| {
+                s.history_normalization = Some(*normalization)
+            });
+        }
+    }
     log::info!("Created session {} for run {}", session_id, run_id);
-    let conversation: Vec<Message> = messages.into_iter().map(|m| m.into()).collect();
+
+    // Save the conversation as it goes; a run that can't is still worth running
+    let mut session_audit = SessionAudit::new(session_store.clone(), session_id.clone());
+    let header = TranscriptHeader {
+        session_id: session_id.clone(),
+        created_at: chrono::Utc::now(),
+        workspace: workspace_path.clone(),
+        task: start.session_task().to_string(),
+        config: agent_config.clone(),
+        resumed_from: match &start {
+            RunStart::Task { .. } => None,
+            RunStart::Resume { resumed_from, .. } => Some(resumed_from.clone()),
+        },
+    };
+    match transcripts.create(header) {
+        Ok(writer) => session_audit = session_audit.with_transcript(writer),
+        Err(e) => log::warn!("Not saving a transcript for session {}: {}", session_id, e),
+    }
 
     // Get extension registry for the agent (read access is sufficient)
     let ext_registry = {
         let registry = extensions
             .read()
             .map_err(|e| format!("Failed to read extension registry: {}", e))?;
-        // Clone the registry data into an Arc for the agent
-        Arc::new(registry.clone())
+        Arc::clone(&registry)
     };
 
-    // Create event channel
-    let (tx, mut rx) = mpsc::channel::<AgentEvent>(32);
+    // Track activity so the idle reaper can cancel an abandoned run
+    let activity = run_activity.clone();
+    activity.register(&run_id, &session_id, cancel_token.clone());
 
-    // Spawn task to forward events to frontend
-    let app_handle = app.clone();
-    tokio::spawn(async move {
-        while let Some(event) = rx.recv().await {
-            if let Err(e) = app_handle.emit("native-agent-event", &event) {
-                log::warn!("Failed to emit agent event: {}", e);
-            }
+    // Run the agent with extensions and cancellation support, forwarding its
+    // events to the frontend
+    let events = app_event_sink(
+        app,
+        agent_config.event_buffer,
+        Some((activity.clone(), run_id.clone())),
+    );
+    let context = RunContext {
+        events: Some(events),
+        approvals: Some(Arc::new(tool_approvals.clone())),
+        extensions: Some(ext_registry),
+        cancel_token: Some(cancel_token),
+        session_audit: Some(session_audit),
+        llm: None,
+        rate_limits: Some(rate_limits.clone()),
+        activity: Some(activity.clone()),
+        entity_cache: Some(workspace_run.entity_cache()),
+    };
+    let result = match start {
+        RunStart::Task { task, history, .. } => {
+            agent::run_agent(
+                &task,
+                system_prompt,
+                history,
+                &workspace_path,
+                agent_config,
+                context,
+            )
+            .await
         }
-    });
-
-    // Run the agent with extensions and cancellation support
-    let result = agent::run_agent(
-        &task,
-        &system_prompt,
-        conversation,
-        &workspace_path,
-        agent_config,
-        Some(tx),
-        Some(ext_registry),
-        Some(tool_approvals.inner().clone()),
-        Some(cancel_token),
-    )
-    .await;
-
-    // Clone session store and session_id for result handling
-    let session_store_inner = session_store.inner().clone();
+        RunStart::Resume { messages, task, .. } => {
+            agent::resume_agent(
+                system_prompt,
+                messages,
+                task.as_deref(),
+                &workspace_path,
+                agent_config,
+                context,
+            )
+            .await
+        }
+    };
+    let idle_cancellation = activity.unregister(&run_id);
 
     match result {
         Ok(result) => {
-            // Update session as completed
-            session_store_inner.update_session(&session_id, |s| {
-                if let Some(ref usage) = result.usage {
-                    s.record_tokens(usage.total_tokens);
-                }
+            // Token usage was recorded per LLM call as the run went
+            session_store.update_session(&session_id, |s| {
+                s.record_file_changes(result.file_changes.clone());
+                s.record_scratchpad(result.scratchpad.clone());
                 s.complete();
             });
 
@@ -375,14 +1195,22 @@ pub async fn run_native_agent(
                 success: true,
                 response: Some(result.response),
                 error: None,
+                error_info: None,
                 tool_call_count: result.tool_results.len(),
+                summary: Some(result.summary),
+                file_changes: result.file_changes,
+                plan: result.plan,
             })
         }
-        Err(e) => {
-            let error_msg = e.to_string();
+        Err(failure) => {
+            // An idle-reaped run reports why it was cancelled
+            let error_msg = match idle_cancellation {
+                Some(idle) => idle.reason(),
+                None => failure.to_string(),
+            };
 
             // Update session as failed (or cancelled)
-            session_store_inner.update_session(&session_id, |s| {
+            session_store.update_session(&session_id, |s| {
                 if error_msg.contains("cancelled") || error_msg.contains("Cancelled") {
                     s.cancel();
                 } else {
@@ -395,64 +1223,248 @@ pub async fn run_native_agent(
                 "native-agent-event",
                 AgentEvent::Error {
                     error: error_msg.clone(),
+                    summary: Some((*failure.summary).clone()),
                     run_id: Some(run_id),
                 },
             );
-            Ok(AgentResult {
-                success: false,
-                response: None,
-                error: Some(error_msg),
-                tool_call_count: 0,
-            })
+            let mut error_info = failure.error.info();
+            error_info.message = error_msg;
+            Ok(AgentResult::failed(error_info).with_summary(*failure.summary))
         }
     }
 }
 
-/// Respond to a pending tool approval request.
+/// Apply a plan recorded by a dry run. Steps run in order under `approval_mode`
+/// (`approve_writes` unless given), so each change can still be approved or
+/// denied, and stream the usual tool events.
 #[tauri::command]
-pub async fn respond_tool_approval(
+pub async fn apply_agent_plan(
+    app: AppHandle,
+    extensions: State<'_, SharedExtensionRegistry>,
+    running_tasks: State<'_, RunningTasks>,
     tool_approvals: State<'_, ToolApprovalStore>,
-    approval_id: String,
-    approved: bool,
-) -> Result<(), String> {
-    let tx = {
-        let mut pending = tool_approvals.lock().await;
-        pending.remove(&approval_id)
-    };
-
-    match tx {
-        Some(sender) => sender
-            .send(approved)
-            .map_err(|_| "Approval request already resolved".to_string()),
-        None => Err("Unknown or expired approval_id".to_string()),
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    rate_limits: State<'_, SharedRateLimits>,
+    workspace: String,
+    plan: Vec<PlannedToolCall>,
+    approval_mode: Option<ApprovalMode>,
+    shell_policy: Option<ShellPolicy>,
+    shell_env_allowlist: Option<Vec<String>>,
+    shell: Option<String>,
+) -> Result<AgentResult, String> {
+    let workspace_path = canonical_workspace(Path::new(&workspace))?;
+    let approval_mode = approval_mode.unwrap_or(ApprovalMode::ApproveWrites);
+    if approval_mode == ApprovalMode::DryRun {
+        return Err("A plan can't be applied in dry-run mode".to_string());
     }
-}
-
-/// Cancel a running agent task
-#[tauri::command]
-pub fn cancel_agent_task(
-    running_tasks: State<'_, RunningTasks>,
-    task_id: String,
-) -> Result<bool, String> {
-    let tasks = running_tasks
-        .read()
-        .map_err(|e| format!("Failed to read running tasks: {}", e))?;
-
-    if let Some(token) = tasks.get(&task_id) {
-        token.cancel();
-        log::info!("Cancelled agent task: {}", task_id);
-        Ok(true)
-    } else {
-        Ok(false)
+    let shell_policy = shell_policy.unwrap_or_default();
+    shell_policy.validate()?;
+    let shell_env_allowlist = shell_env_allowlist.unwrap_or_default();
+    shell_env::validate_allowlist(&shell_env_allowlist)?;
+    if let Some(shell) = &shell {
+        validate_shell(shell)?;
     }
-}
+    log::info!("Applying a {}-step plan in {}", plan.len(), workspace);
 
-/// List running agent tasks
-#[tauri::command]
-pub fn list_running_tasks(running_tasks: State<'_, RunningTasks>) -> Result<Vec<String>, String> {
-    let tasks = running_tasks
-        .read()
-        .map_err(|e| format!("Failed to read running tasks: {}", e))?;
+    let cancel_token = CancellationToken::new();
+    let run_id = uuid::Uuid::new_v4().to_string();
+    {
+        let mut tasks = running_tasks
+            .write()
+            .map_err(|e| format!("Failed to write running tasks: {}", e))?;
+        if tasks.len() >= MAX_CONCURRENT_RUNS {
+            return Err(format!(
+                "Too many concurrent agent runs ({}/{}). Please wait for an existing run to complete or cancel one.",
+                tasks.len(),
+                MAX_CONCURRENT_RUNS
+            ));
+        }
+        tasks.insert(run_id.clone(), cancel_token.clone());
+    }
+    let _task_guard = RunningTaskGuard::new(running_tasks.inner().clone(), run_id.clone());
+    let workspace_run = workspaces.begin_run(
+        &workspace_path,
+        &run_id,
+        cancel_token.clone(),
+        RunConcurrency::Reject,
+    )?;
+
+    let ext_registry = {
+        let registry = extensions
+            .read()
+            .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+        Arc::clone(&registry)
+    };
+
+    let config = AgentConfig {
+        approval_mode,
+        shell_policy,
+        shell_env_allowlist,
+        shell,
+        ..AgentConfig::default()
+    };
+    let context = RunContext {
+        events: Some(app_event_sink(&app, config.event_buffer, None)),
+        approvals: Some(Arc::new(tool_approvals.inner().clone())),
+        extensions: Some(ext_registry),
+        cancel_token: Some(cancel_token),
+        rate_limits: Some(rate_limits.inner().clone()),
+        entity_cache: Some(workspace_run.entity_cache()),
+        ..RunContext::default()
+    };
+    let result = agent::apply_plan(plan, &workspace_path, config, context).await;
+
+    Ok(match result {
+        Ok(result) => AgentResult {
+            success: true,
+            response: Some(result.response),
+            error: None,
+            error_info: None,
+            tool_call_count: result.tool_results.len(),
+            summary: Some(result.summary),
+            file_changes: result.file_changes,
+            plan: Vec::new(),
+        },
+        Err(failure) => AgentResult::failed(failure.error.info()).with_summary(*failure.summary),
+    })
+}
+
+/// Get the app-wide agent settings.
+#[tauri::command]
+pub fn get_global_config(
+    global_config: State<'_, SharedGlobalConfig>,
+) -> Result<GlobalConfig, String> {
+    global_config
+        .read()
+        .map(|c| c.clone())
+        .map_err(|e| format!("Failed to read global config: {}", e))
+}
+
+/// Replace the app-wide agent settings and save them. Changes apply from the next
+/// reaper sweep.
+#[tauri::command]
+pub fn update_global_config(
+    app: AppHandle,
+    global_config: State<'_, SharedGlobalConfig>,
+    config: GlobalConfig,
+) -> Result<GlobalConfig, String> {
+    config.validate()?;
+    let path = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| format!("Failed to resolve app config dir: {}", e))?
+        .join(GLOBAL_CONFIG_FILE);
+    let mut current = global_config
+        .write()
+        .map_err(|e| format!("Failed to write global config: {}", e))?;
+    config.save(&path)?;
+    *current = config.clone();
+    Ok(config)
+}
+
+/// Respond to a pending tool approval request.
+///
+/// `scope` is "once" (the default), "tool" to give the same answer to later calls
+/// of this tool in the run, or "run" to approve every later call in the run that
+/// is no riskier than this one.
+#[tauri::command]
+pub async fn respond_tool_approval(
+    tool_approvals: State<'_, ToolApprovalStore>,
+    approval_id: String,
+    approved: bool,
+    scope: Option<ApprovalScope>,
+) -> Result<(), String> {
+    let scope = scope.unwrap_or(ApprovalScope::Call);
+    if scope == ApprovalScope::Session {
+        return Err("Session-wide approvals are not supported; use \"run\"".to_string());
+    }
+
+    let tx = {
+        let mut pending = tool_approvals.lock().await;
+        pending.remove(&approval_id)
+    };
+
+    match tx {
+        Some(sender) => sender
+            .send(ApprovalAnswer { approved, scope })
+            .map_err(|_| "Approval request already resolved".to_string()),
+        None => Err("Unknown or expired approval_id".to_string()),
+    }
+}
+
+/// Cancel a running agent task, identified by its run ID (`task_id`) or by the
+/// session recording it
+#[tauri::command]
+pub fn cancel_agent_task(
+    running_tasks: State<'_, RunningTasks>,
+    session_store: State<'_, SharedSessionStore>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    task_id: Option<String>,
+    session_id: Option<String>,
+) -> Result<bool, String> {
+    let task_id = match (task_id, session_id) {
+        (Some(task_id), _) => task_id,
+        (None, Some(session_id)) => match session_store
+            .get_session(&session_id)
+            .and_then(|s| s.run_id)
+        {
+            Some(run_id) => run_id,
+            None => return Ok(false),
+        },
+        (None, None) => return Err("Either task_id or session_id is required".to_string()),
+    };
+
+    let tasks = running_tasks
+        .read()
+        .map_err(|e| format!("Failed to read running tasks: {}", e))?;
+
+    if let Some(token) = tasks.get(&task_id) {
+        token.cancel();
+        log::info!("Cancelled agent task: {}", task_id);
+        Ok(true)
+    } else if workspaces.cancel_queued(&task_id) {
+        // Still waiting for its workspace, so not registered as running yet
+        log::info!("Cancelled queued agent task: {}", task_id);
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Cancel all running agent tasks and close their sessions before the app exits.
+///
+/// Callers that want the shutdown report can invoke this before exiting; the same
+/// sequence always runs from the Tauri exit handler.
+#[tauri::command]
+pub async fn prepare_shutdown(
+    running_tasks: State<'_, RunningTasks>,
+    session_store: State<'_, SharedSessionStore>,
+) -> Result<ShutdownReport, String> {
+    Ok(shutdown::shutdown(
+        running_tasks.inner(),
+        session_store.inner(),
+        shutdown::SHUTDOWN_GRACE_PERIOD,
+    )
+    .await)
+}
+
+/// List running agent tasks, only those in `workspace` when given
+#[tauri::command]
+pub fn list_running_tasks(
+    running_tasks: State<'_, RunningTasks>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: Option<String>,
+) -> Result<Vec<String>, String> {
+    if let Some(workspace) = workspace {
+        return Ok(workspaces
+            .get(Path::new(&workspace))
+            .map(|state| state.run_ids())
+            .unwrap_or_default());
+    }
+
+    let tasks = running_tasks
+        .read()
+        .map_err(|e| format!("Failed to read running tasks: {}", e))?;
 
     Ok(tasks.keys().cloned().collect())
 }
@@ -460,28 +1472,140 @@ pub fn list_running_tasks(running_tasks: State<'_, RunningTasks>) -> Result<Vec<
 /// Agent run capacity status
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunCapacityStatus {
+    /// Workspace the counts are scoped to, if any
+    pub workspace: Option<String>,
     pub current_runs: usize,
+    /// Limit on `parallel` runs sharing the workspace
     pub max_runs: usize,
+    /// Runs waiting for the workspace
+    pub queued_runs: usize,
+    /// Runs across all workspaces
+    pub total_runs: usize,
+    pub max_total_runs: usize,
+    /// Whether a run would start now without waiting or being refused; scoped to
+    /// a workspace, that means nothing else is running or queued there
     pub can_start_new: bool,
 }
 
-/// Get the current agent run capacity status
+/// Get the current agent run capacity status, scoped to `workspace` when given
 #[tauri::command]
 pub fn get_agent_run_capacity(
     running_tasks: State<'_, RunningTasks>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: Option<String>,
 ) -> Result<RunCapacityStatus, String> {
-    let tasks = running_tasks
+    let total = running_tasks
         .read()
-        .map_err(|e| format!("Failed to read running tasks: {}", e))?;
+        .map_err(|e| format!("Failed to read running tasks: {}", e))?
+        .len();
+    let global_ok = total < MAX_CONCURRENT_RUNS;
+
+    let Some(workspace) = workspace else {
+        return Ok(RunCapacityStatus {
+            workspace: None,
+            current_runs: total,
+            max_runs: MAX_CONCURRENT_RUNS,
+            queued_runs: 0,
+            total_runs: total,
+            max_total_runs: MAX_CONCURRENT_RUNS,
+            can_start_new: global_ok,
+        });
+    };
 
-    let current = tasks.len();
+    let (current, max, queued) = match workspaces.get(Path::new(&workspace)) {
+        Some(state) => (state.run_count(), state.max_runs(), state.queued_count()),
+        None => (0, MAX_RUNS_PER_WORKSPACE, 0),
+    };
     Ok(RunCapacityStatus {
+        workspace: Some(workspace),
         current_runs: current,
-        max_runs: MAX_CONCURRENT_RUNS,
-        can_start_new: current < MAX_CONCURRENT_RUNS,
+        max_runs: max,
+        queued_runs: queued,
+        total_runs: total,
+        max_total_runs: MAX_CONCURRENT_RUNS,
+        can_start_new: global_ok && current == 0 && queued == 0,
     })
 }
 
+/// Open a workspace so runs, hooks, and watchers can use it. Opening one that is
+/// already open is a no-op. Returns the canonical workspace root.
+#[tauri::command]
+pub fn open_workspace(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+) -> Result<String, String> {
+    let state = workspaces.open(Path::new(&workspace))?;
+    log::info!("Opened workspace {}", state.root().display());
+    Ok(state.root().to_string_lossy().to_string())
+}
+
+/// Close a workspace window: cancel its runs and release its state.
+/// Returns the number of runs that were cancelled.
+#[tauri::command]
+pub fn close_workspace(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+) -> Result<usize, String> {
+    let cancelled = workspaces.close(Path::new(&workspace))?;
+    log::info!(
+        "Closed workspace {} ({} run(s) cancelled)",
+        workspace,
+        cancelled
+    );
+    Ok(cancelled)
+}
+
+/// Watch a workspace and fire `on_section_save` / `on_entity_change` hooks when
+/// its files change. Returns false if it was already being watched.
+#[tauri::command]
+pub fn start_workspace_watcher(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    extensions: State<'_, SharedExtensionRegistry>,
+    workspace: String,
+) -> Result<bool, String> {
+    let state = workspaces.require(Path::new(&workspace))?;
+    let extensions = extensions.inner().clone();
+    let root = state.root().to_path_buf();
+    let started = state.start_watcher(move |hook, args| {
+        // Take the current registry so the lock isn't held while hooks run
+        let registry = match extensions.read() {
+            Ok(registry) => Arc::clone(&registry),
+            Err(e) => {
+                log::error!("Failed to read extension registry: {}", e);
+                return;
+            }
+        };
+        let results =
+            tauri::async_runtime::block_on(registry.run_hook_all(hook, args, root.clone(), 30));
+        for (extension_id, result) in results {
+            if !result.success {
+                log::warn!(
+                    "{} hook failed in extension {}: {}",
+                    hook.function_name(),
+                    extension_id,
+                    result.error.unwrap_or_default()
+                );
+            }
+        }
+    })?;
+    if started {
+        log::info!("Watching workspace {} for hook triggers", workspace);
+    }
+    Ok(started)
+}
+
+/// Stop watching a workspace. Returns false if it wasn't being watched.
+#[tauri::command]
+pub fn stop_workspace_watcher(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+) -> Result<bool, String> {
+    Ok(workspaces
+        .get(Path::new(&workspace))
+        .map(|state| state.stop_watcher())
+        .unwrap_or(false))
+}
+
 /// Get the status of the native agent
 #[tauri::command]
 pub fn get_native_agent_status(
@@ -505,6 +1629,101 @@ pub fn get_available_providers(
     credentials.get_provider_status()
 }
 
+/// Save a provider's API key in the OS keychain
+#[tauri::command]
+pub fn set_provider_api_key(
+    provider: LlmProvider,
+    key: String,
+    credentials: State<'_, SharedCredentialManager>,
+) -> Result<(), String> {
+    credentials.set_key(provider, &key)?;
+    log::info!("Saved {:?} API key to the keychain", provider);
+    Ok(())
+}
+
+/// Remove a provider's API key from the OS keychain
+#[tauri::command]
+pub fn clear_provider_api_key(
+    provider: LlmProvider,
+    credentials: State<'_, SharedCredentialManager>,
+) -> Result<(), String> {
+    credentials.clear_key(provider)?;
+    log::info!("Removed {:?} API key from the keychain", provider);
+    Ok(())
+}
+
+/// What each provider's latest reply said about its rate limits. Providers
+/// whose limits have since reset (or that haven't been called) are left out.
+#[tauri::command]
+pub fn get_provider_rate_limits(
+    rate_limits: State<'_, SharedRateLimits>,
+) -> HashMap<LlmProvider, RateLimitSnapshot> {
+    rate_limits.all(chrono::Utc::now())
+}
+
+// ============================================================================
+// Ollama Model Commands
+// ============================================================================
+
+/// Pull an Ollama model, emitting `ollama-pull-progress` events as it downloads.
+///
+/// Only one pull per model runs at a time; use `cancel_ollama_pull` to stop it.
+#[tauri::command]
+pub async fn pull_ollama_model(
+    app: AppHandle,
+    pulls: State<'_, OllamaPulls>,
+    model: String,
+    base_url: Option<String>,
+) -> Result<(), String> {
+    let model = model.trim().to_string();
+    if model.is_empty() {
+        return Err("Model name is required".to_string());
+    }
+
+    let guard = pulls.begin(&model)?;
+    let base_url = base_url.unwrap_or_else(|| LlmProvider::Ollama.default_base_url().to_string());
+
+    log::info!("Pulling Ollama model {} from {}", model, base_url);
+    let result = ollama::pull_model(
+        &reqwest::Client::new(),
+        &base_url,
+        &model,
+        guard.token(),
+        |progress| {
+            if let Err(e) = app.emit(ollama::PULL_PROGRESS_EVENT, &progress) {
+                log::warn!("Failed to emit Ollama pull progress: {}", e);
+            }
+        },
+    )
+    .await;
+
+    if let Err(ref e) = result {
+        log::warn!("Ollama pull for {} failed: {}", model, e);
+    }
+    result
+}
+
+/// List the models installed on the Ollama server and whether `model` is one of
+/// them. A server that isn't running comes back as `reachable: false` with a hint,
+/// not as an error.
+#[tauri::command]
+pub async fn list_ollama_models(
+    base_url: Option<String>,
+    model: Option<String>,
+) -> Result<OllamaStatus, String> {
+    let base_url = base_url
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| LlmProvider::Ollama.default_base_url().to_string());
+    let model = model.as_deref().map(str::trim).filter(|m| !m.is_empty());
+    Ok(ollama::list_models(&reqwest::Client::new(), &base_url, model).await)
+}
+
+/// Cancel an in-flight Ollama model pull. Returns false if none was running.
+#[tauri::command]
+pub fn cancel_ollama_pull(pulls: State<'_, OllamaPulls>, model: String) -> bool {
+    pulls.cancel(&model)
+}
+
 // ============================================================================
 // Extension Management Commands
 // ============================================================================
@@ -517,6 +1736,26 @@ pub struct ExtensionInfo {
     pub version: String,
     pub description: Option<String>,
     pub tool_count: usize,
+    /// Manifest signature status, checked when the extension was loaded
+    pub signature: SignatureVerification,
+}
+
+impl ExtensionInfo {
+    fn from_loaded(extension: &LoadedExtension) -> Self {
+        let manifest = &extension.manifest;
+        ExtensionInfo {
+            id: manifest.id.clone(),
+            name: manifest.name.clone(),
+            version: manifest.version.clone(),
+            description: manifest.description.clone(),
+            tool_count: manifest
+                .tools
+                .iter()
+                .filter(|t| t.lua_script.is_some())
+                .count(),
+            signature: extension.signature.clone(),
+        }
+    }
 }
 
 /// Load a Lua extension from a directory
@@ -534,10 +1773,9 @@ pub fn load_lua_extension(
         .write()
         .map_err(|e| format!("Failed to write extension registry: {}", e))?;
 
-    registry.load_extension(&path)?;
+    Arc::make_mut(&mut registry).load_extension(&path)?;
 
-    // Get the loaded extension info
-    // We need to read the manifest to get the info
+    // Look the extension up by the ID in its manifest
     let manifest_path = path.join("manifest.json");
     let manifest_content = std::fs::read_to_string(&manifest_path)
         .map_err(|e| format!("Failed to read manifest: {}", e))?;
@@ -545,19 +1783,10 @@ pub fn load_lua_extension(
         serde_json::from_str(&manifest_content)
             .map_err(|e| format!("Failed to parse manifest: {}", e))?;
 
-    let lua_tool_count = manifest
-        .tools
-        .iter()
-        .filter(|t| t.lua_script.is_some())
-        .count();
-
-    Ok(ExtensionInfo {
-        id: manifest.id,
-        name: manifest.name,
-        version: manifest.version,
-        description: manifest.description,
-        tool_count: lua_tool_count,
-    })
+    registry
+        .get_extension(&manifest.id)
+        .map(ExtensionInfo::from_loaded)
+        .ok_or_else(|| format!("Extension '{}' not found after loading", manifest.id))
 }
 
 /// Unload a Lua extension
@@ -570,26 +1799,62 @@ pub fn unload_lua_extension(
         .write()
         .map_err(|e| format!("Failed to write extension registry: {}", e))?;
 
-    registry.unload_extension(&extension_id)
+    Arc::make_mut(&mut registry).unload_extension(&extension_id)
 }
 
-/// List all loaded Lua extensions
+/// List all loaded Lua extensions, sorted by ID.
+///
+/// A summary kept for compatibility; `get_extension_details` has the full picture.
 #[tauri::command]
 pub fn list_lua_extensions(
     extensions: State<'_, SharedExtensionRegistry>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<ExtensionInfo>, String> {
     let registry = extensions
         .read()
         .map_err(|e| format!("Failed to read extension registry: {}", e))?;
 
-    Ok(registry
+    let mut infos: Vec<ExtensionInfo> = registry
         .list_extensions()
         .into_iter()
-        .map(|s| s.to_string())
-        .collect())
+        .filter_map(|id| registry.get_extension(id))
+        .map(ExtensionInfo::from_loaded)
+        .collect();
+    infos.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(infos)
+}
+
+/// Everything about each loaded extension in one call: metadata, directory,
+/// signature status, enabled hooks, and tools with their schemas and risk
+#[tauri::command]
+pub fn get_extension_details(
+    extensions: State<'_, SharedExtensionRegistry>,
+) -> Result<Vec<ExtensionDetails>, String> {
+    let registry = extensions
+        .read()
+        .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+    Ok(registry.get_extension_details())
 }
 
-/// Get tools from all loaded extensions
+/// Set which extensions may load, judged by their manifest signature
+/// (`allow_unsigned`, `warn_unsigned`, or `require_trusted`). Applies to
+/// extensions loaded afterwards; returns the previous policy.
+#[tauri::command]
+pub fn set_extension_signature_policy(
+    extensions: State<'_, SharedExtensionRegistry>,
+    policy: SignaturePolicy,
+) -> Result<SignaturePolicy, String> {
+    let mut registry = extensions
+        .write()
+        .map_err(|e| format!("Failed to write extension registry: {}", e))?;
+
+    let previous = registry.signature_policy();
+    Arc::make_mut(&mut registry).set_signature_policy(policy);
+    Ok(previous)
+}
+
+/// Get tools from all loaded extensions (names and descriptions only).
+///
+/// Kept for compatibility; `get_extension_details` also has schemas and risk.
 #[tauri::command]
 pub fn get_extension_tools(
     extensions: State<'_, SharedExtensionRegistry>,
@@ -612,14 +1877,39 @@ pub fn get_extension_tools(
     Ok(tool_infos)
 }
 
+/// Execution stats (totals, p50/p95 durations, failure rates) for one extension, or
+/// for every extension that has run when `extension_id` is omitted
+#[tauri::command]
+pub fn get_extension_stats(
+    extensions: State<'_, SharedExtensionRegistry>,
+    extension_id: Option<String>,
+) -> Result<Vec<ExtensionStatsSummary>, String> {
+    let registry = extensions
+        .read()
+        .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+
+    let Some(extension_id) = extension_id else {
+        return Ok(registry.stats().summaries());
+    };
+    if !registry.list_extensions().contains(&extension_id.as_str()) {
+        return Err(format!("Extension '{}' not found", extension_id));
+    }
+    Ok(registry
+        .stats()
+        .summary(&extension_id)
+        .into_iter()
+        .collect())
+}
+
 // ============================================================================
 // Lifecycle Hook Commands
 // ============================================================================
 
-/// Execute a lifecycle hook for a specific extension
+/// Execute a lifecycle hook for a specific extension, off the command thread
 #[tauri::command]
-pub fn execute_extension_hook(
+pub async fn execute_extension_hook(
     extensions: State<'_, SharedExtensionRegistry>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
     extension_id: String,
     hook_name: String,
     args: serde_json::Value,
@@ -635,22 +1925,32 @@ pub fn execute_extension_hook(
         _ => return Err(format!("Unknown hook: {}", hook_name)),
     };
 
-    let workspace_path = std::path::PathBuf::from(&workspace);
-    if !workspace_path.exists() {
-        return Err(format!("Workspace path does not exist: {}", workspace));
-    }
+    // Hooks only run against an open workspace, so they can't reach another project
+    let workspace_path = workspaces
+        .require(Path::new(&workspace))?
+        .root()
+        .to_path_buf();
 
-    let registry = extensions
-        .read()
-        .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+    // Take the current registry so the lock isn't held while the hook runs
+    let registry = {
+        let registry = extensions
+            .read()
+            .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+        Arc::clone(&registry)
+    };
 
-    registry.execute_hook(&extension_id, hook, args, &workspace_path, 30)
+    registry
+        .run_hook(extension_id, hook, args, workspace_path, 30)
+        .await
 }
 
-/// Execute a lifecycle hook for all extensions that have it enabled
+/// Execute a lifecycle hook for all extensions that have it enabled, off the
+/// command thread. A hook that hangs fails at its extension's timeout and the
+/// other extensions' results are still returned.
 #[tauri::command]
-pub fn execute_hook_all(
+pub async fn execute_hook_all(
     extensions: State<'_, SharedExtensionRegistry>,
+    workspaces: State<'_, SharedWorkspaceRegistry>,
     hook_name: String,
     args: serde_json::Value,
     workspace: String,
@@ -665,19 +1965,25 @@ pub fn execute_hook_all(
         _ => return Err(format!("Unknown hook: {}", hook_name)),
     };
 
-    let workspace_path = std::path::PathBuf::from(&workspace);
-    if !workspace_path.exists() {
-        return Err(format!("Workspace path does not exist: {}", workspace));
-    }
+    // Hooks only run against an open workspace, so they can't reach another project
+    let workspace_path = workspaces
+        .require(Path::new(&workspace))?
+        .root()
+        .to_path_buf();
 
-    let registry = extensions
-        .read()
-        .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+    let registry = {
+        let registry = extensions
+            .read()
+            .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+        Arc::clone(&registry)
+    };
 
-    Ok(registry.execute_hook_all(hook, args, &workspace_path, 30))
+    Ok(registry.run_hook_all(hook, args, workspace_path, 30).await)
 }
 
-/// Get list of enabled hooks for an extension
+/// Get list of enabled hooks for an extension.
+///
+/// Kept for compatibility; `get_extension_details` lists hooks for every extension.
 #[tauri::command]
 pub fn get_extension_hooks(
     extensions: State<'_, SharedExtensionRegistry>,
@@ -698,34 +2004,280 @@ pub fn get_extension_hooks(
 // Health Check Commands
 // ============================================================================
 
-/// Run a health check on the agent backend
+/// Run a health check on the agent backend.
+///
+/// The cached report (from the startup probe or an earlier call) is returned while it
+/// is fresh; `force` runs the checks again regardless. With `probe_network`, each
+/// provider in `probe_targets` (by default those with a key, plus Ollama) is also
+/// pinged and the results are added to the report, along with the models the Ollama
+/// server has installed and whether `ollama_model` is among them. `disabled_tools`
+/// (from the agent settings) is listed in the report.
 #[tauri::command]
-pub fn run_agent_health_check(
+pub async fn run_agent_health_check(
     credentials: State<'_, SharedCredentialManager>,
     extensions: State<'_, SharedExtensionRegistry>,
-) -> Result<crate::agent::doctor::HealthReport, String> {
-    let registry = extensions
+    health_cache: State<'_, SharedHealthCache>,
+    global_config: State<'_, SharedGlobalConfig>,
+    force: Option<bool>,
+    probe_network: Option<bool>,
+    probe_targets: Option<Vec<ProbeTarget>>,
+    disabled_tools: Option<Vec<String>>,
+    ollama_model: Option<String>,
+) -> Result<HealthReport, String> {
+    let disabled_tools = disabled_tools.unwrap_or_default();
+    validate_disabled_tools(&disabled_tools)?;
+    let max_age = global_config
         .read()
-        .map_err(|e| format!("Failed to read extension registry: {}", e))?;
+        .map_err(|e| format!("Failed to read global config: {}", e))?
+        .health_probe
+        .cache_ttl();
+
+    let report = health_cache
+        .get_or_refresh(HealthScope::Full, max_age, force.unwrap_or(false), || {
+            doctor::run_health_check(
+                &credentials,
+                &extensions,
+                HealthScope::Full,
+                Some(LlmProvider::Ollama.default_base_url()),
+            )
+        })
+        .await
+        .with_disabled_tools(disabled_tools);
+    if !probe_network.unwrap_or(false) {
+        return Ok(report);
+    }
+
+    // Probes always run fresh and aren't cached
+    let targets = probe_targets.unwrap_or_else(|| doctor::default_probe_targets(&credentials));
+    let (results, ollama) = tokio::join!(
+        doctor::probe_providers(targets.clone(), &credentials),
+        doctor::probe_ollama_models(&targets, ollama_model.as_deref()),
+    );
+    let report = report.with_connectivity(results);
+    Ok(match ollama {
+        Some(status) => report.with_ollama(status),
+        None => report,
+    })
+}
+
+/// Check a workspace's files for structural problems: unparseable entities or
+/// sections, duplicate IDs and section orders, and tags or links to entities
+/// that don't exist
+#[tauri::command]
+pub fn run_workspace_diagnostics(workspace: String) -> Result<Vec<WorkspaceFinding>, String> {
+    Ok(doctor::check_workspace(&canonical_workspace(Path::new(
+        &workspace,
+    ))?))
+}
+
+/// Describe the environment run_shell uses in a workspace: shell binary (`shell`, or
+/// the platform default), PATH entries in lookup order, the home directory, the
+/// variables passed under `shell_env_allowlist`, and the resolved working directory
+#[tauri::command]
+pub fn get_shell_environment(
+    workspace: String,
+    cwd: Option<String>,
+    shell_env_allowlist: Option<Vec<String>>,
+    shell: Option<String>,
+) -> Result<ShellEnvironment, String> {
+    let shell_env_allowlist = shell_env_allowlist.unwrap_or_default();
+    shell_env::validate_allowlist(&shell_env_allowlist)?;
+    let paths = PathValidator::new(std::path::Path::new(&workspace));
+    let working_dir = shell_working_dir(&paths, cwd.as_deref())?;
+    ShellEnvironment::for_working_dir(
+        &working_dir,
+        &ShellEnv::inherited(&shell_env_allowlist),
+        shell.as_deref(),
+    )
+}
+
+// ============================================================================
+// Entity Graph and Tag Commands
+// ============================================================================
+
+/// Export the workspace's entity graph as Graphviz DOT or nodes/edges JSON.
+///
+/// Writes to `output_path` when given (a path inside the workspace, checked like
+/// the file tools' paths), otherwise returns the rendered graph inline.
+#[tauri::command]
+pub fn export_entity_graph(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+    format: String,
+    entity_types: Option<Vec<String>>,
+    min_weight: Option<u32>,
+    output_path: Option<String>,
+) -> Result<GraphExport, String> {
+    let workspace_path = canonical_workspace(Path::new(&workspace))?;
+    let format = GraphFormat::parse(&format)?;
+    let options = GraphOptions {
+        entity_types: entity_types.unwrap_or_default(),
+        min_weight: min_weight.unwrap_or(0),
+    };
+    let output = output_path
+        .map(|p| PathValidator::new(&workspace_path).resolve(&p))
+        .transpose()?;
+
+    let store = entity_store(&workspaces, &workspace_path);
+    entity_graph::export_graph(&store, format, &options, output.as_deref())
+}
+
+/// An entity store on `workspace_path` (canonical), sharing the workspace's
+/// cache while it is open
+fn entity_store(workspaces: &WorkspaceRegistry, workspace_path: &Path) -> EntityStore {
+    match workspaces.get(workspace_path) {
+        Some(state) => state.entity_store(),
+        None => EntityStore::new(workspace_path),
+    }
+}
 
-    Ok(crate::agent::doctor::run_health_check(
-        &credentials,
-        &registry,
-    ))
+/// Propose entity tags for a section from entity names and aliases found in its
+/// text. Nothing is written.
+#[tauri::command]
+pub fn suggest_entity_tags(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+    section_id: String,
+) -> Result<Vec<SuggestedTag>, String> {
+    let workspace_path = canonical_workspace(Path::new(&workspace))?;
+    let store = entity_store(&workspaces, &workspace_path);
+    entity_tags::suggest_entity_tags(&store, &section_id)
+}
+
+/// Add accepted tag suggestions to a section
+#[tauri::command]
+pub fn apply_suggested_tags(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+    section_id: String,
+    tags: Vec<TagRange>,
+) -> Result<Vec<Tag>, String> {
+    let workspace_path = canonical_workspace(Path::new(&workspace))?;
+    let store = entity_store(&workspaces, &workspace_path);
+    entity_tags::apply_suggested_tags(&store, &section_id, &tags)
+}
+
+/// Fold a duplicate entity into another, moving its section references and tags
+#[tauri::command]
+pub fn merge_entities(
+    workspaces: State<'_, SharedWorkspaceRegistry>,
+    workspace: String,
+    source_id: String,
+    target_id: String,
+) -> Result<MergeSummary, String> {
+    let workspace_path = canonical_workspace(Path::new(&workspace))?;
+    let store = entity_store(&workspaces, &workspace_path);
+    store.merge_entities(&source_id, &target_id)
 }
 
 // ============================================================================
 // Session Management Commands
 // ============================================================================
 
-/// List recent agent sessions
+/// List recent agent sessions, only those in `workspace` when given
 #[tauri::command]
 pub fn list_agent_sessions(
     session_store: State<'_, SharedSessionStore>,
     limit: Option<usize>,
+    workspace: Option<String>,
 ) -> Vec<Session> {
     let limit = limit.unwrap_or(20).min(100);
-    session_store.list_sessions(limit)
+    // Sessions store the canonical path; a workspace that no longer exists
+    // can still match sessions recorded under its raw spelling
+    let workspace = workspace.map(|w| {
+        let raw = PathBuf::from(w);
+        canonical_workspace(&raw).unwrap_or(raw)
+    });
+    session_store.list_sessions(workspace.as_deref(), limit)
+}
+
+/// List sessions whose run is still going, with their live progress
+#[tauri::command]
+pub fn get_active_sessions(session_store: State<'_, SharedSessionStore>) -> Vec<Session> {
+    session_store.active_sessions()
+}
+
+/// List the prompt templates stored in a workspace's `.vswrite/prompts`
+#[tauri::command]
+pub fn list_prompt_templates(workspace: String) -> Result<Vec<PromptTemplate>, String> {
+    prompt_templates::list_templates(&canonical_workspace(Path::new(&workspace))?)
+}
+
+/// Lay out a new project at `path` from a built-in template (`novel` or `blank`).
+/// A folder that isn't empty is refused unless `force` is set.
+#[tauri::command]
+pub fn scaffold_workspace(
+    path: String,
+    template: String,
+    force: Option<bool>,
+) -> Result<ScaffoldManifest, String> {
+    scaffold::scaffold_workspace(Path::new(&path), &template, force.unwrap_or(false))
+}
+
+/// Join a workspace's sections into one Markdown or HTML file inside it
+#[tauri::command]
+pub fn compile_manuscript(
+    workspace: String,
+    options: CompileOptions,
+) -> Result<CompileReport, String> {
+    manuscript::compile_manuscript(&canonical_workspace(Path::new(&workspace))?, &options)
+}
+
+/// Build or refresh the semantic search index of a workspace's sections,
+/// emitting `semantic-index-progress` events. Only sections changed since the
+/// last build are embedded. Embeddings come from OpenAI, with `api_key` or else
+/// the saved OpenAI key, at `base_url` if given; with `session_id`, the tokens
+/// are added to that session's usage.
+#[tauri::command]
+pub async fn build_semantic_index(
+    app: AppHandle,
+    credentials: State<'_, SharedCredentialManager>,
+    session_store: State<'_, SharedSessionStore>,
+    workspace: String,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    session_id: Option<String>,
+) -> Result<IndexReport, String> {
+    let workspace = canonical_workspace(Path::new(&workspace))?;
+    let api_key = api_key
+        .filter(|key| !key.is_empty())
+        .or_else(|| credentials.get_key(LlmProvider::OpenAI))
+        .ok_or_else(|| "Semantic indexing needs an OpenAI API key".to_string())?;
+    let client = EmbeddingClient::openai(api_key, base_url);
+
+    let report = semantic_index::build_index(&workspace, &client, |progress| {
+        if let Err(e) = app.emit(semantic_index::INDEX_PROGRESS_EVENT, &progress) {
+            log::warn!("Failed to emit semantic index progress: {}", e);
+        }
+    })
+    .await?;
+    if let Some(session_id) = session_id {
+        session_store.update_session(&session_id, |s| {
+            s.record_embedding_usage(client.model(), report.tokens)
+        });
+    }
+    Ok(report)
+}
+
+/// List the files a run backed up before changing them
+#[tauri::command]
+pub fn list_run_backups(workspace: String, run_id: String) -> Result<BackupManifest, String> {
+    backups::list_run_backups(&canonical_workspace(Path::new(&workspace))?, &run_id)
+}
+
+/// Restore one file a run backed up, or every file when `path` is omitted.
+/// Returns the restored paths.
+#[tauri::command]
+pub fn restore_run_backup(
+    workspace: String,
+    run_id: String,
+    path: Option<String>,
+) -> Result<Vec<String>, String> {
+    backups::restore_run_backup(
+        &canonical_workspace(Path::new(&workspace))?,
+        &run_id,
+        path.as_deref(),
+    )
 }
 
 /// Get a specific session by ID
@@ -737,15 +2289,83 @@ pub fn get_agent_session(
     session_store.get_session(&session_id)
 }
 
-/// Get audit log entries for a session
+/// Get a session's saved conversation, which outlives the session itself
+/// across restarts
+#[tauri::command]
+pub fn get_session_transcript(
+    transcripts: State<'_, SharedTranscriptStore>,
+    session_id: String,
+) -> Result<Transcript, String> {
+    transcripts.load(&session_id)
+}
+
+/// Export a session's trace (record, config without the API key, audit log,
+/// changed files, token usage, and saved conversation) as `json` or `markdown`,
+/// for attaching to a bug report. `path` may be anywhere the user picked, but an
+/// existing file is only replaced when `overwrite` is set. Returns the path written.
+#[tauri::command]
+pub fn export_session_report(
+    session_store: State<'_, SharedSessionStore>,
+    transcripts: State<'_, SharedTranscriptStore>,
+    session_id: String,
+    format: String,
+    path: String,
+    overwrite: Option<bool>,
+) -> Result<String, String> {
+    let format = ReportFormat::parse(&format)?;
+    let session = session_store.get_session(&session_id);
+    let audit = session_store.get_session_audit(&session_id, usize::MAX);
+    let transcript = match transcripts.load(&session_id) {
+        Ok(transcript) => Some(transcript),
+        Err(e) => {
+            log::info!(
+                "Exporting session {} without a transcript: {}",
+                session_id,
+                e
+            );
+            None
+        }
+    };
+
+    let report = session_report::build_report(&session_id, session, audit, transcript)?;
+    let rendered = session_report::render(&report, format)?;
+    let path = PathBuf::from(path);
+    session_report::write_report(&path, &rendered, overwrite.unwrap_or(false))?;
+    Ok(path.display().to_string())
+}
+
+/// Get the session recording a run, by the run ID its events carry
+#[tauri::command]
+pub fn get_session_for_run(
+    session_store: State<'_, SharedSessionStore>,
+    run_id: String,
+) -> Option<Session> {
+    session_store.session_for_run(&run_id)
+}
+
+/// Get token totals and the estimated cost of a session
+#[tauri::command]
+pub fn get_session_usage(
+    session_store: State<'_, SharedSessionStore>,
+    session_id: String,
+) -> Result<SessionUsage, String> {
+    session_store
+        .get_session(&session_id)
+        .map(|s| s.usage())
+        .ok_or_else(|| format!("Session not found: {}", session_id))
+}
+
+/// Get audit log entries for a session, optionally only those of one event type
+/// (e.g. `approval` to list tool approval decisions)
 #[tauri::command]
 pub fn get_session_audit_log(
     session_store: State<'_, SharedSessionStore>,
     session_id: String,
     limit: Option<usize>,
+    event_type: Option<AuditEventType>,
 ) -> Vec<AuditEntry> {
     let limit = limit.unwrap_or(50).min(500);
-    session_store.get_session_audit(&session_id, limit)
+    session_store.get_session_audit_filtered(&session_id, event_type, limit)
 }
 
 /// Get recent audit log entries across all sessions
@@ -757,3 +2377,415 @@ pub fn get_recent_audit_log(
     let limit = limit.unwrap_or(50).min(500);
     session_store.get_recent_audit(limit)
 }
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, content: &str) -> InputMessage {
+        InputMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    /// Roles for a history of alternating turns, so nothing is merged
+    fn alternating_role(i: usize) -> &'static str {
+        if i % 2 == 0 {
+            "user"
+        } else {
+            "assistant"
+        }
+    }
+
+    #[test]
+    fn test_prepare_history_accepts_valid() {
+        let history = vec![msg("user", "Hello"), msg("assistant", "Hi there")];
+        let converted = prepare_history(history, false, LlmProvider::OpenAI)
+            .unwrap()
+            .0;
+        assert_eq!(converted.len(), 2);
+        assert_eq!(converted[0].role, MessageRole::User);
+        assert_eq!(converted[1].role, MessageRole::Assistant);
+    }
+
+    #[test]
+    fn test_prepare_history_rejects_empty_role() {
+        let history = vec![msg("user", "ok"), msg("  ", "no role")];
+        let err = prepare_history(history, false, LlmProvider::OpenAI).unwrap_err();
+        assert!(err.contains("messages[1]"));
+        assert!(err.contains("role cannot be empty"));
+    }
+
+    #[test]
+    fn test_prepare_history_unknown_role_becomes_user() {
+        let history = vec![msg("narrator", "Once upon a time")];
+        let converted = prepare_history(history, false, LlmProvider::OpenAI)
+            .unwrap()
+            .0;
+        assert_eq!(converted[0].role, MessageRole::User);
+    }
+
+    #[test]
+    fn test_prepare_history_rejects_oversized_message() {
+        let big = "x".repeat(MAX_MESSAGE_BYTES + 1);
+        let history = vec![msg("user", "fine"), msg("user", "fine"), msg("user", &big)];
+
+        // Per-message cap applies even when trimming is enabled
+        for trim in [false, true] {
+            let err = prepare_history(history.clone(), trim, LlmProvider::OpenAI).unwrap_err();
+            assert!(err.contains("messages[2]"));
+            assert!(err.contains("content too large"));
+        }
+    }
+
+    #[test]
+    fn test_prepare_history_normalizes_content() {
+        let history = vec![
+            msg("user", "Chapter\u{0000} one"),
+            msg(
+                "assistant",
+                &format!("ok{}", "\u{200D}".repeat(MAX_MESSAGE_BYTES)),
+            ),
+        ];
+        let converted = prepare_history(history, false, LlmProvider::OpenAI)
+            .unwrap()
+            .0;
+        assert_eq!(converted[0].content.as_deref(), Some("Chapter one"));
+        assert_eq!(converted[1].content.as_deref(), Some("ok\u{200D}"));
+
+        let history = vec![msg("user", "fine"), msg("user", "\u{0007}\u{0007}")];
+        let err = prepare_history(history, false, LlmProvider::OpenAI).unwrap_err();
+        assert!(err.starts_with("messages[1]: content is empty after normalization"));
+    }
+
+    #[test]
+    fn test_prepare_prompt() {
+        let (task, prompt) = prepare_prompt("Fix\u{001B}[31m typos", "Be brief.").unwrap();
+        assert_eq!(task, "Fix[31m typos");
+        assert_eq!(prompt, "Be brief.");
+
+        assert_eq!(
+            prepare_prompt("  ", "").unwrap_err(),
+            "Task cannot be empty"
+        );
+        let err = prepare_prompt(&"\u{200B}".repeat(10), "").unwrap_err();
+        assert!(err.starts_with("Task is empty after normalization"));
+
+        let at_cap = "x".repeat(TASK_LIMITS.max_chars);
+        assert!(prepare_prompt(&at_cap, "").is_ok());
+        let err = prepare_prompt(&format!("{}x", at_cap), "").unwrap_err();
+        assert!(err.starts_with("Task too long"));
+
+        // Under the char cap but over the byte cap
+        let wide = "日".repeat(TASK_LIMITS.max_bytes / 3 + 1);
+        let err = prepare_prompt(&wide, "").unwrap_err();
+        assert!(err.starts_with("Task too large"));
+
+        let err =
+            prepare_prompt("task", &"p".repeat(SYSTEM_PROMPT_LIMITS.max_chars + 1)).unwrap_err();
+        assert!(err.starts_with("System prompt too long"));
+    }
+
+    #[test]
+    fn test_prepare_history_message_count_cap() {
+        let history: Vec<InputMessage> = (0..MAX_HISTORY_MESSAGES + 5)
+            .map(|i| msg(alternating_role(i), &format!("message {}", i)))
+            .collect();
+
+        let err = prepare_history(history.clone(), false, LlmProvider::OpenAI).unwrap_err();
+        assert!(err.contains("Too many messages"));
+
+        let trimmed = prepare_history(history, true, LlmProvider::OpenAI)
+            .unwrap()
+            .0;
+        assert_eq!(trimmed.len(), MAX_HISTORY_MESSAGES);
+        // Oldest messages are dropped first
+        assert_eq!(trimmed[0].content.as_deref(), Some("message 5"));
+    }
+
+    #[test]
+    fn test_prepare_history_total_bytes_cap() {
+        let chunk = "y".repeat(MAX_MESSAGE_BYTES);
+        let count = MAX_HISTORY_BYTES / MAX_MESSAGE_BYTES + 2;
+        let mut history: Vec<InputMessage> = (0..count)
+            .map(|i| msg(alternating_role(i), &chunk))
+            .collect();
+        history.push(msg(alternating_role(count), "latest"));
+
+        let err = prepare_history(history.clone(), false, LlmProvider::OpenAI).unwrap_err();
+        assert!(err.contains("Message history too large"));
+
+        let trimmed = prepare_history(history, true, LlmProvider::OpenAI)
+            .unwrap()
+            .0;
+        let total: usize = trimmed
+            .iter()
+            .map(|m| m.content.as_ref().map(|c| c.len()).unwrap_or(0))
+            .sum();
+        assert!(total <= MAX_HISTORY_BYTES);
+        assert_eq!(trimmed.last().unwrap().content.as_deref(), Some("latest"));
+    }
+
+    const PROVIDERS: [LlmProvider; 4] = [
+        LlmProvider::OpenAI,
+        LlmProvider::Claude,
+        LlmProvider::Ollama,
+        LlmProvider::OpenRouter,
+    ];
+
+    #[test]
+    fn test_prepare_history_drops_empty_and_merges_user_messages() {
+        let history = vec![
+            msg("user", "First"),
+            msg("assistant", "   "),
+            msg("user", "Second"),
+            msg("assistant", "Reply"),
+            msg("user", ""),
+            msg("user", "Third"),
+        ];
+        for provider in PROVIDERS {
+            let (converted, report) = prepare_history(history.clone(), false, provider).unwrap();
+            let shape: Vec<(MessageRole, &str)> = converted
+                .iter()
+                .map(|m| (m.role, m.content.as_deref().unwrap()))
+                .collect();
+            assert_eq!(
+                shape,
+                vec![
+                    (MessageRole::User, "First\n\nSecond"),
+                    (MessageRole::Assistant, "Reply"),
+                    (MessageRole::User, "Third"),
+                ],
+                "{:?}",
+                provider
+            );
+            assert_eq!(
+                report,
+                HistoryNormalization {
+                    dropped_empty: 2,
+                    trimmed: 0,
+                    // "Second" into "First", and "Third" into the task
+                    merged: 2,
+                    dropped_leading: 0,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_prepare_history_leading_assistant_dropped_for_claude() {
+        let history = vec![
+            msg("system", "Earlier instructions"),
+            msg("assistant", "Welcome back!"),
+            msg("assistant", "What next?"),
+            msg("user", "Continue the chapter"),
+            msg("assistant", "Done"),
+        ];
+        for provider in PROVIDERS {
+            let (converted, report) = prepare_history(history.clone(), false, provider).unwrap();
+            let roles: Vec<MessageRole> = converted.iter().map(|m| m.role).collect();
+            if provider == LlmProvider::Claude {
+                assert_eq!(
+                    roles,
+                    vec![
+                        MessageRole::System,
+                        MessageRole::User,
+                        MessageRole::Assistant
+                    ]
+                );
+                assert_eq!(report.dropped_leading, 2);
+            } else {
+                assert_eq!(roles.len(), 5, "{:?}", provider);
+                assert!(report.is_unchanged(), "{:?}", provider);
+            }
+        }
+
+        // Trimming can leave an assistant message first too
+        let history: Vec<InputMessage> = (0..MAX_HISTORY_MESSAGES + 3)
+            .map(|i| msg(alternating_role(i), &format!("message {}", i)))
+            .collect();
+        let (converted, report) = prepare_history(history, true, LlmProvider::Claude).unwrap();
+        assert_eq!(report.trimmed, 3);
+        assert_eq!(report.dropped_leading, 1);
+        assert_eq!(converted[0].role, MessageRole::User);
+        assert_eq!(converted[0].content.as_deref(), Some("message 4"));
+    }
+
+    #[test]
+    fn test_input_config_timeouts() {
+        let config =
+            |extra: serde_json::Value| -> InputConfig { serde_json::from_value(extra).unwrap() };
+
+        assert!(config(serde_json::json!({})).validate().is_ok());
+        assert!(config(
+            serde_json::json!({"request_timeout_secs": 300, "connect_timeout_secs": 5})
+        )
+        .validate()
+        .is_ok());
+
+        let err = config(serde_json::json!({"request_timeout_secs": 2}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("request_timeout_secs"), "{}", err);
+        assert!(config(serde_json::json!({"connect_timeout_secs": 601}))
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_input_config_openrouter_routing() {
+        let config =
+            |extra: serde_json::Value| -> InputConfig { serde_json::from_value(extra).unwrap() };
+
+        let routed = config(serde_json::json!({
+            "provider": "openrouter",
+            "openrouter_routing": {"order": ["Anthropic"], "allow_fallbacks": false, "sort": "price"}
+        }));
+        assert!(routed.validate().is_ok());
+        let routing = routed.openrouter_routing.unwrap();
+        assert_eq!(routing.order, vec!["Anthropic"]);
+        assert_eq!(routing.allow_fallbacks, Some(false));
+
+        let err = config(serde_json::json!({"openrouter_routing": {"sort": "cheapest"}}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("openrouter_routing.sort"), "{}", err);
+        assert!(
+            config(serde_json::json!({"openrouter_routing": {"ignore": [""]}}))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_input_config_reasoning() {
+        let config =
+            |extra: serde_json::Value| -> InputConfig { serde_json::from_value(extra).unwrap() };
+
+        let reasoning = config(serde_json::json!({
+            "api_key": "sk-test",
+            "reasoning_effort": "high",
+            "thinking_budget_tokens": 2048,
+            "max_tokens": 8192
+        }));
+        assert!(reasoning.validate().is_ok());
+        let agent_config = reasoning
+            .into_agent_config(&CredentialManager::new())
+            .unwrap();
+        assert_eq!(agent_config.reasoning_effort.as_deref(), Some("high"));
+        assert_eq!(agent_config.thinking_budget_tokens, Some(2048));
+
+        let err = config(serde_json::json!({"reasoning_effort": "maximum"}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("reasoning_effort"), "{}", err);
+        let err = config(serde_json::json!({"thinking_budget_tokens": 512}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("at least 1024"), "{}", err);
+        let err = config(serde_json::json!({"thinking_budget_tokens": 4096, "max_tokens": 4096}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("less than max_tokens"), "{}", err);
+    }
+
+    #[test]
+    fn test_input_config_concurrency() {
+        let config =
+            |extra: serde_json::Value| -> InputConfig { serde_json::from_value(extra).unwrap() };
+
+        let defaults = config(serde_json::json!({"api_key": "sk-test"}))
+            .into_agent_config(&CredentialManager::new())
+            .unwrap();
+        assert_eq!(defaults.concurrency, RunConcurrency::Reject);
+        assert_eq!(defaults.queue_timeout_secs, 300);
+        assert_eq!(defaults.event_buffer, 256);
+
+        let queued = config(serde_json::json!({
+            "api_key": "sk-test",
+            "concurrency": "queue",
+            "queue_timeout_secs": 30
+        }))
+        .into_agent_config(&CredentialManager::new())
+        .unwrap();
+        assert_eq!(queued.concurrency, RunConcurrency::Queue);
+        assert_eq!(queued.queue_timeout_secs, 30);
+
+        let err = config(serde_json::json!({"queue_timeout_secs": 0}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("queue_timeout_secs"), "{}", err);
+        let err = config(serde_json::json!({"event_buffer": 1}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("event_buffer"), "{}", err);
+        assert!(serde_json::from_value::<InputConfig>(
+            serde_json::json!({"concurrency": "always"})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_input_config_tool_limits() {
+        let config =
+            |extra: serde_json::Value| -> InputConfig { serde_json::from_value(extra).unwrap() };
+
+        // Caps left out keep their defaults
+        let limited = config(serde_json::json!({"tool_limits": {"grep_max_matches": 20}}));
+        assert!(limited.validate().is_ok());
+        assert_eq!(
+            limited.tool_limits,
+            ToolLimits {
+                grep_max_matches: 20,
+                ..ToolLimits::default()
+            }
+        );
+
+        let err = config(serde_json::json!({"tool_limits": {"result_bytes": 10}}))
+            .validate()
+            .unwrap_err();
+        assert!(err.contains("tool_limits.result_bytes"), "{}", err);
+    }
+
+    #[test]
+    fn test_agent_result_error_info() {
+        let failed = AgentResult::failed(AgentError::MissingApiKey("OpenAI".to_string()).info());
+        let value = serde_json::to_value(&failed).unwrap();
+        assert_eq!(value["success"], false);
+        // The log string stays alongside the structured form
+        assert_eq!(
+            value["error"],
+            "Config error: OpenAI API key is not configured"
+        );
+        assert_eq!(
+            value["error_info"],
+            serde_json::json!({
+                "code": "missing_api_key",
+                "message": "Config error: OpenAI API key is not configured",
+                "retryable": false,
+                "provider": "OpenAI"
+            })
+        );
+
+        let value = serde_json::to_value(AgentResult::failed(
+            AgentError::WorkspaceMissing("/gone".to_string()).info(),
+        ))
+        .unwrap();
+        assert_eq!(value["error_info"]["code"], "workspace_missing");
+        assert!(value["error_info"].get("provider").is_none());
+
+        let done = AgentResult {
+            success: true,
+            error: None,
+            error_info: None,
+            ..failed
+        };
+        let value = serde_json::to_value(&done).unwrap();
+        assert!(value.get("error_info").is_none());
+    }
+}
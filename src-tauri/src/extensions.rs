@@ -1,13 +1,12 @@
-use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use ed25519_dalek::{Signature, Verifier, VerifyingKey};
-use sha2::{Digest, Sha256};
 use std::fs::{self, File};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
-use tauri::{AppHandle, Manager};
+use tauri::{AppHandle, Manager, State};
 use zip::ZipArchive;
 
-use crate::agent::lua_extensions::ExtensionManifest;
+use crate::agent::lua_extensions::{lint_manifest, ExtensionManifest};
+use crate::agent::signature::{self, SignatureVerification};
+use crate::agent::trust_store::{SharedTrustStore, TrustStore, TrustedPublisher};
 
 /// Validate extension ID to prevent path traversal attacks
 ///
@@ -103,184 +102,78 @@ pub struct ExtensionInfo {
     pub version: String,
 }
 
-/// Result of signature verification
-#[derive(serde::Serialize, Clone)]
-pub struct SignatureVerification {
-    /// Whether the extension is signed
-    pub is_signed: bool,
-    /// Whether the signature is valid (only meaningful if is_signed is true)
-    pub is_valid: bool,
-    /// The publisher/key ID that signed the extension
-    pub publisher_id: Option<String>,
-    /// Whether the publisher is trusted
-    pub is_trusted: bool,
-    /// Human-readable status message
-    pub status: String,
-    /// Error message if verification failed
-    pub error: Option<String>,
-}
-
-/// Trusted publisher public keys
-/// These are base64-encoded Ed25519 public keys (raw 32-byte keys)
-static TRUSTED_PUBLISHERS: &[(&str, &str)] = &[
-    // VS Write official key - used to sign bundled extensions
-    (
-        "vswrite-official",
-        "Nqh5oHbH6TO6WrAV1r64m0Z8FWhQru7Ku75tDmMNqkA=",
-    ),
-    // Add more trusted publishers here
-];
-
-/// Get the canonical manifest content for signing
-/// This removes signature-related fields and produces deterministic JSON
-fn get_signable_content(manifest: &serde_json::Value) -> String {
-    let mut manifest_copy = manifest.clone();
-
-    // Remove signature fields before hashing
-    if let Some(obj) = manifest_copy.as_object_mut() {
-        obj.remove("signature");
-        obj.remove("signatureAlgorithm");
-        obj.remove("publicKeyId");
-    }
-
-    // Produce deterministic JSON (sorted keys, no extra whitespace)
-    serde_json::to_string(&manifest_copy).unwrap_or_default()
+/// Verify an extension's signature from its manifest file
+#[tauri::command]
+pub fn verify_extension_signature(
+    manifest_path: String,
+    trust_store: State<'_, SharedTrustStore>,
+) -> Result<SignatureVerification, String> {
+    verify_manifest_file(&manifest_path, &trust_store)
 }
 
-/// Verify an extension's signature
-fn verify_signature(
-    manifest: &serde_json::Value,
-    signature_b64: &str,
-    public_key_id: &str,
+/// Verify the manifest at `manifest_path` and the script files next to it
+pub fn verify_manifest_file(
+    manifest_path: &str,
+    trust_store: &TrustStore,
 ) -> Result<SignatureVerification, String> {
-    // Find the public key for this publisher
-    let public_key_b64 = TRUSTED_PUBLISHERS
-        .iter()
-        .find(|(id, _)| *id == public_key_id)
-        .map(|(_, key)| *key);
-
-    let is_trusted = public_key_b64.is_some();
-
-    // If publisher not in trusted list, try to get key from manifest
-    // (for self-signed extensions)
-    let public_key_b64 =
-        public_key_b64.or_else(|| manifest.get("publicKey").and_then(|v| v.as_str()));
-
-    let public_key_b64 = match public_key_b64 {
-        Some(key) => key,
-        None => {
-            return Ok(SignatureVerification {
-                is_signed: true,
-                is_valid: false,
-                publisher_id: Some(public_key_id.to_string()),
-                is_trusted: false,
-                status: "Unknown publisher - public key not found".to_string(),
-                error: Some("Public key not found for publisher".to_string()),
-            });
-        }
-    };
+    log::info!("Verifying extension signature for {}", manifest_path);
 
-    // Decode the public key
-    let public_key_bytes = BASE64
-        .decode(public_key_b64)
-        .map_err(|e| format!("Invalid public key encoding: {}", e))?;
-
-    // Ed25519 public keys are 32 bytes
-    let public_key_array: [u8; 32] = public_key_bytes
-        .try_into()
-        .map_err(|_| "Invalid public key length (expected 32 bytes)")?;
-
-    let verifying_key = VerifyingKey::from_bytes(&public_key_array)
-        .map_err(|e| format!("Invalid public key: {}", e))?;
-
-    // Decode the signature
-    let signature_bytes = BASE64
-        .decode(signature_b64)
-        .map_err(|e| format!("Invalid signature encoding: {}", e))?;
-
-    let signature =
-        Signature::from_slice(&signature_bytes).map_err(|e| format!("Invalid signature: {}", e))?;
-
-    // Get the content that was signed
-    let signable_content = get_signable_content(manifest);
-
-    // Hash the content (we sign the SHA-256 hash)
-    let mut hasher = Sha256::new();
-    hasher.update(signable_content.as_bytes());
-    let hash = hasher.finalize();
-
-    // Verify the signature
-    match verifying_key.verify(&hash, &signature) {
-        Ok(_) => Ok(SignatureVerification {
-            is_signed: true,
-            is_valid: true,
-            publisher_id: Some(public_key_id.to_string()),
-            is_trusted,
-            status: if is_trusted {
-                format!("Verified - signed by trusted publisher '{}'", public_key_id)
-            } else {
-                format!(
-                    "Valid signature from untrusted publisher '{}'",
-                    public_key_id
-                )
-            },
-            error: None,
-        }),
-        Err(e) => Ok(SignatureVerification {
-            is_signed: true,
-            is_valid: false,
-            publisher_id: Some(public_key_id.to_string()),
-            is_trusted,
-            status: "Signature verification failed".to_string(),
-            error: Some(format!("Signature verification failed: {}", e)),
-        }),
+    // Read the manifest
+    let manifest_content = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("Failed to read manifest: {}", e))?;
+
+    let manifest: serde_json::Value = serde_json::from_str(&manifest_content)
+        .map_err(|e| format!("Failed to parse manifest JSON: {}", e))?;
+
+    let mut verification = signature::verify_manifest(&manifest, trust_store)?;
+    if let Some(dir) = Path::new(manifest_path).parent() {
+        signature::verify_files(dir, &manifest, &mut verification);
     }
+    Ok(verification)
 }
 
-/// Verify an extension's signature from its manifest file
+/// Validate an extension manifest and report likely mistakes.
+///
+/// Returns lint warnings (e.g. misspelled field names) for a manifest that parses;
+/// parse failures are returned as an error.
 #[tauri::command]
-pub fn verify_extension_signature(manifest_path: String) -> Result<SignatureVerification, String> {
-    log::info!("Verifying extension signature for {}", manifest_path);
-
-    // Read the manifest
+pub fn validate_extension_manifest(manifest_path: String) -> Result<Vec<String>, String> {
     let manifest_content = fs::read_to_string(&manifest_path)
         .map_err(|e| format!("Failed to read manifest: {}", e))?;
 
     let manifest: serde_json::Value = serde_json::from_str(&manifest_content)
         .map_err(|e| format!("Failed to parse manifest JSON: {}", e))?;
 
-    // Check if the extension is signed
-    let signature = manifest.get("signature").and_then(|v| v.as_str());
-    let public_key_id = manifest.get("publicKeyId").and_then(|v| v.as_str());
-
-    match (signature, public_key_id) {
-        (Some(sig), Some(key_id)) => verify_signature(&manifest, sig, key_id),
-        (Some(_), None) => Ok(SignatureVerification {
-            is_signed: true,
-            is_valid: false,
-            publisher_id: None,
-            is_trusted: false,
-            status: "Signed but missing publicKeyId".to_string(),
-            error: Some("Extension has signature but no publicKeyId".to_string()),
-        }),
-        _ => Ok(SignatureVerification {
-            is_signed: false,
-            is_valid: false,
-            publisher_id: None,
-            is_trusted: false,
-            status: "Not signed".to_string(),
-            error: None,
-        }),
-    }
+    serde_json::from_value::<ExtensionManifest>(manifest.clone())
+        .map_err(|e| format!("Invalid manifest: {}", e))?;
+
+    Ok(lint_manifest(&manifest))
+}
+
+/// Get list of trusted publishers, built-in and user-added
+#[tauri::command]
+pub fn get_trusted_publishers(trust_store: State<'_, SharedTrustStore>) -> Vec<TrustedPublisher> {
+    trust_store.list()
 }
 
-/// Get list of trusted publishers
+/// Trust a publisher's base64-encoded Ed25519 public key. Applies to extensions
+/// loaded from now on.
 #[tauri::command]
-pub fn get_trusted_publishers() -> Vec<String> {
-    TRUSTED_PUBLISHERS
-        .iter()
-        .map(|(id, _)| id.to_string())
-        .collect()
+pub fn add_trusted_publisher(
+    id: String,
+    public_key: String,
+    trust_store: State<'_, SharedTrustStore>,
+) -> Result<TrustedPublisher, String> {
+    trust_store.add(&id, &public_key)
+}
+
+/// Stop trusting a user-added publisher. Returns whether it was trusted.
+#[tauri::command]
+pub fn remove_trusted_publisher(
+    id: String,
+    trust_store: State<'_, SharedTrustStore>,
+) -> Result<bool, String> {
+    trust_store.remove(&id)
 }
 
 /// Install bundled Lua extensions into the app data extensions directory.
@@ -291,7 +184,10 @@ pub fn get_trusted_publishers() -> Vec<String> {
 /// The install is idempotent: if an extension is already installed with the same version, it's
 /// skipped. If the bundled version differs, the installed copy is replaced.
 #[tauri::command]
-pub fn install_bundled_lua_extensions(app: AppHandle) -> Result<Vec<String>, String> {
+pub fn install_bundled_lua_extensions(
+    app: AppHandle,
+    trust_store: State<'_, SharedTrustStore>,
+) -> Result<Vec<String>, String> {
     let bundled_root = match bundled_extensions_roots(&app)
         .into_iter()
         .find(|p| p.exists())
@@ -346,7 +242,19 @@ pub fn install_bundled_lua_extensions(app: AppHandle) -> Result<Vec<String>, Str
                 e
             )
         })?;
-        let manifest: ExtensionManifest = serde_json::from_str(&manifest_content).map_err(|e| {
+        let raw_manifest: serde_json::Value =
+            serde_json::from_str(&manifest_content).map_err(|e| {
+                format!(
+                    "Failed to parse bundled manifest {}: {}",
+                    manifest_path.display(),
+                    e
+                )
+            })?;
+        for warning in lint_manifest(&raw_manifest) {
+            log::warn!("{}: {}", manifest_path.display(), warning);
+        }
+        let bundled = signature::verify_extension(&src_dir, &raw_manifest, &trust_store);
+        let manifest: ExtensionManifest = serde_json::from_value(raw_manifest).map_err(|e| {
             format!(
                 "Failed to parse bundled manifest {}: {}",
                 manifest_path.display(),
@@ -418,17 +326,96 @@ pub fn install_bundled_lua_extensions(app: AppHandle) -> Result<Vec<String>, Str
             )
         })?;
 
+        // Re-verify the copy so a corrupted install isn't left behind
+        if let Err(e) = verify_installed_copy(&dest_dir, &bundled, &trust_store) {
+            log::error!(
+                "Bundled extension '{}' failed verification after install: {}",
+                manifest.id,
+                e
+            );
+            if let Err(e) = fs::remove_dir_all(&dest_dir) {
+                log::warn!("Failed to remove {}: {}", dest_dir.display(), e);
+            }
+            continue;
+        }
+
         installed_ids.push(manifest.id);
     }
 
     Ok(installed_ids)
 }
 
+/// Check an installed copy of a bundled extension against the bundled original:
+/// its script files must match the manifest, and a signature that verified in the
+/// bundle must still verify
+fn verify_installed_copy(
+    dir: &Path,
+    bundled: &SignatureVerification,
+    trust_store: &TrustStore,
+) -> Result<(), String> {
+    let manifest_content = fs::read_to_string(dir.join("manifest.json"))
+        .map_err(|e| format!("Failed to read manifest: {}", e))?;
+    let manifest: serde_json::Value = serde_json::from_str(&manifest_content)
+        .map_err(|e| format!("Failed to parse manifest: {}", e))?;
+
+    let installed = signature::verify_extension(dir, &manifest, trust_store);
+    if installed.is_tampered() || (bundled.is_valid && !installed.is_valid) {
+        return Err(installed.error.unwrap_or(installed.status));
+    }
+    Ok(())
+}
+
+/// Default cap on the total uncompressed size of a .vsext
+pub const DEFAULT_MAX_EXTRACT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Default cap on the number of entries in a .vsext
+pub const DEFAULT_MAX_EXTRACT_ENTRIES: usize = 1_000;
+
+/// Default cap on how much larger than its compressed size an entry may be
+pub const DEFAULT_MAX_COMPRESSION_RATIO: u64 = 100;
+
+/// Entries smaller than this skip the compression ratio check; small text
+/// files can compress very well without being a zip bomb
+const COMPRESSION_RATIO_MIN_BYTES: u64 = 1024 * 1024;
+
+/// Limits applied while extracting a .vsext
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExtractLimits {
+    pub max_total_bytes: u64,
+    pub max_entries: usize,
+    pub max_compression_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        ExtractLimits {
+            max_total_bytes: DEFAULT_MAX_EXTRACT_BYTES,
+            max_entries: DEFAULT_MAX_EXTRACT_ENTRIES,
+            max_compression_ratio: DEFAULT_MAX_COMPRESSION_RATIO,
+        }
+    }
+}
+
+impl ExtractLimits {
+    /// Most bytes an entry may decompress to: what's left of the total budget,
+    /// and no more than the compression ratio allows
+    fn entry_budget(&self, compressed_size: u64, remaining: u64) -> u64 {
+        let by_ratio = compressed_size
+            .saturating_mul(self.max_compression_ratio)
+            .max(COMPRESSION_RATIO_MIN_BYTES);
+        remaining.min(by_ratio)
+    }
+}
+
 /// Extract a .vsext (ZIP) file to the extensions directory
+///
+/// `limits` overrides the default size, entry-count, and compression-ratio caps.
 #[tauri::command]
 pub fn extract_extension(
     vsext_path: String,
     extensions_dir: String,
+    limits: Option<ExtractLimits>,
 ) -> Result<ExtractResult, String> {
     log::info!(
         "Extracting extension from {} to {}",
@@ -436,8 +423,23 @@ pub fn extract_extension(
         extensions_dir
     );
 
+    extract_vsext(
+        Path::new(&vsext_path),
+        Path::new(&extensions_dir),
+        &limits.unwrap_or_default(),
+    )
+}
+
+/// Extract a .vsext into `extensions_dir/<id>`. The archive is unpacked into a
+/// staging directory first, so a failure leaves any existing install untouched
+/// and nothing half-extracted behind.
+fn extract_vsext(
+    vsext_path: &Path,
+    extensions_dir: &Path,
+    limits: &ExtractLimits,
+) -> Result<ExtractResult, String> {
     // Open the .vsext (ZIP) file
-    let file = File::open(&vsext_path).map_err(|e| format!("Failed to open .vsext file: {}", e))?;
+    let file = File::open(vsext_path).map_err(|e| format!("Failed to open .vsext file: {}", e))?;
 
     let mut archive =
         ZipArchive::new(file).map_err(|e| format!("Failed to read ZIP archive: {}", e))?;
@@ -491,55 +493,163 @@ pub fn extract_extension(
     // Validate extension ID to prevent path traversal attacks
     validate_extension_id(&extension_id)?;
 
-    // Create extraction directory
-    let extract_path = PathBuf::from(&extensions_dir).join(&extension_id);
+    fs::create_dir_all(extensions_dir)
+        .map_err(|e| format!("Failed to create extensions directory: {}", e))?;
+    let extract_path = extensions_dir.join(&extension_id);
+    let staging_path = extensions_dir.join(format!(".{}.extracting", extension_id));
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)
+            .map_err(|e| format!("Failed to remove stale staging directory: {}", e))?;
+    }
+
+    let extracted = extract_archive(&mut archive, &staging_path, limits)
+        .and_then(|()| check_extracted_manifest(&staging_path, &extension_id));
+    if let Err(e) = extracted {
+        if let Err(cleanup) = fs::remove_dir_all(&staging_path) {
+            log::warn!("Failed to clean up {}: {}", staging_path.display(), cleanup);
+        }
+        return Err(e);
+    }
 
-    // Delete existing directory if it exists (for updates)
+    // Replace the existing directory if there is one (for updates)
     if extract_path.exists() {
         log::info!("Removing existing extension at {:?}", extract_path);
-        fs::remove_dir_all(&extract_path)
-            .map_err(|e| format!("Failed to remove existing extension: {}", e))?;
+        if let Err(e) = fs::remove_dir_all(&extract_path) {
+            let _ = fs::remove_dir_all(&staging_path);
+            return Err(format!("Failed to remove existing extension: {}", e));
+        }
+    }
+    fs::rename(&staging_path, &extract_path).map_err(|e| {
+        let _ = fs::remove_dir_all(&staging_path);
+        format!("Failed to move extension into place: {}", e)
+    })?;
+
+    log::info!("Extension extracted successfully to {:?}", extract_path);
+
+    Ok(ExtractResult {
+        extension_id,
+        path: extract_path.to_string_lossy().to_string(),
+    })
+}
+
+/// Unpack every entry of `archive` under `target`, enforcing `limits`. Entries
+/// whose path would leave `target` are an error.
+fn extract_archive<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    target: &Path,
+    limits: &ExtractLimits,
+) -> Result<(), String> {
+    if archive.len() > limits.max_entries {
+        return Err(format!(
+            "Archive has {} entries, more than the limit of {}",
+            archive.len(),
+            limits.max_entries
+        ));
+    }
+
+    // Check declared sizes and paths before writing anything
+    let mut declared_total: u64 = 0;
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .map_err(|e| format!("Failed to read file from archive: {}", e))?;
+        if file.enclosed_name().is_none() {
+            return Err(format!(
+                "Archive entry '{}' would be extracted outside the extension directory",
+                file.name()
+            ));
+        }
+        declared_total = declared_total.saturating_add(file.size());
+        if declared_total > limits.max_total_bytes {
+            return Err(format!(
+                "Archive unpacks to more than the limit of {} bytes",
+                limits.max_total_bytes
+            ));
+        }
+        if file.size() > limits.entry_budget(file.compressed_size(), u64::MAX) {
+            return Err(format!(
+                "Archive entry '{}' is compressed more than {}:1; refusing a possible zip bomb",
+                file.name(),
+                limits.max_compression_ratio
+            ));
+        }
     }
 
-    fs::create_dir_all(&extract_path)
+    fs::create_dir_all(target)
         .map_err(|e| format!("Failed to create extension directory: {}", e))?;
 
-    // Extract all files
+    // Declared sizes can lie, so count the bytes actually written too
+    let mut written: u64 = 0;
     for i in 0..archive.len() {
         let mut file = archive
             .by_index(i)
             .map_err(|e| format!("Failed to read file from archive: {}", e))?;
 
         let outpath = match file.enclosed_name() {
-            Some(path) => extract_path.join(path),
-            None => continue, // Skip if path is unsafe
+            Some(path) => target.join(path),
+            None => {
+                return Err(format!(
+                    "Archive entry '{}' would be extracted outside the extension directory",
+                    file.name()
+                ))
+            }
         };
 
         if file.is_dir() {
             fs::create_dir_all(&outpath)
                 .map_err(|e| format!("Failed to create directory: {}", e))?;
-        } else {
-            if let Some(p) = outpath.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p)
-                        .map_err(|e| format!("Failed to create parent directory: {}", e))?;
-                }
+            continue;
+        }
+
+        if let Some(p) = outpath.parent() {
+            if !p.exists() {
+                fs::create_dir_all(p)
+                    .map_err(|e| format!("Failed to create parent directory: {}", e))?;
             }
+        }
 
-            let mut outfile =
-                File::create(&outpath).map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut outfile =
+            File::create(&outpath).map_err(|e| format!("Failed to create file: {}", e))?;
 
-            io::copy(&mut file, &mut outfile)
-                .map_err(|e| format!("Failed to write file: {}", e))?;
+        let budget = limits.entry_budget(
+            file.compressed_size(),
+            limits.max_total_bytes.saturating_sub(written),
+        );
+        let name = file.name().to_string();
+        let copied = io::copy(&mut (&mut file).take(budget + 1), &mut outfile)
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        if copied > budget {
+            return Err(format!(
+                "Archive entry '{}' unpacks to more than its size or compression limit allows",
+                name
+            ));
         }
+        written += copied;
     }
 
-    log::info!("Extension extracted successfully to {:?}", extract_path);
+    Ok(())
+}
 
-    Ok(ExtractResult {
-        extension_id,
-        path: extract_path.to_string_lossy().to_string(),
-    })
+/// After extraction, the manifest on disk must still carry the ID the archive
+/// was unpacked under
+fn check_extracted_manifest(dir: &Path, extension_id: &str) -> Result<(), String> {
+    let manifest_path = dir.join("manifest.json");
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&manifest_path)
+        .map_err(|e| format!("Failed to read extracted manifest: {}", e))?;
+    let manifest: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse extracted manifest: {}", e))?;
+    match manifest["id"].as_str() {
+        Some(id) if id == extension_id => Ok(()),
+        found => Err(format!(
+            "Extracted manifest.json has id {:?}, but the archive was read as '{}'",
+            found.unwrap_or_default(),
+            extension_id
+        )),
+    }
 }
 
 /// Delete an extension directory
@@ -641,6 +751,149 @@ pub fn read_extension_info(vsext_path: String) -> Result<ExtensionInfo, String>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+    use zip::write::FileOptions;
+    use zip::CompressionMethod;
+
+    fn manifest(id: &str) -> Vec<u8> {
+        serde_json::json!({"id": id, "name": id, "version": "1.0.0"})
+            .to_string()
+            .into_bytes()
+    }
+
+    /// Write a .vsext with the given (name, content) entries, deflated
+    fn write_vsext(dir: &Path, entries: &[(&str, Vec<u8>)]) -> PathBuf {
+        let path = dir.join("test.vsext");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, content) in entries {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    fn extract(
+        dir: &TempDir,
+        vsext: &Path,
+        limits: ExtractLimits,
+    ) -> Result<ExtractResult, String> {
+        extract_vsext(vsext, &dir.path().join("extensions"), &limits)
+    }
+
+    /// Only the installed extension directories are left behind
+    fn installed(dir: &TempDir) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir.path().join("extensions"))
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_extract_extension() {
+        let dir = TempDir::new().unwrap();
+        let vsext = write_vsext(
+            dir.path(),
+            &[
+                ("manifest.json", manifest("good-ext")),
+                ("lib/tools.lua", b"function greet() end".to_vec()),
+            ],
+        );
+
+        let result = extract(&dir, &vsext, ExtractLimits::default()).unwrap();
+        assert_eq!(result.extension_id, "good-ext");
+        let installed_path = PathBuf::from(&result.path);
+        assert!(installed_path.join("lib/tools.lua").is_file());
+        assert_eq!(installed(&dir), vec!["good-ext"]);
+    }
+
+    #[test]
+    fn test_extract_rejects_escaping_entries() {
+        let dir = TempDir::new().unwrap();
+        let vsext = write_vsext(
+            dir.path(),
+            &[
+                ("manifest.json", manifest("evil-ext")),
+                ("../escaped.lua", b"-- outside".to_vec()),
+            ],
+        );
+
+        let err = extract(&dir, &vsext, ExtractLimits::default()).unwrap_err();
+        assert!(err.contains("'../escaped.lua'"), "{}", err);
+        assert!(!dir.path().join("extensions/escaped.lua").exists());
+        assert!(installed(&dir).is_empty());
+    }
+
+    #[test]
+    fn test_extract_enforces_size_and_entry_limits() {
+        let dir = TempDir::new().unwrap();
+        let mut entries = vec![("manifest.json", manifest("big-ext"))];
+        entries.push(("a.lua", vec![b'x'; 2000]));
+        let vsext = write_vsext(dir.path(), &entries);
+
+        let small = ExtractLimits {
+            max_total_bytes: 1000,
+            ..Default::default()
+        };
+        let err = extract(&dir, &vsext, small).unwrap_err();
+        assert!(err.contains("more than the limit of 1000 bytes"), "{}", err);
+
+        let few = ExtractLimits {
+            max_entries: 1,
+            ..Default::default()
+        };
+        let err = extract(&dir, &vsext, few).unwrap_err();
+        assert!(err.contains("2 entries"), "{}", err);
+        assert!(installed(&dir).is_empty());
+    }
+
+    #[test]
+    fn test_extract_rejects_zip_bomb() {
+        let dir = TempDir::new().unwrap();
+        let vsext = write_vsext(
+            dir.path(),
+            &[
+                ("manifest.json", manifest("bomb-ext")),
+                ("zeros.bin", vec![0; 4 * 1024 * 1024]),
+            ],
+        );
+
+        let err = extract(&dir, &vsext, ExtractLimits::default()).unwrap_err();
+        assert!(
+            err.contains("'zeros.bin' is compressed more than 100:1"),
+            "{}",
+            err
+        );
+        assert!(installed(&dir).is_empty());
+    }
+
+    #[test]
+    fn test_extract_checks_manifest_id_and_keeps_existing_install() {
+        let dir = TempDir::new().unwrap();
+        let good = write_vsext(dir.path(), &[("manifest.json", manifest("swap-ext"))]);
+        extract(&dir, &good, ExtractLimits::default()).unwrap();
+
+        // A second entry that resolves to manifest.json replaces the one the ID was read from
+        let swapped = write_vsext(
+            dir.path(),
+            &[
+                ("manifest.json", manifest("swap-ext")),
+                ("x/../manifest.json", manifest("other-ext")),
+            ],
+        );
+        let err = extract(&dir, &swapped, ExtractLimits::default()).unwrap_err();
+        assert!(err.contains("\"other-ext\""), "{}", err);
+
+        // The earlier install survives and no staging directory is left
+        assert_eq!(installed(&dir), vec!["swap-ext"]);
+        let kept =
+            fs::read_to_string(dir.path().join("extensions/swap-ext/manifest.json")).unwrap();
+        assert!(kept.contains("\"swap-ext\""));
+    }
 
     #[test]
     fn test_valid_extension_ids() {
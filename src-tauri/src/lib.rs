@@ -8,9 +8,17 @@ use std::sync::{Arc, RwLock};
 use tauri::{Emitter, Manager};
 use tokio::sync::Mutex;
 
-use agent::credentials::{CredentialManager, SharedCredentialManager};
+use agent::credentials::{CredentialManager, OsKeychain, SharedCredentialManager};
+use agent::doctor::{HealthCache, SharedHealthCache, StartupProbe};
+use agent::global_config::{GlobalConfig, SharedGlobalConfig, GLOBAL_CONFIG_FILE};
+use agent::idle::{RunActivity, SharedRunActivity};
+use agent::llm::SharedRateLimits;
 use agent::lua_extensions::ExtensionRegistry;
+use agent::ollama::OllamaPulls;
 use agent::session::{SessionStore, SharedSessionStore};
+use agent::transcripts::{SharedTranscriptStore, TranscriptStore, TRANSCRIPTS_DIR};
+use agent::trust_store::{SharedTrustStore, TrustStore, TRUSTED_PUBLISHERS_FILE};
+use agent::workspaces::{SharedWorkspaceRegistry, WorkspaceRegistry};
 use agent_commands::{RunningTasks, SharedExtensionRegistry};
 
 #[tauri::command]
@@ -173,14 +181,26 @@ pub fn run() {
             }
 
             // Create credential manager for secure API key handling
-            // Keys are read from environment variables, never exposed to frontend
-            let credential_manager: SharedCredentialManager = Arc::new(CredentialManager::new());
-            app.manage(credential_manager);
+            // Keys are read from the OS keychain or environment, never exposed to frontend
+            let credential_manager: SharedCredentialManager =
+                Arc::new(CredentialManager::new().with_keychain(Arc::new(OsKeychain)));
+            app.manage(credential_manager.clone());
+
+            // What providers last said about their rate limits, shared by every run
+            let rate_limits: SharedRateLimits = Arc::default();
+            app.manage(rate_limits);
+
+            // Publisher keys the user trusts on top of the built-in ones
+            let trust_store: SharedTrustStore = Arc::new(TrustStore::load(
+                app.path().app_data_dir()?.join(TRUSTED_PUBLISHERS_FILE),
+            ));
+            app.manage(trust_store.clone());
 
             // Create extension registry for Lua extensions (RwLock allows concurrent reads)
-            let extension_registry: SharedExtensionRegistry =
-                Arc::new(RwLock::new(ExtensionRegistry::new()));
-            app.manage(extension_registry);
+            let extension_registry: SharedExtensionRegistry = Arc::new(RwLock::new(Arc::new(
+                ExtensionRegistry::new().with_trust_store(trust_store),
+            )));
+            app.manage(extension_registry.clone());
 
             // Create running tasks map for agent cancellation
             let running_tasks: RunningTasks =
@@ -189,12 +209,62 @@ pub fn run() {
 
             // Create session store for tracking agent sessions and audit logging
             let session_store: SharedSessionStore = Arc::new(SessionStore::new());
-            app.manage(session_store);
+            app.manage(session_store.clone());
+
+            // Conversations are saved as they go so a run can be resumed after a restart
+            let transcripts: SharedTranscriptStore = Arc::new(TranscriptStore::new(
+                app.path().app_data_dir()?.join(TRANSCRIPTS_DIR),
+            ));
+            app.manage(transcripts);
+
+            // App-wide agent settings, editable at runtime and saved across restarts
+            let global_config: SharedGlobalConfig = Arc::new(RwLock::new(GlobalConfig::load(
+                &app.path().app_config_dir()?.join(GLOBAL_CONFIG_FILE),
+            )));
+            app.manage(global_config.clone());
+            let global_config_for_probe = global_config.clone();
+
+            // Per-workspace state for projects open in separate windows
+            let workspaces: SharedWorkspaceRegistry = Arc::new(WorkspaceRegistry::new());
+            app.manage(workspaces);
+
+            // Track run activity and cancel runs left idle (e.g. unanswered approvals)
+            let run_activity: SharedRunActivity = Arc::new(RunActivity::new());
+            app.manage(run_activity.clone());
+            tauri::async_runtime::spawn(agent::idle::run_idle_reaper(
+                run_activity,
+                global_config,
+                session_store,
+            ));
 
             // Create tool approval store for gated tool execution
             let tool_approvals: agent::ToolApprovalStore = Arc::new(Mutex::new(HashMap::new()));
             app.manage(tool_approvals);
 
+            // Track in-flight Ollama model pulls (one per model)
+            app.manage(OllamaPulls::new());
+
+            // Probe health in the background shortly after launch so problems surface
+            // before the first run; the report is cached for run_agent_health_check
+            let health_cache: SharedHealthCache = Arc::new(HealthCache::new());
+            app.manage(health_cache.clone());
+            let startup_probe = StartupProbe::default();
+            app.manage(startup_probe.clone());
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let report = agent::doctor::run_startup_probe(
+                    credential_manager,
+                    extension_registry,
+                    health_cache,
+                    global_config_for_probe,
+                    startup_probe.token(),
+                )
+                .await;
+                if let Some(report) = report {
+                    let _ = handle.emit("health-report-ready", &report);
+                }
+            });
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -204,33 +274,90 @@ pub fn run() {
             extensions::delete_extension,
             extensions::read_extension_info,
             extensions::verify_extension_signature,
+            extensions::validate_extension_manifest,
             extensions::get_trusted_publishers,
+            extensions::add_trusted_publisher,
+            extensions::remove_trusted_publisher,
             extensions::install_bundled_lua_extensions,
             // Native agent commands
             agent_commands::run_native_agent,
+            agent_commands::apply_agent_plan,
             agent_commands::get_native_agent_status,
             agent_commands::get_available_providers,
+            agent_commands::set_provider_api_key,
+            agent_commands::clear_provider_api_key,
+            agent_commands::get_provider_rate_limits,
             agent_commands::cancel_agent_task,
+            agent_commands::prepare_shutdown,
             agent_commands::list_running_tasks,
             agent_commands::get_agent_run_capacity,
+            agent_commands::open_workspace,
+            agent_commands::close_workspace,
+            agent_commands::start_workspace_watcher,
+            agent_commands::stop_workspace_watcher,
             agent_commands::respond_tool_approval,
+            agent_commands::get_global_config,
+            agent_commands::update_global_config,
+            // Ollama model management
+            agent_commands::list_ollama_models,
+            agent_commands::pull_ollama_model,
+            agent_commands::cancel_ollama_pull,
             // Lua extension management commands
             agent_commands::load_lua_extension,
             agent_commands::unload_lua_extension,
             agent_commands::list_lua_extensions,
+            agent_commands::get_extension_details,
+            agent_commands::set_extension_signature_policy,
             agent_commands::get_extension_tools,
+            agent_commands::get_extension_stats,
             // Lifecycle hook commands
             agent_commands::execute_extension_hook,
             agent_commands::execute_hook_all,
             agent_commands::get_extension_hooks,
             // Health check
             agent_commands::run_agent_health_check,
+            agent_commands::run_workspace_diagnostics,
+            agent_commands::get_shell_environment,
+            // Entity graph and tags
+            agent_commands::export_entity_graph,
+            agent_commands::suggest_entity_tags,
+            agent_commands::apply_suggested_tags,
+            agent_commands::merge_entities,
             // Session management
             agent_commands::list_agent_sessions,
             agent_commands::get_agent_session,
+            agent_commands::get_session_transcript,
+            agent_commands::export_session_report,
+            agent_commands::resume_agent_session,
+            agent_commands::get_active_sessions,
+            agent_commands::get_session_for_run,
+            agent_commands::list_prompt_templates,
+            agent_commands::scaffold_workspace,
+            agent_commands::compile_manuscript,
+            agent_commands::build_semantic_index,
+            agent_commands::list_run_backups,
+            agent_commands::restore_run_backup,
+            agent_commands::get_session_usage,
             agent_commands::get_session_audit_log,
             agent_commands::get_recent_audit_log
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            // Stop file watchers, cancel in-flight agent runs and close their sessions before the process exits.
+            if let tauri::RunEvent::Exit = event {
+                app.state::<StartupProbe>().cancel();
+                let running_tasks = app.state::<RunningTasks>().inner().clone();
+                let session_store = app.state::<SharedSessionStore>().inner().clone();
+                app.state::<SharedWorkspaceRegistry>().stop_watchers();
+                tauri::async_runtime::block_on(async move {
+                    agent::shutdown::shutdown(
+                        &running_tasks,
+                        &session_store,
+                        agent::shutdown::SHUTDOWN_GRACE_PERIOD,
+                    )
+                    .await
+                });
+            }
+        });
 }
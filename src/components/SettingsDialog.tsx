@@ -651,8 +651,8 @@ export function SettingsDialog({ open, onOpenChange }: SettingsDialogProps) {
                   </div>
 
                   <div className="rounded-md bg-yellow-500/10 text-yellow-600 dark:text-yellow-400 p-3 text-xs">
-                    <p className="font-medium mb-1">Note: Limited tool support</p>
-                    <p>Ollama models may not support tool calling reliably. Some agent features may not work as expected.</p>
+                    <p className="font-medium mb-1">Note: Tool support depends on the model</p>
+                    <p>Models with tool calling (e.g. llama3.1, qwen2.5, mistral-nemo) can read and edit files. Other models run in chat-only mode.</p>
                   </div>
                 </div>
               )}
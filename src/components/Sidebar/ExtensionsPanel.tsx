@@ -184,7 +184,19 @@ export function ExtensionsPanel() {
 	                    </span>
 	                  </div>
                   <div style={{ fontSize: '12px', color: '#808080', marginBottom: '4px' }}>
-                    v{ext.version} - {ext.toolCount} tool(s)
+                    v{ext.version} - {ext.toolCount} tool(s) -{' '}
+                    <span
+                      title={ext.signature.status}
+                      style={{ color: ext.signature.is_valid && ext.signature.is_trusted ? '#4ade80' : '#f59e0b' }}
+                    >
+                      {!ext.signature.is_signed
+                        ? 'unsigned'
+                        : !ext.signature.is_valid
+                          ? 'invalid signature'
+                          : ext.signature.is_trusted
+                            ? 'verified'
+                            : 'untrusted publisher'}
+                    </span>
                   </div>
                   {ext.description && (
                     <div style={{ fontSize: '12px', color: '#a0a0a0', marginTop: '8px' }}>
@@ -26,26 +26,100 @@ interface AgentEvent {
   type:
     | 'start'
     | 'tool_call_start'
+    | 'tool_output_chunk'
     | 'tool_call_complete'
     | 'tool_approval_required'
     | 'tool_skipped'
+    | 'tool_blocked'
+    | 'extension_tool_killed'
     | 'text_chunk'
+    | 'reasoning'
+    | 'warning'
+    | 'context_truncated'
+    | 'plan_generated'
+    | 'retrying_llm_call'
+    | 'waiting'
+    | 'queued'
+    | 'dropped_events'
+    | 'injection_suspected'
     | 'complete'
     | 'error'
     | 'cancelled';
   task?: string;
+  content?: string;
+  message?: string;
+  attempt?: number;
+  max_retries?: number;
+  delay_ms?: number;
+  position?: number;
+  count?: number;
+  elided_messages?: number;
+  tokens_before?: number;
+  tokens_after?: number;
+  budget?: number;
+  steps?: PlannedToolCall[];
+  phrases?: string[];
+  path?: string;
   approval_id?: string;
   name?: string;
   args?: Record<string, unknown>;
   risk?: 'low' | 'medium' | 'high';
+  preview?: string;
   reason?: string;
   result?: string;
   success?: boolean;
   truncated?: boolean;
+  seq?: number;
+  duration_ms?: number;
+  output_bytes?: number;
+  effects?: { bytes_written: number; files_affected: string[] };
+  approval?: 'approved' | 'denied' | 'timed_out' | 'auto_approved';
   response?: string;
   usage?: { prompt_tokens: number; completion_tokens: number; total_tokens: number };
+  estimated_cost?: number;
+  served_model?: { model: string; provider?: string };
   error?: string;
+  summary?: RunSummary;
   run_id?: string;
+  session_id?: string;
+}
+
+/**
+ * A tool call a dry run recorded instead of executing
+ * Matches PlannedToolCall in src-tauri/src/agent/types.rs
+ */
+interface PlannedToolCall {
+  tool: string;
+  args: Record<string, unknown>;
+  risk: 'low' | 'medium' | 'high';
+  effect: string;
+  files: string[];
+}
+
+/**
+ * Structured account of a run, attached to complete/error/cancelled events
+ * Matches RunSummary in src-tauri/src/agent/types.rs
+ */
+interface RunSummary {
+  segments: { iteration: number; text: string }[];
+  tool_calls: Record<string, number>;
+  working_set: string[];
+  file_changes?: FileChange[];
+  bytes_written?: number;
+  usage?: { prompt_tokens: number; completion_tokens: number; total_tokens: number };
+  iterations: number;
+  duration_ms: number;
+  scratchpad?: string;
+}
+
+/**
+ * Net effect of a run on one file
+ * Matches FileChange in src-tauri/src/agent/types.rs
+ */
+interface FileChange {
+  path: string;
+  operation: 'created' | 'modified' | 'deleted';
+  byte_delta: number;
 }
 
 /**
@@ -61,6 +135,25 @@ interface AgentConfig {
   max_iterations: number;
   base_url?: string;
   approval_mode?: 'auto_approve' | 'approve_dangerous' | 'approve_writes' | 'approve_all' | 'dry_run';
+  event_detail?: 'full' | 'truncated' | 'metadata_only';
+  stream?: boolean;
+  shell_policy?: { allow?: string[]; deny?: string[]; default_deny?: boolean };
+  shell_env_allowlist?: string[];
+  shell?: string;
+  injection_guard?: 'off' | 'wrap' | 'wrap_and_flag';
+  tool_limits?: {
+    read_line_bytes?: number;
+    shell_output_bytes?: number;
+    grep_max_matches?: number;
+    glob_max_entries?: number;
+    result_bytes?: number;
+  };
+  disabled_tools?: string[];
+  reasoning_effort?: 'low' | 'medium' | 'high';
+  thinking_budget_tokens?: number;
+  concurrency?: 'reject' | 'queue' | 'parallel';
+  queue_timeout_secs?: number;
+  event_buffer?: number;
 }
 
 /**
@@ -71,6 +164,22 @@ interface ToolCallEvent {
   args: Record<string, unknown>;
   result: string;
   success: boolean;
+  seq?: number;
+  durationMs?: number;
+  outputBytes?: number;
+}
+
+/** "8.2 s · 45.0 KB" for a finished tool call, when the agent reported timing */
+function formatToolStats(toolCall: ToolCallEvent): string | null {
+  if (toolCall.durationMs === undefined) return null;
+  const duration = toolCall.durationMs < 1000
+    ? `${toolCall.durationMs} ms`
+    : `${(toolCall.durationMs / 1000).toFixed(1)} s`;
+  if (toolCall.outputBytes === undefined) return duration;
+  const size = toolCall.outputBytes < 1024
+    ? `${toolCall.outputBytes} B`
+    : `${(toolCall.outputBytes / 1024).toFixed(1)} KB`;
+  return `${duration} · ${size}`;
 }
 
 interface DisplayMessage extends ChatMessage {
@@ -308,11 +417,69 @@ export function NativeAgentPanel() {
             setIsLoading(true);
             break;
 
+          case 'text_chunk':
+            // Show streamed text in the loading bubble until the run completes
+            if (agentEvent.content) {
+              const chunk = agentEvent.content;
+              setTimeline(prev => prev.map(item =>
+                'isLoading' in item && item.isLoading ? { ...item, content: item.content + chunk } : item
+              ));
+            }
+            break;
+
+          case 'reasoning':
+            // Model reasoning is kept out of the answer bubble
+            console.debug('[Agent] Reasoning:', agentEvent.content);
+            break;
+
+          case 'warning':
+            console.warn('[Agent] Warning:', agentEvent.message);
+            break;
+
+          case 'context_truncated':
+            // Answers may miss details from the elided tool output
+            console.warn(
+              `[Agent] Elided ${agentEvent.elided_messages} old tool results to fit the context ` +
+              `(~${agentEvent.tokens_before} -> ~${agentEvent.tokens_after} of ${agentEvent.budget} tokens)`
+            );
+            break;
+
+          case 'plan_generated':
+            // The plan also comes back in the run result for apply_agent_plan
+            console.log(`[Agent] Dry run planned ${agentEvent.steps?.length ?? 0} step(s)`, agentEvent.steps);
+            break;
+
+          case 'queued':
+            // Another run has the workspace; `start` follows when this one gets it
+            console.log(`[Agent] Waiting for the workspace (position ${agentEvent.position})`);
+            break;
+
+          case 'dropped_events':
+            // The panel fell behind; streamed text or tool output is missing from the timeline
+            console.warn(`[Agent] ${agentEvent.count} events were dropped while the panel was busy`);
+            break;
+
+          case 'retrying_llm_call':
+            console.warn(
+              `[Agent] ${agentEvent.reason}; retrying in ${agentEvent.delay_ms} ms ` +
+              `(attempt ${agentEvent.attempt}/${agentEvent.max_retries})`
+            );
+            break;
+
+          case 'waiting':
+            // The provider's rate limit is nearly used up; the next call waits for it to reset
+            console.log(`[Agent] ${agentEvent.reason}; waiting ${agentEvent.delay_ms} ms`);
+            break;
+
           case 'tool_call_start':
+            // A tool call ends the streamed preamble
+            setTimeline(prev => prev.map(item =>
+              'isLoading' in item && item.isLoading && item.content ? { ...item, content: '' } : item
+            ));
             // Mark files as recently written BEFORE the tool executes
             // This prevents the file watcher from triggering before tool_call_complete arrives
             if (agentEvent.name) {
-              const fileWriteTools = ['write_file', 'append_file', 'delete_file'];
+              const fileWriteTools = ['write_file', 'append_file', 'edit_file', 'delete_file', 'begin_write'];
               if (fileWriteTools.includes(agentEvent.name) && agentEvent.args?.path) {
                 const ps = projectServiceRef.current;
                 if (ps) {
@@ -328,7 +495,8 @@ export function NativeAgentPanel() {
                 name: agentEvent.name,
                 args: (agentEvent.args as Record<string, unknown>) || {},
                 result: '', // Empty until complete
-                success: true // Assume success until we know otherwise
+                success: true, // Assume success until we know otherwise
+                seq: agentEvent.seq
               };
 
               setTimeline(prev => {
@@ -354,17 +522,40 @@ export function NativeAgentPanel() {
             }
             break;
 
+          case 'tool_output_chunk':
+            // Live output from a running command, shown until the tool completes
+            if (agentEvent.content) {
+              const chunk = agentEvent.content;
+              setTimeline(prev => prev.map(item =>
+                'isLoading' in item && item.isLoading ? { ...item, content: item.content + chunk } : item
+              ));
+            }
+            break;
+
           case 'tool_call_complete': {
             const toolName = agentEvent.name;
             if (!toolName) break;
+            // The full result replaces any live output
+            setTimeline(prev => prev.map(item =>
+              'isLoading' in item && item.isLoading && item.content ? { ...item, content: '' } : item
+            ));
 
             const toolArgs = (agentEvent.args as Record<string, unknown>) || {};
             const toolResult = agentEvent.result || '';
             const toolSuccess = agentEvent.success ?? true;
+            const completedCall: ToolCallEvent = {
+              name: toolName,
+              args: toolArgs,
+              result: toolResult,
+              success: toolSuccess,
+              seq: agentEvent.seq,
+              durationMs: agentEvent.duration_ms,
+              outputBytes: agentEvent.output_bytes
+            };
 
             // Mark files as recently written when agent file-writing tools complete
             // This prevents the file watcher from triggering a reload prompt
-            const fileWriteTools = ['write_file', 'append_file', 'delete_file'];
+            const fileWriteTools = ['write_file', 'append_file', 'edit_file', 'delete_file', 'begin_write'];
             const pathArg = (toolArgs as { path?: unknown }).path;
             if (fileWriteTools.includes(toolName) && typeof pathArg === 'string') {
               const ps = projectServiceRef.current;
@@ -379,27 +570,17 @@ export function NativeAgentPanel() {
               const lastItem = updated[updated.length - 1];
 
               if (lastItem && 'type' in lastItem && lastItem.type === 'tool_execution') {
-                // Find the pending tool call (one with empty result) and update it
-                const pendingIndex = lastItem.toolCalls.findIndex(
-                  tc => tc.name === toolName && tc.result === ''
-                );
+                // Find the pending tool call (same seq, else same name with empty result) and update it
+                const pendingIndex = agentEvent.seq !== undefined
+                  ? lastItem.toolCalls.findIndex(tc => tc.seq === agentEvent.seq)
+                  : lastItem.toolCalls.findIndex(tc => tc.name === toolName && tc.result === '');
 
                 if (pendingIndex !== -1) {
                   // Update the pending entry with the result
-                  lastItem.toolCalls[pendingIndex] = {
-                    name: toolName,
-                    args: toolArgs,
-                    result: toolResult,
-                    success: toolSuccess
-                  };
+                  lastItem.toolCalls[pendingIndex] = completedCall;
                 } else {
                   // No pending entry found, add as new (fallback)
-                  lastItem.toolCalls.push({
-                    name: toolName,
-                    args: toolArgs,
-                    result: toolResult,
-                    success: toolSuccess
-                  });
+                  lastItem.toolCalls.push(completedCall);
                 }
               } else {
                 // No tool execution message exists, create one (shouldn't happen normally)
@@ -407,12 +588,7 @@ export function NativeAgentPanel() {
                   id: `tool-exec-${Date.now()}-${Math.random().toString(36).slice(2, 8)}`,
                   type: 'tool_execution',
                   conversation_id: convId,
-                  toolCalls: [{
-                    name: toolName,
-                    args: toolArgs,
-                    result: toolResult,
-                    success: toolSuccess
-                  }],
+                  toolCalls: [completedCall],
                   created_at: new Date().toISOString()
                 };
                 updated.push(toolExecMsg);
@@ -422,6 +598,51 @@ export function NativeAgentPanel() {
             break;
           }
 
+          case 'tool_blocked': {
+            // Rejected by the shell policy before it ever ran
+            const toolName = agentEvent.name;
+            if (!toolName) break;
+            console.warn('[Agent] Tool blocked:', agentEvent.reason);
+
+            const blockedCall: ToolCallEvent = {
+              name: toolName,
+              args: (agentEvent.args as Record<string, unknown>) || {},
+              result: `Blocked: ${agentEvent.reason ?? 'command blocked by policy'}`,
+              success: false
+            };
+            setTimeline(prev => {
+              const updated = [...prev];
+              const lastItem = updated[updated.length - 1];
+              if (lastItem && 'type' in lastItem && lastItem.type === 'tool_execution') {
+                lastItem.toolCalls.push(blockedCall);
+              } else {
+                updated.push({
+                  id: `tool-exec-${Date.now()}-${Math.random().toString(36).slice(2, 8)}`,
+                  type: 'tool_execution',
+                  conversation_id: convId,
+                  toolCalls: [blockedCall],
+                  created_at: new Date().toISOString()
+                });
+              }
+              return updated;
+            });
+            break;
+          }
+
+          case 'injection_suspected':
+            // The output still reached the model, fenced off as data
+            console.warn(
+              `[Agent] ${agentEvent.name} output${agentEvent.path ? ` from ${agentEvent.path}` : ''} ` +
+              `looks like a prompt injection:`,
+              agentEvent.phrases
+            );
+            break;
+
+          case 'extension_tool_killed':
+            // The tool's failed tool_call_complete follows
+            console.warn('[Agent] Extension tool killed:', agentEvent.name, agentEvent.reason);
+            break;
+
           case 'tool_approval_required': {
             const approvalId = agentEvent.approval_id;
             const toolName = agentEvent.name;
@@ -432,15 +653,31 @@ export function NativeAgentPanel() {
             const risk = agentEvent.risk || 'medium';
             const argsText = JSON.stringify(args, null, 2);
             const truncatedArgs = argsText.length > 2000 ? `${argsText.slice(0, 2000)}\n…(truncated)` : argsText;
+            // File-changing tools come with a diff, which says more than the raw args
+            const details = agentEvent.preview
+              ? `Changes:\n${agentEvent.preview}`
+              : `Args:\n${truncatedArgs}`;
 
             void (async () => {
               try {
                 const approved = await confirmDialog(
-                  `Allow the agent to execute tool "${toolName}"?\n\nRisk: ${risk}\n\nArgs:\n${truncatedArgs}`,
+                  `Allow the agent to execute tool "${toolName}"?\n\nRisk: ${risk}\n\n${details}`,
                   { kind: 'warning', okLabel: 'Allow', cancelLabel: 'Deny' },
                 );
 
-                await invoke('respond_tool_approval', { approvalId, approved });
+                // Offer to reuse the answer so multi-file edits don't prompt per file
+                const remember = await confirmDialog(
+                  approved
+                    ? `Also allow "${toolName}" for the rest of this run without asking?`
+                    : `Also deny "${toolName}" for the rest of this run without asking?`,
+                  { kind: 'info', okLabel: 'Rest of run', cancelLabel: 'Just this once' },
+                );
+
+                await invoke('respond_tool_approval', {
+                  approvalId,
+                  approved,
+                  scope: remember ? 'tool' : 'once',
+                });
               } catch (error) {
                 console.error('Failed to handle tool approval:', error);
                 // Best-effort: deny if we couldn't prompt.
@@ -457,6 +694,13 @@ export function NativeAgentPanel() {
           case 'complete':
             setIsLoading(false);
             setAgentStatus('idle');
+            if (agentEvent.usage) {
+              const cost = agentEvent.estimated_cost;
+              console.log(
+                `[Agent] Used ${agentEvent.usage.total_tokens} tokens` +
+                (cost !== undefined ? ` (~$${cost.toFixed(4)})` : '')
+              );
+            }
             if (agentEvent.response) {
               const assistantMessage: DisplayMessage = {
                 id: generateMessageId(),
@@ -919,6 +1163,11 @@ export function NativeAgentPanel() {
 	                                    </span>
 	                                  )}
 	                                </div>
+	                                {formatToolStats(toolCall) && (
+	                                  <span className="shrink-0 text-[10px] text-muted-foreground">
+	                                    {formatToolStats(toolCall)}
+	                                  </span>
+	                                )}
 	                                <span className={`ml-auto shrink-0 text-xs ${toolCall.result === '' ? 'text-blue-500' : toolCall.success ? 'text-green-600' : 'text-red-600'}`}>
 	                                  {toolCall.result === '' ? '⋯' : toolCall.success ? '✓' : '✗'}
 	                                </span>
@@ -974,7 +1223,7 @@ export function NativeAgentPanel() {
 	                        <CopyIconButton text={message.content} variant={message.role === 'user' ? 'user' : 'assistant'} />
 	                      </div>
 	                    )}
-                    {message.isLoading ? (
+                    {message.isLoading && !message.content ? (
                       <div className="flex items-center gap-2">
                         <div className="w-2 h-2 rounded-full bg-current animate-bounce" style={{ animationDelay: '0ms' }} />
                         <div className="w-2 h-2 rounded-full bg-current animate-bounce" style={{ animationDelay: '150ms' }} />
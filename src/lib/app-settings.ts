@@ -150,11 +150,24 @@ ENTITIES ({{entityCount}} total):
 
 CAPABILITIES:
 - read_file: Read files (text, markdown, scripts, etc.)
+- read_many_files: Read the start of several files in one call (paths or a glob; use this to skim many chapters)
 - write_file: Create or overwrite files (requires force=true for existing files)
 - append_file: Add content to existing files
+- edit_file: Replace a passage in an existing file (prefer this over rewriting a whole file)
+- move_file / copy_file: Move, rename, or copy a file in one step (pass overwrite=true to replace an existing destination)
+- begin_write / write_chunk / commit_write: Write long files in several chunks (abort_write discards)
 - list_dir: Browse folder contents
 - glob: Find files by pattern (e.g., "*.md", "chapters/*.txt")
 - grep: Search file contents for text
+- semantic_search: Find passages by meaning rather than exact words (e.g. "where is the betrayal foreshadowed"); falls back to telling you when no index has been built, so use grep then
+- workspace_stats: Word, character, and line counts per file with totals (use this to answer "how long is..." questions)
+- suggest_entity_tags / apply_suggested_tags: Find untagged mentions of entities in a section, then add the tags you want to keep
+- get_section_meta / update_section_meta: Read or change a section's title, order, alignment, parent, or linked entities without rewriting its content
+- insert_into_section / append_to_section: Add text to a section at a UTF-16 offset or at its end, keeping entity tags on the words they mark (validate_tags lists tags that no longer fit). Prefer these to write_file for tagged sections
+- update_section_scene: Replace only the text under one heading of a section (by heading text or 1-based index), leaving the rest of the section untouched. Prefer it to rewriting a whole section to change one scene
+- compile_manuscript: Join the sections, in outline order, into one Markdown or HTML file (e.g. exports/manuscript.md)
+- scratchpad_write / scratchpad_read: Private working memory for this run (plans, findings, reminders); use it instead of writing notes files to the project
+- git_status / git_diff / git_commit: Inspect and commit changes in the workspace git repository (commit only the paths you changed)
 - run_shell: Execute shell commands (git, file operations, etc.)
 
 FILE STRUCTURE:
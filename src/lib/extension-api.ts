@@ -621,6 +621,9 @@ export interface SignatureVerification {
 
   /** Error message if verification failed */
   error: string | null;
+
+  /** Script files checked against the manifest's `files` hashes */
+  files: Array<{ path: string; status: 'ok' | 'missing' | 'mismatch' | 'unlisted' }>;
 }
 
 /**
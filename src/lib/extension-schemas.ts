@@ -8,6 +8,7 @@
  */
 
 import { z } from 'zod';
+import type { SignatureVerification } from './extension-api';
 
 /**
  * Regex for valid extension IDs
@@ -153,7 +154,7 @@ export function safeValidateLuaExtensionManifest(
 // ============================================================================
 
 /**
- * Extension info returned from load_lua_extension command
+ * Extension info returned from load_lua_extension and list_lua_extensions
  */
 export interface ExtensionInfo {
   id: string;
@@ -161,8 +162,15 @@ export interface ExtensionInfo {
   version: string;
   description?: string;
   tool_count: number;
+  /** Manifest signature status, checked when the extension was loaded */
+  signature: SignatureVerification;
 }
 
+/**
+ * Which extensions may load, judged by their signature (set_extension_signature_policy)
+ */
+export type SignaturePolicy = 'allow_unsigned' | 'warn_unsigned' | 'require_trusted';
+
 /**
  * Tool info returned from get_extension_tools command
  */
@@ -171,6 +179,34 @@ export interface ExtensionToolInfo {
   description: string;
 }
 
+/**
+ * One extension tool in get_extension_details
+ */
+export interface ExtensionToolDetails {
+  /** Name the agent calls it by (`extension_id:tool`) */
+  name: string;
+  description: string;
+  /** JSON schema of the parameters, as declared in the manifest */
+  parameters: Record<string, unknown>;
+  /** Risk the tool runs at (untrusted extensions can't declare low) */
+  risk: 'low' | 'medium' | 'high';
+  declared_risk: 'low' | 'medium' | 'high' | null;
+}
+
+/**
+ * Everything about a loaded extension, returned from get_extension_details
+ */
+export interface ExtensionDetails {
+  id: string;
+  name: string;
+  version: string;
+  description: string | null;
+  directory: string;
+  signature: SignatureVerification;
+  hooks: LifecycleHookName[];
+  tools: ExtensionToolDetails[];
+}
+
 /**
  * Hook result returned from execute_extension_hook command
  */
@@ -178,6 +214,54 @@ export interface HookResult {
   success: boolean;
   result?: string;
   error?: string;
+  /** What became of the directives the hook returned, in order */
+  directives?: DirectiveOutcome[];
+}
+
+/**
+ * Follow-up action a hook can return in `directives` (matches Rust HookDirective enum)
+ */
+export type HookDirective =
+  | { type: 'notify'; level: 'info' | 'warning' | 'error'; message: string }
+  | { type: 'add_tag'; section_id: string; entity_id: string; from: number; to: number }
+  | { type: 'run_tool'; name: string; args: unknown };
+
+/**
+ * Outcome of one directive (matches Rust DirectiveOutcome)
+ */
+export interface DirectiveOutcome {
+  type: string;
+  status: 'executed' | 'skipped' | 'failed' | 'invalid';
+  directive?: HookDirective;
+  detail?: string;
+}
+
+/**
+ * Failure classes counted in ExtensionStats (matches Rust ErrorClass enum)
+ */
+export type ExtensionErrorClass =
+  | 'cancelled'
+  | 'timeout'
+  | 'load'
+  | 'missing_function'
+  | 'runtime'
+  | 'other';
+
+/**
+ * Execution stats returned from get_extension_stats command
+ */
+export interface ExtensionStats {
+  extension_id: string;
+  executions: number;
+  tool_executions: number;
+  hook_executions: number;
+  failures: number;
+  failure_rate: number;
+  errors: Partial<Record<ExtensionErrorClass, number>>;
+  window: number;
+  p50_ms: number;
+  p95_ms: number;
+  max_ms: number;
 }
 
 /**
@@ -129,6 +129,7 @@ export const useStoryStore = create<StoryStore>()(
       createNewProject: async (projectRoot, name) => {
         const service = await ProjectService.create(projectRoot, name);
         const project = service.getProject();
+        await NativeExtensionService.openWorkspace(projectRoot);
         set((state) => {
           state.projectService = service;
           state.projectRoot = projectRoot;
@@ -147,6 +148,9 @@ export const useStoryStore = create<StoryStore>()(
         const service = await ProjectService.open(projectRoot);
         const project = service.getProject();
 
+        // Register the workspace with the native agent before anything runs in it
+        await NativeExtensionService.openWorkspace(projectRoot);
+
         // Trigger native Lua extension hooks
         try {
           console.log('[Store] Triggering on_project_open hook for native Lua extensions');
@@ -222,6 +226,13 @@ export const useStoryStore = create<StoryStore>()(
           } catch (error) {
             console.error('[Store] Native extension on_project_close hook failed:', error);
           }
+
+          // Cancels any agent runs still working in this project
+          try {
+            await NativeExtensionService.closeWorkspace(projectRoot);
+          } catch (error) {
+            console.error('[Store] Failed to close workspace:', error);
+          }
         }
 
         // Close project service
@@ -60,12 +60,55 @@ export const TOOL_REGISTRY: ToolDefinition[] = [
       'What\'s in the outline document?',
       'Show me lines 50-100 of chapter 3',
     ],
-    documentation: 'Reads text files with optional line range control. Supports all text-based formats including markdown, plain text, scripts, and more. Use offset and limit to read specific sections of large files.',
+    documentation: 'Reads text files with optional line range control. Supports all text-based formats including markdown, plain text, scripts, and more. Use offset and limit to read specific sections of large files. The output ends with the file\'s line count and says when the limit cut it short. Binary files (images, .docx) are described rather than shown, and text in other encodings is read with unreadable bytes replaced.',
     tips: [
       'For large files, use offset and limit to read specific sections',
       'The agent can read multiple files in one response',
     ],
   },
+  {
+    name: 'read_many_files',
+    description: 'Read the start of several files in one call',
+    category: 'file',
+    icon: 'Files',
+    parameters: [
+      {
+        name: 'paths',
+        type: 'array',
+        description: 'Files to read (or use glob)',
+        required: false,
+      },
+      {
+        name: 'glob',
+        type: 'string',
+        description: 'Glob pattern selecting the files to read',
+        required: false,
+      },
+      {
+        name: 'limit',
+        type: 'integer',
+        description: 'Maximum number of lines to read from each file',
+        required: false,
+        default: 200,
+      },
+      {
+        name: 'max_bytes',
+        type: 'integer',
+        description: 'Total bytes of content across all files',
+        required: false,
+        default: 200000,
+      },
+    ],
+    examples: [
+      'Skim the opening of every chapter',
+      'Compare the first pages of sections 1-5',
+    ],
+    documentation: 'Reads up to 100 files at once and returns each one\'s content, whether it was cut short, and any error. A missing or protected file gets an error without failing the others. Output is capped at 200 KB in total.',
+    tips: [
+      'Use a glob like "sections/*.md" to cover every chapter',
+      'Lower the line limit to skim more files within the byte budget',
+    ],
+  },
   {
     name: 'write_file',
     description: 'Create new files or overwrite existing ones',
@@ -135,6 +178,119 @@ export const TOOL_REGISTRY: ToolDefinition[] = [
       'Safer than write_file for adding to existing content',
     ],
   },
+  {
+    name: 'edit_file',
+    description: 'Replace a specific passage in an existing file',
+    category: 'file',
+    icon: 'FileEdit',
+    parameters: [
+      {
+        name: 'path',
+        type: 'string',
+        description: 'Path to the file to edit',
+        required: true,
+      },
+      {
+        name: 'old_string',
+        type: 'string',
+        description: 'Exact text to replace',
+        required: true,
+      },
+      {
+        name: 'new_string',
+        type: 'string',
+        description: 'Replacement text',
+        required: true,
+      },
+      {
+        name: 'replace_all',
+        type: 'boolean',
+        description: 'Replace every occurrence instead of exactly one',
+        required: false,
+        default: false,
+      },
+    ],
+    examples: [
+      'Change the opening line of chapter 3',
+      'Rename the tavern to "The Gilded Stag" everywhere in this chapter',
+      'Fix the typo in Alice\'s profile',
+    ],
+    documentation: 'Replaces old_string with new_string without rewriting the rest of the file. The edit fails if the text is not found, or if it appears more than once and replace_all is not set, so the agent never changes the wrong passage.',
+    tips: [
+      'Much cheaper than write_file for small changes to long chapters',
+      'Reports how many replacements were made',
+    ],
+  },
+  {
+    name: 'move_file',
+    description: 'Move or rename a file',
+    category: 'file',
+    icon: 'FileSymlink',
+    parameters: [
+      {
+        name: 'source',
+        type: 'string',
+        description: 'File to move',
+        required: true,
+      },
+      {
+        name: 'destination',
+        type: 'string',
+        description: 'New file path (parent folders are created)',
+        required: true,
+      },
+      {
+        name: 'overwrite',
+        type: 'boolean',
+        description: 'Replace the destination if it already exists',
+        required: false,
+        default: false,
+      },
+    ],
+    examples: [
+      'Rename chapter-2.md to 02-the-storm.md',
+      'Move the old drafts into an archive folder',
+    ],
+    documentation: 'Moves a file in a single step, so a rename can never leave the manuscript half-copied. Refuses to replace an existing file unless overwrite is set, and does not move folders.',
+    tips: [
+      'Safer than reading, rewriting, and deleting a file',
+    ],
+  },
+  {
+    name: 'copy_file',
+    description: 'Copy a file',
+    category: 'file',
+    icon: 'Copy',
+    parameters: [
+      {
+        name: 'source',
+        type: 'string',
+        description: 'File to copy',
+        required: true,
+      },
+      {
+        name: 'destination',
+        type: 'string',
+        description: 'New file path (parent folders are created)',
+        required: true,
+      },
+      {
+        name: 'overwrite',
+        type: 'boolean',
+        description: 'Replace the destination if it already exists',
+        required: false,
+        default: false,
+      },
+    ],
+    examples: [
+      'Make a copy of chapter 5 before rewriting it',
+      'Duplicate the character template for a new character',
+    ],
+    documentation: 'Copies a file to a new path. Refuses to replace an existing file unless overwrite is set, and does not copy folders.',
+    tips: [
+      'Useful for keeping a snapshot of a chapter before a big edit',
+    ],
+  },
   {
     name: 'list_dir',
     description: 'Browse folder contents and directory structure',
@@ -227,6 +383,31 @@ export const TOOL_REGISTRY: ToolDefinition[] = [
       'Great for finding character mentions or plot points',
     ],
   },
+  {
+    name: 'workspace_stats',
+    description: 'Count words, characters, and lines in your manuscript',
+    category: 'search',
+    icon: 'BarChart',
+    parameters: [
+      {
+        name: 'path',
+        type: 'string',
+        description: 'File, folder, or glob pattern to count (defaults to every markdown file)',
+        required: false,
+        default: '.',
+      },
+    ],
+    examples: [
+      'How long is chapter 3?',
+      'What is the total word count of my manuscript?',
+      'Which sections are the longest?',
+    ],
+    documentation: 'Counts words, characters, and lines per file and in total, without the agent having to read the files. YAML frontmatter at the top of section files is not counted.',
+    tips: [
+      'Accepts globs like sections/*.md',
+      'Lists up to 200 files; totals always cover every match',
+    ],
+  },
   {
     name: 'run_shell',
     description: 'Execute shell commands (git, file operations, etc.)',
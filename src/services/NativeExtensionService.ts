@@ -11,11 +11,15 @@ import { invoke } from '@tauri-apps/api/core';
 import { appDataDir, join } from '@tauri-apps/api/path';
 import { readDir, exists } from '@tauri-apps/plugin-fs';
 import type {
+  ExtensionDetails,
   ExtensionInfo,
+  ExtensionStats,
   ExtensionToolInfo,
   HookResult,
   LifecycleHookName,
+  SignaturePolicy,
 } from '../lib/extension-schemas';
+import type { SignatureVerification } from '../lib/extension-api';
 
 /**
  * Loaded extension state for UI display
@@ -27,6 +31,7 @@ export interface LoadedExtension {
   description?: string;
   toolCount: number;
   hooks: LifecycleHookName[];
+  signature: SignatureVerification;
   loadedAt: Date;
 }
 
@@ -61,6 +66,7 @@ class NativeExtensionServiceClass {
       description: info.description,
       toolCount: info.tool_count,
       hooks,
+      signature: info.signature,
       loadedAt: new Date(),
     });
 
@@ -107,12 +113,33 @@ class NativeExtensionServiceClass {
   }
 
   /**
-   * List all loaded extension IDs
+   * List all loaded extensions
    *
-   * @returns Array of extension IDs
+   * @returns Array of extension info, including signature status
    */
-  async listExtensions(): Promise<string[]> {
-    return await invoke<string[]>('list_lua_extensions');
+  async listExtensions(): Promise<ExtensionInfo[]> {
+    return await invoke<ExtensionInfo[]>('list_lua_extensions');
+  }
+
+  /**
+   * Get everything about each loaded extension in one call: metadata, signature
+   * status, enabled hooks, and tools with their schemas and risk
+   *
+   * @returns Array of extension details, sorted by ID
+   */
+  async getExtensionDetails(): Promise<ExtensionDetails[]> {
+    return await invoke<ExtensionDetails[]>('get_extension_details');
+  }
+
+  /**
+   * Set which extensions may load, judged by their signature
+   *
+   * Applies to extensions loaded afterwards.
+   * @param policy - 'allow_unsigned', 'warn_unsigned' (default), or 'require_trusted'
+   * @returns The previous policy
+   */
+  async setSignaturePolicy(policy: SignaturePolicy): Promise<SignaturePolicy> {
+    return await invoke<SignaturePolicy>('set_extension_signature_policy', { policy });
   }
 
   /**
@@ -124,6 +151,18 @@ class NativeExtensionServiceClass {
     return await invoke<ExtensionToolInfo[]>('get_extension_tools');
   }
 
+  /**
+   * Get execution stats (totals, p50/p95 durations, failure rates)
+   *
+   * @param extensionId - Only this extension; omit for every extension that has run
+   * @returns Array of per-extension stats
+   */
+  async getExtensionStats(extensionId?: string): Promise<ExtensionStats[]> {
+    return await invoke<ExtensionStats[]>('get_extension_stats', {
+      extensionId: extensionId ?? null,
+    });
+  }
+
   /**
    * Execute a lifecycle hook for a specific extension
    *
@@ -147,6 +186,26 @@ class NativeExtensionServiceClass {
     });
   }
 
+  /**
+   * Open a workspace in the native agent so runs, hooks, and watchers accept it
+   *
+   * @param workspace - Workspace path
+   * @returns The canonical workspace root
+   */
+  async openWorkspace(workspace: string): Promise<string> {
+    return await invoke<string>('open_workspace', { workspace });
+  }
+
+  /**
+   * Close a workspace in the native agent, cancelling its running agent tasks
+   *
+   * @param workspace - Workspace path
+   * @returns Number of runs that were cancelled
+   */
+  async closeWorkspace(workspace: string): Promise<number> {
+    return await invoke<number>('close_workspace', { workspace });
+  }
+
   /**
    * Execute a lifecycle hook for all extensions that have it enabled
    *
@@ -210,7 +269,8 @@ class NativeExtensionServiceClass {
    * Refresh the cache of loaded extensions from the backend
    */
   async refreshLoadedExtensions(): Promise<void> {
-    const extensionIds = await this.listExtensions();
+    const infos = await this.listExtensions();
+    const extensionIds = infos.map((info) => info.id);
     const tools = await this.getExtensionTools();
 
     // Build tool count map
@@ -222,21 +282,24 @@ class NativeExtensionServiceClass {
     }
 
     // Update cache for each extension
-    for (const id of extensionIds) {
+    for (const info of infos) {
+      const id = info.id;
       const toolCount = toolCountMap.get(id) || 0;
       const existing = this.loadedExtensions.get(id);
       if (existing) {
-        this.loadedExtensions.set(id, { ...existing, toolCount });
+        this.loadedExtensions.set(id, { ...existing, toolCount, signature: info.signature });
         continue;
       }
 
       const hooks = await this.getExtensionHooks(id);
       this.loadedExtensions.set(id, {
         id,
-        name: id, // We don't have the full name without re-reading manifest
-        version: 'unknown',
+        name: info.name,
+        version: info.version,
+        description: info.description,
         toolCount,
         hooks,
+        signature: info.signature,
         loadedAt: new Date(),
       });
     }